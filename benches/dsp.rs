@@ -0,0 +1,140 @@
+//! Benchmarks of the per-frame hot path
+//!
+//! Every stage runs once per 20 ms frame per session, so its cost bounds
+//! the sessions a core can hold. Targets per call, on one core of a
+//! 3 GHz x86-64, with 16 kHz frames of 320 samples:
+//!
+//! | Stage                        | Target   |
+//! |------------------------------|----------|
+//! | RTP parse / serialize        | < 200 ns |
+//! | Jitter buffer insert and pop | < 500 ns |
+//! | VAD                          | < 2 µs   |
+//! | Pitch estimation, 40 ms      | < 500 µs |
+//! | Feature extraction           | < 10 µs  |
+//! | Fusion                       | < 100 ns |
+//!
+//! Pitch needs a window longer than the 20 ms period of 50 Hz, frames of
+//! 20 ms skip it, which is why feature extraction comes in under it.
+//!
+//! Usage: cargo bench --bench dsp [-- <filter>]
+
+use amwaj_media::audio::features::extract_features;
+use amwaj_media::audio::processor::pcm_to_float;
+use amwaj_media::audio::{estimate_pitch, VoiceActivityDetector};
+use amwaj_media::detection::{ConversationContext, MultiSignalFusion};
+use amwaj_media::webrtc::{JitterBuffer, RtpPacket};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+const SAMPLE_RATE: u32 = 16000;
+
+/// Samples in a 20 ms frame
+const FRAME_SAMPLES: usize = 320;
+
+/// Voiced audio, 150 Hz with a harmonic
+fn speech(samples: usize) -> Vec<f32> {
+    (0..samples)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let phase = t * 150.0 * std::f32::consts::TAU;
+            0.4 * phase.sin() + 0.1 * (2.0 * phase).sin()
+        })
+        .collect()
+}
+
+/// A G.711 packet of one frame
+fn rtp_packet(sequence_number: u16) -> RtpPacket {
+    RtpPacket {
+        version: 2,
+        padding: false,
+        extension: false,
+        csrc_count: 0,
+        marker: false,
+        payload_type: 0,
+        sequence_number,
+        timestamp: u32::from(sequence_number) * 160,
+        ssrc: 0x1234_abcd,
+        payload: vec![0xff; 160],
+    }
+}
+
+fn bench_rtp(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rtp");
+    group.throughput(Throughput::Elements(1));
+    let packet = rtp_packet(1);
+    let bytes = packet.serialize();
+    group.bench_function("parse", |b| {
+        b.iter(|| RtpPacket::parse(black_box(&bytes)).unwrap())
+    });
+    group.bench_function("serialize", |b| b.iter(|| black_box(&packet).serialize()));
+    group.finish();
+}
+
+fn bench_jitter_buffer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("jitter_buffer");
+    group.throughput(Throughput::Elements(1));
+    let payload = vec![0xff; 160];
+    group.bench_function("insert_pop", |b| {
+        let mut buffer = JitterBuffer::new(100, 8000);
+        let mut sequence_number: u16 = 0;
+        // Keep a few packets queued, as under jitter
+        for _ in 0..3 {
+            buffer.insert(sequence_number, payload.clone());
+            sequence_number = sequence_number.wrapping_add(1);
+        }
+        b.iter(|| {
+            buffer.insert(sequence_number, payload.clone());
+            sequence_number = sequence_number.wrapping_add(1);
+            black_box(buffer.get_ready_frame())
+        })
+    });
+    group.finish();
+}
+
+fn bench_audio(c: &mut Criterion) {
+    let mut group = c.benchmark_group("audio");
+    group.throughput(Throughput::Elements(FRAME_SAMPLES as u64));
+    let frame = speech(FRAME_SAMPLES);
+    // Autocorrelation needs more than the longest period, 20 ms at 50 Hz
+    let window = speech(2 * FRAME_SAMPLES);
+    let pcm: Vec<i16> = frame.iter().map(|s| (s * 32767.0) as i16).collect();
+
+    group.bench_function("vad", |b| {
+        let mut vad = VoiceActivityDetector::new(SAMPLE_RATE);
+        b.iter(|| vad.process(black_box(&frame)).unwrap())
+    });
+    group.bench_function("pcm_to_float", |b| b.iter(|| pcm_to_float(black_box(&pcm))));
+    group.bench_function("features", |b| {
+        b.iter(|| extract_features(black_box(&frame), SAMPLE_RATE))
+    });
+    group.throughput(Throughput::Elements(window.len() as u64));
+    group.bench_function("pitch", |b| {
+        b.iter(|| estimate_pitch(black_box(&window), SAMPLE_RATE))
+    });
+    group.finish();
+}
+
+fn bench_fusion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fusion");
+    group.throughput(Throughput::Elements(1));
+    let features = extract_features(&speech(FRAME_SAMPLES), SAMPLE_RATE);
+    let mut fusion = MultiSignalFusion::new();
+    group.bench_function("fuse_and_record", |b| {
+        b.iter(|| {
+            fusion.fuse_and_record(
+                black_box(0.8),
+                black_box(&features),
+                Some(ConversationContext::UserSpeaking),
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_rtp,
+    bench_jitter_buffer,
+    bench_audio,
+    bench_fusion
+);
+criterion_main!(benches);
@@ -0,0 +1,363 @@
+//! Load a running server with concurrent sessions and report what it sustained
+//!
+//! Each session streams real-time PCM16 over `StreamAudioIn`, or G.711 over
+//! plain RTP for `--rtp-sessions` (needs `[transports.rtp]` on the server),
+//! alternating a second of tone with a second of silence, and reads its turn
+//! events on a `MediaStream`. Event latency is the wall time between sending
+//! the audio at an event's media timestamp and receiving the event.
+//!
+//! Usage: cargo run --release --example loadgen -- --sessions 200 --duration-secs 60
+
+use amwaj_media::client::MediaClient;
+use amwaj_media::grpc::subscription::{EventCategories, Subscription};
+use amwaj_media::proto::{self, media_event, orchestration_command};
+use amwaj_media::webrtc::codec;
+use amwaj_media::webrtc::RtpPacket;
+use clap::Parser;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Audio per chunk or packet
+const CHUNK_MS: u64 = 20;
+
+/// Length of each tone and each silence
+const BURST_MS: u64 = 1000;
+
+/// How long events are awaited after the audio stops
+const SETTLE: Duration = Duration::from_secs(2);
+
+#[derive(Parser, Debug)]
+#[command(about = "Load generator for Amwaj Media")]
+struct Args {
+    /// Server URL
+    #[arg(long, default_value = "http://127.0.0.1:50051")]
+    server: String,
+
+    /// Sessions streaming audio over gRPC
+    #[arg(long, default_value_t = 100)]
+    sessions: usize,
+
+    /// Sessions sending plain RTP
+    #[arg(long, default_value_t = 0)]
+    rtp_sessions: usize,
+
+    /// How long each session streams
+    #[arg(long, default_value_t = 30)]
+    duration_secs: u64,
+
+    /// Delay between session starts
+    #[arg(long, default_value_t = 10)]
+    ramp_up_ms: u64,
+
+    /// API key sent with every request
+    #[arg(long)]
+    api_key: Option<String>,
+}
+
+/// How a session sends its audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Grpc,
+    Rtp,
+}
+
+/// What one session achieved
+#[derive(Debug, Default)]
+struct SessionReport {
+    chunks_sent: u64,
+    /// Audio the server never processed
+    chunks_lost: u64,
+    turns_expected: u64,
+    turns_received: u64,
+    latencies_ms: Vec<f64>,
+}
+
+/// Wall-clock send times of a session's chunks, by index
+type SendTimes = Arc<Mutex<Vec<Instant>>>;
+
+/// Generate the audio of a chunk, tone or silence by its position
+fn chunk_audio(index: u64, sample_rate: u32) -> Vec<i16> {
+    let samples = (sample_rate as u64 * CHUNK_MS / 1000) as usize;
+    if (index * CHUNK_MS / BURST_MS) % 2 == 1 {
+        return vec![0; samples];
+    }
+    let offset = index as usize * samples;
+    (0..samples)
+        .map(|i| {
+            let t = (offset + i) as f32 / sample_rate as f32;
+            ((t * 440.0 * std::f32::consts::TAU).sin() * 10000.0) as i16
+        })
+        .collect()
+}
+
+/// Read turn events, measuring each against the send time of its audio
+async fn read_events(
+    mut events: amwaj_media::client::EventStream,
+    sent: SendTimes,
+) -> (u64, Vec<f64>) {
+    let mut turns = 0;
+    let mut latencies_ms = Vec::new();
+    while let Some(event) = events.next().await {
+        match event.event {
+            Some(media_event::Event::TurnStarted(_)) | Some(media_event::Event::TurnEnded(_)) => {
+                let received = Instant::now();
+                if matches!(event.event, Some(media_event::Event::TurnStarted(_))) {
+                    turns += 1;
+                }
+                // Stamped with the end of the frame that raised it
+                let index = (event.timestamp_ms.max(0) as u64)
+                    .div_ceil(CHUNK_MS)
+                    .saturating_sub(1);
+                if let Some(sent) = sent.lock().get(index as usize) {
+                    latencies_ms.push(received.duration_since(*sent).as_secs_f64() * 1000.0);
+                }
+            }
+            Some(media_event::Event::SessionEnded(_)) => break,
+            _ => {}
+        }
+    }
+    (turns, latencies_ms)
+}
+
+/// Resolve where to send RTP, the server host when it advertises any address
+async fn rtp_target(server: &str, endpoint: &proto::RtpEndpoint) -> anyhow::Result<SocketAddr> {
+    let address: SocketAddr = endpoint.address.parse()?;
+    if !address.ip().is_unspecified() {
+        return Ok(address);
+    }
+    let uri: tonic::transport::Uri = server.parse()?;
+    let host = uri.host().unwrap_or("127.0.0.1");
+    let mut resolved = tokio::net::lookup_host((host, address.port())).await?;
+    resolved
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Cannot resolve {}", host))
+}
+
+/// Run one session from creation to end
+async fn run_session(
+    client: MediaClient,
+    session_id: String,
+    transport: Transport,
+    duration: Duration,
+) -> anyhow::Result<SessionReport> {
+    let mut grpc = client.grpc_client().await?;
+    let (sample_rate, rtp) = match transport {
+        Transport::Grpc => (16000, None),
+        Transport::Rtp => (
+            8000,
+            Some(proto::RtpIngest {
+                codec: "pcmu".to_string(),
+                ..Default::default()
+            }),
+        ),
+    };
+    let info = grpc
+        .create_session(proto::CreateSessionRequest {
+            session_id: session_id.clone(),
+            sample_rate,
+            tags: HashMap::from([("loadgen".to_string(), "true".to_string())]),
+            rtp,
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+
+    let (commands, events) = client.open_stream().await?;
+    commands
+        .send(proto::OrchestrationCommand {
+            session_id: session_id.clone(),
+            command: Some(orchestration_command::Command::Resume(proto::Resume {})),
+            ..Default::default()
+        })
+        .await?;
+    let sent = SendTimes::default();
+    let reader = tokio::spawn(read_events(events, Arc::clone(&sent)));
+
+    let chunks = duration.as_millis() as u64 / CHUNK_MS;
+    let mut pacing = tokio::time::interval(Duration::from_millis(CHUNK_MS));
+    let chunks_lost = match info.rtp {
+        None => {
+            let (chunk_tx, chunk_rx) = tokio::sync::mpsc::channel(16);
+            let upload = tokio::spawn({
+                let mut grpc = grpc.clone();
+                async move { grpc.stream_audio_in(ReceiverStream::new(chunk_rx)).await }
+            });
+            for index in 0..chunks {
+                pacing.tick().await;
+                let data = chunk_audio(index, sample_rate)
+                    .iter()
+                    .flat_map(|sample| sample.to_le_bytes())
+                    .collect();
+                sent.lock().push(Instant::now());
+                let chunk = proto::AudioChunk {
+                    session_id: session_id.clone(),
+                    sequence_number: index,
+                    timestamp_ms: (index * CHUNK_MS) as i64,
+                    encoding: proto::audio_chunk::Encoding::Pcm16 as i32,
+                    sample_rate,
+                    channels: 1,
+                    data,
+                };
+                if chunk_tx.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+            drop(chunk_tx);
+            let summary = upload.await??.into_inner();
+            chunks.saturating_sub(summary.chunks_received)
+                + summary.chunks_missing
+                + summary.chunks_dropped
+        }
+        Some(endpoint) => {
+            let target = rtp_target(client.endpoint(), &endpoint).await?;
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            let ssrc = uuid::Uuid::new_v4().as_u128() as u32;
+            let samples = (sample_rate as u64 * CHUNK_MS / 1000) as u32;
+            for index in 0..chunks {
+                pacing.tick().await;
+                let packet = RtpPacket {
+                    version: 2,
+                    padding: false,
+                    extension: false,
+                    csrc_count: 0,
+                    marker: index == 0,
+                    payload_type: endpoint.payload_type as u8,
+                    sequence_number: index as u16,
+                    timestamp: (index as u32).wrapping_mul(samples),
+                    ssrc,
+                    payload: codec::encode_pcmu(&chunk_audio(index, sample_rate)),
+                };
+                sent.lock().push(Instant::now());
+                socket.send_to(&packet.serialize(), target).await?;
+            }
+            tokio::time::sleep(SETTLE).await;
+            let processed = grpc
+                .get_session(proto::GetSessionRequest {
+                    session_id: session_id.clone(),
+                })
+                .await?
+                .into_inner()
+                .rtp_packets_processed;
+            chunks.saturating_sub(processed)
+        }
+    };
+
+    tokio::time::sleep(SETTLE).await;
+    grpc.end_session(proto::EndSessionRequest { session_id })
+        .await?;
+    drop(commands);
+    let (turns_received, latencies_ms) =
+        match tokio::time::timeout(Duration::from_secs(5), reader).await {
+            Ok(read) => read?,
+            Err(_) => (0, Vec::new()),
+        };
+
+    Ok(SessionReport {
+        chunks_sent: chunks,
+        chunks_lost,
+        // A turn starts with each tone
+        turns_expected: (chunks * CHUNK_MS).div_ceil(2 * BURST_MS),
+        turns_received,
+        latencies_ms,
+    })
+}
+
+/// Get a percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    Some(sorted[rank])
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    let mut client = MediaClient::new(args.server.clone());
+    if let Some(api_key) = &args.api_key {
+        client = client.with_api_key(api_key.clone());
+    }
+    let client = client.with_subscription(Subscription {
+        categories: EventCategories::TURNS,
+        audio_decimation: 1,
+    });
+    let duration = Duration::from_secs(args.duration_secs);
+    let run = uuid::Uuid::new_v4().simple().to_string();
+
+    let started = Instant::now();
+    let mut sessions = JoinSet::new();
+    let transports = std::iter::repeat_n(Transport::Grpc, args.sessions)
+        .chain(std::iter::repeat_n(Transport::Rtp, args.rtp_sessions));
+    for (index, transport) in transports.enumerate() {
+        if index > 0 && args.ramp_up_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(args.ramp_up_ms)).await;
+        }
+        let session_id = format!("loadgen-{}-{}", &run[..8], index);
+        sessions.spawn(run_session(client.clone(), session_id, transport, duration));
+    }
+
+    let mut reports = Vec::new();
+    let mut failures: HashMap<String, usize> = HashMap::new();
+    while let Some(ended) = sessions.join_next().await {
+        match ended? {
+            Ok(report) => reports.push(report),
+            Err(e) => *failures.entry(format!("{:#}", e)).or_default() += 1,
+        }
+    }
+
+    let sent: u64 = reports.iter().map(|r| r.chunks_sent).sum();
+    let lost: u64 = reports.iter().map(|r| r.chunks_lost).sum();
+    let expected: u64 = reports.iter().map(|r| r.turns_expected).sum();
+    let received: u64 = reports.iter().map(|r| r.turns_received).sum();
+    let mut latencies: Vec<f64> = reports
+        .iter()
+        .flat_map(|r| r.latencies_ms.iter().copied())
+        .collect();
+    latencies.sort_by(f64::total_cmp);
+    let rate = |part: u64, whole: u64| {
+        if whole == 0 {
+            0.0
+        } else {
+            part as f64 * 100.0 / whole as f64
+        }
+    };
+
+    println!("elapsed:        {:.1} s", started.elapsed().as_secs_f64());
+    println!(
+        "sessions:       {} of {} completed",
+        reports.len(),
+        args.sessions + args.rtp_sessions
+    );
+    for (error, count) in &failures {
+        println!("  failed {}x:  {}", count, error);
+    }
+    println!(
+        "audio dropped:  {:.2}% ({} of {} chunks)",
+        rate(lost, sent),
+        lost,
+        sent
+    );
+    println!(
+        "turns missed:   {:.2}% ({} of {} received)",
+        rate(expected.saturating_sub(received), expected),
+        received,
+        expected
+    );
+    for p in [50.0, 95.0, 99.0] {
+        match percentile(&latencies, p) {
+            Some(ms) => println!("latency p{}:    {:.1} ms", p, ms),
+            None => println!("latency p{}:    n/a", p),
+        }
+    }
+
+    Ok(())
+}
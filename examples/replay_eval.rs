@@ -0,0 +1,43 @@
+//! Replay a directory of labelled WAV files through turn detection
+//!
+//! Usage: cargo run --example replay_eval -- <dir> [--dump]
+
+use amwaj_media::detection::{ReplayHarness, TurnDetectionConfig};
+use std::path::PathBuf;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let dir = args
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("Usage: replay_eval <dir> [--dump]"))?;
+    let dump = args.any(|a| a == "--dump");
+
+    let harness = ReplayHarness::new(TurnDetectionConfig::default());
+    let report = harness.replay_dir(&dir)?;
+
+    for file in &report.files {
+        println!(
+            "{}: {} detected, {} reference, {} matched",
+            file.name,
+            file.detected.len(),
+            file.reference.len(),
+            file.true_positives
+        );
+        if dump {
+            print!("{}", file.event_dump());
+        }
+    }
+
+    println!("files:     {}", report.files.len());
+    println!("precision: {:.3}", report.precision());
+    println!("recall:    {:.3}", report.recall());
+    for p in [50.0, 90.0, 99.0] {
+        match report.latency_percentile(p) {
+            Some(ms) => println!("latency p{}: {} ms", p, ms),
+            None => println!("latency p{}: n/a", p),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Recognizers serving the `SpeechRecognizer` gRPC service
+//!
+//! Each session is one `Recognize` call: a `RecognitionConfig`, then PCM16
+//! audio at the session's rate and `end_of_turn` markers. The recognizer
+//! answers with the transcript of the current turn so far.
+
+use super::{Recognizer, Transcript};
+use crate::config::AsrConfig;
+use crate::proto;
+use crate::proto::recognize_request::Request as RecognizeInput;
+use crate::proto::speech_recognizer_client::SpeechRecognizerClient;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic::Streaming;
+
+/// Requests buffered on the way to the recognizer
+const REQUEST_CHANNEL_CAPACITY: usize = 64;
+
+/// Recognizer reached over gRPC
+pub struct GrpcRecognizer {
+    requests: mpsc::Sender<proto::RecognizeRequest>,
+    responses: Streaming<proto::RecognizeResponse>,
+}
+
+impl GrpcRecognizer {
+    /// Open a `Recognize` call for a session with audio at `sample_rate`
+    pub async fn connect(
+        config: &AsrConfig,
+        session_id: &str,
+        sample_rate: u32,
+    ) -> anyhow::Result<Self> {
+        let mut endpoint = Endpoint::from_shared(config.url.clone())?;
+        if config.url.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+        let mut client = SpeechRecognizerClient::new(endpoint.connect().await?);
+
+        let (requests, receiver) = mpsc::channel(REQUEST_CHANNEL_CAPACITY);
+        let recognition = proto::RecognitionConfig {
+            session_id: session_id.to_string(),
+            sample_rate,
+            model: config.model.clone(),
+            language: config.language.clone().unwrap_or_default(),
+        };
+        requests
+            .send(request(RecognizeInput::Config(recognition)))
+            .await?;
+        let mut call = tonic::Request::new(ReceiverStream::new(receiver));
+        if let Some(api_key) = &config.api_key {
+            call.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", api_key.expose()).parse()?,
+            );
+        }
+        let responses = client.recognize(call).await?.into_inner();
+        Ok(Self {
+            requests,
+            responses,
+        })
+    }
+
+    async fn send(&mut self, input: RecognizeInput) -> anyhow::Result<()> {
+        self.requests
+            .send(request(input))
+            .await
+            .map_err(|_| anyhow::anyhow!("Recognize call closed"))
+    }
+}
+
+fn request(input: RecognizeInput) -> proto::RecognizeRequest {
+    proto::RecognizeRequest {
+        request: Some(input),
+    }
+}
+
+#[async_trait::async_trait]
+impl Recognizer for GrpcRecognizer {
+    async fn send_audio(&mut self, pcm: &[i16]) -> anyhow::Result<()> {
+        let audio = pcm.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        self.send(RecognizeInput::Audio(audio)).await
+    }
+
+    async fn end_turn(&mut self) -> anyhow::Result<()> {
+        self.send(RecognizeInput::EndOfTurn(true)).await
+    }
+
+    async fn next_transcript(&mut self) -> anyhow::Result<Option<Transcript>> {
+        Ok(self.responses.message().await?.map(|response| Transcript {
+            text: response.text,
+            is_final: response.is_final,
+            confidence: response.confidence,
+        }))
+    }
+}
@@ -0,0 +1,219 @@
+//! Bridge to an external speech recognizer
+//!
+//! With `[asr]` set, each session streams its audio to a recognizer, either
+//! the audio of each turn or all of it, and applies the transcripts coming
+//! back like `UpdateTranscript` commands: they drive semantic endpointing
+//! and go out to the media stream as `PartialTranscript` events.
+//!
+//! Audio is queued without ever blocking the media path and dropped once
+//! the queue is full. A recognizer that fails is reconnected after a
+//! backoff; the audio queued meanwhile is dropped.
+
+pub mod grpc;
+pub mod realtime;
+
+pub use grpc::GrpcRecognizer;
+pub use realtime::RealtimeRecognizer;
+
+use crate::audio::PreRollBuffer;
+use crate::config::{AsrConfig, AsrMode, AsrProtocol};
+use crate::grpc::service::{AmwajMediaService, MediaEvent};
+use crate::metrics::runtime::{self, TASK_ASR};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Transcript of the current turn so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub text: String,
+    /// The text of the turn won't change anymore
+    pub is_final: bool,
+    pub confidence: f32,
+}
+
+/// A connection to a recognizer, for one session
+#[async_trait::async_trait]
+pub trait Recognizer: Send {
+    /// Send PCM audio at the session's sample rate
+    async fn send_audio(&mut self, pcm: &[i16]) -> anyhow::Result<()>;
+
+    /// Mark the audio sent so far as the end of a turn
+    async fn end_turn(&mut self) -> anyhow::Result<()>;
+
+    /// Wait for the next transcript, `None` once the recognizer hung up
+    ///
+    /// Must be cancel safe, it is raced against the audio to send.
+    async fn next_transcript(&mut self) -> anyhow::Result<Option<Transcript>>;
+}
+
+/// Connect to the configured recognizer for a session
+pub async fn connect(
+    config: &AsrConfig,
+    session_id: &str,
+    sample_rate: u32,
+) -> anyhow::Result<Box<dyn Recognizer>> {
+    Ok(match config.protocol {
+        AsrProtocol::OpenaiRealtime => {
+            Box::new(RealtimeRecognizer::connect(config, sample_rate).await?)
+        }
+        AsrProtocol::Grpc => {
+            Box::new(GrpcRecognizer::connect(config, session_id, sample_rate).await?)
+        }
+    })
+}
+
+/// Input of a session's recognizer worker
+#[derive(Debug)]
+enum AsrInput {
+    Audio(Vec<i16>),
+    EndOfTurn,
+}
+
+/// Taps a session's audio for the recognizer
+///
+/// The worker relaying to the recognizer stops when this is dropped.
+pub struct AsrSession {
+    mode: AsrMode,
+    queue: mpsc::Sender<AsrInput>,
+    /// Latest frames outside a turn, `turns` mode only
+    pre_roll: PreRollBuffer<i16>,
+    frame_duration_ms: u32,
+    timestamp_ms: i64,
+    in_turn: bool,
+    worker: JoinHandle<()>,
+}
+
+impl AsrSession {
+    /// Start relaying a session's audio to the recognizer
+    ///
+    /// The session is to exist once the caller releases the sessions lock.
+    pub fn spawn(
+        service: AmwajMediaService,
+        config: Arc<AsrConfig>,
+        session_id: &str,
+        sample_rate: u32,
+        frame_duration_ms: u32,
+    ) -> Self {
+        let (queue, receiver) = mpsc::channel(config.queue_frames);
+        let mode = config.mode;
+        let pre_roll = PreRollBuffer::new(config.pre_roll_ms, frame_duration_ms);
+        let session_id = session_id.to_string();
+        let supervisor = service.clone();
+        let worker = tokio::spawn(async move {
+            let worker = run(service, config, session_id, sample_rate, receiver);
+            runtime::supervise(supervisor.metrics(), TASK_ASR, worker).await;
+        });
+        Self {
+            mode,
+            queue,
+            pre_roll,
+            frame_duration_ms,
+            timestamp_ms: 0,
+            in_turn: false,
+            worker,
+        }
+    }
+
+    /// Queue a processed frame, following the turns its events start and end
+    ///
+    /// Returns how many frames or turn ends were dropped on a full queue.
+    pub fn push(&mut self, pcm: &[i16], events: &[MediaEvent]) -> u64 {
+        let started = events
+            .iter()
+            .any(|event| matches!(event, MediaEvent::TurnStarted { .. }));
+        let ended = events
+            .iter()
+            .any(|event| matches!(event, MediaEvent::TurnEnded { .. }));
+        let mut dropped = 0;
+        match self.mode {
+            AsrMode::Stream => dropped += self.send(AsrInput::Audio(pcm.to_vec())),
+            AsrMode::Turns => {
+                if started && !self.in_turn {
+                    self.in_turn = true;
+                    for frame in self.pre_roll.drain() {
+                        dropped += self.send(AsrInput::Audio(frame.pcm));
+                    }
+                }
+                if self.in_turn {
+                    dropped += self.send(AsrInput::Audio(pcm.to_vec()));
+                } else {
+                    self.pre_roll.push(self.timestamp_ms, pcm);
+                }
+            }
+        }
+        self.timestamp_ms += i64::from(self.frame_duration_ms);
+        if ended {
+            self.in_turn = false;
+            dropped += self.send(AsrInput::EndOfTurn);
+        }
+        dropped
+    }
+
+    fn send(&self, input: AsrInput) -> u64 {
+        u64::from(self.queue.try_send(input).is_err())
+    }
+}
+
+impl Drop for AsrSession {
+    fn drop(&mut self) {
+        self.worker.abort();
+    }
+}
+
+/// Relay a session's audio, reconnecting until its queue closes
+async fn run(
+    service: AmwajMediaService,
+    config: Arc<AsrConfig>,
+    session_id: String,
+    sample_rate: u32,
+    mut queue: mpsc::Receiver<AsrInput>,
+) {
+    let backoff = Duration::from_millis(config.reconnect_backoff_ms);
+    loop {
+        let result = match connect(&config, &session_id, sample_rate).await {
+            Ok(mut recognizer) => {
+                relay(&service, &session_id, recognizer.as_mut(), &mut queue).await
+            }
+            Err(e) => Err(e.context("Failed to connect")),
+        };
+        match result {
+            Ok(()) => return,
+            Err(e) => {
+                tracing::warn!("Recognizer of session {}: {:#}", session_id, e);
+                service.metrics().asr_errors.inc();
+            }
+        }
+        tokio::time::sleep(backoff).await;
+        // Audio from before the outage would arrive late and out of turn
+        while queue.try_recv().is_ok() {}
+    }
+}
+
+/// Send queued audio and apply transcripts until the queue closes
+async fn relay(
+    service: &AmwajMediaService,
+    session_id: &str,
+    recognizer: &mut dyn Recognizer,
+    queue: &mut mpsc::Receiver<AsrInput>,
+) -> anyhow::Result<()> {
+    loop {
+        tokio::select! {
+            input = queue.recv() => match input {
+                Some(AsrInput::Audio(pcm)) => recognizer.send_audio(&pcm).await?,
+                Some(AsrInput::EndOfTurn) => recognizer.end_turn().await?,
+                None => return Ok(()),
+            },
+            transcript = recognizer.next_transcript() => {
+                let Some(transcript) = transcript? else {
+                    return Err(anyhow::anyhow!("Recognizer hung up"));
+                };
+                service.metrics().record_asr_transcript(transcript.is_final);
+                if let Err(e) = service.apply_transcript(session_id, &transcript) {
+                    tracing::debug!("Dropping transcript of session {}: {}", session_id, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! OpenAI Realtime transcription sessions
+//!
+//! Audio is resampled to the 24 kHz PCM16 the API takes and appended to its
+//! input buffer. Turn detection is left to this server: the buffer is
+//! committed at each turn end, and every `interim_commit_ms` of audio while
+//! a turn goes on, since the API only transcribes committed audio. The
+//! transcript of a turn is the text of all its commits, final once the one
+//! ending the turn is transcribed. The API reports no confidence,
+//! transcripts carry 1.0.
+
+use super::{Recognizer, Transcript};
+use crate::config::AsrConfig;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Sample rate of the audio the API takes
+pub const REALTIME_SAMPLE_RATE: u32 = 24000;
+
+/// Least audio the API accepts in a commit, 100 ms
+const MIN_COMMIT_SAMPLES: usize = REALTIME_SAMPLE_RATE as usize / 10;
+
+/// Audio committed at once, transcribed as one conversation item
+#[derive(Debug, Default)]
+struct Segment {
+    /// Set once the API acknowledged the commit
+    item_id: Option<String>,
+    text: String,
+    transcribed: bool,
+    ends_turn: bool,
+}
+
+/// Transcripts of turns, from the commits making them up
+#[derive(Debug, Default)]
+struct TurnTranscripts {
+    /// Commits not transcribed yet or of the current turn, oldest first
+    segments: VecDeque<Segment>,
+    /// Transcripts waiting to be returned
+    ready: VecDeque<Transcript>,
+    last_partial: String,
+}
+
+impl TurnTranscripts {
+    /// Track audio just committed
+    fn committed(&mut self, ends_turn: bool) {
+        self.segments.push_back(Segment {
+            ends_turn,
+            ..Segment::default()
+        });
+    }
+
+    /// End the current turn with its last commit, if it had one
+    fn end_turn(&mut self) {
+        if let Some(segment) = self.segments.back_mut().filter(|s| !s.ends_turn) {
+            segment.ends_turn = true;
+            self.collect();
+        }
+    }
+
+    /// Apply an event of the API
+    fn handle(&mut self, event: &Value) {
+        let item_id = event["item_id"].as_str().unwrap_or_default();
+        match event["type"].as_str().unwrap_or_default() {
+            "input_audio_buffer.committed" => {
+                match self.segments.iter_mut().find(|s| s.item_id.is_none()) {
+                    Some(segment) => segment.item_id = Some(item_id.to_string()),
+                    // Not committed by this recognizer, taken as a turn
+                    None => self.segments.push_back(Segment {
+                        item_id: Some(item_id.to_string()),
+                        ends_turn: true,
+                        ..Segment::default()
+                    }),
+                }
+            }
+            "conversation.item.input_audio_transcription.delta" => {
+                if let Some(segment) = self.segment_mut(item_id) {
+                    segment
+                        .text
+                        .push_str(event["delta"].as_str().unwrap_or_default());
+                    self.collect();
+                }
+            }
+            "conversation.item.input_audio_transcription.completed" => {
+                if let Some(segment) = self.segment_mut(item_id) {
+                    segment.text = event["transcript"].as_str().unwrap_or_default().into();
+                    segment.transcribed = true;
+                    self.collect();
+                }
+            }
+            "conversation.item.input_audio_transcription.failed" => {
+                tracing::warn!("Transcription failed: {}", event["error"]["message"]);
+                if let Some(segment) = self.segment_mut(item_id) {
+                    segment.transcribed = true;
+                    self.collect();
+                }
+            }
+            "error" => tracing::warn!("Realtime API error: {}", event["error"]["message"]),
+            _ => {}
+        }
+    }
+
+    fn segment_mut(&mut self, item_id: &str) -> Option<&mut Segment> {
+        self.segments
+            .iter_mut()
+            .find(|segment| segment.item_id.as_deref() == Some(item_id))
+    }
+
+    /// Queue the transcripts of the current turn the segments make up
+    fn collect(&mut self) {
+        loop {
+            let end = self.segments.iter().position(|segment| segment.ends_turn);
+            let turn = end.map_or(self.segments.len(), |end| end + 1);
+            let text = join_text(self.segments.iter().take(turn));
+            let complete = end.is_some()
+                && self
+                    .segments
+                    .iter()
+                    .take(turn)
+                    .all(|segment| segment.transcribed);
+            if !complete {
+                if !text.is_empty() && text != self.last_partial {
+                    self.ready.push_back(transcript(text.clone(), false));
+                    self.last_partial = text;
+                }
+                return;
+            }
+            self.segments.drain(..turn);
+            self.last_partial.clear();
+            if !text.is_empty() {
+                self.ready.push_back(transcript(text, true));
+            }
+        }
+    }
+}
+
+/// Recognizer speaking the OpenAI Realtime API
+pub struct RealtimeRecognizer {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    sample_rate: u32,
+    /// Audio appended since the last commit, at 24 kHz
+    uncommitted: usize,
+    interim_commit_samples: usize,
+    turns: TurnTranscripts,
+}
+
+impl RealtimeRecognizer {
+    /// Open a transcription session for audio at `sample_rate`
+    pub async fn connect(config: &AsrConfig, sample_rate: u32) -> anyhow::Result<Self> {
+        let mut request = config.url.as_str().into_client_request()?;
+        let headers = request.headers_mut();
+        if let Some(api_key) = &config.api_key {
+            headers.insert(
+                "Authorization",
+                format!("Bearer {}", api_key.expose()).parse()?,
+            );
+        }
+        headers.insert("OpenAI-Beta", HeaderValue::from_static("realtime=v1"));
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await?;
+
+        let mut transcription = json!({ "model": config.model });
+        if let Some(language) = &config.language {
+            transcription["language"] = json!(language);
+        }
+        let update = json!({
+            "type": "transcription_session.update",
+            "session": {
+                "input_audio_format": "pcm16",
+                "input_audio_transcription": transcription,
+                "turn_detection": null,
+            },
+        });
+        socket.send(Message::Text(update.to_string())).await?;
+        Ok(Self {
+            socket,
+            sample_rate,
+            uncommitted: 0,
+            interim_commit_samples: (config.interim_commit_ms as usize)
+                * (REALTIME_SAMPLE_RATE as usize / 1000),
+            turns: TurnTranscripts::default(),
+        })
+    }
+
+    async fn send_event(&mut self, event: Value) -> anyhow::Result<()> {
+        self.socket.send(Message::Text(event.to_string())).await?;
+        Ok(())
+    }
+
+    /// Commit the buffered audio, or drop it if too short to transcribe
+    async fn commit(&mut self, ends_turn: bool) -> anyhow::Result<()> {
+        if self.uncommitted >= MIN_COMMIT_SAMPLES {
+            self.send_event(json!({ "type": "input_audio_buffer.commit" }))
+                .await?;
+            self.turns.committed(ends_turn);
+        } else {
+            if self.uncommitted > 0 {
+                self.send_event(json!({ "type": "input_audio_buffer.clear" }))
+                    .await?;
+            }
+            if ends_turn {
+                self.turns.end_turn();
+            }
+        }
+        self.uncommitted = 0;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Recognizer for RealtimeRecognizer {
+    async fn send_audio(&mut self, pcm: &[i16]) -> anyhow::Result<()> {
+        let pcm = resample(pcm, self.sample_rate, REALTIME_SAMPLE_RATE);
+        let bytes: Vec<u8> = pcm.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        self.send_event(json!({
+            "type": "input_audio_buffer.append",
+            "audio": STANDARD.encode(bytes),
+        }))
+        .await?;
+        self.uncommitted += pcm.len();
+        if self.interim_commit_samples > 0 && self.uncommitted >= self.interim_commit_samples {
+            self.commit(false).await?;
+        }
+        Ok(())
+    }
+
+    async fn end_turn(&mut self) -> anyhow::Result<()> {
+        self.commit(true).await
+    }
+
+    async fn next_transcript(&mut self) -> anyhow::Result<Option<Transcript>> {
+        loop {
+            if let Some(transcript) = self.turns.ready.pop_front() {
+                return Ok(Some(transcript));
+            }
+            let Some(message) = self.socket.next().await else {
+                return Ok(None);
+            };
+            match message? {
+                Message::Text(text) => self.turns.handle(&serde_json::from_str(&text)?),
+                Message::Close(_) => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn transcript(text: String, is_final: bool) -> Transcript {
+    Transcript {
+        text,
+        is_final,
+        confidence: 1.0,
+    }
+}
+
+/// Join the text of segments, one space apart
+fn join_text<'a>(segments: impl Iterator<Item = &'a Segment>) -> String {
+    segments
+        .map(|segment| segment.text.trim())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Resample PCM by linear interpolation
+///
+/// Nothing is filtered when downsampling; speech has little energy above
+/// the 12 kHz a 24 kHz rate keeps.
+pub(crate) fn resample(pcm: &[i16], from: u32, to: u32) -> Vec<i16> {
+    if from == to || pcm.is_empty() {
+        return pcm.to_vec();
+    }
+    let len = (pcm.len() as u64 * u64::from(to) / u64::from(from)) as usize;
+    let step = f64::from(from) / f64::from(to);
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let current = f64::from(pcm[index]);
+            let next = f64::from(pcm[(index + 1).min(pcm.len() - 1)]);
+            (current + (next - current) * (position - index as f64)).round() as i16
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resample() {
+        let pcm: Vec<i16> = (0..160).map(|i| i * 100).collect();
+        let resampled = resample(&pcm, 8000, REALTIME_SAMPLE_RATE);
+        assert_eq!(resampled.len(), 480);
+        assert_eq!(&resampled[..4], &[0, 33, 67, 100]);
+        assert_eq!(resample(&pcm, 48000, REALTIME_SAMPLE_RATE).len(), 80);
+        assert_eq!(
+            resample(&pcm, REALTIME_SAMPLE_RATE, REALTIME_SAMPLE_RATE),
+            pcm
+        );
+    }
+
+    #[test]
+    fn test_join_text() {
+        let segments = [
+            Segment {
+                text: " my number is ".to_string(),
+                ..Segment::default()
+            },
+            Segment::default(),
+            Segment {
+                text: "five".to_string(),
+                ..Segment::default()
+            },
+        ];
+        assert_eq!(join_text(segments.iter()), "my number is five");
+    }
+
+    fn event(kind: &str, item_id: &str, field: &str, text: &str) -> Value {
+        json!({ "type": kind, "item_id": item_id, field: text })
+    }
+
+    fn drain(turns: &mut TurnTranscripts) -> Vec<(String, bool)> {
+        turns
+            .ready
+            .drain(..)
+            .map(|transcript| (transcript.text, transcript.is_final))
+            .collect()
+    }
+
+    #[test]
+    fn test_turn_across_commits() {
+        const DELTA: &str = "conversation.item.input_audio_transcription.delta";
+        const COMPLETED: &str = "conversation.item.input_audio_transcription.completed";
+        let mut turns = TurnTranscripts::default();
+        // An interim commit, then the one ending the turn
+        turns.committed(false);
+        turns.committed(true);
+        turns.handle(&event("input_audio_buffer.committed", "a", "", ""));
+        turns.handle(&event("input_audio_buffer.committed", "b", "", ""));
+        turns.handle(&event(DELTA, "a", "delta", "my number"));
+        turns.handle(&event(DELTA, "a", "delta", " is"));
+        turns.handle(&event(COMPLETED, "a", "transcript", "my number is"));
+        assert_eq!(
+            drain(&mut turns),
+            [
+                ("my number".to_string(), false),
+                ("my number is".to_string(), false)
+            ]
+        );
+        turns.handle(&event(COMPLETED, "b", "transcript", "five"));
+        assert_eq!(drain(&mut turns), [("my number is five".to_string(), true)]);
+        assert!(turns.segments.is_empty());
+
+        // A turn too short for a commit of its own ends with the previous one
+        turns.committed(false);
+        turns.handle(&event("input_audio_buffer.committed", "c", "", ""));
+        turns.handle(&event(COMPLETED, "c", "transcript", "hello"));
+        assert_eq!(drain(&mut turns), [("hello".to_string(), false)]);
+        turns.end_turn();
+        assert_eq!(drain(&mut turns), [("hello".to_string(), true)]);
+    }
+}
@@ -0,0 +1,269 @@
+//! Per-frame processing budget enforcement
+//!
+//! Measures each pipeline stage against a latency budget and decides when
+//! optional stages should be shed under sustained overload.
+
+/// Audio pipeline stage measured by the budget watchdog
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProcessingStage {
+    /// Voice isolation / noise suppression (optional)
+    VoiceIsolation,
+    /// Feature extraction (volume, pitch, spectral)
+    Features,
+    /// Voice activity detection
+    Vad,
+}
+
+impl ProcessingStage {
+    /// Stable name used in logs and metric labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessingStage::VoiceIsolation => "voice_isolation",
+            ProcessingStage::Features => "features",
+            ProcessingStage::Vad => "vad",
+        }
+    }
+}
+
+/// Latency budget configuration for a single audio frame
+#[derive(Debug, Clone)]
+pub struct ProcessingBudget {
+    /// Total budget for one frame across all stages (ms)
+    pub frame_budget_ms: f64,
+    /// Budget for voice isolation (ms)
+    pub isolation_budget_ms: f64,
+    /// Budget for feature extraction (ms)
+    pub features_budget_ms: f64,
+    /// Budget for VAD (ms)
+    pub vad_budget_ms: f64,
+    /// Consecutive over-budget frames before optional stages are shed
+    pub overload_frames: u32,
+    /// Automatically disable optional stages under sustained overload
+    pub auto_shed: bool,
+}
+
+impl Default for ProcessingBudget {
+    fn default() -> Self {
+        Self {
+            frame_budget_ms: 10.0, // Half of a 20ms frame
+            isolation_budget_ms: 5.0,
+            features_budget_ms: 2.0,
+            vad_budget_ms: 2.0,
+            overload_frames: 50, // 1 second of 20ms frames
+            auto_shed: true,
+        }
+    }
+}
+
+impl ProcessingBudget {
+    /// Get the budget for a specific stage
+    pub fn stage_budget_ms(&self, stage: ProcessingStage) -> f64 {
+        match stage {
+            ProcessingStage::VoiceIsolation => self.isolation_budget_ms,
+            ProcessingStage::Features => self.features_budget_ms,
+            ProcessingStage::Vad => self.vad_budget_ms,
+        }
+    }
+}
+
+/// Measured stage latencies for a single frame
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StageTimings {
+    /// Voice isolation latency (ms)
+    pub isolation_ms: f64,
+    /// Feature extraction latency (ms)
+    pub features_ms: f64,
+    /// VAD latency (ms)
+    pub vad_ms: f64,
+}
+
+impl StageTimings {
+    /// Total latency across all stages (ms)
+    pub fn total_ms(&self) -> f64 {
+        self.isolation_ms + self.features_ms + self.vad_ms
+    }
+
+    /// Get the latency of a specific stage
+    pub fn stage_ms(&self, stage: ProcessingStage) -> f64 {
+        match stage {
+            ProcessingStage::VoiceIsolation => self.isolation_ms,
+            ProcessingStage::Features => self.features_ms,
+            ProcessingStage::Vad => self.vad_ms,
+        }
+    }
+}
+
+/// Outcome of checking a frame against the budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetVerdict {
+    /// Frame completed within budget
+    WithinBudget,
+    /// Frame exceeded the budget
+    Exceeded,
+    /// Sustained overload, the given optional stage should be disabled
+    Shed(ProcessingStage),
+}
+
+/// Watchdog that tracks budget overruns across frames
+pub struct BudgetWatchdog {
+    budget: ProcessingBudget,
+    consecutive_overruns: u32,
+    total_overruns: u64,
+    shed_stage: Option<ProcessingStage>,
+}
+
+impl BudgetWatchdog {
+    /// Create a new watchdog
+    pub fn new(budget: ProcessingBudget) -> Self {
+        Self {
+            budget,
+            consecutive_overruns: 0,
+            total_overruns: 0,
+            shed_stage: None,
+        }
+    }
+
+    /// Check a frame's stage timings against the budget
+    pub fn check(&mut self, timings: &StageTimings) -> BudgetVerdict {
+        for stage in [
+            ProcessingStage::VoiceIsolation,
+            ProcessingStage::Features,
+            ProcessingStage::Vad,
+        ] {
+            let elapsed = timings.stage_ms(stage);
+            let budget = self.budget.stage_budget_ms(stage);
+            if elapsed > budget {
+                tracing::debug!(
+                    "Stage {} over budget: {:.2}ms > {:.2}ms",
+                    stage.as_str(),
+                    elapsed,
+                    budget
+                );
+            }
+        }
+
+        let total = timings.total_ms();
+        if total <= self.budget.frame_budget_ms {
+            if self.consecutive_overruns > 0 {
+                tracing::info!(
+                    "Frames back within budget after {} over it",
+                    self.consecutive_overruns
+                );
+            }
+            self.consecutive_overruns = 0;
+            return BudgetVerdict::WithinBudget;
+        }
+
+        self.consecutive_overruns += 1;
+        self.total_overruns += 1;
+        // Logged once per run of overruns, the counter has every frame
+        if self.consecutive_overruns == 1 {
+            tracing::warn!(
+                "Frame over budget: {:.2}ms > {:.2}ms",
+                total,
+                self.budget.frame_budget_ms
+            );
+        }
+
+        if self.budget.auto_shed
+            && self.shed_stage.is_none()
+            && self.consecutive_overruns >= self.budget.overload_frames
+        {
+            // Voice isolation is the only optional stage
+            self.shed_stage = Some(ProcessingStage::VoiceIsolation);
+            return BudgetVerdict::Shed(ProcessingStage::VoiceIsolation);
+        }
+
+        BudgetVerdict::Exceeded
+    }
+
+    /// Get the budget configuration
+    pub fn budget(&self) -> &ProcessingBudget {
+        &self.budget
+    }
+
+    /// Get the number of consecutive over-budget frames
+    pub fn consecutive_overruns(&self) -> u32 {
+        self.consecutive_overruns
+    }
+
+    /// Get the total number of over-budget frames
+    pub fn total_overruns(&self) -> u64 {
+        self.total_overruns
+    }
+
+    /// Get the stage that has been shed, if any
+    pub fn shed_stage(&self) -> Option<ProcessingStage> {
+        self.shed_stage
+    }
+
+    /// Reset the watchdog state
+    pub fn reset(&mut self) {
+        self.consecutive_overruns = 0;
+        self.total_overruns = 0;
+        self.shed_stage = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timings(total_ms: f64) -> StageTimings {
+        StageTimings {
+            isolation_ms: total_ms,
+            features_ms: 0.0,
+            vad_ms: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_within_budget() {
+        let mut watchdog = BudgetWatchdog::new(ProcessingBudget::default());
+        assert_eq!(watchdog.check(&timings(1.0)), BudgetVerdict::WithinBudget);
+        assert_eq!(watchdog.total_overruns(), 0);
+    }
+
+    #[test]
+    fn test_exceeded_resets_on_recovery() {
+        let mut watchdog = BudgetWatchdog::new(ProcessingBudget::default());
+
+        assert_eq!(watchdog.check(&timings(15.0)), BudgetVerdict::Exceeded);
+        assert_eq!(watchdog.consecutive_overruns(), 1);
+
+        watchdog.check(&timings(1.0));
+        assert_eq!(watchdog.consecutive_overruns(), 0);
+        assert_eq!(watchdog.total_overruns(), 1);
+    }
+
+    #[test]
+    fn test_sustained_overload_sheds_once() {
+        let budget = ProcessingBudget {
+            overload_frames: 3,
+            ..ProcessingBudget::default()
+        };
+        let mut watchdog = BudgetWatchdog::new(budget);
+
+        assert_eq!(watchdog.check(&timings(15.0)), BudgetVerdict::Exceeded);
+        assert_eq!(watchdog.check(&timings(15.0)), BudgetVerdict::Exceeded);
+        assert_eq!(
+            watchdog.check(&timings(15.0)),
+            BudgetVerdict::Shed(ProcessingStage::VoiceIsolation)
+        );
+        assert_eq!(watchdog.check(&timings(15.0)), BudgetVerdict::Exceeded);
+        assert_eq!(watchdog.shed_stage(), Some(ProcessingStage::VoiceIsolation));
+    }
+
+    #[test]
+    fn test_auto_shed_disabled() {
+        let budget = ProcessingBudget {
+            overload_frames: 1,
+            auto_shed: false,
+            ..ProcessingBudget::default()
+        };
+        let mut watchdog = BudgetWatchdog::new(budget);
+
+        assert_eq!(watchdog.check(&timings(15.0)), BudgetVerdict::Exceeded);
+        assert!(watchdog.shed_stage().is_none());
+    }
+}
@@ -0,0 +1,199 @@
+//! Per-session VAD probability calibration
+//!
+//! Raw energy-based VAD scores depend heavily on microphone gain, so a fixed
+//! threshold means different things on different devices. The calibrator
+//! keeps a decaying histogram of frame energy and maps each frame onto the
+//! session's own range between background noise and speech, so a threshold
+//! of 0.6 reads as "60% of the way from this device's noise to its speech".
+
+/// Lowest energy tracked by the histogram (dB)
+const MIN_ENERGY_DB: f32 = -100.0;
+
+/// Configuration for VAD calibration
+#[derive(Debug, Clone)]
+pub struct VadCalibrationConfig {
+    /// Histogram bin width (dB)
+    pub bin_width_db: f32,
+    /// Per-frame decay applied to old observations (0.0 - 1.0)
+    pub decay: f32,
+    /// Frames observed before calibration takes effect
+    pub warmup_frames: u64,
+    /// Energy percentile taken as the noise level (0.0 - 1.0)
+    pub noise_percentile: f32,
+    /// Energy percentile taken as the speech level (0.0 - 1.0)
+    pub speech_percentile: f32,
+    /// Smallest noise-to-speech range assumed, keeps pure noise near 0 (dB)
+    pub min_dynamic_range_db: f32,
+}
+
+impl Default for VadCalibrationConfig {
+    fn default() -> Self {
+        Self {
+            bin_width_db: 1.0,
+            decay: 0.999,
+            warmup_frames: 50,
+            noise_percentile: 0.1,
+            speech_percentile: 0.9,
+            min_dynamic_range_db: 20.0,
+        }
+    }
+}
+
+/// Maps frame energy to a calibrated speech probability
+pub struct VadCalibrator {
+    config: VadCalibrationConfig,
+    bins: Vec<f32>,
+    total: f32,
+    frames_observed: u64,
+}
+
+impl VadCalibrator {
+    /// Create a new calibrator
+    pub fn new(config: VadCalibrationConfig) -> Self {
+        let bin_width = config.bin_width_db.max(0.1);
+        let bin_count = (-MIN_ENERGY_DB / bin_width).ceil() as usize + 1;
+        Self {
+            config,
+            bins: vec![0.0; bin_count],
+            total: 0.0,
+            frames_observed: 0,
+        }
+    }
+
+    /// Add a frame energy (dB) to the histogram
+    pub fn observe(&mut self, energy_db: f32) {
+        if !energy_db.is_finite() {
+            return;
+        }
+
+        let decay = self.config.decay.clamp(0.0, 1.0);
+        for bin in &mut self.bins {
+            *bin *= decay;
+        }
+        let index = self.bin_index(energy_db);
+        self.bins[index] += 1.0;
+        self.total = self.total * decay + 1.0;
+        self.frames_observed += 1;
+    }
+
+    /// Calibrated probability for a frame energy, `None` during warm-up
+    pub fn calibrate(&self, energy_db: f32) -> Option<f32> {
+        let (noise_db, speech_db) = self.levels()?;
+        let range = (speech_db - noise_db).max(self.config.min_dynamic_range_db.max(1.0));
+        Some(((energy_db - noise_db) / range).clamp(0.0, 1.0))
+    }
+
+    /// Estimated (noise, speech) energy levels (dB), `None` during warm-up
+    pub fn levels(&self) -> Option<(f32, f32)> {
+        if self.frames_observed < self.config.warmup_frames || self.total <= 0.0 {
+            return None;
+        }
+
+        Some((
+            self.percentile(self.config.noise_percentile),
+            self.percentile(self.config.speech_percentile),
+        ))
+    }
+
+    fn percentile(&self, fraction: f32) -> f32 {
+        let target = self.total * fraction.clamp(0.0, 1.0);
+        let mut cumulative = 0.0;
+        for (index, count) in self.bins.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= target {
+                return self.bin_center(index);
+            }
+        }
+        self.bin_center(self.bins.len() - 1)
+    }
+
+    fn bin_index(&self, energy_db: f32) -> usize {
+        let offset = (energy_db.clamp(MIN_ENERGY_DB, 0.0) - MIN_ENERGY_DB) / self.bin_width();
+        (offset as usize).min(self.bins.len() - 1)
+    }
+
+    fn bin_center(&self, index: usize) -> f32 {
+        MIN_ENERGY_DB + (index as f32 + 0.5) * self.bin_width()
+    }
+
+    fn bin_width(&self) -> f32 {
+        self.config.bin_width_db.max(0.1)
+    }
+
+    /// Get the number of frames observed
+    pub fn frames_observed(&self) -> u64 {
+        self.frames_observed
+    }
+
+    /// Clear the histogram
+    pub fn reset(&mut self) {
+        self.bins.iter_mut().for_each(|bin| *bin = 0.0);
+        self.total = 0.0;
+        self.frames_observed = 0;
+    }
+}
+
+/// Convert mean frame energy to dB, floored at the histogram minimum
+pub fn energy_to_db(energy: f32) -> f32 {
+    if energy <= 0.0 {
+        MIN_ENERGY_DB
+    } else {
+        (10.0 * energy.log10()).max(MIN_ENERGY_DB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alternate noise and speech frames at the given levels
+    fn observe_session(calibrator: &mut VadCalibrator, noise_db: f32, speech_db: f32) {
+        for i in 0..200 {
+            calibrator.observe(if i % 2 == 0 { noise_db } else { speech_db });
+        }
+    }
+
+    #[test]
+    fn test_warmup() {
+        let mut calibrator = VadCalibrator::new(VadCalibrationConfig::default());
+        for _ in 0..49 {
+            calibrator.observe(-60.0);
+        }
+        assert_eq!(calibrator.calibrate(-20.0), None);
+
+        calibrator.observe(-60.0);
+        assert!(calibrator.calibrate(-20.0).is_some());
+    }
+
+    #[test]
+    fn test_same_meaning_across_gains() {
+        let mut quiet = VadCalibrator::new(VadCalibrationConfig::default());
+        let mut loud = VadCalibrator::new(VadCalibrationConfig::default());
+        observe_session(&mut quiet, -70.0, -40.0);
+        observe_session(&mut loud, -50.0, -20.0);
+
+        // Speech at each device's own level calibrates identically
+        let quiet_speech = quiet.calibrate(-40.0).unwrap();
+        let loud_speech = loud.calibrate(-20.0).unwrap();
+        assert!((quiet_speech - loud_speech).abs() < 1e-6);
+        assert!(quiet_speech > 0.9);
+
+        assert!(quiet.calibrate(-70.0).unwrap() < 0.1);
+        assert!(loud.calibrate(-50.0).unwrap() < 0.1);
+    }
+
+    #[test]
+    fn test_noise_only_session_stays_low() {
+        let mut calibrator = VadCalibrator::new(VadCalibrationConfig::default());
+        observe_session(&mut calibrator, -62.0, -58.0);
+
+        // Small fluctuations are not stretched to the full range
+        assert!(calibrator.calibrate(-58.0).unwrap() < 0.3);
+    }
+
+    #[test]
+    fn test_energy_to_db() {
+        assert_eq!(energy_to_db(0.0), -100.0);
+        assert!((energy_to_db(0.01) + 20.0).abs() < 1e-4);
+    }
+}
@@ -1,5 +1,13 @@
 //! Audio Feature Extraction
 
+use std::collections::VecDeque;
+
+/// Audio the pitch is estimated over, twice the longest period (50 Hz)
+///
+/// A 20 ms frame holds a single 50 Hz period, too little for the
+/// autocorrelation to find it.
+pub const PITCH_WINDOW_MS: u32 = 40;
+
 /// Audio features extracted from a frame
 #[derive(Debug, Clone, Default)]
 pub struct AudioFeatures {
@@ -56,39 +64,88 @@ pub fn estimate_pitch(audio: &[f32], sample_rate: u32) -> f32 {
         return 0.0;
     }
 
-    let mut best_correlation = 0.0f32;
-    let mut best_period = 0;
-
-    for period in min_period..max_period.min(audio.len() / 2) {
-        let mut correlation = 0.0f32;
-        let mut norm1 = 0.0f32;
-        let mut norm2 = 0.0f32;
+    let periods = min_period..max_period.min(audio.len() / 2);
+    let best_correlation = periods
+        .clone()
+        .map(|period| autocorrelation(audio, period))
+        .fold(0.0f32, f32::max);
+    if best_correlation <= 0.6 {
+        return 0.0;
+    }
 
-        for i in 0..(audio.len() - period) {
-            correlation += audio[i] * audio[i + period];
-            norm1 += audio[i] * audio[i];
-            norm2 += audio[i + period] * audio[i + period];
+    // Multiples of the period correlate as well, the fundamental is the
+    // first peak close to the best one
+    let mut periods = periods
+        .skip_while(|&period| autocorrelation(audio, period) < PEAK_RATIO * best_correlation);
+    let Some(mut best_period) = periods.next() else {
+        return 0.0;
+    };
+    let mut correlation = autocorrelation(audio, best_period);
+    for period in periods {
+        let next = autocorrelation(audio, period);
+        if next <= correlation {
+            break;
         }
+        best_period = period;
+        correlation = next;
+    }
+    sample_rate as f32 / best_period as f32
+}
 
-        let normalized = if norm1 > 0.0 && norm2 > 0.0 {
-            correlation / (norm1.sqrt() * norm2.sqrt())
-        } else {
-            0.0
-        };
+/// Share of the best correlation a peak needs to be taken for the fundamental
+const PEAK_RATIO: f32 = 0.9;
 
-        if normalized > best_correlation {
-            best_correlation = normalized;
-            best_period = period;
-        }
+/// Normalized autocorrelation of audio at a lag
+fn autocorrelation(audio: &[f32], period: usize) -> f32 {
+    let mut correlation = 0.0f32;
+    let mut norm1 = 0.0f32;
+    let mut norm2 = 0.0f32;
+
+    for i in 0..(audio.len() - period) {
+        correlation += audio[i] * audio[i + period];
+        norm1 += audio[i] * audio[i];
+        norm2 += audio[i + period] * audio[i + period];
     }
 
-    if best_period > 0 && best_correlation > 0.6 {
-        sample_rate as f32 / best_period as f32
+    if norm1 > 0.0 && norm2 > 0.0 {
+        correlation / (norm1.sqrt() * norm2.sqrt())
     } else {
         0.0
     }
 }
 
+/// Estimates the pitch of a stream over its last `PITCH_WINDOW_MS`
+#[derive(Debug, Clone)]
+pub struct PitchTracker {
+    sample_rate: u32,
+    window: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl PitchTracker {
+    pub fn new(sample_rate: u32) -> Self {
+        let capacity = (sample_rate * PITCH_WINDOW_MS / 1000) as usize;
+        Self {
+            sample_rate,
+            window: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Add a frame, returns the pitch of the window ending with it
+    pub fn push(&mut self, frame: &[f32]) -> f32 {
+        let frame = &frame[frame.len().saturating_sub(self.capacity)..];
+        let overflow = (self.window.len() + frame.len()).saturating_sub(self.capacity);
+        self.window.drain(..overflow);
+        self.window.extend(frame);
+        estimate_pitch(self.window.make_contiguous(), self.sample_rate)
+    }
+
+    pub fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
 /// Calculate zero crossing rate
 pub fn calculate_zero_crossing_rate(audio: &[f32]) -> f32 {
     if audio.len() < 2 {
@@ -154,6 +211,30 @@ mod tests {
         assert!(zcr > 0.9);
     }
 
+    #[test]
+    fn test_pitch_tracker() {
+        let sample_rate = 16000;
+        let tone = |start: usize| -> Vec<f32> {
+            (start..start + 320)
+                .map(|i| {
+                    let t = i as f32 / sample_rate as f32;
+                    0.5 * (2.0 * std::f32::consts::PI * 200.0 * t).sin()
+                })
+                .collect()
+        };
+        // A 20 ms frame alone is too short
+        assert_eq!(estimate_pitch(&tone(0), sample_rate), 0.0);
+
+        let mut tracker = PitchTracker::new(sample_rate);
+        tracker.push(&tone(0));
+        for frame in 1..5 {
+            let pitch = tracker.push(&tone(frame * 320));
+            assert!((pitch - 200.0).abs() < 5.0, "pitch {}", pitch);
+        }
+        tracker.reset();
+        assert_eq!(tracker.push(&[0.0; 320]), 0.0);
+    }
+
     #[test]
     fn test_features_default() {
         let features = AudioFeatures::default();
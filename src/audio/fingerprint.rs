@@ -0,0 +1,292 @@
+//! Audio fingerprints for echo loop detection
+//!
+//! Agent audio played through a caller's speaker can come back on the
+//! microphone and be taken for the caller talking, sending the agent into a
+//! loop with itself. Every 5 ms, the last 20 ms of audio is reduced to a few
+//! bits: whether its loudness and the balance between neighbouring frequency
+//! bands rose or fell over the last 10 ms. The bits hold up to gain
+//! changes and room coloration, so the echo of played audio matches its
+//! fingerprint at some delay, while other speech matches about half of
+//! them, as chance would.
+
+use crate::config::EchoLoopConfig;
+use std::collections::VecDeque;
+
+/// Hop between fingerprints
+const HOP_MS: u32 = 5;
+
+/// Hops of audio measured for a fingerprint, overlapping so that echo off
+/// by part of a hop still lines up
+const WINDOW_HOPS: usize = 4;
+
+/// Hops back to the levels a fingerprint is compared with
+const DELTA_HOPS: usize = 2;
+
+/// Upper edges of the frequency bands below the top one (Hz)
+const BAND_EDGES_HZ: [f32; 3] = [500.0, 1500.0, 3000.0];
+
+/// Bits of a fingerprint, one per band pair plus the loudness
+const BITS: u32 = BAND_EDGES_HZ.len() as u32 + 1;
+
+/// Windows quieter than this are left out of comparisons (dBFS)
+const SILENCE_DB: f32 = -50.0;
+
+/// Share of the window that must be voiced both ways to compare
+const MIN_VOICED_SHARE: f32 = 0.5;
+
+/// Turns a stream of audio into fingerprints, `None` for silence
+struct Fingerprinter {
+    hop_size: usize,
+    /// Lowpass coefficients for the band edges
+    coefficients: [f32; 3],
+    lowpass: [f32; 3],
+    /// Band energies of the hop in progress
+    energies: [f32; 4],
+    samples: usize,
+    /// Band energies of the latest hops
+    hops: VecDeque<[f32; 4]>,
+    /// Band levels of the latest windows (dB)
+    levels: VecDeque<[f32; 4]>,
+}
+
+impl Fingerprinter {
+    fn new(sample_rate: u32) -> Self {
+        let coefficients = BAND_EDGES_HZ.map(|edge_hz| {
+            let edge_hz = edge_hz.min(sample_rate as f32 * 0.45);
+            1.0 - (-2.0 * std::f32::consts::PI * edge_hz / sample_rate as f32).exp()
+        });
+        Self {
+            hop_size: (sample_rate * HOP_MS / 1000).max(1) as usize,
+            coefficients,
+            lowpass: [0.0; 3],
+            energies: [0.0; 4],
+            samples: 0,
+            hops: VecDeque::with_capacity(WINDOW_HOPS + 1),
+            levels: VecDeque::with_capacity(DELTA_HOPS + 2),
+        }
+    }
+
+    /// Feed PCM, appending the fingerprints of the hops it completes
+    fn push(&mut self, pcm: &[i16], prints: &mut VecDeque<Option<u8>>) {
+        for &sample in pcm {
+            let x = sample as f32 / 32768.0;
+            for (lowpass, coefficient) in self.lowpass.iter_mut().zip(self.coefficients) {
+                *lowpass += coefficient * (x - *lowpass);
+            }
+            let bands = [
+                self.lowpass[0],
+                self.lowpass[1] - self.lowpass[0],
+                self.lowpass[2] - self.lowpass[1],
+                x - self.lowpass[2],
+            ];
+            for (energy, band) in self.energies.iter_mut().zip(bands) {
+                *energy += band * band;
+            }
+            self.samples += 1;
+            if self.samples == self.hop_size {
+                if let Some(print) = self.finish_hop() {
+                    prints.push_back(print);
+                }
+            }
+        }
+    }
+
+    /// Close a hop, returns its fingerprint once enough audio was seen
+    fn finish_hop(&mut self) -> Option<Option<u8>> {
+        self.hops.push_back(std::mem::take(&mut self.energies));
+        self.samples = 0;
+        if self.hops.len() > WINDOW_HOPS {
+            self.hops.pop_front();
+        }
+        if self.hops.len() < WINDOW_HOPS {
+            return None;
+        }
+
+        let n = (self.hop_size * WINDOW_HOPS) as f32;
+        let mut window = [0.0f32; 4];
+        for hop in &self.hops {
+            for (energy, band) in window.iter_mut().zip(hop) {
+                *energy += band;
+            }
+        }
+        let total_db = 10.0 * (window.iter().sum::<f32>() / n + 1e-10).log10();
+        let levels = window.map(|energy| 10.0 * (energy / n + 1e-10).log10());
+        self.levels.push_back(levels);
+        if self.levels.len() <= DELTA_HOPS {
+            return None;
+        }
+        let previous = self.levels.pop_front()?;
+        if total_db < SILENCE_DB {
+            return Some(None);
+        }
+        let mut bits = 0u8;
+        for band in 0..3 {
+            let balance = levels[band] - levels[band + 1];
+            if balance > previous[band] - previous[band + 1] {
+                bits |= 1 << band;
+            }
+        }
+        if levels.iter().sum::<f32>() > previous.iter().sum::<f32>() {
+            bits |= 1 << 3;
+        }
+        Some(Some(bits))
+    }
+}
+
+/// Compares what a session hears with what it played
+pub struct EchoLoopDetector {
+    threshold: f32,
+    played: Fingerprinter,
+    heard: Fingerprinter,
+    /// Fingerprints of the audio played, oldest first
+    played_prints: VecDeque<Option<u8>>,
+    /// Fingerprints of the latest window heard
+    heard_prints: VecDeque<Option<u8>>,
+    history_blocks: usize,
+    window_blocks: usize,
+}
+
+impl EchoLoopDetector {
+    pub fn new(config: &EchoLoopConfig, sample_rate: u32) -> Self {
+        let history_blocks = (config.history_ms / HOP_MS) as usize;
+        let window_blocks = (config.window_ms / HOP_MS) as usize;
+        Self {
+            threshold: config.similarity_threshold,
+            played: Fingerprinter::new(sample_rate),
+            heard: Fingerprinter::new(sample_rate),
+            played_prints: VecDeque::with_capacity(history_blocks + 1),
+            heard_prints: VecDeque::with_capacity(window_blocks + 1),
+            history_blocks,
+            window_blocks,
+        }
+    }
+
+    /// Fingerprint agent audio sent for playback
+    pub fn record_played(&mut self, pcm: &[i16]) {
+        self.played.push(pcm, &mut self.played_prints);
+        let excess = self.played_prints.len().saturating_sub(self.history_blocks);
+        self.played_prints.drain(..excess);
+    }
+
+    /// Fingerprint audio from the caller
+    pub fn record_heard(&mut self, pcm: &[i16]) {
+        self.heard.push(pcm, &mut self.heard_prints);
+        let excess = self.heard_prints.len().saturating_sub(self.window_blocks);
+        self.heard_prints.drain(..excess);
+    }
+
+    /// Get the best match of the window heard against the audio played, at
+    /// any delay, as the share of matching bits
+    ///
+    /// `None` until a full window was heard and played with enough of it
+    /// voiced to tell.
+    pub fn similarity(&self) -> Option<f32> {
+        let window = self.window_blocks;
+        if self.heard_prints.len() < window || self.played_prints.len() < window {
+            return None;
+        }
+        let min_compared = (window as f32 * MIN_VOICED_SHARE).ceil() as u32;
+        let mut best: Option<f32> = None;
+        for offset in 0..=self.played_prints.len() - window {
+            let mut compared = 0u32;
+            let mut matching = 0u32;
+            let played = self.played_prints.range(offset..offset + window);
+            for (heard, played) in self.heard_prints.iter().zip(played) {
+                if let (Some(heard), Some(played)) = (heard, played) {
+                    compared += 1;
+                    matching += BITS - (heard ^ played).count_ones();
+                }
+            }
+            if compared >= min_compared {
+                let similarity = matching as f32 / (compared * BITS) as f32;
+                best = Some(best.map_or(similarity, |best| best.max(similarity)));
+            }
+        }
+        best
+    }
+
+    /// Check the window heard against the audio played, returns the
+    /// similarity when it is echo
+    pub fn detect(&self) -> Option<f32> {
+        self.similarity()
+            .filter(|similarity| *similarity >= self.threshold)
+    }
+
+    /// Forget the window heard, e.g. once a loop was reported
+    pub fn reset_heard(&mut self) {
+        self.heard_prints.clear();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::transport::simulation::Rng;
+
+    /// Noise shaped like speech: loudness and spectral tilt change every
+    /// 10-40 ms at random
+    pub(crate) fn babble(seed: u64, samples: usize) -> Vec<i16> {
+        let mut rng = Rng::new(seed);
+        let mut pcm = Vec::with_capacity(samples);
+        let (mut gain, mut tilt, mut lowpass) = (0.0, 0.0, 0.0);
+        let mut hold = 0;
+        for _ in 0..samples {
+            if hold == 0 {
+                hold = 80 + (rng.next_f64() * 240.0) as usize;
+                gain = 6000.0 + rng.next_f64() * 14000.0;
+                tilt = rng.next_f64();
+            }
+            hold -= 1;
+            let noise = rng.next_f64() * 2.0 - 1.0;
+            lowpass += 0.2 * (noise - lowpass);
+            let sample = tilt * lowpass * 3.0 + (1.0 - tilt) * noise;
+            pcm.push((sample * gain).clamp(-32768.0, 32767.0) as i16);
+        }
+        pcm
+    }
+
+    /// The played audio as a microphone would pick it up: delayed, quieter
+    /// and with some noise
+    pub(crate) fn echo_of(played: &[i16], delay_samples: usize) -> Vec<i16> {
+        let mut rng = Rng::new(99);
+        std::iter::repeat_n(0, delay_samples)
+            .chain(played.iter().copied())
+            .map(|sample| (sample as f64 * 0.5 + (rng.next_f64() - 0.5) * 200.0) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn test_echo_matches_played_audio() {
+        let config = EchoLoopConfig::default();
+        let mut detector = EchoLoopDetector::new(&config, 8000);
+        let played = babble(1, 8000 * 3);
+        detector.record_played(&played);
+        assert_eq!(detector.similarity(), None);
+
+        detector.record_heard(&echo_of(&played[..8000 * 2], 8000 * 137 / 1000));
+        let similarity = detector.detect().unwrap();
+        assert!(similarity > 0.8, "{}", similarity);
+
+        detector.reset_heard();
+        assert_eq!(detector.similarity(), None);
+    }
+
+    #[test]
+    fn test_other_speech_does_not_match() {
+        let config = EchoLoopConfig::default();
+        let mut detector = EchoLoopDetector::new(&config, 16000);
+        detector.record_played(&babble(1, 16000 * 3));
+        detector.record_heard(&babble(2, 16000));
+        let similarity = detector.similarity().unwrap();
+        assert!(similarity < 0.7, "{}", similarity);
+        assert_eq!(detector.detect(), None);
+    }
+
+    #[test]
+    fn test_silence_is_not_compared() {
+        let mut detector = EchoLoopDetector::new(&EchoLoopConfig::default(), 8000);
+        detector.record_played(&[0i16; 8000]);
+        detector.record_heard(&[0i16; 8000]);
+        assert_eq!(detector.similarity(), None);
+    }
+}
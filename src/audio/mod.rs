@@ -1,11 +1,19 @@
 //! Audio processing module for Amwaj Media Server
 
+pub mod budget;
+pub mod calibration;
 pub mod features;
+pub mod fingerprint;
+pub mod pre_roll;
 pub mod processor;
 pub mod vad;
 pub mod voice_isolation;
 
-pub use features::{calculate_volume, estimate_pitch, AudioFeatures};
+pub use budget::{BudgetWatchdog, ProcessingBudget, StageTimings};
+pub use calibration::{VadCalibrationConfig, VadCalibrator};
+pub use features::{calculate_volume, estimate_pitch, AudioFeatures, PitchTracker};
+pub use fingerprint::EchoLoopDetector;
+pub use pre_roll::{PreRollBuffer, PreRollFrame};
 pub use processor::{AudioProcessor, ProcessedFrame};
 pub use vad::VoiceActivityDetector;
 pub use voice_isolation::VoiceIsolation;
@@ -0,0 +1,131 @@
+//! Pre-speech padding buffer
+//!
+//! Keeps a short rolling window of recent frames so that a segment can be
+//! emitted with its word onset intact once a turn starts. Audio older than
+//! the window is trimmed.
+
+use std::collections::VecDeque;
+
+/// Default pre-roll duration in milliseconds
+pub const DEFAULT_PRE_ROLL_MS: u32 = 200;
+
+/// A frame held in the pre-roll buffer
+#[derive(Debug, Clone)]
+pub struct PreRollFrame<S = f32> {
+    /// Frame timestamp
+    pub timestamp_ms: i64,
+    /// Processed audio samples
+    pub pcm: Vec<S>,
+}
+
+/// Rolling buffer of the most recent audio frames
+///
+/// Holds float samples by default; the recognizer tap keeps 16-bit PCM.
+pub struct PreRollBuffer<S = f32> {
+    frames: VecDeque<PreRollFrame<S>>,
+    max_frames: usize,
+    frame_duration_ms: u32,
+}
+
+impl<S: Clone> PreRollBuffer<S> {
+    /// Create a buffer holding `pre_roll_ms` of audio
+    pub fn new(pre_roll_ms: u32, frame_duration_ms: u32) -> Self {
+        let max_frames = pre_roll_ms.div_ceil(frame_duration_ms.max(1)) as usize;
+        Self {
+            frames: VecDeque::with_capacity(max_frames),
+            max_frames,
+            frame_duration_ms,
+        }
+    }
+
+    /// Push a frame, dropping the oldest once the window is full
+    pub fn push(&mut self, timestamp_ms: i64, pcm: &[S]) {
+        if self.max_frames == 0 {
+            return;
+        }
+
+        if self.frames.len() == self.max_frames {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(PreRollFrame {
+            timestamp_ms,
+            pcm: pcm.to_vec(),
+        });
+    }
+
+    /// Take all buffered frames, oldest first
+    pub fn drain(&mut self) -> Vec<PreRollFrame<S>> {
+        self.frames.drain(..).collect()
+    }
+
+    /// Get the memory held by the buffered samples
+    pub fn bytes(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|frame| std::mem::size_of_val(frame.pcm.as_slice()))
+            .sum()
+    }
+
+    /// Get the number of buffered frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Check if the buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Get the maximum number of frames held
+    pub fn capacity(&self) -> usize {
+        self.max_frames
+    }
+
+    /// Get the buffered audio duration in milliseconds
+    pub fn duration_ms(&self) -> u32 {
+        self.frames.len() as u32 * self.frame_duration_ms
+    }
+
+    /// Clear the buffer
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capacity_from_duration() {
+        let buffer: PreRollBuffer = PreRollBuffer::new(200, 20);
+        assert_eq!(buffer.capacity(), 10);
+
+        let buffer: PreRollBuffer = PreRollBuffer::new(210, 20);
+        assert_eq!(buffer.capacity(), 11);
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let mut buffer = PreRollBuffer::new(60, 20);
+
+        for i in 0..5 {
+            buffer.push(i * 20, &[i as f32]);
+        }
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.duration_ms(), 60);
+
+        let frames = buffer.drain();
+        let timestamps: Vec<i64> = frames.iter().map(|f| f.timestamp_ms).collect();
+        assert_eq!(timestamps, vec![40, 60, 80]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_zero_pre_roll() {
+        let mut buffer = PreRollBuffer::new(0, 20);
+        buffer.push(0, &[0.0]);
+        assert!(buffer.is_empty());
+    }
+}
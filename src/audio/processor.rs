@@ -1,7 +1,15 @@
 //! Audio Processor - Main audio processing pipeline
 
-use crate::audio::features::extract_features;
+use crate::audio::budget::{
+    BudgetVerdict, BudgetWatchdog, ProcessingBudget, ProcessingStage, StageTimings,
+};
+use crate::audio::calibration::VadCalibrationConfig;
+use crate::audio::features::{extract_features, PitchTracker};
+use crate::audio::pre_roll::{PreRollBuffer, PreRollFrame};
 use crate::audio::{AudioFeatures, VoiceActivityDetector, VoiceIsolation};
+use crate::metrics::{telemetry, Metrics};
+use std::sync::Arc;
+use std::time::Instant;
 
 /// Main audio processor that orchestrates the audio pipeline
 pub struct AudioProcessor {
@@ -10,8 +18,17 @@ pub struct AudioProcessor {
     voice_isolation: Option<VoiceIsolation>,
     vad: VoiceActivityDetector,
     frames_processed: u64,
+    watchdog: Option<BudgetWatchdog>,
+    metrics: Option<Arc<Metrics>>,
+    /// Codec label of the stage latencies
+    codec: &'static str,
+    pre_roll: Option<PreRollBuffer>,
+    pitch: PitchTracker,
 }
 
+/// Codec label of audio fed as PCM
+const DEFAULT_CODEC: &str = "pcm16";
+
 /// Result of processing an audio frame
 #[derive(Debug, Clone)]
 pub struct ProcessedFrame {
@@ -23,6 +40,8 @@ pub struct ProcessedFrame {
     pub vad_probability: f32,
     /// Frame timestamp
     pub timestamp_ms: i64,
+    /// Per-stage processing latency for this frame
+    pub stage_timings: StageTimings,
 }
 
 impl AudioProcessor {
@@ -34,6 +53,11 @@ impl AudioProcessor {
             voice_isolation: None,
             vad: VoiceActivityDetector::new(sample_rate),
             frames_processed: 0,
+            watchdog: None,
+            metrics: None,
+            codec: DEFAULT_CODEC,
+            pre_roll: None,
+            pitch: PitchTracker::new(sample_rate),
         }
     }
 
@@ -50,66 +74,132 @@ impl AudioProcessor {
             voice_isolation: Some(vi),
             vad: VoiceActivityDetector::new(sample_rate),
             frames_processed: 0,
+            watchdog: None,
+            metrics: None,
+            codec: DEFAULT_CODEC,
+            pre_roll: None,
+            pitch: PitchTracker::new(sample_rate),
         })
     }
 
+    /// Enforce a per-frame processing budget
+    pub fn with_budget(mut self, budget: ProcessingBudget) -> Self {
+        self.watchdog = Some(BudgetWatchdog::new(budget));
+        self
+    }
+
+    /// Keep a rolling pre-roll buffer of `pre_roll_ms` of processed audio
+    pub fn with_pre_roll(mut self, pre_roll_ms: u32) -> Self {
+        let frame_duration_ms = self.frame_duration_ms().max(1);
+        // One extra slot for the frame that triggers the turn
+        self.pre_roll = Some(PreRollBuffer::new(
+            pre_roll_ms + frame_duration_ms,
+            frame_duration_ms,
+        ));
+        self
+    }
+
+    /// Calibrate VAD probabilities per session
+    pub fn with_vad_calibration(mut self, config: VadCalibrationConfig) -> Self {
+        self.vad = VoiceActivityDetector::new(self.sample_rate).with_calibration(config);
+        self
+    }
+
+    /// Report stage latencies and budget overruns to metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Label the stage latencies with the codec the audio arrived in
+    pub fn with_codec(mut self, codec: &'static str) -> Self {
+        self.codec = codec;
+        self
+    }
+
     /// Process an audio frame (PCM i16)
     pub fn process_frame(&mut self, pcm_data: &[i16]) -> anyhow::Result<ProcessedFrame> {
-        self.frames_processed += 1;
-
         // Convert to float
         let float_data = pcm_to_float(pcm_data);
+        self.run_pipeline(float_data)
+    }
+
+    /// Process float audio frame directly
+    pub fn process_frame_float(&mut self, float_data: &[f32]) -> anyhow::Result<ProcessedFrame> {
+        self.run_pipeline(float_data.to_vec())
+    }
+
+    fn run_pipeline(&mut self, float_data: Vec<f32>) -> anyhow::Result<ProcessedFrame> {
+        self.frames_processed += 1;
+        let mut timings = StageTimings::default();
 
         // Apply voice isolation if available
+        let start = Instant::now();
         let isolated = if let Some(vi) = &mut self.voice_isolation {
             vi.isolate(&float_data)?
         } else {
             float_data
         };
+        timings.isolation_ms = elapsed_ms(start);
 
         // Extract audio features
-        let features = extract_features(&isolated, self.sample_rate);
+        let start = Instant::now();
+        let mut features = extract_features(&isolated, self.sample_rate);
+        // Frames are too short for pitch on their own
+        features.pitch_hz = self.pitch.push(&isolated);
+        timings.features_ms = elapsed_ms(start);
 
         // Run VAD
-        let vad_prob = self.vad.process(&isolated)?;
+        let start = Instant::now();
+        let vad_prob = telemetry::stage_span(ProcessingStage::Vad.as_str())
+            .in_scope(|| self.vad.process(&isolated))?;
+        timings.vad_ms = elapsed_ms(start);
+
+        self.enforce_budget(&timings);
 
         // Calculate timestamp
         let timestamp_ms = self.calculate_timestamp();
 
+        if let Some(pre_roll) = &mut self.pre_roll {
+            pre_roll.push(timestamp_ms, &isolated);
+        }
+
         Ok(ProcessedFrame {
             pcm: isolated,
             features,
             vad_probability: vad_prob,
             timestamp_ms,
+            stage_timings: timings,
         })
     }
 
-    /// Process float audio frame directly
-    pub fn process_frame_float(&mut self, float_data: &[f32]) -> anyhow::Result<ProcessedFrame> {
-        self.frames_processed += 1;
+    fn enforce_budget(&mut self, timings: &StageTimings) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_latency(timings.total_ms());
+            metrics.record_stage_timings(self.codec, timings);
+        }
 
-        // Apply voice isolation if available
-        let isolated = if let Some(vi) = &mut self.voice_isolation {
-            vi.isolate(float_data)?
-        } else {
-            float_data.to_vec()
+        let Some(watchdog) = &mut self.watchdog else {
+            return;
         };
 
-        // Extract audio features
-        let features = extract_features(&isolated, self.sample_rate);
-
-        // Run VAD
-        let vad_prob = self.vad.process(&isolated)?;
-
-        // Calculate timestamp
-        let timestamp_ms = self.calculate_timestamp();
-
-        Ok(ProcessedFrame {
-            pcm: isolated,
-            features,
-            vad_probability: vad_prob,
-            timestamp_ms,
-        })
+        match watchdog.check(timings) {
+            BudgetVerdict::WithinBudget => {}
+            BudgetVerdict::Exceeded => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_budget_overrun();
+                }
+            }
+            BudgetVerdict::Shed(stage) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_budget_overrun();
+                }
+                if let Some(vi) = &mut self.voice_isolation {
+                    tracing::warn!("Sustained overload, disabling {} stage", stage.as_str());
+                    vi.set_enabled(false);
+                }
+            }
+        }
     }
 
     /// Get sample rate
@@ -127,10 +217,66 @@ impl AudioProcessor {
         self.frames_processed
     }
 
+    /// Take the pre-roll frames preceding the most recently processed frame
+    ///
+    /// Call this when a turn starts so the segment can be emitted with its
+    /// onset. The triggering frame itself is not included.
+    pub fn take_pre_roll(&mut self) -> Vec<PreRollFrame> {
+        let Some(pre_roll) = &mut self.pre_roll else {
+            return Vec::new();
+        };
+
+        let mut frames = pre_roll.drain();
+        frames.pop();
+        frames
+    }
+
+    /// Get the memory held by the pre-roll buffer
+    pub fn pre_roll_bytes(&self) -> usize {
+        self.pre_roll.as_ref().map_or(0, PreRollBuffer::bytes)
+    }
+
+    /// Get the voice activity detector
+    pub fn vad(&self) -> &VoiceActivityDetector {
+        &self.vad
+    }
+
+    /// Get the budget watchdog, if a budget is enforced
+    pub fn budget_watchdog(&self) -> Option<&BudgetWatchdog> {
+        self.watchdog.as_ref()
+    }
+
     /// Reset processor state
+    ///
+    /// Stages shed by the budget watchdog are re-enabled.
     pub fn reset(&mut self) {
         self.vad.reset();
         self.frames_processed = 0;
+        self.pitch.reset();
+        if let Some(pre_roll) = &mut self.pre_roll {
+            pre_roll.clear();
+        }
+        if let Some(watchdog) = &mut self.watchdog {
+            if watchdog.shed_stage().is_some() {
+                if let Some(vi) = &mut self.voice_isolation {
+                    vi.set_enabled(true);
+                }
+            }
+            watchdog.reset();
+        }
+    }
+
+    /// Advance the media clock past a frame without processing it
+    ///
+    /// Returns the skipped frame's timestamp.
+    pub fn skip_frame(&mut self) -> i64 {
+        self.frames_processed += 1;
+        self.calculate_timestamp()
+    }
+
+    /// Continue the media clock of a migrated session
+    pub fn resume_at(&mut self, frames_processed: u64) {
+        self.frames_processed = frames_processed;
     }
 
     /// Enable or disable voice isolation
@@ -140,12 +286,20 @@ impl AudioProcessor {
         }
     }
 
+    fn frame_duration_ms(&self) -> u32 {
+        (self.frame_size as u64 * 1000 / self.sample_rate.max(1) as u64) as u32
+    }
+
     fn calculate_timestamp(&self) -> i64 {
         let frame_duration_ms = (self.frame_size as f64 / self.sample_rate as f64) * 1000.0;
         (self.frames_processed as f64 * frame_duration_ms) as i64
     }
 }
 
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
 /// Convert PCM i16 samples to float
 pub fn pcm_to_float(pcm: &[i16]) -> Vec<f32> {
     pcm.iter().map(|&x| x as f32 / 32768.0).collect()
@@ -197,6 +351,62 @@ mod tests {
         assert!(frame.vad_probability < 0.5);
     }
 
+    #[test]
+    fn test_stage_timings_recorded() {
+        let mut processor = AudioProcessor::new(16000, 320);
+        let frame = processor.process_frame(&vec![100i16; 320]).unwrap();
+
+        assert!(frame.stage_timings.total_ms() > 0.0);
+        assert!(processor.budget_watchdog().is_none());
+    }
+
+    #[test]
+    fn test_budget_overload_sheds_isolation() {
+        let budget = ProcessingBudget {
+            frame_budget_ms: 0.0,
+            overload_frames: 2,
+            ..ProcessingBudget::default()
+        };
+        let mut processor =
+            AudioProcessor::with_voice_isolation(16000, 320, "model.onnx".to_string())
+                .unwrap()
+                .with_budget(budget);
+
+        for _ in 0..3 {
+            processor.process_frame(&vec![100i16; 320]).unwrap();
+        }
+
+        let watchdog = processor.budget_watchdog().unwrap();
+        assert!(watchdog.shed_stage().is_some());
+        assert!(watchdog.total_overruns() >= 2);
+
+        processor.reset();
+        assert!(processor.budget_watchdog().unwrap().shed_stage().is_none());
+    }
+
+    #[test]
+    fn test_pre_roll_excludes_trigger_frame() {
+        let mut processor = AudioProcessor::new(16000, 320).with_pre_roll(60);
+
+        for _ in 0..6 {
+            processor.process_frame(&vec![100i16; 320]).unwrap();
+        }
+
+        let frames = processor.take_pre_roll();
+        let timestamps: Vec<i64> = frames.iter().map(|f| f.timestamp_ms).collect();
+        assert_eq!(timestamps, vec![60, 80, 100]);
+
+        // Buffer is consumed
+        assert!(processor.take_pre_roll().is_empty());
+    }
+
+    #[test]
+    fn test_pre_roll_disabled() {
+        let mut processor = AudioProcessor::new(16000, 320);
+        processor.process_frame(&vec![100i16; 320]).unwrap();
+        assert!(processor.take_pre_roll().is_empty());
+    }
+
     #[test]
     fn test_pcm_conversion_roundtrip() {
         let original = vec![100i16, -200, 32000, -32000, 0];
@@ -1,5 +1,7 @@
 //! Voice Activity Detection (VAD)
 
+use crate::audio::calibration::{energy_to_db, VadCalibrationConfig, VadCalibrator};
+
 /// Voice Activity Detector using energy-based detection
 pub struct VoiceActivityDetector {
     sample_rate: u32,
@@ -7,6 +9,7 @@ pub struct VoiceActivityDetector {
     smoothing_factor: f32,
     previous_prob: f32,
     frame_count: u64,
+    calibrator: Option<VadCalibrator>,
 }
 
 impl VoiceActivityDetector {
@@ -18,6 +21,7 @@ impl VoiceActivityDetector {
             smoothing_factor: 0.7,
             previous_prob: 0.0,
             frame_count: 0,
+            calibrator: None,
         }
     }
 
@@ -29,9 +33,18 @@ impl VoiceActivityDetector {
             smoothing_factor: 0.7,
             previous_prob: 0.0,
             frame_count: 0,
+            calibrator: None,
         }
     }
 
+    /// Calibrate probabilities against a per-session energy histogram
+    ///
+    /// Until the calibrator has warmed up the uncalibrated score is used.
+    pub fn with_calibration(mut self, config: VadCalibrationConfig) -> Self {
+        self.calibrator = Some(VadCalibrator::new(config));
+        self
+    }
+
     /// Process an audio frame and return VAD probability
     pub fn process(&mut self, audio: &[f32]) -> anyhow::Result<f32> {
         if audio.is_empty() {
@@ -44,7 +57,7 @@ impl VoiceActivityDetector {
         let energy = audio.iter().map(|x| x * x).sum::<f32>() / audio.len() as f32;
 
         // Calculate raw probability based on energy
-        let raw_prob = if energy > self.energy_threshold {
+        let uncalibrated = if energy > self.energy_threshold {
             // Logarithmic scaling for better sensitivity
             let ratio = (energy / self.energy_threshold).ln();
             (ratio / 5.0).clamp(0.0, 1.0) // Scale and clamp
@@ -52,6 +65,15 @@ impl VoiceActivityDetector {
             0.0
         };
 
+        let raw_prob = match &mut self.calibrator {
+            Some(calibrator) => {
+                let energy_db = energy_to_db(energy);
+                calibrator.observe(energy_db);
+                calibrator.calibrate(energy_db).unwrap_or(uncalibrated)
+            }
+            None => uncalibrated,
+        };
+
         // Apply temporal smoothing
         let smoothed_prob =
             self.smoothing_factor * raw_prob + (1.0 - self.smoothing_factor) * self.previous_prob;
@@ -68,6 +90,9 @@ impl VoiceActivityDetector {
     }
 
     /// Reset the VAD state
+    ///
+    /// The calibration histogram is kept, it describes the device rather
+    /// than the current turn.
     pub fn reset(&mut self) {
         self.previous_prob = 0.0;
         self.frame_count = 0;
@@ -83,6 +108,11 @@ impl VoiceActivityDetector {
         self.frame_count
     }
 
+    /// Get the calibrator, if calibration is enabled
+    pub fn calibrator(&self) -> Option<&VadCalibrator> {
+        self.calibrator.as_ref()
+    }
+
     /// Update the energy threshold adaptively
     pub fn adapt_threshold(&mut self, noise_floor: f32) {
         // Set threshold slightly above noise floor
@@ -133,6 +163,29 @@ mod tests {
         assert!(prob2 > 0.0 && prob2 < 1.0);
     }
 
+    #[test]
+    fn test_vad_calibration() {
+        let config = VadCalibrationConfig {
+            warmup_frames: 20,
+            ..Default::default()
+        };
+        let mut quiet = VoiceActivityDetector::new(16000).with_calibration(config.clone());
+        let mut loud = VoiceActivityDetector::new(16000).with_calibration(config);
+
+        // Same speech-to-noise ratio at 20 dB different gain
+        let mut quiet_prob = 0.0;
+        let mut loud_prob = 0.0;
+        for i in 0..100 {
+            let level = if i % 4 == 3 { 0.03f32 } else { 0.001 };
+            quiet_prob = quiet.process(&vec![level; 320]).unwrap();
+            loud_prob = loud.process(&vec![level * 10.0; 320]).unwrap();
+        }
+
+        assert!(quiet.calibrator().unwrap().frames_observed() == 100);
+        assert!((quiet_prob - loud_prob).abs() < 0.05);
+        assert!(quiet_prob > 0.6);
+    }
+
     #[test]
     fn test_vad_reset() {
         let mut vad = VoiceActivityDetector::new(16000);
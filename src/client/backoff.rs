@@ -0,0 +1,83 @@
+//! Exponential backoff between reconnect attempts
+
+use std::time::Duration;
+
+/// Exponential backoff policy
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    max_attempts: Option<u32>,
+    attempt: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(10))
+    }
+}
+
+impl Backoff {
+    /// Create a backoff doubling from `initial` up to `max`, retrying forever
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            multiplier: 2.0,
+            max_attempts: None,
+            attempt: 0,
+        }
+    }
+
+    /// Set the growth factor between attempts
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Give up after `max_attempts` retries
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Get the delay before the next attempt, `None` once exhausted
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self
+            .max_attempts
+            .is_some_and(|max_attempts| self.attempt >= max_attempts)
+        {
+            return None;
+        }
+        let delay = self
+            .initial
+            .mul_f64(self.multiplier.powi(self.attempt as i32))
+            .min(self.max);
+        self.attempt += 1;
+        Some(delay)
+    }
+
+    /// Start over after a successful attempt
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delays() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500))
+            .with_max_attempts(5);
+        let delays: Vec<u64> = std::iter::from_fn(|| backoff.next_delay())
+            .map(|delay| delay.as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+    }
+}
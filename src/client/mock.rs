@@ -0,0 +1,240 @@
+//! In-process mock server for client tests
+//!
+//! Records the commands it receives, acks them and lets the test push
+//! events or drop the connected streams. Only `MediaStream` and
+//! `ServerCapabilities` are implemented.
+
+use crate::config::Config;
+use crate::grpc::capabilities;
+use crate::grpc::service::CommandStatus;
+use crate::proto;
+use crate::proto::amwaj_media_server_server::{AmwajMediaServer, AmwajMediaServerServer};
+use parking_lot::Mutex;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status, Streaming};
+
+type EventSender = mpsc::Sender<Result<proto::MediaEvent, Status>>;
+
+#[derive(Default)]
+struct MockState {
+    commands: Vec<proto::OrchestrationCommand>,
+    streams: Vec<EventSender>,
+    rejection: Option<String>,
+}
+
+/// Mock Amwaj Media server on a local port
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    /// Start the server on a free port
+    pub async fn start() -> anyhow::Result<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+
+        let service = MockService {
+            state: Arc::clone(&state),
+        };
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(AmwajMediaServerServer::new(service))
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    let _ = shutdown_rx.await;
+                }),
+        );
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Get the URL to connect to
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Get the commands received so far
+    pub fn commands(&self) -> Vec<proto::OrchestrationCommand> {
+        self.state.lock().commands.clone()
+    }
+
+    /// Get the number of open media streams
+    pub fn stream_count(&self) -> usize {
+        let mut state = self.state.lock();
+        state.streams.retain(|stream| !stream.is_closed());
+        state.streams.len()
+    }
+
+    /// Reject further commands with a reason, or accept them again
+    pub fn reject_commands(&self, reason: Option<&str>) {
+        self.state.lock().rejection = reason.map(str::to_string);
+    }
+
+    /// Send an event to every open media stream, returns how many got it
+    pub fn send_event(&self, event: proto::MediaEvent) -> usize {
+        self.state
+            .lock()
+            .streams
+            .iter()
+            .filter(|stream| stream.try_send(Ok(event.clone())).is_ok())
+            .count()
+    }
+
+    /// Fail every open media stream, as if the server restarted
+    pub fn disconnect_streams(&self) {
+        for stream in self.state.lock().streams.drain(..) {
+            let _ = stream.try_send(Err(Status::unavailable("Mock disconnect")));
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+struct MockService {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockService {
+    async fn run_stream(
+        state: Arc<Mutex<MockState>>,
+        mut inbound: Streaming<proto::OrchestrationCommand>,
+        sender: EventSender,
+    ) {
+        while let Ok(Some(command)) = inbound.message().await {
+            let ack = {
+                let mut state = state.lock();
+                state.commands.push(command.clone());
+                let (status, reason) = match &state.rejection {
+                    Some(reason) => (CommandStatus::Rejected, reason.clone()),
+                    None => (CommandStatus::Completed, String::new()),
+                };
+                proto::MediaEvent {
+                    session_id: command.session_id.clone(),
+                    timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                    event: Some(proto::media_event::Event::CommandAck(proto::CommandAck {
+                        command_id: command.command_id.clone(),
+                        status: proto::command_ack::Status::from(status) as i32,
+                        reason,
+                    })),
+                }
+            };
+            if !command.command_id.is_empty() && sender.send(Ok(ack)).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl AmwajMediaServer for MockService {
+    type MediaStreamStream = ReceiverStream<Result<proto::MediaEvent, Status>>;
+
+    async fn media_stream(
+        &self,
+        request: Request<Streaming<proto::OrchestrationCommand>>,
+    ) -> Result<Response<Self::MediaStreamStream>, Status> {
+        if let Some(status) = capabilities::negotiate(request.metadata()) {
+            return Err(status);
+        }
+        let (sender, receiver) = mpsc::channel(100);
+        self.state.lock().streams.push(sender.clone());
+        tokio::spawn(Self::run_stream(
+            Arc::clone(&self.state),
+            request.into_inner(),
+            sender,
+        ));
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn server_capabilities(
+        &self,
+        _request: Request<proto::ServerCapabilitiesRequest>,
+    ) -> Result<Response<proto::Capabilities>, Status> {
+        Ok(Response::new(capabilities::server_capabilities(
+            &Config::default(),
+        )))
+    }
+
+    async fn get_detection_debug(
+        &self,
+        _request: Request<proto::DetectionDebugRequest>,
+    ) -> Result<Response<proto::DetectionDebugResponse>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+
+    async fn stream_audio_in(
+        &self,
+        _request: Request<Streaming<proto::AudioChunk>>,
+    ) -> Result<Response<proto::StreamAudioInSummary>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+
+    type StreamAudioOutStream = ReceiverStream<Result<proto::AudioChunk, Status>>;
+
+    async fn stream_audio_out(
+        &self,
+        _request: Request<proto::StreamAudioOutRequest>,
+    ) -> Result<Response<Self::StreamAudioOutStream>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+
+    type SignalStream = ReceiverStream<Result<proto::SignalMessage, Status>>;
+
+    async fn signal(
+        &self,
+        _request: Request<Streaming<proto::SignalMessage>>,
+    ) -> Result<Response<Self::SignalStream>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+
+    async fn create_session(
+        &self,
+        _request: Request<proto::CreateSessionRequest>,
+    ) -> Result<Response<proto::SessionInfo>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+
+    async fn get_session(
+        &self,
+        _request: Request<proto::GetSessionRequest>,
+    ) -> Result<Response<proto::SessionInfo>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+
+    async fn get_session_owner(
+        &self,
+        _request: Request<proto::GetSessionRequest>,
+    ) -> Result<Response<proto::SessionOwner>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+
+    async fn get_session_history(
+        &self,
+        _request: Request<proto::GetSessionRequest>,
+    ) -> Result<Response<proto::SessionHistory>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+
+    async fn end_session(
+        &self,
+        _request: Request<proto::EndSessionRequest>,
+    ) -> Result<Response<proto::SessionEnded>, Status> {
+        Err(Status::unimplemented("Not mocked"))
+    }
+}
@@ -0,0 +1,197 @@
+//! Rust client for the Amwaj Media gRPC API
+//!
+//! Wraps the generated client with what every orchestrator needs: request
+//! authentication, reconnects with backoff, a media stream whose commands
+//! are matched to their acks, and a mock server for tests.
+//!
+//! ```rust,ignore
+//! let client = MediaClient::new("http://127.0.0.1:50051").with_api_key("key-123");
+//! let (commands, mut events) = client.open_stream().await?;
+//! commands.send(stop_audio).await?;
+//! while let Some(event) = events.next().await {
+//!     // ...
+//! }
+//! ```
+
+pub mod backoff;
+pub mod mock;
+pub mod stream;
+
+pub use backoff::Backoff;
+pub use mock::MockServer;
+pub use stream::{CommandSender, EventStream};
+
+pub use crate::error::{error_code, is_retryable};
+
+use crate::grpc::capabilities::{SCHEMA_VERSION, SCHEMA_VERSION_HEADER};
+use crate::grpc::subscription::{Subscription, AUDIO_DECIMATION_HEADER, EVENT_MASK_HEADER};
+use crate::proto;
+use crate::proto::amwaj_media_server_client::AmwajMediaServerClient;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status, Streaming};
+
+/// Generated client with authentication applied
+pub type GrpcClient = AmwajMediaServerClient<InterceptedService<Channel, ClientAuth>>;
+
+/// Interceptor adding credentials to every request
+#[derive(Debug, Clone, Default)]
+pub struct ClientAuth {
+    api_key: Option<MetadataValue<Ascii>>,
+    bearer_token: Option<MetadataValue<Ascii>>,
+}
+
+impl Interceptor for ClientAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(api_key) = &self.api_key {
+            request.metadata_mut().insert("x-api-key", api_key.clone());
+        }
+        if let Some(bearer_token) = &self.bearer_token {
+            request
+                .metadata_mut()
+                .insert("authorization", bearer_token.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Client for an Amwaj Media server
+#[derive(Debug, Clone)]
+pub struct MediaClient {
+    endpoint: String,
+    api_key: Option<String>,
+    bearer_token: Option<String>,
+    backoff: Backoff,
+    subscription: Subscription,
+    ack_timeout: Duration,
+}
+
+impl MediaClient {
+    /// Create a client for a server URL, e.g. `http://127.0.0.1:50051`
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            api_key: None,
+            bearer_token: None,
+            backoff: Backoff::default(),
+            subscription: Subscription::default(),
+            ack_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Authenticate with an API key
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Authenticate with a JWT bearer token
+    pub fn with_bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    /// Set the backoff between connection attempts
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Receive only some events on media streams
+    pub fn with_subscription(mut self, subscription: Subscription) -> Self {
+        self.subscription = subscription;
+        self
+    }
+
+    /// Set how long `CommandSender::send` waits for an ack
+    pub fn with_ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    /// Get the server URL
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Connect, retrying with backoff, for unary RPCs
+    pub async fn grpc_client(&self) -> anyhow::Result<GrpcClient> {
+        let mut backoff = self.backoff.clone();
+        loop {
+            match self.connect().await {
+                Ok(client) => return Ok(client),
+                Err(e) => match backoff.next_delay() {
+                    Some(delay) => {
+                        tracing::debug!("Connecting to {} failed, retrying: {}", self.endpoint, e);
+                        tokio::time::sleep(delay).await;
+                    }
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+
+    /// Open a media stream that reconnects when the server goes away
+    ///
+    /// Sessions are attached to the new stream by their next command.
+    pub async fn open_stream(&self) -> anyhow::Result<(CommandSender, EventStream)> {
+        let (outbound, inbound) = self.open_media_stream().await?;
+        Ok(stream::spawn(self.clone(), outbound, inbound))
+    }
+
+    /// Connect once
+    async fn connect(&self) -> anyhow::Result<GrpcClient> {
+        let parse = |value: String| {
+            value
+                .parse::<MetadataValue<Ascii>>()
+                .map_err(|_| anyhow::anyhow!("Credentials must be ASCII"))
+        };
+        let auth = ClientAuth {
+            api_key: self.api_key.clone().map(parse).transpose()?,
+            bearer_token: self
+                .bearer_token
+                .as_ref()
+                .map(|token| parse(format!("Bearer {}", token)))
+                .transpose()?,
+        };
+
+        let channel = Endpoint::from_shared(self.endpoint.clone())?
+            .connect()
+            .await?;
+        Ok(AmwajMediaServerClient::with_interceptor(channel, auth))
+    }
+
+    /// Open the raw media stream, returns its command sender and events
+    async fn open_media_stream(
+        &self,
+    ) -> anyhow::Result<(
+        mpsc::Sender<proto::OrchestrationCommand>,
+        Streaming<proto::MediaEvent>,
+    )> {
+        let mut client = self.grpc_client().await?;
+        let (outbound, receiver) = mpsc::channel(stream::COMMAND_CHANNEL_CAPACITY);
+
+        let mut request = Request::new(ReceiverStream::new(receiver));
+        request
+            .metadata_mut()
+            .insert(SCHEMA_VERSION_HEADER, SCHEMA_VERSION.to_string().parse()?);
+        if self.subscription != Subscription::default() {
+            let metadata = request.metadata_mut();
+            metadata.insert(
+                EVENT_MASK_HEADER,
+                self.subscription.categories.bits().to_string().parse()?,
+            );
+            metadata.insert(
+                AUDIO_DECIMATION_HEADER,
+                self.subscription.audio_decimation.to_string().parse()?,
+            );
+        }
+        let inbound = client.media_stream(request).await?.into_inner();
+        Ok((outbound, inbound))
+    }
+}
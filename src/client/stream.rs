@@ -0,0 +1,193 @@
+//! Reconnecting media stream
+
+use crate::client::MediaClient;
+use crate::grpc::service::CommandStatus;
+use crate::proto;
+use crate::proto::media_event::Event;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::Stream;
+use tonic::Streaming;
+
+/// Buffered commands per stream
+pub(crate) const COMMAND_CHANNEL_CAPACITY: usize = 64;
+
+/// Buffered events per stream
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Commands waiting for their ack, by command ID
+type PendingAcks = Arc<Mutex<HashMap<String, oneshot::Sender<proto::CommandAck>>>>;
+
+/// Sends commands on a media stream
+#[derive(Clone)]
+pub struct CommandSender {
+    commands: mpsc::Sender<proto::OrchestrationCommand>,
+    pending: PendingAcks,
+    ack_timeout: Duration,
+}
+
+impl CommandSender {
+    /// Send a command and wait for its ack
+    ///
+    /// A command ID is generated unless set. Rejected commands are errors.
+    pub async fn send(
+        &self,
+        mut command: proto::OrchestrationCommand,
+    ) -> anyhow::Result<CommandStatus> {
+        if command.command_id.is_empty() {
+            command.command_id = uuid::Uuid::new_v4().to_string();
+        }
+        let command_id = command.command_id.clone();
+        let (ack_tx, ack_rx) = oneshot::channel();
+        self.pending.lock().insert(command_id.clone(), ack_tx);
+
+        if self.commands.send(command).await.is_err() {
+            self.pending.lock().remove(&command_id);
+            return Err(anyhow::anyhow!("Media stream closed"));
+        }
+        let ack = match tokio::time::timeout(self.ack_timeout, ack_rx).await {
+            Ok(Ok(ack)) => ack,
+            Ok(Err(_)) => return Err(anyhow::anyhow!("Media stream lost before the ack")),
+            Err(_) => {
+                self.pending.lock().remove(&command_id);
+                return Err(anyhow::anyhow!("No ack for command {}", command_id));
+            }
+        };
+
+        match CommandStatus::from(ack.status()) {
+            CommandStatus::Rejected => Err(anyhow::anyhow!("Command rejected: {}", ack.reason)),
+            status => Ok(status),
+        }
+    }
+
+    /// Send a command without waiting for an ack
+    pub async fn send_nowait(
+        &self,
+        mut command: proto::OrchestrationCommand,
+    ) -> anyhow::Result<()> {
+        command.command_id.clear();
+        self.commands
+            .send(command)
+            .await
+            .map_err(|_| anyhow::anyhow!("Media stream closed"))
+    }
+}
+
+/// Events of a media stream, across reconnects
+///
+/// Command acks are consumed by `CommandSender::send`. The stream ends once
+/// the commands are dropped or reconnecting gives up.
+pub struct EventStream {
+    events: mpsc::Receiver<proto::MediaEvent>,
+}
+
+impl EventStream {
+    /// Wait for the next event
+    pub async fn next(&mut self) -> Option<proto::MediaEvent> {
+        self.events.recv().await
+    }
+}
+
+impl Stream for EventStream {
+    type Item = proto::MediaEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// Start relaying a media stream in the background
+pub(crate) fn spawn(
+    client: MediaClient,
+    outbound: mpsc::Sender<proto::OrchestrationCommand>,
+    inbound: Streaming<proto::MediaEvent>,
+) -> (CommandSender, EventStream) {
+    let (commands_tx, commands) = mpsc::channel(COMMAND_CHANNEL_CAPACITY);
+    let (events_tx, events) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+    let pending = PendingAcks::default();
+
+    let relay = Relay {
+        client: client.clone(),
+        commands,
+        events: events_tx,
+        pending: Arc::clone(&pending),
+    };
+    tokio::spawn(relay.run(outbound, inbound));
+
+    let sender = CommandSender {
+        commands: commands_tx,
+        pending,
+        ack_timeout: client.ack_timeout,
+    };
+    (sender, EventStream { events })
+}
+
+/// Moves commands and events between the caller and the current stream
+struct Relay {
+    client: MediaClient,
+    commands: mpsc::Receiver<proto::OrchestrationCommand>,
+    events: mpsc::Sender<proto::MediaEvent>,
+    pending: PendingAcks,
+}
+
+impl Relay {
+    async fn run(
+        mut self,
+        mut outbound: mpsc::Sender<proto::OrchestrationCommand>,
+        mut inbound: Streaming<proto::MediaEvent>,
+    ) {
+        loop {
+            let connected = tokio::select! {
+                command = self.commands.recv() => match command {
+                    Some(command) => outbound.send(command).await.is_ok(),
+                    // Every sender is gone, close the stream
+                    None => return,
+                },
+                event = inbound.message() => match event {
+                    Ok(Some(event)) => {
+                        self.deliver(event).await;
+                        true
+                    }
+                    Ok(None) => false,
+                    Err(status) => {
+                        tracing::warn!("Media stream error: {}", status);
+                        false
+                    }
+                },
+            };
+            if connected {
+                continue;
+            }
+
+            // Acks of commands sent on the lost stream never arrive
+            self.pending.lock().clear();
+            match self.client.open_media_stream().await {
+                Ok((new_outbound, new_inbound)) => {
+                    tracing::info!("Media stream reconnected to {}", self.client.endpoint);
+                    outbound = new_outbound;
+                    inbound = new_inbound;
+                }
+                Err(e) => {
+                    tracing::error!("Giving up on media stream: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, event: proto::MediaEvent) {
+        if let Some(Event::CommandAck(ack)) = &event.event {
+            if let Some(waiter) = self.pending.lock().remove(&ack.command_id) {
+                let _ = waiter.send(ack.clone());
+            }
+            return;
+        }
+        // The caller may only be interested in acks
+        let _ = self.events.send(event).await;
+    }
+}
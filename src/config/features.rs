@@ -0,0 +1,148 @@
+//! Settings that need a Cargo feature
+//!
+//! A configuration turning on something this build was compiled without,
+//! like a Redis session store without the `redis-feature`, is rejected at
+//! startup rather than found out when the first session needs it.
+
+use super::Config;
+use crate::metrics::prometheus::FEATURES;
+
+/// A Cargo feature, whether this build has it and what asks for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    pub feature: &'static str,
+    /// Compiled into this build
+    pub compiled: bool,
+    /// Settings of the configuration that need the feature
+    pub required_by: Vec<&'static str>,
+}
+
+impl Capability {
+    /// Check if the configuration needs the feature this build lacks
+    pub fn is_missing(&self) -> bool {
+        !self.compiled && !self.required_by.is_empty()
+    }
+}
+
+/// Settings of the configuration needing each feature
+fn requirements(config: &Config) -> Vec<(&'static str, &'static str)> {
+    let mut required = Vec::new();
+    if config.sessions.redis_url.is_some() || config.sessions.redis_url_file.is_some() {
+        required.push(("redis-feature", "sessions.redis_url"));
+    }
+    if config.sinks.kafka.is_some() {
+        required.push(("kafka-feature", "sinks.kafka"));
+    }
+    if config.sinks.nats.is_some() {
+        required.push(("nats-feature", "sinks.nats"));
+    }
+    if config
+        .cdr
+        .as_ref()
+        .is_some_and(|cdr| cdr.postgres.is_some())
+    {
+        required.push(("postgres-feature", "cdr.postgres"));
+    }
+    if config.detection.endpointing.is_some() {
+        required.push(("audio-feature", "detection.endpointing"));
+    }
+    if config.transports.sip.is_some() {
+        required.push(("sip-feature", "transports.sip"));
+    }
+    if config.metrics.enable_tracing {
+        required.push(("otel-feature", "metrics.enable_tracing"));
+    }
+    required
+}
+
+/// Get every feature with whether it is compiled in and needed
+pub fn capabilities(config: &Config) -> Vec<Capability> {
+    let required = requirements(config);
+    FEATURES
+        .into_iter()
+        .map(|(feature, compiled)| Capability {
+            feature,
+            compiled,
+            required_by: required
+                .iter()
+                .filter(|(needed, _)| *needed == feature)
+                .map(|(_, setting)| *setting)
+                .collect(),
+        })
+        .collect()
+}
+
+/// Fail if the configuration needs a feature this build lacks
+///
+/// The error names every such setting and the feature it needs.
+pub fn check(config: &Config) -> anyhow::Result<()> {
+    let missing: Vec<String> = capabilities(config)
+        .into_iter()
+        .filter(Capability::is_missing)
+        .flat_map(|capability| {
+            capability
+                .required_by
+                .into_iter()
+                .map(move |setting| format!("{} needs the {}", setting, capability.feature))
+        })
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "Configuration needs features this build lacks: {}; rebuild with --features or remove the settings",
+        missing.join(", ")
+    ))
+}
+
+/// Log which features are compiled in and which the configuration uses
+pub fn log_capabilities(config: &Config) {
+    for capability in capabilities(config) {
+        let state = if capability.compiled { "on" } else { "off" };
+        if capability.required_by.is_empty() {
+            tracing::info!("Feature {}: {}", capability.feature, state);
+        } else {
+            tracing::info!(
+                "Feature {}: {}, used by {}",
+                capability.feature,
+                state,
+                capability.required_by.join(", ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let mut config = Config::default();
+        assert!(check(&config).is_ok());
+        assert!(capabilities(&config)
+            .iter()
+            .all(|capability| capability.required_by.is_empty()));
+
+        config.sessions.redis_url = Some("redis://redis:6379".into());
+        config.metrics.enable_tracing = true;
+        let capabilities = capabilities(&config);
+        let redis = capabilities
+            .iter()
+            .find(|capability| capability.feature == "redis-feature")
+            .unwrap();
+        assert_eq!(redis.required_by, vec!["sessions.redis_url"]);
+        assert_eq!(redis.compiled, cfg!(feature = "redis-feature"));
+
+        let result = check(&config);
+        if cfg!(all(feature = "redis-feature", feature = "otel-feature")) {
+            assert!(result.is_ok());
+        } else {
+            let message = result.unwrap_err().to_string();
+            assert_eq!(
+                message.contains("sessions.redis_url needs the redis-feature"),
+                !cfg!(feature = "redis-feature")
+            );
+        }
+    }
+}
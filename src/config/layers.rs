@@ -0,0 +1,349 @@
+//! Layered configuration
+//!
+//! The effective configuration is the file, or the defaults without one,
+//! overlaid by the selected `[profile.<name>]` table of the file, then by
+//! the variables platforms set (`REDIS_URL`, `HOSTNAME`, `POD_IP`), then by
+//! `AMWAJ__` environment variables, then by `--set` flags. The last two name
+//! a field by its path: `AMWAJ__SERVER__PORT=50052` and `--set server.port=50052`
+//! set the same field. Values are read as TOML, so `true`, `0.5` or
+//! `["gzip"]` keep their type; anything that doesn't parse is a string.
+
+use super::Config;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Prefix of the environment variables overriding configuration fields
+pub const ENV_PREFIX: &str = "AMWAJ__";
+
+/// Separator of the path segments in environment variable names
+const ENV_SEPARATOR: &str = "__";
+
+/// Environment variable selecting the profile
+pub const PROFILE_ENV: &str = "AMWAJ_PROFILE";
+
+/// Table of the file holding the profiles
+const PROFILES_KEY: &str = "profile";
+
+/// Settings deployments pass in the environment, under the `AMWAJ__` layer
+#[derive(Debug, Clone, Default)]
+struct Platform {
+    /// `REDIS_URL`, a secret kept out of the file
+    redis_url: Option<String>,
+    /// `HOSTNAME`, the push gateway instance when unset
+    hostname: Option<String>,
+    /// `POD_IP`, the advertised address with the server port when unset
+    pod_ip: Option<String>,
+}
+
+impl Platform {
+    fn from_env() -> Self {
+        Self {
+            redis_url: std::env::var("REDIS_URL").ok(),
+            hostname: std::env::var("HOSTNAME").ok(),
+            pod_ip: std::env::var("POD_IP").ok(),
+        }
+    }
+
+    /// Overlay the Redis URL, replacing any URL file below it
+    fn overlay(&self, value: &mut Value) {
+        let (Some(redis_url), Some(config)) = (&self.redis_url, value.as_table_mut()) else {
+            return;
+        };
+        let sessions = config
+            .entry("sessions")
+            .or_insert_with(|| Value::Table(Table::new()));
+        if let Some(sessions) = sessions.as_table_mut() {
+            sessions.remove("redis_url_file");
+            sessions.insert("redis_url".to_string(), Value::String(redis_url.clone()));
+        }
+    }
+
+    /// Fill in what the layers above left unset
+    fn fill_in(&self, config: &mut Config) {
+        let sessions = &mut config.sessions;
+        // A URL file set over REDIS_URL takes its place
+        if sessions.redis_url_file.is_some()
+            && sessions.redis_url.as_ref().map(|url| url.expose()) == self.redis_url.as_deref()
+        {
+            sessions.redis_url = None;
+        }
+        if let Some(push) = &mut config.metrics.push {
+            if push.instance.is_none() {
+                push.instance = self.hostname.clone();
+            }
+        }
+        if let (None, Some(pod_ip)) = (&config.sessions.advertise_address, &self.pod_ip) {
+            config.sessions.advertise_address = Some(format!("{}:{}", pod_ip, config.server.port));
+        }
+    }
+}
+
+/// What goes over the config file
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    /// Profile overlaid on the file, `AMWAJ_PROFILE` when unset
+    pub profile: Option<String>,
+    /// `path=value` pairs applied last
+    pub overrides: Vec<String>,
+}
+
+impl Layers {
+    /// Get the selected profile, from the flag or the environment
+    pub fn selected_profile(&self) -> Option<String> {
+        self.profile
+            .clone()
+            .or_else(|| std::env::var(PROFILE_ENV).ok())
+            .filter(|profile| !profile.is_empty())
+    }
+}
+
+/// Get the overrides set in the environment, as `path=value`
+pub fn env_overrides() -> Vec<String> {
+    let mut overrides: Vec<String> = std::env::vars()
+        .filter_map(|(name, value)| {
+            let path = name.strip_prefix(ENV_PREFIX)?;
+            let path = path
+                .split(ENV_SEPARATOR)
+                .map(str::to_ascii_lowercase)
+                .collect::<Vec<_>>()
+                .join(".");
+            Some(format!("{}={}", path, value))
+        })
+        .collect();
+    overrides.sort_unstable();
+    overrides
+}
+
+/// Load the configuration from its layers
+///
+/// A missing file leaves the defaults in place, one that doesn't parse is
+/// an error, as is selecting a profile the file doesn't have. Secrets are
+/// resolved last, see `secrets`.
+pub fn load(path: Option<&Path>, layers: &Layers) -> anyhow::Result<Config> {
+    load_over(path, layers, &Platform::from_env())
+}
+
+fn load_over(path: Option<&Path>, layers: &Layers, platform: &Platform) -> anyhow::Result<Config> {
+    // The file replaces the defaults rather than overlaying them, so
+    // leaving out an optional setting still turns it off
+    let mut file = match path.filter(|path| path.exists()) {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)?;
+            toml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))?
+        }
+        None => match Value::try_from(Config::default())? {
+            Value::Table(defaults) => defaults,
+            _ => unreachable!("the configuration serializes to a table"),
+        },
+    };
+    let mut profiles = match file.remove(PROFILES_KEY) {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => return Err(anyhow::anyhow!("[{}] must be a table", PROFILES_KEY)),
+        None => Table::new(),
+    };
+    let mut value = Value::Table(file);
+    if let Some(name) = layers.selected_profile() {
+        let profile = profiles
+            .remove(&name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown config profile: {}", name))?;
+        merge(&mut value, profile);
+    }
+    platform.overlay(&mut value);
+    for entry in env_overrides().iter().chain(&layers.overrides) {
+        apply_override(&mut value, entry)?;
+    }
+    let mut config: Config = value.try_into()?;
+    platform.fill_in(&mut config);
+    super::secrets::resolve_all(&mut config)?;
+    Ok(config)
+}
+
+/// Set one field from a `path=value` pair
+pub fn apply_override(config: &mut Value, entry: &str) -> anyhow::Result<()> {
+    let (path, raw) = entry
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Override {:?} is not path=value", entry))?;
+    let mut segments = path.trim().split('.').peekable();
+    let mut target = config;
+    while let Some(segment) = segments.next() {
+        if segment.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Override {:?} has an empty path segment",
+                entry
+            ));
+        }
+        let table = target
+            .as_table_mut()
+            .ok_or_else(|| anyhow::anyhow!("Override {:?} goes through a value", entry))?;
+        if segments.peek().is_none() {
+            table.insert(segment.to_string(), parse_value(raw));
+            return Ok(());
+        }
+        target = table
+            .entry(segment)
+            .or_insert_with(|| Value::Table(Table::new()));
+    }
+    Err(anyhow::anyhow!("Override {:?} has no path", entry))
+}
+
+/// Overlay `other` on `base`, merging tables and replacing anything else
+fn merge(base: &mut Value, other: Value) {
+    match (base, other) {
+        (Value::Table(base), Value::Table(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+/// Read a value as TOML, falling back to a string
+fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_types() {
+        let mut value = Value::try_from(Config::default()).unwrap();
+        apply_override(&mut value, "server.port=50052").unwrap();
+        apply_override(&mut value, "logging.level=debug").unwrap();
+        apply_override(&mut value, "grpc.compression=[\"gzip\"]").unwrap();
+        apply_override(&mut value, "metrics.push.url=http://pushgateway:9091").unwrap();
+        let config = value.try_into::<Config>().unwrap();
+        assert_eq!(config.server.port, 50052);
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.grpc.compression, vec!["gzip"]);
+        assert_eq!(config.metrics.push.unwrap().url, "http://pushgateway:9091");
+    }
+
+    #[test]
+    fn test_invalid_override() {
+        let mut value = Value::try_from(Config::default()).unwrap();
+        assert!(apply_override(&mut value, "server.port").is_err());
+        assert!(apply_override(&mut value, "server..port=1").is_err());
+        assert!(apply_override(&mut value, "server.port.x=1").is_err());
+        apply_override(&mut value, "server.port=not-a-port").unwrap();
+        assert!(value.try_into::<Config>().is_err());
+    }
+
+    #[test]
+    fn test_layers() {
+        let path = std::env::temp_dir().join(format!("amwaj-layers-{}.toml", uuid::Uuid::new_v4()));
+        let mut file = Config::default();
+        file.server.port = 6000;
+        file.grpc.idle_stream_timeout_ms = None;
+        std::fs::write(&path, toml::to_string(&file).unwrap()).unwrap();
+
+        let layers = Layers {
+            profile: None,
+            overrides: vec!["server.port=7000".to_string()],
+        };
+        let config = load(Some(&path), &layers).unwrap();
+        assert_eq!(config.server.port, 7000);
+        // Left out of the file, not filled in from the defaults
+        assert_eq!(config.grpc.idle_stream_timeout_ms, None);
+
+        std::fs::write(&path, "[server\n").unwrap();
+        assert!(load(Some(&path), &Layers::default()).is_err());
+        let _ = std::fs::remove_file(&path);
+
+        let config = load(Some(&path), &Layers::default()).unwrap();
+        assert_eq!(config.server.port, 50051);
+    }
+
+    #[test]
+    fn test_profiles() {
+        let path =
+            std::env::temp_dir().join(format!("amwaj-profiles-{}.toml", uuid::Uuid::new_v4()));
+        let mut content = toml::to_string(&Config::default()).unwrap();
+        content.push_str(
+            "\n[profile.dev.logging]\nlevel = \"debug\"\n\n\
+             [profile.production.grpc]\nmax_message_size = 1024\n",
+        );
+        std::fs::write(&path, content).unwrap();
+
+        let dev = Layers {
+            profile: Some("dev".to_string()),
+            overrides: Vec::new(),
+        };
+        let config = load(Some(&path), &dev).unwrap();
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.grpc.max_message_size, 10 * 1024 * 1024);
+        // Fields left out of the profile keep the file's value
+        assert_eq!(config.logging.format, "json");
+
+        let staging = Layers {
+            profile: Some("staging".to_string()),
+            overrides: Vec::new(),
+        };
+        assert!(load(Some(&path), &staging).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_platform_layer() {
+        let platform = Platform {
+            redis_url: Some("redis://a".to_string()),
+            hostname: Some("pod-1".to_string()),
+            pod_ip: Some("10.0.3.7".to_string()),
+        };
+        let layers = |overrides: &[&str]| Layers {
+            profile: None,
+            overrides: overrides.iter().map(|entry| entry.to_string()).collect(),
+        };
+
+        let config = load_over(None, &layers(&["server.port=6000"]), &platform).unwrap();
+        assert_eq!(config.sessions.redis_url.unwrap(), "redis://a");
+        assert_eq!(
+            config.sessions.advertise_address.as_deref(),
+            Some("10.0.3.7:6000")
+        );
+
+        // --set wins over the platform's variables
+        let config = load_over(
+            None,
+            &layers(&[
+                "sessions.redis_url=redis://b",
+                "sessions.advertise_address=10.0.0.1:50051",
+                "metrics.push.url=http://pushgateway:9091",
+                "metrics.push.instance=set",
+            ]),
+            &platform,
+        )
+        .unwrap();
+        assert_eq!(config.sessions.redis_url.unwrap(), "redis://b");
+        assert_eq!(
+            config.sessions.advertise_address.as_deref(),
+            Some("10.0.0.1:50051")
+        );
+        assert_eq!(
+            config.metrics.push.unwrap().instance.as_deref(),
+            Some("set")
+        );
+
+        let config = load_over(
+            None,
+            &layers(&["metrics.push.url=http://pushgateway:9091"]),
+            &platform,
+        )
+        .unwrap();
+        assert_eq!(
+            config.metrics.push.unwrap().instance.as_deref(),
+            Some("pod-1")
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! Hot reload of the configuration
+//!
+//! On SIGHUP, or when the file changes on disk, the configuration is read
+//! again, validated and compared with the one in effect. Only the logging
+//! level, the detection settings and the metrics targets and trace sampling
+//! can change on a running server; a file changing anything else is
+//! rejected as a whole, so the server never runs on a mix of old and new
+//! settings. Detection settings apply to sessions created after the reload.
+
+use super::layers::Layers;
+use super::Config;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+
+/// Section label of the logging level
+pub const SECTION_LOGGING_LEVEL: &str = "logging.level";
+/// Section label of the detection settings
+pub const SECTION_DETECTION: &str = "detection";
+/// Section label of the reloadable metrics options
+pub const SECTION_METRICS: &str = "metrics";
+
+/// Configuration in effect, swapped as a whole on reload
+#[derive(Debug, Clone)]
+pub struct ConfigHandle(Arc<watch::Sender<Arc<Config>>>);
+
+impl ConfigHandle {
+    pub fn new(config: Config) -> Self {
+        Self(Arc::new(watch::Sender::new(Arc::new(config))))
+    }
+
+    /// Get the configuration in effect
+    pub fn current(&self) -> Arc<Config> {
+        Arc::clone(&self.0.borrow())
+    }
+
+    /// Watch for reloads
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.0.subscribe()
+    }
+
+    /// Validate a new configuration and put it in effect
+    ///
+    /// Returns the reloadable sections that changed, none if the
+    /// configuration is the same. Fails without applying anything if the
+    /// configuration is invalid or changes a section that can't be reloaded.
+    pub fn apply(&self, config: Config) -> anyhow::Result<Vec<&'static str>> {
+        config.validate()?;
+        let current = self.current();
+        let changed = reloadable_changes(&current, &config)?;
+        if !changed.is_empty() {
+            self.0.send_replace(Arc::new(config));
+        }
+        Ok(changed)
+    }
+}
+
+/// Compare two configurations, returns the reloadable sections that differ
+///
+/// Fails naming the sections that differ but can't be reloaded.
+pub fn reloadable_changes(current: &Config, new: &Config) -> anyhow::Result<Vec<&'static str>> {
+    // Carry the reloadable settings over, what differs after that is fixed
+    let mut fixed = new.clone();
+    fixed.logging.level = current.logging.level.clone();
+    fixed.detection = current.detection.clone();
+    fixed.metrics.frame_trace_ratio = current.metrics.frame_trace_ratio;
+    fixed.metrics.endpointing_target_ms = current.metrics.endpointing_target_ms;
+    fixed.metrics.barge_in_target_ms = current.metrics.barge_in_target_ms;
+
+    let current_value = serde_json::to_value(current)?;
+    let fixed_value = serde_json::to_value(&fixed)?;
+    let rejected = differing_fields(&current_value, &fixed_value, "");
+    if !rejected.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Settings that need a restart changed: {}",
+            rejected.join(", ")
+        ));
+    }
+
+    let mut changed = Vec::new();
+    if new.logging.level != current.logging.level {
+        changed.push(SECTION_LOGGING_LEVEL);
+    }
+    if serde_json::to_value(&new.detection)? != serde_json::to_value(&current.detection)? {
+        changed.push(SECTION_DETECTION);
+    }
+    if new.metrics.frame_trace_ratio != current.metrics.frame_trace_ratio
+        || new.metrics.endpointing_target_ms != current.metrics.endpointing_target_ms
+        || new.metrics.barge_in_target_ms != current.metrics.barge_in_target_ms
+    {
+        changed.push(SECTION_METRICS);
+    }
+    Ok(changed)
+}
+
+/// Get the dotted paths of the fields that differ between two values
+fn differing_fields(a: &serde_json::Value, b: &serde_json::Value, path: &str) -> Vec<String> {
+    match (a, b) {
+        (serde_json::Value::Object(a), serde_json::Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort_unstable();
+            keys.dedup();
+            keys.into_iter()
+                .flat_map(|key| {
+                    let null = serde_json::Value::Null;
+                    let field = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    differing_fields(
+                        a.get(key).unwrap_or(&null),
+                        b.get(key).unwrap_or(&null),
+                        &field,
+                    )
+                })
+                .collect()
+        }
+        _ if a == b => Vec::new(),
+        _ => vec![path.to_string()],
+    }
+}
+
+/// Reloads the configuration file into a handle
+pub struct ConfigReloader {
+    path: PathBuf,
+    /// Profile and overrides given on the command line
+    layers: Layers,
+    handle: ConfigHandle,
+    /// Modification time of the file when last read
+    modified: Option<SystemTime>,
+}
+
+impl ConfigReloader {
+    pub fn new(path: impl Into<PathBuf>, handle: ConfigHandle) -> Self {
+        let path = path.into();
+        let modified = modified_at(&path);
+        Self {
+            path,
+            layers: Layers::default(),
+            handle,
+            modified,
+        }
+    }
+
+    /// Apply the same profile and overrides as at startup on every reload
+    pub fn with_layers(mut self, layers: Layers) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Get the handle reloads are applied to
+    pub fn handle(&self) -> &ConfigHandle {
+        &self.handle
+    }
+
+    /// Read the file and the environment again and apply them
+    ///
+    /// Returns the reloadable sections that changed.
+    pub fn reload(&mut self) -> anyhow::Result<Vec<&'static str>> {
+        self.modified = modified_at(&self.path);
+        if !self.path.exists() {
+            return Err(anyhow::anyhow!(
+                "Config file not found: {}",
+                self.path.display()
+            ));
+        }
+        let config = Config::load(&self.path, &self.layers)?;
+        self.handle.apply(config)
+    }
+
+    /// Check if the file changed since it was last read
+    pub fn file_changed(&self) -> bool {
+        let modified = modified_at(&self.path);
+        modified.is_some() && modified != self.modified
+    }
+
+    /// Reload and log the outcome
+    fn reload_logged(&mut self, trigger: &str) {
+        match self.reload() {
+            Ok(changed) if changed.is_empty() => {
+                tracing::info!("Configuration reloaded on {}, nothing changed", trigger)
+            }
+            Ok(changed) => tracing::info!(
+                "Configuration reloaded on {}, applied: {}",
+                trigger,
+                changed.join(", ")
+            ),
+            Err(e) => tracing::error!(
+                "Configuration reload on {} rejected, keeping the current one: {}",
+                trigger,
+                e
+            ),
+        }
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Reload the configuration on SIGHUP and, with a poll interval, whenever
+/// the file changes
+pub fn spawn_config_reloader(
+    mut reloader: ConfigReloader,
+    poll_interval: Option<Duration>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut hangup = hangup_signal();
+        let mut poll = poll_interval.map(|period| {
+            let mut poll = tokio::time::interval(period);
+            poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            poll
+        });
+        loop {
+            tokio::select! {
+                Some(()) = recv_hangup(&mut hangup) => reloader.reload_logged("SIGHUP"),
+                Some(_) = async {
+                    match poll.as_mut() {
+                        Some(poll) => Some(poll.tick().await),
+                        None => None,
+                    }
+                } => {
+                    if reloader.file_changed() {
+                        reloader.reload_logged("file change");
+                    }
+                }
+                else => break,
+            }
+        }
+    })
+}
+
+#[cfg(unix)]
+type HangupSignal = Option<tokio::signal::unix::Signal>;
+#[cfg(not(unix))]
+type HangupSignal = Option<()>;
+
+#[cfg(unix)]
+fn hangup_signal() -> HangupSignal {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(e) => {
+            tracing::warn!("Failed to listen for SIGHUP, reload on it is off: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn hangup_signal() -> HangupSignal {
+    None
+}
+
+#[cfg(unix)]
+async fn recv_hangup(hangup: &mut HangupSignal) -> Option<()> {
+    match hangup {
+        Some(hangup) => hangup.recv().await,
+        None => None,
+    }
+}
+
+#[cfg(not(unix))]
+async fn recv_hangup(_hangup: &mut HangupSignal) -> Option<()> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reloadable_changes() {
+        let current = Config::default();
+        let mut new = current.clone();
+        assert!(reloadable_changes(&current, &new).unwrap().is_empty());
+
+        new.logging.level = "debug".to_string();
+        new.detection.max_silence_duration_ms = 600;
+        new.metrics.endpointing_target_ms = 700;
+        assert_eq!(
+            reloadable_changes(&current, &new).unwrap(),
+            vec![SECTION_LOGGING_LEVEL, SECTION_DETECTION, SECTION_METRICS]
+        );
+    }
+
+    #[test]
+    fn test_fixed_sections_rejected() {
+        let current = Config::default();
+        let mut new = current.clone();
+        new.detection.vad_sensitivity = 0.8;
+        new.server.port = 50052;
+        new.metrics.prometheus_port = 9091;
+        let e = reloadable_changes(&current, &new).unwrap_err().to_string();
+        assert!(e.contains("metrics.prometheus_port"), "{}", e);
+        assert!(e.contains("server.port"), "{}", e);
+        assert!(!e.contains("detection"), "{}", e);
+    }
+
+    #[test]
+    fn test_apply_is_all_or_nothing() {
+        let handle = ConfigHandle::new(Config::default());
+        let receiver = handle.subscribe();
+
+        let mut invalid = Config::default();
+        invalid.detection.max_silence_duration_ms = 600;
+        invalid.detection.vad_sensitivity = 1.5;
+        assert!(handle.apply(invalid).is_err());
+
+        let mut restart = Config::default();
+        restart.detection.max_silence_duration_ms = 600;
+        restart.grpc.max_message_size = 1024;
+        assert!(handle.apply(restart).is_err());
+        assert!(!receiver.has_changed().unwrap());
+        assert_eq!(handle.current().detection.max_silence_duration_ms, 400);
+
+        let mut valid = Config::default();
+        valid.detection.max_silence_duration_ms = 600;
+        assert_eq!(handle.apply(valid).unwrap(), vec![SECTION_DETECTION]);
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(handle.current().detection.max_silence_duration_ms, 600);
+    }
+
+    #[test]
+    fn test_reloader_reads_file() {
+        let path = std::env::temp_dir().join(format!("amwaj-reload-{}.toml", uuid::Uuid::new_v4()));
+        let mut config = Config::default();
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+        let mut reloader = ConfigReloader::new(&path, ConfigHandle::new(config.clone()));
+        assert!(!reloader.file_changed());
+
+        config.logging.level = "debug".to_string();
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reloader.reload().unwrap(), vec![SECTION_LOGGING_LEVEL]);
+        assert_eq!(reloader.handle().current().logging.level, "debug");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,208 @@
+//! Anticipated end-of-turn prediction
+//!
+//! Speakers usually let their energy and pitch fall over the last few
+//! hundred milliseconds of a turn. Spotting that decline lets orchestrators
+//! pre-warm the LLM/TTS before the turn actually ends.
+
+use std::collections::VecDeque;
+
+use crate::config::AnticipationFeatureConfig;
+
+/// Configuration for end-of-turn anticipation
+#[derive(Debug, Clone)]
+pub struct AnticipationConfig {
+    /// Frames compared to find the trend (split into two halves)
+    pub window_frames: usize,
+    /// Energy drop between the halves that counts as fully falling (dB)
+    pub min_energy_drop_db: f32,
+    /// Pitch drop between the halves that counts as fully falling (Hz)
+    pub min_pitch_drop_hz: f32,
+    /// Confidence required to emit a hint (0.0 - 1.0)
+    pub min_confidence: f32,
+    /// Speech required before hints are emitted (ms)
+    pub min_speech_ms: u32,
+}
+
+impl From<&AnticipationFeatureConfig> for AnticipationConfig {
+    fn from(config: &AnticipationFeatureConfig) -> Self {
+        Self {
+            window_frames: config.window_frames,
+            min_energy_drop_db: config.min_energy_drop_db,
+            min_pitch_drop_hz: config.min_pitch_drop_hz,
+            min_confidence: config.min_confidence,
+            min_speech_ms: config.min_speech_ms,
+        }
+    }
+}
+
+impl Default for AnticipationConfig {
+    fn default() -> Self {
+        Self {
+            window_frames: 10,
+            min_energy_drop_db: 6.0,
+            min_pitch_drop_hz: 15.0,
+            min_confidence: 0.7,
+            min_speech_ms: 400,
+        }
+    }
+}
+
+/// Predicts an upcoming end of turn from falling energy and pitch
+pub struct EndOfTurnPredictor {
+    config: AnticipationConfig,
+    volume_db: VecDeque<f32>,
+    pitch_hz: VecDeque<f32>,
+}
+
+impl EndOfTurnPredictor {
+    /// Create a new predictor
+    pub fn new(config: AnticipationConfig) -> Self {
+        Self {
+            volume_db: VecDeque::with_capacity(config.window_frames),
+            pitch_hz: VecDeque::with_capacity(config.window_frames),
+            config,
+        }
+    }
+
+    /// Add a frame of the current turn
+    pub fn observe(&mut self, volume_db: f32, pitch_hz: f32) {
+        if self.volume_db.len() == self.config.window_frames {
+            self.volume_db.pop_front();
+            self.pitch_hz.pop_front();
+        }
+        self.volume_db.push_back(volume_db.max(-100.0));
+        self.pitch_hz.push_back(pitch_hz);
+    }
+
+    /// Confidence (0.0 - 1.0) that the turn ends within the next window
+    pub fn confidence(&self) -> f32 {
+        let window = self.config.window_frames.max(2);
+        if self.volume_db.len() < window {
+            return 0.0;
+        }
+
+        let half = window / 2;
+        let energy_drop = mean(self.volume_db.iter().take(half).copied())
+            - mean(self.volume_db.iter().skip(half).copied());
+        let energy_score = (energy_drop / self.config.min_energy_drop_db).clamp(0.0, 1.0);
+
+        let voiced = |pitch: &&f32| **pitch > 0.0;
+        let early: Vec<f32> = self
+            .pitch_hz
+            .iter()
+            .take(half)
+            .filter(voiced)
+            .copied()
+            .collect();
+        let late: Vec<f32> = self
+            .pitch_hz
+            .iter()
+            .skip(half)
+            .filter(voiced)
+            .copied()
+            .collect();
+        let pitch_score = if early.is_empty() || late.is_empty() {
+            // No pitch contour to judge, stay neutral
+            0.5
+        } else {
+            let pitch_drop = mean(early.into_iter()) - mean(late.into_iter());
+            (pitch_drop / self.config.min_pitch_drop_hz).clamp(0.0, 1.0)
+        };
+
+        0.6 * energy_score + 0.4 * pitch_score
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &AnticipationConfig {
+        &self.config
+    }
+
+    /// Clear the observed window
+    pub fn reset(&mut self) {
+        self.volume_db.clear();
+        self.pitch_hz.clear();
+    }
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0, 0usize), |(s, c), v| (s + v, c + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::processor::AudioProcessor;
+
+    #[test]
+    fn test_steady_speech_low_confidence() {
+        let mut predictor = EndOfTurnPredictor::new(AnticipationConfig::default());
+        for _ in 0..10 {
+            predictor.observe(-20.0, 180.0);
+        }
+        assert!(predictor.confidence() < 0.1);
+    }
+
+    #[test]
+    fn test_falling_energy_and_pitch() {
+        let mut predictor = EndOfTurnPredictor::new(AnticipationConfig::default());
+        for i in 0..10 {
+            predictor.observe(-20.0 - i as f32 * 2.0, 200.0 - i as f32 * 5.0);
+        }
+        assert!(predictor.confidence() > 0.9);
+    }
+
+    #[test]
+    fn test_needs_full_window() {
+        let mut predictor = EndOfTurnPredictor::new(AnticipationConfig::default());
+        for i in 0..5 {
+            predictor.observe(-20.0 - i as f32 * 5.0, 0.0);
+        }
+        assert_eq!(predictor.confidence(), 0.0);
+    }
+
+    #[test]
+    fn test_falling_pitch_of_real_frames() {
+        // 20 ms frames at 16 kHz whose energy falls, at a steady or falling pitch
+        let confidence = |pitch_at: &dyn Fn(usize) -> f32| {
+            let mut processor = AudioProcessor::new(16000, 320);
+            let mut predictor = EndOfTurnPredictor::new(AnticipationConfig::default());
+            let mut phase = 0.0f32;
+            for frame in 0..20 {
+                let amplitude = 8000.0 * 0.85f32.powi(frame as i32);
+                let step = 2.0 * std::f32::consts::PI * pitch_at(frame) / 16000.0;
+                let pcm: Vec<i16> = (0..320)
+                    .map(|_| {
+                        phase += step;
+                        (amplitude * phase.sin()) as i16
+                    })
+                    .collect();
+                let processed = processor.process_frame(&pcm).unwrap();
+                predictor.observe(processed.features.volume_db, processed.features.pitch_hz);
+            }
+            predictor.confidence()
+        };
+        let steady = confidence(&|_| 200.0);
+        let falling = confidence(&|frame| 240.0 - frame as f32 * 5.0);
+        assert!(
+            falling - steady > 0.3,
+            "steady {} falling {}",
+            steady,
+            falling
+        );
+    }
+
+    #[test]
+    fn test_unvoiced_pitch_is_neutral() {
+        let mut predictor = EndOfTurnPredictor::new(AnticipationConfig::default());
+        for i in 0..10 {
+            predictor.observe(-20.0 - i as f32 * 2.0, 0.0);
+        }
+        let confidence = predictor.confidence();
+        assert!((confidence - 0.8).abs() < 1e-6);
+    }
+}
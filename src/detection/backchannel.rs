@@ -0,0 +1,206 @@
+//! Backchannel filtering during agent playback
+//!
+//! Short acknowledgments ("uh-huh", "yeah") while the agent is speaking
+//! should not interrupt it. Utterances that start during playback are held
+//! as candidates until they either persist past a configurable length or
+//! their energy envelope (or an optional model) shows real speech.
+
+use crate::config::BackchannelFilterConfig;
+
+/// Configuration for the backchannel classifier
+#[derive(Debug, Clone)]
+pub struct BackchannelConfig {
+    /// Utterances shorter than this are treated as backchannels (ms)
+    pub max_duration_ms: u32,
+    /// Maximum energy peaks (syllables) a backchannel may contain
+    pub max_energy_peaks: usize,
+    /// Margin above the mean envelope for a frame to count as a peak (dB)
+    pub peak_margin_db: f32,
+}
+
+impl From<&BackchannelFilterConfig> for BackchannelConfig {
+    fn from(config: &BackchannelFilterConfig) -> Self {
+        Self {
+            max_duration_ms: config.max_duration_ms,
+            max_energy_peaks: config.max_energy_peaks,
+            peak_margin_db: config.peak_margin_db,
+        }
+    }
+}
+
+impl Default for BackchannelConfig {
+    fn default() -> Self {
+        Self {
+            max_duration_ms: 600,
+            max_energy_peaks: 2,
+            peak_margin_db: 3.0,
+        }
+    }
+}
+
+/// Optional tiny model scoring an energy envelope
+pub trait BackchannelModel: Send {
+    /// Probability (0.0 - 1.0) that the envelope is a backchannel
+    fn predict(&mut self, envelope_db: &[f32]) -> anyhow::Result<f32>;
+}
+
+/// Classification of the current candidate utterance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackchannelVerdict {
+    /// Not enough evidence yet
+    Pending,
+    /// Utterance is real speech and should start a turn
+    Speech,
+}
+
+/// Classifies utterances started during playback
+pub struct BackchannelClassifier {
+    config: BackchannelConfig,
+    model: Option<Box<dyn BackchannelModel>>,
+    envelope_db: Vec<f32>,
+    duration_ms: u32,
+    filtered_count: u64,
+}
+
+impl BackchannelClassifier {
+    /// Create a new classifier
+    pub fn new(config: BackchannelConfig) -> Self {
+        Self {
+            config,
+            model: None,
+            envelope_db: Vec::new(),
+            duration_ms: 0,
+            filtered_count: 0,
+        }
+    }
+
+    /// Use a model to score candidate envelopes
+    pub fn with_model(mut self, model: Box<dyn BackchannelModel>) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// Start tracking a new candidate utterance
+    pub fn begin(&mut self) {
+        self.envelope_db.clear();
+        self.duration_ms = 0;
+    }
+
+    /// Add a frame of the candidate utterance and classify it
+    pub fn observe(&mut self, volume_db: f32, frame_duration_ms: u32) -> BackchannelVerdict {
+        self.envelope_db.push(volume_db.max(-100.0));
+        self.duration_ms += frame_duration_ms;
+
+        if self.duration_ms >= self.config.max_duration_ms {
+            return BackchannelVerdict::Speech;
+        }
+
+        if let Some(model) = &mut self.model {
+            match model.predict(&self.envelope_db) {
+                Ok(p) if p < 0.5 => return BackchannelVerdict::Speech,
+                Ok(_) => return BackchannelVerdict::Pending,
+                Err(e) => tracing::debug!("Backchannel model failed: {}", e),
+            }
+        }
+
+        if self.energy_peaks() > self.config.max_energy_peaks {
+            BackchannelVerdict::Speech
+        } else {
+            BackchannelVerdict::Pending
+        }
+    }
+
+    /// Record that the candidate ended without persisting
+    pub fn mark_filtered(&mut self) {
+        self.filtered_count += 1;
+        self.begin();
+    }
+
+    /// Count local maxima in the envelope above the mean plus margin
+    fn energy_peaks(&self) -> usize {
+        if self.envelope_db.len() < 3 {
+            return 0;
+        }
+
+        let mean = self.envelope_db.iter().sum::<f32>() / self.envelope_db.len() as f32;
+        let floor = mean + self.config.peak_margin_db;
+
+        self.envelope_db
+            .windows(3)
+            .filter(|w| w[1] > floor && w[1] > w[0] && w[1] >= w[2])
+            .count()
+    }
+
+    /// Get the candidate duration in ms
+    pub fn duration_ms(&self) -> u32 {
+        self.duration_ms
+    }
+
+    /// Get the number of utterances filtered as backchannels
+    pub fn filtered_count(&self) -> u64 {
+        self.filtered_count
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &BackchannelConfig {
+        &self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedModel(f32);
+
+    impl BackchannelModel for FixedModel {
+        fn predict(&mut self, _envelope_db: &[f32]) -> anyhow::Result<f32> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn test_short_utterance_pending() {
+        let mut classifier = BackchannelClassifier::new(BackchannelConfig::default());
+        classifier.begin();
+
+        for _ in 0..10 {
+            assert_eq!(classifier.observe(-20.0, 20), BackchannelVerdict::Pending);
+        }
+    }
+
+    #[test]
+    fn test_persistent_utterance_is_speech() {
+        let mut classifier = BackchannelClassifier::new(BackchannelConfig::default());
+        classifier.begin();
+
+        let verdicts: Vec<_> = (0..30).map(|_| classifier.observe(-20.0, 20)).collect();
+        assert_eq!(verdicts[29], BackchannelVerdict::Speech);
+        assert_eq!(verdicts[28], BackchannelVerdict::Pending);
+    }
+
+    #[test]
+    fn test_many_syllables_is_speech() {
+        let mut classifier = BackchannelClassifier::new(BackchannelConfig::default());
+        classifier.begin();
+
+        let envelope = [
+            -30.0, -15.0, -30.0, -15.0, -30.0, -15.0, -30.0, -15.0, -30.0,
+        ];
+        let last = envelope
+            .iter()
+            .map(|v| classifier.observe(*v, 20))
+            .last()
+            .unwrap();
+        assert_eq!(last, BackchannelVerdict::Speech);
+    }
+
+    #[test]
+    fn test_model_overrides_envelope() {
+        let mut classifier = BackchannelClassifier::new(BackchannelConfig::default())
+            .with_model(Box::new(FixedModel(0.1)));
+        classifier.begin();
+
+        assert_eq!(classifier.observe(-20.0, 20), BackchannelVerdict::Speech);
+    }
+}
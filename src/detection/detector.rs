@@ -0,0 +1,402 @@
+//! Pluggable turn detection strategies
+//!
+//! `TurnDetector` abstracts over turn-taking strategies so each session can
+//! run the built-in state machine or a user-registered alternative
+//! (push-to-talk, externally driven, ...), selected by name.
+
+use std::collections::HashMap;
+
+use crate::audio::AudioFeatures;
+use crate::config::DetectionConfig;
+use crate::detection::multi_signal::MultiSignalFusion;
+use crate::detection::noise_floor::AdaptedThresholds;
+use crate::detection::semantic::{PartialTranscriptState, SemanticEndpointingConfig};
+use crate::detection::signals::ExternalSignal;
+use crate::detection::turn_detection::{
+    DetectorSnapshot, TurnConfigUpdate, TurnDetectionConfig, TurnDetectionEngine, TurnEvent,
+    TurnState,
+};
+
+/// Name of the built-in state machine detector
+pub const STATE_MACHINE_DETECTOR: &str = "state_machine";
+
+/// A turn-taking strategy driven frame by frame
+pub trait TurnDetector: Send {
+    /// Process an audio frame and return any turn events
+    fn process(
+        &mut self,
+        vad_prob: f32,
+        features: &AudioFeatures,
+        frame_duration_ms: u32,
+    ) -> TurnEvent;
+
+    /// Get current state
+    fn state(&self) -> TurnState;
+
+    /// Reset the detector state
+    fn reset(&mut self);
+
+    /// Update agent playback state
+    fn set_playback_active(&mut self, _active: bool) {}
+
+    /// Check if agent audio is currently playing
+    fn is_playback_active(&self) -> bool {
+        false
+    }
+
+    /// Set the energy of the agent audio being played (dB)
+    fn set_playback_reference(&mut self, _reference_db: Option<f32>) {}
+
+    /// Update the partial transcript for the current turn
+    fn update_transcript(&mut self, _text: String, _is_final: bool) {}
+
+    /// Adjust the end of turn silence from transcript completeness, if the
+    /// detector waits on silence
+    fn set_semantic_endpointing(&mut self, _config: Option<SemanticEndpointingConfig>) {}
+
+    /// Get the partial transcript for the current turn
+    fn transcript(&self) -> Option<&PartialTranscriptState> {
+        None
+    }
+
+    /// Get the threshold configuration, if the detector uses one
+    fn config(&self) -> Option<&TurnDetectionConfig> {
+        None
+    }
+
+    /// Apply a partial configuration update
+    fn apply_config_update(&mut self, _update: &TurnConfigUpdate) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Turn detector does not support configuration updates"
+        ))
+    }
+
+    /// Get the thresholds currently in effect, if the detector uses them
+    fn thresholds(&self) -> Option<AdaptedThresholds> {
+        None
+    }
+
+    /// Check if the thresholds adapt to the caller's noise floor
+    fn adapts_thresholds(&self) -> bool {
+        false
+    }
+
+    /// Get the signal fusion, if the detector uses one
+    fn fusion(&self) -> Option<&MultiSignalFusion> {
+        None
+    }
+
+    /// Feed an external signal (ASR words, mute, push-to-talk)
+    fn inject_signal(&mut self, _signal: ExternalSignal) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Turn detector does not support external signals"
+        ))
+    }
+
+    /// Capture the state a migrated session resumes from, if supported
+    fn snapshot(&self) -> Option<DetectorSnapshot> {
+        None
+    }
+
+    /// Resume from the snapshot of a migrated session
+    fn restore(&mut self, _snapshot: &DetectorSnapshot) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("Turn detector does not support snapshots"))
+    }
+}
+
+impl TurnDetector for TurnDetectionEngine {
+    fn process(
+        &mut self,
+        vad_prob: f32,
+        features: &AudioFeatures,
+        frame_duration_ms: u32,
+    ) -> TurnEvent {
+        TurnDetectionEngine::process(self, vad_prob, features, frame_duration_ms)
+    }
+
+    fn state(&self) -> TurnState {
+        TurnDetectionEngine::state(self)
+    }
+
+    fn reset(&mut self) {
+        TurnDetectionEngine::reset(self)
+    }
+
+    fn set_playback_active(&mut self, active: bool) {
+        TurnDetectionEngine::set_playback_active(self, active)
+    }
+
+    fn is_playback_active(&self) -> bool {
+        TurnDetectionEngine::is_playback_active(self)
+    }
+
+    fn set_playback_reference(&mut self, reference_db: Option<f32>) {
+        TurnDetectionEngine::set_playback_reference(self, reference_db)
+    }
+
+    fn update_transcript(&mut self, text: String, is_final: bool) {
+        TurnDetectionEngine::update_transcript(self, text, is_final)
+    }
+
+    fn set_semantic_endpointing(&mut self, config: Option<SemanticEndpointingConfig>) {
+        TurnDetectionEngine::set_semantic_endpointing(self, config)
+    }
+
+    fn transcript(&self) -> Option<&PartialTranscriptState> {
+        TurnDetectionEngine::transcript(self)
+    }
+
+    fn config(&self) -> Option<&TurnDetectionConfig> {
+        Some(TurnDetectionEngine::config(self))
+    }
+
+    fn apply_config_update(&mut self, update: &TurnConfigUpdate) -> anyhow::Result<()> {
+        TurnDetectionEngine::apply_config_update(self, update)
+    }
+
+    fn thresholds(&self) -> Option<AdaptedThresholds> {
+        Some(TurnDetectionEngine::thresholds(self))
+    }
+
+    fn adapts_thresholds(&self) -> bool {
+        self.noise_floor().is_some()
+    }
+
+    fn fusion(&self) -> Option<&MultiSignalFusion> {
+        TurnDetectionEngine::fusion(self)
+    }
+
+    fn inject_signal(&mut self, signal: ExternalSignal) -> anyhow::Result<()> {
+        TurnDetectionEngine::inject_signal(self, signal)
+    }
+
+    fn snapshot(&self) -> Option<DetectorSnapshot> {
+        Some(TurnDetectionEngine::snapshot(self))
+    }
+
+    fn restore(&mut self, snapshot: &DetectorSnapshot) -> anyhow::Result<()> {
+        TurnDetectionEngine::restore(self, snapshot);
+        Ok(())
+    }
+}
+
+/// Factory building a detector from the detection configuration
+pub type TurnDetectorFactory = Box<dyn Fn(&DetectionConfig) -> Box<dyn TurnDetector> + Send + Sync>;
+
+/// Named turn detector factories
+pub struct TurnDetectorRegistry {
+    factories: HashMap<String, TurnDetectorFactory>,
+}
+
+impl TurnDetectorRegistry {
+    /// Create a registry with only the built-in state machine
+    pub fn new() -> Self {
+        let mut registry = Self {
+            factories: HashMap::new(),
+        };
+        registry.register(STATE_MACHINE_DETECTOR, |config| {
+            Box::new(TurnDetectionEngine::from_config(config))
+        });
+        registry
+    }
+
+    /// Register a detector factory, replacing any existing one with the name
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&DetectionConfig) -> Box<dyn TurnDetector> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    /// Create a detector by name
+    pub fn create(
+        &self,
+        name: &str,
+        config: &DetectionConfig,
+    ) -> anyhow::Result<Box<dyn TurnDetector>> {
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown turn detector: {}", name))?;
+        Ok(factory(config))
+    }
+
+    /// Check if a detector is registered
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    /// Get the registered detector names
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.factories.keys().map(|n| n.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl Default for TurnDetectorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// Detector whose turns follow an external flag, like push-to-talk
+    struct FlagDetector {
+        pressed: bool,
+        state: TurnState,
+    }
+
+    impl TurnDetector for FlagDetector {
+        fn process(&mut self, _vad: f32, _features: &AudioFeatures, _ms: u32) -> TurnEvent {
+            match (self.state, self.pressed) {
+                (TurnState::Idle, true) => {
+                    self.state = TurnState::Speaking;
+                    TurnEvent::TurnStarted
+                }
+                (TurnState::Speaking, false) => {
+                    self.state = TurnState::Idle;
+                    TurnEvent::TurnEnded(Default::default())
+                }
+                _ => TurnEvent::None,
+            }
+        }
+
+        fn state(&self) -> TurnState {
+            self.state
+        }
+
+        fn reset(&mut self) {
+            self.state = TurnState::Idle;
+        }
+    }
+
+    #[test]
+    fn test_default_registry() {
+        let registry = TurnDetectorRegistry::default();
+        let config = Config::default();
+
+        assert_eq!(registry.names(), vec![STATE_MACHINE_DETECTOR]);
+        let detector = registry
+            .create(STATE_MACHINE_DETECTOR, &config.detection)
+            .unwrap();
+        assert_eq!(detector.state(), TurnState::Idle);
+        assert_eq!(detector.config().unwrap().vad_threshold_enter, 0.6);
+    }
+
+    #[test]
+    fn test_endpointing_model_checked() {
+        let mut config = Config::default();
+        config.detection.endpointing = Some(crate::config::EndpointerConfig {
+            model_path: "missing.onnx".to_string(),
+            threshold: 0.7,
+        });
+        let e = config.validate().unwrap_err().to_string();
+        assert!(e.contains("detection.endpointing.model_path"), "{}", e);
+
+        // A model that can't be loaded leaves the detector on silence
+        let path = std::env::temp_dir().join(format!("amwaj-{}.onnx", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"model").unwrap();
+        config.detection.endpointing = Some(crate::config::EndpointerConfig {
+            model_path: path.display().to_string(),
+            threshold: 0.7,
+        });
+        if cfg!(not(feature = "audio-feature")) {
+            let e = config.validate().unwrap_err().to_string();
+            assert!(e.contains("needs the audio-feature"), "{}", e);
+        }
+        let detector = TurnDetectorRegistry::default()
+            .create(STATE_MACHINE_DETECTOR, &config.detection)
+            .unwrap();
+        assert_eq!(detector.state(), TurnState::Idle);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_anticipation_from_config() {
+        let mut config = Config::default();
+        let hints = |config: &Config| {
+            let mut detector = TurnDetectorRegistry::default()
+                .create(STATE_MACHINE_DETECTOR, &config.detection)
+                .unwrap();
+            // Steady speech, then trailing off in energy and pitch
+            (0..40)
+                .map(|i: i32| {
+                    let fall = (i - 30).max(0) as f32;
+                    let features = AudioFeatures {
+                        volume_db: -20.0 - fall * 2.0,
+                        pitch_hz: 200.0 - fall * 5.0,
+                        ..Default::default()
+                    };
+                    detector.process(0.9, &features, 20)
+                })
+                .filter(|e| matches!(e, TurnEvent::EndOfTurnAnticipated(_)))
+                .count()
+        };
+        assert_eq!(hints(&config), 0);
+
+        config.detection.anticipation = Some(crate::config::AnticipationFeatureConfig::default());
+        config.validate().unwrap();
+        assert_eq!(hints(&config), 1);
+    }
+
+    #[test]
+    fn test_segmentation_from_config() {
+        let mut config = Config::default();
+        let segments = |config: &Config| {
+            let mut detector = TurnDetectorRegistry::default()
+                .create(STATE_MACHINE_DETECTOR, &config.detection)
+                .unwrap();
+            let features = AudioFeatures {
+                volume_db: -20.0,
+                ..Default::default()
+            };
+            (0..120)
+                .map(|_| detector.process(0.9, &features, 20))
+                .filter(|e| matches!(e, TurnEvent::TurnSegmented(_)))
+                .count()
+        };
+        assert_eq!(segments(&config), 0);
+
+        config.detection.segmentation = Some(crate::config::TurnSegmentationConfig {
+            max_segment_ms: 1000,
+            search_window_ms: 400,
+            ..Default::default()
+        });
+        config.validate().unwrap();
+        assert_eq!(segments(&config), 2);
+    }
+
+    #[test]
+    fn test_unknown_detector() {
+        let registry = TurnDetectorRegistry::default();
+        assert!(registry
+            .create("missing", &Config::default().detection)
+            .is_err());
+    }
+
+    #[test]
+    fn test_custom_detector() {
+        let mut registry = TurnDetectorRegistry::default();
+        registry.register("flag", |_| {
+            Box::new(FlagDetector {
+                pressed: true,
+                state: TurnState::Idle,
+            })
+        });
+
+        let mut detector = registry
+            .create("flag", &Config::default().detection)
+            .unwrap();
+        let event = detector.process(0.0, &AudioFeatures::default(), 20);
+        assert_eq!(event, TurnEvent::TurnStarted);
+        assert!(detector.config().is_none());
+        assert!(detector
+            .apply_config_update(&TurnConfigUpdate::default())
+            .is_err());
+    }
+}
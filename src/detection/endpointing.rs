@@ -0,0 +1,398 @@
+//! ML endpointing for end-of-turn prediction
+//!
+//! Feeds recent VAD/pitch/energy trajectories (and optionally the partial
+//! transcript length) into a small classifier that predicts the probability
+//! that the user has finished speaking. When the `audio-feature` is enabled,
+//! an ONNX model can be loaded with ort.
+
+use crate::audio::AudioFeatures;
+use crate::config::EndpointerConfig;
+use crate::metrics::inference::{ModelInfo, MODEL_ENDPOINTER};
+use crate::metrics::Metrics;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Number of per-frame values in the trajectory (VAD, pitch, volume)
+const VALUES_PER_FRAME: usize = 3;
+
+/// Configuration for ML endpointing
+#[derive(Debug, Clone)]
+pub struct EndpointingConfig {
+    /// Path to the ONNX endpointing model
+    pub model_path: String,
+    /// Number of recent frames fed to the model
+    pub window_frames: usize,
+    /// End-of-turn probability required to close the turn
+    pub end_of_turn_threshold: f32,
+    /// Minimum silence before the model is consulted (ms)
+    pub min_silence_ms: u32,
+    /// Maximum silence the model may hold a turn open for (ms)
+    pub max_hold_silence_ms: u32,
+}
+
+impl Default for EndpointingConfig {
+    fn default() -> Self {
+        Self {
+            model_path: "models/endpointing.onnx".to_string(),
+            window_frames: 32, // 640ms of 20ms frames
+            end_of_turn_threshold: 0.7,
+            min_silence_ms: 100,
+            max_hold_silence_ms: 1200,
+        }
+    }
+}
+
+impl From<&EndpointerConfig> for EndpointingConfig {
+    fn from(config: &EndpointerConfig) -> Self {
+        Self {
+            model_path: config.model_path.clone(),
+            end_of_turn_threshold: config.threshold,
+            ..Self::default()
+        }
+    }
+}
+
+/// Classifier that predicts end-of-turn probability from a feature vector
+pub trait EndpointModel: Send {
+    /// Predict end-of-turn probability (0.0 - 1.0)
+    fn predict(&mut self, input: &[f32]) -> anyhow::Result<f32>;
+
+    /// Get the provider and hash of the loaded model, if it has any
+    fn info(&self) -> Option<ModelInfo> {
+        None
+    }
+}
+
+/// Rolling window of per-frame detection features
+pub struct FeatureTrajectory {
+    frames: VecDeque<[f32; VALUES_PER_FRAME]>,
+    window_frames: usize,
+}
+
+impl FeatureTrajectory {
+    /// Create a trajectory holding `window_frames` frames
+    pub fn new(window_frames: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(window_frames),
+            window_frames,
+        }
+    }
+
+    /// Push a frame's features
+    pub fn push(&mut self, vad_prob: f32, features: &AudioFeatures) {
+        // Normalize to roughly 0-1 so the model sees a stable scale
+        let pitch = (features.pitch_hz / 400.0).clamp(0.0, 1.0);
+        let volume = ((features.volume_db + 50.0) / 50.0).clamp(0.0, 1.0);
+
+        if self.frames.len() == self.window_frames {
+            self.frames.pop_front();
+        }
+        self.frames
+            .push_back([vad_prob.clamp(0.0, 1.0), pitch, volume]);
+    }
+
+    /// Build the model input vector
+    ///
+    /// Layout is `window_frames * 3` values (oldest frame first, zero padded
+    /// at the front) followed by the transcript length in words.
+    pub fn to_input(&self, transcript_words: Option<usize>) -> Vec<f32> {
+        let mut input = vec![0.0; self.window_frames * VALUES_PER_FRAME + 1];
+        let offset = (self.window_frames - self.frames.len()) * VALUES_PER_FRAME;
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let start = offset + i * VALUES_PER_FRAME;
+            input[start..start + VALUES_PER_FRAME].copy_from_slice(frame);
+        }
+
+        input[self.window_frames * VALUES_PER_FRAME] = transcript_words.unwrap_or(0) as f32;
+        input
+    }
+
+    /// Get the number of frames held
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Check if the trajectory is empty
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Clear the trajectory
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+/// Endpointing model backed by ONNX Runtime
+#[cfg(feature = "audio-feature")]
+pub struct OnnxEndpointModel {
+    session: ort::session::Session,
+    info: ModelInfo,
+}
+
+#[cfg(feature = "audio-feature")]
+impl OnnxEndpointModel {
+    /// Load the model from disk
+    pub fn load(model_path: &str) -> anyhow::Result<Self> {
+        let session = ort::session::Session::builder()
+            .map_err(|e| anyhow::anyhow!("Failed to create ONNX session: {}", e))?
+            .commit_from_file(model_path)
+            .map_err(|e| anyhow::anyhow!("Failed to load endpointing model: {}", e))?;
+        let info = ModelInfo::from_file(model_path, crate::metrics::inference::PROVIDER_CPU)?;
+        tracing::info!(
+            "Endpointing model loaded from: {} ({})",
+            model_path,
+            info.hash
+        );
+        Ok(Self { session, info })
+    }
+}
+
+#[cfg(feature = "audio-feature")]
+impl EndpointModel for OnnxEndpointModel {
+    fn predict(&mut self, input: &[f32]) -> anyhow::Result<f32> {
+        let tensor = ort::value::TensorRef::from_array_view(([1usize, input.len()], input))?;
+        let outputs = self.session.run(ort::inputs![tensor])?;
+        let (_, data) = outputs[0].try_extract_tensor::<f32>()?;
+        data.first()
+            .map(|p| p.clamp(0.0, 1.0))
+            .ok_or_else(|| anyhow::anyhow!("Endpointing model returned no output"))
+    }
+
+    fn info(&self) -> Option<ModelInfo> {
+        Some(self.info.clone())
+    }
+}
+
+/// End-of-turn predictor combining a model with its feature trajectory
+pub struct Endpointer {
+    config: EndpointingConfig,
+    model: Box<dyn EndpointModel>,
+    trajectory: FeatureTrajectory,
+    transcript_words: Option<usize>,
+    last_probability: Option<f32>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl Endpointer {
+    /// Create an endpointer with a custom model
+    pub fn new(config: EndpointingConfig, model: Box<dyn EndpointModel>) -> Self {
+        Self {
+            trajectory: FeatureTrajectory::new(config.window_frames),
+            config,
+            model,
+            transcript_words: None,
+            last_probability: None,
+            metrics: None,
+        }
+    }
+
+    /// Record inference metrics, and the loaded model
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        if let Some(info) = self.model.info() {
+            metrics.record_model_loaded(MODEL_ENDPOINTER, &info);
+        }
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Create an endpointer loading the ONNX model from `config.model_path`
+    #[cfg(feature = "audio-feature")]
+    pub fn from_config(config: EndpointingConfig) -> anyhow::Result<Self> {
+        let model = OnnxEndpointModel::load(&config.model_path)?;
+        Ok(Self::new(config, Box::new(model)))
+    }
+
+    /// Create the endpointer of `[detection.endpointing]`
+    ///
+    /// A model that fails to load is logged, turns then end on silence.
+    #[cfg(feature = "audio-feature")]
+    pub fn load(config: &EndpointerConfig) -> Option<Self> {
+        Self::from_config(config.into())
+            .map_err(|e| tracing::warn!("Turns end on silence only: {}", e))
+            .ok()
+    }
+
+    /// Create the endpointer of `[detection.endpointing]`, which this build
+    /// can't load
+    #[cfg(not(feature = "audio-feature"))]
+    pub fn load(config: &EndpointerConfig) -> Option<Self> {
+        tracing::warn!(
+            "Turns end on silence only, {} needs the audio-feature",
+            config.model_path
+        );
+        None
+    }
+
+    /// Record a frame's features
+    pub fn observe(&mut self, vad_prob: f32, features: &AudioFeatures) {
+        self.trajectory.push(vad_prob, features);
+    }
+
+    /// Set the partial transcript length in words
+    pub fn set_transcript_words(&mut self, words: Option<usize>) {
+        self.transcript_words = words;
+    }
+
+    /// Predict end-of-turn probability
+    ///
+    /// Returns `None` if the model fails, so callers can fall back to the
+    /// heuristic state machine.
+    pub fn predict(&mut self) -> Option<f32> {
+        let input = self.trajectory.to_input(self.transcript_words);
+        let started = Instant::now();
+        match self.model.predict(&input) {
+            Ok(p) => {
+                if let Some(metrics) = &self.metrics {
+                    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+                    metrics.record_inference(MODEL_ENDPOINTER, 1, latency_ms);
+                }
+                self.last_probability = Some(p);
+                Some(p)
+            }
+            Err(e) => {
+                tracing::warn!("Endpointing model failed, using heuristic: {}", e);
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_inference_failure(MODEL_ENDPOINTER, true);
+                }
+                self.last_probability = None;
+                None
+            }
+        }
+    }
+
+    /// Get the most recent prediction
+    pub fn last_probability(&self) -> Option<f32> {
+        self.last_probability
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &EndpointingConfig {
+        &self.config
+    }
+
+    /// Reset per-turn state
+    pub fn reset(&mut self) {
+        self.trajectory.clear();
+        self.transcript_words = None;
+        self.last_probability = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::processor::AudioProcessor;
+
+    struct FixedModel(anyhow::Result<f32>);
+
+    impl EndpointModel for FixedModel {
+        fn predict(&mut self, _input: &[f32]) -> anyhow::Result<f32> {
+            match &self.0 {
+                Ok(p) => Ok(*p),
+                Err(e) => Err(anyhow::anyhow!("{}", e)),
+            }
+        }
+    }
+
+    fn features(volume_db: f32, pitch_hz: f32) -> AudioFeatures {
+        AudioFeatures {
+            volume_db,
+            pitch_hz,
+            spectral_centroid: 0.0,
+            zero_crossing_rate: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_trajectory_input_layout() {
+        let mut trajectory = FeatureTrajectory::new(3);
+        trajectory.push(0.8, &features(0.0, 200.0));
+
+        let input = trajectory.to_input(Some(4));
+        assert_eq!(input.len(), 10);
+        // Oldest slots are zero padded
+        assert_eq!(&input[0..6], &[0.0; 6]);
+        assert_eq!(&input[6..9], &[0.8, 0.5, 1.0]);
+        assert_eq!(input[9], 4.0);
+    }
+
+    #[test]
+    fn test_trajectory_pitch_of_real_frames() {
+        let mut processor = AudioProcessor::new(16000, 320);
+        let mut trajectory = FeatureTrajectory::new(4);
+        for frame in 0..4 {
+            let pcm: Vec<i16> = (frame * 320..(frame + 1) * 320)
+                .map(|i| {
+                    let t = i as f32 / 16000.0;
+                    (8000.0 * (2.0 * std::f32::consts::PI * 200.0 * t).sin()) as i16
+                })
+                .collect();
+            let processed = processor.process_frame(&pcm).unwrap();
+            trajectory.push(processed.vad_probability, &processed.features);
+        }
+        // Pitch slot of the last frame, 200 Hz on the 0-400 Hz scale
+        let input = trajectory.to_input(None);
+        assert!((input[3 * VALUES_PER_FRAME + 1] - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_trajectory_window() {
+        let mut trajectory = FeatureTrajectory::new(2);
+        for _ in 0..5 {
+            trajectory.push(0.5, &features(-20.0, 100.0));
+        }
+        assert_eq!(trajectory.len(), 2);
+    }
+
+    #[test]
+    fn test_endpointer_prediction() {
+        let mut endpointer =
+            Endpointer::new(EndpointingConfig::default(), Box::new(FixedModel(Ok(0.9))));
+        endpointer.observe(0.1, &features(-50.0, 0.0));
+
+        assert_eq!(endpointer.predict(), Some(0.9));
+        assert_eq!(endpointer.last_probability(), Some(0.9));
+    }
+
+    #[test]
+    fn test_endpointer_model_failure() {
+        let mut endpointer = Endpointer::new(
+            EndpointingConfig::default(),
+            Box::new(FixedModel(Err(anyhow::anyhow!("broken")))),
+        );
+
+        assert_eq!(endpointer.predict(), None);
+    }
+
+    #[test]
+    fn test_endpointer_metrics() {
+        let metrics = Arc::new(Metrics::new(&crate::config::Config::default()));
+        let mut endpointer =
+            Endpointer::new(EndpointingConfig::default(), Box::new(FixedModel(Ok(0.9))))
+                .with_metrics(metrics.clone());
+        endpointer.predict();
+        let latency = metrics
+            .model_inference_latency_ms
+            .with_label_values(&[MODEL_ENDPOINTER]);
+        assert_eq!(latency.get_sample_count(), 1);
+
+        let mut failing = Endpointer::new(
+            EndpointingConfig::default(),
+            Box::new(FixedModel(Err(anyhow::anyhow!("broken")))),
+        )
+        .with_metrics(metrics.clone());
+        failing.predict();
+        let failures = metrics
+            .model_inference_failures
+            .with_label_values(&[MODEL_ENDPOINTER]);
+        assert_eq!(failures.get(), 1);
+        let fallbacks = metrics
+            .model_fallbacks
+            .with_label_values(&[MODEL_ENDPOINTER]);
+        assert_eq!(fallbacks.get(), 1);
+        assert_eq!(latency.get_sample_count(), 1);
+    }
+}
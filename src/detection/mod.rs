@@ -1,7 +1,28 @@
 //! Turn detection module for Amwaj Media Server
 
+pub mod anticipation;
+pub mod backchannel;
+pub mod detector;
+pub mod endpointing;
 pub mod multi_signal;
+pub mod noise_floor;
+pub mod overlap;
+pub mod replay;
+pub mod semantic;
+pub mod signals;
 pub mod turn_detection;
 
-pub use multi_signal::MultiSignalFusion;
-pub use turn_detection::{TurnDetectionConfig, TurnDetectionEngine, TurnEvent, TurnState};
+pub use anticipation::{AnticipationConfig, EndOfTurnPredictor};
+pub use backchannel::{BackchannelClassifier, BackchannelConfig};
+pub use detector::{TurnDetector, TurnDetectorRegistry};
+pub use endpointing::{EndpointModel, Endpointer, EndpointingConfig};
+pub use multi_signal::{FusionBreakdown, FusionWeights, MultiSignalFusion};
+pub use noise_floor::{AdaptedThresholds, AdaptiveThresholdConfig, NoiseFloorEstimator};
+pub use overlap::{OverlapConfig, OverlapTracker};
+pub use replay::{ReplayHarness, ReplayReport};
+pub use semantic::{SemanticEndpointingConfig, TranscriptCompleteness};
+pub use signals::{ConversationContext, ExternalSignal, ExternalSignalKind, ExternalSignals};
+pub use turn_detection::{
+    BargeInConfig, DebounceConfig, DetectorSnapshot, SegmentationConfig, TurnConfigUpdate,
+    TurnDetectionConfig, TurnDetectionEngine, TurnEvent, TurnSegment, TurnState,
+};
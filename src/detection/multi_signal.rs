@@ -1,13 +1,43 @@
 //! Multi-Signal Fusion for turn detection
 
 use crate::audio::AudioFeatures;
+use crate::detection::signals::{ConversationContext, ExternalSignal, ExternalSignals};
+use std::collections::VecDeque;
 
-/// Multi-signal fusion combines VAD, volume, pitch, and context signals
+/// Default number of fused frames kept in the history
+pub const DEFAULT_HISTORY_SIZE: usize = 50;
+
+/// Weights applied to each fused signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FusionWeights {
+    pub vad: f32,
+    pub volume: f32,
+    pub pitch: f32,
+    pub context: f32,
+}
+
+/// Weighted contribution of each signal to a fused score
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FusionBreakdown {
+    pub vad: f32,
+    pub volume: f32,
+    pub pitch: f32,
+    pub context: f32,
+    /// Weighted sum of the live external signals
+    pub external: f32,
+    /// Final clamped score
+    pub score: f32,
+}
+
+/// Multi-signal fusion combines VAD, volume, pitch, context, and external signals
 pub struct MultiSignalFusion {
     vad_weight: f32,
     volume_weight: f32,
     pitch_weight: f32,
     context_weight: f32,
+    history: VecDeque<FusionBreakdown>,
+    history_size: usize,
+    external: ExternalSignals,
 }
 
 impl MultiSignalFusion {
@@ -18,6 +48,9 @@ impl MultiSignalFusion {
             volume_weight: 0.3,
             pitch_weight: 0.1,
             context_weight: 0.1,
+            history: VecDeque::new(),
+            history_size: DEFAULT_HISTORY_SIZE,
+            external: ExternalSignals::new(),
         }
     }
 
@@ -28,16 +61,52 @@ impl MultiSignalFusion {
             volume_weight: volume,
             pitch_weight: pitch,
             context_weight: context,
+            history: VecDeque::new(),
+            history_size: DEFAULT_HISTORY_SIZE,
+            external: ExternalSignals::new(),
         }
     }
 
+    /// Set how many recorded frames are kept in the history
+    pub fn with_history_size(mut self, size: usize) -> Self {
+        self.history_size = size;
+        self
+    }
+
     /// Fuse multiple signals into a single confidence score
     pub fn fuse_signals(
         &self,
         vad_prob: f32,
         features: &AudioFeatures,
-        context: Option<&str>,
+        context: Option<ConversationContext>,
     ) -> f32 {
+        self.breakdown(vad_prob, features, context).score
+    }
+
+    /// Fuse signals and record the breakdown in the history
+    pub fn fuse_and_record(
+        &mut self,
+        vad_prob: f32,
+        features: &AudioFeatures,
+        context: Option<ConversationContext>,
+    ) -> f32 {
+        let breakdown = self.breakdown(vad_prob, features, context);
+        if self.history_size > 0 {
+            if self.history.len() == self.history_size {
+                self.history.pop_front();
+            }
+            self.history.push_back(breakdown);
+        }
+        breakdown.score
+    }
+
+    /// Fuse signals, returning each weighted contribution
+    pub fn breakdown(
+        &self,
+        vad_prob: f32,
+        features: &AudioFeatures,
+        context: Option<ConversationContext>,
+    ) -> FusionBreakdown {
         // Normalize volume: map -50db to 0db range to 0-1
         let volume_normalized = ((features.volume_db + 50.0) / 50.0).clamp(0.0, 1.0);
 
@@ -51,23 +120,53 @@ impl MultiSignalFusion {
         };
 
         // Context boost based on conversation state
-        let context_boost = match context {
-            Some("expecting_response") => 0.2,
-            Some("user_speaking") => 0.1,
-            Some("thinking") => -0.1,
-            Some("playing_audio") => -0.2,
-            _ => 0.0,
-        };
+        let context_boost = context.map(|c| c.boost()).unwrap_or(0.0);
+
+        // Weighted combination with context adjustment
+        let vad = vad_prob * self.vad_weight;
+        let volume = volume_normalized * self.volume_weight;
+        let pitch = pitch_score * self.pitch_weight;
+        let context = context_boost * self.context_weight;
+        let external = self.external.contribution();
+
+        FusionBreakdown {
+            vad,
+            volume,
+            pitch,
+            context,
+            external,
+            score: (vad + volume + pitch + context + external).clamp(0.0, 1.0),
+        }
+    }
+
+    /// Set or refresh an external signal
+    pub fn inject_signal(&mut self, signal: ExternalSignal) -> anyhow::Result<()> {
+        self.external.inject(signal)
+    }
+
+    /// Age external signals by one frame, dropping stale ones
+    pub fn advance(&mut self, elapsed_ms: u32) {
+        self.external.advance(elapsed_ms);
+    }
 
-        // Weighted combination
-        let base_score = vad_prob * self.vad_weight
-            + volume_normalized * self.volume_weight
-            + pitch_score * self.pitch_weight;
+    /// Get the external signals
+    pub fn external_signals(&self) -> &ExternalSignals {
+        &self.external
+    }
 
-        // Apply context adjustment
-        let fused = base_score + context_boost * self.context_weight;
+    /// Get the external signals mutably, e.g. to override weights
+    pub fn external_signals_mut(&mut self) -> &mut ExternalSignals {
+        &mut self.external
+    }
 
-        fused.clamp(0.0, 1.0)
+    /// Get the recorded breakdowns, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &FusionBreakdown> {
+        self.history.iter()
+    }
+
+    /// Get the most recent recorded breakdown
+    pub fn last_breakdown(&self) -> Option<&FusionBreakdown> {
+        self.history.back()
     }
 
     /// Get a confidence level classification
@@ -90,6 +189,16 @@ impl MultiSignalFusion {
         self.pitch_weight = pitch;
         self.context_weight = context;
     }
+
+    /// Get the current weights
+    pub fn weights(&self) -> FusionWeights {
+        FusionWeights {
+            vad: self.vad_weight,
+            volume: self.volume_weight,
+            pitch: self.pitch_weight,
+            context: self.context_weight,
+        }
+    }
 }
 
 impl Default for MultiSignalFusion {
@@ -144,8 +253,10 @@ mod tests {
         let features = create_features(-30.0, 150.0);
 
         let score_neutral = fusion.fuse_signals(0.5, &features, None);
-        let score_expecting = fusion.fuse_signals(0.5, &features, Some("expecting_response"));
-        let score_playing = fusion.fuse_signals(0.5, &features, Some("playing_audio"));
+        let score_expecting =
+            fusion.fuse_signals(0.5, &features, Some(ConversationContext::ExpectingResponse));
+        let score_playing =
+            fusion.fuse_signals(0.5, &features, Some(ConversationContext::PlayingAudio));
 
         assert!(score_expecting > score_neutral);
         assert!(score_playing < score_neutral);
@@ -171,12 +282,58 @@ mod tests {
         assert!(score > 0.7);
     }
 
+    #[test]
+    fn test_weights_roundtrip() {
+        let mut fusion = MultiSignalFusion::new();
+        fusion.set_weights(0.7, 0.2, 0.05, 0.05);
+
+        let weights = fusion.weights();
+        assert_eq!(weights.vad, 0.7);
+        assert_eq!(weights.context, 0.05);
+    }
+
+    #[test]
+    fn test_history_window() {
+        let mut fusion = MultiSignalFusion::new().with_history_size(3);
+        let features = create_features(-20.0, 200.0);
+
+        for vad in [0.1, 0.2, 0.3, 0.4] {
+            fusion.fuse_and_record(vad, &features, None);
+        }
+
+        let vads: Vec<f32> = fusion.history().map(|b| b.vad).collect();
+        assert_eq!(vads.len(), 3);
+        assert!((vads[0] - 0.1).abs() < 1e-6);
+
+        let last = fusion.last_breakdown().unwrap();
+        assert!((last.score - (last.vad + last.volume + last.pitch + last.context)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_external_signals() {
+        let mut fusion = MultiSignalFusion::new();
+        let features = create_features(-20.0, 200.0);
+        let baseline = fusion.fuse_signals(0.9, &features, None);
+
+        fusion
+            .inject_signal(ExternalSignal::client_mute(true).with_timeout(40))
+            .unwrap();
+        let breakdown = fusion.breakdown(0.9, &features, None);
+        assert_eq!(breakdown.external, -1.0);
+        assert_eq!(breakdown.score, 0.0);
+
+        // Stale signals no longer affect the score
+        fusion.advance(40);
+        assert_eq!(fusion.fuse_signals(0.9, &features, None), baseline);
+    }
+
     #[test]
     fn test_clamping() {
         let fusion = MultiSignalFusion::new();
         let features = create_features(10.0, 300.0); // Very loud
 
-        let score = fusion.fuse_signals(1.0, &features, Some("expecting_response"));
+        let score =
+            fusion.fuse_signals(1.0, &features, Some(ConversationContext::ExpectingResponse));
 
         // Should be clamped to 1.0
         assert!(score <= 1.0);
@@ -0,0 +1,196 @@
+//! Per-session noise floor estimation and adaptive thresholds
+//!
+//! Tracks background energy and VAD output during non-speech periods and
+//! derives VAD and volume thresholds suited to the caller's environment.
+
+use crate::config::AdaptiveThresholdsConfig;
+
+/// Configuration for adaptive thresholds
+#[derive(Debug, Clone)]
+pub struct AdaptiveThresholdConfig {
+    /// Exponential smoothing factor for the noise estimate (0.0 - 1.0)
+    pub smoothing: f32,
+    /// Frames observed before thresholds start adapting
+    pub warmup_frames: u32,
+    /// Volume threshold margin above the noise floor (dB)
+    pub volume_margin_db: f32,
+    /// Bounds for the adapted volume threshold (dB)
+    pub volume_threshold_bounds_db: (f32, f32),
+    /// VAD margin above the background VAD probability
+    pub vad_margin: f32,
+    /// Bounds for the adapted VAD enter threshold
+    pub vad_enter_bounds: (f32, f32),
+    /// Bounds for the adapted VAD exit threshold
+    pub vad_exit_bounds: (f32, f32),
+}
+
+impl From<&AdaptiveThresholdsConfig> for AdaptiveThresholdConfig {
+    fn from(config: &AdaptiveThresholdsConfig) -> Self {
+        Self {
+            smoothing: config.smoothing,
+            warmup_frames: config.warmup_frames,
+            volume_margin_db: config.volume_margin_db,
+            volume_threshold_bounds_db: config.volume_threshold_bounds_db,
+            vad_margin: config.vad_margin,
+            vad_enter_bounds: config.vad_enter_bounds,
+            vad_exit_bounds: config.vad_exit_bounds,
+        }
+    }
+}
+
+impl Default for AdaptiveThresholdConfig {
+    fn default() -> Self {
+        Self {
+            smoothing: 0.05,
+            warmup_frames: 10,
+            volume_margin_db: 10.0,
+            volume_threshold_bounds_db: (-60.0, -20.0),
+            vad_margin: 0.3,
+            vad_enter_bounds: (0.4, 0.9),
+            vad_exit_bounds: (0.15, 0.6),
+        }
+    }
+}
+
+/// Thresholds adapted to the current noise floor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptedThresholds {
+    /// VAD threshold to enter speaking state
+    pub vad_threshold_enter: f32,
+    /// VAD threshold to exit speaking state
+    pub vad_threshold_exit: f32,
+    /// Minimum volume threshold (dB)
+    pub volume_threshold_db: f32,
+}
+
+/// Noise floor estimator fed with non-speech frames
+pub struct NoiseFloorEstimator {
+    config: AdaptiveThresholdConfig,
+    noise_db: Option<f32>,
+    noise_vad: f32,
+    frames_observed: u32,
+}
+
+impl NoiseFloorEstimator {
+    /// Create a new estimator
+    pub fn new(config: AdaptiveThresholdConfig) -> Self {
+        Self {
+            config,
+            noise_db: None,
+            noise_vad: 0.0,
+            frames_observed: 0,
+        }
+    }
+
+    /// Observe a non-speech frame
+    pub fn observe(&mut self, vad_prob: f32, volume_db: f32) {
+        // Digital silence carries no information about the environment
+        if !volume_db.is_finite() {
+            return;
+        }
+
+        let alpha = self.config.smoothing;
+        self.noise_db = Some(match self.noise_db {
+            Some(db) => db + alpha * (volume_db - db),
+            None => volume_db,
+        });
+        self.noise_vad += alpha * (vad_prob - self.noise_vad);
+        self.frames_observed += 1;
+    }
+
+    /// Derive thresholds from the base values and the current noise floor
+    ///
+    /// Returns `None` until enough frames have been observed.
+    pub fn thresholds(&self, base_enter: f32, base_exit: f32) -> Option<AdaptedThresholds> {
+        if self.frames_observed < self.config.warmup_frames {
+            return None;
+        }
+        let noise_db = self.noise_db?;
+
+        let (vol_min, vol_max) = self.config.volume_threshold_bounds_db;
+        let volume_threshold_db = (noise_db + self.config.volume_margin_db).clamp(vol_min, vol_max);
+
+        // Keep the base hysteresis gap between enter and exit
+        let gap = (base_enter - base_exit).max(0.0);
+        let (enter_min, enter_max) = self.config.vad_enter_bounds;
+        let vad_threshold_enter = base_enter
+            .max(self.noise_vad + self.config.vad_margin)
+            .clamp(enter_min, enter_max);
+        let (exit_min, exit_max) = self.config.vad_exit_bounds;
+        let vad_threshold_exit = (vad_threshold_enter - gap).clamp(exit_min, exit_max);
+
+        Some(AdaptedThresholds {
+            vad_threshold_enter,
+            vad_threshold_exit,
+            volume_threshold_db,
+        })
+    }
+
+    /// Get the estimated noise floor (dB)
+    pub fn noise_floor_db(&self) -> Option<f32> {
+        self.noise_db
+    }
+
+    /// Get the number of frames observed
+    pub fn frames_observed(&self) -> u32 {
+        self.frames_observed
+    }
+
+    /// Reset the estimate
+    pub fn reset(&mut self) {
+        self.noise_db = None;
+        self.noise_vad = 0.0;
+        self.frames_observed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warmup() {
+        let mut estimator = NoiseFloorEstimator::new(AdaptiveThresholdConfig::default());
+        for _ in 0..9 {
+            estimator.observe(0.0, -55.0);
+        }
+        assert!(estimator.thresholds(0.6, 0.3).is_none());
+
+        estimator.observe(0.0, -55.0);
+        assert!(estimator.thresholds(0.6, 0.3).is_some());
+    }
+
+    #[test]
+    fn test_noisy_environment_raises_thresholds() {
+        let mut estimator = NoiseFloorEstimator::new(AdaptiveThresholdConfig::default());
+        for _ in 0..50 {
+            estimator.observe(0.5, -35.0);
+        }
+
+        let adapted = estimator.thresholds(0.6, 0.3).unwrap();
+        assert!((adapted.volume_threshold_db - -25.0).abs() < 0.1);
+        assert!(adapted.vad_threshold_enter > 0.6);
+        assert!(adapted.vad_threshold_exit < adapted.vad_threshold_enter);
+    }
+
+    #[test]
+    fn test_thresholds_are_bounded() {
+        let mut estimator = NoiseFloorEstimator::new(AdaptiveThresholdConfig::default());
+        for _ in 0..50 {
+            estimator.observe(1.0, 0.0);
+        }
+
+        let adapted = estimator.thresholds(0.6, 0.3).unwrap();
+        assert_eq!(adapted.volume_threshold_db, -20.0);
+        assert_eq!(adapted.vad_threshold_enter, 0.9);
+        assert!((adapted.vad_threshold_exit - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_silence_ignored() {
+        let mut estimator = NoiseFloorEstimator::new(AdaptiveThresholdConfig::default());
+        estimator.observe(0.0, f32::NEG_INFINITY);
+        assert_eq!(estimator.frames_observed(), 0);
+        assert!(estimator.noise_floor_db().is_none());
+    }
+}
@@ -0,0 +1,160 @@
+//! Overlapping speech (double-talk) detection
+//!
+//! Measures how long the user and the agent talk at the same time. User
+//! speech that is quieter than the agent playback reference by more than
+//! the echo margin is treated as residual echo rather than double-talk.
+
+use crate::config::OverlapDetectionConfig;
+
+/// Configuration for overlap detection
+#[derive(Debug, Clone)]
+pub struct OverlapConfig {
+    /// Minimum overlap reported as an event (ms)
+    pub min_overlap_ms: u32,
+    /// Mic energy this far below the playback reference counts as echo (dB)
+    pub echo_margin_db: f32,
+}
+
+impl From<&OverlapDetectionConfig> for OverlapConfig {
+    fn from(config: &OverlapDetectionConfig) -> Self {
+        Self {
+            min_overlap_ms: config.min_overlap_ms,
+            echo_margin_db: config.echo_margin_db,
+        }
+    }
+}
+
+impl Default for OverlapConfig {
+    fn default() -> Self {
+        Self {
+            min_overlap_ms: 100,
+            echo_margin_db: 10.0,
+        }
+    }
+}
+
+/// Tracks overlapping user speech and agent playback
+pub struct OverlapTracker {
+    config: OverlapConfig,
+    reference_db: Option<f32>,
+    overlap_ms: u32,
+    total_overlap_ms: u64,
+    overlap_count: u64,
+}
+
+impl OverlapTracker {
+    /// Create a new tracker
+    pub fn new(config: OverlapConfig) -> Self {
+        Self {
+            config,
+            reference_db: None,
+            overlap_ms: 0,
+            total_overlap_ms: 0,
+            overlap_count: 0,
+        }
+    }
+
+    /// Set the energy of the agent audio currently being played (dB)
+    pub fn set_reference_db(&mut self, reference_db: Option<f32>) {
+        self.reference_db = reference_db;
+    }
+
+    /// Observe a frame, returns the overlap duration when an overlap ends
+    pub fn observe(
+        &mut self,
+        playback_active: bool,
+        user_speaking: bool,
+        volume_db: f32,
+        frame_duration_ms: u32,
+    ) -> Option<u32> {
+        if playback_active && user_speaking && !self.is_echo(volume_db) {
+            self.overlap_ms += frame_duration_ms;
+            None
+        } else {
+            self.finish()
+        }
+    }
+
+    /// End any overlap in progress, returns its duration if long enough
+    pub fn finish(&mut self) -> Option<u32> {
+        let duration = std::mem::take(&mut self.overlap_ms);
+        if duration == 0 || duration < self.config.min_overlap_ms {
+            return None;
+        }
+
+        self.total_overlap_ms += duration as u64;
+        self.overlap_count += 1;
+        Some(duration)
+    }
+
+    fn is_echo(&self, volume_db: f32) -> bool {
+        self.reference_db
+            .is_some_and(|reference| volume_db < reference - self.config.echo_margin_db)
+    }
+
+    /// Get the duration of the overlap in progress (ms)
+    pub fn current_overlap_ms(&self) -> u32 {
+        self.overlap_ms
+    }
+
+    /// Get the number of overlaps reported
+    pub fn overlap_count(&self) -> u64 {
+        self.overlap_count
+    }
+
+    /// Get the total duration of reported overlaps (ms)
+    pub fn total_overlap_ms(&self) -> u64 {
+        self.total_overlap_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlap_reported_when_speech_stops() {
+        let mut tracker = OverlapTracker::new(OverlapConfig::default());
+
+        for _ in 0..10 {
+            assert_eq!(tracker.observe(true, true, -20.0, 20), None);
+        }
+        assert_eq!(tracker.observe(true, false, -60.0, 20), Some(200));
+        assert_eq!(tracker.overlap_count(), 1);
+        assert_eq!(tracker.total_overlap_ms(), 200);
+    }
+
+    #[test]
+    fn test_short_overlap_ignored() {
+        let mut tracker = OverlapTracker::new(OverlapConfig::default());
+
+        tracker.observe(true, true, -20.0, 20);
+        tracker.observe(true, true, -20.0, 20);
+        assert_eq!(tracker.finish(), None);
+        assert_eq!(tracker.overlap_count(), 0);
+    }
+
+    #[test]
+    fn test_echo_is_not_overlap() {
+        let mut tracker = OverlapTracker::new(OverlapConfig::default());
+        tracker.set_reference_db(Some(-10.0));
+
+        for _ in 0..10 {
+            tracker.observe(true, true, -30.0, 20);
+        }
+        assert_eq!(tracker.current_overlap_ms(), 0);
+
+        // Mic louder than the echo margin is real double-talk
+        tracker.observe(true, true, -15.0, 20);
+        assert_eq!(tracker.current_overlap_ms(), 20);
+    }
+
+    #[test]
+    fn test_no_overlap_without_playback() {
+        let mut tracker = OverlapTracker::new(OverlapConfig::default());
+        for _ in 0..10 {
+            tracker.observe(false, true, -20.0, 20);
+        }
+        assert_eq!(tracker.current_overlap_ms(), 0);
+    }
+}
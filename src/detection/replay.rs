@@ -0,0 +1,380 @@
+//! Offline turn detection replay and evaluation
+//!
+//! Runs the audio processor and a turn detector over recorded WAV files and
+//! scores the detected turns against reference labels, so threshold changes
+//! can be validated before deployment.
+//!
+//! Reference labels live next to each WAV file as `<name>.txt` in Audacity
+//! label format: one turn per line as `start_sec end_sec [label]`.
+
+use std::path::{Path, PathBuf};
+
+use crate::audio::AudioProcessor;
+use crate::detection::detector::TurnDetector;
+use crate::detection::turn_detection::{TurnDetectionConfig, TurnDetectionEngine, TurnEvent};
+
+/// A labelled reference turn (media clock ms)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReferenceTurn {
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+/// A turn event with the media time it was emitted at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayEvent {
+    /// End of the frame that produced the event (ms)
+    pub timestamp_ms: i64,
+    pub event: TurnEvent,
+}
+
+/// Replay result for a single file
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub name: String,
+    pub events: Vec<ReplayEvent>,
+    pub reference: Vec<ReferenceTurn>,
+    /// Detected turns as (start_ms, end_ms)
+    pub detected: Vec<(i64, i64)>,
+    /// Detected turns that matched a reference turn
+    pub true_positives: usize,
+    /// Emission time of `TurnEnded` minus the reference end, per match
+    pub endpoint_latencies_ms: Vec<i64>,
+}
+
+impl FileReport {
+    /// Render the event dump, one event per line
+    pub fn event_dump(&self) -> String {
+        self.events
+            .iter()
+            .map(|e| format!("{}\t{:?}\n", e.timestamp_ms, e.event))
+            .collect()
+    }
+}
+
+/// Aggregated replay results
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    pub files: Vec<FileReport>,
+}
+
+impl ReplayReport {
+    /// Fraction of detected turns that match a reference turn
+    pub fn precision(&self) -> f64 {
+        let detected: usize = self.files.iter().map(|f| f.detected.len()).sum();
+        ratio(self.true_positives(), detected)
+    }
+
+    /// Fraction of reference turns that were detected
+    pub fn recall(&self) -> f64 {
+        let reference: usize = self.files.iter().map(|f| f.reference.len()).sum();
+        ratio(self.true_positives(), reference)
+    }
+
+    /// Endpointing latency percentile (nearest rank), `None` without matches
+    pub fn latency_percentile(&self, percentile: f64) -> Option<i64> {
+        let mut latencies: Vec<i64> = self
+            .files
+            .iter()
+            .flat_map(|f| f.endpoint_latencies_ms.iter().copied())
+            .collect();
+        if latencies.is_empty() {
+            return None;
+        }
+
+        latencies.sort_unstable();
+        let rank = ((percentile / 100.0) * latencies.len() as f64).ceil() as usize;
+        Some(latencies[rank.clamp(1, latencies.len()) - 1])
+    }
+
+    fn true_positives(&self) -> usize {
+        self.files.iter().map(|f| f.true_positives).sum()
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+/// Parse reference labels in Audacity format (seconds)
+pub fn parse_labels(content: &str) -> anyhow::Result<Vec<ReferenceTurn>> {
+    let mut turns = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let mut next_seconds = || -> anyhow::Result<f64> {
+            let field = fields
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing field on label line {}", index + 1))?;
+            field
+                .parse::<f64>()
+                .map_err(|e| anyhow::anyhow!("Invalid label line {}: {}", index + 1, e))
+        };
+        let start = next_seconds()?;
+        let end = next_seconds()?;
+
+        turns.push(ReferenceTurn {
+            start_ms: (start * 1000.0).round() as i64,
+            end_ms: (end * 1000.0).round() as i64,
+        });
+    }
+
+    Ok(turns)
+}
+
+/// Read a WAV file as mono 16-bit PCM, returns samples and sample rate
+pub fn read_wav(path: &Path) -> anyhow::Result<(Vec<i16>, u32)> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let interleaved: Vec<i16> = match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, 16) => reader.samples::<i16>().collect::<Result<_, _>>()?,
+        (hound::SampleFormat::Float, 32) => reader
+            .samples::<f32>()
+            .map(|s| s.map(|v| (v.clamp(-1.0, 1.0) * 32767.0) as i16))
+            .collect::<Result<_, _>>()?,
+        (format, bits) => {
+            return Err(anyhow::anyhow!(
+                "Unsupported WAV format: {:?} {} bit",
+                format,
+                bits
+            ))
+        }
+    };
+
+    let channels = spec.channels.max(1) as usize;
+    let mono = interleaved
+        .chunks(channels)
+        .map(|frame| (frame.iter().map(|s| *s as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect();
+
+    Ok((mono, spec.sample_rate))
+}
+
+/// Factory creating a fresh detector for each replayed file
+pub type DetectorFactory = Box<dyn Fn() -> Box<dyn TurnDetector>>;
+
+/// Replays recordings through the audio processor and a turn detector
+pub struct ReplayHarness {
+    detector_factory: DetectorFactory,
+    frame_duration_ms: u32,
+    tail_silence_ms: u32,
+}
+
+impl ReplayHarness {
+    /// Create a harness using the state machine detector
+    pub fn new(config: TurnDetectionConfig) -> Self {
+        Self {
+            detector_factory: Box::new(move || Box::new(TurnDetectionEngine::new(config.clone()))),
+            frame_duration_ms: 20,
+            tail_silence_ms: 2000,
+        }
+    }
+
+    /// Use a custom detector
+    pub fn with_detector(mut self, factory: DetectorFactory) -> Self {
+        self.detector_factory = factory;
+        self
+    }
+
+    /// Set the frame duration (ms)
+    pub fn with_frame_duration(mut self, frame_duration_ms: u32) -> Self {
+        self.frame_duration_ms = frame_duration_ms;
+        self
+    }
+
+    /// Replay in-memory samples against reference turns
+    pub fn replay_samples(
+        &self,
+        name: &str,
+        samples: &[i16],
+        sample_rate: u32,
+        reference: Vec<ReferenceTurn>,
+    ) -> anyhow::Result<FileReport> {
+        let frame_size = (sample_rate * self.frame_duration_ms / 1000) as usize;
+        if frame_size == 0 {
+            return Err(anyhow::anyhow!("Sample rate {} too low", sample_rate));
+        }
+
+        let mut processor = AudioProcessor::new(sample_rate, frame_size);
+        let mut detector = (self.detector_factory)();
+
+        // Trailing silence lets a turn in progress at the end of the file close
+        let tail = vec![0i16; (sample_rate * self.tail_silence_ms / 1000) as usize];
+        let mut events = Vec::new();
+        let mut ended = Vec::new();
+
+        for chunk in samples.chunks(frame_size).chain(tail.chunks(frame_size)) {
+            if chunk.len() < frame_size {
+                break;
+            }
+
+            let frame = processor.process_frame(chunk)?;
+            let event = detector.process(
+                frame.vad_probability,
+                &frame.features,
+                self.frame_duration_ms,
+            );
+            if event == TurnEvent::None {
+                continue;
+            }
+
+            let timestamp_ms = frame.timestamp_ms + self.frame_duration_ms as i64;
+            if let TurnEvent::TurnEnded(segment) = event {
+                ended.push((segment.start_ms, segment.end_ms, timestamp_ms));
+            }
+            events.push(ReplayEvent {
+                timestamp_ms,
+                event,
+            });
+        }
+
+        let mut matched = vec![false; reference.len()];
+        let mut endpoint_latencies_ms = Vec::new();
+        for (start, end, emitted_at) in &ended {
+            let hit = reference
+                .iter()
+                .enumerate()
+                .find(|(i, r)| !matched[*i] && *start < r.end_ms && *end > r.start_ms);
+            if let Some((i, r)) = hit {
+                matched[i] = true;
+                endpoint_latencies_ms.push(emitted_at - r.end_ms);
+            }
+        }
+
+        Ok(FileReport {
+            name: name.to_string(),
+            events,
+            reference,
+            detected: ended.iter().map(|(s, e, _)| (*s, *e)).collect(),
+            true_positives: endpoint_latencies_ms.len(),
+            endpoint_latencies_ms,
+        })
+    }
+
+    /// Replay a WAV file, reading labels from the sibling `.txt` file
+    ///
+    /// Files without labels are replayed with an empty reference.
+    pub fn replay_file(&self, wav_path: &Path) -> anyhow::Result<FileReport> {
+        let (samples, sample_rate) = read_wav(wav_path)?;
+
+        let label_path = wav_path.with_extension("txt");
+        let reference = if label_path.exists() {
+            parse_labels(&std::fs::read_to_string(&label_path)?)?
+        } else {
+            Vec::new()
+        };
+
+        let name = wav_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.replay_samples(&name, &samples, sample_rate, reference)
+    }
+
+    /// Replay every WAV file in a directory, in name order
+    pub fn replay_dir(&self, dir: &Path) -> anyhow::Result<ReplayReport> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"))
+            })
+            .collect();
+        paths.sort();
+
+        let files = paths
+            .iter()
+            .map(|p| self.replay_file(p))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(ReplayReport { files })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1s silence, 1s loud tone, 1s silence at 16kHz
+    fn synthetic_turn() -> Vec<i16> {
+        let mut samples = vec![0i16; 16000];
+        samples.extend((0..16000).map(|i| {
+            let t = i as f32 / 16000.0;
+            ((2.0 * std::f32::consts::PI * 200.0 * t).sin() * 10000.0) as i16
+        }));
+        samples.extend(vec![0i16; 16000]);
+        samples
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let labels = parse_labels("# turns\n1.0\t2.5\tuser\n\n3.25 4.0\n").unwrap();
+        assert_eq!(
+            labels,
+            vec![
+                ReferenceTurn {
+                    start_ms: 1000,
+                    end_ms: 2500
+                },
+                ReferenceTurn {
+                    start_ms: 3250,
+                    end_ms: 4000
+                },
+            ]
+        );
+        assert!(parse_labels("1.0\n").is_err());
+    }
+
+    #[test]
+    fn test_replay_detects_labelled_turn() {
+        let harness = ReplayHarness::new(TurnDetectionConfig::default());
+        let reference = vec![ReferenceTurn {
+            start_ms: 1000,
+            end_ms: 2000,
+        }];
+
+        let report = harness
+            .replay_samples("tone", &synthetic_turn(), 16000, reference)
+            .unwrap();
+        assert_eq!(report.true_positives, 1);
+        assert_eq!(report.detected.len(), 1);
+        assert!(report.event_dump().contains("TurnStarted"));
+
+        let latency = report.endpoint_latencies_ms[0];
+        assert!(latency > 0 && latency <= 1000);
+
+        let summary = ReplayReport {
+            files: vec![report],
+        };
+        assert_eq!(summary.precision(), 1.0);
+        assert_eq!(summary.recall(), 1.0);
+        assert_eq!(summary.latency_percentile(50.0), Some(latency));
+    }
+
+    #[test]
+    fn test_missed_turn_lowers_recall() {
+        let harness = ReplayHarness::new(TurnDetectionConfig::default());
+        let reference = vec![ReferenceTurn {
+            start_ms: 0,
+            end_ms: 500,
+        }];
+
+        let report = harness
+            .replay_samples("silence", &vec![0i16; 16000], 16000, reference)
+            .unwrap();
+        let summary = ReplayReport {
+            files: vec![report],
+        };
+        assert_eq!(summary.recall(), 0.0);
+        assert_eq!(summary.latency_percentile(90.0), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! Semantic end-of-turn hints from partial transcripts
+//!
+//! Classifies partial ASR text as complete or incomplete so the turn
+//! detector can hold a turn open on "my number is..." and close it quickly
+//! on a finished sentence.
+
+use crate::config::SemanticConfig;
+
+/// Words that rarely end a complete utterance
+const TRAILING_CONNECTORS: &[&str] = &[
+    "a", "an", "and", "the", "to", "of", "or", "but", "so", "because", "is", "are", "was", "my",
+    "your", "with", "for", "in", "on", "at", "from", "that", "if", "then", "um", "uh", "like",
+];
+
+/// Completeness of a partial transcript
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptCompleteness {
+    /// No transcript or no strong signal either way
+    Unknown,
+    /// Transcript ends mid-sentence
+    Incomplete,
+    /// Transcript looks like a finished utterance
+    Complete,
+}
+
+/// Classify the completeness of a partial transcript
+pub fn classify_transcript(text: &str) -> TranscriptCompleteness {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return TranscriptCompleteness::Unknown;
+    }
+
+    if trimmed.ends_with("...") || trimmed.ends_with(',') || trimmed.ends_with('-') {
+        return TranscriptCompleteness::Incomplete;
+    }
+    if trimmed.ends_with(['.', '?', '!']) {
+        return TranscriptCompleteness::Complete;
+    }
+
+    let last_word = trimmed
+        .split_whitespace()
+        .last()
+        .unwrap_or("")
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+
+    if TRAILING_CONNECTORS.contains(&last_word.as_str()) {
+        TranscriptCompleteness::Incomplete
+    } else {
+        TranscriptCompleteness::Unknown
+    }
+}
+
+/// Silence thresholds applied based on transcript completeness
+#[derive(Debug, Clone)]
+pub struct SemanticEndpointingConfig {
+    /// Silence before ending a turn whose transcript is complete (ms)
+    pub complete_silence_ms: u32,
+    /// Silence before ending a turn whose transcript is incomplete (ms)
+    pub incomplete_silence_ms: u32,
+}
+
+impl From<&SemanticConfig> for SemanticEndpointingConfig {
+    fn from(config: &SemanticConfig) -> Self {
+        Self {
+            complete_silence_ms: config.complete_silence_ms,
+            incomplete_silence_ms: config.incomplete_silence_ms,
+        }
+    }
+}
+
+impl Default for SemanticEndpointingConfig {
+    fn default() -> Self {
+        Self {
+            complete_silence_ms: 200,
+            incomplete_silence_ms: 1200,
+        }
+    }
+}
+
+/// Latest partial transcript for the current turn
+#[derive(Debug, Clone)]
+pub struct PartialTranscriptState {
+    text: String,
+    is_final: bool,
+    completeness: TranscriptCompleteness,
+}
+
+impl PartialTranscriptState {
+    /// Create state from a partial transcript
+    pub fn new(text: String, is_final: bool) -> Self {
+        let completeness = if is_final {
+            TranscriptCompleteness::Complete
+        } else {
+            classify_transcript(&text)
+        };
+        Self {
+            text,
+            is_final,
+            completeness,
+        }
+    }
+
+    /// Get the transcript text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Check if the ASR marked this transcript final
+    pub fn is_final(&self) -> bool {
+        self.is_final
+    }
+
+    /// Get the classified completeness
+    pub fn completeness(&self) -> TranscriptCompleteness {
+        self.completeness
+    }
+
+    /// Get the number of words
+    pub fn word_count(&self) -> usize {
+        self.text.split_whitespace().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incomplete_transcripts() {
+        assert_eq!(
+            classify_transcript("my number is"),
+            TranscriptCompleteness::Incomplete
+        );
+        assert_eq!(
+            classify_transcript("I want to go to the..."),
+            TranscriptCompleteness::Incomplete
+        );
+        assert_eq!(
+            classify_transcript("well,"),
+            TranscriptCompleteness::Incomplete
+        );
+    }
+
+    #[test]
+    fn test_complete_transcripts() {
+        assert_eq!(
+            classify_transcript("Book a table for two."),
+            TranscriptCompleteness::Complete
+        );
+        assert_eq!(
+            classify_transcript("Can you hear me?"),
+            TranscriptCompleteness::Complete
+        );
+    }
+
+    #[test]
+    fn test_unknown_transcripts() {
+        assert_eq!(classify_transcript(""), TranscriptCompleteness::Unknown);
+        assert_eq!(
+            classify_transcript("book a table tomorrow"),
+            TranscriptCompleteness::Unknown
+        );
+    }
+
+    #[test]
+    fn test_final_transcript_is_complete() {
+        let state = PartialTranscriptState::new("my number is".to_string(), true);
+        assert_eq!(state.completeness(), TranscriptCompleteness::Complete);
+        assert_eq!(state.word_count(), 3);
+    }
+}
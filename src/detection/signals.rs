@@ -0,0 +1,271 @@
+//! Inputs fused alongside the audio signals
+//!
+//! The conversation context is derived by the detector itself, while
+//! external signals (ASR word timings, client mute state, push-to-talk) are
+//! injected by the orchestrator. External signals expire once they have not
+//! been refreshed within their timeout, so a lost client cannot pin the
+//! fused score.
+
+use std::collections::HashMap;
+
+/// Conversation state used to bias the fused score
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversationContext {
+    /// The agent asked something and waits for the user
+    ExpectingResponse,
+    /// The user is in a turn
+    UserSpeaking,
+    /// The agent is preparing a response
+    Thinking,
+    /// Agent audio is being played
+    PlayingAudio,
+}
+
+impl ConversationContext {
+    /// Score adjustment before weighting
+    pub fn boost(&self) -> f32 {
+        match self {
+            ConversationContext::ExpectingResponse => 0.2,
+            ConversationContext::UserSpeaking => 0.1,
+            ConversationContext::Thinking => -0.1,
+            ConversationContext::PlayingAudio => -0.2,
+        }
+    }
+}
+
+/// Source of an externally injected signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExternalSignalKind {
+    /// ASR recognized a word in the current audio
+    AsrWord,
+    /// Client-side microphone mute state
+    ClientMute,
+    /// UI push-to-talk button state
+    PushToTalk,
+}
+
+impl ExternalSignalKind {
+    /// Default weight of the signal in the fused score
+    pub fn default_weight(&self) -> f32 {
+        match self {
+            ExternalSignalKind::AsrWord => 0.2,
+            ExternalSignalKind::ClientMute => 1.0,
+            ExternalSignalKind::PushToTalk => 1.0,
+        }
+    }
+
+    /// Default time a signal stays valid without a refresh (ms)
+    pub fn default_timeout_ms(&self) -> u32 {
+        match self {
+            ExternalSignalKind::AsrWord => 500,
+            ExternalSignalKind::ClientMute | ExternalSignalKind::PushToTalk => 5000,
+        }
+    }
+
+    /// Get the signal name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExternalSignalKind::AsrWord => "asr_word",
+            ExternalSignalKind::ClientMute => "client_mute",
+            ExternalSignalKind::PushToTalk => "push_to_talk",
+        }
+    }
+}
+
+impl std::str::FromStr for ExternalSignalKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "asr_word" => Ok(ExternalSignalKind::AsrWord),
+            "client_mute" => Ok(ExternalSignalKind::ClientMute),
+            "push_to_talk" => Ok(ExternalSignalKind::PushToTalk),
+            other => Err(anyhow::anyhow!("Unknown external signal: {}", other)),
+        }
+    }
+}
+
+/// An external signal value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExternalSignal {
+    pub kind: ExternalSignalKind,
+    /// Evidence for (+1.0) or against (-1.0) user speech
+    pub value: f32,
+    /// Time the value stays valid without a refresh (ms)
+    pub timeout_ms: u32,
+}
+
+impl ExternalSignal {
+    /// Create a signal with the default timeout for its kind
+    pub fn new(kind: ExternalSignalKind, value: f32) -> Self {
+        Self {
+            kind,
+            value,
+            timeout_ms: kind.default_timeout_ms(),
+        }
+    }
+
+    /// ASR recognized a word
+    pub fn asr_word() -> Self {
+        Self::new(ExternalSignalKind::AsrWord, 1.0)
+    }
+
+    /// Client microphone mute state
+    pub fn client_mute(muted: bool) -> Self {
+        Self::new(
+            ExternalSignalKind::ClientMute,
+            if muted { -1.0 } else { 0.0 },
+        )
+    }
+
+    /// Push-to-talk button state
+    pub fn push_to_talk(pressed: bool) -> Self {
+        Self::new(
+            ExternalSignalKind::PushToTalk,
+            if pressed { 1.0 } else { -1.0 },
+        )
+    }
+
+    /// Set the timeout
+    pub fn with_timeout(mut self, timeout_ms: u32) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActiveSignal {
+    value: f32,
+    remaining_ms: u32,
+}
+
+/// Current external signals with their weights
+#[derive(Debug, Clone, Default)]
+pub struct ExternalSignals {
+    active: HashMap<ExternalSignalKind, ActiveSignal>,
+    weights: HashMap<ExternalSignalKind, f32>,
+}
+
+impl ExternalSignals {
+    /// Create an empty signal set using the default weights
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set or refresh a signal
+    pub fn inject(&mut self, signal: ExternalSignal) -> anyhow::Result<()> {
+        if !signal.value.is_finite() {
+            return Err(anyhow::anyhow!(
+                "Invalid {} signal value: {}",
+                signal.kind.as_str(),
+                signal.value
+            ));
+        }
+
+        self.active.insert(
+            signal.kind,
+            ActiveSignal {
+                value: signal.value.clamp(-1.0, 1.0),
+                remaining_ms: signal.timeout_ms,
+            },
+        );
+        Ok(())
+    }
+
+    /// Age all signals, dropping those that went stale
+    pub fn advance(&mut self, elapsed_ms: u32) {
+        self.active.retain(|_, signal| {
+            signal.remaining_ms = signal.remaining_ms.saturating_sub(elapsed_ms);
+            signal.remaining_ms > 0
+        });
+    }
+
+    /// Override the weight of a signal kind
+    pub fn set_weight(&mut self, kind: ExternalSignalKind, weight: f32) {
+        self.weights.insert(kind, weight);
+    }
+
+    /// Get the weight of a signal kind
+    pub fn weight(&self, kind: ExternalSignalKind) -> f32 {
+        self.weights
+            .get(&kind)
+            .copied()
+            .unwrap_or_else(|| kind.default_weight())
+    }
+
+    /// Get the current value of a signal, `None` if absent or stale
+    pub fn value(&self, kind: ExternalSignalKind) -> Option<f32> {
+        self.active.get(&kind).map(|signal| signal.value)
+    }
+
+    /// Sum of the weighted values of all live signals
+    pub fn contribution(&self) -> f32 {
+        self.active
+            .iter()
+            .map(|(kind, signal)| signal.value * self.weight(*kind))
+            .sum()
+    }
+
+    /// Drop all signals
+    pub fn clear(&mut self) {
+        self.active.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_expires() {
+        let mut signals = ExternalSignals::new();
+        signals
+            .inject(ExternalSignal::asr_word().with_timeout(100))
+            .unwrap();
+
+        signals.advance(60);
+        assert_eq!(signals.value(ExternalSignalKind::AsrWord), Some(1.0));
+
+        signals.advance(40);
+        assert_eq!(signals.value(ExternalSignalKind::AsrWord), None);
+        assert_eq!(signals.contribution(), 0.0);
+    }
+
+    #[test]
+    fn test_refresh_extends_signal() {
+        let mut signals = ExternalSignals::new();
+        signals
+            .inject(ExternalSignal::push_to_talk(true).with_timeout(100))
+            .unwrap();
+        signals.advance(80);
+        signals
+            .inject(ExternalSignal::push_to_talk(true).with_timeout(100))
+            .unwrap();
+        signals.advance(80);
+
+        assert_eq!(signals.value(ExternalSignalKind::PushToTalk), Some(1.0));
+    }
+
+    #[test]
+    fn test_weighted_contribution() {
+        let mut signals = ExternalSignals::new();
+        signals.inject(ExternalSignal::asr_word()).unwrap();
+        signals.inject(ExternalSignal::client_mute(true)).unwrap();
+        signals.set_weight(ExternalSignalKind::ClientMute, 0.5);
+
+        assert!((signals.contribution() - (0.2 - 0.5)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_invalid_value_rejected() {
+        let mut signals = ExternalSignals::new();
+        assert!(signals
+            .inject(ExternalSignal::new(ExternalSignalKind::AsrWord, f32::NAN))
+            .is_err());
+        assert_eq!(
+            "push_to_talk".parse::<ExternalSignalKind>().unwrap(),
+            ExternalSignalKind::PushToTalk
+        );
+        assert!("gaze".parse::<ExternalSignalKind>().is_err());
+    }
+}
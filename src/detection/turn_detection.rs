@@ -1,9 +1,24 @@
 //! Turn Detection Engine - State machine for voice turn-taking
 
 use crate::audio::AudioFeatures;
+use crate::config::{DebounceFramesConfig, DetectionConfig, TurnSegmentationConfig};
+use crate::detection::anticipation::{AnticipationConfig, EndOfTurnPredictor};
+use crate::detection::backchannel::{BackchannelClassifier, BackchannelConfig, BackchannelVerdict};
+use crate::detection::endpointing::Endpointer;
+use crate::detection::multi_signal::{FusionWeights, MultiSignalFusion};
+use crate::detection::noise_floor::{
+    AdaptedThresholds, AdaptiveThresholdConfig, NoiseFloorEstimator,
+};
+use crate::detection::overlap::{OverlapConfig, OverlapTracker};
+use crate::detection::semantic::{
+    PartialTranscriptState, SemanticEndpointingConfig, TranscriptCompleteness,
+};
+use crate::detection::signals::{ConversationContext, ExternalSignal};
+use serde::{Deserialize, Serialize};
 
 /// State of the turn detection
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TurnState {
     /// No active speech
     Idle,
@@ -14,7 +29,7 @@ pub enum TurnState {
 }
 
 /// Configuration for turn detection
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TurnDetectionConfig {
     /// VAD threshold to enter speaking state
     pub vad_threshold_enter: f32,
@@ -40,6 +55,163 @@ impl Default for TurnDetectionConfig {
     }
 }
 
+impl From<&DetectionConfig> for TurnDetectionConfig {
+    fn from(config: &DetectionConfig) -> Self {
+        Self {
+            vad_threshold_enter: config.vad_sensitivity,
+            min_speech_duration_ms: config.min_turn_duration_ms,
+            max_silence_duration_ms: config.max_silence_duration_ms,
+            ..Self::default()
+        }
+    }
+}
+
+/// Partial update of the turn detection configuration
+///
+/// Unset fields keep their current value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TurnConfigUpdate {
+    pub vad_threshold_enter: Option<f32>,
+    pub vad_threshold_exit: Option<f32>,
+    pub min_speech_duration_ms: Option<u32>,
+    pub max_silence_duration_ms: Option<u32>,
+    pub volume_threshold_db: Option<f32>,
+    /// Fusion weights, enables signal fusion if it was off
+    pub fusion_weights: Option<FusionWeights>,
+}
+
+impl TurnConfigUpdate {
+    /// Apply the update on top of a configuration, validating the result
+    pub fn apply_to(&self, config: &TurnDetectionConfig) -> anyhow::Result<TurnDetectionConfig> {
+        let updated = TurnDetectionConfig {
+            vad_threshold_enter: self
+                .vad_threshold_enter
+                .unwrap_or(config.vad_threshold_enter),
+            vad_threshold_exit: self.vad_threshold_exit.unwrap_or(config.vad_threshold_exit),
+            min_speech_duration_ms: self
+                .min_speech_duration_ms
+                .unwrap_or(config.min_speech_duration_ms),
+            max_silence_duration_ms: self
+                .max_silence_duration_ms
+                .unwrap_or(config.max_silence_duration_ms),
+            volume_threshold_db: self
+                .volume_threshold_db
+                .unwrap_or(config.volume_threshold_db),
+        };
+
+        for threshold in [updated.vad_threshold_enter, updated.vad_threshold_exit] {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(anyhow::anyhow!(
+                    "VAD threshold {} out of range 0.0-1.0",
+                    threshold
+                ));
+            }
+        }
+        if updated.vad_threshold_exit > updated.vad_threshold_enter {
+            return Err(anyhow::anyhow!(
+                "VAD exit threshold {} above enter threshold {}",
+                updated.vad_threshold_exit,
+                updated.vad_threshold_enter
+            ));
+        }
+        if let Some(weights) = &self.fusion_weights {
+            let all = [weights.vad, weights.volume, weights.pitch, weights.context];
+            if all.iter().any(|w| !w.is_finite() || *w < 0.0) {
+                return Err(anyhow::anyhow!("Fusion weights must be non-negative"));
+            }
+        }
+
+        Ok(updated)
+    }
+}
+
+/// Configuration for automatic barge-in detection
+#[derive(Debug, Clone)]
+pub struct BargeInConfig {
+    /// Continuous confident speech required during playback (ms)
+    pub confirmation_ms: u32,
+    /// VAD probability counted as confident speech
+    pub min_vad_probability: f32,
+}
+
+impl Default for BargeInConfig {
+    fn default() -> Self {
+        Self {
+            confirmation_ms: 200,
+            min_vad_probability: 0.7,
+        }
+    }
+}
+
+/// Forced segmentation of long utterances
+///
+/// Once a segment approaches `max_segment_ms`, the next VAD dip closes it
+/// with `TurnSegmented` while the turn itself stays open. Segments that
+/// reach the limit without a dip are cut anyway.
+#[derive(Debug, Clone)]
+pub struct SegmentationConfig {
+    /// Maximum segment length (ms)
+    pub max_segment_ms: u32,
+    /// How long before the limit to start looking for a pause (ms)
+    pub search_window_ms: u32,
+    /// VAD probability below which a frame counts as a dip
+    pub dip_vad_threshold: f32,
+}
+
+impl From<&TurnSegmentationConfig> for SegmentationConfig {
+    fn from(config: &TurnSegmentationConfig) -> Self {
+        Self {
+            max_segment_ms: config.max_segment_ms,
+            search_window_ms: config.search_window_ms,
+            dip_vad_threshold: config.dip_vad_threshold,
+        }
+    }
+}
+
+impl Default for SegmentationConfig {
+    fn default() -> Self {
+        Self {
+            max_segment_ms: 30_000,
+            search_window_ms: 5_000,
+            dip_vad_threshold: 0.5,
+        }
+    }
+}
+
+/// Minimum consecutive frames required before each state transition
+///
+/// `SilenceGap` to `Idle` is governed by the silence thresholds instead.
+/// A value of 1 transitions on the first qualifying frame.
+#[derive(Debug, Clone)]
+pub struct DebounceConfig {
+    /// Frames above the enter thresholds before `Idle` -> `Speaking`
+    pub idle_to_speaking_frames: u32,
+    /// Frames below the exit threshold before `Speaking` -> `SilenceGap`
+    pub speaking_to_silence_frames: u32,
+    /// Frames above the enter threshold before `SilenceGap` -> `Speaking`
+    pub silence_to_speaking_frames: u32,
+}
+
+impl From<&DebounceFramesConfig> for DebounceConfig {
+    fn from(config: &DebounceFramesConfig) -> Self {
+        Self {
+            idle_to_speaking_frames: config.idle_to_speaking_frames,
+            speaking_to_silence_frames: config.speaking_to_silence_frames,
+            silence_to_speaking_frames: config.silence_to_speaking_frames,
+        }
+    }
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        Self {
+            idle_to_speaking_frames: 1,
+            speaking_to_silence_frames: 1,
+            silence_to_speaking_frames: 1,
+        }
+    }
+}
+
 /// Turn detection engine using state machine approach
 pub struct TurnDetectionEngine {
     state: TurnState,
@@ -49,6 +221,28 @@ pub struct TurnDetectionEngine {
     max_history_size: usize,
     config: TurnDetectionConfig,
     barge_in_pending: bool,
+    barge_in_config: BargeInConfig,
+    playback_active: bool,
+    barge_in_speech_ms: u32,
+    barge_in_emitted: bool,
+    backchannel: Option<BackchannelClassifier>,
+    turn_start_pending: bool,
+    endpointer: Option<Endpointer>,
+    semantic: Option<SemanticEndpointingConfig>,
+    transcript: Option<PartialTranscriptState>,
+    noise_floor: Option<NoiseFloorEstimator>,
+    frames_processed: u64,
+    media_clock_ms: i64,
+    segment: SegmentTracker,
+    debounce: DebounceConfig,
+    pending_transition_frames: u32,
+    fusion: Option<MultiSignalFusion>,
+    overlap: Option<OverlapTracker>,
+    pending_overlap_ms: Option<u32>,
+    anticipation: Option<EndOfTurnPredictor>,
+    anticipation_emitted: bool,
+    segmentation: Option<SegmentationConfig>,
+    sub_segment: SegmentTracker,
 }
 
 impl TurnDetectionEngine {
@@ -62,7 +256,275 @@ impl TurnDetectionEngine {
             max_history_size: 50,
             config,
             barge_in_pending: false,
+            barge_in_config: BargeInConfig::default(),
+            playback_active: false,
+            barge_in_speech_ms: 0,
+            barge_in_emitted: false,
+            backchannel: None,
+            turn_start_pending: false,
+            endpointer: None,
+            semantic: None,
+            transcript: None,
+            noise_floor: None,
+            frames_processed: 0,
+            media_clock_ms: 0,
+            segment: SegmentTracker::default(),
+            debounce: DebounceConfig::default(),
+            pending_transition_frames: 0,
+            fusion: None,
+            overlap: None,
+            pending_overlap_ms: None,
+            anticipation: None,
+            anticipation_emitted: false,
+            segmentation: None,
+            sub_segment: SegmentTracker::default(),
+        }
+    }
+
+    /// Create an engine with the optional stages of the detection config
+    pub fn from_config(config: &DetectionConfig) -> Self {
+        let mut engine = Self::new(TurnDetectionConfig::from(config));
+        if let Some(endpointer) = config.endpointing.as_ref().and_then(Endpointer::load) {
+            engine = engine.with_endpointer(endpointer);
+        }
+        if let Some(semantic) = &config.semantic {
+            engine = engine.with_semantic_endpointing(SemanticEndpointingConfig::from(semantic));
+        }
+        if let Some(backchannel) = &config.backchannel {
+            engine = engine.with_backchannel_filter(BackchannelClassifier::new(
+                BackchannelConfig::from(backchannel),
+            ));
+        }
+        if let Some(adaptive) = &config.adaptive_thresholds {
+            engine = engine.with_adaptive_thresholds(AdaptiveThresholdConfig::from(adaptive));
+        }
+        if let Some(debounce) = &config.debounce {
+            engine = engine.with_debounce(DebounceConfig::from(debounce));
+        }
+        if let Some(overlap) = &config.overlap {
+            engine = engine.with_overlap_detection(OverlapConfig::from(overlap));
+        }
+        if let Some(anticipation) = &config.anticipation {
+            engine = engine.with_anticipation(AnticipationConfig::from(anticipation));
+        }
+        if let Some(segmentation) = &config.segmentation {
+            engine = engine.with_segmentation(SegmentationConfig::from(segmentation));
+        }
+        engine
+    }
+
+    /// Configure automatic barge-in detection
+    pub fn with_barge_in(mut self, config: BargeInConfig) -> Self {
+        self.barge_in_config = config;
+        self
+    }
+
+    /// Require a minimum dwell before each state transition
+    ///
+    /// Prevents rapid `Idle` <-> `Speaking` flapping on noisy input.
+    pub fn with_debounce(mut self, config: DebounceConfig) -> Self {
+        self.debounce = config;
+        self
+    }
+
+    /// Filter backchannels ("uh-huh", "yeah") during agent playback
+    ///
+    /// Speech starting during playback only emits `TurnStarted` (and later
+    /// `BargeIn`) once the classifier decides it is not a backchannel.
+    pub fn with_backchannel_filter(mut self, classifier: BackchannelClassifier) -> Self {
+        self.backchannel = Some(classifier);
+        self
+    }
+
+    /// Get the backchannel classifier, if filtering is enabled
+    pub fn backchannel_filter(&self) -> Option<&BackchannelClassifier> {
+        self.backchannel.as_ref()
+    }
+
+    /// Update agent playback state, fed by the playback pipeline
+    pub fn set_playback_active(&mut self, active: bool) {
+        self.playback_active = active;
+        if !active {
+            self.barge_in_speech_ms = 0;
+            if let Some(tracker) = &mut self.overlap {
+                self.pending_overlap_ms = tracker.finish().or(self.pending_overlap_ms);
+                tracker.set_reference_db(None);
+            }
+        }
+    }
+
+    /// Emit `Overlap` events when the user and agent talk at the same time
+    ///
+    /// The event carries the overlap duration and is emitted once either
+    /// side stops, on the next frame without another event.
+    pub fn with_overlap_detection(mut self, config: OverlapConfig) -> Self {
+        self.overlap = Some(OverlapTracker::new(config));
+        self
+    }
+
+    /// Emit `EndOfTurnAnticipated` hints when energy and pitch fall
+    ///
+    /// At most one hint is emitted per speech run; it is re-armed when
+    /// speech resumes after a silence gap.
+    pub fn with_anticipation(mut self, config: AnticipationConfig) -> Self {
+        self.anticipation = Some(EndOfTurnPredictor::new(config));
+        self
+    }
+
+    /// Split long utterances into bounded `TurnSegmented` segments
+    pub fn with_segmentation(mut self, config: SegmentationConfig) -> Self {
+        self.segmentation = Some(config);
+        self
+    }
+
+    /// Get the overlap tracker, if overlap detection is enabled
+    pub fn overlap_tracker(&self) -> Option<&OverlapTracker> {
+        self.overlap.as_ref()
+    }
+
+    /// Set the energy of the agent audio being played (dB)
+    ///
+    /// Used as the echo reference so residual echo is not counted as
+    /// overlapping speech.
+    pub fn set_playback_reference(&mut self, reference_db: Option<f32>) {
+        if let Some(tracker) = &mut self.overlap {
+            tracker.set_reference_db(reference_db);
+        }
+    }
+
+    /// Check if agent audio is currently playing
+    pub fn is_playback_active(&self) -> bool {
+        self.playback_active
+    }
+
+    /// Use an ML endpointer to decide end-of-turn during silence gaps
+    ///
+    /// The heuristic silence threshold remains the fallback whenever the
+    /// model fails.
+    pub fn with_endpointer(mut self, endpointer: Endpointer) -> Self {
+        self.endpointer = Some(endpointer);
+        self
+    }
+
+    /// Set the partial transcript length fed to the endpointer
+    pub fn set_transcript_words(&mut self, words: Option<usize>) {
+        if let Some(endpointer) = &mut self.endpointer {
+            endpointer.set_transcript_words(words);
+        }
+    }
+
+    /// Adjust the silence threshold from partial transcript completeness
+    pub fn with_semantic_endpointing(mut self, config: SemanticEndpointingConfig) -> Self {
+        self.semantic = Some(config);
+        self
+    }
+
+    /// Adjust the silence threshold from transcript completeness, or stop
+    pub fn set_semantic_endpointing(&mut self, config: Option<SemanticEndpointingConfig>) {
+        self.semantic = config;
+    }
+
+    /// Update the partial transcript for the current turn
+    ///
+    /// Transcripts received while idle are ignored.
+    pub fn update_transcript(&mut self, text: String, is_final: bool) {
+        if self.state == TurnState::Idle {
+            return;
+        }
+
+        let transcript = PartialTranscriptState::new(text, is_final);
+        self.set_transcript_words(Some(transcript.word_count()));
+        self.transcript = Some(transcript);
+    }
+
+    /// Get the partial transcript for the current turn
+    pub fn transcript(&self) -> Option<&PartialTranscriptState> {
+        self.transcript.as_ref()
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &TurnDetectionConfig {
+        &self.config
+    }
+
+    /// Replace the configuration
+    ///
+    /// Takes effect from the next processed frame.
+    pub fn set_config(&mut self, config: TurnDetectionConfig) {
+        self.config = config;
+    }
+
+    /// Adapt VAD and volume thresholds to the session's noise floor
+    ///
+    /// The noise floor is tracked while idle. The configured thresholds
+    /// remain the base values until the estimator has warmed up.
+    pub fn with_adaptive_thresholds(mut self, config: AdaptiveThresholdConfig) -> Self {
+        self.noise_floor = Some(NoiseFloorEstimator::new(config));
+        self
+    }
+
+    /// Get the noise floor estimator, if adaptive thresholds are enabled
+    pub fn noise_floor(&self) -> Option<&NoiseFloorEstimator> {
+        self.noise_floor.as_ref()
+    }
+
+    /// Get the thresholds currently in effect
+    pub fn thresholds(&self) -> AdaptedThresholds {
+        self.noise_floor
+            .as_ref()
+            .and_then(|estimator| {
+                estimator.thresholds(
+                    self.config.vad_threshold_enter,
+                    self.config.vad_threshold_exit,
+                )
+            })
+            .unwrap_or(AdaptedThresholds {
+                vad_threshold_enter: self.config.vad_threshold_enter,
+                vad_threshold_exit: self.config.vad_threshold_exit,
+                volume_threshold_db: self.config.volume_threshold_db,
+            })
+    }
+
+    /// Drive the state machine from a fused VAD/volume/pitch score
+    ///
+    /// The fused score replaces the raw VAD probability for all thresholds.
+    pub fn with_fusion(mut self, fusion: MultiSignalFusion) -> Self {
+        self.fusion = Some(fusion);
+        self
+    }
+
+    /// Get the signal fusion, if enabled
+    pub fn fusion(&self) -> Option<&MultiSignalFusion> {
+        self.fusion.as_ref()
+    }
+
+    /// Apply a partial configuration update
+    ///
+    /// The update is validated in full before anything changes, so a
+    /// rejected update leaves the engine untouched.
+    pub fn apply_config_update(&mut self, update: &TurnConfigUpdate) -> anyhow::Result<()> {
+        self.config = update.apply_to(&self.config)?;
+
+        if let Some(w) = update.fusion_weights {
+            self.fusion
+                .get_or_insert_with(MultiSignalFusion::new)
+                .set_weights(w.vad, w.volume, w.pitch, w.context);
         }
+        Ok(())
+    }
+
+    /// Feed an external signal into the fusion
+    ///
+    /// Enables fusion with the default weights if it is not configured yet.
+    pub fn inject_signal(&mut self, signal: ExternalSignal) -> anyhow::Result<()> {
+        self.fusion
+            .get_or_insert_with(MultiSignalFusion::new)
+            .inject_signal(signal)
+    }
+
+    /// Get the endpointer, if ML endpointing is enabled
+    pub fn endpointer(&self) -> Option<&Endpointer> {
+        self.endpointer.as_ref()
     }
 
     /// Process an audio frame and return any turn events
@@ -72,16 +534,103 @@ impl TurnDetectionEngine {
         features: &AudioFeatures,
         frame_duration_ms: u32,
     ) -> TurnEvent {
+        let context = self.fusion_context();
+        let vad_prob = match &mut self.fusion {
+            Some(fusion) => {
+                let fused = fusion.fuse_and_record(vad_prob, features, context);
+                fusion.advance(frame_duration_ms);
+                fused
+            }
+            None => vad_prob,
+        };
+        let previous_state = self.state;
+
         // Update VAD history
         self.vad_history.push(vad_prob);
         if self.vad_history.len() > self.max_history_size {
             self.vad_history.remove(0);
         }
 
-        match self.state {
+        if let Some(endpointer) = &mut self.endpointer {
+            endpointer.observe(vad_prob, features);
+        }
+
+        let event = match self.state {
             TurnState::Idle => self.handle_idle(vad_prob, features, frame_duration_ms),
             TurnState::Speaking => self.handle_speaking(vad_prob, features, frame_duration_ms),
             TurnState::SilenceGap => self.handle_silence_gap(vad_prob, features, frame_duration_ms),
+        };
+        self.frames_processed += 1;
+        self.media_clock_ms += frame_duration_ms as i64;
+
+        if let Some(tracker) = &mut self.overlap {
+            let user_speaking = self.state == TurnState::Speaking && !self.turn_start_pending;
+            if let Some(duration) = tracker.observe(
+                self.playback_active,
+                user_speaking,
+                features.volume_db,
+                frame_duration_ms,
+            ) {
+                self.pending_overlap_ms = Some(duration);
+            }
+        }
+
+        // Only frames that stayed idle describe the background noise
+        if self.state == TurnState::Idle && self.pending_transition_frames == 0 {
+            if let Some(estimator) = &mut self.noise_floor {
+                estimator.observe(vad_prob, features.volume_db);
+            }
+        }
+
+        let event = match event {
+            TurnEvent::None => self.check_segmentation(vad_prob),
+            _ => event,
+        };
+
+        let event = match (event, self.pending_overlap_ms) {
+            (TurnEvent::None, Some(duration)) => {
+                self.pending_overlap_ms = None;
+                TurnEvent::Overlap(duration)
+            }
+            _ => event,
+        };
+
+        match event {
+            TurnEvent::None => self.anticipate(previous_state, features),
+            _ => event,
+        }
+    }
+
+    /// Feed the end-of-turn predictor, returns a hint when confident
+    fn anticipate(&mut self, previous_state: TurnState, features: &AudioFeatures) -> TurnEvent {
+        let Some(predictor) = &mut self.anticipation else {
+            return TurnEvent::None;
+        };
+
+        match (previous_state, self.state) {
+            (_, TurnState::Idle) => {
+                predictor.reset();
+                self.anticipation_emitted = false;
+                return TurnEvent::None;
+            }
+            (TurnState::SilenceGap, TurnState::Speaking) => self.anticipation_emitted = false,
+            _ => {}
+        }
+
+        predictor.observe(features.volume_db, features.pitch_hz);
+        if self.anticipation_emitted
+            || self.turn_start_pending
+            || self.speech_duration_ms < predictor.config().min_speech_ms
+        {
+            return TurnEvent::None;
+        }
+
+        let confidence = predictor.confidence();
+        if confidence >= predictor.config().min_confidence {
+            self.anticipation_emitted = true;
+            TurnEvent::EndOfTurnAnticipated(confidence)
+        } else {
+            TurnEvent::None
         }
     }
 
@@ -91,64 +640,267 @@ impl TurnDetectionEngine {
         features: &AudioFeatures,
         frame_duration_ms: u32,
     ) -> TurnEvent {
-        if vad_prob > self.config.vad_threshold_enter
-            && features.volume_db > self.config.volume_threshold_db
+        let thresholds = self.thresholds();
+        if vad_prob > thresholds.vad_threshold_enter
+            && features.volume_db > thresholds.volume_threshold_db
         {
+            if self.pending_transition_frames == 0 {
+                self.segment
+                    .begin(self.frames_processed, self.media_clock_ms);
+                self.sub_segment
+                    .begin(self.frames_processed, self.media_clock_ms);
+            }
+            self.mark_speech_frame(vad_prob, frame_duration_ms);
+            if !self.dwell_elapsed(self.debounce.idle_to_speaking_frames) {
+                return TurnEvent::None;
+            }
+
             self.state = TurnState::Speaking;
-            self.speech_duration_ms = frame_duration_ms;
+            self.speech_duration_ms = self.pending_transition_frames * frame_duration_ms;
+            self.pending_transition_frames = 0;
+            self.barge_in_speech_ms = 0;
+            self.barge_in_emitted = false;
+            self.track_barge_in(vad_prob, frame_duration_ms);
+
+            if self.playback_active {
+                if let Some(backchannel) = &mut self.backchannel {
+                    backchannel.begin();
+                    self.turn_start_pending = true;
+                    return self.observe_backchannel(features, frame_duration_ms);
+                }
+            }
             TurnEvent::TurnStarted
         } else {
+            self.pending_transition_frames = 0;
             TurnEvent::None
         }
     }
 
+    /// Count a frame towards a pending transition, returns true once the
+    /// required dwell is reached
+    fn dwell_elapsed(&mut self, required_frames: u32) -> bool {
+        self.pending_transition_frames += 1;
+        self.pending_transition_frames >= required_frames.max(1)
+    }
+
     fn handle_speaking(
         &mut self,
         vad_prob: f32,
-        _features: &AudioFeatures,
+        features: &AudioFeatures,
         frame_duration_ms: u32,
     ) -> TurnEvent {
         self.speech_duration_ms += frame_duration_ms;
 
-        if vad_prob < self.config.vad_threshold_exit {
-            self.state = TurnState::SilenceGap;
-            self.silence_duration_ms = frame_duration_ms;
-            TurnEvent::None
+        if vad_prob < self.thresholds().vad_threshold_exit {
+            if self.dwell_elapsed(self.debounce.speaking_to_silence_frames) {
+                self.state = TurnState::SilenceGap;
+                self.silence_duration_ms = self.pending_transition_frames * frame_duration_ms;
+                self.pending_transition_frames = 0;
+                self.barge_in_speech_ms = 0;
+            }
+            return TurnEvent::None;
+        }
+
+        self.pending_transition_frames = 0;
+        self.mark_speech_frame(vad_prob, frame_duration_ms);
+        if self.turn_start_pending {
+            self.track_barge_in(vad_prob, frame_duration_ms);
+            self.observe_backchannel(features, frame_duration_ms)
+        } else if self.track_barge_in(vad_prob, frame_duration_ms) {
+            TurnEvent::BargeIn
         } else {
             TurnEvent::None
         }
     }
 
+    /// Feed a frame to the backchannel classifier while the turn start is
+    /// pending, returns `TurnStarted` once the utterance is real speech
+    fn observe_backchannel(
+        &mut self,
+        features: &AudioFeatures,
+        frame_duration_ms: u32,
+    ) -> TurnEvent {
+        let verdict = match &mut self.backchannel {
+            Some(backchannel) if self.playback_active => {
+                backchannel.observe(features.volume_db, frame_duration_ms)
+            }
+            // Playback stopped or filter removed, nothing left to interrupt
+            _ => BackchannelVerdict::Speech,
+        };
+
+        match verdict {
+            BackchannelVerdict::Speech => {
+                self.turn_start_pending = false;
+                TurnEvent::TurnStarted
+            }
+            BackchannelVerdict::Pending => TurnEvent::None,
+        }
+    }
+
+    /// Accumulate confident speech during playback, returns true once the
+    /// barge-in is confirmed
+    fn track_barge_in(&mut self, vad_prob: f32, frame_duration_ms: u32) -> bool {
+        if !self.playback_active || self.barge_in_emitted {
+            return false;
+        }
+
+        if vad_prob < self.barge_in_config.min_vad_probability {
+            self.barge_in_speech_ms = 0;
+            return false;
+        }
+
+        self.barge_in_speech_ms += frame_duration_ms;
+        if self.barge_in_speech_ms >= self.barge_in_config.confirmation_ms
+            && !self.turn_start_pending
+        {
+            self.barge_in_emitted = true;
+            true
+        } else {
+            false
+        }
+    }
+
     fn handle_silence_gap(
         &mut self,
         vad_prob: f32,
-        _features: &AudioFeatures,
+        features: &AudioFeatures,
         frame_duration_ms: u32,
     ) -> TurnEvent {
         self.silence_duration_ms += frame_duration_ms;
 
-        if vad_prob > self.config.vad_threshold_enter {
+        if vad_prob > self.thresholds().vad_threshold_enter {
+            if !self.dwell_elapsed(self.debounce.silence_to_speaking_frames) {
+                return TurnEvent::None;
+            }
+
             // Speech resumed, go back to speaking
             self.state = TurnState::Speaking;
-            self.speech_duration_ms += frame_duration_ms;
-            TurnEvent::None
-        } else if self.silence_duration_ms >= self.config.max_silence_duration_ms {
-            // Silence threshold exceeded, turn ended
-            self.state = TurnState::Idle;
-            let duration = self.speech_duration_ms;
-            self.speech_duration_ms = 0;
-            self.silence_duration_ms = 0;
-
-            if duration >= self.config.min_speech_duration_ms {
-                TurnEvent::TurnEnded(duration)
+            self.speech_duration_ms += self.pending_transition_frames * frame_duration_ms;
+            self.pending_transition_frames = 0;
+            self.mark_speech_frame(vad_prob, frame_duration_ms);
+            return if self.turn_start_pending {
+                self.track_barge_in(vad_prob, frame_duration_ms);
+                self.observe_backchannel(features, frame_duration_ms)
+            } else if self.track_barge_in(vad_prob, frame_duration_ms) {
+                TurnEvent::BargeIn
             } else {
                 TurnEvent::None
+            };
+        }
+
+        self.pending_transition_frames = 0;
+        if !self.should_end_turn() {
+            return TurnEvent::None;
+        }
+
+        // Silence threshold exceeded, turn ended
+        self.state = TurnState::Idle;
+        let duration = self.speech_duration_ms;
+        self.speech_duration_ms = 0;
+        self.silence_duration_ms = 0;
+        self.transcript = None;
+        if let Some(endpointer) = &mut self.endpointer {
+            endpointer.reset();
+        }
+
+        if self.turn_start_pending {
+            // Never promoted to a turn, it was a backchannel
+            self.turn_start_pending = false;
+            if let Some(backchannel) = &mut self.backchannel {
+                backchannel.mark_filtered();
             }
+            return TurnEvent::None;
+        }
+
+        if duration >= self.config.min_speech_duration_ms {
+            TurnEvent::TurnEnded(self.segment.finish(duration))
+        } else {
+            TurnEvent::None
+        }
+    }
+
+    /// Conversation context passed to signal fusion
+    fn fusion_context(&self) -> Option<ConversationContext> {
+        if self.playback_active {
+            Some(ConversationContext::PlayingAudio)
+        } else if self.state != TurnState::Idle {
+            Some(ConversationContext::UserSpeaking)
+        } else {
+            None
+        }
+    }
+
+    /// Extend the current segment with a speech frame
+    fn mark_speech_frame(&mut self, vad_prob: f32, frame_duration_ms: u32) {
+        let end_ms = self.media_clock_ms + frame_duration_ms as i64;
+        self.segment.extend(self.frames_processed, end_ms, vad_prob);
+        self.sub_segment
+            .extend(self.frames_processed, end_ms, vad_prob);
+    }
+
+    /// Close the current sub-segment at a pause near the length limit
+    ///
+    /// Runs after the frame clock advanced, so the next segment starts on
+    /// the following frame.
+    fn check_segmentation(&mut self, vad_prob: f32) -> TurnEvent {
+        let Some(config) = &self.segmentation else {
+            return TurnEvent::None;
+        };
+        if self.state == TurnState::Idle || self.turn_start_pending || self.sub_segment.is_empty() {
+            return TurnEvent::None;
+        }
+
+        let elapsed = self.media_clock_ms - self.sub_segment.start_ms();
+        let limit = config.max_segment_ms as i64;
+        let search_from = limit - config.search_window_ms as i64;
+        let at_pause = self.state == TurnState::SilenceGap || vad_prob < config.dip_vad_threshold;
+
+        if elapsed >= limit || (elapsed >= search_from && at_pause) {
+            let segment = self.sub_segment.finish_at_end();
+            self.sub_segment
+                .begin(self.frames_processed, self.media_clock_ms);
+            TurnEvent::TurnSegmented(segment)
         } else {
             TurnEvent::None
         }
     }
 
+    fn should_end_turn(&mut self) -> bool {
+        let silence = self.silence_duration_ms;
+
+        if let Some(endpointer) = &mut self.endpointer {
+            let config = endpointer.config();
+            let (min_silence, max_hold, threshold) = (
+                config.min_silence_ms,
+                config.max_hold_silence_ms,
+                config.end_of_turn_threshold,
+            );
+
+            if silence < min_silence {
+                return false;
+            }
+            if let Some(p) = endpointer.predict() {
+                return p >= threshold || silence >= max_hold;
+            }
+        }
+
+        silence >= self.silence_threshold_ms()
+    }
+
+    /// Heuristic silence threshold, adjusted by transcript completeness
+    fn silence_threshold_ms(&self) -> u32 {
+        let default = self.config.max_silence_duration_ms;
+        let (Some(semantic), Some(transcript)) = (&self.semantic, &self.transcript) else {
+            return default;
+        };
+
+        match transcript.completeness() {
+            TranscriptCompleteness::Complete => semantic.complete_silence_ms,
+            TranscriptCompleteness::Incomplete => semantic.incomplete_silence_ms,
+            TranscriptCompleteness::Unknown => default,
+        }
+    }
+
     /// Get current state
     pub fn state(&self) -> TurnState {
         self.state
@@ -171,6 +923,66 @@ impl TurnDetectionEngine {
         self.silence_duration_ms = 0;
         self.speech_duration_ms = 0;
         self.barge_in_pending = false;
+        self.barge_in_speech_ms = 0;
+        self.barge_in_emitted = false;
+        self.turn_start_pending = false;
+        self.pending_transition_frames = 0;
+        self.anticipation_emitted = false;
+        if let Some(predictor) = &mut self.anticipation {
+            predictor.reset();
+        }
+        self.transcript = None;
+        if let Some(endpointer) = &mut self.endpointer {
+            endpointer.reset();
+        }
+    }
+
+    /// Capture the state a migrated session resumes from
+    pub fn snapshot(&self) -> DetectorSnapshot {
+        DetectorSnapshot {
+            state: self.state,
+            config: self.config.clone(),
+            vad_history: self.vad_history.clone(),
+            silence_duration_ms: self.silence_duration_ms,
+            speech_duration_ms: self.speech_duration_ms,
+            playback_active: self.playback_active,
+            turn_start_pending: self.turn_start_pending,
+            pending_transition_frames: self.pending_transition_frames,
+            transcript: self
+                .transcript
+                .as_ref()
+                .map(|transcript| (transcript.text().to_string(), transcript.is_final())),
+            frames_processed: self.frames_processed,
+            media_clock_ms: self.media_clock_ms,
+            segment: self.segment.segment,
+            segment_vad_sum: self.segment.vad_sum,
+            segment_speech_frames: self.segment.speech_frames,
+        }
+    }
+
+    /// Resume from a snapshot taken on another instance
+    pub fn restore(&mut self, snapshot: &DetectorSnapshot) {
+        self.reset();
+        self.state = snapshot.state;
+        self.config = snapshot.config.clone();
+        self.vad_history = snapshot.vad_history.clone();
+        self.silence_duration_ms = snapshot.silence_duration_ms;
+        self.speech_duration_ms = snapshot.speech_duration_ms;
+        self.playback_active = snapshot.playback_active;
+        self.turn_start_pending = snapshot.turn_start_pending;
+        self.pending_transition_frames = snapshot.pending_transition_frames;
+        if let Some((text, is_final)) = &snapshot.transcript {
+            let transcript = PartialTranscriptState::new(text.clone(), *is_final);
+            self.set_transcript_words(Some(transcript.word_count()));
+            self.transcript = Some(transcript);
+        }
+        self.frames_processed = snapshot.frames_processed;
+        self.media_clock_ms = snapshot.media_clock_ms;
+        self.segment = SegmentTracker {
+            segment: snapshot.segment,
+            vad_sum: snapshot.segment_vad_sum,
+            speech_frames: snapshot.segment_speech_frames,
+        };
     }
 
     /// Get average VAD probability from history
@@ -205,6 +1017,107 @@ impl TurnDetectionEngine {
     }
 }
 
+/// Boundaries of a completed speech segment
+///
+/// Media clock timestamps count the audio fed to the engine, matching the
+/// processor's frame timestamps. Frame indices are zero-based and the end
+/// frame is the last frame of speech, excluding trailing silence.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct TurnSegment {
+    /// Media clock time of the first speech frame (ms)
+    pub start_ms: i64,
+    /// Media clock time at the end of the last speech frame (ms)
+    pub end_ms: i64,
+    /// Wall clock time the first speech frame was processed (Unix ms)
+    pub start_wall_clock_ms: i64,
+    /// Wall clock time the last speech frame was processed (Unix ms)
+    pub end_wall_clock_ms: i64,
+    /// Index of the first speech frame
+    pub start_frame: u64,
+    /// Index of the last speech frame
+    pub end_frame: u64,
+    /// Speech duration (ms)
+    pub duration_ms: u32,
+    /// Average VAD probability over the speech frames
+    pub average_vad: f32,
+}
+
+/// Turn detection state carried over when a session migrates
+///
+/// Covers the state machine, the thresholds in effect and the turn in
+/// progress. Adaptive estimators (noise floor, fusion, anticipation) are not
+/// carried, they re-learn within a few frames on the new instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectorSnapshot {
+    pub state: TurnState,
+    pub config: TurnDetectionConfig,
+    pub vad_history: Vec<f32>,
+    pub silence_duration_ms: u32,
+    pub speech_duration_ms: u32,
+    pub playback_active: bool,
+    pub turn_start_pending: bool,
+    pub pending_transition_frames: u32,
+    /// Partial transcript of the current turn, and whether it was final
+    pub transcript: Option<(String, bool)>,
+    pub frames_processed: u64,
+    pub media_clock_ms: i64,
+    /// Bounds of the turn in progress
+    pub segment: TurnSegment,
+    pub segment_vad_sum: f32,
+    pub segment_speech_frames: u32,
+}
+
+/// Accumulates the bounds of the segment in progress
+#[derive(Debug, Default)]
+struct SegmentTracker {
+    segment: TurnSegment,
+    vad_sum: f32,
+    speech_frames: u32,
+}
+
+impl SegmentTracker {
+    fn begin(&mut self, frame: u64, start_ms: i64) {
+        self.segment = TurnSegment {
+            start_ms,
+            start_wall_clock_ms: chrono::Utc::now().timestamp_millis(),
+            start_frame: frame,
+            ..Default::default()
+        };
+        self.vad_sum = 0.0;
+        self.speech_frames = 0;
+    }
+
+    fn extend(&mut self, frame: u64, end_ms: i64, vad_prob: f32) {
+        self.segment.end_frame = frame;
+        self.segment.end_ms = end_ms;
+        self.segment.end_wall_clock_ms = chrono::Utc::now().timestamp_millis();
+        self.vad_sum += vad_prob;
+        self.speech_frames += 1;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.speech_frames == 0
+    }
+
+    fn start_ms(&self) -> i64 {
+        self.segment.start_ms
+    }
+
+    /// Finish with the duration spanned by the segment bounds
+    fn finish_at_end(&self) -> TurnSegment {
+        let duration = (self.segment.end_ms - self.segment.start_ms).max(0) as u32;
+        self.finish(duration)
+    }
+
+    fn finish(&self, duration_ms: u32) -> TurnSegment {
+        TurnSegment {
+            duration_ms,
+            average_vad: self.vad_sum / self.speech_frames.max(1) as f32,
+            ..self.segment
+        }
+    }
+}
+
 /// Events emitted by the turn detection engine
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TurnEvent {
@@ -212,10 +1125,16 @@ pub enum TurnEvent {
     None,
     /// User started speaking
     TurnStarted,
-    /// User finished speaking (includes duration in ms)
-    TurnEnded(u32),
+    /// User finished speaking (includes the segment boundaries)
+    TurnEnded(TurnSegment),
     /// User interrupted (barge-in detected)
     BargeIn,
+    /// User and agent talked at the same time (includes duration in ms)
+    Overlap(u32),
+    /// User is likely to finish soon (includes confidence 0.0 - 1.0)
+    EndOfTurnAnticipated(f32),
+    /// Long utterance split at a pause, the turn stays open
+    TurnSegmented(TurnSegment),
 }
 
 #[cfg(test)]
@@ -247,6 +1166,42 @@ mod tests {
         assert_eq!(engine.state(), TurnState::Speaking);
     }
 
+    #[test]
+    fn test_snapshot_resumes_turn() {
+        let config = TurnDetectionConfig {
+            min_speech_duration_ms: 100,
+            max_silence_duration_ms: 200,
+            ..TurnDetectionConfig::default()
+        };
+        let mut engine = TurnDetectionEngine::new(config.clone());
+        let features = create_features(-20.0);
+        for _ in 0..10 {
+            engine.process(0.8, &features, 20);
+        }
+        engine.update_transcript("book a table".to_string(), false);
+
+        // Survives the trip through a session store
+        let snapshot: DetectorSnapshot =
+            serde_json::from_str(&serde_json::to_string(&engine.snapshot()).unwrap()).unwrap();
+        let mut resumed = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        resumed.restore(&snapshot);
+        assert_eq!(resumed.state(), TurnState::Speaking);
+        assert_eq!(resumed.config(), &config);
+        assert_eq!(resumed.speech_duration_ms(), engine.speech_duration_ms());
+        assert_eq!(resumed.transcript().unwrap().text(), "book a table");
+
+        let mut ended = None;
+        for _ in 0..20 {
+            if let TurnEvent::TurnEnded(segment) = resumed.process(0.1, &features, 20) {
+                ended = Some(segment);
+                break;
+            }
+        }
+        let segment = ended.expect("resumed turn should end");
+        assert_eq!(segment.start_frame, 0);
+        assert!(segment.duration_ms >= 200);
+    }
+
     #[test]
     fn test_speaking_to_silence_gap() {
         let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
@@ -314,6 +1269,492 @@ mod tests {
         assert_eq!(engine.state(), TurnState::Speaking);
     }
 
+    struct FixedModel(f32);
+
+    impl crate::detection::endpointing::EndpointModel for FixedModel {
+        fn predict(&mut self, _input: &[f32]) -> anyhow::Result<f32> {
+            Ok(self.0)
+        }
+    }
+
+    fn frames_until_turn_end(engine: &mut TurnDetectionEngine) -> Option<usize> {
+        let features = create_features(-20.0);
+        for _ in 0..20 {
+            engine.process(0.8, &features, 20);
+        }
+        (1..=100).find(|_| matches!(engine.process(0.1, &features, 20), TurnEvent::TurnEnded(_)))
+    }
+
+    #[test]
+    fn test_endpointer_ends_turn_early() {
+        use crate::detection::endpointing::{Endpointer, EndpointingConfig};
+
+        let endpointer = Endpointer::new(EndpointingConfig::default(), Box::new(FixedModel(0.95)));
+        let mut engine =
+            TurnDetectionEngine::new(TurnDetectionConfig::default()).with_endpointer(endpointer);
+
+        // Ends once min_silence_ms (100ms) is reached instead of 400ms
+        assert_eq!(frames_until_turn_end(&mut engine), Some(5));
+    }
+
+    #[test]
+    fn test_endpointer_holds_turn_open() {
+        use crate::detection::endpointing::{Endpointer, EndpointingConfig};
+
+        let endpointer = Endpointer::new(EndpointingConfig::default(), Box::new(FixedModel(0.1)));
+        let mut engine =
+            TurnDetectionEngine::new(TurnDetectionConfig::default()).with_endpointer(endpointer);
+
+        // Held open until max_hold_silence_ms (1200ms)
+        assert_eq!(frames_until_turn_end(&mut engine), Some(60));
+    }
+
+    #[test]
+    fn test_incomplete_transcript_holds_turn() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_semantic_endpointing(SemanticEndpointingConfig::default());
+
+        let features = create_features(-20.0);
+        for _ in 0..15 {
+            engine.process(0.8, &features, 20);
+        }
+        engine.update_transcript("my number is".to_string(), false);
+
+        // Held open until incomplete_silence_ms (1200ms)
+        let frames = (1..=100)
+            .find(|_| matches!(engine.process(0.1, &features, 20), TurnEvent::TurnEnded(_)));
+        assert_eq!(frames, Some(60));
+        assert!(engine.transcript().is_none());
+    }
+
+    #[test]
+    fn test_complete_transcript_ends_quickly() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_semantic_endpointing(SemanticEndpointingConfig::default());
+
+        let features = create_features(-20.0);
+        for _ in 0..15 {
+            engine.process(0.8, &features, 20);
+        }
+        engine.update_transcript("Book a table for two.".to_string(), false);
+
+        let frames = (1..=100)
+            .find(|_| matches!(engine.process(0.1, &features, 20), TurnEvent::TurnEnded(_)));
+        assert_eq!(frames, Some(10));
+    }
+
+    #[test]
+    fn test_transcript_ignored_when_idle() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        engine.update_transcript("hello".to_string(), true);
+        assert!(engine.transcript().is_none());
+    }
+
+    #[test]
+    fn test_barge_in_during_playback() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        let features = create_features(-20.0);
+        engine.set_playback_active(true);
+
+        let events: Vec<TurnEvent> = (0..20)
+            .map(|_| engine.process(0.9, &features, 20))
+            .collect();
+
+        assert_eq!(events[0], TurnEvent::TurnStarted);
+        // Confirmed after 200ms of confident speech, emitted once
+        assert_eq!(events[9], TurnEvent::BargeIn);
+        assert_eq!(
+            events.iter().filter(|e| **e == TurnEvent::BargeIn).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_no_barge_in_on_short_burst() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        let features = create_features(-20.0);
+        engine.set_playback_active(true);
+
+        // A cough: brief confident frames then silence
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            events.push(engine.process(0.9, &features, 20));
+        }
+        for _ in 0..30 {
+            events.push(engine.process(0.1, &features, 20));
+        }
+
+        assert!(!events.contains(&TurnEvent::BargeIn));
+    }
+
+    #[test]
+    fn test_no_barge_in_without_playback() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        let features = create_features(-20.0);
+
+        let events: Vec<TurnEvent> = (0..20)
+            .map(|_| engine.process(0.9, &features, 20))
+            .collect();
+        assert!(!events.contains(&TurnEvent::BargeIn));
+    }
+
+    fn engine_with_backchannel_filter() -> TurnDetectionEngine {
+        use crate::detection::backchannel::BackchannelConfig;
+
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_backchannel_filter(BackchannelClassifier::new(BackchannelConfig::default()));
+        engine.set_playback_active(true);
+        engine
+    }
+
+    #[test]
+    fn test_backchannel_suppressed() {
+        let mut engine = engine_with_backchannel_filter();
+        let features = create_features(-20.0);
+
+        // "uh-huh": 300ms of speech then silence
+        let mut events = Vec::new();
+        for _ in 0..15 {
+            events.push(engine.process(0.9, &features, 20));
+        }
+        for _ in 0..30 {
+            events.push(engine.process(0.1, &features, 20));
+        }
+
+        assert!(events.iter().all(|e| *e == TurnEvent::None));
+        assert_eq!(engine.state(), TurnState::Idle);
+        assert_eq!(engine.backchannel_filter().unwrap().filtered_count(), 1);
+    }
+
+    #[test]
+    fn test_persistent_speech_promoted() {
+        let mut engine = engine_with_backchannel_filter();
+        let features = create_features(-20.0);
+
+        let events: Vec<TurnEvent> = (0..40)
+            .map(|_| engine.process(0.9, &features, 20))
+            .collect();
+
+        // Promoted after 600ms, barge-in follows on the next frame
+        assert_eq!(events[29], TurnEvent::TurnStarted);
+        assert_eq!(events[30], TurnEvent::BargeIn);
+        assert!(events[..29].iter().all(|e| *e == TurnEvent::None));
+    }
+
+    #[test]
+    fn test_adaptive_thresholds_track_noise() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_adaptive_thresholds(AdaptiveThresholdConfig::default());
+        assert_eq!(engine.thresholds().volume_threshold_db, -40.0);
+
+        // Noisy room: -35dB background with a jittery VAD
+        for _ in 0..100 {
+            engine.process(0.4, &create_features(-35.0), 20);
+        }
+
+        let thresholds = engine.thresholds();
+        assert!(thresholds.volume_threshold_db > -30.0);
+        assert!(thresholds.vad_threshold_enter > 0.6);
+        assert_eq!(engine.state(), TurnState::Idle);
+
+        // Background-level energy no longer starts a turn
+        let event = engine.process(0.65, &create_features(-32.0), 20);
+        assert_eq!(event, TurnEvent::None);
+    }
+
+    #[test]
+    fn test_adaptive_thresholds_ignore_speech() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_adaptive_thresholds(AdaptiveThresholdConfig::default());
+
+        for _ in 0..20 {
+            engine.process(0.05, &create_features(-55.0), 20);
+        }
+        let before = engine.noise_floor().unwrap().frames_observed();
+
+        for _ in 0..20 {
+            engine.process(0.9, &create_features(-10.0), 20);
+        }
+        assert_eq!(engine.noise_floor().unwrap().frames_observed(), before);
+    }
+
+    #[test]
+    fn test_turn_ended_segment_bounds() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        let features = create_features(-20.0);
+
+        // Frames 0-4 idle, 5-24 speech, then silence
+        for _ in 0..5 {
+            engine.process(0.1, &features, 20);
+        }
+        for _ in 0..20 {
+            engine.process(0.8, &features, 20);
+        }
+        let segment = (0..100)
+            .find_map(|_| match engine.process(0.1, &features, 20) {
+                TurnEvent::TurnEnded(segment) => Some(segment),
+                _ => None,
+            })
+            .expect("turn should end");
+
+        assert_eq!(segment.start_frame, 5);
+        assert_eq!(segment.end_frame, 24);
+        assert_eq!(segment.start_ms, 100);
+        assert_eq!(segment.end_ms, 500);
+        // Turn duration still counts the frame that dropped below exit
+        assert_eq!(segment.duration_ms, 420);
+        assert!((segment.average_vad - 0.8).abs() < 1e-6);
+        assert!(segment.end_wall_clock_ms >= segment.start_wall_clock_ms);
+    }
+
+    fn debounced_engine() -> TurnDetectionEngine {
+        TurnDetectionEngine::new(TurnDetectionConfig::default()).with_debounce(DebounceConfig {
+            idle_to_speaking_frames: 3,
+            speaking_to_silence_frames: 2,
+            silence_to_speaking_frames: 2,
+        })
+    }
+
+    #[test]
+    fn test_debounce_ignores_flapping_input() {
+        let mut engine = debounced_engine();
+        let features = create_features(-20.0);
+
+        // Alternating speech/noise frames never hold long enough to start
+        for i in 0..40 {
+            let vad = if i % 3 == 2 { 0.1 } else { 0.9 };
+            assert_eq!(engine.process(vad, &features, 20), TurnEvent::None);
+        }
+        assert_eq!(engine.state(), TurnState::Idle);
+    }
+
+    #[test]
+    fn test_debounce_onset_dwell() {
+        let mut engine = debounced_engine();
+        let features = create_features(-20.0);
+
+        assert_eq!(engine.process(0.9, &features, 20), TurnEvent::None);
+        assert_eq!(engine.process(0.9, &features, 20), TurnEvent::None);
+        assert_eq!(engine.process(0.9, &features, 20), TurnEvent::TurnStarted);
+        assert_eq!(engine.speech_duration_ms(), 60);
+    }
+
+    #[test]
+    fn test_debounce_offset_dwell() {
+        let mut engine = debounced_engine();
+        let features = create_features(-20.0);
+
+        for _ in 0..5 {
+            engine.process(0.9, &features, 20);
+        }
+
+        // A single dropout stays in Speaking
+        engine.process(0.1, &features, 20);
+        assert_eq!(engine.state(), TurnState::Speaking);
+        engine.process(0.9, &features, 20);
+        assert_eq!(engine.state(), TurnState::Speaking);
+
+        engine.process(0.1, &features, 20);
+        engine.process(0.1, &features, 20);
+        assert_eq!(engine.state(), TurnState::SilenceGap);
+
+        // A single spike does not resume speech
+        engine.process(0.9, &features, 20);
+        assert_eq!(engine.state(), TurnState::SilenceGap);
+        engine.process(0.9, &features, 20);
+        assert_eq!(engine.state(), TurnState::Speaking);
+    }
+
+    #[test]
+    fn test_config_update_partial() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        engine
+            .apply_config_update(&TurnConfigUpdate {
+                max_silence_duration_ms: Some(800),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(engine.config().max_silence_duration_ms, 800);
+        assert_eq!(engine.config().vad_threshold_enter, 0.6);
+        assert!(engine.fusion().is_none());
+    }
+
+    #[test]
+    fn test_config_update_rejected_atomically() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        let result = engine.apply_config_update(&TurnConfigUpdate {
+            max_silence_duration_ms: Some(800),
+            vad_threshold_exit: Some(0.9),
+            ..Default::default()
+        });
+
+        assert!(result.is_err());
+        assert_eq!(engine.config().max_silence_duration_ms, 400);
+    }
+
+    #[test]
+    fn test_config_update_enables_fusion() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        let weights = FusionWeights {
+            vad: 0.6,
+            volume: 0.4,
+            pitch: 0.0,
+            context: 0.0,
+        };
+        engine
+            .apply_config_update(&TurnConfigUpdate {
+                fusion_weights: Some(weights),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(engine.fusion().unwrap().weights(), weights);
+
+        // Loud frame with high VAD still starts a turn through fusion
+        let event = engine.process(0.9, &create_features(-5.0), 20);
+        assert_eq!(event, TurnEvent::TurnStarted);
+    }
+
+    #[test]
+    fn test_overlap_event_when_user_stops() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_overlap_detection(OverlapConfig::default());
+        engine.set_playback_active(true);
+        let features = create_features(-20.0);
+
+        let mut events: Vec<TurnEvent> = (0..15)
+            .map(|_| engine.process(0.9, &features, 20))
+            .collect();
+        events.extend((0..5).map(|_| engine.process(0.1, &features, 20)));
+
+        assert!(events.contains(&TurnEvent::Overlap(300)));
+        assert_eq!(engine.overlap_tracker().unwrap().overlap_count(), 1);
+    }
+
+    #[test]
+    fn test_overlap_event_when_playback_stops() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_overlap_detection(OverlapConfig::default());
+        engine.set_playback_active(true);
+        let features = create_features(-20.0);
+
+        for _ in 0..11 {
+            engine.process(0.9, &features, 20);
+        }
+        engine.set_playback_active(false);
+
+        assert_eq!(engine.process(0.9, &features, 20), TurnEvent::Overlap(220));
+    }
+
+    #[test]
+    fn test_end_of_turn_anticipated_on_falling_energy() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_anticipation(AnticipationConfig::default());
+
+        let steady = AudioFeatures {
+            volume_db: -20.0,
+            pitch_hz: 200.0,
+            ..Default::default()
+        };
+        let mut events: Vec<TurnEvent> =
+            (0..30).map(|_| engine.process(0.9, &steady, 20)).collect();
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, TurnEvent::EndOfTurnAnticipated(_))));
+
+        // Trailing off: energy and pitch fall while still above the VAD exit
+        for i in 0..10 {
+            let falling = AudioFeatures {
+                volume_db: -20.0 - i as f32 * 2.0,
+                pitch_hz: 200.0 - i as f32 * 5.0,
+                ..Default::default()
+            };
+            events.push(engine.process(0.8, &falling, 20));
+        }
+
+        let hints = events
+            .iter()
+            .filter(|e| matches!(e, TurnEvent::EndOfTurnAnticipated(_)))
+            .count();
+        assert_eq!(hints, 1);
+        assert_eq!(engine.state(), TurnState::Speaking);
+    }
+
+    #[test]
+    fn test_no_anticipation_without_predictor() {
+        let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
+        for i in 0..40 {
+            let features = create_features(-20.0 - i as f32);
+            let event = engine.process(0.9, &features, 20);
+            assert!(!matches!(event, TurnEvent::EndOfTurnAnticipated(_)));
+        }
+    }
+
+    fn segmenting_engine() -> TurnDetectionEngine {
+        TurnDetectionEngine::new(TurnDetectionConfig::default()).with_segmentation(
+            SegmentationConfig {
+                max_segment_ms: 1000,
+                search_window_ms: 400,
+                dip_vad_threshold: 0.5,
+            },
+        )
+    }
+
+    #[test]
+    fn test_segmented_at_dip_near_limit() {
+        let mut engine = segmenting_engine();
+        let features = create_features(-20.0);
+
+        // A dip before the search window is ignored
+        let mut events: Vec<TurnEvent> = (0..20)
+            .map(|_| engine.process(0.9, &features, 20))
+            .collect();
+        events.push(engine.process(0.45, &features, 20));
+        events.extend((0..10).map(|_| engine.process(0.9, &features, 20)));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, TurnEvent::TurnSegmented(_))));
+
+        // 620ms in, the next dip closes the segment
+        let event = engine.process(0.45, &features, 20);
+        let TurnEvent::TurnSegmented(segment) = event else {
+            panic!("expected TurnSegmented, got {:?}", event);
+        };
+        assert_eq!(segment.start_ms, 0);
+        assert_eq!(segment.end_ms, 640);
+        assert_eq!(engine.state(), TurnState::Speaking);
+    }
+
+    #[test]
+    fn test_forced_segment_at_limit() {
+        let mut engine = segmenting_engine();
+        let features = create_features(-20.0);
+
+        let events: Vec<TurnEvent> = (0..120)
+            .map(|_| engine.process(0.9, &features, 20))
+            .collect();
+        let segments: Vec<TurnSegment> = events
+            .iter()
+            .filter_map(|e| match e {
+                TurnEvent::TurnSegmented(segment) => Some(*segment),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].duration_ms, 1000);
+        assert_eq!(segments[1].start_ms, 1000);
+        assert_eq!(engine.state(), TurnState::Speaking);
+
+        // The logical turn still spans all segments
+        let ended = (0..50).find_map(|_| match engine.process(0.1, &features, 20) {
+            TurnEvent::TurnEnded(segment) => Some(segment),
+            _ => None,
+        });
+        assert_eq!(ended.unwrap().start_ms, 0);
+    }
+
     #[test]
     fn test_reset() {
         let mut engine = TurnDetectionEngine::new(TurnDetectionConfig::default());
@@ -1,6 +1,108 @@
 //! Error types for Amwaj Media Server
+//!
+//! Every error has a stable `ErrorCode` from the proto, a `Severity` for
+//! grouping failures on dashboards and whether retrying can succeed. The
+//! code and retryability travel to clients in the metadata of the gRPC
+//! status, see `AmwajError::to_status`.
+//!
+//! Errors out of the media path carry an `ErrorContext` naming the session,
+//! stream and frame they happened on, so logs of many concurrent sessions
+//! can be told apart.
 
+use crate::proto::ErrorCode;
+use std::fmt;
 use thiserror::Error;
+use tonic::{Code, Status};
+
+/// Metadata of a gRPC status carrying its `ErrorCode`, as a number
+pub const ERROR_CODE_HEADER: &str = "x-amwaj-error-code";
+
+/// Metadata of a gRPC status telling whether to retry, `true` or `false`
+pub const RETRYABLE_HEADER: &str = "x-amwaj-retryable";
+
+/// How bad an error is for the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Caused by the request or expected under load, the server is fine
+    Warning,
+    /// The operation failed
+    Error,
+    /// The server can't work as configured or lost a dependency
+    Critical,
+}
+
+impl Severity {
+    /// Get the label of the severity in metrics and logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Where in the media path an error happened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub session_id: Option<String>,
+    /// SSRC of the RTP stream
+    pub ssrc: Option<u32>,
+    /// Index of the frame in the session
+    pub frame: Option<u64>,
+    /// Media time of the frame since the session started, ms
+    pub timestamp_ms: Option<i64>,
+}
+
+impl ErrorContext {
+    /// Create the context of a session
+    pub fn session(session_id: &str) -> Self {
+        Self {
+            session_id: Some(session_id.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// Add the RTP stream
+    pub fn with_ssrc(mut self, ssrc: u32) -> Self {
+        self.ssrc = Some(ssrc);
+        self
+    }
+
+    /// Add the frame, by its index and media time in the session
+    pub fn with_frame(mut self, frame: u64, timestamp_ms: i64) -> Self {
+        self.frame = Some(frame);
+        self.timestamp_ms = Some(timestamp_ms);
+        self
+    }
+
+    /// Fill the fields left unset from `outer`
+    fn merge(&mut self, outer: ErrorContext) {
+        self.session_id = self.session_id.take().or(outer.session_id);
+        self.ssrc = self.ssrc.or(outer.ssrc);
+        self.frame = self.frame.or(outer.frame);
+        self.timestamp_ms = self.timestamp_ms.or(outer.timestamp_ms);
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields = Vec::new();
+        if let Some(session_id) = &self.session_id {
+            fields.push(format!("session {}", session_id));
+        }
+        if let Some(ssrc) = self.ssrc {
+            fields.push(format!("ssrc {:#010x}", ssrc));
+        }
+        if let Some(frame) = self.frame {
+            fields.push(format!("frame {}", frame));
+        }
+        if let Some(timestamp_ms) = self.timestamp_ms {
+            fields.push(format!("at {}ms", timestamp_ms));
+        }
+        f.write_str(&fields.join(", "))
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum AmwajError {
@@ -19,14 +121,276 @@ pub enum AmwajError {
     #[error("Turn detection error: {0}")]
     DetectionError(String),
 
+    #[error("RTP parse error: {0}")]
+    RtpParseError(String),
+
+    #[error("Codec error: {0}")]
+    CodecError(String),
+
+    #[error("Session not found: {0}")]
+    SessionNotFound(String),
+
+    /// Another instance holds the lease on the session
+    #[error("{0}")]
+    SessionOwnedElsewhere(String),
+
+    /// The instance, or a user, is at its session limit
+    #[error("{0}")]
+    CapacityExceeded(String),
+
+    #[error("Resource limit exceeded: {0}")]
+    ResourceLimit(String),
+
+    #[error("Invalid metadata: {0}")]
+    InvalidMetadata(String),
+
+    #[error("Invalid tags: {0}")]
+    InvalidTags(String),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
+    /// A session store backend failed
+    #[error("Session store error: {0}")]
+    StoreError(#[source] anyhow::Error),
+
     #[error("Unknown error: {0}")]
     Unknown(String),
+
+    /// A bug, like a panic caught while processing a session
+    #[error("Internal error: {0}")]
+    Internal(String),
+
+    /// An error with where it happened, see `AmwajError::with_context`
+    #[error("{error} ({context})")]
+    WithContext {
+        error: Box<AmwajError>,
+        context: ErrorContext,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, AmwajError>;
+
+impl AmwajError {
+    /// Attach where the error happened
+    ///
+    /// Context added further out only fills the fields left unset, the
+    /// innermost context is the most precise.
+    pub fn with_context(self, context: ErrorContext) -> Self {
+        match self {
+            AmwajError::WithContext {
+                error,
+                context: mut inner,
+            } => {
+                inner.merge(context);
+                AmwajError::WithContext {
+                    error,
+                    context: inner,
+                }
+            }
+            error => AmwajError::WithContext {
+                error: Box::new(error),
+                context,
+            },
+        }
+    }
+
+    /// Get where the error happened, if known
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            AmwajError::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Get the error without its context, for matching on the variant
+    pub fn kind(&self) -> &AmwajError {
+        match self {
+            AmwajError::WithContext { error, .. } => error.kind(),
+            error => error,
+        }
+    }
+
+    /// Get the stable code of the error
+    pub fn code(&self) -> ErrorCode {
+        match self.kind() {
+            AmwajError::WebRtcError(_) => ErrorCode::Webrtc,
+            AmwajError::AudioError(_) => ErrorCode::Audio,
+            AmwajError::GrpcError(_) => ErrorCode::Grpc,
+            AmwajError::ConfigError(_) => ErrorCode::Config,
+            AmwajError::DetectionError(_) => ErrorCode::Detection,
+            AmwajError::RtpParseError(_) => ErrorCode::RtpParse,
+            AmwajError::CodecError(_) => ErrorCode::Codec,
+            AmwajError::SessionNotFound(_) => ErrorCode::SessionNotFound,
+            AmwajError::SessionOwnedElsewhere(_) => ErrorCode::SessionOwnedElsewhere,
+            AmwajError::CapacityExceeded(_) => ErrorCode::CapacityExceeded,
+            AmwajError::ResourceLimit(_) => ErrorCode::ResourceLimit,
+            AmwajError::InvalidMetadata(_) => ErrorCode::InvalidMetadata,
+            AmwajError::InvalidTags(_) => ErrorCode::InvalidTags,
+            AmwajError::IoError(_) => ErrorCode::Io,
+            AmwajError::SerializationError(_) => ErrorCode::Serialization,
+            AmwajError::StoreError(_) => ErrorCode::Store,
+            AmwajError::Unknown(_) => ErrorCode::Unknown,
+            AmwajError::Internal(_) => ErrorCode::Internal,
+            AmwajError::WithContext { .. } => unreachable!("kind() has no context"),
+        }
+    }
+
+    /// Check if the same call can succeed later, after a backoff
+    ///
+    /// Capacity and ownership errors may also succeed at once on another
+    /// instance.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind(),
+            AmwajError::CapacityExceeded(_)
+                | AmwajError::SessionOwnedElsewhere(_)
+                | AmwajError::StoreError(_)
+                | AmwajError::IoError(_)
+        )
+    }
+
+    /// Get how bad the error is for the server
+    pub fn severity(&self) -> Severity {
+        match self.kind() {
+            AmwajError::RtpParseError(_)
+            | AmwajError::SessionNotFound(_)
+            | AmwajError::SessionOwnedElsewhere(_)
+            | AmwajError::CapacityExceeded(_)
+            | AmwajError::ResourceLimit(_)
+            | AmwajError::InvalidMetadata(_)
+            | AmwajError::InvalidTags(_) => Severity::Warning,
+            AmwajError::ConfigError(_)
+            | AmwajError::IoError(_)
+            | AmwajError::StoreError(_)
+            | AmwajError::Internal(_) => Severity::Critical,
+            _ => Severity::Error,
+        }
+    }
+
+    /// Get the gRPC status code of the error
+    pub fn status_code(&self) -> Code {
+        match self.kind() {
+            AmwajError::RtpParseError(_)
+            | AmwajError::InvalidMetadata(_)
+            | AmwajError::InvalidTags(_) => Code::InvalidArgument,
+            AmwajError::SessionNotFound(_) => Code::NotFound,
+            AmwajError::SessionOwnedElsewhere(_) => Code::FailedPrecondition,
+            AmwajError::CapacityExceeded(_) | AmwajError::ResourceLimit(_) => {
+                Code::ResourceExhausted
+            }
+            AmwajError::StoreError(_) | AmwajError::IoError(_) => Code::Unavailable,
+            _ => Code::Internal,
+        }
+    }
+
+    /// Convert to a gRPC status carrying the error code and retryability
+    pub fn to_status(&self) -> Status {
+        let mut status = Status::new(self.status_code(), self.to_string());
+        let metadata = status.metadata_mut();
+        metadata.insert(ERROR_CODE_HEADER, (self.code() as i32).into());
+        let retryable = if self.is_retryable() { "true" } else { "false" };
+        metadata.insert(RETRYABLE_HEADER, retryable.parse().expect("ASCII value"));
+        status
+    }
+}
+
+/// Get the error code of a status returned by the server
+///
+/// `ErrorCode::Unspecified` for statuses without one, like transport errors.
+pub fn error_code(status: &Status) -> ErrorCode {
+    status
+        .metadata()
+        .get(ERROR_CODE_HEADER)
+        .and_then(|value| value.to_str().ok()?.parse::<i32>().ok())
+        .and_then(|code| ErrorCode::try_from(code).ok())
+        .unwrap_or(ErrorCode::Unspecified)
+}
+
+/// Check if a call that failed with `status` is worth retrying
+///
+/// Follows the server's `x-amwaj-retryable` metadata, and the status code
+/// when there is none.
+pub fn is_retryable(status: &Status) -> bool {
+    match status
+        .metadata()
+        .get(RETRYABLE_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(retryable) => retryable == "true",
+        None => matches!(
+            status.code(),
+            Code::Unavailable | Code::ResourceExhausted | Code::Aborted | Code::DeadlineExceeded
+        ),
+    }
+}
+
+impl From<anyhow::Error> for AmwajError {
+    /// Keep the variant of an `AmwajError` passed through `anyhow`, anything
+    /// else came from a session store
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<AmwajError>() {
+            Ok(error) => error,
+            Err(error) => AmwajError::StoreError(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_anyhow() {
+        let error: anyhow::Error = AmwajError::SessionNotFound("s1".into()).into();
+        assert!(matches!(
+            AmwajError::from(error),
+            AmwajError::SessionNotFound(_)
+        ));
+        let error = AmwajError::from(anyhow::anyhow!("connection refused"));
+        assert!(matches!(error, AmwajError::StoreError(_)));
+        assert_eq!(error.to_string(), "Session store error: connection refused");
+    }
+
+    #[test]
+    fn test_context() {
+        let error = AmwajError::CodecError("Empty opus data".into())
+            .with_context(ErrorContext::default().with_ssrc(0x1234))
+            .with_context(ErrorContext::session("s1").with_ssrc(1).with_frame(3, 60));
+        assert!(matches!(error.kind(), AmwajError::CodecError(_)));
+        assert_eq!(error.code(), ErrorCode::Codec);
+        let context = error.context().unwrap();
+        assert_eq!(context.session_id.as_deref(), Some("s1"));
+        // The innermost context wins
+        assert_eq!(context.ssrc, Some(0x1234));
+        assert_eq!(
+            error.to_string(),
+            "Codec error: Empty opus data (session s1, ssrc 0x00001234, frame 3, at 60ms)"
+        );
+    }
+
+    #[test]
+    fn test_status_round_trip() {
+        let error = AmwajError::CapacityExceeded("Maximum session limit reached".into());
+        assert_eq!(error.severity(), Severity::Warning);
+        let status = error.to_status();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(error_code(&status), ErrorCode::CapacityExceeded);
+        assert!(is_retryable(&status));
+
+        let status = AmwajError::InvalidTags("too many".into()).to_status();
+        assert_eq!(error_code(&status), ErrorCode::InvalidTags);
+        assert!(!is_retryable(&status));
+
+        // Statuses without the metadata fall back to their code
+        assert_eq!(
+            error_code(&Status::unavailable("down")),
+            ErrorCode::Unspecified
+        );
+        assert!(is_retryable(&Status::unavailable("down")));
+        assert!(!is_retryable(&Status::not_found("gone")));
+    }
+}
@@ -0,0 +1,651 @@
+//! Admin API for runtime introspection
+//!
+//! Lets operators list live sessions, find the heaviest ones, compare the
+//! load of instances, read the effective configuration, debug or record a
+//! single session and end stuck ones. It is served next to the media API but only
+//! accepts the `[grpc.admin]` credentials.
+
+use crate::grpc::convert;
+use crate::grpc::service::{AmwajMediaService, EndReason, SessionResources, SessionStats};
+use crate::proto;
+use crate::proto::amwaj_admin_server::AmwajAdmin;
+use crate::session::{matches_tags, UsageMetric};
+use tonic::{Request, Response, Status};
+
+/// Admin API over a media service
+#[derive(Clone)]
+pub struct AdminService {
+    media: AmwajMediaService,
+}
+
+impl AdminService {
+    /// Create the admin API for a media service
+    pub fn new(media: AmwajMediaService) -> Self {
+        Self { media }
+    }
+}
+
+impl From<SessionStats> for proto::SessionStats {
+    fn from(stats: SessionStats) -> Self {
+        Self {
+            session_id: stats.session_id,
+            created_at_ms: stats.created_at_ms,
+            idle_ms: stats.idle_ms as i64,
+            turn_state: convert::state_name(stats.turn_state).to_string(),
+            frames_processed: stats.frames_processed,
+            stream_attached: stats.stream_attached,
+            event_queue_depth: stats.event_queue_depth as u32,
+            playback_attached: stats.playback_attached,
+            debug: stats.debug,
+            recording: stats.recording,
+            rtp_packets_processed: stats.rtp_packets_processed,
+            jitter_buffer_packets: stats.jitter_buffer_packets as u32,
+            jitter_buffer_level_percent: stats.jitter_buffer_level_percent,
+            packet_loss_ratio: stats.packet_loss_ratio,
+            jitter_ms: stats.jitter_ms,
+            rtt_ms: stats.rtt_ms,
+            mos: stats.mos,
+            paused: stats.paused,
+            paused_ms: stats.paused_ms,
+            tags: stats.tags,
+            tenant: stats.tenant.unwrap_or_default(),
+        }
+    }
+}
+
+/// Sessions listed by `TopSessions` unless the request asks otherwise
+const DEFAULT_TOP_SESSIONS: usize = 10;
+
+impl From<SessionResources> for proto::SessionUsage {
+    fn from(resources: SessionResources) -> Self {
+        Self {
+            session_id: resources.session_id,
+            cpu_ms: resources.cpu_ms,
+            cpu_ms_per_sec: resources.cpu_ms_per_sec as f32,
+            buffered_bytes: resources.buffered_bytes as u64,
+            bytes_in: resources.bytes_in,
+            bytes_out: resources.bytes_out,
+            bandwidth_bytes_per_sec: resources.bandwidth_bytes_per_sec as f32,
+            degraded: resources.degraded,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl AmwajAdmin for AdminService {
+    async fn list_sessions(
+        &self,
+        request: Request<proto::ListSessionsRequest>,
+    ) -> Result<Response<proto::ListSessionsResponse>, Status> {
+        let request = request.into_inner();
+        // Stats come ordered by session ID, pages resume after the last one
+        let mut sessions: Vec<SessionStats> = self
+            .media
+            .session_stats()
+            .into_iter()
+            .filter(|stats| matches_tags(&stats.tags, &request.tags))
+            .filter(|stats| stats.session_id > request.page_token)
+            .collect();
+        let page_size = request.page_size as usize;
+        let mut next_page_token = String::new();
+        if page_size > 0 && sessions.len() > page_size {
+            sessions.truncate(page_size);
+            next_page_token = sessions[page_size - 1].session_id.clone();
+        }
+        Ok(Response::new(proto::ListSessionsResponse {
+            sessions: sessions.into_iter().map(Into::into).collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn top_sessions(
+        &self,
+        request: Request<proto::TopSessionsRequest>,
+    ) -> Result<Response<proto::TopSessionsResponse>, Status> {
+        let request = request.into_inner();
+        let metric = match proto::UsageMetric::try_from(request.metric) {
+            Ok(proto::UsageMetric::Cpu) => UsageMetric::Cpu,
+            Ok(proto::UsageMetric::Memory) => UsageMetric::Memory,
+            Ok(proto::UsageMetric::Bandwidth) => UsageMetric::Bandwidth,
+            Err(_) => {
+                return Err(Status::invalid_argument(format!(
+                    "Unknown usage metric: {}",
+                    request.metric
+                )))
+            }
+        };
+        let limit = match request.limit {
+            0 => DEFAULT_TOP_SESSIONS,
+            limit => limit as usize,
+        };
+        let sessions = self
+            .media
+            .heaviest_sessions(metric, limit)
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        Ok(Response::new(proto::TopSessionsResponse { sessions }))
+    }
+
+    async fn list_orphaned_sessions(
+        &self,
+        _request: Request<proto::ListOrphanedSessionsRequest>,
+    ) -> Result<Response<proto::ListOrphanedSessionsResponse>, Status> {
+        let sessions = self
+            .media
+            .session_manager()
+            .orphaned_sessions()
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        Ok(Response::new(proto::ListOrphanedSessionsResponse {
+            sessions,
+        }))
+    }
+
+    async fn list_instance_load(
+        &self,
+        _request: Request<proto::ListInstanceLoadRequest>,
+    ) -> Result<Response<proto::ListInstanceLoadResponse>, Status> {
+        let instances = self
+            .media
+            .session_manager()
+            .load_reports()
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        Ok(Response::new(proto::ListInstanceLoadResponse { instances }))
+    }
+
+    async fn get_config(
+        &self,
+        _request: Request<proto::GetConfigRequest>,
+    ) -> Result<Response<proto::ConfigDump>, Status> {
+        let toml = toml::to_string_pretty(&self.media.config().redacted())
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(proto::ConfigDump { toml }))
+    }
+
+    async fn set_session_debug(
+        &self,
+        request: Request<proto::SetSessionDebugRequest>,
+    ) -> Result<Response<proto::SessionStats>, Status> {
+        let request = request.into_inner();
+        self.media
+            .set_session_debug(&request.session_id, request.enabled)
+            .map_err(|e| Status::not_found(e.to_string()))?;
+        let stats = self
+            .media
+            .session_stats()
+            .into_iter()
+            .find(|stats| stats.session_id == request.session_id)
+            .ok_or_else(|| Status::not_found(format!("Unknown session: {}", request.session_id)))?;
+        Ok(Response::new(stats.into()))
+    }
+
+    async fn get_session_debug_log(
+        &self,
+        request: Request<proto::GetSessionRequest>,
+    ) -> Result<Response<proto::SessionDebugLog>, Status> {
+        let session_id = request.into_inner().session_id;
+        let (lines, dropped_lines) = self
+            .media
+            .session_debug_log(&session_id)
+            .map_err(|e| Status::not_found(e.to_string()))?;
+        Ok(Response::new(proto::SessionDebugLog {
+            session_id,
+            lines: lines
+                .into_iter()
+                .map(|line| proto::DebugLogLine {
+                    timestamp_ms: line.timestamp_ms,
+                    message: line.message,
+                })
+                .collect(),
+            dropped_lines,
+        }))
+    }
+
+    async fn set_session_recording(
+        &self,
+        request: Request<proto::SetSessionRecordingRequest>,
+    ) -> Result<Response<proto::SessionStats>, Status> {
+        let request = request.into_inner();
+        if self.media.config().recorder.is_none() {
+            return Err(Status::failed_precondition("Recording is not configured"));
+        }
+        self.media
+            .set_session_recording(&request.session_id, request.enabled)
+            .map_err(|e| Status::not_found(e.to_string()))?;
+        let stats = self
+            .media
+            .session_stats()
+            .into_iter()
+            .find(|stats| stats.session_id == request.session_id)
+            .ok_or_else(|| Status::not_found(format!("Unknown session: {}", request.session_id)))?;
+        Ok(Response::new(stats.into()))
+    }
+
+    async fn force_end_session(
+        &self,
+        request: Request<proto::EndSessionRequest>,
+    ) -> Result<Response<proto::SessionEnded>, Status> {
+        let session_id = request.into_inner().session_id;
+        let event = self
+            .media
+            .end_session_with_reason(&session_id, EndReason::Forced)
+            .await
+            .map_err(|e| Status::not_found(e.to_string()))?;
+        tracing::warn!("Session {} force-ended by an admin", session_id);
+        match proto::MediaEvent::from(event).event {
+            Some(proto::media_event::Event::SessionEnded(ended)) => Ok(Response::new(ended)),
+            _ => Err(Status::internal("Unexpected session end event")),
+        }
+    }
+
+    async fn migrate_session(
+        &self,
+        request: Request<proto::MigrateSessionRequest>,
+    ) -> Result<Response<proto::MigrateSessionResponse>, Status> {
+        let session_id = request.into_inner().session_id;
+        if self.media.session_status(&session_id).await.is_none() {
+            return Err(Status::not_found(format!(
+                "Unknown session: {}",
+                session_id
+            )));
+        }
+        let snapshot = self
+            .media
+            .migrate_session(&session_id)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        let snapshot_bytes = serde_json::to_vec(&snapshot)
+            .map_err(|e| Status::internal(e.to_string()))?
+            .len();
+        tracing::info!("Session {} migrated by an admin", session_id);
+        Ok(Response::new(proto::MigrateSessionResponse {
+            session_id,
+            frames_processed: snapshot.frames_processed,
+            snapshot_bytes: snapshot_bytes as u32,
+            turn_state_carried: snapshot.detection.is_some(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AdminConfig, ApiKeyConfig, AuthConfig, Config, RecorderConfig};
+    use crate::grpc::service::OrchestrationCommand;
+    use crate::metrics::Metrics;
+    use crate::session::{read_trace, TraceRecord};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn admin() -> (AmwajMediaService, AdminService) {
+        let mut config = Config::default();
+        config.grpc.admin = Some(AdminConfig {
+            auth: AuthConfig {
+                api_keys: vec![ApiKeyConfig {
+                    principal: "ops".to_string(),
+                    key: "admin-secret".into(),
+                    key_file: None,
+                }],
+                jwt: None,
+            },
+        });
+        let metrics = Arc::new(Metrics::new(&config));
+        let media = AmwajMediaService::new(config, metrics);
+        (media.clone(), AdminService::new(media))
+    }
+
+    #[tokio::test]
+    async fn test_list_and_debug_sessions() {
+        let (media, admin) = admin();
+        media
+            .create_session(crate::grpc::service::SessionOptions {
+                session_id: Some("s1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        media.push_audio("s1", &[0i16; 320]).unwrap();
+
+        let sessions = admin
+            .list_sessions(Request::new(proto::ListSessionsRequest::default()))
+            .await
+            .unwrap()
+            .into_inner()
+            .sessions;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].session_id, "s1");
+        assert_eq!(sessions[0].frames_processed, 1);
+        assert_eq!(sessions[0].turn_state, "idle");
+        assert!(!sessions[0].debug);
+
+        let stats = admin
+            .set_session_debug(Request::new(proto::SetSessionDebugRequest {
+                session_id: "s1".to_string(),
+                enabled: true,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(stats.debug);
+
+        media
+            .apply_command(&OrchestrationCommand::StopAudio {
+                session_id: "s1".to_string(),
+                reason: String::new(),
+            })
+            .unwrap();
+        let log = admin
+            .get_session_debug_log(Request::new(proto::GetSessionRequest {
+                session_id: "s1".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            log.lines.last().unwrap().message,
+            "Command stop_audio Completed"
+        );
+        assert_eq!(log.dropped_lines, 0);
+
+        let missing = admin
+            .set_session_debug(Request::new(proto::SetSessionDebugRequest {
+                session_id: "missing".to_string(),
+                enabled: true,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+
+        let ended = admin
+            .force_end_session(Request::new(proto::EndSessionRequest {
+                session_id: "s1".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(ended.total_frames, 1);
+        assert_eq!(media.session_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_session_recording() {
+        let (media, admin) = admin();
+        media
+            .create_session(crate::grpc::service::SessionOptions {
+                session_id: Some("s1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let request = || {
+            Request::new(proto::SetSessionRecordingRequest {
+                session_id: "s1".to_string(),
+                enabled: true,
+            })
+        };
+        let error = admin.set_session_recording(request()).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::FailedPrecondition);
+
+        let dir = std::env::temp_dir().join(format!("amwaj-admin-{}", uuid::Uuid::new_v4()));
+        let mut config = media.config().clone();
+        config.recorder = Some(RecorderConfig {
+            dir: dir.to_string_lossy().into_owned(),
+            ..Default::default()
+        });
+        let media = AmwajMediaService::new(config, Arc::new(Metrics::new(&Config::default())));
+        let admin = AdminService::new(media.clone());
+        media
+            .create_session(crate::grpc::service::SessionOptions {
+                session_id: Some("s1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        media.push_audio("s1", &[0i16; 320]).unwrap();
+        let stats = admin
+            .set_session_recording(request())
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(stats.recording);
+        for _ in 0..3 {
+            media.push_audio("s1", &[0i16; 320]).unwrap();
+        }
+        admin
+            .set_session_recording(Request::new(proto::SetSessionRecordingRequest {
+                session_id: "s1".to_string(),
+                enabled: false,
+            }))
+            .await
+            .unwrap();
+        media.push_audio("s1", &[0i16; 320]).unwrap();
+
+        let trace = std::fs::read_dir(&dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let records: Vec<TraceRecord> = read_trace(&trace)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.record)
+            .collect();
+        assert!(matches!(&records[0], TraceRecord::Start { session_id, .. } if session_id == "s1"));
+        let frames: Vec<i64> = records
+            .iter()
+            .filter_map(|record| match record {
+                TraceRecord::Frame { media_ms, .. } => Some(*media_ms),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(frames, vec![40, 60, 80]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_list_sessions_by_tags() {
+        let (media, admin) = admin();
+        for (session_id, lang) in [("s1", "ar"), ("s2", "en"), ("s3", "ar"), ("s4", "ar")] {
+            media
+                .create_session(crate::grpc::service::SessionOptions {
+                    session_id: Some(session_id.to_string()),
+                    tags: HashMap::from([("lang".to_string(), lang.to_string())]),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        media
+            .apply_command(&OrchestrationCommand::SetTags {
+                session_id: "s4".to_string(),
+                tags: HashMap::from([("campaign".to_string(), "support".to_string())]),
+                remove: vec!["lang".to_string()],
+            })
+            .unwrap();
+
+        let list = |tags: &[(&str, &str)], page_size, page_token: &str| {
+            admin.list_sessions(Request::new(proto::ListSessionsRequest {
+                tags: tags
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                page_size,
+                page_token: page_token.to_string(),
+            }))
+        };
+        let ids = |response: &proto::ListSessionsResponse| -> Vec<String> {
+            response
+                .sessions
+                .iter()
+                .map(|stats| stats.session_id.clone())
+                .collect()
+        };
+
+        let page = list(&[("lang", "ar")], 1, "").await.unwrap().into_inner();
+        assert_eq!(ids(&page), vec!["s1"]);
+        assert_eq!(page.sessions[0].tags["lang"], "ar");
+        let page = list(&[("lang", "ar")], 1, &page.next_page_token)
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(ids(&page), vec!["s3"]);
+        assert!(page.next_page_token.is_empty());
+
+        let all = list(&[("lang", "")], 0, "").await.unwrap().into_inner();
+        assert_eq!(ids(&all), vec!["s1", "s2", "s3"]);
+        let tagged = list(&[("campaign", "support")], 0, "")
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(ids(&tagged), vec!["s4"]);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_session() {
+        let (media, admin) = admin();
+        media
+            .create_session(crate::grpc::service::SessionOptions {
+                session_id: Some("s1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        media.push_audio("s1", &[0i16; 320]).unwrap();
+
+        let migrated = admin
+            .migrate_session(Request::new(proto::MigrateSessionRequest {
+                session_id: "s1".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(migrated.frames_processed, 1);
+        assert!(migrated.turn_state_carried);
+        assert!(migrated.snapshot_bytes > 0);
+        assert_eq!(media.session_count(), 0);
+
+        let missing = admin
+            .migrate_session(Request::new(proto::MigrateSessionRequest {
+                session_id: "s1".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_config_dump_redacted() {
+        let (_, admin) = admin();
+        let dump = admin
+            .get_config(Request::new(proto::GetConfigRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .toml;
+
+        let config: Config = toml::from_str(&dump).unwrap();
+        assert_eq!(config.server.port, 50051);
+        assert!(!dump.contains("admin-secret"));
+        assert_eq!(
+            config.grpc.admin.unwrap().auth.api_keys[0].key,
+            "<redacted>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_top_sessions() {
+        let (media, admin) = admin();
+        for session_id in ["s1", "s2", "s3"] {
+            media
+                .create_session(crate::grpc::service::SessionOptions {
+                    session_id: Some(session_id.to_string()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        media.push_audio("s2", &[0i16; 320]).unwrap();
+        media.push_audio("s2", &[0i16; 320]).unwrap();
+        media.push_audio("s3", &[0i16; 320]).unwrap();
+
+        let top = |metric: proto::UsageMetric, limit: u32| {
+            let admin = admin.clone();
+            async move {
+                admin
+                    .top_sessions(Request::new(proto::TopSessionsRequest {
+                        metric: metric as i32,
+                        limit,
+                    }))
+                    .await
+                    .map(|response| response.into_inner().sessions)
+            }
+        };
+        let sessions = top(proto::UsageMetric::Bandwidth, 2).await.unwrap();
+        let ids: Vec<&str> = sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s2", "s3"]);
+        assert_eq!(sessions[0].bytes_in, 1280);
+        assert_eq!(top(proto::UsageMetric::Cpu, 0).await.unwrap().len(), 3);
+
+        let error = admin
+            .top_sessions(Request::new(proto::TopSessionsRequest {
+                metric: 7,
+                limit: 0,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_list_instance_load() {
+        let (media, admin) = admin();
+        media
+            .create_session(crate::grpc::service::SessionOptions::default())
+            .await
+            .unwrap();
+        let report = media.load_report();
+        assert_eq!(report.active_sessions, 1);
+        assert_eq!(report.max_sessions, 10000);
+        let manager = media.session_manager();
+        manager.publish_load(&report).await.unwrap();
+        manager
+            .publish_load(&crate::session::LoadReport {
+                instance_id: "other".to_string(),
+                cpu_headroom: 0.2,
+                ..report.clone()
+            })
+            .await
+            .unwrap();
+
+        let list = || {
+            let admin = admin.clone();
+            async move {
+                admin
+                    .list_instance_load(Request::new(proto::ListInstanceLoadRequest {}))
+                    .await
+                    .unwrap()
+                    .into_inner()
+                    .instances
+            }
+        };
+        let instances = list().await;
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[0].instance_id, manager.instance_id());
+        assert_eq!(instances[1].instance_id, "other");
+
+        // A draining instance is published right away and placed last
+        media.drain(std::time::Duration::ZERO).await;
+        let instances = list().await;
+        assert_eq!(instances[1].instance_id, manager.instance_id());
+        assert!(instances[1].draining);
+    }
+}
@@ -0,0 +1,286 @@
+//! Raw audio carried over gRPC
+//!
+//! Clients without WebRTC push PCM or Opus chunks with `StreamAudioIn`, and
+//! renderers receive the agent's playback audio with `StreamAudioOut`. Each
+//! chunk carries a sequence number so gaps and reordering can be detected.
+
+use crate::metrics::{telemetry, Metrics, STAGE_DECODE};
+use crate::webrtc::codec;
+use crate::webrtc::OpusDecoder;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Encoding of an audio chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioEncoding {
+    /// Signed 16-bit little-endian PCM, interleaved
+    Pcm16,
+    /// One Opus packet per chunk
+    Opus,
+    /// G.711 mu-law, one byte per sample, as telephony carries it
+    Pcmu,
+}
+
+impl AudioEncoding {
+    /// Encoding for an orchestrator `audio_format` string
+    pub fn from_format(format: &str) -> Self {
+        if format.eq_ignore_ascii_case("opus") {
+            AudioEncoding::Opus
+        } else if format.eq_ignore_ascii_case("pcmu") || format.eq_ignore_ascii_case("mulaw") {
+            AudioEncoding::Pcmu
+        } else {
+            AudioEncoding::Pcm16
+        }
+    }
+
+    /// Stable name used in logs and metric labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioEncoding::Pcm16 => "pcm16",
+            AudioEncoding::Opus => "opus",
+            AudioEncoding::Pcmu => "pcmu",
+        }
+    }
+}
+
+/// A chunk of audio streamed over gRPC
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioChunk {
+    pub session_id: String,
+    pub sequence_number: u64,
+    pub timestamp_ms: i64,
+    pub encoding: AudioEncoding,
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub data: Vec<u8>,
+}
+
+/// Counters for an ingest stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    pub chunks_received: u64,
+    pub frames_produced: u64,
+    /// Chunks skipped over by a jump in sequence numbers
+    pub chunks_missing: u64,
+    /// Late or duplicate chunks that were dropped
+    pub chunks_dropped: u64,
+}
+
+/// Turns ingested chunks into fixed-size mono PCM frames
+pub struct AudioIngest {
+    sample_rate: u32,
+    frame_size: usize,
+    buffer: Vec<i16>,
+    decoder: Option<OpusDecoder>,
+    next_sequence: Option<u64>,
+    stats: IngestStats,
+    /// Decode time not taken yet, in ms
+    decode_ms: f64,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl AudioIngest {
+    /// Create an ingest producing `frame_size` samples at `sample_rate`
+    pub fn new(sample_rate: u32, frame_size: usize) -> Self {
+        Self {
+            sample_rate,
+            frame_size: frame_size.max(1),
+            buffer: Vec::with_capacity(frame_size * 2),
+            decoder: None,
+            next_sequence: None,
+            stats: IngestStats::default(),
+            decode_ms: 0.0,
+            metrics: None,
+        }
+    }
+
+    /// Report decode latency per codec to metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Add a chunk, returns the frames it completed
+    pub fn push(&mut self, chunk: &AudioChunk) -> anyhow::Result<Vec<Vec<i16>>> {
+        if chunk.sample_rate != self.sample_rate {
+            return Err(anyhow::anyhow!(
+                "Unsupported sample rate {}, expected {}",
+                chunk.sample_rate,
+                self.sample_rate
+            ));
+        }
+        self.stats.chunks_received += 1;
+
+        if let Some(expected) = self.next_sequence {
+            if chunk.sequence_number < expected {
+                self.stats.chunks_dropped += 1;
+                return Ok(Vec::new());
+            }
+            self.stats.chunks_missing += chunk.sequence_number - expected;
+        }
+        self.next_sequence = Some(chunk.sequence_number + 1);
+
+        let started = Instant::now();
+        let decode_span = telemetry::stage_span(STAGE_DECODE);
+        let decoding = decode_span.enter();
+        let samples: Vec<i16> = match chunk.encoding {
+            AudioEncoding::Pcm16 => {
+                if !chunk.data.len().is_multiple_of(2) {
+                    return Err(anyhow::anyhow!("PCM16 chunk has an odd byte count"));
+                }
+                chunk
+                    .data
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect()
+            }
+            AudioEncoding::Opus => {
+                let sample_rate = self.sample_rate;
+                self.decoder
+                    .get_or_insert_with(|| OpusDecoder::new(sample_rate))
+                    .decode(&chunk.data)?
+            }
+            AudioEncoding::Pcmu => codec::decode_pcmu(&chunk.data),
+        };
+        drop(decoding);
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        self.decode_ms += latency_ms;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_decode(chunk.encoding.as_str(), None, latency_ms);
+        }
+        self.buffer.extend(downmix(&samples, chunk.channels));
+
+        let mut frames = Vec::new();
+        while self.buffer.len() >= self.frame_size {
+            frames.push(self.buffer.drain(..self.frame_size).collect());
+        }
+        self.stats.frames_produced += frames.len() as u64;
+        Ok(frames)
+    }
+
+    /// Get the stream counters
+    pub fn stats(&self) -> IngestStats {
+        self.stats
+    }
+
+    /// Take the time spent decoding since the last call, in ms
+    pub fn take_decode_ms(&mut self) -> f64 {
+        std::mem::take(&mut self.decode_ms)
+    }
+}
+
+fn downmix(samples: &[i16], channels: u32) -> Vec<i16> {
+    let channels = channels.max(1) as usize;
+    if channels == 1 {
+        return samples.to_vec();
+    }
+    samples
+        .chunks(channels)
+        .map(|frame| (frame.iter().map(|s| *s as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcm_chunk(sequence_number: u64, samples: &[i16], channels: u32) -> AudioChunk {
+        AudioChunk {
+            session_id: "s1".to_string(),
+            sequence_number,
+            timestamp_ms: 0,
+            encoding: AudioEncoding::Pcm16,
+            sample_rate: 16000,
+            channels,
+            data: samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_chunks_reframed() {
+        let mut ingest = AudioIngest::new(16000, 320);
+
+        assert!(ingest.push(&pcm_chunk(0, &[1; 200], 1)).unwrap().is_empty());
+        let frames = ingest.push(&pcm_chunk(1, &[2; 500], 1)).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].len(), 320);
+        assert_eq!(frames[0][199], 1);
+        assert_eq!(frames[0][200], 2);
+    }
+
+    #[test]
+    fn test_sequence_tracking() {
+        let mut ingest = AudioIngest::new(16000, 320);
+        ingest.push(&pcm_chunk(0, &[0; 320], 1)).unwrap();
+        ingest.push(&pcm_chunk(3, &[0; 320], 1)).unwrap();
+        assert!(ingest.push(&pcm_chunk(2, &[0; 320], 1)).unwrap().is_empty());
+
+        let stats = ingest.stats();
+        assert_eq!(stats.chunks_received, 3);
+        assert_eq!(stats.chunks_missing, 2);
+        assert_eq!(stats.chunks_dropped, 1);
+        assert_eq!(stats.frames_produced, 2);
+    }
+
+    #[test]
+    fn test_stereo_downmix() {
+        let mut ingest = AudioIngest::new(16000, 2);
+        let frames = ingest.push(&pcm_chunk(0, &[100, 300, -50, 50], 2)).unwrap();
+        assert_eq!(frames, vec![vec![200, 0]]);
+    }
+
+    #[test]
+    fn test_opus_and_rejections() {
+        let mut ingest = AudioIngest::new(16000, 320);
+        let opus = AudioChunk {
+            encoding: AudioEncoding::Opus,
+            data: vec![0xfc, 0xff],
+            ..pcm_chunk(0, &[], 1)
+        };
+        assert_eq!(ingest.push(&opus).unwrap().len(), 1);
+
+        let wrong_rate = AudioChunk {
+            sample_rate: 48000,
+            ..pcm_chunk(1, &[0; 320], 1)
+        };
+        assert!(ingest.push(&wrong_rate).is_err());
+
+        let odd = AudioChunk {
+            data: vec![0; 3],
+            ..pcm_chunk(2, &[], 1)
+        };
+        assert!(ingest.push(&odd).is_err());
+    }
+
+    #[test]
+    fn test_decode_metrics_per_codec() {
+        let metrics = Arc::new(Metrics::new(&crate::config::Config::default()));
+        let mut ingest = AudioIngest::new(16000, 320).with_metrics(Arc::clone(&metrics));
+        ingest.push(&pcm_chunk(0, &[0; 320], 1)).unwrap();
+        let opus = AudioChunk {
+            encoding: AudioEncoding::Opus,
+            data: vec![0xfc, 0xff],
+            ..pcm_chunk(1, &[], 1)
+        };
+        ingest.push(&opus).unwrap();
+        ingest.push(&opus.clone()).unwrap();
+
+        let decoded = |codec: &str| {
+            metrics
+                .audio_packets_decoded
+                .with_label_values(&[codec, "none"])
+                .get()
+        };
+        assert_eq!(decoded("pcm16"), 1);
+        // The late duplicate is dropped before decoding
+        assert_eq!(decoded("opus"), 1);
+        let latency = metrics
+            .stage_latency_ms
+            .with_label_values(&["decode", "opus"]);
+        assert_eq!(latency.get_sample_count(), 1);
+        assert!(ingest.take_decode_ms() > 0.0);
+        assert_eq!(ingest.take_decode_ms(), 0.0);
+    }
+}
@@ -0,0 +1,471 @@
+//! Token authentication for the gRPC endpoint
+//!
+//! `TokenAuthenticator` is a tonic interceptor accepting static API keys
+//! (`x-api-key` header or bearer token) and JWTs verified against a JWKS
+//! endpoint. The authenticated `Principal` is attached to the request
+//! extensions; unauthenticated calls, streams included, fail with
+//! `UNAUTHENTICATED` before reaching the service.
+
+use crate::config::{AuthConfig, JwtConfig};
+use crate::metrics::Metrics;
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use parking_lot::RwLock;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// Metric label of callers authenticated with a JWT, whose subjects are
+/// not known in advance
+pub const JWT_PRINCIPAL_LABEL: &str = "other";
+
+/// How a caller authenticated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    ApiKey,
+    Jwt,
+}
+
+/// The authenticated caller of an RPC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// API key principal name or JWT subject
+    pub id: String,
+    pub method: AuthMethod,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: Option<String>,
+}
+
+/// A verification key and the algorithms it may be used with
+struct VerificationKey {
+    key: DecodingKey,
+    algorithms: Vec<Algorithm>,
+}
+
+/// Verifies JWTs against keys from a JWKS endpoint
+pub struct JwtVerifier {
+    config: JwtConfig,
+    keys: RwLock<HashMap<String, VerificationKey>>,
+    client: reqwest::Client,
+}
+
+impl JwtVerifier {
+    /// Create a verifier, keys are loaded with `refresh` or `set_jwks`
+    pub fn new(config: JwtConfig) -> Self {
+        Self {
+            config,
+            keys: RwLock::new(HashMap::new()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Replace the verification keys, returns the number loaded
+    ///
+    /// Keys without a key ID are skipped since tokens select keys by `kid`.
+    pub fn set_jwks(&self, jwks: &JwkSet) -> anyhow::Result<usize> {
+        let mut keys = HashMap::new();
+        for jwk in &jwks.keys {
+            let Some(kid) = &jwk.common.key_id else {
+                continue;
+            };
+            // Never let the token header pick an algorithm outside the key's family
+            let algorithms = match &jwk.algorithm {
+                AlgorithmParameters::RSA(_) => vec![
+                    Algorithm::RS256,
+                    Algorithm::RS384,
+                    Algorithm::RS512,
+                    Algorithm::PS256,
+                    Algorithm::PS384,
+                    Algorithm::PS512,
+                ],
+                AlgorithmParameters::EllipticCurve(_) => vec![Algorithm::ES256, Algorithm::ES384],
+                AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+                AlgorithmParameters::OctetKey(_) => {
+                    vec![Algorithm::HS256, Algorithm::HS384, Algorithm::HS512]
+                }
+            };
+            keys.insert(
+                kid.clone(),
+                VerificationKey {
+                    key: DecodingKey::from_jwk(jwk)?,
+                    algorithms,
+                },
+            );
+        }
+
+        let count = keys.len();
+        *self.keys.write() = keys;
+        Ok(count)
+    }
+
+    /// Fetch the JWKS and replace the verification keys
+    pub async fn refresh(&self) -> anyhow::Result<usize> {
+        let jwks: JwkSet = self
+            .client
+            .get(&self.config.jwks_url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        self.set_jwks(&jwks)
+    }
+
+    /// Verify a token, returns its subject
+    pub fn verify(&self, token: &str) -> anyhow::Result<String> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let kid = header
+            .kid
+            .ok_or_else(|| anyhow::anyhow!("Token has no key ID"))?;
+
+        let keys = self.keys.read();
+        let key = keys
+            .get(&kid)
+            .ok_or_else(|| anyhow::anyhow!("Unknown signing key: {}", kid))?;
+        if !key.algorithms.contains(&header.alg) {
+            return Err(anyhow::anyhow!(
+                "Algorithm {:?} not allowed for key {}",
+                header.alg,
+                kid
+            ));
+        }
+
+        let mut validation = Validation::new(header.alg);
+        match &self.config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        let claims = jsonwebtoken::decode::<Claims>(token, &key.key, &validation)?.claims;
+        claims
+            .sub
+            .ok_or_else(|| anyhow::anyhow!("Token has no subject"))
+    }
+}
+
+/// Interceptor authenticating every RPC with an API key or JWT
+#[derive(Clone)]
+pub struct TokenAuthenticator {
+    /// SHA-256 digests of the API keys and their principal names
+    api_keys: Arc<Vec<([u8; 32], String)>>,
+    jwt: Option<Arc<JwtVerifier>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl TokenAuthenticator {
+    /// Create an authenticator from the auth configuration
+    pub fn new(config: &AuthConfig) -> Self {
+        Self {
+            api_keys: Arc::new(
+                config
+                    .api_keys
+                    .iter()
+                    .map(|api_key| (digest(api_key.key.expose()), api_key.principal.clone()))
+                    .collect(),
+            ),
+            jwt: config
+                .jwt
+                .clone()
+                .map(|jwt| Arc::new(JwtVerifier::new(jwt))),
+            metrics: None,
+        }
+    }
+
+    /// Count authentications per principal
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Get the JWT verifier, if JWTs are accepted
+    pub fn jwt_verifier(&self) -> Option<&JwtVerifier> {
+        self.jwt.as_deref()
+    }
+
+    /// Periodically refresh the JWKS in the background
+    ///
+    /// Refresh failures are logged and the previous keys stay in use.
+    pub fn spawn_jwks_refresh(&self) -> Option<tokio::task::JoinHandle<()>> {
+        let jwt = Arc::clone(self.jwt.as_ref()?);
+        let interval = Duration::from_secs(jwt.config.jwks_refresh_secs.max(1));
+
+        Some(tokio::spawn(async move {
+            loop {
+                match jwt.refresh().await {
+                    Ok(count) => tracing::debug!("Loaded {} JWKS keys", count),
+                    Err(e) => tracing::warn!("Failed to refresh JWKS: {}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        }))
+    }
+
+    /// Authenticate a request from its metadata
+    pub fn authenticate(&self, metadata: &MetadataMap) -> anyhow::Result<Principal> {
+        let header = |name: &str| metadata.get(name).and_then(|value| value.to_str().ok());
+
+        let token = match (header("x-api-key"), header("authorization")) {
+            (Some(api_key), _) => api_key,
+            (None, Some(authorization)) => authorization
+                .strip_prefix("Bearer ")
+                .or_else(|| authorization.strip_prefix("bearer "))
+                .ok_or_else(|| anyhow::anyhow!("Expected a bearer token"))?,
+            (None, None) => return Err(anyhow::anyhow!("Missing credentials")),
+        };
+
+        if let Some(principal) = self.api_key_principal(token) {
+            return Ok(Principal {
+                id: principal.to_string(),
+                method: AuthMethod::ApiKey,
+            });
+        }
+
+        let jwt = self
+            .jwt
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Invalid API key"))?;
+        let subject = jwt.verify(token).map_err(|e| {
+            tracing::debug!("Rejected token: {}", e);
+            anyhow::anyhow!("Invalid token")
+        })?;
+        Ok(Principal {
+            id: subject,
+            method: AuthMethod::Jwt,
+        })
+    }
+
+    /// Find the principal of an API key
+    ///
+    /// Every configured key is compared in constant time, so the time taken
+    /// does not tell how much of a guess matched. Digests are compared rather
+    /// than the keys, which would give away their length.
+    fn api_key_principal(&self, token: &str) -> Option<&str> {
+        let token = digest(token);
+        self.api_keys.iter().fold(None, |found, (key, principal)| {
+            let matches: bool = key.ct_eq(&token).into();
+            found.or(matches.then_some(principal.as_str()))
+        })
+    }
+
+    /// Authenticate a request and count the outcome in the metrics
+    ///
+    /// Calls are counted by API key principal, JWT subjects all fall under
+    /// `JWT_PRINCIPAL_LABEL` to keep the label set bounded.
+    pub fn authenticate_counted(&self, metadata: &MetadataMap) -> anyhow::Result<Principal> {
+        let authenticated = self.authenticate(metadata);
+        if let Some(metrics) = &self.metrics {
+            match &authenticated {
+                Ok(principal) => metrics.record_auth_success(match principal.method {
+                    AuthMethod::ApiKey => &principal.id,
+                    AuthMethod::Jwt => JWT_PRINCIPAL_LABEL,
+                }),
+                Err(_) => metrics.record_auth_failure(),
+            }
+        }
+        authenticated
+    }
+}
+
+fn digest(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+impl Interceptor for TokenAuthenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let principal = self
+            .authenticate_counted(request.metadata())
+            .map_err(|e| Status::unauthenticated(e.to_string()))?;
+        request.extensions_mut().insert(principal);
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ApiKeyConfig, Config};
+    use jsonwebtoken::{EncodingKey, Header};
+
+    const SECRET: &[u8] = b"amwaj-test-secret";
+
+    fn jwt_config(jwks_url: &str) -> JwtConfig {
+        JwtConfig {
+            issuer: Some("https://auth.amwaj.test".to_string()),
+            audience: Some("amwaj-media".to_string()),
+            jwks_url: jwks_url.to_string(),
+            jwks_refresh_secs: 300,
+        }
+    }
+
+    fn jwks() -> JwkSet {
+        // base64url of SECRET
+        serde_json::from_value(serde_json::json!({
+            "keys": [{"kty": "oct", "kid": "k1", "k": "YW13YWotdGVzdC1zZWNyZXQ"}]
+        }))
+        .unwrap()
+    }
+
+    fn token(kid: &str, issuer: &str, subject: &str) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(kid.to_string());
+        let claims = serde_json::json!({
+            "sub": subject,
+            "iss": issuer,
+            "aud": "amwaj-media",
+            "exp": chrono::Utc::now().timestamp() + 600,
+        });
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    fn authenticator() -> TokenAuthenticator {
+        let config = AuthConfig {
+            api_keys: vec![ApiKeyConfig {
+                principal: "orchestrator".to_string(),
+                key: "key-123".into(),
+                key_file: None,
+            }],
+            jwt: Some(jwt_config("http://127.0.0.1:1/jwks")),
+        };
+        let authenticator = TokenAuthenticator::new(&config);
+        authenticator
+            .jwt_verifier()
+            .unwrap()
+            .set_jwks(&jwks())
+            .unwrap();
+        authenticator
+    }
+
+    fn with_header(header: &'static str, value: &str) -> Request<()> {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert(header, value.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn test_api_key() {
+        let mut authenticator = authenticator();
+
+        let request = authenticator
+            .call(with_header("x-api-key", "key-123"))
+            .unwrap();
+        let principal = request.extensions().get::<Principal>().unwrap();
+        assert_eq!(principal.id, "orchestrator");
+        assert_eq!(principal.method, AuthMethod::ApiKey);
+
+        assert!(authenticator
+            .call(with_header("authorization", "Bearer key-123"))
+            .is_ok());
+        for wrong in ["wrong", "key-12", "key-1234"] {
+            let status = authenticator
+                .call(with_header("x-api-key", wrong))
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+        let status = authenticator.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_jwt() {
+        let mut authenticator = authenticator();
+
+        let valid = token("k1", "https://auth.amwaj.test", "user-1");
+        let request = authenticator
+            .call(with_header("authorization", &format!("Bearer {}", valid)))
+            .unwrap();
+        let principal = request.extensions().get::<Principal>().unwrap();
+        assert_eq!(principal.id, "user-1");
+        assert_eq!(principal.method, AuthMethod::Jwt);
+
+        for invalid in [
+            token("k1", "https://evil.test", "user-1"),
+            token("k2", "https://auth.amwaj.test", "user-1"),
+        ] {
+            let status = authenticator
+                .call(with_header("authorization", &format!("Bearer {}", invalid)))
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+    }
+
+    #[test]
+    fn test_per_principal_metrics() {
+        let metrics = Arc::new(Metrics::new(&Config::default()));
+        let mut authenticator = authenticator().with_metrics(Arc::clone(&metrics));
+
+        authenticator
+            .call(with_header("x-api-key", "key-123"))
+            .unwrap();
+        authenticator
+            .call(with_header("x-api-key", "key-123"))
+            .unwrap();
+        assert!(authenticator
+            .call(with_header("x-api-key", "nope"))
+            .is_err());
+        for subject in ["user-1", "user-2"] {
+            let valid = token("k1", "https://auth.amwaj.test", subject);
+            authenticator
+                .call(with_header("authorization", &format!("Bearer {}", valid)))
+                .unwrap();
+        }
+
+        assert_eq!(
+            metrics
+                .auth_requests
+                .with_label_values(&["orchestrator"])
+                .get(),
+            2
+        );
+        // JWT subjects share one label
+        assert_eq!(
+            metrics
+                .auth_requests
+                .with_label_values(&[JWT_PRINCIPAL_LABEL])
+                .get(),
+            2
+        );
+        assert_eq!(metrics.auth_failures.get(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_jwks_refresh() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 1024];
+            let _ = socket.read(&mut buffer).await;
+            let body = serde_json::to_string(&jwks()).unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let verifier = JwtVerifier::new(jwt_config(&url));
+        assert_eq!(verifier.refresh().await.unwrap(), 1);
+        let subject = verifier
+            .verify(&token("k1", "https://auth.amwaj.test", "user-2"))
+            .unwrap();
+        assert_eq!(subject, "user-2");
+    }
+}
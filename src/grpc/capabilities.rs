@@ -0,0 +1,136 @@
+//! Wire schema versioning and capability negotiation
+//!
+//! The schema version is bumped whenever a change to the proto alters
+//! behavior a client relies on. Clients send the version they were built
+//! against in the `x-amwaj-schema-version` header of `MediaStream`; a
+//! version this server can't serve fails with `FAILED_PRECONDITION` instead
+//! of a silent mismatch. Clients predating the header are accepted.
+
+use crate::config::Config;
+use crate::detection::detector::TurnDetectorRegistry;
+use crate::proto;
+use tonic::metadata::MetadataMap;
+use tonic::Status;
+
+/// Header carrying the schema version a client expects
+pub const SCHEMA_VERSION_HEADER: &str = "x-amwaj-schema-version";
+
+/// Schema version spoken by this server
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Oldest client schema version still served
+pub const MIN_SCHEMA_VERSION: u32 = 1;
+
+/// `MediaEvent` event types this server sends
+pub const EVENT_TYPES: &[&str] = &[
+    "audio_frame",
+    "turn_started",
+    "turn_ended",
+    "partial_transcript",
+    "metrics",
+    "session_ended",
+    "barge_in",
+    "overlap",
+    "end_of_turn_anticipated",
+    "turn_segmented",
+    "detection_debug",
+    "command_ack",
+    "server_draining",
+    "session_migrated",
+    "latency_report",
+];
+
+/// Read the schema version a stream request expects, `None` when unset
+pub fn requested_schema_version(metadata: &MetadataMap) -> anyhow::Result<Option<u32>> {
+    let Some(value) = metadata.get(SCHEMA_VERSION_HEADER) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .map(Some)
+        .ok_or_else(|| anyhow::anyhow!("Invalid {} header", SCHEMA_VERSION_HEADER))
+}
+
+/// Check if a client's schema version can be served
+pub fn check_schema_version(version: u32) -> anyhow::Result<()> {
+    if !(MIN_SCHEMA_VERSION..=SCHEMA_VERSION).contains(&version) {
+        return Err(anyhow::anyhow!(
+            "Client expects schema version {}, server supports {} to {}",
+            version,
+            MIN_SCHEMA_VERSION,
+            SCHEMA_VERSION
+        ));
+    }
+    Ok(())
+}
+
+/// Negotiate the schema version of a stream request
+///
+/// An invalid header is `INVALID_ARGUMENT`, an unsupported version
+/// `FAILED_PRECONDITION`.
+pub fn negotiate(metadata: &MetadataMap) -> Option<Status> {
+    let version = match requested_schema_version(metadata) {
+        Ok(version) => version?,
+        Err(e) => return Some(Status::invalid_argument(e.to_string())),
+    };
+    check_schema_version(version)
+        .err()
+        .map(|e| Status::failed_precondition(e.to_string()))
+}
+
+/// Describe what this server supports
+pub fn server_capabilities(config: &Config) -> proto::Capabilities {
+    proto::Capabilities {
+        schema_version: SCHEMA_VERSION,
+        min_schema_version: MIN_SCHEMA_VERSION,
+        codecs: vec![
+            proto::audio_chunk::Encoding::Pcm16 as i32,
+            proto::audio_chunk::Encoding::Opus as i32,
+        ],
+        event_types: EVENT_TYPES.iter().map(|name| name.to_string()).collect(),
+        detectors: TurnDetectorRegistry::default()
+            .names()
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        compression: config.grpc.compression.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(version: &str) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(SCHEMA_VERSION_HEADER, version.parse().unwrap());
+        metadata
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert!(negotiate(&MetadataMap::new()).is_none());
+        assert!(negotiate(&metadata(&SCHEMA_VERSION.to_string())).is_none());
+
+        let newer = negotiate(&metadata(&(SCHEMA_VERSION + 1).to_string())).unwrap();
+        assert_eq!(newer.code(), tonic::Code::FailedPrecondition);
+        let older = negotiate(&metadata(&(MIN_SCHEMA_VERSION - 1).to_string())).unwrap();
+        assert_eq!(older.code(), tonic::Code::FailedPrecondition);
+        let invalid = negotiate(&metadata("v1")).unwrap();
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_server_capabilities() {
+        let mut config = Config::default();
+        config.grpc.compression = vec!["gzip".to_string()];
+        let capabilities = server_capabilities(&config);
+        assert_eq!(capabilities.schema_version, SCHEMA_VERSION);
+        assert_eq!(capabilities.codecs.len(), 2);
+        assert!(capabilities.event_types.contains(&"turn_ended".to_string()));
+        assert_eq!(capabilities.detectors, vec!["state_machine"]);
+        assert_eq!(capabilities.compression, vec!["gzip"]);
+    }
+}
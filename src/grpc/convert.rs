@@ -0,0 +1,852 @@
+//! Conversions between service types and generated protobuf messages
+
+use crate::detection::{
+    ExternalSignal, ExternalSignalKind, FusionBreakdown, FusionWeights, TurnConfigUpdate,
+    TurnSegment, TurnState,
+};
+use crate::grpc::audio_stream::{AudioChunk, AudioEncoding};
+use crate::grpc::service::{
+    CommandStatus, EndReason, MediaEvent, OrchestrationCommand, SessionHistory, SessionOptions,
+    SessionStatus,
+};
+use crate::grpc::signaling::{Signal, SignalMessage};
+use crate::proto;
+use crate::proto::journal_entry::Entry;
+use crate::proto::media_event::Event;
+use crate::proto::orchestration_command::Command;
+use crate::session::{
+    JournalEntry, JournalEvent, LoadReport, OrphanedSession, SessionOwner, SessionState,
+};
+use crate::transport::RtpIngestOptions;
+use crate::webrtc::ConnectionState;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Wire name of a turn state
+pub fn state_name(state: TurnState) -> &'static str {
+    match state {
+        TurnState::Idle => "idle",
+        TurnState::Speaking => "speaking",
+        TurnState::SilenceGap => "silence_gap",
+    }
+}
+
+/// Build a detection debug message
+pub fn detection_debug(
+    timestamp_ms: i64,
+    state: Option<TurnState>,
+    breakdown: &FusionBreakdown,
+) -> proto::DetectionDebug {
+    proto::DetectionDebug {
+        timestamp_ms,
+        state: state.map(state_name).unwrap_or_default().to_string(),
+        vad_contribution: breakdown.vad,
+        volume_contribution: breakdown.volume,
+        pitch_contribution: breakdown.pitch,
+        context_contribution: breakdown.context,
+        score: breakdown.score,
+        external_contribution: breakdown.external,
+    }
+}
+
+/// Wire name of a session state
+pub fn session_state_name(state: SessionState) -> &'static str {
+    match state {
+        SessionState::Active => "active",
+        SessionState::Paused => "paused",
+        SessionState::Terminating => "terminating",
+        SessionState::Ended => "ended",
+    }
+}
+
+/// Convert the owner of a session to its wire form
+pub fn session_owner(session_id: &str, owner: SessionOwner) -> proto::SessionOwner {
+    proto::SessionOwner {
+        session_id: session_id.to_string(),
+        instance_id: owner.instance_id,
+        address: owner.address.unwrap_or_default(),
+        lease_expires_ms: owner.lease_expires_ms,
+    }
+}
+
+fn turn_config_update(update: proto::UpdateTurnConfig) -> TurnConfigUpdate {
+    TurnConfigUpdate {
+        vad_threshold_enter: update.vad_threshold_enter,
+        vad_threshold_exit: update.vad_threshold_exit,
+        min_speech_duration_ms: update.min_speech_duration_ms,
+        max_silence_duration_ms: update.max_silence_duration_ms,
+        volume_threshold_db: update.volume_threshold_db,
+        fusion_weights: update.fusion_weights.map(|w| FusionWeights {
+            vad: w.vad,
+            volume: w.volume,
+            pitch: w.pitch,
+            context: w.context,
+        }),
+    }
+}
+
+fn audio_encoding(encoding: i32) -> anyhow::Result<AudioEncoding> {
+    match proto::audio_chunk::Encoding::try_from(encoding) {
+        Ok(proto::audio_chunk::Encoding::Pcm16) => Ok(AudioEncoding::Pcm16),
+        Ok(proto::audio_chunk::Encoding::Opus) => Ok(AudioEncoding::Opus),
+        Ok(proto::audio_chunk::Encoding::Pcmu) => Ok(AudioEncoding::Pcmu),
+        Err(_) => Err(anyhow::anyhow!("Unknown audio encoding: {}", encoding)),
+    }
+}
+
+fn proto_encoding(encoding: AudioEncoding) -> proto::audio_chunk::Encoding {
+    match encoding {
+        AudioEncoding::Pcm16 => proto::audio_chunk::Encoding::Pcm16,
+        AudioEncoding::Opus => proto::audio_chunk::Encoding::Opus,
+        AudioEncoding::Pcmu => proto::audio_chunk::Encoding::Pcmu,
+    }
+}
+
+fn turn_ended(timestamp_ms: i64, duration_ms: u32, segment: &TurnSegment) -> proto::TurnEnded {
+    proto::TurnEnded {
+        transcript_preview: String::new(),
+        timestamp_ms,
+        duration_ms,
+        start_timestamp_ms: segment.start_ms,
+        end_timestamp_ms: segment.end_ms,
+        start_wall_clock_ms: segment.start_wall_clock_ms,
+        end_wall_clock_ms: segment.end_wall_clock_ms,
+        start_frame: segment.start_frame,
+        end_frame: segment.end_frame,
+        average_vad: segment.average_vad,
+    }
+}
+
+impl From<MediaEvent> for proto::MediaEvent {
+    fn from(event: MediaEvent) -> Self {
+        let (session_id, timestamp_ms, event) = match event {
+            MediaEvent::AudioFrame {
+                session_id,
+                timestamp_ms,
+                pcm_data,
+                sample_rate,
+                channels,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::AudioFrame(proto::AudioFrame {
+                    pcm_data,
+                    sample_rate,
+                    channels,
+                    frame_timestamp_ms: timestamp_ms,
+                }),
+            ),
+            MediaEvent::TurnStarted {
+                session_id,
+                timestamp_ms,
+                vad_probability,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::TurnStarted(proto::TurnStarted {
+                    vad_probability,
+                    volume_db: 0.0,
+                    timestamp_ms,
+                }),
+            ),
+            MediaEvent::TurnEnded {
+                session_id,
+                timestamp_ms,
+                duration_ms,
+                segment,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::TurnEnded(turn_ended(timestamp_ms, duration_ms, &segment)),
+            ),
+            MediaEvent::BargeIn {
+                session_id,
+                timestamp_ms,
+                vad_probability,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::BargeIn(proto::BargeIn {
+                    vad_probability,
+                    timestamp_ms,
+                }),
+            ),
+            MediaEvent::Overlap {
+                session_id,
+                timestamp_ms,
+                duration_ms,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::Overlap(proto::Overlap {
+                    duration_ms,
+                    timestamp_ms,
+                }),
+            ),
+            MediaEvent::EndOfTurnAnticipated {
+                session_id,
+                timestamp_ms,
+                confidence,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::EndOfTurnAnticipated(proto::EndOfTurnAnticipated {
+                    confidence,
+                    timestamp_ms,
+                }),
+            ),
+            MediaEvent::EchoLoopDetected {
+                session_id,
+                timestamp_ms,
+                similarity,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::EchoLoopDetected(proto::EchoLoopDetected {
+                    similarity,
+                    timestamp_ms,
+                }),
+            ),
+            MediaEvent::ConnectionStateChanged {
+                session_id,
+                timestamp_ms,
+                state,
+                previous,
+                previous_duration_ms,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::ConnectionStateChanged(proto::ConnectionStateChanged {
+                    state: proto::connection_state_changed::State::from(state).into(),
+                    previous: proto::connection_state_changed::State::from(previous).into(),
+                    previous_duration_ms,
+                    timestamp_ms,
+                }),
+            ),
+            MediaEvent::TurnSegmented {
+                session_id,
+                timestamp_ms,
+                segment,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::TurnSegmented(proto::TurnSegmented {
+                    timestamp_ms,
+                    duration_ms: segment.duration_ms,
+                    start_timestamp_ms: segment.start_ms,
+                    end_timestamp_ms: segment.end_ms,
+                    start_frame: segment.start_frame,
+                    end_frame: segment.end_frame,
+                    average_vad: segment.average_vad,
+                    start_wall_clock_ms: segment.start_wall_clock_ms,
+                    end_wall_clock_ms: segment.end_wall_clock_ms,
+                }),
+            ),
+            MediaEvent::DetectionDebug {
+                session_id,
+                timestamp_ms,
+                state,
+                breakdown,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::DetectionDebug(detection_debug(timestamp_ms, Some(state), &breakdown)),
+            ),
+            MediaEvent::PartialTranscript {
+                session_id,
+                timestamp_ms,
+                text,
+                confidence,
+                is_final,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::PartialTranscript(proto::PartialTranscript {
+                    text,
+                    confidence,
+                    timestamp_ms,
+                    is_final,
+                }),
+            ),
+            MediaEvent::SessionEnded {
+                session_id,
+                duration_ms,
+                total_frames,
+                reason,
+            } => (
+                session_id.clone(),
+                0,
+                Event::SessionEnded(proto::SessionEnded {
+                    session_id,
+                    duration_ms,
+                    total_frames,
+                    reason: proto::session_ended::Reason::from(reason).into(),
+                }),
+            ),
+            MediaEvent::CommandAck {
+                session_id,
+                timestamp_ms,
+                command_id,
+                status,
+                reason,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::CommandAck(proto::CommandAck {
+                    command_id,
+                    status: proto::command_ack::Status::from(status) as i32,
+                    reason,
+                }),
+            ),
+            MediaEvent::ServerDraining {
+                session_id,
+                timestamp_ms,
+                deadline_ms,
+                handoff,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::ServerDraining(proto::ServerDraining {
+                    deadline_ms,
+                    handoff: handoff.map(Into::into),
+                }),
+            ),
+            MediaEvent::SessionMigrated {
+                session_id,
+                timestamp_ms,
+                frames_processed,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::SessionMigrated(proto::SessionMigrated { frames_processed }),
+            ),
+            MediaEvent::LatencyReport {
+                session_id,
+                timestamp_ms,
+                frames,
+                latency,
+            } => (
+                session_id,
+                timestamp_ms,
+                Event::LatencyReport(proto::LatencyReport {
+                    frames,
+                    network_ms: latency.network_ms as f32,
+                    decode_ms: latency.decode_ms as f32,
+                    dsp_ms: latency.dsp_ms as f32,
+                    detection_ms: latency.detection_ms as f32,
+                    queueing_ms: latency.queueing_ms as f32,
+                    total_ms: latency.total_ms() as f32,
+                }),
+            ),
+        };
+
+        proto::MediaEvent {
+            session_id,
+            timestamp_ms,
+            event: Some(event),
+        }
+    }
+}
+
+impl From<CommandStatus> for proto::command_ack::Status {
+    fn from(status: CommandStatus) -> Self {
+        match status {
+            CommandStatus::Accepted => proto::command_ack::Status::Accepted,
+            CommandStatus::Rejected => proto::command_ack::Status::Rejected,
+            CommandStatus::Completed => proto::command_ack::Status::Completed,
+        }
+    }
+}
+
+impl From<OrphanedSession> for proto::OrphanedSession {
+    fn from(orphan: OrphanedSession) -> Self {
+        Self {
+            session_id: orphan.session_id,
+            source_instance_id: orphan.source_instance_id,
+            user_id: orphan.user_id.unwrap_or_default(),
+            orphaned_at_ms: orphan.orphaned_at_ms,
+            resume_by_ms: orphan.resume_by_ms,
+            frames_processed: orphan.frames_processed,
+            playback_sequence: orphan.playback_sequence,
+        }
+    }
+}
+
+impl From<LoadReport> for proto::LoadReport {
+    fn from(report: LoadReport) -> Self {
+        Self {
+            instance_id: report.instance_id,
+            address: report.address.unwrap_or_default(),
+            active_sessions: report.active_sessions as u32,
+            max_sessions: report.max_sessions as u32,
+            cpu_headroom: report.cpu_headroom,
+            audio_thread_utilization: report.audio_thread_utilization,
+            draining: report.draining,
+            reported_at_ms: report.reported_at_ms,
+        }
+    }
+}
+
+impl From<EndReason> for proto::session_ended::Reason {
+    fn from(reason: EndReason) -> Self {
+        match reason {
+            EndReason::Completed => proto::session_ended::Reason::Completed,
+            EndReason::Idle => proto::session_ended::Reason::Idle,
+            EndReason::NoMedia => proto::session_ended::Reason::NoMedia,
+            EndReason::ResourceLimit => proto::session_ended::Reason::ResourceLimit,
+            EndReason::LeaseLost => proto::session_ended::Reason::LeaseLost,
+            EndReason::Drained => proto::session_ended::Reason::Drained,
+            EndReason::Forced => proto::session_ended::Reason::Forced,
+            EndReason::InternalError => proto::session_ended::Reason::InternalError,
+            EndReason::ConnectionLost => proto::session_ended::Reason::ConnectionLost,
+        }
+    }
+}
+
+impl From<ConnectionState> for proto::connection_state_changed::State {
+    fn from(state: ConnectionState) -> Self {
+        match state {
+            ConnectionState::New => proto::connection_state_changed::State::New,
+            ConnectionState::Connecting => proto::connection_state_changed::State::Connecting,
+            ConnectionState::Connected => proto::connection_state_changed::State::Connected,
+            ConnectionState::Disconnected => proto::connection_state_changed::State::Disconnected,
+            ConnectionState::Failed => proto::connection_state_changed::State::Failed,
+            ConnectionState::Closed => proto::connection_state_changed::State::Closed,
+        }
+    }
+}
+
+impl From<proto::command_ack::Status> for CommandStatus {
+    fn from(status: proto::command_ack::Status) -> Self {
+        match status {
+            proto::command_ack::Status::Accepted => CommandStatus::Accepted,
+            proto::command_ack::Status::Rejected => CommandStatus::Rejected,
+            proto::command_ack::Status::Completed => CommandStatus::Completed,
+        }
+    }
+}
+
+impl TryFrom<proto::OrchestrationCommand> for OrchestrationCommand {
+    type Error = anyhow::Error;
+
+    fn try_from(message: proto::OrchestrationCommand) -> anyhow::Result<Self> {
+        let session_id = message.session_id;
+        let command = message
+            .command
+            .ok_or_else(|| anyhow::anyhow!("Orchestration command is empty"))?;
+
+        Ok(match command {
+            Command::PlayAudio(play) => OrchestrationCommand::PlayAudio {
+                session_id,
+                audio_data: play.audio_data,
+                audio_format: play.audio_format,
+            },
+            Command::StopAudio(stop) => OrchestrationCommand::StopAudio {
+                session_id,
+                reason: stop.reason,
+            },
+            Command::ClearContext(clear) => OrchestrationCommand::ClearContext {
+                session_id,
+                context_type: clear.context_type,
+            },
+            Command::AdjustVad(adjust) => OrchestrationCommand::AdjustVAD {
+                session_id,
+                sensitivity: adjust.sensitivity,
+                threshold_ms: adjust.threshold_ms,
+            },
+            Command::TranscriptUpdate(update) => OrchestrationCommand::UpdateTranscript {
+                session_id,
+                text: update.text,
+                is_final: update.is_final,
+            },
+            Command::UpdateTurnConfig(update) => OrchestrationCommand::UpdateTurnConfig {
+                session_id,
+                update: turn_config_update(update),
+            },
+            Command::InjectSignal(inject) => {
+                let kind = match proto::inject_signal::Kind::try_from(inject.kind) {
+                    Ok(proto::inject_signal::Kind::AsrWord) => ExternalSignalKind::AsrWord,
+                    Ok(proto::inject_signal::Kind::ClientMute) => ExternalSignalKind::ClientMute,
+                    Ok(proto::inject_signal::Kind::PushToTalk) => ExternalSignalKind::PushToTalk,
+                    Err(_) => return Err(anyhow::anyhow!("Unknown signal kind: {}", inject.kind)),
+                };
+                let mut signal = ExternalSignal::new(kind, inject.value);
+                if let Some(timeout_ms) = inject.timeout_ms {
+                    signal = signal.with_timeout(timeout_ms);
+                }
+                OrchestrationCommand::InjectSignal { session_id, signal }
+            }
+            Command::Pause(pause) => OrchestrationCommand::Pause {
+                session_id,
+                mute_audio: pause.mute_audio,
+            },
+            Command::Resume(_) => OrchestrationCommand::Resume { session_id },
+            Command::SetTags(set) => OrchestrationCommand::SetTags {
+                session_id,
+                tags: set.tags,
+                remove: set.remove,
+            },
+        })
+    }
+}
+
+impl TryFrom<proto::AudioChunk> for AudioChunk {
+    type Error = anyhow::Error;
+
+    fn try_from(message: proto::AudioChunk) -> anyhow::Result<Self> {
+        let encoding = audio_encoding(message.encoding)?;
+
+        Ok(AudioChunk {
+            session_id: message.session_id,
+            sequence_number: message.sequence_number,
+            timestamp_ms: message.timestamp_ms,
+            encoding,
+            sample_rate: message.sample_rate,
+            channels: message.channels,
+            data: message.data,
+        })
+    }
+}
+
+impl From<AudioChunk> for proto::AudioChunk {
+    fn from(chunk: AudioChunk) -> Self {
+        proto::AudioChunk {
+            session_id: chunk.session_id,
+            sequence_number: chunk.sequence_number,
+            timestamp_ms: chunk.timestamp_ms,
+            encoding: proto_encoding(chunk.encoding) as i32,
+            sample_rate: chunk.sample_rate,
+            channels: chunk.channels,
+            data: chunk.data,
+        }
+    }
+}
+
+impl TryFrom<proto::CreateSessionRequest> for SessionOptions {
+    type Error = anyhow::Error;
+
+    fn try_from(message: proto::CreateSessionRequest) -> anyhow::Result<Self> {
+        let non_empty = |value: String| (!value.is_empty()).then_some(value);
+        let mut metadata: HashMap<String, Value> = message
+            .metadata
+            .into_iter()
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect();
+        for (key, json) in message.metadata_json {
+            let value = serde_json::from_str(&json)
+                .map_err(|e| anyhow::anyhow!("Invalid JSON for metadata {}: {}", key, e))?;
+            metadata.insert(key, value);
+        }
+
+        Ok(SessionOptions {
+            session_id: non_empty(message.session_id),
+            user_id: non_empty(message.user_id),
+            codec: Some(audio_encoding(message.codec)?),
+            sample_rate: (message.sample_rate != 0).then_some(message.sample_rate),
+            detector: non_empty(message.detector),
+            turn_config: message.turn_config.map(turn_config_update),
+            webrtc: message.webrtc,
+            rtp: message
+                .rtp
+                .map(|rtp| -> anyhow::Result<RtpIngestOptions> {
+                    Ok(RtpIngestOptions {
+                        codec: rtp.codec,
+                        payload_type: match rtp.payload_type {
+                            0 => None,
+                            payload_type => Some(u8::try_from(payload_type).map_err(|_| {
+                                anyhow::anyhow!("Invalid payload type {}", payload_type)
+                            })?),
+                        },
+                        ssrc: (rtp.ssrc != 0).then_some(rtp.ssrc),
+                    })
+                })
+                .transpose()?,
+            metadata,
+            tags: message.tags,
+            tenant: None,
+        })
+    }
+}
+
+impl From<SessionStatus> for proto::SessionInfo {
+    fn from(status: SessionStatus) -> Self {
+        proto::SessionInfo {
+            session_id: status.session_id,
+            user_id: status.user_id.unwrap_or_default(),
+            state: session_state_name(status.state).to_string(),
+            created_at_ms: status.created_at_ms,
+            last_activity_ms: status.last_activity_ms,
+            codec: proto_encoding(status.codec) as i32,
+            sample_rate: status.sample_rate,
+            detector: status.detector,
+            turn_state: state_name(status.turn_state).to_string(),
+            frames_processed: status.frames_processed,
+            webrtc: status.webrtc_connected.is_some(),
+            webrtc_connected: status.webrtc_connected.unwrap_or(false),
+            rtp_packets_processed: status.rtp_packets_processed,
+            metadata: status
+                .metadata
+                .iter()
+                .map(|(key, value)| match value {
+                    Value::String(value) => (key.clone(), value.clone()),
+                    value => (key.clone(), value.to_string()),
+                })
+                .collect(),
+            metadata_json: status
+                .metadata
+                .into_iter()
+                .map(|(key, value)| (key, value.to_string()))
+                .collect(),
+            tags: status.tags,
+            rtp: status.rtp.map(|rtp| proto::RtpEndpoint {
+                address: rtp.address.to_string(),
+                codec: rtp.codec.to_string(),
+                payload_type: u32::from(rtp.payload_type),
+                ssrc: rtp.ssrc.unwrap_or_default(),
+            }),
+            tenant: status.tenant.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<JournalEntry> for proto::JournalEntry {
+    fn from(entry: JournalEntry) -> Self {
+        let timestamp_ms = entry.timestamp_ms;
+        let entry = match entry.event {
+            JournalEvent::TurnStarted { vad_probability } => {
+                Entry::TurnStarted(proto::TurnStarted {
+                    vad_probability,
+                    volume_db: 0.0,
+                    timestamp_ms,
+                })
+            }
+            JournalEvent::TurnEnded {
+                duration_ms,
+                segment,
+            } => Entry::TurnEnded(turn_ended(timestamp_ms, duration_ms, &segment)),
+            JournalEvent::BargeIn { vad_probability } => Entry::BargeIn(proto::BargeIn {
+                vad_probability,
+                timestamp_ms,
+            }),
+            JournalEvent::Overlap { duration_ms } => Entry::Overlap(proto::Overlap {
+                duration_ms,
+                timestamp_ms,
+            }),
+            JournalEvent::Command {
+                command,
+                status,
+                reason,
+            } => Entry::Command(proto::JournalCommand {
+                command,
+                status: proto::command_ack::Status::from(status) as i32,
+                reason: reason.unwrap_or_default(),
+            }),
+            JournalEvent::Quality {
+                frames_processed,
+                rtp_packets_processed,
+                packet_loss_ratio,
+                jitter_buffer_level_percent,
+            } => Entry::Quality(proto::JournalQuality {
+                frames_processed,
+                rtp_packets_processed,
+                packet_loss_ratio,
+                jitter_buffer_level_percent,
+            }),
+        };
+        proto::JournalEntry {
+            timestamp_ms,
+            entry: Some(entry),
+        }
+    }
+}
+
+impl From<SessionHistory> for proto::SessionHistory {
+    fn from(history: SessionHistory) -> Self {
+        proto::SessionHistory {
+            session_id: history.session_id,
+            live: history.live,
+            entries: history
+                .journal
+                .entries()
+                .iter()
+                .cloned()
+                .map(Into::into)
+                .collect(),
+            dropped_entries: history.journal.dropped(),
+        }
+    }
+}
+
+impl TryFrom<proto::SignalMessage> for SignalMessage {
+    type Error = anyhow::Error;
+
+    fn try_from(message: proto::SignalMessage) -> anyhow::Result<Self> {
+        let signal = match message.signal {
+            Some(proto::signal_message::Signal::OfferSdp(sdp)) => Signal::Offer(sdp),
+            Some(proto::signal_message::Signal::AnswerSdp(sdp)) => Signal::Answer(sdp),
+            Some(proto::signal_message::Signal::Candidate(init)) if init.candidate.is_empty() => {
+                Signal::EndOfCandidates
+            }
+            Some(proto::signal_message::Signal::Candidate(init)) => {
+                Signal::Candidate(init.candidate.parse()?)
+            }
+            None => return Err(anyhow::anyhow!("Signal message is empty")),
+        };
+
+        Ok(SignalMessage {
+            session_id: message.session_id,
+            signal,
+        })
+    }
+}
+
+impl From<SignalMessage> for proto::SignalMessage {
+    fn from(message: SignalMessage) -> Self {
+        let candidate = |candidate: String| {
+            proto::signal_message::Signal::Candidate(proto::IceCandidateInit {
+                candidate,
+                sdp_mid: "0".to_string(),
+                sdp_m_line_index: 0,
+            })
+        };
+        let signal = match message.signal {
+            Signal::Offer(sdp) => proto::signal_message::Signal::OfferSdp(sdp),
+            Signal::Answer(sdp) => proto::signal_message::Signal::AnswerSdp(sdp),
+            Signal::Candidate(ice) => candidate(ice.to_sdp()),
+            Signal::EndOfCandidates => candidate(String::new()),
+        };
+
+        proto::SignalMessage {
+            session_id: message.session_id,
+            signal: Some(signal),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turn_ended_to_proto() {
+        let event = MediaEvent::TurnEnded {
+            session_id: "s1".to_string(),
+            timestamp_ms: 2000,
+            duration_ms: 1000,
+            segment: TurnSegment {
+                start_ms: 1000,
+                end_ms: 2000,
+                start_frame: 50,
+                end_frame: 100,
+                ..Default::default()
+            },
+        };
+
+        let message = proto::MediaEvent::from(event);
+        assert_eq!(message.session_id, "s1");
+        match message.event {
+            Some(Event::TurnEnded(ended)) => {
+                assert_eq!(ended.duration_ms, 1000);
+                assert_eq!(ended.start_timestamp_ms, 1000);
+                assert_eq!(ended.end_frame, 100);
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_inject_signal_from_proto() {
+        let message = proto::OrchestrationCommand {
+            session_id: "s1".to_string(),
+            timestamp_ms: 0,
+            command_id: String::new(),
+            command: Some(Command::InjectSignal(proto::InjectSignal {
+                kind: proto::inject_signal::Kind::PushToTalk as i32,
+                value: 1.0,
+                timeout_ms: Some(250),
+            })),
+        };
+
+        match OrchestrationCommand::try_from(message).unwrap() {
+            OrchestrationCommand::InjectSignal { session_id, signal } => {
+                assert_eq!(session_id, "s1");
+                assert_eq!(signal.kind, ExternalSignalKind::PushToTalk);
+                assert_eq!(signal.timeout_ms, 250);
+            }
+            other => panic!("Unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_create_session_request_defaults() {
+        let options = SessionOptions::try_from(proto::CreateSessionRequest {
+            detector: "state_machine".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(options.session_id.is_none());
+        assert!(options.sample_rate.is_none());
+        assert_eq!(options.codec, Some(AudioEncoding::Pcm16));
+        assert_eq!(options.detector.as_deref(), Some("state_machine"));
+
+        let bad_codec = proto::CreateSessionRequest {
+            codec: 7,
+            ..Default::default()
+        };
+        assert!(SessionOptions::try_from(bad_codec).is_err());
+    }
+
+    #[test]
+    fn test_typed_metadata() {
+        let options = SessionOptions::try_from(proto::CreateSessionRequest {
+            metadata: [("lang".to_string(), "ar".to_string())].into(),
+            metadata_json: [("tier".to_string(), "2".to_string())].into(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(options.metadata["lang"], "ar");
+        assert_eq!(options.metadata["tier"], 2);
+
+        let bad_json = proto::CreateSessionRequest {
+            metadata_json: [("tier".to_string(), "two".to_string())].into(),
+            ..Default::default()
+        };
+        assert!(SessionOptions::try_from(bad_json).is_err());
+    }
+
+    #[test]
+    fn test_signal_candidates_from_proto() {
+        let message = |candidate: &str| proto::SignalMessage {
+            session_id: "s1".to_string(),
+            signal: Some(proto::signal_message::Signal::Candidate(
+                proto::IceCandidateInit {
+                    candidate: candidate.to_string(),
+                    ..Default::default()
+                },
+            )),
+        };
+
+        let parsed = SignalMessage::try_from(message(
+            "candidate:1 1 udp 2130706431 10.0.0.1 5000 typ host",
+        ))
+        .unwrap();
+        assert!(matches!(parsed.signal, Signal::Candidate(_)));
+        assert!(matches!(
+            SignalMessage::try_from(message("")).unwrap().signal,
+            Signal::EndOfCandidates
+        ));
+        assert!(SignalMessage::try_from(message("garbage")).is_err());
+    }
+
+    #[test]
+    fn test_empty_command_rejected() {
+        let message = proto::OrchestrationCommand {
+            session_id: "s1".to_string(),
+            timestamp_ms: 0,
+            command_id: String::new(),
+            command: None,
+        };
+        assert!(OrchestrationCommand::try_from(message).is_err());
+    }
+}
@@ -1,4 +1,13 @@
 //! gRPC module for Amwaj Media Server
 
+pub mod admin;
+pub mod audio_stream;
+pub mod auth;
+pub mod capabilities;
+pub mod convert;
+pub mod rest;
 pub mod server;
 pub mod service;
+pub mod signaling;
+pub mod subscription;
+pub mod tls;
@@ -0,0 +1,529 @@
+//! REST facade of the admin API
+//!
+//! Mirrors the parts of `AmwajAdmin` that ops tools and dashboards need
+//! without a gRPC client: listing sessions, reading a session's stats,
+//! finding the heaviest sessions, ending a session and draining the
+//! instance. It also serves the stats of a session's WebRTC connection in
+//! the shape of `getStats()`, for dashboards built for browsers. The
+//! routes are served under `/admin` on the metrics port and accept the
+//! same `[grpc.admin]` credentials, an `x-api-key` header or a bearer
+//! token.
+//!
+//! The facade is created before the media service exists; until the gRPC
+//! server attaches it, every route answers 503.
+
+use crate::config::AdminConfig;
+use crate::grpc::auth::TokenAuthenticator;
+use crate::grpc::service::{AmwajMediaService, EndReason, SessionResources, SessionStats};
+use crate::metrics::prometheus::Readiness;
+use crate::metrics::Metrics;
+use crate::session::{matches_tags, UsageMetric};
+use crate::webrtc::RtcStatsReport;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::metadata::MetadataMap;
+
+/// Sessions listed by `/admin/top` unless the request asks otherwise
+const DEFAULT_TOP_SESSIONS: usize = 10;
+
+/// Query parameters prefixed with this filter sessions by tag
+const TAG_PREFIX: &str = "tag.";
+
+/// Page of sessions served on `/admin/sessions`
+#[derive(Debug, Serialize)]
+struct SessionPage {
+    sessions: Vec<SessionStats>,
+    /// Pass as `page_token` for the next page, empty on the last one
+    next_page_token: String,
+}
+
+/// Answer to a drain request
+#[derive(Debug, Serialize)]
+struct DrainStarted {
+    sessions: usize,
+    timeout_secs: u64,
+}
+
+/// Admin routes served over HTTP
+#[derive(Clone)]
+pub struct RestAdmin {
+    authenticator: TokenAuthenticator,
+    media: Arc<RwLock<Option<AmwajMediaService>>>,
+    readiness: Option<Readiness>,
+    drain_timeout: Duration,
+}
+
+impl RestAdmin {
+    /// Create the facade, accepting the admin API credentials
+    pub fn new(config: &AdminConfig, drain_timeout: Duration) -> Self {
+        Self {
+            authenticator: TokenAuthenticator::new(&config.auth),
+            media: Arc::new(RwLock::new(None)),
+            readiness: None,
+            drain_timeout,
+        }
+    }
+
+    /// Count authentications per principal
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.authenticator = self.authenticator.with_metrics(metrics);
+        self
+    }
+
+    /// Clear a readiness flag when a drain is triggered
+    pub fn with_readiness(mut self, readiness: Readiness) -> Self {
+        self.readiness = Some(readiness);
+        self
+    }
+
+    /// Serve the routes from a media service
+    pub fn attach(&self, media: AmwajMediaService) {
+        *self.media.write() = Some(media);
+    }
+
+    /// Periodically refresh the JWKS in the background, if JWTs are accepted
+    pub fn spawn_jwks_refresh(&self) -> Option<tokio::task::JoinHandle<()>> {
+        self.authenticator.spawn_jwks_refresh()
+    }
+
+    /// Get the routes of the facade
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/admin/sessions", get(list_sessions))
+            .route(
+                "/admin/sessions/:session_id",
+                get(get_session).delete(end_session),
+            )
+            .route(
+                "/admin/sessions/:session_id/webrtc-stats",
+                get(get_webrtc_stats),
+            )
+            .route("/admin/top", get(top_sessions))
+            .route("/admin/drain", post(drain))
+            .with_state(self.clone())
+    }
+
+    /// Authenticate the request and get the media service
+    fn media(&self, headers: &HeaderMap) -> Result<AmwajMediaService, ApiError> {
+        let metadata = MetadataMap::from_headers(headers.clone());
+        self.authenticator
+            .authenticate_counted(&metadata)
+            .map_err(|e| ApiError(StatusCode::UNAUTHORIZED, e.to_string()))?;
+        self.media.read().clone().ok_or_else(|| {
+            ApiError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Media service not started".to_string(),
+            )
+        })
+    }
+}
+
+/// Failed request, answered as `{"error": ...}`
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// Parse an optional numeric query parameter
+fn parse_param<T: FromStr>(
+    query: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<T>, ApiError>
+where
+    T::Err: Display,
+{
+    query
+        .get(name)
+        .map(|value| value.parse())
+        .transpose()
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("Invalid {}: {}", name, e)))
+}
+
+async fn list_sessions(
+    State(admin): State<RestAdmin>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Json<SessionPage>, ApiError> {
+    let media = admin.media(&headers)?;
+    let tags: HashMap<String, String> = query
+        .iter()
+        .filter_map(|(key, value)| Some((key.strip_prefix(TAG_PREFIX)?.to_string(), value.clone())))
+        .collect();
+    let page_token = query.get("page_token").cloned().unwrap_or_default();
+    let page_size = parse_param(&query, "page_size")?.unwrap_or(0);
+
+    // Stats come ordered by session ID, pages resume after the last one
+    let mut sessions: Vec<SessionStats> = media
+        .session_stats()
+        .into_iter()
+        .filter(|stats| matches_tags(&stats.tags, &tags))
+        .filter(|stats| stats.session_id > page_token)
+        .collect();
+    let mut next_page_token = String::new();
+    if page_size > 0 && sessions.len() > page_size {
+        sessions.truncate(page_size);
+        next_page_token = sessions[page_size - 1].session_id.clone();
+    }
+    Ok(Json(SessionPage {
+        sessions,
+        next_page_token,
+    }))
+}
+
+async fn get_session(
+    State(admin): State<RestAdmin>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<Json<SessionStats>, ApiError> {
+    admin
+        .media(&headers)?
+        .session_stats()
+        .into_iter()
+        .find(|stats| stats.session_id == session_id)
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError(
+                StatusCode::NOT_FOUND,
+                format!("Unknown session: {}", session_id),
+            )
+        })
+}
+
+async fn get_webrtc_stats(
+    State(admin): State<RestAdmin>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<Json<RtcStatsReport>, ApiError> {
+    admin
+        .media(&headers)?
+        .webrtc_stats(&session_id)
+        .await
+        .map(Json)
+        .map_err(|e| ApiError(StatusCode::NOT_FOUND, e.to_string()))
+}
+
+async fn end_session(
+    State(admin): State<RestAdmin>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    admin
+        .media(&headers)?
+        .end_session_with_reason(&session_id, EndReason::Forced)
+        .await
+        .map_err(|e| ApiError(StatusCode::NOT_FOUND, e.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn top_sessions(
+    State(admin): State<RestAdmin>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<SessionResources>>, ApiError> {
+    let media = admin.media(&headers)?;
+    let metric = match query.get("metric").map(String::as_str) {
+        None | Some("cpu") => UsageMetric::Cpu,
+        Some("memory") => UsageMetric::Memory,
+        Some("bandwidth") => UsageMetric::Bandwidth,
+        Some(metric) => {
+            return Err(ApiError(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown usage metric: {}", metric),
+            ))
+        }
+    };
+    let limit = match parse_param(&query, "limit")? {
+        Some(0) | None => DEFAULT_TOP_SESSIONS,
+        Some(limit) => limit,
+    };
+    Ok(Json(media.heaviest_sessions(metric, limit)))
+}
+
+/// Start draining the instance, like a shutdown signal would
+///
+/// Answers right away; `timeout_secs` overrides `grpc.drain_timeout_secs`.
+async fn drain(
+    State(admin): State<RestAdmin>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<DrainStarted>), ApiError> {
+    let media = admin.media(&headers)?;
+    let timeout = parse_param(&query, "timeout_secs")?
+        .map(Duration::from_secs)
+        .unwrap_or(admin.drain_timeout);
+    if media.is_draining() {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            "Already draining".to_string(),
+        ));
+    }
+    if let Some(readiness) = &admin.readiness {
+        readiness.set_ready(false);
+    }
+    let sessions = media.session_count();
+    tracing::info!(
+        "Draining {} sessions for up to {:?} on admin request",
+        sessions,
+        timeout
+    );
+    tokio::spawn(async move {
+        let closed = media.drain(timeout).await;
+        if closed > 0 {
+            tracing::warn!("Force-closed {} sessions", closed);
+        }
+    });
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(DrainStarted {
+            sessions,
+            timeout_secs: timeout.as_secs(),
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ApiKeyConfig, AuthConfig, Config};
+    use crate::grpc::service::SessionOptions;
+
+    fn rest_admin() -> (AmwajMediaService, RestAdmin) {
+        let admin = AdminConfig {
+            auth: AuthConfig {
+                api_keys: vec![ApiKeyConfig {
+                    principal: "ops".to_string(),
+                    key: "admin-secret".into(),
+                    key_file: None,
+                }],
+                jwt: None,
+            },
+        };
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let media = AmwajMediaService::new(config, Arc::clone(&metrics));
+        let rest = RestAdmin::new(&admin, Duration::from_secs(1)).with_metrics(metrics);
+        (media, rest)
+    }
+
+    async fn serve(rest: &RestAdmin, port: u16) -> tokio::sync::oneshot::Sender<()> {
+        let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+        let server = axum::Server::bind(&addr).serve(rest.router().into_make_service());
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            let _ = shutdown_rx.await;
+        }));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown_tx
+    }
+
+    #[tokio::test]
+    async fn test_rest_sessions() {
+        let (media, rest) = rest_admin();
+        for (session_id, env) in [("s1", "prod"), ("s2", "prod"), ("s3", "dev")] {
+            media
+                .create_session(SessionOptions {
+                    session_id: Some(session_id.to_string()),
+                    tags: HashMap::from([("env".to_string(), env.to_string())]),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let shutdown = serve(&rest, 59092).await;
+        let client = reqwest::Client::new();
+        let url = |path: &str| format!("http://127.0.0.1:59092{}", path);
+
+        let unauthenticated = client.get(url("/admin/sessions")).send().await.unwrap();
+        assert_eq!(unauthenticated.status(), 401);
+        let not_attached = client
+            .get(url("/admin/sessions"))
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(not_attached.status(), 503);
+        rest.attach(media.clone());
+
+        let page: serde_json::Value = client
+            .get(url("/admin/sessions?tag.env=prod&page_size=1"))
+            .bearer_auth("admin-secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(page["sessions"].as_array().unwrap().len(), 1);
+        assert_eq!(page["sessions"][0]["session_id"], "s1");
+        assert_eq!(page["sessions"][0]["turn_state"], "idle");
+        assert_eq!(page["next_page_token"], "s1");
+        let page: serde_json::Value = client
+            .get(url(
+                "/admin/sessions?tag.env=prod&page_size=1&page_token=s1",
+            ))
+            .bearer_auth("admin-secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(page["sessions"][0]["session_id"], "s2");
+        assert_eq!(page["next_page_token"], "");
+
+        let stats: serde_json::Value = client
+            .get(url("/admin/sessions/s3"))
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(stats["tags"]["env"], "dev");
+        let top: serde_json::Value = client
+            .get(url("/admin/top?metric=memory&limit=2"))
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(top.as_array().unwrap().len(), 2);
+        let invalid = client
+            .get(url("/admin/top?metric=disk"))
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(invalid.status(), 400);
+
+        let ended = client
+            .delete(url("/admin/sessions/s3"))
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(ended.status(), 204);
+        assert_eq!(media.session_count(), 2);
+        let missing = client
+            .get(url("/admin/sessions/s3"))
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), 404);
+
+        let metrics = media.metrics();
+        assert_eq!(metrics.auth_failures.get(), 1.0);
+        shutdown.send(()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rest_drain() {
+        let (media, rest) = rest_admin();
+        let readiness = Readiness::new();
+        readiness.set_ready(true);
+        let rest = rest.with_readiness(readiness.clone());
+        rest.attach(media.clone());
+        media
+            .create_session(SessionOptions {
+                session_id: Some("s1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let shutdown = serve(&rest, 59093).await;
+        let client = reqwest::Client::new();
+        let url = "http://127.0.0.1:59093/admin/drain?timeout_secs=0";
+
+        let started: serde_json::Value = client
+            .post(url)
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(started["sessions"], 1);
+        assert!(media.is_draining());
+        assert!(!readiness.is_ready());
+        let again = client
+            .post(url)
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(again.status(), 409);
+
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while media.session_count() > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown.send(()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rest_webrtc_stats() {
+        let (media, rest) = rest_admin();
+        rest.attach(media.clone());
+        for (session_id, webrtc) in [("s1", true), ("s2", false)] {
+            media
+                .create_session(SessionOptions {
+                    session_id: Some(session_id.to_string()),
+                    webrtc,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let shutdown = serve(&rest, 59094).await;
+        let client = reqwest::Client::new();
+        let url = |session_id: &str| {
+            format!(
+                "http://127.0.0.1:59094/admin/sessions/{}/webrtc-stats",
+                session_id
+            )
+        };
+
+        let report: serde_json::Value = client
+            .get(url("s1"))
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(report["candidate-pair"]["type"], "candidate-pair");
+        assert_eq!(report["candidate-pair"]["state"], "frozen");
+        // A session without a WebRTC connection has no stats
+        let missing = client
+            .get(url("s2"))
+            .header("x-api-key", "admin-secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), 404);
+        shutdown.send(()).unwrap();
+    }
+}
@@ -1,96 +1,317 @@
 //! gRPC server implementation
 
-use crate::config::Config;
+use crate::config::{AuthConfig, Config, ConfigHandle};
+use crate::grpc::admin::AdminService;
+use crate::grpc::auth::TokenAuthenticator;
+use crate::grpc::rest::RestAdmin;
 use crate::grpc::service::AmwajMediaService;
+use crate::grpc::tls::{self, SanAuthorizer};
+use crate::metrics::prometheus::Readiness;
+use crate::metrics::runtime;
 use crate::metrics::Metrics;
+use crate::proto::amwaj_admin_server::AmwajAdminServer;
+use crate::proto::amwaj_media_server_server::AmwajMediaServerServer;
+use crate::session::DistributedSessionManager;
+use crate::sinks::EventSinks;
+use crate::transport::{Simulation, WebSocketServer};
+use crate::webrtc::pool;
+use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::time::Duration;
+use tonic::codec::CompressionEncoding;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Server;
+use tonic::{Request, Status};
+
+/// Certificate authorization followed by token authentication
+#[derive(Clone)]
+struct RequestGuard {
+    authorizer: SanAuthorizer,
+    authenticator: Option<TokenAuthenticator>,
+}
+
+impl Interceptor for RequestGuard {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let request = self.authorizer.call(request)?;
+        match self.authenticator.as_mut() {
+            Some(authenticator) => authenticator.call(request),
+            None => Ok(request),
+        }
+    }
+}
+
+/// Parse a configured stream compression
+fn compression_encoding(name: &str) -> anyhow::Result<CompressionEncoding> {
+    match name.to_ascii_lowercase().as_str() {
+        "gzip" => Ok(CompressionEncoding::Gzip),
+        "zstd" => Ok(CompressionEncoding::Zstd),
+        _ => Err(anyhow::anyhow!("Unsupported compression: {}", name)),
+    }
+}
 
 /// gRPC Server for Amwaj Media
 pub struct GrpcServer {
     config: Config,
     metrics: Arc<Metrics>,
+    readiness: Readiness,
+    live_config: Option<ConfigHandle>,
+    rest_admin: Option<RestAdmin>,
 }
 
 impl GrpcServer {
     /// Create a new gRPC server
     pub fn new(config: Config, metrics: Arc<Metrics>) -> Self {
-        Self { config, metrics }
+        Self {
+            config,
+            metrics,
+            readiness: Readiness::new(),
+            live_config: None,
+            rest_admin: None,
+        }
+    }
+
+    /// Report readiness through a shared flag
+    ///
+    /// The flag is set once the server is listening and cleared as soon as
+    /// it starts draining.
+    pub fn with_readiness(mut self, readiness: Readiness) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    /// Apply configuration reloads to new sessions
+    pub fn with_config_handle(mut self, live_config: ConfigHandle) -> Self {
+        self.live_config = Some(live_config);
+        self
+    }
+
+    /// Serve the admin REST API from this server's media service
+    pub fn with_rest_admin(mut self, rest_admin: RestAdmin) -> Self {
+        self.rest_admin = Some(rest_admin);
+        self
     }
 
     /// Get the service instance
     pub fn create_service(&self) -> AmwajMediaService {
-        AmwajMediaService::new(self.config.clone(), Arc::clone(&self.metrics))
+        let service = AmwajMediaService::new(self.config.clone(), Arc::clone(&self.metrics));
+        match &self.live_config {
+            Some(live_config) => service.with_config_handle(live_config.clone()),
+            None => service,
+        }
     }
 
     /// Start the gRPC server
     pub async fn start(self) -> anyhow::Result<()> {
-        let addr = format!("{}:{}", self.config.server.host, self.config.server.port);
-
-        tracing::info!("gRPC server starting on {}", addr);
-
-        // For now, we use a TCP listener as a stub
-        // Full tonic gRPC server will be added when proto compilation is integrated
-        let listener = TcpListener::bind(&addr).await?;
-
-        tracing::info!("Server listening on {}", addr);
-
-        // Create service for validation
-        let _service = self.create_service();
-
-        loop {
-            let (socket, peer_addr) = listener.accept().await?;
-            let metrics = Arc::clone(&self.metrics);
-
-            tokio::spawn(async move {
-                tracing::debug!("New connection from {}", peer_addr);
-                metrics.active_connections.inc();
-
-                // Handle connection (stub for now)
-                // Real gRPC handling would use tonic here
-                drop(socket);
-
-                metrics.active_connections.dec();
-            });
-        }
+        self.serve(std::future::pending()).await
     }
 
     /// Start the server with graceful shutdown
+    ///
+    /// On the signal the service drains: new sessions are refused and active
+    /// ones get `drain_timeout_secs` to end before they are force-closed.
     pub async fn start_with_shutdown(
         self,
-        mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
+        shutdown_rx: tokio::sync::oneshot::Receiver<()>,
     ) -> anyhow::Result<()> {
-        let addr = format!("{}:{}", self.config.server.host, self.config.server.port);
-
-        tracing::info!("gRPC server starting on {}", addr);
-
-        let listener = TcpListener::bind(&addr).await?;
-
-        tracing::info!("Server listening on {} (with graceful shutdown)", addr);
+        self.serve(async {
+            let _ = shutdown_rx.await;
+            tracing::info!("Shutdown signal received, stopping server");
+        })
+        .await
+    }
 
-        loop {
-            tokio::select! {
-                accept_result = listener.accept() => {
-                    let (socket, peer_addr) = accept_result?;
-                    let metrics = Arc::clone(&self.metrics);
+    async fn serve(self, shutdown: impl Future<Output = ()>) -> anyhow::Result<()> {
+        let addr: SocketAddr = self.address().parse()?;
+        if !pool::configure(self.config.webrtc.buffer_pool_capacity) {
+            tracing::warn!("Buffer pools already in use, keeping their capacity");
+        }
+        let sinks = EventSinks::from_config(&self.config.sinks, Arc::clone(&self.metrics)).await?;
+        let cdrs = match &self.config.cdr {
+            Some(cdr) => EventSinks::from_cdr_config(cdr, Arc::clone(&self.metrics)).await?,
+            None => EventSinks::default(),
+        };
+        let media_service = self
+            .create_service()
+            .with_session_manager(Arc::new(DistributedSessionManager::from_config(
+                &self.config.sessions,
+            )?))
+            .with_event_sinks(sinks.clone())
+            .with_cdr_sinks(cdrs.clone());
+        if let Some(rest_admin) = &self.rest_admin {
+            rest_admin.attach(media_service.clone());
+        }
+        let mut service = AmwajMediaServerServer::new(media_service.clone())
+            .max_decoding_message_size(self.config.grpc.max_message_size)
+            .max_encoding_message_size(self.config.grpc.max_message_size);
+        for name in &self.config.grpc.compression {
+            let encoding = compression_encoding(name)?;
+            service = service
+                .accept_compressed(encoding)
+                .send_compressed(encoding);
+        }
 
-                    tokio::spawn(async move {
-                        tracing::debug!("New connection from {}", peer_addr);
-                        metrics.active_connections.inc();
-                        drop(socket);
-                        metrics.active_connections.dec();
-                    });
+        let tls_config = self.config.grpc.tls.as_ref();
+        let mut jwks_refreshes = Vec::new();
+        let media_guard = self.request_guard(self.config.grpc.auth.as_ref(), &mut jwks_refreshes);
+        let service = InterceptedService::new(service, media_guard);
+
+        // The admin API never falls back to the media API credentials
+        let admin_service = self.config.grpc.admin.as_ref().map(|admin| {
+            let guard = self.request_guard(Some(&admin.auth), &mut jwks_refreshes);
+            InterceptedService::new(
+                AmwajAdminServer::new(AdminService::new(media_service.clone())),
+                guard,
+            )
+        });
+
+        let mut builder = Server::builder();
+        if let Some(tls_config) = tls_config {
+            builder = builder.tls_config(tls::server_tls_config(tls_config)?)?;
+        }
+        tracing::info!(
+            "gRPC server listening on {} ({})",
+            addr,
+            if tls_config.is_some() {
+                "TLS"
+            } else {
+                "plaintext"
+            }
+        );
+
+        // Deadlines are applied per RPC by the service, a server-wide timeout
+        // would also cut off long-running client streams
+        let grpc = &self.config.grpc;
+        let drain_timeout = Duration::from_secs(grpc.drain_timeout_secs);
+        let flush_timeout = Duration::from_secs(self.config.sinks.flush_timeout_secs);
+        let drained_service = media_service.clone();
+        let readiness = self.readiness.clone();
+        let shutdown = async move {
+            shutdown.await;
+            readiness.set_ready(false);
+            tracing::info!(
+                "Draining {} sessions for up to {:?}",
+                drained_service.session_count(),
+                drain_timeout
+            );
+            let closed = drained_service.drain(drain_timeout).await;
+            if closed > 0 {
+                tracing::warn!("Force-closed {} sessions", closed);
+            }
+            // The session ends just queued go out before the process exits
+            let undelivered = sinks.flush(flush_timeout).await + cdrs.flush(flush_timeout).await;
+            if undelivered > 0 {
+                tracing::warn!("Shutting down with {} sink events undelivered", undelivered);
+            }
+        };
+        let idle_reaper = grpc
+            .idle_stream_timeout_ms
+            .map(|idle_ms| media_service.spawn_idle_reaper(Duration::from_millis(idle_ms)));
+        let media_watchdog = self.config.sessions.no_media_timeout_ms.map(|timeout_ms| {
+            media_service.spawn_media_watchdog(Duration::from_millis(timeout_ms))
+        });
+        let reconnect_watchdog = media_service.spawn_reconnect_watchdog(Duration::from_millis(
+            self.config.webrtc.reconnect_window_ms,
+        ));
+        let websocket = self.config.transports.websocket.as_ref().map(|websocket| {
+            let server = WebSocketServer::new(media_service.clone(), websocket);
+            let addr = format!("{}:{}", self.config.server.host, websocket.port);
+            tokio::spawn(async move {
+                let served = match addr.parse() {
+                    Ok(addr) => server.serve(addr, std::future::pending()).await,
+                    Err(e) => Err(anyhow::anyhow!("Invalid WebSocket address {}: {}", addr, e)),
+                };
+                if let Err(e) = served {
+                    tracing::error!("WebSocket media endpoint error: {}", e);
                 }
-                _ = &mut shutdown_rx => {
-                    tracing::info!("Shutdown signal received, stopping server");
-                    break;
+            })
+        });
+        #[cfg(feature = "sip-feature")]
+        let sip = self.config.transports.sip.as_ref().map(|sip| {
+            let gateway = crate::transport::SipGateway::new(media_service.clone(), sip);
+            let addr = format!("{}:{}", self.config.server.host, sip.port);
+            tokio::spawn(async move {
+                let served = match addr.parse() {
+                    Ok(addr) => gateway.serve(addr, std::future::pending()).await,
+                    Err(e) => Err(anyhow::anyhow!("Invalid SIP address {}: {}", addr, e)),
+                };
+                if let Err(e) = served {
+                    tracing::error!("SIP gateway error: {}", e);
                 }
-            }
+            })
+        });
+        let simulation = match &self.config.simulation {
+            Some(simulation) => Some(Simulation::new(media_service.clone(), simulation)?),
+            None => None,
+        };
+        let session_cleanup = media_service.spawn_session_cleanup();
+        let lease_heartbeat = media_service.spawn_lease_heartbeat();
+        let load_reporter = media_service.spawn_load_reporter();
+        let runtime_sampler = runtime::spawn_runtime_sampler(
+            Arc::clone(&self.metrics),
+            Duration::from_secs(self.config.metrics.runtime_sample_interval_seconds.max(1)),
+        );
+        self.readiness.set_ready(true);
+        let simulation = simulation.map(Simulation::spawn);
+        let served = builder
+            .http2_keepalive_interval(grpc.keepalive_interval_ms.map(Duration::from_millis))
+            .http2_keepalive_timeout(grpc.keepalive_timeout_ms.map(Duration::from_millis))
+            .add_service(service)
+            .add_optional_service(admin_service)
+            .serve_with_shutdown(addr, shutdown)
+            .await;
+        self.readiness.set_ready(false);
+        served?;
+
+        for jwks_refresh in jwks_refreshes {
+            jwks_refresh.abort();
         }
-
+        if let Some(idle_reaper) = idle_reaper {
+            idle_reaper.abort();
+        }
+        if let Some(media_watchdog) = media_watchdog {
+            media_watchdog.abort();
+        }
+        reconnect_watchdog.abort();
+        if let Some(session_cleanup) = session_cleanup {
+            session_cleanup.abort();
+        }
+        if let Some(websocket) = websocket {
+            websocket.abort();
+        }
+        if let Some(simulation) = simulation {
+            simulation.abort();
+        }
+        #[cfg(feature = "sip-feature")]
+        if let Some(sip) = sip {
+            sip.abort();
+        }
+        lease_heartbeat.abort();
+        load_reporter.abort();
+        runtime_sampler.abort();
         Ok(())
     }
 
+    /// Build the interceptor for a service authenticated with `auth`
+    fn request_guard(
+        &self,
+        auth: Option<&AuthConfig>,
+        jwks_refreshes: &mut Vec<tokio::task::JoinHandle<()>>,
+    ) -> RequestGuard {
+        let authenticator =
+            auth.map(|auth| TokenAuthenticator::new(auth).with_metrics(Arc::clone(&self.metrics)));
+        jwks_refreshes.extend(
+            authenticator
+                .as_ref()
+                .and_then(TokenAuthenticator::spawn_jwks_refresh),
+        );
+        RequestGuard {
+            authorizer: SanAuthorizer::from_config(self.config.grpc.tls.as_ref()),
+            authenticator,
+        }
+    }
+
     /// Get the server address
     pub fn address(&self) -> String {
         format!("{}:{}", self.config.server.host, self.config.server.port)
@@ -120,6 +341,19 @@ mod tests {
         assert_eq!(service.config().server.port, 50051);
     }
 
+    #[test]
+    fn test_compression_encoding() {
+        assert_eq!(
+            compression_encoding("gzip").unwrap(),
+            CompressionEncoding::Gzip
+        );
+        assert_eq!(
+            compression_encoding("ZSTD").unwrap(),
+            CompressionEncoding::Zstd
+        );
+        assert!(compression_encoding("brotli").is_err());
+    }
+
     #[tokio::test]
     async fn test_server_graceful_shutdown() {
         let config = Config {
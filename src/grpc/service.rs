@@ -1,25 +1,385 @@
 //! gRPC Service Implementation
+//!
+//! `AmwajMediaService` implements the generated `AmwajMediaServer` trait.
+//! Each session owns a `MediaPipeline`; orchestrators drive it through the
+//! `MediaStream` RPC and receive its media events on the same stream.
+//! Sessions are created with `CreateSession`, or implicitly on first use.
 
-use crate::config::Config;
-use crate::metrics::Metrics;
+use crate::asr::{AsrSession, Transcript};
+use crate::audio::processor::float_to_pcm;
+use crate::audio::PreRollFrame;
+use crate::config::{AsrConfig, Config, ConfigHandle, LimitAction, RecorderConfig, TenantConfig};
+use crate::detection::{
+    ExternalSignal, FusionBreakdown, SemanticEndpointingConfig, TurnConfigUpdate, TurnSegment,
+    TurnState,
+};
+use crate::error::{AmwajError, ErrorContext};
+use crate::grpc::audio_stream::{AudioChunk, AudioEncoding, AudioIngest};
+use crate::grpc::auth::Principal;
+use crate::grpc::signaling::{Signal, SignalMessage};
+use crate::grpc::subscription::{EventFilter, Subscription};
+use crate::grpc::{capabilities, convert};
+use crate::metrics::latency_report::{LatencyBreakdown, LatencyReporter};
+use crate::metrics::runtime::{self, TASK_AUDIO_IN, TASK_MEDIA_STREAM, TASK_SESSION, TASK_SIGNAL};
+use crate::metrics::telemetry::{self, FrameSampler};
+use crate::metrics::{
+    Metrics, LOSS_ASR_AUDIO, LOSS_CHANNEL_SEND, LOSS_JITTER_BUFFER, LOSS_MEDIA_EVENT,
+    LOSS_MESSAGE_BUFFER, LOSS_PLAYBACK_AUDIO, OUTCOME_MIGRATED, OUTCOME_NONE,
+};
+use crate::pipeline::MediaPipeline;
+use crate::proto;
+use crate::proto::amwaj_media_server_server::AmwajMediaServer;
+use crate::session::{
+    check_tags, recorder, DebugLine, DebugLog, DistributedSessionManager, JournalEvent, LoadReport,
+    LoadSampler, OrphanedSession, SessionConfig, SessionJournal, SessionRecorder, SessionSnapshot,
+    SessionState, SessionUsage, TraceRecord, UsageMetric, SNAPSHOT_VERSION,
+};
+use crate::sinks::{CallDetailRecord, CallTally, EventSinks, SinkEvent};
+use crate::transport::reactor::RtpReactors;
+use crate::transport::rtp::{self, RtpEndpoint, RtpIngest, RtpIngestOptions, RtpPorts, RtpRelay};
+use crate::webrtc::{
+    ConnectionState, DtlsState, IceGatherer, IceState, RtcStatsReport, StateChange, WebRtcManager,
+};
+use parking_lot::Mutex;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+
+/// Capacity of the per-stream outbound event channel
+const EVENT_CHANNEL_CAPACITY: usize = 100;
+
+/// Packet loss above which a session's end gets its debug log attached
+const ANOMALOUS_PACKET_LOSS_RATIO: f32 = 0.05;
+
+/// Outbound half of a media stream
+type EventSender = mpsc::Sender<Result<proto::MediaEvent, Status>>;
+
+/// Outbound half of a signaling stream
+type SignalSender = mpsc::Sender<Result<proto::SignalMessage, Status>>;
+
+/// Outbound half of a playback audio stream
+type PlaybackSender = mpsc::Sender<Result<proto::AudioChunk, Status>>;
+
+/// Options for a new session
+///
+/// Unset fields fall back to the server configuration.
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    /// Session ID, generated when unset
+    pub session_id: Option<String>,
+    pub user_id: Option<String>,
+    pub codec: Option<AudioEncoding>,
+    pub sample_rate: Option<u32>,
+    /// Registered turn detector name
+    pub detector: Option<String>,
+    pub turn_config: Option<TurnConfigUpdate>,
+    /// Open a WebRTC peer connection for the session
+    pub webrtc: bool,
+    /// Receive plain RTP on a port of the session's own, needs `[transports.rtp]`
+    pub rtp: Option<RtpIngestOptions>,
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub tags: HashMap<String, String>,
+    /// Tenant of the caller, its policy applies to the session
+    pub tenant: Option<String>,
+}
+
+/// Point-in-time status of a session
+#[derive(Debug, Clone)]
+pub struct SessionStatus {
+    pub session_id: String,
+    pub user_id: Option<String>,
+    pub state: SessionState,
+    pub created_at_ms: i64,
+    pub last_activity_ms: i64,
+    pub codec: AudioEncoding,
+    pub sample_rate: u32,
+    pub detector: String,
+    pub turn_state: TurnState,
+    pub frames_processed: u64,
+    /// Peer connection state, `None` without WebRTC
+    pub webrtc_connected: Option<bool>,
+    pub rtp_packets_processed: u64,
+    /// Plain RTP endpoint, `None` without plain RTP
+    pub rtp: Option<RtpEndpoint>,
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub tags: HashMap<String, String>,
+    pub tenant: Option<String>,
+}
+
+/// Journal of a session, live or saved when it ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionHistory {
+    pub session_id: String,
+    pub live: bool,
+    pub journal: SessionJournal,
+}
+
+/// Live counters of a session for operators
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionStats {
+    pub session_id: String,
+    pub created_at_ms: i64,
+    pub idle_ms: u64,
+    pub turn_state: TurnState,
+    pub frames_processed: u64,
+    pub stream_attached: bool,
+    /// Events waiting to be sent on the media stream
+    pub event_queue_depth: usize,
+    pub playback_attached: bool,
+    pub debug: bool,
+    /// Writing a trace, see `session::recorder`
+    pub recording: bool,
+    pub rtp_packets_processed: u64,
+    pub jitter_buffer_packets: usize,
+    pub jitter_buffer_level_percent: f32,
+    pub packet_loss_ratio: f32,
+    pub jitter_ms: f32,
+    pub rtt_ms: Option<f32>,
+    /// Estimated mean opinion score, 0 without WebRTC
+    pub mos: f32,
+    pub paused: bool,
+    /// Total time spent paused
+    pub paused_ms: u64,
+    pub tags: HashMap<String, String>,
+    pub tenant: Option<String>,
+}
+
+/// Resources used by a session, for finding the heaviest ones
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionResources {
+    pub session_id: String,
+    pub cpu_ms: u64,
+    /// Processing time per second of audio
+    pub cpu_ms_per_sec: f64,
+    pub buffered_bytes: usize,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub bandwidth_bytes_per_sec: f64,
+    /// Shedding optional work after exceeding a ceiling
+    pub degraded: bool,
+}
+
+/// A session and the streams its events and playback audio are delivered to
+struct StreamSession {
+    pipeline: MediaPipeline,
+    codec: AudioEncoding,
+    sample_rate: u32,
+    detector: String,
+    created_at_ms: i64,
+    /// Last command or audio frame
+    last_activity: Instant,
+    /// Last audio frame, or the start of the session before the first one
+    last_media: Instant,
+    events: Option<EventSender>,
+    /// Subscription of the media stream in `events`
+    filter: EventFilter,
+    playback: Option<PlaybackSender>,
+    playback_sequence: u64,
+    /// Log every frame and command
+    debug: bool,
+    journal: SessionJournal,
+    /// Latest debug lines, captured even with `debug` off
+    debug_log: DebugLog,
+    latency: LatencyReporter,
+    usage: SessionUsage,
+    /// Sizes of the latest playback chunks, to tell what is still queued
+    playback_sizes: VecDeque<usize>,
+    tags: HashMap<String, String>,
+    /// Root span of the session's traces
+    span: tracing::Span,
+    trace_sampler: FrameSampler,
+    /// Set once processing panicked, frames are refused until it ends
+    failed: bool,
+    /// Plain RTP relay, stopped with the session
+    rtp: Option<RtpIngest>,
+    /// Audio tap of the external recognizer, stopped with the session
+    asr: Option<AsrSession>,
+    /// Trace of the session, written while recording
+    recorder: Option<SessionRecorder>,
+    tenant: Option<String>,
+    /// Counts for the call detail record
+    tally: CallTally,
+    /// When the WebRTC connection dropped, cleared once it is restored
+    disconnected_at: Option<Instant>,
+}
+
+impl StreamSession {
+    /// Append a trace line, if the session is recorded
+    fn trace(&mut self, record: impl FnOnce() -> TraceRecord) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(record());
+        }
+    }
+
+    /// Capture a debug line, formatted only if the session keeps any
+    fn debug_line(&mut self, message: impl FnOnce() -> String) {
+        if self.debug_log.is_enabled() {
+            self.debug_log
+                .record(chrono::Utc::now().timestamp_millis(), message());
+        }
+    }
+
+    /// Relay agent audio to the playback stream, if one is attached
+    ///
+    /// Returns whether the audio was handed to the stream.
+    fn forward_playback(&mut self, chunk: AudioChunk) -> bool {
+        let Some(sender) = &self.playback else {
+            return false;
+        };
+
+        self.playback_sequence += 1;
+        let size = chunk.data.len();
+        match sender.try_send(Ok(chunk.into())) {
+            Ok(()) => {
+                if self.playback_sizes.len() == EVENT_CHANNEL_CAPACITY {
+                    self.playback_sizes.pop_front();
+                }
+                self.playback_sizes.push_back(size);
+                self.usage.record_sent(size);
+                true
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                tracing::warn!("Dropping playback audio, stream is full");
+                self.usage.record_loss(LOSS_PLAYBACK_AUDIO, 1);
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                self.usage.record_loss(LOSS_CHANNEL_SEND, 1);
+                self.playback = None;
+                false
+            }
+        }
+    }
+
+    /// Get the bytes of playback audio the renderer has yet to receive
+    fn queued_playback_bytes(&self) -> usize {
+        let Some(sender) = &self.playback else {
+            return 0;
+        };
+        // The channel is FIFO, the queued chunks are the latest ones sent
+        let queued = sender.max_capacity() - sender.capacity();
+        self.playback_sizes.iter().rev().take(queued).sum()
+    }
+
+    /// Refresh the bytes of audio buffered for the session
+    fn refresh_buffered_bytes(&mut self) {
+        let bytes = self.pipeline.buffered_bytes() + self.queued_playback_bytes();
+        self.usage.set_buffered_bytes(bytes);
+    }
+
+    fn resources(&self, session_id: &str) -> SessionResources {
+        SessionResources {
+            session_id: session_id.to_string(),
+            cpu_ms: self.usage.cpu_time().as_millis() as u64,
+            cpu_ms_per_sec: self.usage.cpu_ms_per_sec(),
+            buffered_bytes: self.usage.buffered_bytes(),
+            bytes_in: self.usage.bytes_in(),
+            bytes_out: self.usage.bytes_out(),
+            bandwidth_bytes_per_sec: self.usage.bandwidth_bytes_per_sec(),
+            degraded: self.usage.is_degraded(),
+        }
+    }
+}
 
 /// gRPC Media Service handler
+#[derive(Clone)]
 pub struct AmwajMediaService {
     config: Arc<Config>,
+    /// Configuration in effect for new sessions, swapped on reload
+    live_config: ConfigHandle,
     metrics: Arc<Metrics>,
+    sessions: Arc<Mutex<HashMap<String, StreamSession>>>,
+    session_manager: Arc<DistributedSessionManager>,
+    webrtc: Arc<WebRtcManager>,
+    /// Ports of plain RTP sessions, `None` when they are refused
+    rtp_ports: Option<Arc<Mutex<RtpPorts>>>,
+    /// Receive tasks of the shared RTP port, started with its first session
+    rtp_reactors: Arc<Mutex<Option<Arc<RtpReactors>>>>,
+    /// External recognizer fed with the audio of every session
+    asr: Option<Arc<AsrConfig>>,
+    /// Where session traces go, `None` when they are never recorded
+    recorder: Option<Arc<RecorderConfig>>,
+    sinks: EventSinks,
+    /// Destinations of call detail records, none when they are off
+    cdrs: EventSinks,
+    /// Set once a drain starts, new sessions are refused
+    draining: Arc<AtomicBool>,
+    /// Set once a drain ends, open streams are closed
+    closed: Arc<watch::Sender<bool>>,
+    /// Time spent processing audio, for load reports
+    load: Arc<LoadSampler>,
 }
 
 impl AmwajMediaService {
     /// Create a new AmwajMediaService
     pub fn new(config: Config, metrics: Arc<Metrics>) -> Self {
+        let session_manager = DistributedSessionManager::new(SessionConfig::from(&config.sessions));
+        let webrtc = WebRtcManager::new()
+            .with_metrics(Arc::clone(&metrics))
+            .with_codecs(config.audio.codecs.clone());
+        let rtp_ports = config.transports.rtp.as_ref().map(|rtp| {
+            let ports =
+                RtpPorts::new(rtp.port_min, rtp.port_max).with_socket(config.webrtc.socket.clone());
+            Arc::new(Mutex::new(ports))
+        });
+        let asr = config.asr.clone().map(Arc::new);
+        let recorder = config.recorder.clone().map(Arc::new);
         Self {
+            live_config: ConfigHandle::new(config.clone()),
             config: Arc::new(config),
             metrics,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            session_manager: Arc::new(session_manager),
+            webrtc: Arc::new(webrtc),
+            rtp_ports,
+            rtp_reactors: Arc::default(),
+            asr,
+            recorder,
+            sinks: EventSinks::default(),
+            cdrs: EventSinks::default(),
+            draining: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(watch::Sender::new(false)),
+            load: Arc::new(LoadSampler::new()),
         }
     }
 
+    /// Share a session manager, e.g. one backed by Redis
+    pub fn with_session_manager(mut self, session_manager: Arc<DistributedSessionManager>) -> Self {
+        self.session_manager = session_manager;
+        self
+    }
+
+    /// Publish session lifecycle events to sinks
+    pub fn with_event_sinks(mut self, sinks: EventSinks) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
+    /// Write a call detail record to `cdrs` when a session ends
+    pub fn with_cdr_sinks(mut self, cdrs: EventSinks) -> Self {
+        self.cdrs = cdrs;
+        self
+    }
+
+    /// Follow configuration reloads
+    ///
+    /// New sessions take their detection settings and trace sampling from
+    /// the configuration in effect when they are created.
+    pub fn with_config_handle(mut self, live_config: ConfigHandle) -> Self {
+        self.live_config = live_config;
+        self
+    }
+
     /// Get the configuration
     pub fn config(&self) -> &Config {
         &self.config
@@ -29,206 +389,4160 @@ impl AmwajMediaService {
     pub fn metrics(&self) -> &Metrics {
         &self.metrics
     }
-}
 
-/// Media event types for the gRPC stream
-#[derive(Debug, Clone)]
-pub enum MediaEvent {
-    AudioFrame {
-        session_id: String,
-        timestamp_ms: i64,
-        pcm_data: Vec<u8>,
-        sample_rate: u32,
-        channels: u32,
-    },
-    TurnStarted {
-        session_id: String,
-        timestamp_ms: i64,
-        vad_probability: f32,
-    },
-    TurnEnded {
-        session_id: String,
-        timestamp_ms: i64,
-        duration_ms: u32,
-    },
-    PartialTranscript {
-        session_id: String,
-        timestamp_ms: i64,
-        text: String,
-        confidence: f32,
-    },
-    SessionEnded {
-        session_id: String,
-        duration_ms: i64,
-        total_frames: u32,
-    },
-}
+    /// Get the session manager
+    pub fn session_manager(&self) -> &DistributedSessionManager {
+        &self.session_manager
+    }
 
-/// Orchestration commands from the server
-#[derive(Debug, Clone)]
-pub enum OrchestrationCommand {
-    PlayAudio {
-        session_id: String,
-        audio_data: Vec<u8>,
-        audio_format: String,
-    },
-    StopAudio {
-        session_id: String,
-        reason: String,
-    },
-    ClearContext {
-        session_id: String,
-        context_type: String,
-    },
-    AdjustVAD {
-        session_id: String,
-        sensitivity: f32,
-        threshold_ms: u32,
-    },
-}
+    /// Get the number of live sessions
+    pub fn session_count(&self) -> usize {
+        self.sessions.lock().len()
+    }
 
-/// Session handler for managing a single media stream session
-pub struct SessionHandler {
-    session_id: String,
-    event_tx: mpsc::Sender<MediaEvent>,
-    command_rx: mpsc::Receiver<OrchestrationCommand>,
-    #[allow(dead_code)]
-    config: Arc<Config>,
-    metrics: Arc<Metrics>,
-}
+    /// Create a session, returns its ID
+    ///
+    /// Fails if a session with the requested ID already exists or the server
+    /// is draining. A session migrating from another instance resumes from
+    /// its snapshot, which takes precedence over `options`.
+    pub async fn create_session(&self, options: SessionOptions) -> anyhow::Result<String> {
+        if self.is_draining() {
+            return Err(anyhow::anyhow!("Server is draining"));
+        }
+        let session_id = options
+            .session_id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        if self.sessions.lock().contains_key(&session_id) {
+            return Err(anyhow::anyhow!("Session already exists: {}", session_id));
+        }
 
-impl SessionHandler {
-    /// Create a new session handler
-    pub fn new(
-        session_id: String,
-        config: Arc<Config>,
-        metrics: Arc<Metrics>,
-    ) -> (
-        Self,
-        mpsc::Receiver<MediaEvent>,
-        mpsc::Sender<OrchestrationCommand>,
-    ) {
-        let (event_tx, event_rx) = mpsc::channel(100);
-        let (command_tx, command_rx) = mpsc::channel(100);
+        // Fails while another instance owns the session
+        self.session_manager.claim_session(&session_id).await?;
+        let snapshot = match self.session_manager.take_snapshot(&session_id).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                self.session_manager.release_session(&session_id).await?;
+                return Err(e.into());
+            }
+        };
+        if let Err(e) = self
+            .open_session(&session_id, options, snapshot.as_ref())
+            .await
+        {
+            // Leave a migrating session for another attempt, drop the
+            // record of a new one so it stops counting against the limits
+            match &snapshot {
+                Some(snapshot) => self.session_manager.export_session(snapshot).await?,
+                None => self.session_manager.end_session(&session_id).await?,
+            }
+            return Err(e);
+        }
+        Ok(session_id)
+    }
 
-        let handler = Self {
-            session_id,
-            event_tx,
-            command_rx,
-            config,
-            metrics,
+    /// Set up a claimed session, resuming it from a snapshot if given
+    async fn open_session(
+        &self,
+        session_id: &str,
+        options: SessionOptions,
+        snapshot: Option<&SessionSnapshot>,
+    ) -> anyhow::Result<()> {
+        let live_config = self.live_config.current();
+        let mut config = Config::clone(&self.config);
+        config.detection = live_config.detection.clone();
+        let tenant = snapshot.map_or(options.tenant.clone(), |snapshot| snapshot.tenant.clone());
+        let policy = match &tenant {
+            Some(name) => Some(
+                self.config
+                    .tenants
+                    .get(name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown tenant: {}", name))?,
+            ),
+            None => None,
+        };
+        // Resumed sessions were admitted by the instance they come from
+        if let (Some(name), Some(policy), None) = (&tenant, policy, snapshot) {
+            self.check_tenant_policy(name, policy, &options)?;
+        }
+        let sample_rate = snapshot
+            .map(|snapshot| snapshot.sample_rate)
+            .or(options.sample_rate);
+        if let Some(sample_rate) = sample_rate {
+            if !(8000..=48000).contains(&sample_rate) {
+                return Err(anyhow::anyhow!("Unsupported sample rate: {}", sample_rate));
+            }
+            config.audio.sample_rate = sample_rate;
+        }
+        // Plain RTP is not carried over by migrations
+        let rtp_relay = match (&options.rtp, snapshot) {
+            (Some(rtp), None) => {
+                let relay = self.open_rtp(session_id, rtp)?;
+                let rtp_rate = rtp::session_sample_rate(relay.negotiated_codec().codec);
+                if sample_rate.is_some_and(|sample_rate| sample_rate != rtp_rate) {
+                    return Err(anyhow::anyhow!(
+                        "Plain RTP of {} runs at {} Hz",
+                        rtp.codec,
+                        rtp_rate
+                    ));
+                }
+                config.audio.sample_rate = rtp_rate;
+                Some(relay)
+            }
+            _ => None,
         };
+        if let Some(detector) = snapshot
+            .map(|snapshot| &snapshot.detector)
+            .or(options.detector.as_ref())
+            .or(policy.and_then(|policy| policy.detector.as_ref()))
+        {
+            config.detection.detector = detector.clone();
+        }
 
-        (handler, event_rx, command_tx)
+        let codec = snapshot
+            .map(|snapshot| snapshot.codec)
+            .or(options.codec)
+            .unwrap_or(AudioEncoding::Pcm16);
+        let mut pipeline = MediaPipeline::new(session_id.to_string(), &config)?
+            .with_metrics(Arc::clone(&self.metrics))
+            .with_codec(codec.as_str());
+        match snapshot {
+            Some(snapshot) => {
+                pipeline.resume(snapshot.frames_processed, snapshot.detection.as_ref())
+            }
+            None => {
+                if let Some(policy) = policy {
+                    pipeline
+                        .detector_mut()
+                        .apply_config_update(&TurnConfigUpdate::from(&policy.turn_detection))?;
+                }
+                if let Some(update) = &options.turn_config {
+                    pipeline.detector_mut().apply_config_update(update)?;
+                }
+            }
+        }
+        if let (None, Some(asr)) = (&config.detection.semantic, &self.asr) {
+            pipeline
+                .detector_mut()
+                .set_semantic_endpointing(Some(SemanticEndpointingConfig {
+                    complete_silence_ms: asr.complete_silence_ms,
+                    incomplete_silence_ms: asr.incomplete_silence_ms,
+                }));
+        }
+
+        let mut metadata = snapshot
+            .map(|snapshot| snapshot.metadata.clone())
+            .unwrap_or_default();
+        metadata.extend(options.metadata);
+        self.session_manager.check_metadata(&metadata)?;
+        let mut tags = snapshot
+            .map(|snapshot| snapshot.tags.clone())
+            .unwrap_or_default();
+        tags.extend(options.tags);
+        check_tags(&tags, self.config.sessions.max_tags)?;
+        let user_id = options
+            .user_id
+            .or_else(|| snapshot.and_then(|snapshot| snapshot.user_id.clone()));
+        self.session_manager
+            .register_session(session_id, user_id.clone())
+            .await?;
+        for (key, value) in metadata {
+            self.session_manager
+                .set_metadata(session_id, key, value)
+                .await?;
+        }
+        let peer = snapshot.and_then(|snapshot| snapshot.peer.as_ref());
+        if options.webrtc || peer.is_some() {
+            let connection = self.webrtc.create_connection(session_id.to_string())?;
+            if let Some(peer) = peer {
+                connection.restore(peer.clone()).await?;
+            }
+        }
+
+        let mut journal = snapshot
+            .map(|snapshot| snapshot.journal.clone())
+            .unwrap_or_default();
+        journal.set_max_entries(self.config.sessions.journal_max_entries);
+
+        let mut sessions = self.sessions.lock();
+        if sessions.contains_key(session_id) {
+            return Err(anyhow::anyhow!("Session already exists: {}", session_id));
+        }
+        if let (Some(name), Some(policy), None) = (&tenant, policy, snapshot) {
+            if let Err(e) = self.check_tenant_quota(&sessions, name, policy) {
+                drop(sessions);
+                self.webrtc.remove_connection(session_id);
+                return Err(e);
+            }
+        }
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let trace_sampler = if self.config.metrics.enable_tracing {
+            FrameSampler::new(live_config.metrics.frame_trace_ratio)
+        } else {
+            FrameSampler::disabled()
+        };
+        let span = telemetry::session_span(session_id, codec.as_str(), &config.detection.detector);
+        let recorder = self
+            .recorder
+            .as_ref()
+            .filter(|recorder| recorder.record_all)
+            .and_then(|_| {
+                self.start_recording(
+                    session_id,
+                    config.audio.sample_rate,
+                    &config.detection.detector,
+                )
+            });
+        sessions.insert(
+            session_id.to_string(),
+            StreamSession {
+                pipeline,
+                codec,
+                sample_rate: config.audio.sample_rate,
+                detector: config.detection.detector,
+                created_at_ms: snapshot.map_or(now_ms, |snapshot| snapshot.created_at_ms),
+                last_activity: Instant::now(),
+                last_media: Instant::now(),
+                events: None,
+                filter: EventFilter::default(),
+                playback: None,
+                playback_sequence: snapshot.map_or(0, |snapshot| snapshot.playback_sequence),
+                debug: snapshot.is_some_and(|snapshot| snapshot.debug),
+                journal,
+                debug_log: DebugLog::new(self.config.sessions.debug_log_lines),
+                latency: LatencyReporter::new(self.config.metrics.latency_report_interval_ms),
+                usage: SessionUsage::new(),
+                playback_sizes: VecDeque::new(),
+                tags,
+                span,
+                trace_sampler,
+                failed: false,
+                rtp: rtp_relay
+                    .map(|relay| RtpIngest::spawn(relay, self.rtp_advertised_address()))
+                    .transpose()?,
+                asr: self.asr.as_ref().map(|asr| {
+                    AsrSession::spawn(
+                        self.clone(),
+                        Arc::clone(asr),
+                        session_id,
+                        config.audio.sample_rate,
+                        config.audio.frame_duration_ms,
+                    )
+                }),
+                recorder,
+                tenant: tenant.clone(),
+                tally: snapshot
+                    .map(|snapshot| snapshot.tally.clone())
+                    .unwrap_or_default(),
+                disconnected_at: None,
+            },
+        );
+        drop(sessions);
+        if let Some(tenant) = &tenant {
+            self.metrics
+                .tenant_sessions
+                .with_label_values(&[tenant])
+                .inc();
+        }
+
+        match snapshot {
+            Some(snapshot) => tracing::info!(
+                "Session {} resumed from instance {} after {} ms",
+                session_id,
+                snapshot.source_instance_id,
+                now_ms - snapshot.taken_at_ms
+            ),
+            None => self.sinks.emit(SinkEvent::SessionStarted {
+                session_id: session_id.to_string(),
+                timestamp_ms: now_ms,
+                user_id,
+                tenant,
+            }),
+        }
+        Ok(())
     }
 
-    /// Get session ID
-    pub fn session_id(&self) -> &str {
-        &self.session_id
+    /// Check a new session against the codecs and quota of its tenant
+    fn check_tenant_policy(
+        &self,
+        tenant: &str,
+        policy: &TenantConfig,
+        options: &SessionOptions,
+    ) -> anyhow::Result<()> {
+        let codec = match &options.rtp {
+            Some(rtp) => rtp.codec.as_str(),
+            None => options.codec.unwrap_or(AudioEncoding::Pcm16).as_str(),
+        };
+        if !policy.allows_codec(codec) {
+            self.metrics.record_tenant_rejection(tenant, "codec");
+            return Err(anyhow::anyhow!(
+                "Tenant {} may not use codec {}",
+                tenant,
+                codec
+            ));
+        }
+        self.check_tenant_quota(&self.sessions.lock(), tenant, policy)
     }
 
-    /// Send a media event
-    pub async fn send_event(&self, event: MediaEvent) -> anyhow::Result<()> {
-        self.event_tx
-            .send(event)
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to send event: {}", e))?;
-        self.metrics.grpc_messages_sent.inc();
+    /// Check a tenant has room for one more session
+    ///
+    /// Run again under the lock inserting the session, concurrent creations
+    /// all pass the early check.
+    fn check_tenant_quota(
+        &self,
+        sessions: &HashMap<String, StreamSession>,
+        tenant: &str,
+        policy: &TenantConfig,
+    ) -> anyhow::Result<()> {
+        let Some(limit) = policy.max_sessions else {
+            return Ok(());
+        };
+        let count = sessions
+            .values()
+            .filter(|session| session.tenant.as_deref() == Some(tenant))
+            .count();
+        if count >= limit {
+            self.metrics.record_tenant_rejection(tenant, "quota");
+            return Err(AmwajError::CapacityExceeded(format!(
+                "Tenant {} reached the limit of {} concurrent sessions",
+                tenant, limit
+            ))
+            .into());
+        }
         Ok(())
     }
 
-    /// Receive the next orchestration command
-    pub async fn receive_command(&mut self) -> Option<OrchestrationCommand> {
-        self.command_rx.recv().await
+    /// Count a session of a tenant as gone from this instance
+    fn release_tenant_session(&self, tenant: Option<&str>) {
+        if let Some(tenant) = tenant {
+            self.metrics
+                .tenant_sessions
+                .with_label_values(&[tenant])
+                .dec();
+        }
     }
 
-    /// Check if the event channel is closed
-    pub fn is_closed(&self) -> bool {
-        self.event_tx.is_closed()
+    /// Bind the plain RTP port of a session
+    fn open_rtp(&self, session_id: &str, options: &RtpIngestOptions) -> anyhow::Result<RtpRelay> {
+        let ports = self
+            .rtp_ports
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Plain RTP is disabled, it needs [transports.rtp]"))?;
+        let negotiated = rtp::negotiate(options, &self.config.audio.codecs)?;
+        let ip = self
+            .config
+            .server
+            .host
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid server.host for RTP: {}", e))?;
+        if let Some(port) = self
+            .config
+            .transports
+            .rtp
+            .as_ref()
+            .and_then(|rtp| rtp.shared_port)
+        {
+            let ssrc = options.ssrc.ok_or_else(|| {
+                anyhow::anyhow!("Sessions on the shared RTP port need the SSRC of their stream")
+            })?;
+            let route = self
+                .shared_rtp(std::net::SocketAddr::new(ip, port))?
+                .route(ssrc)?;
+            return Ok(
+                RtpRelay::new(self.clone(), session_id, route, negotiated).with_ssrc(Some(ssrc))
+            );
+        }
+        let socket = ports.lock().bind(ip)?;
+        Ok(RtpRelay::new(self.clone(), session_id, socket, negotiated).with_ssrc(options.ssrc))
     }
-}
-
-/// Message buffer for handling backpressure
-pub struct MessageBuffer<T> {
-    buffer: Vec<T>,
-    max_size: usize,
-}
 
-impl<T> MessageBuffer<T> {
-    /// Create a new message buffer
-    pub fn new(max_size: usize) -> Self {
-        Self {
-            buffer: Vec::with_capacity(max_size),
-            max_size,
+    /// Get the reactors of the shared RTP port, binding it on first use
+    fn shared_rtp(&self, addr: std::net::SocketAddr) -> anyhow::Result<Arc<RtpReactors>> {
+        let mut reactors = self.rtp_reactors.lock();
+        if let Some(reactors) = reactors.as_ref() {
+            return Ok(Arc::clone(reactors));
         }
+        let count = self
+            .config
+            .transports
+            .rtp
+            .as_ref()
+            .map_or(1, |rtp| rtp.reactors);
+        let bound = Arc::new(RtpReactors::bind(
+            addr,
+            count,
+            &self.config.webrtc.socket,
+            Arc::clone(&self.metrics),
+        )?);
+        *reactors = Some(Arc::clone(&bound));
+        Ok(bound)
     }
 
-    /// Add a message to the buffer
-    pub fn push(&mut self, msg: T) -> bool {
-        if self.buffer.len() < self.max_size {
-            self.buffer.push(msg);
-            true
-        } else {
-            false // Buffer full, backpressure
+    /// Address plain RTP sessions report to send to
+    fn rtp_advertised_address(&self) -> Option<std::net::IpAddr> {
+        self.config
+            .transports
+            .rtp
+            .as_ref()
+            .and_then(|rtp| rtp.advertised_address.as_deref())
+            .and_then(|address| address.parse().ok())
+    }
+
+    /// Move a session off this instance, returns its snapshot
+    ///
+    /// The snapshot is parked in the session store and the lease released;
+    /// the media stream receives `SessionMigrated`, and the instance the
+    /// client reconnects to resumes the session from the snapshot.
+    pub async fn migrate_session(&self, session_id: &str) -> anyhow::Result<SessionSnapshot> {
+        let (snapshot, events) = self.export_session(session_id).await?;
+        if let Some(sender) = events {
+            let event = MediaEvent::SessionMigrated {
+                session_id: session_id.to_string(),
+                timestamp_ms: snapshot.taken_at_ms,
+                frames_processed: snapshot.frames_processed,
+            };
+            self.send_final_event(&sender, event, OUTCOME_MIGRATED);
         }
+        tracing::info!("Session {} migrated off this instance", session_id);
+        Ok(snapshot)
     }
 
-    /// Take all messages from the buffer
-    pub fn drain(&mut self) -> Vec<T> {
-        std::mem::take(&mut self.buffer)
+    /// Park a session for another instance while draining, returns its
+    /// orphan record
+    ///
+    /// Like `migrate_session`, but the media stream receives
+    /// `ServerDraining` with the reconnection state, which is also published
+    /// in the session store until the session is resumed.
+    async fn hand_off_session(
+        &self,
+        session_id: &str,
+        deadline_ms: i64,
+    ) -> anyhow::Result<OrphanedSession> {
+        let (snapshot, events) = self.export_session(session_id).await?;
+        let orphan = match self.session_manager.publish_orphan(&snapshot).await {
+            Ok(orphan) => orphan,
+            Err(e) => {
+                // The snapshot is parked, the client can still resume
+                tracing::warn!("Failed to publish orphaned session {}: {}", session_id, e);
+                snapshot.orphan(self.config.sessions.snapshot_ttl_seconds)
+            }
+        };
+        if let Some(sender) = events {
+            let event = MediaEvent::ServerDraining {
+                session_id: session_id.to_string(),
+                timestamp_ms: snapshot.taken_at_ms,
+                deadline_ms,
+                handoff: Some(orphan.clone()),
+            };
+            self.send_final_event(&sender, event, OUTCOME_MIGRATED);
+        }
+        tracing::info!("Session {} handed off for draining", session_id);
+        Ok(orphan)
     }
 
-    /// Get current buffer size
-    pub fn len(&self) -> usize {
-        self.buffer.len()
+    /// Snapshot a session and park it in the session store
+    ///
+    /// Returns the snapshot and the session's media stream, which is left
+    /// for the caller to notify.
+    async fn export_session(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<(SessionSnapshot, Option<EventSender>)> {
+        let data = self.session_manager.get_session(session_id).await?;
+        let (peer, packets_evicted) = match self.webrtc.get_connection(session_id) {
+            Ok(peer) => (
+                Some(peer.snapshot().await?),
+                peer.state().buffer.packets_evicted,
+            ),
+            Err(_) => (None, 0),
+        };
+        let session = self
+            .sessions
+            .lock()
+            .remove(session_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown session: {}", session_id))?;
+
+        let snapshot = SessionSnapshot {
+            version: SNAPSHOT_VERSION,
+            session_id: session_id.to_string(),
+            source_instance_id: self.session_manager.instance_id().to_string(),
+            taken_at_ms: chrono::Utc::now().timestamp_millis(),
+            created_at_ms: session.created_at_ms,
+            user_id: data.as_ref().and_then(|data| data.user_id.clone()),
+            metadata: data.map(|data| data.metadata).unwrap_or_default(),
+            codec: session.codec,
+            sample_rate: session.sample_rate,
+            detector: session.detector.clone(),
+            frames_processed: session.pipeline.frames_processed(),
+            playback_sequence: session.playback_sequence,
+            debug: session.debug,
+            detection: session.pipeline.detector().snapshot(),
+            peer,
+            journal: session.journal.clone(),
+            tags: session.tags.clone(),
+            tenant: session.tenant.clone(),
+            tally: session.tally.clone(),
+        };
+        if let Err(e) = self.session_manager.export_session(&snapshot).await {
+            self.sessions.lock().insert(session_id.to_string(), session);
+            return Err(e.into());
+        }
+
+        self.webrtc.remove_connection(session_id);
+        self.release_tenant_session(session.tenant.as_deref());
+        self.record_losses(&session.usage, packets_evicted, OUTCOME_MIGRATED);
+        self.store_recording(session.recorder);
+        Ok((snapshot, session.events))
     }
 
-    /// Check if buffer is empty
-    pub fn is_empty(&self) -> bool {
-        self.buffer.is_empty()
+    /// Count the data a session lost under load, labelled with its outcome
+    fn record_losses(&self, usage: &SessionUsage, packets_evicted: u64, outcome: &str) {
+        for (kind, &count) in usage.losses() {
+            self.metrics.record_data_loss(kind, outcome, count);
+        }
+        self.metrics
+            .record_data_loss(LOSS_JITTER_BUFFER, outcome, packets_evicted);
     }
 
-    /// Check if buffer is full
-    pub fn is_full(&self) -> bool {
-        self.buffer.len() >= self.max_size
+    /// Send the last event of a session that is leaving this instance
+    fn send_final_event(&self, sender: &EventSender, event: MediaEvent, outcome: &str) {
+        match sender.try_send(Ok(event.into())) {
+            Ok(()) => self.metrics.grpc_messages_sent.inc(),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.metrics.record_data_loss(LOSS_MEDIA_EVENT, outcome, 1)
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                self.metrics.record_data_loss(LOSS_CHANNEL_SEND, outcome, 1)
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Create a session of `tenant` with default options unless it already
+    /// exists
+    ///
+    /// Sessions of another tenant are reported unknown.
+    async fn ensure_session(&self, session_id: &str, tenant: Option<&str>) -> anyhow::Result<()> {
+        if self.hidden_from(session_id, tenant) {
+            return Err(AmwajError::SessionNotFound(session_id.to_string()).into());
+        }
+        if self.sessions.lock().contains_key(session_id) {
+            return Ok(());
+        }
 
-    #[test]
-    fn test_service_creation() {
-        let config = Config::default();
-        let metrics = Arc::new(Metrics::new(&config));
-        let service = AmwajMediaService::new(config, metrics);
+        let options = SessionOptions {
+            session_id: Some(session_id.to_string()),
+            tenant: tenant.map(str::to_string),
+            ..Default::default()
+        };
+        match self.create_session(options).await {
+            // Lost a race with another stream opening the same session
+            Err(_) if self.sessions.lock().contains_key(session_id) => {
+                if self.hidden_from(session_id, tenant) {
+                    return Err(AmwajError::SessionNotFound(session_id.to_string()).into());
+                }
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
+    }
 
-        assert_eq!(service.config().server.port, 50051);
+    /// Check if a live session belongs to another tenant than `tenant`
+    fn hidden_from(&self, session_id: &str, tenant: Option<&str>) -> bool {
+        self.sessions
+            .lock()
+            .get(session_id)
+            .is_some_and(|session| session.tenant.as_deref() != tenant)
     }
 
-    #[tokio::test]
-    async fn test_session_handler() {
-        let config = Arc::new(Config::default());
-        let metrics = Arc::new(Metrics::new(&config));
+    /// Get the tenant of the authenticated caller of an RPC
+    fn caller_tenant<T>(&self, request: &Request<T>) -> Option<String> {
+        let principal = request.extensions().get::<Principal>()?;
+        self.config.tenant_of(&principal.id).map(str::to_string)
+    }
 
-        let (handler, mut event_rx, _command_tx) =
-            SessionHandler::new("test-session".to_string(), config, metrics);
+    /// Get the status refusing a unary RPC on a session of another tenant
+    fn hidden_status(&self, session_id: &str, tenant: Option<&str>) -> Option<Status> {
+        self.hidden_from(session_id, tenant)
+            .then(|| Status::not_found(format!("Unknown session: {}", session_id)))
+    }
 
-        assert_eq!(handler.session_id(), "test-session");
+    /// Get the live counters of every session
+    pub fn session_stats(&self) -> Vec<SessionStats> {
+        let mut stats: Vec<SessionStats> = self
+            .sessions
+            .lock()
+            .iter()
+            .map(|(session_id, session)| SessionStats {
+                session_id: session_id.clone(),
+                created_at_ms: session.created_at_ms,
+                idle_ms: session.last_activity.elapsed().as_millis() as u64,
+                turn_state: session.pipeline.detector().state(),
+                frames_processed: session.pipeline.frames_processed(),
+                stream_attached: session.events.is_some(),
+                event_queue_depth: session
+                    .events
+                    .as_ref()
+                    .map(|events| events.max_capacity() - events.capacity())
+                    .unwrap_or_default(),
+                playback_attached: session.playback.is_some(),
+                debug: session.debug,
+                recording: session.recorder.is_some(),
+                rtp_packets_processed: session.rtp.as_ref().map_or(0, RtpIngest::packets_received),
+                jitter_buffer_packets: 0,
+                jitter_buffer_level_percent: 0.0,
+                packet_loss_ratio: 0.0,
+                jitter_ms: 0.0,
+                rtt_ms: None,
+                mos: 0.0,
+                paused: session.pipeline.is_paused(),
+                paused_ms: session.pipeline.paused_duration().as_millis() as u64,
+                tags: session.tags.clone(),
+                tenant: session.tenant.clone(),
+            })
+            .collect();
 
-        // Send an event
-        let event = MediaEvent::TurnStarted {
-            session_id: "test-session".to_string(),
-            timestamp_ms: 1000,
-            vad_probability: 0.8,
-        };
+        for stats in &mut stats {
+            if let Ok(peer) = self.webrtc.get_connection(&stats.session_id) {
+                let peer = peer.state();
+                let buffer = &peer.buffer;
+                stats.rtp_packets_processed = peer.packets_processed;
+                stats.jitter_buffer_packets = buffer.size;
+                stats.jitter_buffer_level_percent = buffer.level_percent;
+                stats.packet_loss_ratio = buffer.packet_loss_ratio;
+                stats.jitter_ms = buffer.jitter_ms;
+                stats.rtt_ms = buffer.rtt_ms;
+                stats.mos = buffer.mos;
+            }
+        }
+        stats.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+        stats
+    }
 
-        handler.send_event(event).await.unwrap();
+    /// Get the latest debug lines of a session and how many were pushed out
+    pub fn session_debug_log(&self, session_id: &str) -> anyhow::Result<(Vec<DebugLine>, u64)> {
+        let mut sessions = self.sessions.lock();
+        let session = session_mut(&mut sessions, session_id)?;
+        Ok((session.debug_log.lines(), session.debug_log.dropped()))
+    }
 
-        // Receive the event
-        let received = event_rx.recv().await;
-        assert!(received.is_some());
+    /// Turn per-frame debug logging of a session on or off
+    pub fn set_session_debug(&self, session_id: &str, enabled: bool) -> anyhow::Result<()> {
+        let mut sessions = self.sessions.lock();
+        session_mut(&mut sessions, session_id)?.debug = enabled;
+        tracing::info!(
+            "Debug logging {} for session {}",
+            if enabled { "enabled" } else { "disabled" },
+            session_id
+        );
+        Ok(())
+    }
+
+    /// Start or stop writing the trace of a session
+    ///
+    /// Fails without a `[recorder]` section. A stopped trace is stored right
+    /// away, a new one is started when recording is turned back on.
+    pub fn set_session_recording(&self, session_id: &str, enabled: bool) -> anyhow::Result<()> {
+        if self.recorder.is_none() {
+            return Err(anyhow::anyhow!("Recording is not configured"));
+        }
+        let mut sessions = self.sessions.lock();
+        let session = session_mut(&mut sessions, session_id)?;
+        if enabled == session.recorder.is_some() {
+            return Ok(());
+        }
+        if enabled {
+            let recorder = self
+                .start_recording(session_id, session.sample_rate, &session.detector)
+                .ok_or_else(|| anyhow::anyhow!("Failed to start recording {}", session_id))?;
+            session.recorder = Some(recorder);
+        } else {
+            let recorder = session.recorder.take();
+            drop(sessions);
+            self.store_recording(recorder);
+        }
+        tracing::info!(
+            "Recording {} for session {}",
+            if enabled { "enabled" } else { "disabled" },
+            session_id
+        );
+        Ok(())
+    }
+
+    /// Open the trace of a session, logging why it could not be
+    fn start_recording(
+        &self,
+        session_id: &str,
+        sample_rate: u32,
+        detector: &str,
+    ) -> Option<SessionRecorder> {
+        let config = self.recorder.as_ref()?;
+        let mut recorder = match SessionRecorder::create(Path::new(&config.dir), session_id) {
+            Ok(recorder) => recorder,
+            Err(e) => {
+                tracing::warn!("Not recording session {}: {}", session_id, e);
+                return None;
+            }
+        };
+        recorder.record(TraceRecord::Start {
+            session_id: session_id.to_string(),
+            sample_rate,
+            frame_duration_ms: self.config.audio.frame_duration_ms,
+            detector: detector.to_string(),
+        });
+        Some(recorder)
+    }
+
+    /// Store the trace of a session that stopped recording
+    fn store_recording(&self, recorder: Option<SessionRecorder>) {
+        if let (Some(recorder), Some(config)) = (recorder, &self.recorder) {
+            recorder::store(recorder, config);
+        }
+    }
+
+    /// Trace an RTP packet of a recorded session
+    ///
+    /// The jitter is only measured for sessions being recorded.
+    pub fn record_packet(
+        &self,
+        session_id: &str,
+        sequence_number: u16,
+        rtp_timestamp: u32,
+        jitter_ms: impl FnOnce() -> f32,
+    ) {
+        if self.recorder.is_none() {
+            return;
+        }
+        if let Some(session) = self.sessions.lock().get_mut(session_id) {
+            session.trace(|| TraceRecord::Packet {
+                sequence_number,
+                rtp_timestamp,
+                jitter_ms: jitter_ms(),
+            });
+        }
+    }
+
+    /// Get the status of a session
+    pub async fn session_status(&self, session_id: &str) -> Option<SessionStatus> {
+        let paused;
+        let mut status = {
+            let sessions = self.sessions.lock();
+            let session = sessions.get(session_id)?;
+            paused = session.pipeline.is_paused();
+            SessionStatus {
+                session_id: session_id.to_string(),
+                user_id: None,
+                state: SessionState::Active,
+                created_at_ms: session.created_at_ms,
+                last_activity_ms: session.created_at_ms,
+                codec: session.codec,
+                sample_rate: session.sample_rate,
+                detector: session.detector.clone(),
+                turn_state: session.pipeline.detector().state(),
+                frames_processed: session.pipeline.frames_processed(),
+                webrtc_connected: None,
+                rtp_packets_processed: session.rtp.as_ref().map_or(0, RtpIngest::packets_received),
+                rtp: session.rtp.as_ref().map(|rtp| rtp.endpoint().clone()),
+                metadata: HashMap::new(),
+                tags: session.tags.clone(),
+                tenant: session.tenant.clone(),
+            }
+        };
+
+        if let Ok(peer) = self.webrtc.get_connection(session_id) {
+            let peer = peer.state();
+            status.webrtc_connected = Some(peer.is_connected());
+            status.rtp_packets_processed = peer.packets_processed;
+        }
+        match self.session_manager.get_session(session_id).await {
+            Ok(Some(data)) => {
+                status.user_id = data.user_id;
+                status.state = data.state;
+                status.last_activity_ms = data.last_activity.timestamp_millis();
+                status.metadata = data.metadata;
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to load session {}: {}", session_id, e),
+        }
+        if paused {
+            status.state = SessionState::Paused;
+        }
+        Some(status)
+    }
+
+    /// Get the journal of a session
+    ///
+    /// Falls back to the journal saved when the session ended, kept with
+    /// `flush_journal_on_end`.
+    pub async fn session_history(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<Option<SessionHistory>> {
+        let live = self
+            .sessions
+            .lock()
+            .get(session_id)
+            .map(|session| session.journal.clone());
+        let (journal, live) = match live {
+            Some(journal) => (journal, true),
+            None => match self.session_manager.journal(session_id).await? {
+                Some(journal) => (journal, false),
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(SessionHistory {
+            session_id: session_id.to_string(),
+            live,
+            journal,
+        }))
+    }
+
+    /// End a session, returns its `SessionEnded` event
+    ///
+    /// The event is also delivered to the session's media stream.
+    pub async fn end_session(&self, session_id: &str) -> anyhow::Result<MediaEvent> {
+        self.end_session_with_reason(session_id, EndReason::Completed)
+            .await
+    }
+
+    /// End a session for `reason`, returns its `SessionEnded` event
+    pub async fn end_session_with_reason(
+        &self,
+        session_id: &str,
+        reason: EndReason,
+    ) -> anyhow::Result<MediaEvent> {
+        let mut session = self
+            .sessions
+            .lock()
+            .remove(session_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown session: {}", session_id))?;
+
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let (
+            rtp_packets_processed,
+            packet_loss_ratio,
+            jitter_buffer_level_percent,
+            packets_evicted,
+            peer_codec,
+        ) = self
+            .webrtc
+            .get_connection(session_id)
+            .map(|peer| {
+                let peer = peer.state();
+                let buffer = &peer.buffer;
+                (
+                    peer.packets_processed,
+                    buffer.packet_loss_ratio,
+                    buffer.level_percent,
+                    buffer.packets_evicted,
+                    peer.codec,
+                )
+            })
+            .unwrap_or_default();
+        if rtp_packets_processed > 0 {
+            session.debug_line(|| {
+                format!(
+                    "Jitter buffer: {} packets, {:.1}% lost, {} evicted, {:.0}% full",
+                    rtp_packets_processed,
+                    packet_loss_ratio * 100.0,
+                    packets_evicted,
+                    jitter_buffer_level_percent
+                )
+            });
+        }
+        session.journal.record(
+            now_ms,
+            JournalEvent::Quality {
+                frames_processed: session.pipeline.frames_processed(),
+                rtp_packets_processed,
+                packet_loss_ratio,
+                jitter_buffer_level_percent,
+            },
+        );
+        if self.config.sessions.flush_journal_on_end {
+            if let Err(e) = self
+                .session_manager
+                .save_journal(session_id, &session.journal)
+                .await
+            {
+                tracing::warn!(
+                    "Failed to save the journal of session {}: {}",
+                    session_id,
+                    e
+                );
+            }
+        }
+
+        let event = MediaEvent::SessionEnded {
+            session_id: session_id.to_string(),
+            duration_ms: now_ms - session.created_at_ms,
+            total_frames: session.pipeline.frames_processed() as u32,
+            reason,
+        };
+        let anomalous = reason != EndReason::Completed
+            || session.usage.is_degraded()
+            || !session.usage.losses().is_empty()
+            || packets_evicted > 0
+            || packet_loss_ratio > ANOMALOUS_PACKET_LOSS_RATIO;
+        self.sinks.emit(SinkEvent::SessionEnded {
+            session_id: session_id.to_string(),
+            timestamp_ms: now_ms,
+            duration_ms: now_ms - session.created_at_ms,
+            total_frames: session.pipeline.frames_processed() as u32,
+            reason,
+            debug_log: if anomalous {
+                session.debug_log.lines()
+            } else {
+                Vec::new()
+            },
+            tenant: session.tenant.clone(),
+        });
+        if !self.cdrs.is_empty() {
+            let user_id = match self.session_manager.get_session(session_id).await {
+                Ok(data) => data.and_then(|data| data.user_id),
+                Err(e) => {
+                    tracing::warn!("Failed to load session {}: {}", session_id, e);
+                    None
+                }
+            };
+            let rtp = session.rtp.as_ref();
+            let codec = rtp
+                .map(|rtp| rtp.endpoint().codec)
+                .or(peer_codec)
+                .unwrap_or(session.codec.as_str());
+            self.cdrs
+                .emit(SinkEvent::CallDetailRecord(CallDetailRecord {
+                    session_id: session_id.to_string(),
+                    user_id,
+                    tenant: session.tenant.clone(),
+                    started_at_ms: session.created_at_ms,
+                    ended_at_ms: now_ms,
+                    duration_ms: now_ms - session.created_at_ms,
+                    codec: codec.to_string(),
+                    sample_rate: session.sample_rate,
+                    frames: session.pipeline.frames_processed(),
+                    rtp_packets: rtp.map_or(rtp_packets_processed, RtpIngest::packets_received),
+                    packet_loss_ratio,
+                    turns: session.tally.turns(),
+                    barge_ins: session.tally.barge_ins(),
+                    mean_vad_probability: session.tally.mean_vad_probability(),
+                    reason,
+                }));
+        }
+        self.release_tenant_session(session.tenant.as_deref());
+        if let Some(sender) = &session.events {
+            self.send_final_event(sender, event.clone(), reason.name());
+        }
+        self.record_losses(&session.usage, packets_evicted, reason.name());
+        self.store_recording(session.recorder.take());
+
+        self.webrtc.remove_connection(session_id);
+        self.session_manager.end_session(session_id).await?;
+        Ok(event)
+    }
+
+    /// Handle a signaling message of a caller of `tenant`, returns the
+    /// replies for the client
+    ///
+    /// An offer opens the session and its peer connection if needed, and is
+    /// answered with the SDP answer followed by the server's candidates.
+    pub async fn handle_signal(
+        &self,
+        message: SignalMessage,
+        tenant: Option<&str>,
+    ) -> anyhow::Result<Vec<SignalMessage>> {
+        let session_id = message.session_id.as_str();
+        if self.hidden_from(session_id, tenant) {
+            return Err(AmwajError::SessionNotFound(session_id.to_string()).into());
+        }
+        match message.signal {
+            Signal::Offer(sdp) => {
+                self.ensure_session(session_id, tenant).await?;
+                let peer = self.webrtc.get_or_create_connection(session_id);
+                peer.set_remote_sdp(sdp).await?;
+                let answer = peer.create_answer().await?;
+                // Connectivity checks start with the answer, and start over
+                // when a dropped connection offers again
+                let change = peer.start_checks().await?;
+                self.report_state_change(session_id, change);
+
+                let mut gatherer = IceGatherer::new(
+                    self.config.webrtc.stun_servers.clone(),
+                    self.config.webrtc.turn_servers.clone(),
+                );
+                let mut replies = vec![SignalMessage::new(session_id, Signal::Answer(answer))];
+                replies.extend(
+                    gatherer.gather().await?.into_iter().map(|candidate| {
+                        SignalMessage::new(session_id, Signal::Candidate(candidate))
+                    }),
+                );
+                replies.push(SignalMessage::new(session_id, Signal::EndOfCandidates));
+                Ok(replies)
+            }
+            Signal::Candidate(candidate) => {
+                self.webrtc
+                    .get_connection(session_id)?
+                    .add_ice_candidate(candidate)
+                    .await?;
+                Ok(Vec::new())
+            }
+            Signal::EndOfCandidates => {
+                self.webrtc.get_connection(session_id)?;
+                Ok(Vec::new())
+            }
+            Signal::Answer(_) => Err(anyhow::anyhow!("The server only answers offers")),
+        }
+    }
+
+    /// Take the state of the ICE transport of a session's connection
+    pub async fn set_ice_state(&self, session_id: &str, ice: IceState) -> anyhow::Result<()> {
+        let change = self
+            .webrtc
+            .get_connection(session_id)?
+            .set_ice_state(ice)
+            .await?;
+        self.report_state_change(session_id, change);
+        Ok(())
+    }
+
+    /// Get the getStats-shaped statistics of a session's connection
+    pub async fn webrtc_stats(&self, session_id: &str) -> anyhow::Result<RtcStatsReport> {
+        Ok(self.webrtc.get_connection(session_id)?.rtc_stats().await?)
+    }
+
+    /// Take the state of the DTLS transport of a session's connection
+    pub async fn set_dtls_state(&self, session_id: &str, dtls: DtlsState) -> anyhow::Result<()> {
+        let change = self
+            .webrtc
+            .get_connection(session_id)?
+            .set_dtls_state(dtls)
+            .await?;
+        self.report_state_change(session_id, change);
+        Ok(())
+    }
+
+    /// Tell the media stream of a session its connection changed state
+    fn report_state_change(&self, session_id: &str, change: Option<StateChange>) {
+        let Some(change) = change else {
+            return;
+        };
+        tracing::debug!(
+            "Connection of session {} went from {} to {}",
+            session_id,
+            change.previous.as_str(),
+            change.state.as_str()
+        );
+        if let Some(session) = self.sessions.lock().get_mut(session_id) {
+            match change.state {
+                ConnectionState::Disconnected | ConnectionState::Failed => {
+                    session.disconnected_at.get_or_insert_with(Instant::now);
+                }
+                ConnectionState::Connected if session.disconnected_at.take().is_some() => {
+                    tracing::info!("Connection of session {} restored", session_id);
+                    self.metrics.record_reconnection(true);
+                }
+                _ => {}
+            }
+        }
+        let event = MediaEvent::ConnectionStateChanged {
+            session_id: session_id.to_string(),
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+            state: change.state,
+            previous: change.previous,
+            previous_duration_ms: change.previous_duration.as_millis() as u64,
+        };
+        if let Err(e) = self.emit_event(session_id, event) {
+            tracing::debug!("Dropping connection state of session {}: {}", session_id, e);
+        }
+    }
+
+    /// Handle signaling messages until the stream closes
+    async fn run_signal(
+        &self,
+        mut inbound: Streaming<proto::SignalMessage>,
+        sender: SignalSender,
+        tenant: Option<String>,
+    ) {
+        loop {
+            let next = tokio::select! {
+                next = inbound.message() => next,
+                _ = self.wait_closed() => break,
+            };
+            let message = match next {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(status) => {
+                    tracing::warn!("Signal stream error: {}", status);
+                    break;
+                }
+            };
+            self.metrics.grpc_messages_received.inc();
+
+            // Like the media stream, a bad message is logged and skipped
+            let replies = match SignalMessage::try_from(message) {
+                Ok(message) => self.handle_signal(message, tenant.as_deref()).await,
+                Err(e) => Err(e),
+            };
+            match replies {
+                Ok(replies) => {
+                    for reply in replies {
+                        if sender.send(Ok(reply.into())).await.is_err() {
+                            return;
+                        }
+                        self.metrics.grpc_messages_sent.inc();
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to handle signal: {}", e),
+            }
+        }
+    }
+
+    /// Apply an orchestration command to its session
+    ///
+    /// Commands take effect immediately, except `PlayAudio` which is only
+    /// `Completed` once relayed to a playback stream.
+    pub fn apply_command(&self, command: &OrchestrationCommand) -> anyhow::Result<CommandStatus> {
+        let mut sessions = self.sessions.lock();
+        let session = session_mut(&mut sessions, command.session_id())?;
+        session.last_activity = Instant::now();
+        let result = self.run_command(session, command);
+        let (status, reason) = match &result {
+            Ok(status) => (*status, None),
+            Err(e) => (CommandStatus::Rejected, Some(e.to_string())),
+        };
+        session.debug_line(|| match &reason {
+            Some(reason) => format!("Command {} {:?}: {}", command.name(), status, reason),
+            None => format!("Command {} {:?}", command.name(), status),
+        });
+        session.journal.record(
+            chrono::Utc::now().timestamp_millis(),
+            JournalEvent::Command {
+                command: command.name().to_string(),
+                status,
+                reason,
+            },
+        );
+        let status = result?;
+        self.check_limits(command.session_id(), session)?;
+        Ok(status)
+    }
+
+    /// Degrade a session over its resource ceilings
+    ///
+    /// Fails with `AmwajError::ResourceLimit` when sessions over a ceiling
+    /// are terminated instead; the caller ends the session.
+    fn check_limits(&self, session_id: &str, session: &mut StreamSession) -> anyhow::Result<()> {
+        let limits = &self.config.sessions.resource_limits;
+        session.refresh_buffered_bytes();
+        let Some((metric, reason)) = session.usage.exceeded(limits) else {
+            return Ok(());
+        };
+        if !session.usage.is_degraded() {
+            session.debug_line(|| format!("Over the {} ceiling: {}", metric.name(), reason));
+        }
+        match limits.on_exceeded {
+            LimitAction::Terminate => Err(AmwajError::ResourceLimit(format!(
+                "session {} uses {}",
+                session_id, reason
+            ))
+            .into()),
+            LimitAction::Degrade => {
+                if !session.usage.is_degraded() {
+                    tracing::warn!(
+                        "Degrading session {} over its {} ceiling: {}",
+                        session_id,
+                        metric.name(),
+                        reason
+                    );
+                    session.usage.set_degraded();
+                    session.pipeline.degrade();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// End a session that exceeded a resource ceiling, returns the error
+    async fn enforce_limit(&self, session_id: &str, error: anyhow::Error) -> anyhow::Error {
+        if let Some(AmwajError::ResourceLimit(_)) = error.downcast_ref::<AmwajError>() {
+            tracing::warn!("Ending session {}: {}", session_id, error);
+            if let Err(e) = self
+                .end_session_with_reason(session_id, EndReason::ResourceLimit)
+                .await
+            {
+                tracing::warn!("Failed to end session {}: {}", session_id, e);
+            }
+        }
+        error
+    }
+
+    /// Apply a transcript of the external recognizer to its session
+    ///
+    /// It updates the turn detector like an `UpdateTranscript` command and
+    /// goes out to the media stream as a `PartialTranscript` event.
+    pub fn apply_transcript(
+        &self,
+        session_id: &str,
+        transcript: &Transcript,
+    ) -> anyhow::Result<()> {
+        self.apply_command(&OrchestrationCommand::UpdateTranscript {
+            session_id: session_id.to_string(),
+            text: transcript.text.clone(),
+            is_final: transcript.is_final,
+        })?;
+        let event = MediaEvent::PartialTranscript {
+            session_id: session_id.to_string(),
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+            text: transcript.text.clone(),
+            confidence: transcript.confidence,
+            is_final: transcript.is_final,
+        };
+        self.emit_event(session_id, event)
+    }
+
+    /// Send an event to the media stream of a session, when it has one
+    /// that subscribed to the event
+    fn emit_event(&self, session_id: &str, event: MediaEvent) -> anyhow::Result<()> {
+        let mut sessions = self.sessions.lock();
+        let session = session_mut(&mut sessions, session_id)?;
+        let Some(sender) = &session.events else {
+            return Ok(());
+        };
+        if !session.filter.allows(&event) {
+            return Ok(());
+        }
+        let message = proto::MediaEvent::from(event);
+        let size = message.encoded_len();
+        match sender.try_send(Ok(message)) {
+            Ok(()) => {
+                session.usage.record_sent(size);
+                self.metrics.grpc_messages_sent.inc();
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                session.usage.record_loss(LOSS_MEDIA_EVENT, 1)
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                session.usage.record_loss(LOSS_CHANNEL_SEND, 1)
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the sessions using the most of a resource, heaviest first
+    pub fn heaviest_sessions(&self, metric: UsageMetric, limit: usize) -> Vec<SessionResources> {
+        let mut sessions = self.sessions.lock();
+        let mut usage: Vec<(f64, SessionResources)> = sessions
+            .iter_mut()
+            .map(|(session_id, session)| {
+                session.refresh_buffered_bytes();
+                (session.usage.value(metric), session.resources(session_id))
+            })
+            .collect();
+        usage.sort_by(|a, b| b.0.total_cmp(&a.0));
+        usage
+            .into_iter()
+            .take(limit)
+            .map(|(_, resources)| resources)
+            .collect()
+    }
+
+    /// Apply a command, recording pauses in the session store
+    async fn handle_command(
+        &self,
+        command: &OrchestrationCommand,
+    ) -> anyhow::Result<CommandStatus> {
+        let status = match self.apply_command(command) {
+            Ok(status) => status,
+            Err(e) => return Err(self.enforce_limit(command.session_id(), e).await),
+        };
+        let state = match command {
+            OrchestrationCommand::Pause { .. } => SessionState::Paused,
+            OrchestrationCommand::Resume { .. } => SessionState::Active,
+            _ => return Ok(status),
+        };
+        if let Err(e) = self
+            .session_manager
+            .update_state(command.session_id(), state)
+            .await
+        {
+            tracing::warn!(
+                "Failed to record the state of session {}: {}",
+                command.session_id(),
+                e
+            );
+        }
+        Ok(status)
+    }
+
+    fn run_command(
+        &self,
+        session: &mut StreamSession,
+        command: &OrchestrationCommand,
+    ) -> anyhow::Result<CommandStatus> {
+        if session.debug {
+            match command {
+                // Don't dump the audio into the logs
+                OrchestrationCommand::PlayAudio {
+                    session_id,
+                    audio_data,
+                    audio_format,
+                } => tracing::info!(
+                    "Session {} command: PlayAudio of {} {} bytes",
+                    session_id,
+                    audio_data.len(),
+                    audio_format
+                ),
+                command => {
+                    tracing::info!("Session {} command: {:?}", command.session_id(), command)
+                }
+            }
+        }
+        if let OrchestrationCommand::PlayAudio { audio_data, .. } = command {
+            let limit = self.config.grpc.max_play_audio_bytes;
+            if audio_data.len() > limit {
+                return Err(anyhow::anyhow!(
+                    "PlayAudio payload of {} bytes exceeds the {} byte limit",
+                    audio_data.len(),
+                    limit
+                ));
+            }
+            let limits = &self.config.sessions.resource_limits;
+            if let Some(max) = limits.max_buffered_bytes {
+                session.refresh_buffered_bytes();
+                let buffered = session.usage.buffered_bytes() + audio_data.len();
+                if limits.on_exceeded == LimitAction::Degrade && buffered > max {
+                    return Err(anyhow::anyhow!(
+                        "PlayAudio would buffer {} bytes of audio, over the {} byte limit",
+                        buffered,
+                        max
+                    ));
+                }
+            }
+        }
+        if let OrchestrationCommand::SetTags { tags, remove, .. } = command {
+            let mut updated = session.tags.clone();
+            updated.extend(tags.clone());
+            for key in remove {
+                updated.remove(key);
+            }
+            check_tags(&updated, self.config.sessions.max_tags)?;
+            session.tags = updated;
+        }
+        session.pipeline.apply_command(command)?;
+
+        if let OrchestrationCommand::PlayAudio {
+            session_id,
+            audio_data,
+            audio_format,
+        } = command
+        {
+            let chunk = AudioChunk {
+                session_id: session_id.clone(),
+                sequence_number: session.playback_sequence,
+                timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                encoding: AudioEncoding::from_format(audio_format),
+                sample_rate: session.sample_rate,
+                channels: self.config.audio.channels,
+                data: audio_data.clone(),
+            };
+            if !session.forward_playback(chunk) {
+                return Ok(CommandStatus::Accepted);
+            }
+        }
+        Ok(CommandStatus::Completed)
+    }
+
+    /// End a session whose processing panicked, in the background
+    fn isolate_panic(&self, session_id: &str, payload: &(dyn Any + Send)) -> AmwajError {
+        let message = runtime::panic_message(payload);
+        tracing::error!("Session {} panicked, ending it: {}", session_id, message);
+        self.metrics.record_panic(TASK_SESSION);
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let service = self.clone();
+            let session_id = session_id.to_string();
+            handle.spawn(async move {
+                let ended = service
+                    .end_session_with_reason(&session_id, EndReason::InternalError)
+                    .await;
+                if let Err(e) = ended {
+                    tracing::warn!("Failed to end session {}: {}", session_id, e);
+                }
+            });
+        }
+        AmwajError::Internal(format!("Session {} panicked: {}", session_id, message))
+    }
+
+    /// Run a PCM frame through a session's pipeline
+    ///
+    /// The resulting events are forwarded to the session's media stream, if
+    /// one is attached, and returned. Fails with `AmwajError::ResourceLimit`
+    /// once a session to be terminated exceeds a resource ceiling.
+    pub fn push_audio(
+        &self,
+        session_id: &str,
+        pcm_data: &[i16],
+    ) -> anyhow::Result<Vec<MediaEvent>> {
+        // The WebRTC lock is never held with the sessions lock
+        let network_ms = if self.config.metrics.latency_report_interval_ms > 0 {
+            self.jitter_buffer_delay_ms(session_id)
+        } else {
+            0.0
+        };
+        let mut sessions = self.sessions.lock();
+        let session = session_mut(&mut sessions, session_id)?;
+        if session.failed {
+            return Err(AmwajError::Internal(format!("Session {} failed", session_id)).into());
+        }
+        session.last_activity = Instant::now();
+        session.last_media = Instant::now();
+        let state = session.pipeline.detector().state();
+        let frame = session.pipeline.frames_processed();
+        let context = ErrorContext::session(session_id).with_frame(
+            frame,
+            frame as i64 * self.config.audio.frame_duration_ms as i64,
+        );
+        let started = Instant::now();
+        // A panic leaves the pipeline in an unknown state, it ends the
+        // session but no other
+        let processed = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            session.pipeline.process_frame(pcm_data)
+        }));
+        let mut events = match processed {
+            Ok(Ok(events)) => events,
+            Ok(Err(e)) => {
+                self.metrics.audio_frame_errors.inc();
+                let error = match e.downcast::<AmwajError>() {
+                    Ok(error) => error,
+                    Err(e) => AmwajError::AudioError(format!("{:#}", e)),
+                };
+                return Err(error.with_context(context).into());
+            }
+            Err(payload) => {
+                self.metrics.audio_frame_errors.inc();
+                session.failed = true;
+                drop(sessions);
+                let error = self.isolate_panic(session_id, &*payload);
+                return Err(error.with_context(context).into());
+            }
+        };
+        self.metrics.audio_frames_processed.inc();
+        let busy = started.elapsed();
+        let new_state = session.pipeline.detector().state();
+        if !session.pipeline.is_paused() {
+            let vad_probability = session.pipeline.last_vad_probability();
+            session.tally.record_frame(vad_probability);
+        }
+        if new_state != state {
+            let frame = session.pipeline.frames_processed();
+            session.debug_line(|| format!("Frame {}: {:?} -> {:?}", frame, state, new_state));
+        }
+        if session.recorder.is_some() {
+            let media_ms = session.pipeline.frames_processed() as i64
+                * self.config.audio.frame_duration_ms as i64;
+            if !session.pipeline.is_paused() {
+                let vad_probability = session.pipeline.last_vad_probability();
+                session.trace(|| TraceRecord::Frame {
+                    media_ms,
+                    vad_probability,
+                    state: new_state,
+                });
+            }
+            if new_state != state {
+                session.trace(|| TraceRecord::Transition {
+                    media_ms,
+                    from: state,
+                    to: new_state,
+                });
+            }
+            for event in &events {
+                if !matches!(event, MediaEvent::AudioFrame { .. }) {
+                    session.trace(|| TraceRecord::Event {
+                        media_ms,
+                        name: event.name().to_string(),
+                        detail: event.journal_event().map(|(_, detail)| detail),
+                    });
+                }
+            }
+        }
+        self.load.record_busy(busy);
+        let audio_ms = pcm_data.len() as u64 * 1000 / session.sample_rate.max(1) as u64;
+        session
+            .usage
+            .record_frame(busy, audio_ms as u32, std::mem::size_of_val(pcm_data));
+        // A session over its ceilings is degraded before this frame goes out
+        self.check_limits(session_id, session)?;
+        if session.latency.is_enabled() {
+            let latency = LatencyBreakdown {
+                network_ms,
+                queueing_ms: session.events.as_ref().map_or(0.0, |sender| {
+                    let queued = sender.max_capacity() - sender.capacity();
+                    queued as f64 * self.config.audio.frame_duration_ms as f64
+                }),
+                ..session.pipeline.last_latency()
+            };
+            if let Some((latency, frames)) = session.latency.record_frame(&latency, audio_ms) {
+                events.push(MediaEvent::LatencyReport {
+                    session_id: session_id.to_string(),
+                    timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                    frames: frames as u32,
+                    latency,
+                });
+            }
+        }
+        if let Some(asr) = &mut session.asr {
+            let dropped = asr.push(pcm_data, &events);
+            session.usage.record_loss(LOSS_ASR_AUDIO, dropped);
+        }
+        if session.debug {
+            tracing::info!(
+                "Session {} frame {}: {:?}, {} events",
+                session_id,
+                session.pipeline.frames_processed(),
+                session.pipeline.detector().state(),
+                events.len()
+            );
+        }
+
+        if let Some(sender) = &session.events {
+            let degraded = session.usage.is_degraded();
+            let mut allowed = events.iter().filter(|event| {
+                session.filter.allows(event)
+                    && !(degraded && matches!(event, MediaEvent::AudioFrame { .. }))
+            });
+            while let Some(event) = allowed.next() {
+                let message = proto::MediaEvent::from(event.clone());
+                let size = message.encoded_len();
+                // Never block the media path on a slow consumer
+                if let Err(e) = sender.try_send(Ok(message)) {
+                    let kind = match e {
+                        mpsc::error::TrySendError::Full(_) => {
+                            tracing::warn!(
+                                "Dropping event for session {}, stream is full",
+                                session_id
+                            );
+                            LOSS_MEDIA_EVENT
+                        }
+                        mpsc::error::TrySendError::Closed(_) => LOSS_CHANNEL_SEND,
+                    };
+                    // The rest of the frame's events are dropped with it
+                    let dropped = 1 + allowed.count() as u64;
+                    session.usage.record_loss(kind, dropped);
+                    if session.debug_log.is_enabled() {
+                        session.debug_log.record(
+                            chrono::Utc::now().timestamp_millis(),
+                            format!("Dropped {} events: {}", dropped, kind),
+                        );
+                    }
+                    break;
+                }
+                session.usage.record_sent(size);
+                self.metrics.grpc_messages_sent.inc();
+            }
+        }
+        for event in &events {
+            match event {
+                MediaEvent::TurnEnded { .. } => session.tally.record_turn(),
+                MediaEvent::BargeIn { .. } => session.tally.record_barge_in(),
+                _ => {}
+            }
+            if let Some((timestamp_ms, entry)) = event.journal_event() {
+                if session.debug_log.is_enabled() {
+                    session
+                        .debug_log
+                        .record(timestamp_ms, format!("{:?}", entry));
+                }
+                session.journal.record(timestamp_ms, entry);
+            }
+            if let MediaEvent::TurnEnded {
+                timestamp_ms,
+                duration_ms,
+                ..
+            } = event
+            {
+                self.sinks.emit(SinkEvent::TurnEnded {
+                    session_id: session_id.to_string(),
+                    timestamp_ms: *timestamp_ms,
+                    duration_ms: *duration_ms,
+                    tenant: session.tenant.clone(),
+                });
+            }
+        }
+        Ok(events)
+    }
+
+    /// Estimate how long a frame waits in the session's jitter buffer, in ms
+    fn jitter_buffer_delay_ms(&self, session_id: &str) -> f64 {
+        match self.webrtc.get_connection(session_id) {
+            Ok(peer) => {
+                peer.state().buffer.size as f64 * self.config.audio.frame_duration_ms as f64
+            }
+            Err(_) => 0.0,
+        }
+    }
+
+    /// Charge the time spent decoding a session's audio to its next frames
+    fn record_decode_latency(&self, session_id: &str, decode_ms: f64) {
+        if let Some(session) = self.sessions.lock().get_mut(session_id) {
+            session.latency.add_decode(decode_ms);
+        }
+    }
+
+    /// Check if the service is draining for a shutdown
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Drain the service for a shutdown, returns the number of sessions force-closed
+    ///
+    /// New sessions are refused and media streams receive a `ServerDraining`
+    /// event. Sessions still open after `timeout` are ended, then every
+    /// stream is closed. With `migrate_on_drain`, sessions are handed off
+    /// first: parked for other instances, their streams get the
+    /// reconnection state with `ServerDraining`.
+    pub async fn drain(&self, timeout: Duration) -> usize {
+        self.draining.store(true, Ordering::SeqCst);
+        // Take the instance out of placement without waiting for the reporter
+        if let Err(e) = self.session_manager.publish_load(&self.load_report()).await {
+            tracing::warn!("Failed to publish the load report: {}", e);
+        }
+        let timestamp_ms = chrono::Utc::now().timestamp_millis();
+        let deadline_ms = timestamp_ms + timeout.as_millis() as i64;
+        if self.config.sessions.migrate_on_drain {
+            let session_ids: Vec<String> = self.sessions.lock().keys().cloned().collect();
+            for session_id in session_ids {
+                if let Err(e) = self.hand_off_session(&session_id, deadline_ms).await {
+                    tracing::warn!("Failed to hand off session {}: {}", session_id, e);
+                }
+            }
+        }
+
+        let streams: Vec<(String, EventSender)> = self
+            .sessions
+            .lock()
+            .iter()
+            .filter_map(|(session_id, session)| Some((session_id.clone(), session.events.clone()?)))
+            .collect();
+        for (session_id, sender) in streams {
+            let event = MediaEvent::ServerDraining {
+                session_id,
+                timestamp_ms,
+                deadline_ms,
+                handoff: None,
+            };
+            if sender.try_send(Ok(event.into())).is_ok() {
+                self.metrics.grpc_messages_sent.inc();
+            }
+        }
+
+        let started = Instant::now();
+        while self.session_count() > 0 && started.elapsed() < timeout {
+            let remaining = timeout.saturating_sub(started.elapsed());
+            tokio::time::sleep(remaining.min(Duration::from_millis(50))).await;
+        }
+
+        let remaining: Vec<String> = self.sessions.lock().keys().cloned().collect();
+        for session_id in &remaining {
+            tracing::warn!("Force-closing session {} after drain timeout", session_id);
+            if let Err(e) = self
+                .end_session_with_reason(session_id, EndReason::Drained)
+                .await
+            {
+                tracing::warn!("Failed to end session {}: {}", session_id, e);
+            }
+        }
+        self.closed.send_replace(true);
+        remaining.len()
+    }
+
+    /// Wait until a drain closes the open streams
+    async fn wait_closed(&self) {
+        let _ = self.closed.subscribe().wait_for(|closed| *closed).await;
+    }
+
+    /// Map a failure to open a session to a gRPC status
+    fn session_error(&self, error: anyhow::Error) -> Status {
+        if self.is_draining() {
+            Status::unavailable(error.to_string())
+        } else {
+            Status::internal(error.to_string())
+        }
+    }
+
+    /// End sessions idle for at least `idle_timeout`, returns their IDs
+    ///
+    /// Frees the resources of sessions whose client crashed or went away
+    /// without calling `EndSession`.
+    pub async fn reap_idle_sessions(&self, idle_timeout: Duration) -> Vec<String> {
+        let idle: Vec<String> = self
+            .sessions
+            .lock()
+            .iter()
+            .filter(|(_, session)| {
+                session.last_activity.elapsed() >= idle_timeout && !self.reconnecting(session)
+            })
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+
+        for session_id in &idle {
+            tracing::info!("Ending idle session {}", session_id);
+            if let Err(e) = self
+                .end_session_with_reason(session_id, EndReason::Idle)
+                .await
+            {
+                tracing::warn!("Failed to end idle session {}: {}", session_id, e);
+            }
+        }
+        idle
+    }
+
+    /// End sessions that received no audio for at least `timeout`, returns
+    /// their IDs
+    ///
+    /// A peer connection can stay up while no RTP flows, these sessions
+    /// are moved to `Terminating` and end with `EndReason::NoMedia`.
+    pub async fn reap_silent_sessions(&self, timeout: Duration) -> Vec<String> {
+        let silent: Vec<String> = self
+            .sessions
+            .lock()
+            .iter()
+            .filter(|(_, session)| {
+                session.last_media.elapsed() >= timeout && !self.reconnecting(session)
+            })
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+
+        for session_id in &silent {
+            tracing::warn!("Ending session {}, no media for {:?}", session_id, timeout);
+            if let Err(e) = self
+                .session_manager
+                .update_state(session_id, SessionState::Terminating)
+                .await
+            {
+                tracing::warn!("Failed to update session {}: {}", session_id, e);
+            }
+            if let Err(e) = self
+                .end_session_with_reason(session_id, EndReason::NoMedia)
+                .await
+            {
+                tracing::warn!("Failed to end session {}: {}", session_id, e);
+            }
+        }
+        silent
+    }
+
+    /// Whether a session is waiting for its dropped connection to be
+    /// restored, which keeps it from being reaped as idle or silent
+    fn reconnecting(&self, session: &StreamSession) -> bool {
+        let window = Duration::from_millis(self.config.webrtc.reconnect_window_ms);
+        session
+            .disconnected_at
+            .is_some_and(|at| at.elapsed() < window)
+    }
+
+    /// End sessions whose WebRTC connection stayed disconnected or failed
+    /// for at least `window`, returns their IDs
+    pub async fn reap_disconnected_sessions(&self, window: Duration) -> Vec<String> {
+        let lost: Vec<String> = self
+            .sessions
+            .lock()
+            .iter()
+            .filter(|(_, session)| {
+                session
+                    .disconnected_at
+                    .is_some_and(|at| at.elapsed() >= window)
+            })
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+
+        for session_id in &lost {
+            tracing::warn!(
+                "Ending session {}, connection not restored within {:?}",
+                session_id,
+                window
+            );
+            self.metrics.record_reconnection(false);
+            if let Err(e) = self
+                .end_session_with_reason(session_id, EndReason::ConnectionLost)
+                .await
+            {
+                tracing::warn!("Failed to end session {}: {}", session_id, e);
+            }
+        }
+        lost
+    }
+
+    /// Reap sessions whose connection wasn't restored in the background
+    pub fn spawn_reconnect_watchdog(&self, window: Duration) -> tokio::task::JoinHandle<()> {
+        let service = self.clone();
+        let period = (window / 2).max(Duration::from_millis(10));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                service.reap_disconnected_sessions(window).await;
+            }
+        })
+    }
+
+    /// Reap sessions without media in the background
+    pub fn spawn_media_watchdog(&self, timeout: Duration) -> tokio::task::JoinHandle<()> {
+        let service = self.clone();
+        let period = (timeout / 2).max(Duration::from_millis(10));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                service.reap_silent_sessions(timeout).await;
+            }
+        })
+    }
+
+    /// Reap idle sessions in the background
+    pub fn spawn_idle_reaper(&self, idle_timeout: Duration) -> tokio::task::JoinHandle<()> {
+        let service = self.clone();
+        let period = (idle_timeout / 2).max(Duration::from_millis(10));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                service.reap_idle_sessions(idle_timeout).await;
+            }
+        })
+    }
+
+    /// Sweep expired sessions from the session store, returns how many
+    ///
+    /// Sessions open on this instance are refreshed first, so only those
+    /// abandoned here or on other instances expire.
+    pub async fn sweep_expired_sessions(&self) -> usize {
+        let open: Vec<String> = self.sessions.lock().keys().cloned().collect();
+        for session_id in &open {
+            if let Err(e) = self.session_manager.touch_session(session_id).await {
+                tracing::warn!("Failed to refresh session {}: {}", session_id, e);
+            }
+        }
+        match self.session_manager.cleanup_expired().await {
+            Ok(expired) => {
+                if expired > 0 {
+                    tracing::info!("Removed {} expired sessions", expired);
+                }
+                expired
+            }
+            Err(e) => {
+                tracing::warn!("Failed to remove expired sessions: {}", e);
+                0
+            }
+        }
+    }
+
+    /// Sweep expired sessions in the background, at the configured
+    /// `cleanup_interval_seconds`
+    pub fn spawn_session_cleanup(&self) -> Option<tokio::task::JoinHandle<()>> {
+        let service = self.clone();
+        let period = self.session_manager.cleanup_interval()?;
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                service.sweep_expired_sessions().await;
+            }
+        }))
+    }
+
+    /// Renew the leases on this instance's sessions in the background
+    ///
+    /// Sessions whose lease was taken over by another instance are ended.
+    pub fn spawn_lease_heartbeat(&self) -> tokio::task::JoinHandle<()> {
+        let service = self.clone();
+        let period = self.session_manager.lease_renewal_interval();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                for session_id in service.session_manager.renew_leases().await {
+                    if service.sessions.lock().contains_key(&session_id) {
+                        tracing::warn!("Ending session {}, owned elsewhere", session_id);
+                        let _ = service
+                            .end_session_with_reason(&session_id, EndReason::LeaseLost)
+                            .await;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Get the load of this instance since the last report
+    pub fn load_report(&self) -> LoadReport {
+        let (audio_thread_utilization, cpu_headroom) = self.load.sample();
+        self.metrics
+            .record_audio_load(audio_thread_utilization, cpu_headroom);
+        let config = self.session_manager.config();
+        LoadReport {
+            instance_id: self.session_manager.instance_id().to_string(),
+            address: config.advertise_address.clone(),
+            active_sessions: self.session_count(),
+            max_sessions: config.max_sessions,
+            cpu_headroom,
+            audio_thread_utilization,
+            draining: self.is_draining(),
+            reported_at_ms: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Publish the load of this instance to the session store in the
+    /// background, for least-loaded placement
+    pub fn spawn_load_reporter(&self) -> tokio::task::JoinHandle<()> {
+        let service = self.clone();
+        let period = self.session_manager.load_report_interval();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let report = service.load_report();
+                if let Err(e) = service.session_manager.publish_load(&report).await {
+                    tracing::warn!("Failed to publish the load report: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Get the status of a failed call
+    ///
+    /// An `AmwajError` gives the status its code and retryability and is
+    /// counted in metrics, other errors get the `otherwise` status.
+    fn error_status(&self, error: &anyhow::Error, otherwise: fn(String) -> Status) -> Status {
+        match error.downcast_ref::<AmwajError>() {
+            Some(typed) => {
+                self.metrics.record_error(typed);
+                if let Some(context) = typed.context() {
+                    tracing::warn!(
+                        session_id = context.session_id.as_deref(),
+                        ssrc = context.ssrc,
+                        frame = context.frame,
+                        timestamp_ms = context.timestamp_ms,
+                        code = typed.code().as_str_name(),
+                        "{}",
+                        typed
+                    );
+                }
+                typed.to_status()
+            }
+            None => otherwise(error.to_string()),
+        }
+    }
+
+    /// Run a unary RPC under the configured deadline
+    async fn with_deadline<T>(
+        &self,
+        rpc: impl Future<Output = Result<T, Status>>,
+    ) -> Result<T, Status> {
+        let deadline = Duration::from_secs(self.config.grpc.timeout_secs);
+        tokio::time::timeout(deadline, rpc)
+            .await
+            .map_err(|_| Status::deadline_exceeded("Deadline exceeded"))?
+    }
+
+    /// Get the recent fusion breakdowns of a session
+    pub fn detection_debug(&self, session_id: &str) -> Option<Vec<FusionBreakdown>> {
+        self.sessions
+            .lock()
+            .get(session_id)
+            .map(|session| session.pipeline.detection_debug())
+    }
+
+    /// Subscribe to a session's playback audio, replacing any earlier subscriber
+    pub fn subscribe_playback(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<mpsc::Receiver<Result<proto::AudioChunk, Status>>> {
+        let (sender, receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        let mut sessions = self.sessions.lock();
+        session_mut(&mut sessions, session_id)?.playback = Some(sender);
+        Ok(receiver)
+    }
+
+    /// Feed ingested audio chunks through their session until the stream closes
+    async fn run_audio_in(
+        &self,
+        mut inbound: Streaming<proto::AudioChunk>,
+        tenant: Option<String>,
+    ) -> Result<proto::StreamAudioInSummary, Status> {
+        let mut ingest: Option<AudioIngest> = None;
+        let mut session_id = String::new();
+
+        loop {
+            let message = tokio::select! {
+                next = inbound.message() => match next? {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = self.wait_closed() => break,
+            };
+            self.metrics.grpc_messages_received.inc();
+            let chunk = AudioChunk::try_from(message)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+            if session_id.is_empty() {
+                session_id = chunk.session_id.clone();
+            } else if chunk.session_id != session_id {
+                return Err(Status::invalid_argument("Audio stream switched sessions"));
+            }
+
+            if ingest.is_none() {
+                self.ensure_session(&session_id, tenant.as_deref())
+                    .await
+                    .map_err(|e| self.session_error(e))?;
+                ingest = self.sessions.lock().get(&session_id).map(|session| {
+                    let frame_duration_ms = self.config.audio.frame_duration_ms;
+                    let frame_size = session.sample_rate * frame_duration_ms / 1000;
+                    AudioIngest::new(session.sample_rate, frame_size as usize)
+                        .with_metrics(Arc::clone(&self.metrics))
+                });
+            }
+            let Some(ingest) = ingest.as_mut() else {
+                return Err(Status::not_found(format!("Session ended: {}", session_id)));
+            };
+
+            let frame_span = self.frame_span(&session_id);
+            let frames = frame_span
+                .in_scope(|| ingest.push(&chunk))
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+            if self.config.metrics.latency_report_interval_ms > 0 {
+                self.record_decode_latency(&session_id, ingest.take_decode_ms());
+            }
+            for frame in frames {
+                let pushed = frame_span.in_scope(|| self.push_audio(&session_id, &frame));
+                if let Err(e) = pushed {
+                    let e = self.enforce_limit(&session_id, e).await;
+                    return Err(self.error_status(&e, Status::internal));
+                }
+            }
+        }
+
+        let stats = ingest.map(|ingest| ingest.stats()).unwrap_or_default();
+        Ok(proto::StreamAudioInSummary {
+            session_id,
+            chunks_received: stats.chunks_received,
+            frames_processed: stats.frames_produced,
+            chunks_missing: stats.chunks_missing,
+            chunks_dropped: stats.chunks_dropped,
+        })
+    }
+
+    /// Create the span tracing the session's next chunk, if it is sampled
+    fn frame_span(&self, session_id: &str) -> tracing::Span {
+        let mut sessions = self.sessions.lock();
+        match sessions.get_mut(session_id) {
+            Some(session) => session.trace_sampler.frame_span(&session.span),
+            None => tracing::Span::none(),
+        }
+    }
+
+    /// Deliver a session's events to a media stream of a caller of `tenant`
+    async fn attach_stream(
+        &self,
+        session_id: &str,
+        sender: &EventSender,
+        subscription: Subscription,
+        tenant: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.ensure_session(session_id, tenant).await?;
+        let mut sessions = self.sessions.lock();
+        let session = session_mut(&mut sessions, session_id)?;
+        let attached = session
+            .events
+            .as_ref()
+            .is_some_and(|events| events.same_channel(sender));
+        if !attached {
+            session.events = Some(sender.clone());
+            session.filter = EventFilter::new(subscription);
+        }
+        Ok(())
+    }
+
+    /// Check if any session delivers its events to a media stream
+    fn stream_has_sessions(&self, sender: &EventSender) -> bool {
+        self.sessions.lock().values().any(|session| {
+            session
+                .events
+                .as_ref()
+                .is_some_and(|events| events.same_channel(sender))
+        })
+    }
+
+    /// Stop delivering events to a closed media stream
+    fn detach_stream(&self, sender: &EventSender) {
+        for session in self.sessions.lock().values_mut() {
+            if session
+                .events
+                .as_ref()
+                .is_some_and(|events| events.same_channel(sender))
+            {
+                session.events = None;
+            }
+        }
+    }
+
+    /// Apply commands from a media stream until it closes
+    async fn run_stream(
+        &self,
+        mut inbound: Streaming<proto::OrchestrationCommand>,
+        sender: EventSender,
+        subscription: Subscription,
+        tenant: Option<String>,
+    ) {
+        let idle_timeout = self
+            .config
+            .grpc
+            .idle_stream_timeout_ms
+            .map(Duration::from_millis);
+
+        loop {
+            let receive = async {
+                match idle_timeout {
+                    Some(idle_timeout) => {
+                        tokio::time::timeout(idle_timeout, inbound.message()).await
+                    }
+                    None => Ok(inbound.message().await),
+                }
+            };
+            let next = tokio::select! {
+                next = receive => next,
+                _ = self.wait_closed() => break,
+            };
+            let message = match next {
+                Ok(Ok(Some(message))) => message,
+                Ok(Ok(None)) => break,
+                Ok(Err(status)) => {
+                    tracing::warn!("Media stream error: {}", status);
+                    break;
+                }
+                // Live sessions keep the stream open, they are reaped on their own
+                Err(_) if self.stream_has_sessions(&sender) => continue,
+                Err(_) => {
+                    let _ = sender
+                        .send(Err(Status::deadline_exceeded("Media stream idle")))
+                        .await;
+                    break;
+                }
+            };
+            self.metrics.grpc_messages_received.inc();
+            let session_id = message.session_id.clone();
+            let command_id = message.command_id.clone();
+
+            // A bad command is rejected and skipped, it must not tear down the stream
+            let result = match OrchestrationCommand::try_from(message) {
+                Ok(command) => match self
+                    .attach_stream(
+                        command.session_id(),
+                        &sender,
+                        subscription,
+                        tenant.as_deref(),
+                    )
+                    .await
+                {
+                    Ok(()) => self.handle_command(&command).await,
+                    // Sessions already on the stream keep running while draining
+                    Err(e) if self.is_draining() => Err(e),
+                    Err(e) => {
+                        let _ = sender.send(Err(Status::internal(e.to_string()))).await;
+                        break;
+                    }
+                },
+                Err(e) => Err(e),
+            };
+
+            let ack = match result {
+                Ok(status) => MediaEvent::command_ack(&session_id, &command_id, status, ""),
+                Err(e) => {
+                    tracing::warn!("Rejected command for session {}: {}", session_id, e);
+                    MediaEvent::command_ack(
+                        &session_id,
+                        &command_id,
+                        CommandStatus::Rejected,
+                        e.to_string(),
+                    )
+                }
+            };
+            // Commands without an ID are fire-and-forget
+            if !command_id.is_empty() {
+                if sender.send(Ok(ack.into())).await.is_err() {
+                    break;
+                }
+                self.metrics.grpc_messages_sent.inc();
+            }
+        }
+
+        self.detach_stream(&sender);
+    }
+}
+
+fn session_mut<'a>(
+    sessions: &'a mut HashMap<String, StreamSession>,
+    session_id: &str,
+) -> anyhow::Result<&'a mut StreamSession> {
+    sessions
+        .get_mut(session_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown session: {}", session_id))
+}
+
+#[tonic::async_trait]
+impl AmwajMediaServer for AmwajMediaService {
+    type MediaStreamStream = ReceiverStream<Result<proto::MediaEvent, Status>>;
+
+    async fn media_stream(
+        &self,
+        request: Request<Streaming<proto::OrchestrationCommand>>,
+    ) -> Result<Response<Self::MediaStreamStream>, Status> {
+        if let Some(status) = capabilities::negotiate(request.metadata()) {
+            return Err(status);
+        }
+        let subscription = Subscription::from_metadata(request.metadata())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let tenant = self.caller_tenant(&request);
+        let inbound = request.into_inner();
+        let (sender, receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+
+        let service = self.clone();
+        tokio::spawn(async move {
+            service.metrics.active_connections.inc();
+            let stream = {
+                let service = service.clone();
+                async move {
+                    let stream = service.run_stream(inbound, sender, subscription, tenant);
+                    runtime::poll_timed(&service.metrics, TASK_MEDIA_STREAM, stream).await
+                }
+            };
+            runtime::supervise(&service.metrics, TASK_MEDIA_STREAM, stream).await;
+            service.metrics.active_connections.dec();
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn server_capabilities(
+        &self,
+        _request: Request<proto::ServerCapabilitiesRequest>,
+    ) -> Result<Response<proto::Capabilities>, Status> {
+        Ok(Response::new(capabilities::server_capabilities(
+            &self.config,
+        )))
+    }
+
+    async fn get_detection_debug(
+        &self,
+        request: Request<proto::DetectionDebugRequest>,
+    ) -> Result<Response<proto::DetectionDebugResponse>, Status> {
+        let tenant = self.caller_tenant(&request);
+        let session_id = request.into_inner().session_id;
+        if let Some(status) = self.hidden_status(&session_id, tenant.as_deref()) {
+            return Err(status);
+        }
+        let frames = self
+            .detection_debug(&session_id)
+            .ok_or_else(|| Status::not_found(format!("Unknown session: {}", session_id)))?
+            .iter()
+            .map(|breakdown| convert::detection_debug(0, None, breakdown))
+            .collect();
+
+        Ok(Response::new(proto::DetectionDebugResponse {
+            session_id,
+            frames,
+        }))
+    }
+
+    async fn stream_audio_in(
+        &self,
+        request: Request<Streaming<proto::AudioChunk>>,
+    ) -> Result<Response<proto::StreamAudioInSummary>, Status> {
+        let service = self.clone();
+        let tenant = self.caller_tenant(&request);
+        let inbound = request.into_inner();
+        let audio_in = async move {
+            let audio_in = service.run_audio_in(inbound, tenant);
+            runtime::poll_timed(&service.metrics, TASK_AUDIO_IN, audio_in).await
+        };
+        let summary = runtime::supervise(&self.metrics, TASK_AUDIO_IN, audio_in)
+            .await
+            .ok_or_else(|| {
+                AmwajError::Internal("Audio stream task panicked".into()).to_status()
+            })??;
+        Ok(Response::new(summary))
+    }
+
+    type StreamAudioOutStream = ReceiverStream<Result<proto::AudioChunk, Status>>;
+
+    async fn stream_audio_out(
+        &self,
+        request: Request<proto::StreamAudioOutRequest>,
+    ) -> Result<Response<Self::StreamAudioOutStream>, Status> {
+        let tenant = self.caller_tenant(&request);
+        let session_id = request.into_inner().session_id;
+        self.ensure_session(&session_id, tenant.as_deref())
+            .await
+            .map_err(|e| self.session_error(e))?;
+        let receiver = self
+            .subscribe_playback(&session_id)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    type SignalStream = ReceiverStream<Result<proto::SignalMessage, Status>>;
+
+    async fn signal(
+        &self,
+        request: Request<Streaming<proto::SignalMessage>>,
+    ) -> Result<Response<Self::SignalStream>, Status> {
+        let tenant = self.caller_tenant(&request);
+        let inbound = request.into_inner();
+        let (sender, receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+
+        let service = self.clone();
+        tokio::spawn(async move {
+            let signal = {
+                let service = service.clone();
+                async move {
+                    let signal = service.run_signal(inbound, sender, tenant);
+                    runtime::poll_timed(&service.metrics, TASK_SIGNAL, signal).await
+                }
+            };
+            runtime::supervise(&service.metrics, TASK_SIGNAL, signal).await;
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn create_session(
+        &self,
+        request: Request<proto::CreateSessionRequest>,
+    ) -> Result<Response<proto::SessionInfo>, Status> {
+        if self.is_draining() {
+            return Err(Status::unavailable("Server is draining"));
+        }
+        let tenant = self.caller_tenant(&request);
+        let mut options = SessionOptions::try_from(request.into_inner())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        options.tenant = tenant;
+        if let Some(session_id) = &options.session_id {
+            if self.sessions.lock().contains_key(session_id) {
+                return Err(Status::already_exists(format!(
+                    "Session already exists: {}",
+                    session_id
+                )));
+            }
+        }
+
+        self.with_deadline(async {
+            let session_id = self
+                .create_session(options)
+                .await
+                .map_err(|e| self.error_status(&e, Status::failed_precondition))?;
+            let status = self
+                .session_status(&session_id)
+                .await
+                .ok_or_else(|| Status::not_found(format!("Unknown session: {}", session_id)))?;
+            Ok(Response::new(status.into()))
+        })
+        .await
+    }
+
+    async fn get_session(
+        &self,
+        request: Request<proto::GetSessionRequest>,
+    ) -> Result<Response<proto::SessionInfo>, Status> {
+        let tenant = self.caller_tenant(&request);
+        let session_id = request.into_inner().session_id;
+        if let Some(status) = self.hidden_status(&session_id, tenant.as_deref()) {
+            return Err(status);
+        }
+        self.with_deadline(async {
+            let status = self
+                .session_status(&session_id)
+                .await
+                .ok_or_else(|| Status::not_found(format!("Unknown session: {}", session_id)))?;
+            Ok(Response::new(status.into()))
+        })
+        .await
+    }
+
+    async fn get_session_owner(
+        &self,
+        request: Request<proto::GetSessionRequest>,
+    ) -> Result<Response<proto::SessionOwner>, Status> {
+        let tenant = self.caller_tenant(&request);
+        let session_id = request.into_inner().session_id;
+        if let Some(status) = self.hidden_status(&session_id, tenant.as_deref()) {
+            return Err(status);
+        }
+        self.with_deadline(async {
+            let owner = self
+                .session_manager
+                .owner_of(&session_id)
+                .await
+                .map_err(|e| Status::unavailable(e.to_string()))?
+                .ok_or_else(|| {
+                    Status::not_found(format!("No owner for session: {}", session_id))
+                })?;
+            Ok(Response::new(convert::session_owner(&session_id, owner)))
+        })
+        .await
+    }
+
+    async fn get_session_history(
+        &self,
+        request: Request<proto::GetSessionRequest>,
+    ) -> Result<Response<proto::SessionHistory>, Status> {
+        let tenant = self.caller_tenant(&request);
+        let session_id = request.into_inner().session_id;
+        if let Some(status) = self.hidden_status(&session_id, tenant.as_deref()) {
+            return Err(status);
+        }
+        self.with_deadline(async {
+            let history = self
+                .session_history(&session_id)
+                .await
+                .map_err(|e| Status::unavailable(e.to_string()))?
+                // Saved journals don't record their tenant
+                .filter(|history| history.live || tenant.is_none())
+                .ok_or_else(|| {
+                    Status::not_found(format!("No history for session: {}", session_id))
+                })?;
+            Ok(Response::new(history.into()))
+        })
+        .await
+    }
+
+    async fn end_session(
+        &self,
+        request: Request<proto::EndSessionRequest>,
+    ) -> Result<Response<proto::SessionEnded>, Status> {
+        let tenant = self.caller_tenant(&request);
+        let session_id = request.into_inner().session_id;
+        if let Some(status) = self.hidden_status(&session_id, tenant.as_deref()) {
+            return Err(status);
+        }
+        let event = self
+            .with_deadline(async {
+                self.end_session(&session_id)
+                    .await
+                    .map_err(|e| Status::not_found(e.to_string()))
+            })
+            .await?;
+        match proto::MediaEvent::from(event).event {
+            Some(proto::media_event::Event::SessionEnded(ended)) => Ok(Response::new(ended)),
+            _ => Err(Status::internal("Unexpected session end event")),
+        }
+    }
+}
+
+/// Outcome of an orchestration command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandStatus {
+    /// Applied, its effect is still pending
+    Accepted,
+    /// Not applied
+    Rejected,
+    /// Applied and took effect
+    Completed,
+}
+
+/// Why a session ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    /// Ended through `EndSession`
+    #[default]
+    Completed,
+    /// No command or audio within the idle timeout
+    Idle,
+    /// No audio within the no-media timeout, the connection likely died
+    NoMedia,
+    /// Exceeded a resource ceiling
+    ResourceLimit,
+    /// Its lease was taken over by another instance
+    LeaseLost,
+    /// Still open when the drain timeout ran out
+    Drained,
+    /// Ended by an admin through `ForceEndSession`
+    Forced,
+    /// Its processing panicked
+    InternalError,
+    /// Its WebRTC connection wasn't restored within the reconnect window
+    ConnectionLost,
+}
+
+impl EndReason {
+    /// Get the reason's name, as used in metric labels
+    pub fn name(self) -> &'static str {
+        match self {
+            EndReason::Completed => "completed",
+            EndReason::Idle => "idle",
+            EndReason::NoMedia => "no_media",
+            EndReason::ResourceLimit => "resource_limit",
+            EndReason::LeaseLost => "lease_lost",
+            EndReason::Drained => "drained",
+            EndReason::Forced => "forced",
+            EndReason::InternalError => "internal_error",
+            EndReason::ConnectionLost => "connection_lost",
+        }
+    }
+}
+
+/// Media event types for the gRPC stream
+#[derive(Debug, Clone)]
+pub enum MediaEvent {
+    AudioFrame {
+        session_id: String,
+        timestamp_ms: i64,
+        pcm_data: Vec<u8>,
+        sample_rate: u32,
+        channels: u32,
+    },
+    TurnStarted {
+        session_id: String,
+        timestamp_ms: i64,
+        vad_probability: f32,
+    },
+    TurnEnded {
+        session_id: String,
+        timestamp_ms: i64,
+        duration_ms: u32,
+        segment: TurnSegment,
+    },
+    BargeIn {
+        session_id: String,
+        timestamp_ms: i64,
+        vad_probability: f32,
+    },
+    Overlap {
+        session_id: String,
+        timestamp_ms: i64,
+        duration_ms: u32,
+    },
+    EndOfTurnAnticipated {
+        session_id: String,
+        timestamp_ms: i64,
+        confidence: f32,
+    },
+    /// The caller's speech is the agent's own played audio coming back
+    EchoLoopDetected {
+        session_id: String,
+        timestamp_ms: i64,
+        similarity: f32,
+    },
+    /// The caller's WebRTC connection moved to another state
+    ConnectionStateChanged {
+        session_id: String,
+        timestamp_ms: i64,
+        state: ConnectionState,
+        previous: ConnectionState,
+        /// Time spent in the previous state
+        previous_duration_ms: u64,
+    },
+    TurnSegmented {
+        session_id: String,
+        timestamp_ms: i64,
+        segment: TurnSegment,
+    },
+    DetectionDebug {
+        session_id: String,
+        timestamp_ms: i64,
+        state: TurnState,
+        breakdown: FusionBreakdown,
+    },
+    PartialTranscript {
+        session_id: String,
+        timestamp_ms: i64,
+        text: String,
+        confidence: f32,
+        is_final: bool,
+    },
+    SessionEnded {
+        session_id: String,
+        duration_ms: i64,
+        total_frames: u32,
+        reason: EndReason,
+    },
+    CommandAck {
+        session_id: String,
+        timestamp_ms: i64,
+        command_id: String,
+        status: CommandStatus,
+        reason: String,
+    },
+    ServerDraining {
+        session_id: String,
+        timestamp_ms: i64,
+        deadline_ms: i64,
+        /// Set when the session was parked for another instance to resume
+        handoff: Option<OrphanedSession>,
+    },
+    SessionMigrated {
+        session_id: String,
+        timestamp_ms: i64,
+        frames_processed: u64,
+    },
+    /// Average latency of the frames since the last report
+    LatencyReport {
+        session_id: String,
+        timestamp_ms: i64,
+        frames: u32,
+        latency: LatencyBreakdown,
+    },
+}
+
+impl MediaEvent {
+    /// Build a command acknowledgment
+    pub fn command_ack(
+        session_id: &str,
+        command_id: &str,
+        status: CommandStatus,
+        reason: impl Into<String>,
+    ) -> Self {
+        MediaEvent::CommandAck {
+            session_id: session_id.to_string(),
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+            command_id: command_id.to_string(),
+            status,
+            reason: reason.into(),
+        }
+    }
+
+    /// Get the event's field name in `MediaEvent`, like `turn_started`
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaEvent::AudioFrame { .. } => "audio_frame",
+            MediaEvent::TurnStarted { .. } => "turn_started",
+            MediaEvent::TurnEnded { .. } => "turn_ended",
+            MediaEvent::BargeIn { .. } => "barge_in",
+            MediaEvent::Overlap { .. } => "overlap",
+            MediaEvent::EndOfTurnAnticipated { .. } => "end_of_turn_anticipated",
+            MediaEvent::EchoLoopDetected { .. } => "echo_loop_detected",
+            MediaEvent::ConnectionStateChanged { .. } => "connection_state_changed",
+            MediaEvent::TurnSegmented { .. } => "turn_segmented",
+            MediaEvent::DetectionDebug { .. } => "detection_debug",
+            MediaEvent::PartialTranscript { .. } => "partial_transcript",
+            MediaEvent::SessionEnded { .. } => "session_ended",
+            MediaEvent::CommandAck { .. } => "command_ack",
+            MediaEvent::ServerDraining { .. } => "server_draining",
+            MediaEvent::SessionMigrated { .. } => "session_migrated",
+            MediaEvent::LatencyReport { .. } => "latency_report",
+        }
+    }
+
+    /// Get the journal entry of a turn event, with its timestamp
+    fn journal_event(&self) -> Option<(i64, JournalEvent)> {
+        match *self {
+            MediaEvent::TurnStarted {
+                timestamp_ms,
+                vad_probability,
+                ..
+            } => Some((timestamp_ms, JournalEvent::TurnStarted { vad_probability })),
+            MediaEvent::TurnEnded {
+                timestamp_ms,
+                duration_ms,
+                segment,
+                ..
+            } => Some((
+                timestamp_ms,
+                JournalEvent::TurnEnded {
+                    duration_ms,
+                    segment,
+                },
+            )),
+            MediaEvent::BargeIn {
+                timestamp_ms,
+                vad_probability,
+                ..
+            } => Some((timestamp_ms, JournalEvent::BargeIn { vad_probability })),
+            MediaEvent::Overlap {
+                timestamp_ms,
+                duration_ms,
+                ..
+            } => Some((timestamp_ms, JournalEvent::Overlap { duration_ms })),
+            _ => None,
+        }
+    }
+
+    /// Build an audio frame event from processed float samples (mono, i16 LE)
+    pub fn audio_frame(session_id: &str, timestamp_ms: i64, pcm: &[f32], sample_rate: u32) -> Self {
+        let pcm_data = float_to_pcm(pcm)
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        MediaEvent::AudioFrame {
+            session_id: session_id.to_string(),
+            timestamp_ms,
+            pcm_data,
+            sample_rate,
+            channels: 1,
+        }
+    }
+}
+
+/// Orchestration commands from the server
+#[derive(Debug, Clone)]
+pub enum OrchestrationCommand {
+    PlayAudio {
+        session_id: String,
+        audio_data: Vec<u8>,
+        audio_format: String,
+    },
+    StopAudio {
+        session_id: String,
+        reason: String,
+    },
+    ClearContext {
+        session_id: String,
+        context_type: String,
+    },
+    AdjustVAD {
+        session_id: String,
+        sensitivity: f32,
+        threshold_ms: u32,
+    },
+    UpdateTranscript {
+        session_id: String,
+        text: String,
+        is_final: bool,
+    },
+    UpdateTurnConfig {
+        session_id: String,
+        update: TurnConfigUpdate,
+    },
+    InjectSignal {
+        session_id: String,
+        signal: ExternalSignal,
+    },
+    Pause {
+        session_id: String,
+        mute_audio: bool,
+    },
+    Resume {
+        session_id: String,
+    },
+    SetTags {
+        session_id: String,
+        tags: HashMap<String, String>,
+        remove: Vec<String>,
+    },
+}
+
+impl OrchestrationCommand {
+    /// Get the command's field name in `OrchestrationCommand`, like `play_audio`
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrchestrationCommand::PlayAudio { .. } => "play_audio",
+            OrchestrationCommand::StopAudio { .. } => "stop_audio",
+            OrchestrationCommand::ClearContext { .. } => "clear_context",
+            OrchestrationCommand::AdjustVAD { .. } => "adjust_vad",
+            OrchestrationCommand::UpdateTranscript { .. } => "transcript_update",
+            OrchestrationCommand::UpdateTurnConfig { .. } => "update_turn_config",
+            OrchestrationCommand::InjectSignal { .. } => "inject_signal",
+            OrchestrationCommand::Pause { .. } => "pause",
+            OrchestrationCommand::Resume { .. } => "resume",
+            OrchestrationCommand::SetTags { .. } => "set_tags",
+        }
+    }
+
+    /// Get the session the command targets
+    pub fn session_id(&self) -> &str {
+        match self {
+            OrchestrationCommand::PlayAudio { session_id, .. }
+            | OrchestrationCommand::StopAudio { session_id, .. }
+            | OrchestrationCommand::ClearContext { session_id, .. }
+            | OrchestrationCommand::AdjustVAD { session_id, .. }
+            | OrchestrationCommand::UpdateTranscript { session_id, .. }
+            | OrchestrationCommand::UpdateTurnConfig { session_id, .. }
+            | OrchestrationCommand::InjectSignal { session_id, .. }
+            | OrchestrationCommand::Pause { session_id, .. }
+            | OrchestrationCommand::Resume { session_id }
+            | OrchestrationCommand::SetTags { session_id, .. } => session_id,
+        }
+    }
+}
+
+/// Session handler for managing a single media stream session
+pub struct SessionHandler {
+    session_id: String,
+    event_tx: mpsc::Sender<MediaEvent>,
+    command_rx: mpsc::Receiver<OrchestrationCommand>,
+    #[allow(dead_code)]
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+}
+
+impl SessionHandler {
+    /// Create a new session handler
+    pub fn new(
+        session_id: String,
+        config: Arc<Config>,
+        metrics: Arc<Metrics>,
+    ) -> (
+        Self,
+        mpsc::Receiver<MediaEvent>,
+        mpsc::Sender<OrchestrationCommand>,
+    ) {
+        let (event_tx, event_rx) = mpsc::channel(100);
+        let (command_tx, command_rx) = mpsc::channel(100);
+
+        let handler = Self {
+            session_id,
+            event_tx,
+            command_rx,
+            config,
+            metrics,
+        };
+
+        (handler, event_rx, command_tx)
+    }
+
+    /// Get session ID
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Send a media event
+    pub async fn send_event(&self, event: MediaEvent) -> anyhow::Result<()> {
+        if let Err(e) = self.event_tx.send(event).await {
+            self.metrics
+                .record_data_loss(LOSS_CHANNEL_SEND, OUTCOME_NONE, 1);
+            return Err(anyhow::anyhow!("Failed to send event: {}", e));
+        }
+        self.metrics.grpc_messages_sent.inc();
+        Ok(())
+    }
+
+    /// Send pre-roll frames as audio frame events
+    ///
+    /// Emit these right after `TurnStarted` so the consumer receives the
+    /// audio leading into the word onset.
+    pub async fn send_pre_roll(
+        &self,
+        frames: Vec<PreRollFrame>,
+        sample_rate: u32,
+    ) -> anyhow::Result<()> {
+        for frame in frames {
+            self.send_event(MediaEvent::audio_frame(
+                &self.session_id,
+                frame.timestamp_ms,
+                &frame.pcm,
+                sample_rate,
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Receive the next orchestration command
+    pub async fn receive_command(&mut self) -> Option<OrchestrationCommand> {
+        self.command_rx.recv().await
+    }
+
+    /// Check if the event channel is closed
+    pub fn is_closed(&self) -> bool {
+        self.event_tx.is_closed()
+    }
+}
+
+/// Message buffer for handling backpressure
+pub struct MessageBuffer<T> {
+    buffer: Vec<T>,
+    max_size: usize,
+    rejected: u64,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl<T> MessageBuffer<T> {
+    /// Create a new message buffer
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            buffer: Vec::with_capacity(max_size),
+            max_size,
+            rejected: 0,
+            metrics: None,
+        }
+    }
+
+    /// Count rejected messages in `amwaj_data_lost_total`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Add a message to the buffer
+    pub fn push(&mut self, msg: T) -> bool {
+        if self.buffer.len() < self.max_size {
+            self.buffer.push(msg);
+            true
+        } else {
+            // Buffer full, backpressure
+            self.rejected += 1;
+            if let Some(metrics) = &self.metrics {
+                metrics.record_data_loss(LOSS_MESSAGE_BUFFER, OUTCOME_NONE, 1);
+            }
+            false
+        }
+    }
+
+    /// Get the messages rejected because the buffer was full
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Take all messages from the buffer
+    pub fn drain(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.buffer)
+    }
+
+    /// Get current buffer size
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Check if buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Check if buffer is full
+    pub fn is_full(&self) -> bool {
+        self.buffer.len() >= self.max_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_creation() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        assert_eq!(service.config().server.port, 50051);
+    }
+
+    #[tokio::test]
+    async fn test_live_migration() {
+        use crate::session::{MemorySessionStore, SessionStore};
+
+        // Two instances sharing a session store
+        let store: Arc<dyn SessionStore> = Arc::new(MemorySessionStore::new());
+        let instance = || {
+            let config = Config::default();
+            let metrics = Arc::new(Metrics::new(&config));
+            let manager = DistributedSessionManager::with_store(
+                SessionConfig::from(&config.sessions),
+                Arc::clone(&store),
+            );
+            AmwajMediaService::new(config, metrics).with_session_manager(Arc::new(manager))
+        };
+        let (source, target) = (instance(), instance());
+
+        source
+            .create_session(SessionOptions {
+                session_id: Some("s1".to_string()),
+                sample_rate: Some(8000),
+                metadata: HashMap::from([("tenant".to_string(), "acme".into())]),
+                webrtc: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let (sender, mut receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        source
+            .attach_stream("s1", &sender, Subscription::default(), None)
+            .await
+            .unwrap();
+        for _ in 0..10 {
+            source.push_audio("s1", &[10000i16; 160]).unwrap();
+        }
+        assert!(target.ensure_session("s1", None).await.is_err());
+
+        let started = Instant::now();
+        let snapshot = source.migrate_session("s1").await.unwrap();
+        assert_eq!(source.session_count(), 0);
+        assert!(source.webrtc.get_connection("s1").is_err());
+        let event = loop {
+            let event = receiver.recv().await.unwrap().unwrap();
+            if let Some(proto::media_event::Event::SessionMigrated(migrated)) = event.event {
+                break migrated;
+            }
+        };
+        assert_eq!(event.frames_processed, 10);
+
+        // The client reconnects to the other instance, which resumes the session
+        target.ensure_session("s1", None).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(200));
+        let status = target.session_status("s1").await.unwrap();
+        assert_eq!(status.sample_rate, 8000);
+        assert_eq!(status.frames_processed, 10);
+        assert_eq!(status.turn_state, snapshot.detection.unwrap().state);
+        assert_eq!(status.metadata["tenant"], "acme");
+        assert_eq!(status.webrtc_connected, Some(false));
+        let history = target.session_history("s1").await.unwrap().unwrap();
+        assert_eq!(history.journal, snapshot.journal);
+        assert_eq!(
+            target
+                .session_manager()
+                .owner_of("s1")
+                .await
+                .unwrap()
+                .unwrap()
+                .instance_id,
+            target.session_manager().instance_id()
+        );
+        assert!(source.migrate_session("s1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (sender, mut receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        service
+            .attach_stream("s1", &sender, Subscription::default(), None)
+            .await
+            .unwrap();
+        service.ensure_session("s2", None).await.unwrap();
+
+        // s2 ends on its own, s1 outlives the drain
+        let drain = tokio::spawn({
+            let service = service.clone();
+            async move { service.drain(Duration::from_millis(100)).await }
+        });
+        let event = receiver.recv().await.unwrap().unwrap();
+        assert!(matches!(
+            event.event,
+            Some(proto::media_event::Event::ServerDraining(_))
+        ));
+        assert!(service.is_draining());
+        assert!(service
+            .create_session(SessionOptions::default())
+            .await
+            .is_err());
+        service.end_session("s2").await.unwrap();
+
+        assert_eq!(drain.await.unwrap(), 1);
+        assert_eq!(service.session_count(), 0);
+        let event = receiver.recv().await.unwrap().unwrap();
+        assert!(matches!(
+            event.event,
+            Some(proto::media_event::Event::SessionEnded(_))
+        ));
+        tokio::time::timeout(Duration::from_secs(1), service.wait_closed())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hand_off_on_drain() {
+        use crate::session::{MemorySessionStore, SessionStore};
+
+        let store: Arc<dyn SessionStore> = Arc::new(MemorySessionStore::new());
+        let instance = || {
+            let mut config = Config::default();
+            config.sessions.migrate_on_drain = true;
+            let metrics = Arc::new(Metrics::new(&config));
+            let manager = DistributedSessionManager::with_store(
+                SessionConfig::from(&config.sessions),
+                Arc::clone(&store),
+            );
+            AmwajMediaService::new(config, metrics).with_session_manager(Arc::new(manager))
+        };
+        let (source, target) = (instance(), instance());
+
+        let (sender, mut receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        source
+            .attach_stream("s1", &sender, Subscription::default(), None)
+            .await
+            .unwrap();
+        for _ in 0..5 {
+            source.push_audio("s1", &[0i16; 320]).unwrap();
+        }
+        assert_eq!(source.drain(Duration::from_millis(50)).await, 0);
+
+        let handoff = loop {
+            let event = receiver.recv().await.unwrap().unwrap();
+            if let Some(proto::media_event::Event::ServerDraining(draining)) = event.event {
+                break draining.handoff.unwrap();
+            }
+        };
+        assert_eq!(handoff.session_id, "s1");
+        assert_eq!(handoff.frames_processed, 5);
+        assert_eq!(
+            handoff.source_instance_id,
+            source.session_manager().instance_id()
+        );
+        assert!(handoff.resume_by_ms > handoff.orphaned_at_ms);
+        let orphans = target.session_manager().orphaned_sessions().await.unwrap();
+        assert_eq!(orphans.len(), 1);
+
+        // The client re-attaches to the other instance, which resumes it
+        target.ensure_session("s1", None).await.unwrap();
+        assert_eq!(
+            target.session_status("s1").await.unwrap().frames_processed,
+            5
+        );
+        assert!(target
+            .session_manager()
+            .orphaned_sessions()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_tags() {
+        let mut config = Config::default();
+        config.sessions.max_tags = 2;
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let tags = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+
+        let error = service
+            .create_session(SessionOptions {
+                tags: tags(&[("a", "1"), ("b", "2"), ("c", "3")]),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<AmwajError>(),
+            Some(AmwajError::InvalidTags(_))
+        ));
+        assert_eq!(service.session_count(), 0);
+
+        let session_id = service
+            .create_session(SessionOptions {
+                tags: tags(&[("campaign", "support")]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let set_tags =
+            |tags: HashMap<String, String>, remove: &[&str]| OrchestrationCommand::SetTags {
+                session_id: session_id.clone(),
+                tags,
+                remove: remove.iter().map(|key| key.to_string()).collect(),
+            };
+        assert_eq!(
+            service
+                .apply_command(&set_tags(tags(&[("lang", "ar")]), &[]))
+                .unwrap(),
+            CommandStatus::Completed
+        );
+        // Over the limit, the tags are left as they were
+        assert!(service
+            .apply_command(&set_tags(tags(&[("tier", "gold")]), &[]))
+            .is_err());
+        assert!(service
+            .apply_command(&set_tags(tags(&[("bad key", "x")]), &["lang"]))
+            .is_err());
+        service
+            .apply_command(&set_tags(tags(&[("lang", "en")]), &["campaign"]))
+            .unwrap();
+
+        let status = service.session_status(&session_id).await.unwrap();
+        assert_eq!(status.tags, tags(&[("lang", "en")]));
+        let snapshot = service.migrate_session(&session_id).await.unwrap();
+        assert_eq!(snapshot.tags, status.tags);
+    }
+
+    #[tokio::test]
+    async fn test_reap_idle_sessions() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let idle = service
+            .create_session(SessionOptions::default())
+            .await
+            .unwrap();
+        let active = service
+            .create_session(SessionOptions::default())
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        service.push_audio(&active, &[0i16; 320]).unwrap();
+
+        let reaped = service.reap_idle_sessions(Duration::from_millis(20)).await;
+        assert_eq!(reaped, vec![idle.clone()]);
+        assert!(service.session_status(&idle).await.is_none());
+        assert!(service.session_status(&active).await.is_some());
+        assert_eq!(
+            service
+                .session_manager()
+                .total_session_count()
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sweep_expired_sessions() {
+        use crate::session::{MemorySessionStore, SessionData, SessionStore};
+
+        let store: Arc<dyn SessionStore> = Arc::new(MemorySessionStore::new());
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let manager = DistributedSessionManager::with_store(
+            SessionConfig::from(&config.sessions),
+            Arc::clone(&store),
+        );
+        let service =
+            AmwajMediaService::new(config, metrics).with_session_manager(Arc::new(manager));
+        assert_eq!(
+            service.session_manager().cleanup_interval(),
+            Some(Duration::from_secs(60))
+        );
+
+        service.ensure_session("open", None).await.unwrap();
+        let long_ago = chrono::Utc::now() - chrono::Duration::hours(2);
+        let mut stored = store.get("open").await.unwrap().unwrap();
+        stored.last_activity = long_ago;
+        store.update(&stored).await.unwrap();
+        let mut abandoned = SessionData::new("abandoned".to_string());
+        abandoned.last_activity = long_ago;
+        store.insert(&abandoned).await.unwrap();
+
+        assert_eq!(service.sweep_expired_sessions().await, 1);
+        assert!(store.get("abandoned").await.unwrap().is_none());
+        assert!(store.get("open").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_session_panic_is_isolated() {
+        use crate::audio::AudioFeatures;
+        use crate::detection::{TurnDetector, TurnEvent, TurnState};
+
+        struct PanickingDetector;
+
+        impl TurnDetector for PanickingDetector {
+            fn process(&mut self, _: f32, _: &AudioFeatures, _: u32) -> TurnEvent {
+                panic!("detector bug")
+            }
+
+            fn state(&self) -> TurnState {
+                TurnState::Idle
+            }
+
+            fn reset(&mut self) {}
+        }
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config.clone(), metrics);
+        service.ensure_session("broken", None).await.unwrap();
+        service.ensure_session("healthy", None).await.unwrap();
+        let pipeline = MediaPipeline::new("broken".to_string(), &config)
+            .unwrap()
+            .with_detector(Box::new(PanickingDetector));
+        service.sessions.lock().get_mut("broken").unwrap().pipeline = pipeline;
+
+        let frame = vec![0i16; 320];
+        let error = service.push_audio("broken", &frame).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<AmwajError>().map(AmwajError::kind),
+            Some(AmwajError::Internal(_))
+        ));
+        let context = error.downcast_ref::<AmwajError>().unwrap().context();
+        assert_eq!(
+            context,
+            Some(&ErrorContext::session("broken").with_frame(0, 0))
+        );
+        assert!(service.push_audio("broken", &frame).is_err());
+        assert!(service.push_audio("healthy", &frame).is_ok());
+        assert_eq!(
+            service
+                .metrics
+                .panics
+                .with_label_values(&[TASK_SESSION])
+                .get(),
+            1
+        );
+
+        // Ended in the background
+        for _ in 0..100 {
+            if !service.sessions.lock().contains_key("broken") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!service.sessions.lock().contains_key("broken"));
+        assert!(service.sessions.lock().contains_key("healthy"));
+    }
+
+    #[tokio::test]
+    async fn test_reap_silent_sessions() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.ensure_session("silent", None).await.unwrap();
+        service.ensure_session("talking", None).await.unwrap();
+        let (sender, mut receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        service
+            .attach_stream("silent", &sender, Subscription::default(), None)
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        service.push_audio("talking", &[0i16; 320]).unwrap();
+        // Commands keep a session active, but are not media
+        service
+            .handle_command(&OrchestrationCommand::StopAudio {
+                session_id: "silent".to_string(),
+                reason: String::new(),
+            })
+            .await
+            .unwrap();
+        assert!(service
+            .reap_idle_sessions(Duration::from_millis(20))
+            .await
+            .is_empty());
+
+        let reaped = service
+            .reap_silent_sessions(Duration::from_millis(20))
+            .await;
+        assert_eq!(reaped, vec!["silent".to_string()]);
+        assert!(service.session_status("talking").await.is_some());
+        let mut reason = None;
+        while let Ok(event) = receiver.try_recv() {
+            if let Some(proto::media_event::Event::SessionEnded(ended)) = event.unwrap().event {
+                reason = Some(ended.reason());
+            }
+        }
+        assert_eq!(reason, Some(proto::session_ended::Reason::NoMedia));
+    }
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let options = SessionOptions {
+            user_id: Some("user-1".to_string()),
+            sample_rate: Some(8000),
+            webrtc: true,
+            metadata: HashMap::from([("lang".to_string(), "ar".into())]),
+            ..Default::default()
+        };
+        let session_id = service.create_session(options).await.unwrap();
+        service.push_audio(&session_id, &[0i16; 160]).unwrap();
+
+        let status = service.session_status(&session_id).await.unwrap();
+        assert_eq!(status.user_id.as_deref(), Some("user-1"));
+        assert_eq!(status.sample_rate, 8000);
+        assert_eq!(status.frames_processed, 1);
+        assert_eq!(status.webrtc_connected, Some(false));
+        assert_eq!(status.metadata["lang"], "ar");
+
+        match service.end_session(&session_id).await.unwrap() {
+            MediaEvent::SessionEnded { total_frames, .. } => assert_eq!(total_frames, 1),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        assert_eq!(service.session_count(), 0);
+        assert_eq!(
+            service
+                .session_manager()
+                .total_session_count()
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(service.session_status(&session_id).await.is_none());
+        assert!(service.end_session(&session_id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_history() {
+        let mut config = Config::default();
+        config.sessions.flush_journal_on_end = true;
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let session_id = service
+            .create_session(SessionOptions::default())
+            .await
+            .unwrap();
+
+        for _ in 0..30 {
+            service.push_audio(&session_id, &[10000i16; 320]).unwrap();
+        }
+        let stop = OrchestrationCommand::StopAudio {
+            session_id: session_id.clone(),
+            reason: "barge_in".to_string(),
+        };
+        service.apply_command(&stop).unwrap();
+        let history = service.session_history(&session_id).await.unwrap().unwrap();
+        assert!(history.live);
+        let events: Vec<&JournalEvent> = history
+            .journal
+            .entries()
+            .iter()
+            .map(|entry| &entry.event)
+            .collect();
+        assert!(matches!(events[0], JournalEvent::TurnStarted { .. }));
+        assert_eq!(
+            events.last(),
+            Some(&&JournalEvent::Command {
+                command: "stop_audio".to_string(),
+                status: CommandStatus::Completed,
+                reason: None,
+            })
+        );
+
+        // Saved when the session ends, with its quality stats
+        service.end_session(&session_id).await.unwrap();
+        let history = service.session_history(&session_id).await.unwrap().unwrap();
+        assert!(!history.live);
+        assert!(matches!(
+            history.journal.entries().last().unwrap().event,
+            JournalEvent::Quality {
+                frames_processed: 30,
+                ..
+            }
+        ));
+        assert!(service.session_history("unknown").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_call_detail_record() {
+        use crate::sinks::FileSink;
+
+        let dir = std::env::temp_dir().join(format!("amwaj-cdr-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("cdrs.jsonl");
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let cdrs = EventSinks::default().with_sink(
+            Arc::new(FileSink::open("cdr_file", &path).await.unwrap()),
+            8,
+        );
+        let service = AmwajMediaService::new(config, metrics).with_cdr_sinks(cdrs.clone());
+        let session_id = service
+            .create_session(SessionOptions {
+                user_id: Some("user-1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        for _ in 0..30 {
+            service.push_audio(&session_id, &[10000i16; 320]).unwrap();
+        }
+        for _ in 0..100 {
+            service.push_audio(&session_id, &[0i16; 320]).unwrap();
+        }
+        service
+            .end_session_with_reason(&session_id, EndReason::NoMedia)
+            .await
+            .unwrap();
+        assert_eq!(cdrs.flush(Duration::from_secs(1)).await, 0);
+
+        let line = std::fs::read_to_string(&path).unwrap();
+        let record = match serde_json::from_str(line.trim()).unwrap() {
+            SinkEvent::CallDetailRecord(record) => record,
+            other => panic!("Unexpected event: {:?}", other),
+        };
+        assert_eq!(record.session_id, session_id);
+        assert_eq!(record.user_id.as_deref(), Some("user-1"));
+        assert_eq!(record.codec, "pcm16");
+        assert_eq!(record.frames, 130);
+        assert_eq!(record.turns, 1);
+        assert_eq!(record.reason, EndReason::NoMedia);
+        assert!(record.mean_vad_probability > 0.0);
+        assert_eq!(
+            record.duration_ms,
+            record.ended_at_ms - record.started_at_ms
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_all_sessions() {
+        let dir = std::env::temp_dir().join(format!("amwaj-record-{}", uuid::Uuid::new_v4()));
+        let config = Config {
+            recorder: Some(RecorderConfig {
+                dir: dir.to_string_lossy().into_owned(),
+                record_all: true,
+                s3: None,
+            }),
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.ensure_session("s1", None).await.unwrap();
+        assert!(service.session_stats()[0].recording);
+        for _ in 0..30 {
+            service.push_audio("s1", &[10000i16; 320]).unwrap();
+        }
+        service.end_session("s1").await.unwrap();
+
+        let trace = std::fs::read_dir(&dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let records: Vec<TraceRecord> = crate::session::read_trace(&trace)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.record)
+            .collect();
+        let frames = records
+            .iter()
+            .filter(|record| matches!(record, TraceRecord::Frame { .. }))
+            .count();
+        assert_eq!(frames, 30);
+        assert!(records.iter().any(|record| matches!(
+            record,
+            TraceRecord::Transition {
+                from: TurnState::Idle,
+                ..
+            }
+        )));
+        // Turn events carry their details, audio frames are left out
+        assert!(records.iter().any(|record| matches!(
+            record,
+            TraceRecord::Event {
+                name,
+                detail: Some(JournalEvent::TurnStarted { .. }),
+                ..
+            } if name == "turn_started"
+        )));
+        assert!(!records.iter().any(|record| matches!(
+            record,
+            TraceRecord::Event { name, .. } if name == "audio_frame"
+        )));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.ensure_session("s1", None).await.unwrap();
+        let stored_state = || async {
+            service
+                .session_manager()
+                .get_session("s1")
+                .await
+                .unwrap()
+                .unwrap()
+                .state
+        };
+
+        let pause = OrchestrationCommand::Pause {
+            session_id: "s1".to_string(),
+            mute_audio: true,
+        };
+        service.handle_command(&pause).await.unwrap();
+        assert!(service
+            .push_audio("s1", &[10000i16; 320])
+            .unwrap()
+            .is_empty());
+        let status = service.session_status("s1").await.unwrap();
+        assert_eq!(status.state, SessionState::Paused);
+        assert_eq!(stored_state().await, SessionState::Paused);
+        assert!(service.session_stats()[0].paused);
+
+        let resume = OrchestrationCommand::Resume {
+            session_id: "s1".to_string(),
+        };
+        service.handle_command(&resume).await.unwrap();
+        assert_eq!(stored_state().await, SessionState::Active);
+        let stats = &service.session_stats()[0];
+        assert!(!stats.paused);
+        assert_eq!(stats.frames_processed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_endpointing_without_asr() {
+        let mut config = Config::default();
+        config.detection.semantic = Some(crate::config::SemanticConfig::default());
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        assert!(service.asr.is_none());
+        service.ensure_session("s1", None).await.unwrap();
+
+        for _ in 0..25 {
+            service.push_audio("s1", &[10000i16; 320]).unwrap();
+        }
+        service
+            .handle_command(&OrchestrationCommand::UpdateTranscript {
+                session_id: "s1".to_string(),
+                text: "I'd like a table for two.".to_string(),
+                is_final: true,
+            })
+            .await
+            .unwrap();
+
+        // A complete transcript ends the turn well before the 400ms of
+        // silence it takes otherwise
+        let silent_frames = (1..=20)
+            .find(|_| {
+                service
+                    .push_audio("s1", &[0i16; 320])
+                    .unwrap()
+                    .iter()
+                    .any(|event| matches!(event, MediaEvent::TurnEnded { .. }))
+            })
+            .unwrap();
+        assert!(
+            silent_frames * 20 < 400,
+            "ended after {silent_frames} frames"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_session_rejections() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let named = SessionOptions {
+            session_id: Some("s1".to_string()),
+            ..Default::default()
+        };
+        service.create_session(named.clone()).await.unwrap();
+        assert!(service.create_session(named).await.is_err());
+
+        let bad_rate = SessionOptions {
+            sample_rate: Some(1000),
+            ..Default::default()
+        };
+        assert!(service.create_session(bad_rate).await.is_err());
+
+        let bad_detector = SessionOptions {
+            detector: Some("missing".to_string()),
+            ..Default::default()
+        };
+        assert!(service.create_session(bad_detector).await.is_err());
+        assert_eq!(service.session_count(), 1);
+        assert!(service.push_audio("unknown", &[0i16; 320]).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_tenant_quota_concurrent() {
+        let mut config = Config::default();
+        config.tenants.insert(
+            "acme".to_string(),
+            TenantConfig {
+                max_sessions: Some(2),
+                ..Default::default()
+            },
+        );
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let service = service.clone();
+                tokio::spawn(async move {
+                    service
+                        .create_session(SessionOptions {
+                            user_id: Some("user-1".to_string()),
+                            tenant: Some("acme".to_string()),
+                            ..Default::default()
+                        })
+                        .await
+                        .is_ok()
+                })
+            })
+            .collect();
+        let mut created = 0;
+        for task in tasks {
+            created += usize::from(task.await.unwrap());
+        }
+        assert_eq!(created, 2);
+        assert_eq!(service.session_count(), 2);
+        // Rejected sessions left nothing behind in the session manager
+        assert_eq!(service.session_manager.sessions_for_user("user-1").len(), 2);
+        assert_eq!(
+            service.session_manager.list_sessions().await.unwrap().len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tenant_policy() {
+        let mut config = Config::default();
+        config.tenants.insert(
+            "acme".to_string(),
+            TenantConfig {
+                principals: vec!["acme-key".to_string()],
+                codecs: vec!["pcm16".to_string()],
+                turn_detection: crate::config::TenantTurnDetection {
+                    min_speech_duration_ms: Some(500),
+                    ..Default::default()
+                },
+                max_sessions: Some(1),
+                ..Default::default()
+            },
+        );
+        config.tenants.insert(
+            "globex".to_string(),
+            TenantConfig {
+                principals: vec!["globex-key".to_string()],
+                ..Default::default()
+            },
+        );
+        config.validate().unwrap();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config.clone(), Arc::clone(&metrics));
+
+        let mut request = Request::new(());
+        request.extensions_mut().insert(Principal {
+            id: "acme-key".to_string(),
+            method: crate::grpc::auth::AuthMethod::ApiKey,
+        });
+        assert_eq!(service.caller_tenant(&request).as_deref(), Some("acme"));
+
+        let acme = |codec| SessionOptions {
+            codec: Some(codec),
+            tenant: Some("acme".to_string()),
+            ..Default::default()
+        };
+        assert!(service
+            .create_session(acme(AudioEncoding::Opus))
+            .await
+            .is_err());
+        let session_id = service
+            .create_session(acme(AudioEncoding::Pcm16))
+            .await
+            .unwrap();
+        let error = service
+            .create_session(acme(AudioEncoding::Pcm16))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<AmwajError>(),
+            Some(AmwajError::CapacityExceeded(_))
+        ));
+        assert_eq!(
+            metrics
+                .tenant_sessions_rejected
+                .with_label_values(&["acme", "quota"])
+                .get(),
+            1
+        );
+        assert_eq!(
+            service.sessions.lock()[&session_id]
+                .pipeline
+                .detector()
+                .config()
+                .unwrap()
+                .min_speech_duration_ms,
+            500
+        );
+        let status = service.session_status(&session_id).await.unwrap();
+        assert_eq!(status.tenant.as_deref(), Some("acme"));
+
+        // Other tenants and untenanted callers can't reach the session
+        assert!(service
+            .ensure_session(&session_id, Some("globex"))
+            .await
+            .is_err());
+        assert!(service.ensure_session(&session_id, None).await.is_err());
+        service
+            .ensure_session(&session_id, Some("acme"))
+            .await
+            .unwrap();
+        assert!(service.hidden_from(&session_id, Some("globex")));
+
+        let unknown = SessionOptions {
+            tenant: Some("initech".to_string()),
+            ..Default::default()
+        };
+        assert!(service.create_session(unknown).await.is_err());
+
+        service.end_session(&session_id).await.unwrap();
+        assert_eq!(
+            metrics.tenant_sessions.with_label_values(&["acme"]).get(),
+            0
+        );
+        service
+            .create_session(acme(AudioEncoding::Pcm16))
+            .await
+            .unwrap();
+
+        config
+            .tenants
+            .get_mut("globex")
+            .unwrap()
+            .principals
+            .push("acme-key".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_degrade_over_resource_limit() {
+        let mut config = Config::default();
+        config.sessions.resource_limits.max_bandwidth_bytes_per_sec = Some(500);
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.ensure_session("s1", None).await.unwrap();
+        let (sender, mut receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        service
+            .attach_stream("s1", &sender, Subscription::default(), None)
+            .await
+            .unwrap();
+
+        // 640 bytes a frame, the first one crosses the ceiling
+        service.push_audio("s1", &[10000i16; 320]).unwrap();
+        let top = service.heaviest_sessions(UsageMetric::Bandwidth, 1);
+        assert!(top[0].degraded);
+        while receiver.try_recv().is_ok() {}
+
+        // Turn events are still delivered, audio frames are not
+        for _ in 0..29 {
+            service.push_audio("s1", &[10000i16; 320]).unwrap();
+        }
+        for _ in 0..100 {
+            service.push_audio("s1", &[0i16; 320]).unwrap();
+        }
+        assert_eq!(
+            service.heaviest_sessions(UsageMetric::Bandwidth, 1)[0].bytes_in,
+            130 * 640
+        );
+        let mut turn_ended = false;
+        while let Ok(event) = receiver.try_recv() {
+            match event.unwrap().event {
+                Some(proto::media_event::Event::TurnEnded(_)) => turn_ended = true,
+                Some(proto::media_event::Event::AudioFrame(_)) => panic!("Audio frame sent"),
+                _ => {}
+            }
+        }
+        assert!(turn_ended);
+    }
+
+    #[tokio::test]
+    async fn test_terminate_over_resource_limit() {
+        let mut config = Config::default();
+        config.sessions.resource_limits.max_buffered_bytes = Some(0);
+        config.sessions.resource_limits.on_exceeded = LimitAction::Terminate;
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.ensure_session("s1", None).await.unwrap();
+
+        // The frame lands in the pre-roll buffer
+        let error = service.push_audio("s1", &[0i16; 320]).unwrap_err();
+        let error = service.enforce_limit("s1", error).await;
+        assert!(matches!(
+            error.downcast_ref::<AmwajError>(),
+            Some(AmwajError::ResourceLimit(_))
+        ));
+        assert_eq!(service.session_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_playback_over_memory_limit_refused() {
+        let mut config = Config::default();
+        config.sessions.resource_limits.max_buffered_bytes = Some(1000);
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.ensure_session("s1", None).await.unwrap();
+        let mut playback = service.subscribe_playback("s1").unwrap();
+
+        let play = OrchestrationCommand::PlayAudio {
+            session_id: "s1".to_string(),
+            audio_data: vec![0; 800],
+            audio_format: "pcm16".to_string(),
+        };
+        assert_eq!(
+            service.apply_command(&play).unwrap(),
+            CommandStatus::Completed
+        );
+        // The first chunk is still queued for the renderer
+        assert!(service.apply_command(&play).is_err());
+        playback.recv().await.unwrap().unwrap();
+        assert_eq!(
+            service.apply_command(&play).unwrap(),
+            CommandStatus::Completed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_play_audio_rejected() {
+        let mut config = Config::default();
+        config.grpc.max_play_audio_bytes = 640;
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.ensure_session("s1", None).await.unwrap();
+
+        let play = |len: usize| OrchestrationCommand::PlayAudio {
+            session_id: "s1".to_string(),
+            audio_data: vec![0; len],
+            audio_format: "pcm16".to_string(),
+        };
+        assert_eq!(
+            service.apply_command(&play(640)).unwrap(),
+            CommandStatus::Accepted
+        );
+        let error = service.apply_command(&play(641)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "PlayAudio payload of 641 bytes exceeds the 640 byte limit"
+        );
+        let history = service.session_history("s1").await.unwrap().unwrap();
+        assert!(matches!(
+            &history.journal.entries()[1].event,
+            JournalEvent::Command {
+                status: CommandStatus::Rejected,
+                reason: Some(reason),
+                ..
+            } if reason.contains("641 bytes")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_handle_signal() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let candidate: crate::webrtc::IceCandidate =
+            "candidate:1 1 udp 2130706431 10.0.0.1 5000 typ host"
+                .parse()
+                .unwrap();
+        let early = SignalMessage::new("s1", Signal::Candidate(candidate.clone()));
+        assert!(service.handle_signal(early.clone(), None).await.is_err());
+
+        let offer = SignalMessage::new("s1", Signal::Offer("v=0\r\n".to_string()));
+        let replies = service.handle_signal(offer, None).await.unwrap();
+        assert!(matches!(
+            replies.first().map(|r| &r.signal),
+            Some(Signal::Answer(_))
+        ));
+        assert!(matches!(
+            replies.last().map(|r| &r.signal),
+            Some(Signal::EndOfCandidates)
+        ));
+        assert_eq!(service.session_count(), 1);
+
+        assert!(service.handle_signal(early, None).await.unwrap().is_empty());
+        let status = service.session_status("s1").await.unwrap();
+        assert_eq!(status.webrtc_connected, Some(false));
+
+        let (sender, mut receiver) = mpsc::channel(4);
+        service.sessions.lock().get_mut("s1").unwrap().events = Some(sender);
+        service
+            .set_ice_state("s1", IceState::Connected)
+            .await
+            .unwrap();
+        service
+            .set_dtls_state("s1", DtlsState::Connected)
+            .await
+            .unwrap();
+        let event = receiver.recv().await.unwrap().unwrap();
+        let Some(proto::media_event::Event::ConnectionStateChanged(changed)) = event.event else {
+            panic!("{:?}", event);
+        };
+        assert_eq!(
+            (changed.previous(), changed.state()),
+            (
+                proto::connection_state_changed::State::Connecting,
+                proto::connection_state_changed::State::Connected
+            )
+        );
+        let status = service.session_status("s1").await.unwrap();
+        assert_eq!(status.webrtc_connected, Some(true));
+        let left = &service.metrics.connection_state_seconds;
+        assert_eq!(left.with_label_values(&["new"]).get_sample_count(), 1);
+        assert_eq!(
+            left.with_label_values(&["connecting"]).get_sample_count(),
+            1
+        );
+
+        let answer = SignalMessage::new("s1", Signal::Answer(String::new()));
+        assert!(service.handle_signal(answer, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_window() {
+        let mut config = Config::default();
+        config.webrtc.reconnect_window_ms = 50;
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let offer = SignalMessage::new("s1", Signal::Offer("v=0\r\n".to_string()));
+        service.handle_signal(offer.clone(), None).await.unwrap();
+        service
+            .set_ice_state("s1", IceState::Connected)
+            .await
+            .unwrap();
+        service
+            .set_dtls_state("s1", DtlsState::Connected)
+            .await
+            .unwrap();
+
+        // A dropped connection keeps the session from being reaped
+        service
+            .set_ice_state("s1", IceState::Disconnected)
+            .await
+            .unwrap();
+        assert!(service.reap_idle_sessions(Duration::ZERO).await.is_empty());
+        assert!(service
+            .reap_silent_sessions(Duration::ZERO)
+            .await
+            .is_empty());
+
+        // Offering again restarts ICE on the same session
+        service.handle_signal(offer, None).await.unwrap();
+        let peer = service.webrtc.get_connection("s1").unwrap();
+        assert_eq!(peer.state().connection_state, ConnectionState::Connecting);
+        service
+            .set_ice_state("s1", IceState::Connected)
+            .await
+            .unwrap();
+        assert_eq!(peer.state().connection_state, ConnectionState::Connected);
+        let reconnections = &service.metrics.reconnections;
+        assert_eq!(reconnections.with_label_values(&["reconnected"]).get(), 1);
+
+        service.set_ice_state("s1", IceState::Failed).await.unwrap();
+        let window = Duration::from_millis(50);
+        assert!(service.reap_disconnected_sessions(window).await.is_empty());
+        tokio::time::sleep(window).await;
+        assert_eq!(service.reap_disconnected_sessions(window).await, ["s1"]);
+        assert_eq!(service.session_count(), 0);
+        assert_eq!(reconnections.with_label_values(&["expired"]).get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_handler() {
+        let config = Arc::new(Config::default());
+        let metrics = Arc::new(Metrics::new(&config));
+
+        let (handler, mut event_rx, _command_tx) =
+            SessionHandler::new("test-session".to_string(), config, metrics);
+
+        assert_eq!(handler.session_id(), "test-session");
+
+        // Send an event
+        let event = MediaEvent::TurnStarted {
+            session_id: "test-session".to_string(),
+            timestamp_ms: 1000,
+            vad_probability: 0.8,
+        };
+
+        handler.send_event(event).await.unwrap();
+
+        // Receive the event
+        let received = event_rx.recv().await;
+        assert!(received.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_send_pre_roll() {
+        let config = Arc::new(Config::default());
+        let metrics = Arc::new(Metrics::new(&config));
+
+        let (handler, mut event_rx, _command_tx) =
+            SessionHandler::new("test-session".to_string(), config, metrics);
+
+        let frames = vec![
+            PreRollFrame {
+                timestamp_ms: 20,
+                pcm: vec![0.5; 320],
+            },
+            PreRollFrame {
+                timestamp_ms: 40,
+                pcm: vec![0.5; 320],
+            },
+        ];
+        handler.send_pre_roll(frames, 16000).await.unwrap();
+
+        for expected_ts in [20, 40] {
+            match event_rx.recv().await {
+                Some(MediaEvent::AudioFrame {
+                    timestamp_ms,
+                    pcm_data,
+                    ..
+                }) => {
+                    assert_eq!(timestamp_ms, expected_ts);
+                    assert_eq!(pcm_data.len(), 640);
+                }
+                other => panic!("Unexpected event: {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_data_loss_by_outcome() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics.clone());
+        service.ensure_session("slow", None).await.unwrap();
+        let (sender, _receiver) = mpsc::channel(1);
+        service
+            .attach_stream("slow", &sender, Subscription::default(), None)
+            .await
+            .unwrap();
+        // A consumer that never reads
+        sender.try_send(Ok(proto::MediaEvent::default())).unwrap();
+
+        let mut dropped = 0;
+        for _ in 0..20 {
+            dropped += service.push_audio("slow", &[10000i16; 320]).unwrap().len() as u64;
+        }
+        assert!(dropped > 0);
+        service.end_session("slow").await.unwrap();
+
+        // The frames' events and SessionEnded were all lost
+        let lost =
+            |kind: &str, outcome: &str| metrics.data_lost.with_label_values(&[kind, outcome]).get();
+        assert_eq!(lost(LOSS_MEDIA_EVENT, "completed"), dropped + 1);
+        assert_eq!(lost(LOSS_MEDIA_EVENT, OUTCOME_MIGRATED), 0);
+
+        drop(_receiver);
+        service.ensure_session("gone", None).await.unwrap();
+        service
+            .attach_stream("gone", &sender, Subscription::default(), None)
+            .await
+            .unwrap();
+        service
+            .end_session_with_reason("gone", EndReason::Idle)
+            .await
+            .unwrap();
+        assert_eq!(lost(LOSS_CHANNEL_SEND, "idle"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_latency_report() {
+        let mut config = Config::default();
+        config.metrics.latency_report_interval_ms = 100;
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.ensure_session("s1", None).await.unwrap();
+        service.record_decode_latency("s1", 2.5);
+
+        let mut reports = Vec::new();
+        for _ in 0..10 {
+            let events = service.push_audio("s1", &[0i16; 320]).unwrap();
+            reports.extend(events.into_iter().filter_map(|event| match event {
+                MediaEvent::LatencyReport {
+                    frames, latency, ..
+                } => Some((frames, latency)),
+                _ => None,
+            }));
+        }
+        // A report per 100 ms of 20 ms frames
+        assert_eq!(reports.len(), 2);
+        let (frames, latency) = reports[0];
+        assert_eq!(frames, 5);
+        assert_eq!(latency.decode_ms, 0.5);
+        assert_eq!(latency.network_ms, 0.0);
+        assert!(latency.dsp_ms > 0.0);
+        assert_eq!(reports[1].1.decode_ms, 0.0);
     }
 
     #[test]
@@ -239,6 +4553,7 @@ mod tests {
         assert!(buffer.push(2));
         assert!(buffer.push(3));
         assert!(!buffer.push(4)); // Buffer full
+        assert_eq!(buffer.rejected(), 1);
 
         assert!(buffer.is_full());
         assert_eq!(buffer.len(), 3);
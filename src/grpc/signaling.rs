@@ -0,0 +1,37 @@
+//! WebRTC signaling carried over gRPC
+//!
+//! Orchestrators proxy a browser's offer and trickled ICE candidates to the
+//! session's `PeerConnection` with the `Signal` RPC, and relay the answer and
+//! the server's candidates back the same way.
+
+use crate::webrtc::IceCandidate;
+
+/// A signaling payload
+#[derive(Debug, Clone)]
+pub enum Signal {
+    /// SDP offer from the client
+    Offer(String),
+    /// SDP answer from the server
+    Answer(String),
+    /// A trickled ICE candidate
+    Candidate(IceCandidate),
+    /// The sender has no more candidates
+    EndOfCandidates,
+}
+
+/// A signaling message for a session
+#[derive(Debug, Clone)]
+pub struct SignalMessage {
+    pub session_id: String,
+    pub signal: Signal,
+}
+
+impl SignalMessage {
+    /// Create a signaling message
+    pub fn new(session_id: &str, signal: Signal) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            signal,
+        }
+    }
+}
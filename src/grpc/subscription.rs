@@ -0,0 +1,214 @@
+//! Event subscriptions for media streams
+//!
+//! Orchestrators that only drive turn-taking don't need 50 audio frames per
+//! second per session. A client picks the event categories it wants, and
+//! how many audio frames to skip, with headers on the `MediaStream` request:
+//!
+//! - `x-amwaj-event-mask`: bits of `EventCategory`, all events when absent
+//! - `x-amwaj-audio-decimation`: deliver every Nth audio frame, 1 when absent
+//!
+//! Session lifecycle events and command acks are always delivered.
+
+use crate::grpc::service::MediaEvent;
+use bitflags::bitflags;
+use tonic::metadata::MetadataMap;
+
+/// Header carrying the event category mask
+pub const EVENT_MASK_HEADER: &str = "x-amwaj-event-mask";
+
+/// Header carrying the audio frame decimation factor
+pub const AUDIO_DECIMATION_HEADER: &str = "x-amwaj-audio-decimation";
+
+bitflags! {
+    /// Categories of media events, matching the proto `EventCategory` bits
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EventCategories: u32 {
+        const AUDIO_FRAMES = 1;
+        /// Turn start and end, segments and end-of-turn predictions
+        const TURNS = 1 << 1;
+        /// Barge-ins and overlapping speech
+        const INTERRUPTIONS = 1 << 2;
+        const TRANSCRIPTS = 1 << 3;
+        const DETECTION_DEBUG = 1 << 4;
+        const LATENCY = 1 << 5;
+    }
+}
+
+impl MediaEvent {
+    /// Get the category of the event, `None` for events always delivered
+    pub fn category(&self) -> Option<EventCategories> {
+        match self {
+            MediaEvent::AudioFrame { .. } => Some(EventCategories::AUDIO_FRAMES),
+            MediaEvent::TurnStarted { .. }
+            | MediaEvent::TurnEnded { .. }
+            | MediaEvent::EndOfTurnAnticipated { .. }
+            | MediaEvent::EchoLoopDetected { .. }
+            | MediaEvent::TurnSegmented { .. } => Some(EventCategories::TURNS),
+            MediaEvent::BargeIn { .. } | MediaEvent::Overlap { .. } => {
+                Some(EventCategories::INTERRUPTIONS)
+            }
+            MediaEvent::PartialTranscript { .. } => Some(EventCategories::TRANSCRIPTS),
+            MediaEvent::DetectionDebug { .. } => Some(EventCategories::DETECTION_DEBUG),
+            MediaEvent::LatencyReport { .. } => Some(EventCategories::LATENCY),
+            MediaEvent::SessionEnded { .. }
+            | MediaEvent::CommandAck { .. }
+            | MediaEvent::ServerDraining { .. }
+            | MediaEvent::SessionMigrated { .. }
+            | MediaEvent::ConnectionStateChanged { .. } => None,
+        }
+    }
+}
+
+/// What a media stream subscribed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subscription {
+    pub categories: EventCategories,
+    /// Deliver every Nth audio frame
+    pub audio_decimation: u32,
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Self {
+            categories: EventCategories::all(),
+            audio_decimation: 1,
+        }
+    }
+}
+
+impl Subscription {
+    /// Read a subscription from stream request headers
+    pub fn from_metadata(metadata: &MetadataMap) -> anyhow::Result<Self> {
+        let header = |name: &str| -> anyhow::Result<Option<u32>> {
+            let Some(value) = metadata.get(name) else {
+                return Ok(None);
+            };
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("Invalid {} header", name))
+        };
+
+        let mut subscription = Self::default();
+        if let Some(mask) = header(EVENT_MASK_HEADER)? {
+            subscription.categories = EventCategories::from_bits(mask)
+                .ok_or_else(|| anyhow::anyhow!("Unknown event categories in mask {}", mask))?;
+        }
+        if let Some(decimation) = header(AUDIO_DECIMATION_HEADER)? {
+            if decimation == 0 {
+                return Err(anyhow::anyhow!("Audio decimation must be at least 1"));
+            }
+            subscription.audio_decimation = decimation;
+        }
+        Ok(subscription)
+    }
+}
+
+/// Applies a subscription to the events of one session
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    subscription: Subscription,
+    audio_frames_seen: u64,
+}
+
+impl EventFilter {
+    /// Create a filter for a subscription
+    pub fn new(subscription: Subscription) -> Self {
+        Self {
+            subscription,
+            audio_frames_seen: 0,
+        }
+    }
+
+    /// Check if an event should be delivered
+    pub fn allows(&mut self, event: &MediaEvent) -> bool {
+        let Some(category) = event.category() else {
+            return true;
+        };
+        if !self.subscription.categories.contains(category) {
+            return false;
+        }
+        if category == EventCategories::AUDIO_FRAMES {
+            let seen = self.audio_frames_seen;
+            self.audio_frames_seen += 1;
+            return seen.is_multiple_of(self.subscription.audio_decimation as u64);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc::service::EndReason;
+
+    fn audio_frame() -> MediaEvent {
+        MediaEvent::audio_frame("s1", 0, &[0.0; 4], 16000)
+    }
+
+    #[test]
+    fn test_subscription_from_metadata() {
+        assert_eq!(
+            Subscription::from_metadata(&MetadataMap::new()).unwrap(),
+            Subscription::default()
+        );
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(EVENT_MASK_HEADER, "6".parse().unwrap());
+        metadata.insert(AUDIO_DECIMATION_HEADER, "5".parse().unwrap());
+        let subscription = Subscription::from_metadata(&metadata).unwrap();
+        assert_eq!(
+            subscription.categories,
+            EventCategories::TURNS | EventCategories::INTERRUPTIONS
+        );
+        assert_eq!(subscription.audio_decimation, 5);
+
+        for (name, value) in [
+            (EVENT_MASK_HEADER, "1024"),
+            (EVENT_MASK_HEADER, "turns"),
+            (AUDIO_DECIMATION_HEADER, "0"),
+        ] {
+            let mut metadata = MetadataMap::new();
+            metadata.insert(name, value.parse().unwrap());
+            assert!(Subscription::from_metadata(&metadata).is_err());
+        }
+    }
+
+    #[test]
+    fn test_filter_categories() {
+        let mut filter = EventFilter::new(Subscription {
+            categories: EventCategories::TURNS,
+            audio_decimation: 1,
+        });
+        let turn_started = MediaEvent::TurnStarted {
+            session_id: "s1".to_string(),
+            timestamp_ms: 0,
+            vad_probability: 0.9,
+        };
+        let ended = MediaEvent::SessionEnded {
+            session_id: "s1".to_string(),
+            duration_ms: 0,
+            total_frames: 0,
+            reason: EndReason::Completed,
+        };
+
+        assert!(filter.allows(&turn_started));
+        assert!(filter.allows(&ended));
+        assert!(!filter.allows(&audio_frame()));
+    }
+
+    #[test]
+    fn test_audio_decimation() {
+        let mut filter = EventFilter::new(Subscription {
+            audio_decimation: 3,
+            ..Default::default()
+        });
+        let delivered: Vec<bool> = (0..7).map(|_| filter.allows(&audio_frame())).collect();
+        assert_eq!(
+            delivered,
+            vec![true, false, false, true, false, false, true]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! TLS and mutual TLS for the gRPC endpoint
+//!
+//! Session metadata and media events are sensitive, so production
+//! deployments serve gRPC over TLS. With a client CA configured the server
+//! also verifies client certificates, and `SanAuthorizer` restricts which
+//! certificate identities may call it.
+
+use crate::config::TlsConfig;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tonic::{Request, Status};
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// Build the tonic TLS configuration, reading the PEM files from disk
+pub fn server_tls_config(config: &TlsConfig) -> anyhow::Result<ServerTlsConfig> {
+    let read = |path: &str| {
+        std::fs::read(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))
+    };
+
+    let identity = Identity::from_pem(read(&config.cert_path)?, read(&config.key_path)?);
+    let mut tls = ServerTlsConfig::new().identity(identity);
+
+    match &config.client_ca_path {
+        Some(ca_path) => {
+            tls = tls
+                .client_ca_root(Certificate::from_pem(read(ca_path)?))
+                .client_auth_optional(!config.require_client_auth);
+        }
+        None if config.require_client_auth => {
+            return Err(anyhow::anyhow!(
+                "require_client_auth needs a client_ca_path"
+            ));
+        }
+        None => {}
+    }
+    Ok(tls)
+}
+
+/// Get the subject alternative names of a DER certificate
+///
+/// DNS names, URIs and emails are returned as-is, IPs in their textual form.
+pub fn certificate_sans(der: &[u8]) -> anyhow::Result<Vec<String>> {
+    let (_, certificate) = X509Certificate::from_der(der)
+        .map_err(|e| anyhow::anyhow!("Invalid client certificate: {}", e))?;
+    let Some(extension) = certificate
+        .subject_alternative_name()
+        .map_err(|e| anyhow::anyhow!("Invalid subject alternative names: {}", e))?
+    else {
+        return Ok(Vec::new());
+    };
+
+    Ok(extension
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::DNSName(dns) => Some(dns.to_string()),
+            GeneralName::URI(uri) => Some(uri.to_string()),
+            GeneralName::RFC822Name(email) => Some(email.to_string()),
+            GeneralName::IPAddress(bytes) => ip_address(bytes),
+            _ => None,
+        })
+        .collect())
+}
+
+fn ip_address(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes)
+            .ok()
+            .map(|octets| std::net::Ipv4Addr::from(octets).to_string()),
+        16 => <[u8; 16]>::try_from(bytes)
+            .ok()
+            .map(|octets| std::net::Ipv6Addr::from(octets).to_string()),
+        _ => None,
+    }
+}
+
+/// Check a SAN against an allow-list pattern
+fn san_matches(pattern: &str, san: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        // A wildcard covers exactly one DNS label
+        Some(domain) => san
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(domain)),
+        None => pattern.eq_ignore_ascii_case(san),
+    }
+}
+
+/// Interceptor authorizing callers by their client certificate SANs
+///
+/// With an empty allow-list every caller is accepted; the TLS handshake
+/// has already verified any certificate presented.
+#[derive(Debug, Clone, Default)]
+pub struct SanAuthorizer {
+    allowed_sans: Vec<String>,
+}
+
+impl SanAuthorizer {
+    /// Create an authorizer for the given SAN patterns
+    pub fn new(allowed_sans: Vec<String>) -> Self {
+        Self { allowed_sans }
+    }
+
+    /// Create the authorizer configured for a TLS endpoint
+    pub fn from_config(config: Option<&TlsConfig>) -> Self {
+        Self::new(
+            config
+                .map(|tls| tls.allowed_client_sans.clone())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Check if any of a certificate's SANs is allowed
+    pub fn is_authorized(&self, sans: &[String]) -> bool {
+        self.allowed_sans.is_empty()
+            || sans.iter().any(|san| {
+                self.allowed_sans
+                    .iter()
+                    .any(|pattern| san_matches(pattern, san))
+            })
+    }
+}
+
+impl Interceptor for SanAuthorizer {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        if self.allowed_sans.is_empty() {
+            return Ok(request);
+        }
+
+        let certs = request
+            .peer_certs()
+            .ok_or_else(|| Status::unauthenticated("Client certificate required"))?;
+        let leaf = certs
+            .first()
+            .ok_or_else(|| Status::unauthenticated("Client certificate required"))?;
+        let sans =
+            certificate_sans(leaf.get_ref()).map_err(|e| Status::unauthenticated(e.to_string()))?;
+
+        if self.is_authorized(&sans) {
+            Ok(request)
+        } else {
+            tracing::warn!("Rejected client certificate with SANs {:?}", sans);
+            Err(Status::permission_denied(
+                "Client certificate not authorized",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn pem_to_der(pem: &str) -> Vec<u8> {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).unwrap();
+        pem.contents
+    }
+
+    #[test]
+    fn test_certificate_sans() {
+        let pem = std::fs::read_to_string(fixture("orchestrator.pem")).unwrap();
+        let sans = certificate_sans(&pem_to_der(&pem)).unwrap();
+        assert_eq!(
+            sans,
+            vec!["orchestrator.amwaj.internal", "spiffe://amwaj/orchestrator"]
+        );
+
+        let pem = std::fs::read_to_string(fixture("server.pem")).unwrap();
+        let sans = certificate_sans(&pem_to_der(&pem)).unwrap();
+        assert_eq!(sans, vec!["localhost", "127.0.0.1"]);
+
+        assert!(certificate_sans(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn test_san_patterns() {
+        assert!(san_matches(
+            "*.amwaj.internal",
+            "orchestrator.amwaj.internal"
+        ));
+        assert!(!san_matches("*.amwaj.internal", "a.b.amwaj.internal"));
+        assert!(!san_matches("*.amwaj.internal", "amwaj.internal"));
+        assert!(san_matches(
+            "spiffe://amwaj/orchestrator",
+            "spiffe://amwaj/orchestrator"
+        ));
+
+        let authorizer = SanAuthorizer::new(vec!["*.amwaj.internal".to_string()]);
+        assert!(authorizer.is_authorized(&["orchestrator.amwaj.internal".to_string()]));
+        assert!(!authorizer.is_authorized(&["intruder.example.com".to_string()]));
+        assert!(SanAuthorizer::default().is_authorized(&[]));
+    }
+
+    #[test]
+    fn test_server_tls_config() {
+        let config = TlsConfig {
+            cert_path: fixture("server.pem"),
+            key_path: fixture("server.key"),
+            client_ca_path: None,
+            require_client_auth: false,
+            allowed_client_sans: Vec::new(),
+        };
+        assert!(server_tls_config(&config).is_ok());
+
+        let mtls_without_ca = TlsConfig {
+            require_client_auth: true,
+            ..config.clone()
+        };
+        assert!(server_tls_config(&mtls_without_ca).is_err());
+
+        let missing = TlsConfig {
+            cert_path: fixture("missing.pem"),
+            ..config
+        };
+        assert!(server_tls_config(&missing).is_err());
+    }
+
+    #[test]
+    fn test_unauthenticated_without_certificate() {
+        let mut authorizer = SanAuthorizer::new(vec!["*.amwaj.internal".to_string()]);
+        let status = authorizer.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        assert!(SanAuthorizer::default().call(Request::new(())).is_ok());
+    }
+}
@@ -7,8 +7,10 @@
 //! Amwaj Media Server provides:
 //! - WebRTC streaming with RTP packet handling and ICE/STUN/TURN
 //! - Audio processing with VAD, feature extraction, and voice isolation
-//! - Turn detection for conversational AI
+//! - Turn detection for conversational AI, with partial transcripts from an
+//!   external speech recognizer
 //! - gRPC bidirectional streaming
+//! - Twilio Media Streams compatible WebSocket ingest and a SIP gateway
 //! - Distributed session management
 //! - Prometheus metrics and latency tracking
 //!
@@ -28,14 +30,25 @@
 //! }
 //! ```
 
+pub mod asr;
 pub mod audio;
+#[cfg(feature = "client-feature")]
+pub mod client;
 pub mod config;
 pub mod detection;
 pub mod error;
 pub mod grpc;
 pub mod metrics;
+pub mod pipeline;
 pub mod session;
+pub mod sinks;
+pub mod transport;
 pub mod webrtc;
 
+/// Protobuf messages and gRPC stubs generated from `protos/amwaj.proto`
+pub mod proto {
+    tonic::include_proto!("amwaj.media");
+}
+
 pub use config::Config;
-pub use error::{AmwajError, Result};
+pub use error::{AmwajError, ErrorContext, Result, Severity};
@@ -1,72 +1,442 @@
 //! Amwaj Media Server - Real-time media server for voice agents
 
-use amwaj_media::{config::Config, grpc::server::GrpcServer, metrics::Metrics};
-use clap::Parser;
-use std::path::PathBuf;
+use amwaj_media::{
+    config::features,
+    config::layers::Layers,
+    config::reload::{self, ConfigHandle, ConfigReloader},
+    config::Config,
+    grpc::rest::RestAdmin,
+    grpc::server::GrpcServer,
+    metrics::health::{self, HealthMonitor},
+    metrics::inference,
+    metrics::prometheus::{BuildInfo, MetricsServer, Readiness},
+    metrics::push::MetricsPusher,
+    metrics::telemetry,
+    metrics::Metrics,
+    transport::{PcapReplay, RtpIngestOptions},
+};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::info;
+use tracing_subscriber::{reload::Handle, EnvFilter, Registry};
 
 #[derive(Parser)]
 #[command(name = "Amwaj Media Server")]
 #[command(about = "Real-time media server for voice agents")]
 struct Args {
-    #[arg(short, long, default_value = "config.toml")]
+    #[arg(short, long, global = true, default_value = "config.toml")]
     config: PathBuf,
+    /// Overlay the `[profile.<name>]` table of the config file, defaults to
+    /// `AMWAJ_PROFILE`
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Override a config field, like `--set grpc.timeout_secs=10`; takes
+    /// precedence over the file and `AMWAJ__GRPC__TIMEOUT_SECS`
+    #[arg(long = "set", global = true, value_name = "PATH=VALUE")]
+    overrides: Vec<String>,
+    /// Print the effective configuration, secrets redacted, and exit
+    #[arg(long)]
+    print_config: bool,
+    /// Check the config file for changes this often and reload it; it is
+    /// always reloaded on SIGHUP
+    #[arg(long)]
+    watch_config_secs: Option<u64>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the server, the default without a subcommand
+    Serve,
+    /// Load and validate a config file with the profile and overrides given,
+    /// then exit
+    ValidateConfig(ValidateConfigArgs),
+    /// Load the ONNX models and print their inputs and outputs
+    CheckModels(CheckModelsArgs),
+    /// Print the version, commit and features of this build
+    Version(VersionArgs),
+    /// Replay the RTP of a packet capture through the pipeline and print the
+    /// events as JSON lines
+    Replay(ReplayArgs),
+}
+
+#[derive(clap::Args)]
+struct ValidateConfigArgs {
+    /// Config file to check
+    file: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct CheckModelsArgs {
+    /// Model files, every `.onnx` file of `--dir` when none is given
+    paths: Vec<PathBuf>,
+    #[arg(long, default_value = "models")]
+    dir: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct VersionArgs {
+    /// Print the build info as JSON, as served on `/buildinfo`
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct ReplayArgs {
+    /// pcap or pcapng capture
+    #[arg(long)]
+    pcap: PathBuf,
+    /// SSRC of the stream, decimal or 0x-prefixed hex; the first one found
+    /// when unset
+    #[arg(long, value_parser = parse_ssrc)]
+    ssrc: Option<u32>,
+    /// Codec of the stream: pcmu, pcma or opus
+    #[arg(long, default_value = "pcmu")]
+    codec: String,
+    /// Payload type, the codec's configured or static one when unset
+    #[arg(long)]
+    payload_type: Option<u8>,
+    /// Multiple of real time to replay at, 0 replays as fast as possible
+    #[arg(long, default_value_t = 0.0)]
+    speed: f64,
+    /// Write the events to this file instead of stdout
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn parse_ssrc(value: &str) -> Result<u32, std::num::ParseIntError> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+    match &args.command {
+        Some(Command::Version(version)) => return print_version(version.json),
+        Some(Command::CheckModels(models)) => return check_models(models),
+        _ => {}
+    }
 
     // Load configuration
-    let config = Config::from_file(&args.config).unwrap_or_else(|_| Config::default());
+    let layers = Layers {
+        profile: args.profile,
+        overrides: args.overrides,
+    };
+    if let Some(Command::ValidateConfig(validate)) = &args.command {
+        return validate_config(&validate.file, &layers);
+    }
+    let config = Config::load(&args.config, &layers)?;
+    if args.print_config {
+        print!("{}", toml::to_string_pretty(&config.redacted())?);
+        return Ok(());
+    }
+    config.validate()?;
 
-    // Initialize logging
-    initialize_logging(&config);
+    if let Some(Command::Replay(replay)) = args.command {
+        return replay_capture(config, replay).await;
+    }
+
+    // Initialize logging and trace export
+    let log_filter = initialize_logging(&config)?;
+
+    let build = BuildInfo::current();
+    info!(
+        "Amwaj Media Server {} ({}), features: [{}]",
+        build.version,
+        build.git_sha,
+        build.features.join(", ")
+    );
+    if let Some(profile) = layers.selected_profile() {
+        info!("Using config profile {}", profile);
+    }
+    features::log_capabilities(&config);
 
     // Initialize metrics
     let metrics = Arc::new(Metrics::new(&config));
 
-    // Start Prometheus metrics server
+    // Start the metrics and health probe server, it outlives the gRPC drain
+    // so probes keep answering until the process exits
     let metrics_addr = format!("0.0.0.0:{}", config.metrics.prometheus_port).parse()?;
-    let metrics_registry = metrics.registry.clone();
-    tokio::spawn(async move {
-        if let Err(e) =
-            amwaj_media::metrics::prometheus::start_metrics_server(metrics_addr, metrics_registry)
-                .await
-        {
+    let readiness = Readiness::new();
+    let health_monitor = HealthMonitor::new(config.metrics.health.clone(), &metrics);
+    let mut metrics_server = MetricsServer::new(metrics.registry.clone())
+        .with_readiness(readiness.clone())
+        .with_health(
+            health_monitor.score(),
+            config.metrics.health.min_ready_score,
+        );
+    // The admin REST API shares the port, it answers once the gRPC server is up
+    let rest_admin = config.grpc.admin.as_ref().map(|admin| {
+        RestAdmin::new(admin, Duration::from_secs(config.grpc.drain_timeout_secs))
+            .with_metrics(Arc::clone(&metrics))
+            .with_readiness(readiness.clone())
+    });
+    let rest_jwks_refresh = rest_admin.as_ref().and_then(RestAdmin::spawn_jwks_refresh);
+    if let Some(rest_admin) = &rest_admin {
+        metrics_server = metrics_server.with_routes(rest_admin.router());
+    }
+    let health_handle = health::spawn_health_monitor(Arc::clone(&metrics), health_monitor);
+    let (metrics_shutdown_tx, metrics_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let metrics_handle = tokio::spawn(async move {
+        let shutdown = async {
+            let _ = metrics_shutdown_rx.await;
+        };
+        if let Err(e) = metrics_server.serve(metrics_addr, shutdown).await {
             tracing::error!("Metrics server error: {}", e);
         }
     });
 
+    // Push metrics where they can't be scraped
+    let (push_shutdown_tx, push_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let push_handle = match config.metrics.push.clone() {
+        Some(push) => {
+            info!(
+                "Pushing metrics to {} every {}s",
+                push.url, push.interval_seconds
+            );
+            let pusher = MetricsPusher::new(metrics.registry.clone(), push)?;
+            Some(tokio::spawn(pusher.run(async {
+                let _ = push_shutdown_rx.await;
+            })))
+        }
+        None => None,
+    };
+
+    // Reload the logging level, detection settings and metrics targets
+    // without a restart
+    let live_config = ConfigHandle::new(config.clone());
+    let reloader = ConfigReloader::new(&args.config, live_config.clone()).with_layers(layers);
+    let reload_handle = reload::spawn_config_reloader(
+        reloader,
+        args.watch_config_secs
+            .map(|secs| Duration::from_secs(secs.max(1))),
+    );
+    let apply_handle = tokio::spawn(apply_reloads(
+        live_config.clone(),
+        log_filter,
+        Arc::clone(&metrics),
+    ));
+
     // Create and start gRPC server
-    let grpc_server = GrpcServer::new(config.clone(), metrics);
+    let mut grpc_server = GrpcServer::new(config.clone(), metrics)
+        .with_readiness(readiness)
+        .with_config_handle(live_config);
+    if let Some(rest_admin) = rest_admin {
+        grpc_server = grpc_server.with_rest_admin(rest_admin);
+    }
 
     info!(
         "Starting Amwaj Media Server on {}:{}",
         config.server.host, config.server.port
     );
 
-    grpc_server.start().await?;
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        wait_for_termination().await;
+        info!("Shutting down, signal again to exit without draining");
+        let _ = shutdown_tx.send(());
+        wait_for_termination().await;
+        tracing::warn!("Second signal received, exiting now");
+        std::process::exit(1);
+    });
+    let served = grpc_server.start_with_shutdown(shutdown_rx).await;
+    health_handle.abort();
+    reload_handle.abort();
+    apply_handle.abort();
+    if let Some(rest_jwks_refresh) = rest_jwks_refresh {
+        rest_jwks_refresh.abort();
+    }
+
+    let _ = push_shutdown_tx.send(());
+    if let Some(push_handle) = push_handle {
+        let _ = push_handle.await;
+    }
+    let _ = metrics_shutdown_tx.send(());
+    let _ = metrics_handle.await;
+    let _ = tokio::task::spawn_blocking(telemetry::shutdown_tracing).await;
+    served
+}
+
+/// Print the build, on one line or as JSON
+fn print_version(json: bool) -> anyhow::Result<()> {
+    let build = BuildInfo::current();
+    if json {
+        println!("{}", serde_json::to_string(&build)?);
+    } else {
+        println!(
+            "{} {} ({}), schema {}, features: [{}]",
+            build.name,
+            build.version,
+            build.git_sha,
+            build.schema_version,
+            build.features.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Check a config file as the server would load it
+fn validate_config(file: &Path, layers: &Layers) -> anyhow::Result<()> {
+    // Loading falls back to the defaults without a file
+    if !file.is_file() {
+        return Err(anyhow::anyhow!("Config file {} not found", file.display()));
+    }
+    let config = Config::load(file, layers)?;
+    config
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", file.display(), e))?;
+    println!("{}: OK", file.display());
+    Ok(())
+}
+
+/// Load each model, failing if any does not load
+fn check_models(args: &CheckModelsArgs) -> anyhow::Result<()> {
+    let paths = if args.paths.is_empty() {
+        inference::find_models(&args.dir)?
+    } else {
+        args.paths.clone()
+    };
+    if paths.is_empty() {
+        return Err(anyhow::anyhow!("No ONNX models in {}", args.dir.display()));
+    }
+
+    let mut failed = 0;
+    for path in &paths {
+        match inference::inspect_model(path) {
+            Ok(report) => {
+                println!("{} ({}): OK", report.path, report.hash);
+                for input in &report.inputs {
+                    println!("  input  {}: {}", input.name, input.value_type);
+                }
+                for output in &report.outputs {
+                    println!("  output {}: {}", output.name, output.value_type);
+                }
+            }
+            Err(e) => {
+                println!("{}: FAILED, {:#}", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} models failed to load",
+            failed,
+            paths.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Replay a capture offline, events go out as JSON lines and the totals to
+/// stderr
+async fn replay_capture(config: Config, args: ReplayArgs) -> anyhow::Result<()> {
+    use std::io::Write;
 
+    let capture = std::fs::read(&args.pcap)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", args.pcap.display(), e))?;
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let replay = PcapReplay::new(config)
+        .with_stream(RtpIngestOptions {
+            codec: args.codec,
+            payload_type: args.payload_type,
+            ssrc: args.ssrc,
+        })
+        .with_speed(args.speed);
+    let mut written = Ok(());
+    let summary = replay
+        .run(&capture, |event| {
+            if written.is_ok() {
+                written = writeln!(output, "{}", event.to_json());
+            }
+        })
+        .await?;
+    written?;
+    output.flush()?;
+    match summary.ssrc {
+        Some(ssrc) => eprintln!(
+            "SSRC {:#010x}: {} packets, {} frames, {} events",
+            ssrc, summary.packets, summary.frames, summary.events
+        ),
+        None => eprintln!("No RTP stream found"),
+    }
     Ok(())
 }
 
-fn initialize_logging(config: &Config) {
-    use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+/// Wait for Ctrl-C or, on Unix, SIGTERM from the orchestrator
+async fn wait_for_termination() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = terminate.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Apply reloaded settings owned by the binary: the logging level and the
+/// exported latency targets
+async fn apply_reloads(
+    live_config: ConfigHandle,
+    log_filter: Handle<EnvFilter, Registry>,
+    metrics: Arc<Metrics>,
+) {
+    let mut reloads = live_config.subscribe();
+    let mut applied = live_config.current();
+    while reloads.changed().await.is_ok() {
+        let config = Arc::clone(&reloads.borrow_and_update());
+        if config.logging.level != applied.logging.level {
+            if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+                tracing::warn!(
+                    "Logging level not reloaded, {} takes precedence",
+                    EnvFilter::DEFAULT_ENV
+                );
+            } else if let Err(e) = log_filter.reload(EnvFilter::new(&config.logging.level)) {
+                tracing::error!("Failed to reload the logging level: {}", e);
+            }
+        }
+        metrics.set_latency_targets(&config.metrics);
+        applied = config;
+    }
+}
+
+fn initialize_logging(config: &Config) -> anyhow::Result<Handle<EnvFilter, Registry>> {
+    use tracing_subscriber::{fmt, prelude::*, reload};
 
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.logging.level));
+    let (env_filter, handle) = reload::Layer::new(env_filter);
+    let otlp = telemetry::otlp_layer(&config.metrics)?;
 
     if config.logging.format == "json" {
         tracing_subscriber::registry()
             .with(env_filter)
+            .with(otlp)
             .with(fmt::layer().json())
             .init();
     } else {
         tracing_subscriber::registry()
             .with(env_filter)
+            .with(otlp)
             .with(fmt::layer())
             .init();
     }
+    Ok(handle)
 }
@@ -0,0 +1,351 @@
+//! Rolling health score of the instance
+//!
+//! Every few seconds the frame error rate, the p95 frame processing latency
+//! and the drop rate over the last window are compared with their budgets.
+//! The ratio of each to its budget is its burn rate; a signal burning at 1
+//! uses its budget up exactly and scores 0.5, at 2 or more it scores 0. The
+//! health score is the worst of the three, exported as a gauge and checked
+//! by `/readyz` so load balancers eject a degraded instance early.
+
+use crate::config::HealthConfig;
+use crate::metrics::Metrics;
+use prometheus::core::{Collector, Metric};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Signal label of frames failing to process
+pub const SIGNAL_ERRORS: &str = "errors";
+/// Signal label of the p95 frame processing latency
+pub const SIGNAL_LATENCY: &str = "latency";
+/// Signal label of dropped events and packets
+pub const SIGNAL_DROPS: &str = "drops";
+
+/// Quantile of the processing latency held against its budget
+const LATENCY_QUANTILE: f64 = 0.95;
+
+/// How fast a signal uses up its budget, 1 using it up exactly
+pub fn burn_rate(observed: f64, budget: f64) -> f64 {
+    if budget > 0.0 {
+        observed / budget
+    } else if observed > 0.0 {
+        f64::INFINITY
+    } else {
+        0.0
+    }
+}
+
+/// Score a signal from its burn rate, 1 when idle down to 0 at twice the budget
+fn burn_score(burn_rate: f64) -> f64 {
+    (1.0 - burn_rate / 2.0).clamp(0.0, 1.0)
+}
+
+/// Latest health score, shared with `/readyz`
+#[derive(Debug, Clone)]
+pub struct HealthScore(Arc<AtomicU64>);
+
+impl Default for HealthScore {
+    fn default() -> Self {
+        Self(Arc::new(AtomicU64::new(1.0f64.to_bits())))
+    }
+}
+
+impl HealthScore {
+    /// Create a score of a healthy instance
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the score, 0 to 1
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::SeqCst))
+    }
+
+    pub(crate) fn set(&self, score: f64) {
+        self.0.store(score.to_bits(), Ordering::SeqCst);
+    }
+}
+
+/// Health of the instance over the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthReport {
+    /// Share of frames failing to process
+    pub error_rate: f64,
+    /// p95 frame processing latency, `None` without frames
+    pub p95_latency_ms: Option<f64>,
+    /// Dropped events and packets per frame
+    pub drop_rate: f64,
+    pub score: f64,
+}
+
+/// Cumulative metrics at one point in time
+#[derive(Debug, Clone)]
+struct Sample {
+    at: Instant,
+    frames: f64,
+    errors: f64,
+    drops: f64,
+    /// Cumulative count of each latency bucket, then of all observations
+    latency_buckets: Vec<u64>,
+}
+
+impl Sample {
+    fn take(metrics: &Metrics, at: Instant) -> Self {
+        let drops = metrics
+            .data_lost
+            .collect()
+            .iter()
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_counter().get_value())
+            .sum();
+        let histogram = metrics.processing_latency_ms.metric();
+        Self {
+            at,
+            frames: metrics.audio_frames_processed.get(),
+            errors: metrics.audio_frame_errors.get(),
+            drops,
+            latency_buckets: histogram
+                .get_histogram()
+                .get_bucket()
+                .iter()
+                .map(|bucket| bucket.get_cumulative_count())
+                .chain([histogram.get_histogram().get_sample_count()])
+                .collect(),
+        }
+    }
+}
+
+/// Computes the health score from the metrics over a rolling window
+pub struct HealthMonitor {
+    config: HealthConfig,
+    samples: VecDeque<Sample>,
+    /// Upper bounds of the processing latency buckets, then infinity
+    latency_bounds: Vec<f64>,
+    score: HealthScore,
+}
+
+impl HealthMonitor {
+    /// Create a monitor publishing to a fresh score
+    pub fn new(config: HealthConfig, metrics: &Metrics) -> Self {
+        let latency_bounds = metrics
+            .processing_latency_ms
+            .metric()
+            .get_histogram()
+            .get_bucket()
+            .iter()
+            .map(|bucket| bucket.get_upper_bound())
+            .chain([f64::INFINITY])
+            .collect();
+        Self {
+            config,
+            samples: VecDeque::new(),
+            latency_bounds,
+            score: HealthScore::new(),
+        }
+    }
+
+    /// Get the score the monitor publishes to
+    pub fn score(&self) -> HealthScore {
+        self.score.clone()
+    }
+
+    /// Sample the metrics and update the score and its gauges
+    pub fn sample(&mut self, metrics: &Metrics) -> HealthReport {
+        self.sample_at(metrics, Instant::now())
+    }
+
+    fn sample_at(&mut self, metrics: &Metrics, now: Instant) -> HealthReport {
+        let window = Duration::from_secs(self.config.window_seconds);
+        self.samples.push_back(Sample::take(metrics, now));
+        // Keep one sample at least a window old to diff against
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].at) >= window {
+            self.samples.pop_front();
+        }
+        let (Some(first), Some(last)) = (self.samples.front(), self.samples.back()) else {
+            return self.publish(metrics, 0.0, None, 0.0);
+        };
+
+        let frames = last.frames - first.frames;
+        let errors = last.errors - first.errors;
+        let error_rate = if frames + errors > 0.0 {
+            errors / (frames + errors)
+        } else {
+            0.0
+        };
+        let drop_rate = if frames > 0.0 {
+            (last.drops - first.drops) / frames
+        } else {
+            0.0
+        };
+        let buckets: Vec<u64> = last
+            .latency_buckets
+            .iter()
+            .zip(&first.latency_buckets)
+            .map(|(last, first)| last - first)
+            .collect();
+        let p95_latency_ms = quantile(&self.latency_bounds, &buckets, LATENCY_QUANTILE);
+        self.publish(metrics, error_rate, p95_latency_ms, drop_rate)
+    }
+
+    fn publish(
+        &self,
+        metrics: &Metrics,
+        error_rate: f64,
+        p95_latency_ms: Option<f64>,
+        drop_rate: f64,
+    ) -> HealthReport {
+        let burn_rates = [
+            (
+                SIGNAL_ERRORS,
+                burn_rate(error_rate, self.config.max_error_rate),
+            ),
+            (
+                SIGNAL_LATENCY,
+                burn_rate(p95_latency_ms.unwrap_or(0.0), self.config.latency_budget_ms),
+            ),
+            (
+                SIGNAL_DROPS,
+                burn_rate(drop_rate, self.config.max_drop_rate),
+            ),
+        ];
+        let mut score = 1.0f64;
+        for (signal, rate) in burn_rates {
+            metrics.slo_burn_rate.with_label_values(&[signal]).set(rate);
+            score = score.min(burn_score(rate));
+        }
+        metrics.health_score.set(score);
+        self.score.set(score);
+        HealthReport {
+            error_rate,
+            p95_latency_ms,
+            drop_rate,
+            score,
+        }
+    }
+}
+
+/// Estimate a quantile from cumulative bucket counts, like
+/// `histogram_quantile`
+///
+/// The last bucket is unbounded, a quantile falling in it is placed at the
+/// last finite bound.
+fn quantile(bounds: &[f64], cumulative: &[u64], quantile: f64) -> Option<f64> {
+    let total = *cumulative.last()?;
+    if total == 0 {
+        return None;
+    }
+    let rank = quantile * total as f64;
+    let mut lower_bound = 0.0;
+    let mut lower_count = 0;
+    for (&bound, &count) in bounds.iter().zip(cumulative) {
+        if count as f64 >= rank {
+            if bound.is_infinite() {
+                return Some(lower_bound);
+            }
+            let in_bucket = (count - lower_count) as f64;
+            let share = if in_bucket > 0.0 {
+                (rank - lower_count as f64) / in_bucket
+            } else {
+                0.0
+            };
+            return Some(lower_bound + (bound - lower_bound) * share);
+        }
+        lower_bound = bound;
+        lower_count = count;
+    }
+    Some(lower_bound)
+}
+
+/// Sample the health of the instance every `sample_interval_seconds`
+pub fn spawn_health_monitor(
+    metrics: Arc<Metrics>,
+    mut monitor: HealthMonitor,
+) -> tokio::task::JoinHandle<()> {
+    let period = Duration::from_secs(monitor.config.sample_interval_seconds.max(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let report = monitor.sample(&metrics);
+            if report.score < monitor.config.min_ready_score {
+                tracing::warn!("Instance degraded: {:?}", report);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_burn_rate() {
+        assert_eq!(burn_rate(0.02, 0.01), 2.0);
+        assert_eq!(burn_rate(0.0, 0.0), 0.0);
+        assert!(burn_rate(0.1, 0.0).is_infinite());
+        assert_eq!(burn_score(0.0), 1.0);
+        assert_eq!(burn_score(1.0), 0.5);
+        assert_eq!(burn_score(f64::INFINITY), 0.0);
+    }
+
+    #[test]
+    fn test_quantile() {
+        let bounds = [1.0, 2.0, 4.0, f64::INFINITY];
+        assert_eq!(quantile(&bounds, &[0, 0, 0, 0], 0.95), None);
+        // 10 observations in (1, 2], so p50 is half way through the bucket
+        assert_eq!(quantile(&bounds, &[0, 10, 10, 10], 0.5), Some(1.5));
+        assert_eq!(quantile(&bounds, &[10, 10, 10, 10], 0.95), Some(0.95));
+        assert_eq!(quantile(&bounds, &[0, 0, 1, 10], 0.95), Some(4.0));
+    }
+
+    #[test]
+    fn test_health_score() {
+        let config = Config::default();
+        let metrics = Metrics::new(&config);
+        let mut monitor = HealthMonitor::new(config.metrics.health.clone(), &metrics);
+        let start = Instant::now();
+        let report = monitor.sample_at(&metrics, start);
+        assert_eq!(report.score, 1.0);
+
+        // Fast frames without errors or drops
+        for _ in 0..100 {
+            metrics.audio_frames_processed.inc();
+            metrics.record_latency(0.8);
+        }
+        let report = monitor.sample_at(&metrics, start + Duration::from_secs(5));
+        assert_eq!(report.error_rate, 0.0);
+        assert!(report.p95_latency_ms.unwrap() < 1.0);
+        assert!(report.score > 0.9, "{:?}", report);
+
+        // 1% of the window's frames failing uses the error budget up
+        for _ in 0..98 {
+            metrics.audio_frames_processed.inc();
+        }
+        for _ in 0..2 {
+            metrics.audio_frame_errors.inc();
+        }
+        let report = monitor.sample_at(&metrics, start + Duration::from_secs(10));
+        assert!((report.error_rate - 0.01).abs() < 1e-9, "{:?}", report);
+        assert_eq!(
+            metrics
+                .slo_burn_rate
+                .with_label_values(&[SIGNAL_ERRORS])
+                .get(),
+            1.0
+        );
+        assert_eq!(report.score, 0.5);
+        assert_eq!(monitor.score().get(), 0.5);
+        assert_eq!(metrics.health_score.get(), 0.5);
+
+        // Once the window moves past the errors the score recovers
+        for _ in 0..100 {
+            metrics.audio_frames_processed.inc();
+        }
+        monitor.sample_at(&metrics, start + Duration::from_secs(75));
+        let report = monitor.sample_at(&metrics, start + Duration::from_secs(80));
+        assert_eq!(report.error_rate, 0.0);
+        assert_eq!(report.score, 1.0);
+    }
+}
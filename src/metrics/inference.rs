@@ -0,0 +1,166 @@
+//! Identity of the ML models behind the audio and detection paths
+//!
+//! Models are labelled by what they do, so the inference metrics of the
+//! VAD, voice isolation and endpointing models can be told apart, and by
+//! the execution provider and content hash of the loaded file, so a fleet
+//! running mixed model versions or falling back to CPU shows up at a glance.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Model label of voice activity detection
+pub const MODEL_VAD: &str = "vad";
+/// Model label of voice isolation
+pub const MODEL_ISOLATION: &str = "isolation";
+/// Model label of ML endpointing
+pub const MODEL_ENDPOINTER: &str = "endpointer";
+
+/// Provider label of the default CPU execution provider
+pub const PROVIDER_CPU: &str = "cpu";
+
+/// Hex digits of the SHA-256 kept in the hash label
+const HASH_LABEL_LEN: usize = 12;
+
+/// A loaded model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelInfo {
+    /// Execution provider running the model
+    pub provider: String,
+    /// Truncated SHA-256 of the model file
+    pub hash: String,
+}
+
+impl ModelInfo {
+    /// Describe the model loaded from `path`
+    pub fn from_file(path: impl AsRef<Path>, provider: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read(path.as_ref()).map_err(|e| {
+            anyhow::anyhow!("Failed to read model {}: {}", path.as_ref().display(), e)
+        })?;
+        Ok(Self::from_bytes(&data, provider))
+    }
+
+    /// Describe a model from its contents
+    pub fn from_bytes(data: &[u8], provider: &str) -> Self {
+        let mut hash = hex::encode(Sha256::digest(data));
+        hash.truncate(HASH_LABEL_LEN);
+        Self {
+            provider: provider.to_string(),
+            hash,
+        }
+    }
+}
+
+/// Input or output of a model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModelPort {
+    pub name: String,
+    /// Element type and shape, `-1` for dynamic dimensions
+    pub value_type: String,
+}
+
+/// A model file checked by loading it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModelReport {
+    pub path: String,
+    /// Truncated SHA-256, as in the model metrics
+    pub hash: String,
+    pub inputs: Vec<ModelPort>,
+    pub outputs: Vec<ModelPort>,
+}
+
+/// List the ONNX models of a directory, by name
+pub fn find_models(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", dir.display(), e))?;
+    let mut models = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|extension| extension == "onnx")
+        {
+            models.push(path);
+        }
+    }
+    models.sort();
+    Ok(models)
+}
+
+/// Load a model with ONNX Runtime and describe its inputs and outputs
+#[cfg(feature = "audio-feature")]
+pub fn inspect_model(path: &Path) -> anyhow::Result<ModelReport> {
+    let info = ModelInfo::from_file(path, PROVIDER_CPU)?;
+    let session = ort::session::Session::builder()
+        .map_err(|e| anyhow::anyhow!("Failed to create ONNX session: {}", e))?
+        .commit_from_file(path)
+        .map_err(|e| anyhow::anyhow!("Failed to load model {}: {}", path.display(), e))?;
+    let inputs = session
+        .inputs()
+        .iter()
+        .map(|input| ModelPort {
+            name: input.name().to_string(),
+            value_type: input.dtype().to_string(),
+        })
+        .collect();
+    let outputs = session
+        .outputs()
+        .iter()
+        .map(|output| ModelPort {
+            name: output.name().to_string(),
+            value_type: output.dtype().to_string(),
+        })
+        .collect();
+    Ok(ModelReport {
+        path: path.display().to_string(),
+        hash: info.hash,
+        inputs,
+        outputs,
+    })
+}
+
+/// Load a model with ONNX Runtime, which this build lacks
+#[cfg(not(feature = "audio-feature"))]
+pub fn inspect_model(path: &Path) -> anyhow::Result<ModelReport> {
+    // Unreadable files are still told apart from the missing runtime
+    ModelInfo::from_file(path, PROVIDER_CPU)?;
+    Err(anyhow::anyhow!(
+        "Loading {} needs the audio-feature",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_hash() {
+        // echo -n model | sha256sum
+        let info = ModelInfo::from_bytes(b"model", PROVIDER_CPU);
+        assert_eq!(info.hash, "9372c470eead");
+        assert_eq!(info.provider, "cpu");
+        assert!(ModelInfo::from_file("missing.onnx", PROVIDER_CPU).is_err());
+    }
+
+    #[test]
+    fn test_find_models() {
+        let dir = std::env::temp_dir().join(format!("amwaj-models-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("nested.onnx")).unwrap();
+        for name in ["vad.onnx", "README.md", "endpointing.onnx"] {
+            std::fs::write(dir.join(name), b"model").unwrap();
+        }
+
+        let models = find_models(&dir).unwrap();
+        assert_eq!(models, [dir.join("endpointing.onnx"), dir.join("vad.onnx")]);
+        #[cfg(not(feature = "audio-feature"))]
+        assert!(inspect_model(&models[0])
+            .unwrap_err()
+            .to_string()
+            .contains("audio-feature"));
+        assert!(inspect_model(&dir.join("missing.onnx")).is_err());
+        assert!(find_models(&dir.join("missing")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
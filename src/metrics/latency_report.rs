@@ -0,0 +1,132 @@
+//! Per-session breakdown of frame latency
+//!
+//! Each frame's latency is split into the time spent in the jitter buffer,
+//! decoding, DSP (voice isolation, features and VAD), turn detection and
+//! waiting on the media stream. Sessions average the split over an interval
+//! of audio and send it as a `LatencyReport` event, so integrators can see
+//! which stage ate their latency budget.
+
+/// Where the latency of a frame went, in ms
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyBreakdown {
+    /// Delay of the jitter buffer, 0 without WebRTC
+    pub network_ms: f64,
+    pub decode_ms: f64,
+    /// Voice isolation, feature extraction and VAD
+    pub dsp_ms: f64,
+    pub detection_ms: f64,
+    /// Estimated wait of the frame's events on the media stream
+    pub queueing_ms: f64,
+}
+
+impl LatencyBreakdown {
+    /// Get the latency of all components
+    pub fn total_ms(&self) -> f64 {
+        self.network_ms + self.decode_ms + self.dsp_ms + self.detection_ms + self.queueing_ms
+    }
+
+    fn add(&mut self, other: &LatencyBreakdown) {
+        self.network_ms += other.network_ms;
+        self.decode_ms += other.decode_ms;
+        self.dsp_ms += other.dsp_ms;
+        self.detection_ms += other.detection_ms;
+        self.queueing_ms += other.queueing_ms;
+    }
+
+    fn divided(&self, frames: u64) -> LatencyBreakdown {
+        let frames = frames.max(1) as f64;
+        LatencyBreakdown {
+            network_ms: self.network_ms / frames,
+            decode_ms: self.decode_ms / frames,
+            dsp_ms: self.dsp_ms / frames,
+            detection_ms: self.detection_ms / frames,
+            queueing_ms: self.queueing_ms / frames,
+        }
+    }
+}
+
+/// Averages the latency of a session's frames over an interval of audio
+#[derive(Debug, Clone, Default)]
+pub struct LatencyReporter {
+    interval_ms: u64,
+    audio_ms: u64,
+    frames: u64,
+    sum: LatencyBreakdown,
+    /// Decode time not charged to a frame yet
+    pending_decode_ms: f64,
+}
+
+impl LatencyReporter {
+    /// Report every `interval_ms` of audio, never when 0
+    pub fn new(interval_ms: u64) -> Self {
+        Self {
+            interval_ms,
+            ..Default::default()
+        }
+    }
+
+    /// Check if reports are made at all
+    pub fn is_enabled(&self) -> bool {
+        self.interval_ms > 0
+    }
+
+    /// Charge decode time to the next frames
+    ///
+    /// Chunks don't map one to one to frames, so the time is spread over
+    /// the frames of the interval.
+    pub fn add_decode(&mut self, decode_ms: f64) {
+        self.pending_decode_ms += decode_ms;
+    }
+
+    /// Account for a frame of `audio_ms`, returns the averages and frame
+    /// count once the interval is over
+    pub fn record_frame(
+        &mut self,
+        latency: &LatencyBreakdown,
+        audio_ms: u64,
+    ) -> Option<(LatencyBreakdown, u64)> {
+        if !self.is_enabled() {
+            return None;
+        }
+        self.sum.add(latency);
+        self.sum.decode_ms += std::mem::take(&mut self.pending_decode_ms);
+        self.frames += 1;
+        self.audio_ms += audio_ms;
+        if self.audio_ms < self.interval_ms {
+            return None;
+        }
+        let report = (self.sum.divided(self.frames), self.frames);
+        self.sum = LatencyBreakdown::default();
+        self.frames = 0;
+        self.audio_ms = 0;
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_averages_per_interval() {
+        let mut reporter = LatencyReporter::new(60);
+        let frame = LatencyBreakdown {
+            network_ms: 40.0,
+            dsp_ms: 2.0,
+            detection_ms: 0.5,
+            ..Default::default()
+        };
+        reporter.add_decode(1.5);
+        assert!(reporter.record_frame(&frame, 20).is_none());
+        assert!(reporter.record_frame(&frame, 20).is_none());
+        let (average, frames) = reporter.record_frame(&frame, 20).unwrap();
+        assert_eq!(frames, 3);
+        assert_eq!(average.network_ms, 40.0);
+        assert_eq!(average.decode_ms, 0.5);
+        assert_eq!(average.total_ms(), 43.0);
+
+        // The next interval starts from scratch
+        assert!(reporter.record_frame(&frame, 20).is_none());
+        assert!(LatencyReporter::new(0).record_frame(&frame, 1000).is_none());
+    }
+}
@@ -1,17 +1,57 @@
 //! Metrics infrastructure for Amwaj Media Server
 
+pub mod health;
+pub mod inference;
+pub mod latency_report;
 pub mod latency_tracker;
 pub mod prometheus;
+pub mod push;
+pub mod runtime;
+pub mod telemetry;
 
-use crate::config::Config;
-use ::prometheus::{Counter, Histogram, HistogramOpts, IntGauge, Registry};
+use crate::audio::budget::{ProcessingStage, StageTimings};
+use crate::config::{Config, MetricsConfig};
+use crate::error::AmwajError;
+use crate::metrics::inference::ModelInfo;
+use crate::metrics::prometheus::{BuildInfo, FEATURES};
+use crate::webrtc::QualityStats;
+use ::prometheus::{
+    Counter, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry,
+};
+
+/// Stage label of codec decoding
+pub const STAGE_DECODE: &str = "decode";
+/// Stage label of codec encoding
+pub const STAGE_ENCODE: &str = "encode";
+/// Payload type label of audio not carried over RTP
+const NO_PAYLOAD_TYPE: &str = "none";
+
+/// Loss label of media events dropped because the event stream was full
+pub const LOSS_MEDIA_EVENT: &str = "media_event";
+/// Loss label of playback audio dropped because the playback queue was full
+pub const LOSS_PLAYBACK_AUDIO: &str = "playback_audio";
+/// Loss label of messages rejected by a full `MessageBuffer`
+pub const LOSS_MESSAGE_BUFFER: &str = "message_buffer";
+/// Loss label of packets evicted from a full jitter buffer
+pub const LOSS_JITTER_BUFFER: &str = "jitter_buffer";
+/// Loss label of sends on a closed channel
+pub const LOSS_CHANNEL_SEND: &str = "channel_send";
+/// Loss label of audio dropped because the ASR bridge queue was full
+pub const LOSS_ASR_AUDIO: &str = "asr_audio";
+/// Outcome label of sessions handed off to another instance
+pub const OUTCOME_MIGRATED: &str = "migrated";
+/// Outcome label of losses not tied to a session
+pub const OUTCOME_NONE: &str = "none";
 
 /// Centralized metrics collection
 pub struct Metrics {
     pub registry: Registry,
     pub active_connections: IntGauge,
     pub rtp_packets_received: Counter,
+    pub rtp_reactor_packets: IntCounterVec,
     pub audio_frames_processed: Counter,
+    pub audio_frame_errors: Counter,
     pub turn_events_detected: Counter,
     pub processing_latency_ms: Histogram,
     pub grpc_messages_sent: Counter,
@@ -20,11 +60,62 @@ pub struct Metrics {
     pub turn_starts: Counter,
     pub turn_ends: Counter,
     pub barge_ins: Counter,
+    pub budget_overruns: Counter,
+    pub adapted_vad_threshold: Histogram,
+    pub adapted_volume_threshold_db: Histogram,
+    pub overlaps: Counter,
+    pub overlap_duration_ms: Histogram,
+    pub echo_loops: Counter,
+    pub auth_requests: IntCounterVec,
+    pub auth_failures: Counter,
+    pub sink_events_delivered: IntCounterVec,
+    pub sink_events_failed: IntCounterVec,
+    pub sink_events_dropped: IntCounterVec,
+    pub sink_queue_depth: IntGaugeVec,
+    pub asr_transcripts: IntCounterVec,
+    pub asr_errors: Counter,
+    pub stage_latency_ms: HistogramVec,
+    pub audio_packets_decoded: IntCounterVec,
+    pub session_jitter_ms: Histogram,
+    pub session_packet_loss_ratio: Histogram,
+    pub session_rtt_ms: Histogram,
+    pub session_mos: Histogram,
+    pub connection_state_seconds: HistogramVec,
+    pub reconnections: IntCounterVec,
+    pub clock_compensated_samples: IntCounterVec,
+    pub endpointing_latency_ms: HistogramVec,
+    pub barge_in_reaction_ms: HistogramVec,
+    pub endpointing_target_ms: IntGauge,
+    pub barge_in_target_ms: IntGauge,
+    pub runtime_workers: IntGauge,
+    pub runtime_alive_tasks: IntGauge,
+    pub runtime_global_queue_depth: IntGauge,
+    pub runtime_worker_busy_ratio: GaugeVec,
+    pub buffer_pool_idle: IntGaugeVec,
+    pub buffer_pool_buffers: IntCounterVec,
+    pub task_poll_duration_ms: HistogramVec,
+    pub audio_worker_utilization: Gauge,
+    pub audio_worker_saturation: Gauge,
+    pub data_lost: IntCounterVec,
+    pub model_inference_latency_ms: HistogramVec,
+    pub model_batch_size: HistogramVec,
+    /// 1 for the provider and hash of each loaded model
+    pub model_info: IntGaugeVec,
+    pub model_inference_failures: IntCounterVec,
+    pub model_fallbacks: IntCounterVec,
+    pub errors: IntCounterVec,
+    pub panics: IntCounterVec,
+    pub health_score: Gauge,
+    pub slo_burn_rate: GaugeVec,
+    /// Always 1, labelled with the version, commit and features of the build
+    pub build_info: IntGaugeVec,
+    pub tenant_sessions: IntGaugeVec,
+    pub tenant_sessions_rejected: IntCounterVec,
 }
 
 impl Metrics {
     /// Create a new Metrics instance
-    pub fn new(_config: &Config) -> Self {
+    pub fn new(config: &Config) -> Self {
         let registry = Registry::new();
 
         let active_connections = IntGauge::new(
@@ -45,6 +136,12 @@ impl Metrics {
         )
         .expect("Failed to create metric");
 
+        let audio_frame_errors = Counter::new(
+            "amwaj_audio_frame_errors_total",
+            "Total audio frames that failed to process",
+        )
+        .expect("Failed to create metric");
+
         let turn_events_detected = Counter::new(
             "amwaj_turn_events_detected_total",
             "Total turn events detected",
@@ -84,6 +181,432 @@ impl Metrics {
         let barge_ins = Counter::new("amwaj_barge_ins_total", "Total barge-in events detected")
             .expect("Failed to create metric");
 
+        let budget_overruns = Counter::new(
+            "amwaj_processing_budget_overruns_total",
+            "Total audio frames that exceeded the processing budget",
+        )
+        .expect("Failed to create metric");
+
+        let adapted_vad_opts = HistogramOpts::new(
+            "amwaj_adapted_vad_threshold",
+            "Adapted VAD enter threshold at turn start",
+        )
+        .buckets(vec![0.4, 0.5, 0.6, 0.7, 0.8, 0.9]);
+        let adapted_vad_threshold =
+            Histogram::with_opts(adapted_vad_opts).expect("Failed to create metric");
+
+        let adapted_volume_opts = HistogramOpts::new(
+            "amwaj_adapted_volume_threshold_db",
+            "Adapted volume threshold in dB at turn start",
+        )
+        .buckets(vec![-60.0, -50.0, -40.0, -30.0, -20.0]);
+        let adapted_volume_threshold_db =
+            Histogram::with_opts(adapted_volume_opts).expect("Failed to create metric");
+
+        let overlaps = Counter::new(
+            "amwaj_overlaps_total",
+            "Total overlapping user and agent speech events",
+        )
+        .expect("Failed to create metric");
+
+        let echo_loops = Counter::new(
+            "amwaj_echo_loops_total",
+            "Total caller speech segments matched to the agent's own played audio",
+        )
+        .expect("Failed to create metric");
+
+        let overlap_duration_opts = HistogramOpts::new(
+            "amwaj_overlap_duration_ms",
+            "Duration of overlapping user and agent speech in milliseconds",
+        )
+        .buckets(vec![100.0, 250.0, 500.0, 1000.0, 2000.0, 5000.0]);
+        let overlap_duration_ms =
+            Histogram::with_opts(overlap_duration_opts).expect("Failed to create metric");
+
+        let auth_requests = IntCounterVec::new(
+            Opts::new(
+                "amwaj_auth_requests_total",
+                "Total authenticated gRPC calls per API key principal, JWT callers as other",
+            ),
+            &["principal"],
+        )
+        .expect("Failed to create metric");
+
+        let auth_failures = Counter::new(
+            "amwaj_auth_failures_total",
+            "Total gRPC calls rejected as unauthenticated",
+        )
+        .expect("Failed to create metric");
+
+        let sink_events_delivered = IntCounterVec::new(
+            Opts::new(
+                "amwaj_sink_events_delivered_total",
+                "Total lifecycle events delivered per sink",
+            ),
+            &["sink"],
+        )
+        .expect("Failed to create metric");
+
+        let sink_events_failed = IntCounterVec::new(
+            Opts::new(
+                "amwaj_sink_events_failed_total",
+                "Total lifecycle events a sink failed to deliver",
+            ),
+            &["sink"],
+        )
+        .expect("Failed to create metric");
+
+        let sink_events_dropped = IntCounterVec::new(
+            Opts::new(
+                "amwaj_sink_events_dropped_total",
+                "Total lifecycle events dropped because a sink queue was full",
+            ),
+            &["sink"],
+        )
+        .expect("Failed to create metric");
+
+        let sink_queue_depth = IntGaugeVec::new(
+            Opts::new(
+                "amwaj_sink_queue_depth",
+                "Lifecycle events waiting for delivery per sink",
+            ),
+            &["sink"],
+        )
+        .expect("Failed to create metric");
+
+        let asr_transcripts = IntCounterVec::new(
+            Opts::new(
+                "amwaj_asr_transcripts_total",
+                "Total transcripts received from the external recognizer, partial or final",
+            ),
+            &["kind"],
+        )
+        .expect("Failed to create metric");
+
+        let asr_errors = Counter::new(
+            "amwaj_asr_errors_total",
+            "Total failed connections to and errors from the external recognizer",
+        )
+        .expect("Failed to create metric");
+
+        let stage_latency_opts = HistogramOpts::new(
+            "amwaj_stage_latency_ms",
+            "Latency of one audio pipeline stage in milliseconds, per codec",
+        )
+        .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0]);
+        let stage_latency_ms = HistogramVec::new(stage_latency_opts, &["stage", "codec"])
+            .expect("Failed to create metric");
+
+        let audio_packets_decoded = IntCounterVec::new(
+            Opts::new(
+                "amwaj_audio_packets_decoded_total",
+                "Total audio chunks and RTP packets decoded per codec and payload type",
+            ),
+            &["codec", "payload_type"],
+        )
+        .expect("Failed to create metric");
+
+        let session_jitter_ms = Histogram::with_opts(
+            HistogramOpts::new(
+                "amwaj_session_jitter_ms",
+                "Interarrival jitter of WebRTC sessions in milliseconds, per RTCP report",
+            )
+            .buckets(vec![1.0, 5.0, 10.0, 20.0, 30.0, 50.0, 100.0]),
+        )
+        .expect("Failed to create metric");
+
+        let session_packet_loss_ratio = Histogram::with_opts(
+            HistogramOpts::new(
+                "amwaj_session_packet_loss_ratio",
+                "Inbound packet loss of WebRTC sessions, per RTCP report",
+            )
+            .buckets(vec![0.001, 0.005, 0.01, 0.02, 0.05, 0.1, 0.2]),
+        )
+        .expect("Failed to create metric");
+
+        let session_rtt_ms = Histogram::with_opts(
+            HistogramOpts::new(
+                "amwaj_session_rtt_ms",
+                "Round-trip time of WebRTC sessions in milliseconds, from RTCP",
+            )
+            .buckets(vec![25.0, 50.0, 100.0, 150.0, 200.0, 300.0, 500.0, 1000.0]),
+        )
+        .expect("Failed to create metric");
+
+        let session_mos = Histogram::with_opts(
+            HistogramOpts::new(
+                "amwaj_session_mos",
+                "Estimated MOS of WebRTC sessions, per RTCP report",
+            )
+            .buckets(vec![1.5, 2.0, 2.5, 3.0, 3.5, 3.8, 4.0, 4.2, 4.4]),
+        )
+        .expect("Failed to create metric");
+
+        let connection_state_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "amwaj_connection_state_seconds",
+                "Time WebRTC connections spent in a state before leaving it, per state",
+            )
+            .buckets(vec![
+                0.1, 0.5, 1.0, 2.5, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0,
+            ]),
+            &["state"],
+        )
+        .expect("Failed to create metric");
+
+        let reconnections = IntCounterVec::new(
+            Opts::new(
+                "amwaj_reconnections_total",
+                "Sessions whose WebRTC connection dropped, by whether it was restored in time",
+            ),
+            &["outcome"],
+        )
+        .expect("Failed to create metric");
+
+        let clock_compensated_samples = IntCounterVec::new(
+            Opts::new(
+                "amwaj_clock_compensated_samples_total",
+                "Samples of inbound RTP inserted or deleted to make up for sender clock drift",
+            ),
+            &["action"],
+        )
+        .expect("Failed to create metric");
+
+        let endpointing_latency_ms = HistogramVec::new(
+            HistogramOpts::new(
+                "amwaj_endpointing_latency_ms",
+                "Delay from the last speech frame to TurnEnded in milliseconds, per detector",
+            )
+            .buckets(vec![
+                100.0, 200.0, 300.0, 400.0, 500.0, 600.0, 800.0, 1000.0, 1500.0, 2000.0, 3000.0,
+            ]),
+            &["detector"],
+        )
+        .expect("Failed to create metric");
+
+        let barge_in_reaction_ms = HistogramVec::new(
+            HistogramOpts::new(
+                "amwaj_barge_in_reaction_ms",
+                "Delay from speech onset during playback to BargeIn in milliseconds, per detector",
+            )
+            .buckets(vec![
+                50.0, 100.0, 150.0, 200.0, 250.0, 300.0, 400.0, 500.0, 750.0, 1000.0,
+            ]),
+            &["detector"],
+        )
+        .expect("Failed to create metric");
+
+        let endpointing_target_ms = IntGauge::new(
+            "amwaj_endpointing_latency_target_ms",
+            "Configured p95 target of the endpointing latency",
+        )
+        .expect("Failed to create metric");
+        endpointing_target_ms.set(config.metrics.endpointing_target_ms as i64);
+
+        let barge_in_target_ms = IntGauge::new(
+            "amwaj_barge_in_reaction_target_ms",
+            "Configured p95 target of the barge-in reaction time",
+        )
+        .expect("Failed to create metric");
+        barge_in_target_ms.set(config.metrics.barge_in_target_ms as i64);
+
+        let runtime_workers =
+            IntGauge::new("amwaj_runtime_workers", "Number of Tokio worker threads")
+                .expect("Failed to create metric");
+
+        let runtime_alive_tasks = IntGauge::new(
+            "amwaj_runtime_alive_tasks",
+            "Number of Tokio tasks not finished yet",
+        )
+        .expect("Failed to create metric");
+
+        let runtime_global_queue_depth = IntGauge::new(
+            "amwaj_runtime_global_queue_depth",
+            "Tasks waiting in the Tokio global queue",
+        )
+        .expect("Failed to create metric");
+
+        let runtime_worker_busy_ratio = GaugeVec::new(
+            Opts::new(
+                "amwaj_runtime_worker_busy_ratio",
+                "Share of time each Tokio worker was busy since the last sample",
+            ),
+            &["worker"],
+        )
+        .expect("Failed to create metric");
+
+        let rtp_reactor_packets = IntCounterVec::new(
+            Opts::new(
+                "amwaj_rtp_reactor_packets_total",
+                "Datagrams received on the shared RTP port, per receive task",
+            ),
+            &["reactor"],
+        )
+        .expect("Failed to create metric");
+
+        let buffer_pool_idle = IntGaugeVec::new(
+            Opts::new(
+                "amwaj_buffer_pool_idle",
+                "Buffers waiting for reuse in each packet path pool",
+            ),
+            &["pool"],
+        )
+        .expect("Failed to create metric");
+
+        let buffer_pool_buffers = IntCounterVec::new(
+            Opts::new(
+                "amwaj_buffer_pool_buffers_total",
+                "Buffers taken from or given back to each packet path pool, by outcome",
+            ),
+            &["pool", "outcome"],
+        )
+        .expect("Failed to create metric");
+
+        let task_poll_duration_ms = HistogramVec::new(
+            HistogramOpts::new(
+                "amwaj_task_poll_duration_ms",
+                "Duration of one poll of a stream task in milliseconds, per task",
+            )
+            .buckets(vec![0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 50.0]),
+            &["task"],
+        )
+        .expect("Failed to create metric");
+
+        let audio_worker_utilization = Gauge::new(
+            "amwaj_audio_worker_utilization",
+            "Cores busy processing audio, averaged since the last load report",
+        )
+        .expect("Failed to create metric");
+
+        let audio_worker_saturation = Gauge::new(
+            "amwaj_audio_worker_saturation",
+            "Share of the cores busy processing audio, 0 to 1",
+        )
+        .expect("Failed to create metric");
+
+        let data_lost = IntCounterVec::new(
+            Opts::new(
+                "amwaj_data_lost_total",
+                "Total events, audio and packets dropped under load, by kind and session outcome",
+            ),
+            &["kind", "outcome"],
+        )
+        .expect("Failed to create metric");
+
+        let model_inference_latency_ms = HistogramVec::new(
+            HistogramOpts::new(
+                "amwaj_model_inference_latency_ms",
+                "Latency of one model inference in milliseconds, per model",
+            )
+            .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0]),
+            &["model"],
+        )
+        .expect("Failed to create metric");
+
+        let model_batch_size = HistogramVec::new(
+            HistogramOpts::new(
+                "amwaj_model_batch_size",
+                "Inputs per model inference, per model",
+            )
+            .buckets(vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0]),
+            &["model"],
+        )
+        .expect("Failed to create metric");
+
+        let model_info = IntGaugeVec::new(
+            Opts::new(
+                "amwaj_model_info",
+                "Loaded models, labelled with their execution provider and file hash",
+            ),
+            &["model", "provider", "hash"],
+        )
+        .expect("Failed to create metric");
+
+        let model_inference_failures = IntCounterVec::new(
+            Opts::new(
+                "amwaj_model_inference_failures_total",
+                "Total failed model inferences, per model",
+            ),
+            &["model"],
+        )
+        .expect("Failed to create metric");
+
+        let model_fallbacks = IntCounterVec::new(
+            Opts::new(
+                "amwaj_model_fallbacks_total",
+                "Total times a heuristic stood in for a model that failed to load or run",
+            ),
+            &["model"],
+        )
+        .expect("Failed to create metric");
+
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "amwaj_errors_total",
+                "Total failed calls by error code and severity",
+            ),
+            &["code", "severity"],
+        )
+        .expect("Failed to create metric");
+
+        let panics = IntCounterVec::new(
+            Opts::new(
+                "amwaj_panics_total",
+                "Total panics caught in session processing and stream tasks",
+            ),
+            &["task"],
+        )
+        .expect("Failed to create metric");
+
+        let health_score = Gauge::new(
+            "amwaj_health_score",
+            "Health of the instance over the rolling window, 0 to 1",
+        )
+        .expect("Failed to create metric");
+        health_score.set(1.0);
+
+        let slo_burn_rate = GaugeVec::new(
+            Opts::new(
+                "amwaj_slo_burn_rate",
+                "Rate at which a signal uses up its budget over the rolling window, per signal",
+            ),
+            &["signal"],
+        )
+        .expect("Failed to create metric");
+
+        // A label per feature, `redis="true"`, so any of them can be queried
+        let build = BuildInfo::current();
+        let mut build_labels = vec!["version", "git_sha"];
+        let mut build_values = vec![build.version, build.git_sha];
+        for (feature, enabled) in FEATURES {
+            build_labels.push(feature.trim_end_matches("-feature"));
+            build_values.push(if enabled { "true" } else { "false" });
+        }
+        let build_info = IntGaugeVec::new(
+            Opts::new(
+                "amwaj_build_info",
+                "Build of the server, labelled with its version, commit and cargo features",
+            ),
+            &build_labels,
+        )
+        .expect("Failed to create metric");
+        build_info.with_label_values(&build_values).set(1);
+
+        let tenant_sessions = IntGaugeVec::new(
+            Opts::new("amwaj_tenant_sessions", "Live sessions per tenant"),
+            &["tenant"],
+        )
+        .expect("Failed to create metric");
+
+        let tenant_sessions_rejected = IntCounterVec::new(
+            Opts::new(
+                "amwaj_tenant_sessions_rejected_total",
+                "Total sessions refused by the policy of their tenant",
+            ),
+            &["tenant", "reason"],
+        )
+        .expect("Failed to create metric");
+
         // Register all metrics
         registry
             .register(Box::new(active_connections.clone()))
@@ -91,9 +614,15 @@ impl Metrics {
         registry
             .register(Box::new(rtp_packets_received.clone()))
             .unwrap();
+        registry
+            .register(Box::new(rtp_reactor_packets.clone()))
+            .unwrap();
         registry
             .register(Box::new(audio_frames_processed.clone()))
             .unwrap();
+        registry
+            .register(Box::new(audio_frame_errors.clone()))
+            .unwrap();
         registry
             .register(Box::new(turn_events_detected.clone()))
             .unwrap();
@@ -110,12 +639,131 @@ impl Metrics {
         registry.register(Box::new(turn_starts.clone())).unwrap();
         registry.register(Box::new(turn_ends.clone())).unwrap();
         registry.register(Box::new(barge_ins.clone())).unwrap();
+        registry
+            .register(Box::new(budget_overruns.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(adapted_vad_threshold.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(adapted_volume_threshold_db.clone()))
+            .unwrap();
+        registry.register(Box::new(overlaps.clone())).unwrap();
+        registry.register(Box::new(echo_loops.clone())).unwrap();
+        registry
+            .register(Box::new(overlap_duration_ms.clone()))
+            .unwrap();
+        registry.register(Box::new(auth_requests.clone())).unwrap();
+        registry.register(Box::new(auth_failures.clone())).unwrap();
+        registry
+            .register(Box::new(sink_events_delivered.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sink_events_failed.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sink_events_dropped.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sink_queue_depth.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(asr_transcripts.clone()))
+            .unwrap();
+        registry.register(Box::new(asr_errors.clone())).unwrap();
+        registry
+            .register(Box::new(stage_latency_ms.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(audio_packets_decoded.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(session_jitter_ms.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(session_packet_loss_ratio.clone()))
+            .unwrap();
+        registry.register(Box::new(session_rtt_ms.clone())).unwrap();
+        registry.register(Box::new(session_mos.clone())).unwrap();
+        registry
+            .register(Box::new(connection_state_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(reconnections.clone())).unwrap();
+        registry
+            .register(Box::new(clock_compensated_samples.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(endpointing_latency_ms.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(barge_in_reaction_ms.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(endpointing_target_ms.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(barge_in_target_ms.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(runtime_workers.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(runtime_alive_tasks.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(runtime_global_queue_depth.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(runtime_worker_busy_ratio.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(buffer_pool_idle.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(buffer_pool_buffers.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(task_poll_duration_ms.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(audio_worker_utilization.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(audio_worker_saturation.clone()))
+            .unwrap();
+        registry.register(Box::new(data_lost.clone())).unwrap();
+        registry
+            .register(Box::new(model_inference_latency_ms.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(model_batch_size.clone()))
+            .unwrap();
+        registry.register(Box::new(model_info.clone())).unwrap();
+        registry
+            .register(Box::new(model_inference_failures.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(model_fallbacks.clone()))
+            .unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry.register(Box::new(panics.clone())).unwrap();
+        registry.register(Box::new(health_score.clone())).unwrap();
+        registry.register(Box::new(slo_burn_rate.clone())).unwrap();
+        registry.register(Box::new(build_info.clone())).unwrap();
+        registry
+            .register(Box::new(tenant_sessions.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(tenant_sessions_rejected.clone()))
+            .unwrap();
 
         Self {
             registry,
             active_connections,
             rtp_packets_received,
+            rtp_reactor_packets,
             audio_frames_processed,
+            audio_frame_errors,
             turn_events_detected,
             processing_latency_ms,
             grpc_messages_sent,
@@ -124,14 +772,210 @@ impl Metrics {
             turn_starts,
             turn_ends,
             barge_ins,
+            budget_overruns,
+            adapted_vad_threshold,
+            adapted_volume_threshold_db,
+            overlaps,
+            overlap_duration_ms,
+            echo_loops,
+            auth_requests,
+            auth_failures,
+            sink_events_delivered,
+            sink_events_failed,
+            sink_events_dropped,
+            sink_queue_depth,
+            asr_transcripts,
+            asr_errors,
+            stage_latency_ms,
+            audio_packets_decoded,
+            session_jitter_ms,
+            session_packet_loss_ratio,
+            session_rtt_ms,
+            session_mos,
+            connection_state_seconds,
+            reconnections,
+            clock_compensated_samples,
+            endpointing_latency_ms,
+            barge_in_reaction_ms,
+            endpointing_target_ms,
+            barge_in_target_ms,
+            runtime_workers,
+            runtime_alive_tasks,
+            runtime_global_queue_depth,
+            runtime_worker_busy_ratio,
+            buffer_pool_idle,
+            buffer_pool_buffers,
+            task_poll_duration_ms,
+            audio_worker_utilization,
+            audio_worker_saturation,
+            data_lost,
+            model_inference_latency_ms,
+            model_batch_size,
+            model_info,
+            model_inference_failures,
+            model_fallbacks,
+            errors,
+            panics,
+            health_score,
+            slo_burn_rate,
+            build_info,
+            tenant_sessions,
+            tenant_sessions_rejected,
         }
     }
 
+    /// Export the latency targets of a reloaded configuration
+    pub fn set_latency_targets(&self, config: &MetricsConfig) {
+        self.endpointing_target_ms
+            .set(config.endpointing_target_ms as i64);
+        self.barge_in_target_ms
+            .set(config.barge_in_target_ms as i64);
+    }
+
     /// Record processing latency
     pub fn record_latency(&self, latency_ms: f64) {
         self.processing_latency_ms.observe(latency_ms);
     }
 
+    /// Record the latency of a pipeline stage for a codec
+    pub fn record_stage_latency(&self, stage: &str, codec: &str, latency_ms: f64) {
+        self.stage_latency_ms
+            .with_label_values(&[stage, codec])
+            .observe(latency_ms);
+    }
+
+    /// Record the latency of each processing stage of a frame
+    pub fn record_stage_timings(&self, codec: &str, timings: &StageTimings) {
+        for stage in [
+            ProcessingStage::VoiceIsolation,
+            ProcessingStage::Features,
+            ProcessingStage::Vad,
+        ] {
+            self.record_stage_latency(stage.as_str(), codec, timings.stage_ms(stage));
+        }
+    }
+
+    /// Record a decoded chunk or packet, `payload_type` set for RTP
+    pub fn record_decode(&self, codec: &str, payload_type: Option<u8>, latency_ms: f64) {
+        let payload_type = payload_type.map(|pt| pt.to_string());
+        self.audio_packets_decoded
+            .with_label_values(&[codec, payload_type.as_deref().unwrap_or(NO_PAYLOAD_TYPE)])
+            .inc();
+        self.record_stage_latency(STAGE_DECODE, codec, latency_ms);
+    }
+
+    /// Record the call quality of a session
+    pub fn record_quality(&self, quality: &QualityStats) {
+        self.session_jitter_ms.observe(quality.jitter_ms as f64);
+        self.session_packet_loss_ratio
+            .observe(quality.packet_loss_ratio as f64);
+        if let Some(rtt_ms) = quality.rtt_ms {
+            self.session_rtt_ms.observe(rtt_ms as f64);
+        }
+        self.session_mos.observe(quality.mos as f64);
+    }
+
+    /// Record the time a connection spent in a state it left
+    pub fn record_connection_state(&self, state: &str, seconds: f64) {
+        self.connection_state_seconds
+            .with_label_values(&[state])
+            .observe(seconds);
+    }
+
+    /// Record a dropped connection being restored, or the session ending
+    /// without it
+    pub fn record_reconnection(&self, restored: bool) {
+        let outcome = if restored { "reconnected" } else { "expired" };
+        self.reconnections.with_label_values(&[outcome]).inc();
+    }
+
+    /// Record samples inserted or deleted to make up for clock drift
+    pub fn record_clock_compensation(&self, inserted: u64, deleted: u64) {
+        if inserted > 0 {
+            self.clock_compensated_samples
+                .with_label_values(&["inserted"])
+                .inc_by(inserted);
+        }
+        if deleted > 0 {
+            self.clock_compensated_samples
+                .with_label_values(&["deleted"])
+                .inc_by(deleted);
+        }
+    }
+
+    /// Record how busy audio processing keeps the cores
+    pub fn record_audio_load(&self, utilization: f32, cpu_headroom: f32) {
+        self.audio_worker_utilization.set(utilization as f64);
+        self.audio_worker_saturation
+            .set((1.0 - cpu_headroom as f64).clamp(0.0, 1.0));
+    }
+
+    /// Record data dropped under load, `outcome` being how its session ended
+    pub fn record_data_loss(&self, kind: &str, outcome: &str, count: u64) {
+        if count > 0 {
+            self.data_lost
+                .with_label_values(&[kind, outcome])
+                .inc_by(count);
+        }
+    }
+
+    /// Record a session refused by its tenant's policy, `reason` being
+    /// `quota` or `codec`
+    pub fn record_tenant_rejection(&self, tenant: &str, reason: &str) {
+        self.tenant_sessions_rejected
+            .with_label_values(&[tenant, reason])
+            .inc();
+    }
+
+    /// Record a model being loaded
+    pub fn record_model_loaded(&self, model: &str, info: &ModelInfo) {
+        self.model_info
+            .with_label_values(&[model, &info.provider, &info.hash])
+            .set(1);
+    }
+
+    /// Record a successful inference over `batch_size` inputs
+    pub fn record_inference(&self, model: &str, batch_size: usize, latency_ms: f64) {
+        self.model_inference_latency_ms
+            .with_label_values(&[model])
+            .observe(latency_ms);
+        self.model_batch_size
+            .with_label_values(&[model])
+            .observe(batch_size as f64);
+    }
+
+    /// Record a failed inference, and whether a heuristic took over
+    pub fn record_inference_failure(&self, model: &str, fell_back: bool) {
+        self.model_inference_failures
+            .with_label_values(&[model])
+            .inc();
+        if fell_back {
+            self.record_model_fallback(model);
+        }
+    }
+
+    /// Record a heuristic standing in for a model
+    pub fn record_model_fallback(&self, model: &str) {
+        self.model_fallbacks.with_label_values(&[model]).inc();
+    }
+
+    /// Record a call failing with a typed error
+    pub fn record_error(&self, error: &AmwajError) {
+        self.errors
+            .with_label_values(&[error.code().as_str_name(), error.severity().as_str()])
+            .inc();
+    }
+
+    /// Record a panic caught in a task, by its `runtime::TASK_*` label
+    pub fn record_panic(&self, task: &str) {
+        self.panics.with_label_values(&[task]).inc();
+    }
+
+    /// Record the latency of encoding a frame
+    pub fn record_encode(&self, codec: &str, latency_ms: f64) {
+        self.record_stage_latency(STAGE_ENCODE, codec, latency_ms);
+    }
+
     /// Increment connection count
     pub fn connection_opened(&self) {
         self.active_connections.inc();
@@ -158,6 +1002,81 @@ impl Metrics {
     pub fn record_barge_in(&self) {
         self.barge_ins.inc();
     }
+
+    /// Record the delay from the end of speech to `TurnEnded`
+    pub fn record_endpointing_latency(&self, detector: &str, latency_ms: f64) {
+        self.endpointing_latency_ms
+            .with_label_values(&[detector])
+            .observe(latency_ms);
+    }
+
+    /// Record the delay from speech onset to `BargeIn`
+    pub fn record_barge_in_reaction(&self, detector: &str, reaction_ms: f64) {
+        self.barge_in_reaction_ms
+            .with_label_values(&[detector])
+            .observe(reaction_ms);
+    }
+
+    /// Record overlapping user and agent speech
+    pub fn record_overlap(&self, duration_ms: u32) {
+        self.overlaps.inc();
+        self.overlap_duration_ms.observe(duration_ms as f64);
+    }
+
+    /// Record an echo loop caught and its turn dropped
+    pub fn record_echo_loop(&self) {
+        self.echo_loops.inc();
+    }
+
+    /// Record an authenticated call
+    pub fn record_auth_success(&self, principal: &str) {
+        self.auth_requests.with_label_values(&[principal]).inc();
+    }
+
+    /// Record a call rejected as unauthenticated
+    pub fn record_auth_failure(&self) {
+        self.auth_failures.inc();
+    }
+
+    /// Record the outcome of a sink delivery
+    pub fn record_sink_delivery(&self, sink: &str, delivered: bool) {
+        if delivered {
+            self.sink_events_delivered.with_label_values(&[sink]).inc();
+        } else {
+            self.sink_events_failed.with_label_values(&[sink]).inc();
+        }
+    }
+
+    /// Record an event dropped because a sink queue was full
+    pub fn record_sink_drop(&self, sink: &str) {
+        self.sink_events_dropped.with_label_values(&[sink]).inc();
+    }
+
+    /// Record a transcript received from the external recognizer
+    pub fn record_asr_transcript(&self, is_final: bool) {
+        let kind = if is_final { "final" } else { "partial" };
+        self.asr_transcripts.with_label_values(&[kind]).inc();
+    }
+
+    /// Set the number of events queued for a sink
+    pub fn set_sink_queue_depth(&self, sink: &str, depth: usize) {
+        self.sink_queue_depth
+            .with_label_values(&[sink])
+            .set(depth as i64);
+    }
+
+    /// Record a frame that exceeded the processing budget
+    pub fn record_budget_overrun(&self) {
+        self.budget_overruns.inc();
+    }
+
+    /// Record the detection thresholds in effect for a session
+    pub fn record_adapted_thresholds(&self, vad_threshold_enter: f32, volume_threshold_db: f32) {
+        self.adapted_vad_threshold
+            .observe(vad_threshold_enter as f64);
+        self.adapted_volume_threshold_db
+            .observe(volume_threshold_db as f64);
+    }
 }
 
 pub use latency_tracker::LatencyTracker;
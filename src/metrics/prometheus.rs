@@ -1,35 +1,311 @@
-//! Prometheus metrics exporter
+//! HTTP endpoint for metrics and health probes
+//!
+//! Serves `/metrics` in the Prometheus text format, `/healthz` for liveness,
+//! `/readyz` for readiness and `/buildinfo`. Readiness fails until the gRPC
+//! server is up and again once it starts draining, so load balancers stop
+//! routing new sessions while the old ones wind down. HEAD is answered on
+//! every path. With a health score attached, readiness also fails while the
+//! instance is degraded. Other routes, like the admin REST API, can share
+//! the port.
 
+use crate::metrics::health::HealthScore;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
 use prometheus::{Encoder, TextEncoder};
+use serde::Serialize;
+use std::future::Future;
 use std::net::SocketAddr;
-use tokio::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-pub async fn start_metrics_server(
-    addr: SocketAddr,
+/// Whether the server takes new traffic, reported on `/readyz`
+#[derive(Debug, Clone, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    /// Create a flag that is not ready yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the server as ready or not
+    pub fn set_ready(&self, ready: bool) {
+        self.0.store(ready, Ordering::SeqCst);
+    }
+
+    /// Check if the server is ready
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Cargo features of the server and whether this build has them
+pub const FEATURES: [(&str, bool); 12] = [
+    ("webrtc-feature", cfg!(feature = "webrtc-feature")),
+    ("audio-feature", cfg!(feature = "audio-feature")),
+    ("opus-feature", cfg!(feature = "opus-feature")),
+    ("stun-feature", cfg!(feature = "stun-feature")),
+    ("redis-feature", cfg!(feature = "redis-feature")),
+    ("client-feature", cfg!(feature = "client-feature")),
+    ("kafka-feature", cfg!(feature = "kafka-feature")),
+    ("nats-feature", cfg!(feature = "nats-feature")),
+    ("otel-feature", cfg!(feature = "otel-feature")),
+    ("sip-feature", cfg!(feature = "sip-feature")),
+    ("impairment-feature", cfg!(feature = "impairment-feature")),
+    ("postgres-feature", cfg!(feature = "postgres-feature")),
+];
+
+/// Build of the running server, served on `/buildinfo`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    /// Commit the server was built from, `unknown` without git
+    pub git_sha: &'static str,
+    pub schema_version: u32,
+    /// Cargo features compiled in
+    pub features: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    /// Describe this build
+    pub fn current() -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("AMWAJ_GIT_SHA"),
+            schema_version: crate::grpc::capabilities::SCHEMA_VERSION,
+            features: FEATURES
+                .into_iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name)
+                .collect(),
+        }
+    }
+}
+
+/// What the metrics and probe handlers serve
+#[derive(Clone)]
+struct Probes {
     registry: prometheus::Registry,
-) -> anyhow::Result<()> {
-    let listener = TcpListener::bind(addr).await?;
-    tracing::info!("Prometheus metrics server listening on {}", addr);
-
-    loop {
-        let (stream, _) = listener.accept().await?;
-        let registry = registry.clone();
-
-        tokio::spawn(async move {
-            let encoder = TextEncoder::new();
-            let metric_families = registry.gather();
-            let mut buffer = Vec::new();
-            encoder.encode(&metric_families, &mut buffer).unwrap();
-
-            let response = format!(
-                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
-                buffer.len(),
-                String::from_utf8_lossy(&buffer)
-            );
-
-            use tokio::io::AsyncWriteExt;
-            let mut stream = stream;
-            let _ = stream.write_all(response.as_bytes()).await;
-        });
+    readiness: Readiness,
+    /// Health score and the score under which the server is not ready
+    health: Option<(HealthScore, f64)>,
+}
+
+/// HTTP server for metrics and health probes
+#[derive(Clone)]
+pub struct MetricsServer {
+    probes: Probes,
+    /// Extra routes served on the same port
+    routes: Option<Router>,
+}
+
+impl MetricsServer {
+    /// Create a server exposing `registry`
+    ///
+    /// It reports ready from the start, see `with_readiness` to tie it to
+    /// the gRPC server.
+    pub fn new(registry: prometheus::Registry) -> Self {
+        let readiness = Readiness::new();
+        readiness.set_ready(true);
+        Self {
+            probes: Probes {
+                registry,
+                readiness,
+                health: None,
+            },
+            routes: None,
+        }
+    }
+
+    /// Report readiness from a shared flag
+    pub fn with_readiness(mut self, readiness: Readiness) -> Self {
+        self.probes.readiness = readiness;
+        self
+    }
+
+    /// Serve extra routes next to the probes, like the admin REST API
+    pub fn with_routes(mut self, routes: Router) -> Self {
+        self.routes = Some(routes);
+        self
+    }
+
+    /// Fail readiness while the health score is under `min_score`
+    pub fn with_health(mut self, score: HealthScore, min_score: f64) -> Self {
+        self.probes.health = Some((score, min_score));
+        self
+    }
+
+    /// Get the routes of the server
+    pub fn router(&self) -> Router {
+        let router = Router::new()
+            .route("/metrics", get(metrics))
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
+            .route("/buildinfo", get(buildinfo))
+            .with_state(self.probes.clone());
+        match &self.routes {
+            Some(routes) => router.merge(routes.clone()),
+            None => router,
+        }
+    }
+
+    /// Serve on `addr` until `shutdown` completes
+    pub async fn serve(
+        self,
+        addr: SocketAddr,
+        shutdown: impl Future<Output = ()>,
+    ) -> anyhow::Result<()> {
+        let server = axum::Server::try_bind(&addr)?.serve(self.router().into_make_service());
+        tracing::info!("Metrics server listening on {}", addr);
+        server.with_graceful_shutdown(shutdown).await?;
+        Ok(())
+    }
+}
+
+async fn metrics(State(server): State<Probes>) -> Response {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&server.registry.gather(), &mut buffer) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+        .into_response()
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz(State(server): State<Probes>) -> (StatusCode, &'static str) {
+    if !server.readiness.is_ready() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "not ready");
+    }
+    match &server.health {
+        Some((score, min_score)) if score.get() < *min_score => {
+            (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+        }
+        _ => (StatusCode::OK, "ready"),
+    }
+}
+
+async fn buildinfo() -> Json<BuildInfo> {
+    Json(BuildInfo::current())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_server() {
+        let registry = prometheus::Registry::new();
+        let counter = prometheus::IntCounter::new("amwaj_test_total", "Test counter").unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        counter.inc();
+        let readiness = Readiness::new();
+        let server = MetricsServer::new(registry).with_readiness(readiness.clone());
+
+        let addr: SocketAddr = "127.0.0.1:59090".parse().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(server.serve(addr, async {
+            let _ = shutdown_rx.await;
+        }));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let url = |path: &str| format!("http://{}{}", addr, path);
+        let response = client.get(url("/metrics")).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains("amwaj_test_total 1"));
+        let head = client.head(url("/metrics")).send().await.unwrap();
+        assert_eq!(head.status(), 200);
+
+        let healthz = client.get(url("/healthz")).send().await.unwrap();
+        assert_eq!(healthz.status(), 200);
+        let readyz = client.get(url("/readyz")).send().await.unwrap();
+        assert_eq!(readyz.status(), 503);
+        readiness.set_ready(true);
+        let readyz = client.head(url("/readyz")).send().await.unwrap();
+        assert_eq!(readyz.status(), 200);
+
+        let info: serde_json::Value = client
+            .get(url("/buildinfo"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["git_sha"], env!("AMWAJ_GIT_SHA"));
+        let missing = client.get(url("/missing")).send().await.unwrap();
+        assert_eq!(missing.status(), 404);
+
+        shutdown_tx.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_readyz_degraded() {
+        let score = HealthScore::new();
+        let server =
+            MetricsServer::new(prometheus::Registry::new()).with_health(score.clone(), 0.5);
+        let addr: SocketAddr = "127.0.0.1:59091".parse().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(server.serve(addr, async {
+            let _ = shutdown_rx.await;
+        }));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let url = format!("http://{}/readyz", addr);
+        let readyz = reqwest::get(&url).await.unwrap();
+        assert_eq!(readyz.status(), 200);
+        score.set(0.2);
+        let readyz = reqwest::get(&url).await.unwrap();
+        assert_eq!(readyz.status(), 503);
+        assert_eq!(readyz.text().await.unwrap(), "degraded");
+
+        shutdown_tx.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_build_info_metric() {
+        let metrics = crate::metrics::Metrics::new(&crate::config::Config::default());
+        let families = metrics.registry.gather();
+        let family = families
+            .iter()
+            .find(|family| family.get_name() == "amwaj_build_info")
+            .unwrap();
+        let metric = &family.get_metric()[0];
+        assert_eq!(metric.get_gauge().get_value(), 1.0);
+        let label = |name: &str| {
+            metric
+                .get_label()
+                .iter()
+                .find(|label| label.get_name() == name)
+                .map(|label| label.get_value().to_string())
+        };
+        assert_eq!(label("version").unwrap(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(label("git_sha").unwrap(), env!("AMWAJ_GIT_SHA"));
+        let redis = if cfg!(feature = "redis-feature") {
+            "true"
+        } else {
+            "false"
+        };
+        assert_eq!(label("redis").unwrap(), redis);
+        assert_eq!(metric.get_label().len(), 2 + FEATURES.len());
     }
 }
@@ -0,0 +1,360 @@
+//! Metrics push for deployments that can't be scraped
+//!
+//! Serverless and short-lived instances push the same registry `/metrics`
+//! serves, either to a Prometheus Pushgateway, replacing the instance's
+//! group on every push, or to a remote-write endpoint as snappy-compressed
+//! protobuf. A last push on shutdown keeps the final counts.
+
+use crate::config::{MetricsPushConfig, PushMode};
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::{Encoder, Registry, TextEncoder};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+/// Version of the remote-write protocol spoken
+const REMOTE_WRITE_VERSION: &str = "0.1.0";
+/// Largest literal of the snappy encoding with a two-byte length
+const SNAPPY_MAX_LITERAL: usize = 1 << 16;
+
+/// Remote-write request, from `prometheus/prompb/remote.proto`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+}
+
+/// Samples of one series, its labels sorted by name
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
+
+/// Pushes a registry on an interval
+pub struct MetricsPusher {
+    registry: Registry,
+    config: MetricsPushConfig,
+    client: reqwest::Client,
+}
+
+impl MetricsPusher {
+    /// Push `registry` as configured
+    pub fn new(registry: Registry, config: MetricsPushConfig) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+        Ok(Self {
+            registry,
+            config,
+            client,
+        })
+    }
+
+    /// Push the current values once
+    pub async fn push(&self) -> anyhow::Result<()> {
+        let request = match self.config.mode {
+            PushMode::Pushgateway => {
+                let mut body = Vec::new();
+                TextEncoder::new().encode(&self.registry.gather(), &mut body)?;
+                self.client
+                    .put(pushgateway_url(&self.config))
+                    .header(
+                        reqwest::header::CONTENT_TYPE,
+                        TextEncoder::new().format_type(),
+                    )
+                    .body(body)
+            }
+            PushMode::RemoteWrite => {
+                let timestamp_ms = chrono::Utc::now().timestamp_millis();
+                let write = write_request(
+                    &self.registry.gather(),
+                    &self.external_labels(),
+                    timestamp_ms,
+                );
+                self.client
+                    .post(&self.config.url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+                    .header(reqwest::header::CONTENT_ENCODING, "snappy")
+                    .header("X-Prometheus-Remote-Write-Version", REMOTE_WRITE_VERSION)
+                    .body(snappy_compress(&prost::Message::encode_to_vec(&write)))
+            }
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Push every `interval_seconds` until `shutdown` completes, then once
+    /// more
+    pub async fn run(self, shutdown: impl Future<Output = ()>) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.interval_seconds.max(1)));
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = &mut shutdown => break,
+            }
+            if let Err(e) = self.push().await {
+                tracing::warn!("Failed to push metrics to {}: {}", self.config.url, e);
+            }
+        }
+        if let Err(e) = self.push().await {
+            tracing::warn!("Failed to push final metrics to {}: {}", self.config.url, e);
+        }
+    }
+
+    /// Labels identifying this instance's series
+    fn external_labels(&self) -> BTreeMap<String, String> {
+        let mut labels = self.config.labels.clone();
+        labels.insert("job".to_string(), self.config.job.clone());
+        if let Some(instance) = &self.config.instance {
+            labels.insert("instance".to_string(), instance.clone());
+        }
+        labels
+    }
+}
+
+/// Get the Pushgateway URL of the instance's group
+///
+/// Values that can't go in a path segment are base64-encoded, as the
+/// Pushgateway allows with the `@base64` suffix.
+pub fn pushgateway_url(config: &MetricsPushConfig) -> String {
+    let mut url = config.url.trim_end_matches('/').to_string();
+    let instance = config.instance.iter().map(|value| ("instance", value));
+    let labels = config
+        .labels
+        .iter()
+        .map(|(name, value)| (name.as_str(), value));
+    for (name, value) in std::iter::once(("job", &config.job))
+        .chain(instance)
+        .chain(labels)
+    {
+        if value.is_empty() {
+            // An empty value is spelled as a lone padding character
+            url.push_str(&format!("/{}@base64/=", name));
+        } else if !value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        {
+            url.push_str(&format!("/{}@base64/{}", name, URL_SAFE.encode(value)));
+        } else {
+            url.push_str(&format!("/{}/{}", name, value));
+        }
+    }
+    url
+}
+
+/// Convert gathered metrics to a remote-write request
+///
+/// Histograms and summaries are flattened into their `_bucket`, `_sum`
+/// and `_count` series as Prometheus would scrape them.
+pub fn write_request(
+    families: &[MetricFamily],
+    external_labels: &BTreeMap<String, String>,
+    timestamp_ms: i64,
+) -> WriteRequest {
+    let mut timeseries = Vec::new();
+    for family in families {
+        let name = family.get_name();
+        for metric in family.get_metric() {
+            let mut labels = external_labels.clone();
+            for pair in metric.get_label() {
+                labels.insert(pair.get_name().to_string(), pair.get_value().to_string());
+            }
+            let mut series = |suffix: &str, extra: Option<(&str, String)>, value: f64| {
+                let mut labels = labels.clone();
+                labels.insert("__name__".to_string(), format!("{}{}", name, suffix));
+                if let Some((label, label_value)) = extra {
+                    labels.insert(label.to_string(), label_value);
+                }
+                timeseries.push(TimeSeries {
+                    labels: labels
+                        .into_iter()
+                        .map(|(name, value)| Label { name, value })
+                        .collect(),
+                    samples: vec![Sample {
+                        value,
+                        timestamp: timestamp_ms,
+                    }],
+                });
+            };
+            match family.get_field_type() {
+                MetricType::COUNTER => series("", None, metric.get_counter().get_value()),
+                MetricType::GAUGE => series("", None, metric.get_gauge().get_value()),
+                MetricType::UNTYPED => series("", None, metric.get_untyped().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        series(
+                            "_bucket",
+                            Some(("le", bucket.get_upper_bound().to_string())),
+                            bucket.get_cumulative_count() as f64,
+                        );
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    series("_bucket", Some(("le", "+Inf".to_string())), count);
+                    series("_sum", None, histogram.get_sample_sum());
+                    series("_count", None, count);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        series(
+                            "",
+                            Some(("quantile", quantile.get_quantile().to_string())),
+                            quantile.get_value(),
+                        );
+                    }
+                    series("_sum", None, summary.get_sample_sum());
+                    series("_count", None, summary.get_sample_count() as f64);
+                }
+            }
+        }
+    }
+    WriteRequest { timeseries }
+}
+
+/// Compress in the snappy block format, as remote write requires
+///
+/// The data is stored as literals only: valid for any decoder and cheap,
+/// trading the compression ratio for not pulling in a codec.
+pub fn snappy_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / SNAPPY_MAX_LITERAL * 3 + 8);
+    let mut length = data.len() as u64;
+    while length >= 0x80 {
+        out.push((length as u8) | 0x80);
+        length >>= 7;
+    }
+    out.push(length as u8);
+    for literal in data.chunks(SNAPPY_MAX_LITERAL) {
+        let n = literal.len() - 1;
+        if n < 60 {
+            out.push((n as u8) << 2);
+        } else if n < 0x100 {
+            out.extend([60 << 2, n as u8]);
+        } else {
+            out.extend([61 << 2, n as u8, (n >> 8) as u8]);
+        }
+        out.extend_from_slice(literal);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{HistogramOpts, HistogramVec, IntCounter};
+
+    fn push_config(mode: PushMode) -> MetricsPushConfig {
+        MetricsPushConfig {
+            mode,
+            url: "http://pushgateway:9091/".to_string(),
+            job: "amwaj-media".to_string(),
+            instance: Some("pod-1".to_string()),
+            labels: BTreeMap::from([("zone".to_string(), "me-central/1a".to_string())]),
+            interval_seconds: 15,
+            timeout_ms: 1000,
+        }
+    }
+
+    #[test]
+    fn test_pushgateway_url() {
+        assert_eq!(
+            pushgateway_url(&push_config(PushMode::Pushgateway)),
+            "http://pushgateway:9091/job/amwaj-media/instance/pod-1/zone@base64/bWUtY2VudHJhbC8xYQ=="
+        );
+    }
+
+    #[test]
+    fn test_write_request() {
+        let registry = Registry::new();
+        let counter = IntCounter::new("amwaj_test_total", "Test counter").unwrap();
+        let histogram = HistogramVec::new(
+            HistogramOpts::new("amwaj_test_ms", "Test histogram").buckets(vec![10.0, 100.0]),
+            &["stage"],
+        )
+        .unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        counter.inc_by(3);
+        histogram.with_label_values(&["vad"]).observe(50.0);
+
+        let external = BTreeMap::from([("job".to_string(), "amwaj-media".to_string())]);
+        let write = write_request(&registry.gather(), &external, 1000);
+        let find = |name: &str, le: Option<&str>| {
+            write
+                .timeseries
+                .iter()
+                .find(|series| {
+                    series
+                        .labels
+                        .iter()
+                        .any(|label| label.name == "__name__" && label.value == name)
+                        && le.is_none_or(|le| {
+                            series
+                                .labels
+                                .iter()
+                                .any(|label| label.name == "le" && label.value == le)
+                        })
+                })
+                .unwrap()
+        };
+
+        let total = find("amwaj_test_total", None);
+        assert_eq!(
+            total.samples,
+            vec![Sample {
+                value: 3.0,
+                timestamp: 1000
+            }]
+        );
+        let names: Vec<&str> = total.labels.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["__name__", "job"]);
+        assert_eq!(
+            find("amwaj_test_ms_bucket", Some("10")).samples[0].value,
+            0.0
+        );
+        assert_eq!(
+            find("amwaj_test_ms_bucket", Some("+Inf")).samples[0].value,
+            1.0
+        );
+        assert_eq!(find("amwaj_test_ms_sum", None).samples[0].value, 50.0);
+        // Two buckets, +Inf, sum and count plus the counter
+        assert_eq!(write.timeseries.len(), 6);
+    }
+
+    #[test]
+    fn test_snappy_compress() {
+        assert_eq!(snappy_compress(b"abc"), vec![3, 2 << 2, b'a', b'b', b'c']);
+        assert_eq!(snappy_compress(b""), vec![0]);
+
+        let data = vec![7u8; 70_000];
+        let compressed = snappy_compress(&data);
+        // Varint length, then literals of 65536 and 4464 bytes
+        assert_eq!(&compressed[..3], &[0xF0, 0xA2, 0x04]);
+        assert_eq!(&compressed[3..6], &[61 << 2, 0xFF, 0xFF]);
+        assert_eq!(&compressed[65542..65545], &[61 << 2, 0x6F, 0x11]);
+        assert_eq!(compressed.len(), 70_000 + 9);
+    }
+}
@@ -0,0 +1,224 @@
+//! Tokio runtime metrics
+//!
+//! Samples the worker count, live tasks, global queue depth and how busy
+//! each worker is, along with the packet path buffer pools, and times every
+//! poll of the long-running stream tasks. Together with the audio
+//! utilization from the load reports they show how many sessions a core
+//! really takes.
+//!
+//! Stream tasks run under `supervise`, which counts a panic in one of them
+//! rather than letting it unwind into the connection serving it.
+
+use crate::metrics::Metrics;
+use crate::webrtc::pool;
+use prometheus::Histogram;
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+/// Task label of media streams
+pub const TASK_MEDIA_STREAM: &str = "media_stream";
+/// Task label of inbound audio streams
+pub const TASK_AUDIO_IN: &str = "audio_in";
+/// Task label of signaling streams
+pub const TASK_SIGNAL: &str = "signal";
+/// Task label of a session's frame processing
+pub const TASK_SESSION: &str = "session";
+/// Task label of WebSocket media streams
+pub const TASK_WEBSOCKET: &str = "websocket";
+/// Task label of the RTP relays of SIP calls
+pub const TASK_SIP: &str = "sip";
+/// Task label of the plain RTP relays of sessions
+pub const TASK_RTP: &str = "rtp";
+/// Task label of the peer connections running as tasks
+pub const TASK_PEER: &str = "peer";
+/// Task label of the relays to external speech recognizers
+pub const TASK_ASR: &str = "asr";
+
+/// Samples the metrics of a Tokio runtime
+pub struct RuntimeSampler {
+    handle: Handle,
+    last_sample: Instant,
+    /// Total busy time of each worker at the last sample
+    last_busy: Vec<Duration>,
+}
+
+impl RuntimeSampler {
+    /// Sample the runtime of `handle`
+    pub fn new(handle: Handle) -> Self {
+        let runtime = handle.metrics();
+        let last_busy = (0..runtime.num_workers())
+            .map(|worker| runtime.worker_total_busy_duration(worker))
+            .collect();
+        Self {
+            handle,
+            last_sample: Instant::now(),
+            last_busy,
+        }
+    }
+
+    /// Record the runtime's state and worker load since the last sample
+    pub fn sample(&mut self, metrics: &Metrics) {
+        let runtime = self.handle.metrics();
+        let elapsed = self.last_sample.elapsed().as_secs_f64().max(f64::EPSILON);
+        self.last_sample = Instant::now();
+
+        metrics.runtime_workers.set(runtime.num_workers() as i64);
+        metrics
+            .runtime_alive_tasks
+            .set(runtime.num_alive_tasks() as i64);
+        metrics
+            .runtime_global_queue_depth
+            .set(runtime.global_queue_depth() as i64);
+        for (worker, last_busy) in self.last_busy.iter_mut().enumerate() {
+            let busy = runtime.worker_total_busy_duration(worker);
+            let ratio = busy.saturating_sub(*last_busy).as_secs_f64() / elapsed;
+            *last_busy = busy;
+            metrics
+                .runtime_worker_busy_ratio
+                .with_label_values(&[&worker.to_string()])
+                .set(ratio.min(1.0));
+        }
+
+        for stats in pool::stats() {
+            metrics
+                .buffer_pool_idle
+                .with_label_values(&[stats.name])
+                .set(stats.idle as i64);
+            for (outcome, total) in [
+                ("allocated", stats.allocated),
+                ("reused", stats.reused),
+                ("discarded", stats.discarded),
+            ] {
+                let counter = metrics
+                    .buffer_pool_buffers
+                    .with_label_values(&[stats.name, outcome]);
+                counter.inc_by(total.saturating_sub(counter.get()));
+            }
+        }
+    }
+}
+
+/// Sample the current runtime every `period`
+pub fn spawn_runtime_sampler(
+    metrics: Arc<Metrics>,
+    period: Duration,
+) -> tokio::task::JoinHandle<()> {
+    let mut sampler = RuntimeSampler::new(Handle::current());
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            sampler.sample(&metrics);
+        }
+    })
+}
+
+/// A future recording how long each of its polls takes
+pub struct PollTimed<F> {
+    inner: Pin<Box<F>>,
+    poll_duration_ms: Histogram,
+}
+
+impl<F: Future> Future for PollTimed<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let started = Instant::now();
+        let poll = self.inner.as_mut().poll(cx);
+        self.poll_duration_ms
+            .observe(started.elapsed().as_secs_f64() * 1000.0);
+        poll
+    }
+}
+
+/// Time the polls of a task, labelled with the kind of task
+pub fn poll_timed<F: Future>(metrics: &Metrics, task: &str, future: F) -> PollTimed<F> {
+    PollTimed {
+        inner: Box::pin(future),
+        poll_duration_ms: metrics.task_poll_duration_ms.with_label_values(&[task]),
+    }
+}
+
+/// Run a future as its own task, so a panic in it is contained
+///
+/// Returns `None` when the task panicked, which is logged and counted in
+/// `amwaj_panics_total`, or was cancelled.
+pub async fn supervise<F>(metrics: &Metrics, task: &str, future: F) -> Option<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match tokio::spawn(future).await {
+        Ok(output) => Some(output),
+        Err(e) if e.is_panic() => {
+            let payload = e.into_panic();
+            tracing::error!("Task {} panicked: {}", task, panic_message(&*payload));
+            metrics.record_panic(task);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Get the message a panic was raised with
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_runtime_sampler() {
+        let metrics = Metrics::new(&Config::default());
+        let mut sampler = RuntimeSampler::new(Handle::current());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        sampler.sample(&metrics);
+
+        assert_eq!(metrics.runtime_workers.get(), 2);
+        for worker in ["0", "1"] {
+            let ratio = metrics
+                .runtime_worker_busy_ratio
+                .with_label_values(&[worker])
+                .get();
+            assert!((0.0..=1.0).contains(&ratio), "{}", ratio);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_poll_timed() {
+        let metrics = Metrics::new(&Config::default());
+        let value = poll_timed(&metrics, TASK_AUDIO_IN, async {
+            tokio::task::yield_now().await;
+            7
+        })
+        .await;
+        assert_eq!(value, 7);
+        let polls = metrics
+            .task_poll_duration_ms
+            .with_label_values(&[TASK_AUDIO_IN]);
+        assert_eq!(polls.get_sample_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_supervise_contains_panic() {
+        let metrics = Metrics::new(&Config::default());
+        assert_eq!(supervise(&metrics, TASK_SIGNAL, async { 7 }).await, Some(7));
+        let panicked = supervise(&metrics, TASK_SIGNAL, async { panic!("signal bug") }).await;
+        assert_eq!(panicked, None::<()>);
+        assert_eq!(metrics.panics.with_label_values(&[TASK_SIGNAL]).get(), 1);
+    }
+}
@@ -0,0 +1,161 @@
+//! Distributed tracing of sessions and audio frames
+//!
+//! Each session gets a root `session` span. A sampled share of its frames
+//! get a `frame` span under it, with `decode`, `vad` and `detection`
+//! children, so the latency of a frame can be followed end to end in Jaeger
+//! or Tempo. With `enable_tracing` and the `otel-feature` the spans are
+//! exported to an OTLP collector.
+
+use crate::config::MetricsConfig;
+use tracing::Span;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Boxed layer added to the logging subscriber
+pub type BoxedLayer<S> = Box<dyn Layer<S> + Send + Sync>;
+
+/// Build the layer exporting spans over OTLP, `None` when tracing is off
+///
+/// Must be called within the Tokio runtime, spans are exported in batches
+/// by a background task.
+pub fn otlp_layer<S>(config: &MetricsConfig) -> anyhow::Result<Option<BoxedLayer<S>>>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    if !config.enable_tracing {
+        return Ok(None);
+    }
+    #[cfg(feature = "otel-feature")]
+    {
+        use opentelemetry::KeyValue;
+        use opentelemetry_otlp::WithExportConfig;
+
+        let resource = opentelemetry_sdk::Resource::new(vec![KeyValue::new(
+            "service.name",
+            config.trace_service_name.clone(),
+        )]);
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&config.otlp_endpoint),
+            )
+            .with_trace_config(opentelemetry_sdk::trace::config().with_resource(resource))
+            .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+        Ok(Some(Box::new(
+            tracing_opentelemetry::layer().with_tracer(tracer),
+        )))
+    }
+    #[cfg(not(feature = "otel-feature"))]
+    Err(anyhow::anyhow!(
+        "Exporting traces to {} needs the otel-feature",
+        config.otlp_endpoint
+    ))
+}
+
+/// Flush the spans not exported yet
+///
+/// Blocks until the exporter is done, call it off the async workers.
+pub fn shutdown_tracing() {
+    #[cfg(feature = "otel-feature")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Create the root span of a session
+pub fn session_span(session_id: &str, codec: &str, detector: &str) -> Span {
+    tracing::info_span!(
+        parent: None,
+        "session",
+        session_id = %session_id,
+        codec = %codec,
+        detector = %detector
+    )
+}
+
+/// Create a span for one stage of the frame being traced
+///
+/// Frames that were not sampled have no current span, their stages get a
+/// disabled span that costs next to nothing.
+pub fn stage_span(stage: &'static str) -> Span {
+    let current = Span::current();
+    if current.is_none() {
+        return Span::none();
+    }
+    tracing::info_span!(parent: &current, "stage", otel.name = stage, stage)
+}
+
+/// Picks the frames of a session that get traced
+#[derive(Debug, Clone)]
+pub struct FrameSampler {
+    /// Trace one frame out of `every`, none when 0
+    every: u64,
+    frames: u64,
+}
+
+impl FrameSampler {
+    /// Trace `ratio` of the frames, evenly spread
+    pub fn new(ratio: f64) -> Self {
+        let every = if ratio > 0.0 {
+            (1.0 / ratio.min(1.0)).round() as u64
+        } else {
+            0
+        };
+        Self { every, frames: 0 }
+    }
+
+    /// Trace no frames
+    pub fn disabled() -> Self {
+        Self::new(0.0)
+    }
+
+    /// Count a frame, returns whether it is traced
+    pub fn sample(&mut self) -> bool {
+        let sampled = self.every > 0 && self.frames.is_multiple_of(self.every);
+        self.frames += 1;
+        sampled
+    }
+
+    /// Create the span of the next frame, disabled when it isn't sampled
+    pub fn frame_span(&mut self, session: &Span) -> Span {
+        if !self.sample() || session.is_none() {
+            return Span::none();
+        }
+        tracing::info_span!(parent: session, "frame", frame = self.frames - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_sampler() {
+        let mut sampler = FrameSampler::new(0.25);
+        let sampled: Vec<bool> = (0..8).map(|_| sampler.sample()).collect();
+        assert_eq!(
+            sampled,
+            vec![true, false, false, false, true, false, false, false]
+        );
+        let mut all = FrameSampler::new(2.0);
+        assert!((0..4).all(|_| all.sample()));
+        let mut none = FrameSampler::disabled();
+        assert!((0..4).all(|_| !none.sample()));
+    }
+
+    #[test]
+    fn test_stage_spans_follow_frame_spans() {
+        let subscriber = tracing_subscriber::registry();
+        tracing::subscriber::with_default(subscriber, || {
+            // Outside of a traced frame stages are not traced
+            assert!(stage_span("vad").is_none());
+
+            let session = session_span("s1", "pcm16", "state_machine");
+            let mut sampler = FrameSampler::new(0.5);
+            let frame = sampler.frame_span(&session);
+            assert!(!frame.is_none());
+            frame.in_scope(|| assert!(!stage_span("vad").is_none()));
+            assert!(sampler.frame_span(&session).is_none());
+        });
+    }
+}
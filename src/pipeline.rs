@@ -0,0 +1,1126 @@
+//! Per-session media pipeline
+//!
+//! Ties the audio processor and turn detector together for one session,
+//! applies orchestration commands between frames, and turns detection
+//! results into media events for the gRPC stream.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::audio::budget::ProcessingBudget;
+use crate::audio::pre_roll::DEFAULT_PRE_ROLL_MS;
+use crate::audio::processor::pcm_to_float;
+use crate::audio::{calculate_volume, AudioProcessor, EchoLoopDetector, VadCalibrationConfig};
+use crate::config::Config;
+use crate::detection::{
+    DetectorSnapshot, FusionBreakdown, TurnConfigUpdate, TurnDetector, TurnDetectorRegistry,
+    TurnEvent, TurnState,
+};
+use crate::grpc::audio_stream::AudioEncoding;
+use crate::grpc::service::{MediaEvent, OrchestrationCommand};
+use crate::metrics::latency_report::LatencyBreakdown;
+use crate::metrics::{telemetry, Metrics};
+use crate::webrtc::codec::{self, OpusDecoder};
+
+/// VAD probability counted as speech by detectors without thresholds
+const SPEECH_VAD_PROBABILITY: f32 = 0.5;
+
+/// Frames between echo loop checks while the caller speaks
+const ECHO_CHECK_INTERVAL_FRAMES: u64 = 5;
+
+/// Audio processing and turn detection for a single session
+pub struct MediaPipeline {
+    session_id: String,
+    sample_rate: u32,
+    frame_duration_ms: u32,
+    processor: AudioProcessor,
+    detector: Box<dyn TurnDetector>,
+    /// Registered name of the detector, labels the latency metrics
+    detector_name: String,
+    metrics: Option<Arc<Metrics>>,
+    /// When the current run of speech frames started, for the barge-in
+    /// reaction time
+    speech_onset: Option<Instant>,
+    debug_interval_frames: Option<u32>,
+    frames_processed: u64,
+    /// DSP and detection time of the last frame
+    last_latency: LatencyBreakdown,
+    /// VAD probability of the last frame through detection
+    last_vad_probability: f32,
+    pause: Option<Pause>,
+    /// Time spent paused before the current pause
+    paused_total: Duration,
+    echo_loop: Option<EchoLoopDetector>,
+    /// Set once the speech in progress matched played audio, its turn
+    /// events and audio are dropped until the detector is idle again
+    echo_suppressed: bool,
+    /// `PlayAudio` audio still playing, it keeps the detector's playback
+    /// state and echo reference up to date
+    playback: PlaybackQueue,
+}
+
+/// A pause in turn detection
+struct Pause {
+    mute_audio: bool,
+    since: Instant,
+}
+
+/// Agent audio played out a frame at a time, in step with the caller's
+/// frames
+#[derive(Default)]
+struct PlaybackQueue {
+    chunks: VecDeque<PlaybackChunk>,
+}
+
+struct PlaybackChunk {
+    pcm: Vec<i16>,
+    sample_rate: u32,
+    /// Samples already played
+    position: usize,
+    /// Whether `pcm` is the played audio, or silence standing in for audio
+    /// that can't be decoded here
+    decoded: bool,
+}
+
+impl PlaybackQueue {
+    fn push(&mut self, pcm: Vec<i16>, sample_rate: u32, decoded: bool) {
+        if !pcm.is_empty() {
+            self.chunks.push_back(PlaybackChunk {
+                pcm,
+                sample_rate,
+                position: 0,
+                decoded,
+            });
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Play `duration_ms` of audio, returning the decoded samples played
+    fn advance(&mut self, duration_ms: u32) -> Vec<i16> {
+        let mut played = Vec::new();
+        let mut remaining_ms = duration_ms as f64;
+        while remaining_ms > 0.0 {
+            let Some(chunk) = self.chunks.front_mut() else {
+                break;
+            };
+            let wanted = (remaining_ms * chunk.sample_rate as f64 / 1000.0).ceil() as usize;
+            let end = (chunk.position + wanted).min(chunk.pcm.len());
+            if chunk.decoded {
+                played.extend_from_slice(&chunk.pcm[chunk.position..end]);
+            }
+            remaining_ms -= (end - chunk.position) as f64 * 1000.0 / chunk.sample_rate as f64;
+            chunk.position = end;
+            if chunk.position == chunk.pcm.len() {
+                self.chunks.pop_front();
+            }
+        }
+        played
+    }
+}
+
+impl MediaPipeline {
+    /// Create a pipeline from the server configuration
+    ///
+    /// Uses the built-in detectors; see `with_registry` for custom ones.
+    pub fn new(session_id: String, config: &Config) -> anyhow::Result<Self> {
+        Self::with_registry(session_id, config, &TurnDetectorRegistry::default())
+    }
+
+    /// Create a pipeline, resolving the configured detector in a registry
+    pub fn with_registry(
+        session_id: String,
+        config: &Config,
+        registry: &TurnDetectorRegistry,
+    ) -> anyhow::Result<Self> {
+        let sample_rate = config.audio.sample_rate;
+        let frame_duration_ms = config.audio.frame_duration_ms;
+        let frame_size = (sample_rate * frame_duration_ms / 1000) as usize;
+
+        let detector = registry.create(&config.detection.detector, &config.detection)?;
+        let mut processor =
+            AudioProcessor::new(sample_rate, frame_size).with_pre_roll(DEFAULT_PRE_ROLL_MS);
+        if config.audio.vad_calibration {
+            processor = processor.with_vad_calibration(VadCalibrationConfig::default());
+        }
+        if let Some(frame_budget_ms) = config.audio.frame_budget_ms {
+            processor = processor.with_budget(ProcessingBudget {
+                frame_budget_ms,
+                ..ProcessingBudget::default()
+            });
+        }
+        let echo_loop = config
+            .detection
+            .echo_loop
+            .as_ref()
+            .map(|echo_loop| EchoLoopDetector::new(echo_loop, sample_rate));
+
+        Ok(Self {
+            session_id,
+            sample_rate,
+            frame_duration_ms,
+            processor,
+            detector,
+            detector_name: config.detection.detector.clone(),
+            metrics: None,
+            speech_onset: None,
+            debug_interval_frames: None,
+            frames_processed: 0,
+            last_latency: LatencyBreakdown::default(),
+            last_vad_probability: 0.0,
+            pause: None,
+            paused_total: Duration::ZERO,
+            echo_loop,
+            echo_suppressed: false,
+            playback: PlaybackQueue::default(),
+        })
+    }
+
+    /// Replace the turn detector, e.g. one chosen per session
+    pub fn with_detector(mut self, detector: Box<dyn TurnDetector>) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    /// Replace the audio processor
+    pub fn with_processor(mut self, processor: AudioProcessor) -> Self {
+        self.processor = processor;
+        self
+    }
+
+    /// Report turn events, detection thresholds and stage latencies to
+    /// metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.processor = self.processor.with_metrics(Arc::clone(&metrics));
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Label the stage latencies with the session's codec
+    pub fn with_codec(mut self, codec: &'static str) -> Self {
+        self.processor = self.processor.with_codec(codec);
+        self
+    }
+
+    /// Emit `DetectionDebug` events every `interval_frames` frames
+    ///
+    /// Meant for diagnosing why a turn fired (or didn't) in production.
+    pub fn with_detection_debug(mut self, interval_frames: u32) -> Self {
+        self.debug_interval_frames = Some(interval_frames.max(1));
+        self
+    }
+
+    /// Get the recent fusion breakdowns, oldest first
+    ///
+    /// Empty when the detector does not use signal fusion.
+    pub fn detection_debug(&self) -> Vec<FusionBreakdown> {
+        self.detector
+            .fusion()
+            .map(|fusion| fusion.history().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Process a PCM frame and return the resulting media events
+    ///
+    /// Audio is only forwarded while a turn is active. When a turn starts,
+    /// the pre-roll frames are emitted right after `TurnStarted`. While
+    /// paused, frames skip detection and are forwarded unless muted. Speech
+    /// matching the agent's played audio is reported with
+    /// `EchoLoopDetected`, and the rest of its turn is dropped.
+    pub fn process_frame(&mut self, pcm_data: &[i16]) -> anyhow::Result<Vec<MediaEvent>> {
+        if let Some(pause) = &self.pause {
+            return Ok(self.skip_frame(pcm_data, pause.mute_audio));
+        }
+        self.advance_playback();
+        if let Some(echo_loop) = &mut self.echo_loop {
+            echo_loop.record_heard(pcm_data);
+        }
+        let frame = self.processor.process_frame(pcm_data)?;
+        self.track_speech_onset(frame.vad_probability);
+        self.last_vad_probability = frame.vad_probability;
+        let started = Instant::now();
+        let event = telemetry::stage_span("detection").in_scope(|| {
+            self.detector.process(
+                frame.vad_probability,
+                &frame.features,
+                self.frame_duration_ms,
+            )
+        });
+        self.last_latency = LatencyBreakdown {
+            dsp_ms: frame.stage_timings.total_ms(),
+            detection_ms: started.elapsed().as_secs_f64() * 1000.0,
+            ..Default::default()
+        };
+
+        let mut events = Vec::new();
+        match event {
+            TurnEvent::TurnStarted => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_turn_start();
+                    let adapted = self
+                        .detector
+                        .thresholds()
+                        .filter(|_| self.detector.adapts_thresholds());
+                    if let Some(thresholds) = adapted {
+                        metrics.record_adapted_thresholds(
+                            thresholds.vad_threshold_enter,
+                            thresholds.volume_threshold_db,
+                        );
+                    }
+                }
+                events.push(MediaEvent::TurnStarted {
+                    session_id: self.session_id.clone(),
+                    timestamp_ms: frame.timestamp_ms,
+                    vad_probability: frame.vad_probability,
+                });
+                for pre_roll in self.processor.take_pre_roll() {
+                    events.push(MediaEvent::audio_frame(
+                        &self.session_id,
+                        pre_roll.timestamp_ms,
+                        &pre_roll.pcm,
+                        self.sample_rate,
+                    ));
+                }
+            }
+            TurnEvent::TurnEnded(segment) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_turn_end();
+                    let now_ms = chrono::Utc::now().timestamp_millis();
+                    let latency_ms = (now_ms - segment.end_wall_clock_ms).max(0);
+                    metrics.record_endpointing_latency(&self.detector_name, latency_ms as f64);
+                }
+                events.push(MediaEvent::TurnEnded {
+                    session_id: self.session_id.clone(),
+                    timestamp_ms: frame.timestamp_ms,
+                    duration_ms: segment.duration_ms,
+                    segment,
+                });
+            }
+            TurnEvent::BargeIn => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_barge_in();
+                    let reaction = self.speech_onset.map(|onset| onset.elapsed());
+                    metrics.record_barge_in_reaction(
+                        &self.detector_name,
+                        reaction.unwrap_or_default().as_secs_f64() * 1000.0,
+                    );
+                }
+                events.push(MediaEvent::BargeIn {
+                    session_id: self.session_id.clone(),
+                    timestamp_ms: frame.timestamp_ms,
+                    vad_probability: frame.vad_probability,
+                });
+            }
+            TurnEvent::Overlap(duration_ms) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_overlap(duration_ms);
+                }
+                events.push(MediaEvent::Overlap {
+                    session_id: self.session_id.clone(),
+                    timestamp_ms: frame.timestamp_ms,
+                    duration_ms,
+                });
+            }
+            TurnEvent::EndOfTurnAnticipated(confidence) => {
+                events.push(MediaEvent::EndOfTurnAnticipated {
+                    session_id: self.session_id.clone(),
+                    timestamp_ms: frame.timestamp_ms,
+                    confidence,
+                });
+            }
+            TurnEvent::TurnSegmented(segment) => {
+                events.push(MediaEvent::TurnSegmented {
+                    session_id: self.session_id.clone(),
+                    timestamp_ms: frame.timestamp_ms,
+                    segment,
+                });
+            }
+            TurnEvent::None => {}
+        }
+
+        self.frames_processed += 1;
+        if let Some(interval) = self.debug_interval_frames {
+            if self.frames_processed.is_multiple_of(interval as u64) {
+                // Without fusion the raw VAD probability is the whole score
+                let breakdown = self
+                    .detector
+                    .fusion()
+                    .and_then(|fusion| fusion.last_breakdown().copied())
+                    .unwrap_or(FusionBreakdown {
+                        vad: frame.vad_probability,
+                        score: frame.vad_probability,
+                        ..Default::default()
+                    });
+                events.push(MediaEvent::DetectionDebug {
+                    session_id: self.session_id.clone(),
+                    timestamp_ms: frame.timestamp_ms,
+                    state: self.detector.state(),
+                    breakdown,
+                });
+            }
+        }
+
+        if self.detector.state() != TurnState::Idle {
+            events.push(MediaEvent::audio_frame(
+                &self.session_id,
+                frame.timestamp_ms,
+                &frame.pcm,
+                self.sample_rate,
+            ));
+        }
+        self.check_echo_loop(frame.timestamp_ms, &mut events);
+
+        Ok(events)
+    }
+
+    /// Look for the agent's own audio in the speech in progress, dropping
+    /// the turn events and audio of speech found to be echo
+    fn check_echo_loop(&mut self, timestamp_ms: i64, events: &mut Vec<MediaEvent>) {
+        let Some(echo_loop) = &mut self.echo_loop else {
+            return;
+        };
+        let speaking = self.detector.state() != TurnState::Idle;
+        if speaking
+            && !self.echo_suppressed
+            && self
+                .frames_processed
+                .is_multiple_of(ECHO_CHECK_INTERVAL_FRAMES)
+        {
+            if let Some(similarity) = echo_loop.detect() {
+                tracing::info!(
+                    "Session {} hears its own audio back, similarity {:.2}",
+                    self.session_id,
+                    similarity
+                );
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_echo_loop();
+                }
+                echo_loop.reset_heard();
+                self.echo_suppressed = true;
+                events.push(MediaEvent::EchoLoopDetected {
+                    session_id: self.session_id.clone(),
+                    timestamp_ms,
+                    similarity,
+                });
+            }
+        }
+        if self.echo_suppressed {
+            events.retain(|event| {
+                !matches!(
+                    event,
+                    MediaEvent::AudioFrame { .. }
+                        | MediaEvent::TurnStarted { .. }
+                        | MediaEvent::TurnEnded { .. }
+                        | MediaEvent::BargeIn { .. }
+                        | MediaEvent::Overlap { .. }
+                        | MediaEvent::EndOfTurnAnticipated { .. }
+                        | MediaEvent::TurnSegmented { .. }
+                )
+            });
+            self.echo_suppressed = speaking;
+        }
+    }
+
+    /// Follow runs of speech frames, with the detector's hysteresis
+    fn track_speech_onset(&mut self, vad_probability: f32) {
+        let (enter, exit) = self
+            .detector
+            .thresholds()
+            .map_or((SPEECH_VAD_PROBABILITY, SPEECH_VAD_PROBABILITY), |t| {
+                (t.vad_threshold_enter, t.vad_threshold_exit)
+            });
+        if vad_probability >= enter {
+            self.speech_onset.get_or_insert_with(Instant::now);
+        } else if vad_probability < exit {
+            self.speech_onset = None;
+        }
+    }
+
+    /// Pass a frame through while paused, bypassing VAD and turn detection
+    fn skip_frame(&mut self, pcm_data: &[i16], mute_audio: bool) -> Vec<MediaEvent> {
+        self.advance_playback();
+        let timestamp_ms = self.processor.skip_frame();
+        self.frames_processed += 1;
+        self.last_latency = LatencyBreakdown::default();
+        if mute_audio {
+            return Vec::new();
+        }
+        vec![MediaEvent::audio_frame(
+            &self.session_id,
+            timestamp_ms,
+            &pcm_to_float(pcm_data),
+            self.sample_rate,
+        )]
+    }
+
+    /// Play a frame of the queued agent audio, feeding it as the echo
+    /// reference and ending playback once the queue runs dry
+    fn advance_playback(&mut self) {
+        if self.playback.is_empty() {
+            return;
+        }
+        let played = self.playback.advance(self.frame_duration_ms);
+        if !played.is_empty() {
+            self.push_playback_reference(&played);
+        }
+        if self.playback.is_empty() {
+            self.detector.set_playback_active(false);
+            self.detector.set_playback_reference(None);
+        }
+    }
+
+    /// Shed optional work for a session over its resource ceilings
+    ///
+    /// Voice isolation and detection debug events are turned off.
+    pub fn degrade(&mut self) {
+        self.processor.set_voice_isolation_enabled(false);
+        self.debug_interval_frames = None;
+    }
+
+    /// Get the memory held by buffered audio
+    pub fn buffered_bytes(&self) -> usize {
+        self.processor.pre_roll_bytes()
+    }
+
+    /// Check if turn detection is paused
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_some()
+    }
+
+    /// Get the total time spent paused, including a pause in progress
+    pub fn paused_duration(&self) -> Duration {
+        self.paused_total
+            + self
+                .pause
+                .as_ref()
+                .map_or(Duration::ZERO, |pause| pause.since.elapsed())
+    }
+
+    /// Apply an orchestration command addressed to this session
+    ///
+    /// Commands are applied between frames. Playback commands only update
+    /// the detector's playback state, which lasts as long as the queued
+    /// audio plays; audio output is handled elsewhere.
+    /// Invalid configuration updates are rejected without changing state.
+    pub fn apply_command(&mut self, command: &OrchestrationCommand) -> anyhow::Result<()> {
+        match command {
+            OrchestrationCommand::UpdateTranscript { text, is_final, .. } => {
+                self.detector.update_transcript(text.clone(), *is_final);
+            }
+            OrchestrationCommand::AdjustVAD {
+                sensitivity,
+                threshold_ms,
+                ..
+            } => {
+                self.detector.apply_config_update(&TurnConfigUpdate {
+                    vad_threshold_enter: Some(*sensitivity),
+                    max_silence_duration_ms: Some(*threshold_ms),
+                    ..Default::default()
+                })?;
+            }
+            OrchestrationCommand::PlayAudio {
+                audio_data,
+                audio_format,
+                ..
+            } => {
+                self.detector.set_playback_active(true);
+                let (pcm, sample_rate, decoded) = match AudioEncoding::from_format(audio_format) {
+                    AudioEncoding::Pcm16 => {
+                        let pcm: Vec<i16> = audio_data
+                            .chunks_exact(2)
+                            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                            .collect();
+                        (pcm, self.sample_rate, true)
+                    }
+                    AudioEncoding::Pcmu => (codec::decode_pcmu(audio_data), 8000, true),
+                    // Opus playback would have to be decoded, it only keeps
+                    // playback going for its duration and goes unchecked
+                    AudioEncoding::Opus => {
+                        let silence = OpusDecoder::new(self.sample_rate)
+                            .decode(audio_data)
+                            .unwrap_or_default();
+                        (silence, self.sample_rate, false)
+                    }
+                };
+                if let (Some(echo_loop), true) = (&mut self.echo_loop, decoded) {
+                    echo_loop.record_played(&pcm);
+                }
+                self.playback.push(pcm, sample_rate, decoded);
+            }
+            OrchestrationCommand::StopAudio { .. } => {
+                self.set_playback_active(false);
+            }
+            OrchestrationCommand::UpdateTurnConfig { update, .. } => {
+                self.detector.apply_config_update(update)?;
+            }
+            OrchestrationCommand::InjectSignal { signal, .. } => {
+                self.detector.inject_signal(*signal)?;
+            }
+            OrchestrationCommand::Pause { mute_audio, .. } => match &mut self.pause {
+                Some(pause) => pause.mute_audio = *mute_audio,
+                None => {
+                    self.pause = Some(Pause {
+                        mute_audio: *mute_audio,
+                        since: Instant::now(),
+                    })
+                }
+            },
+            OrchestrationCommand::Resume { .. } => {
+                if let Some(pause) = self.pause.take() {
+                    self.paused_total += pause.since.elapsed();
+                }
+            }
+            OrchestrationCommand::ClearContext { .. } | OrchestrationCommand::SetTags { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Notify the pipeline that agent playback started or finished
+    ///
+    /// Finishing drops the `PlayAudio` audio still queued.
+    pub fn set_playback_active(&mut self, active: bool) {
+        self.detector.set_playback_active(active);
+        if !active {
+            self.playback.clear();
+            self.detector.set_playback_reference(None);
+        }
+    }
+
+    /// Feed a frame of agent playback audio as the echo reference
+    pub fn push_playback_reference(&mut self, pcm_data: &[i16]) {
+        let volume_db = calculate_volume(&pcm_to_float(pcm_data));
+        self.detector.set_playback_reference(Some(volume_db));
+    }
+
+    /// Resume the media clock and turn state of a migrated session
+    ///
+    /// Detectors without snapshot support start over from idle.
+    pub fn resume(&mut self, frames_processed: u64, detector: Option<&DetectorSnapshot>) {
+        self.frames_processed = frames_processed;
+        self.processor.resume_at(frames_processed);
+        if let Some(snapshot) = detector {
+            if let Err(e) = self.detector.restore(snapshot) {
+                tracing::warn!(
+                    "Session {} resumes without turn state: {}",
+                    self.session_id,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Get the session ID
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Get the VAD probability of the last frame through detection
+    pub fn last_vad_probability(&self) -> f32 {
+        self.last_vad_probability
+    }
+
+    /// Get the number of frames processed so far
+    pub fn frames_processed(&self) -> u64 {
+        self.frames_processed
+    }
+
+    /// Get the DSP and detection time of the last frame
+    pub fn last_latency(&self) -> LatencyBreakdown {
+        self.last_latency
+    }
+
+    /// Get the turn detector
+    pub fn detector(&self) -> &dyn TurnDetector {
+        self.detector.as_ref()
+    }
+
+    /// Get the turn detector mutably
+    pub fn detector_mut(&mut self) -> &mut dyn TurnDetector {
+        self.detector.as_mut()
+    }
+
+    /// Get the audio processor
+    pub fn processor(&self) -> &AudioProcessor {
+        &self.processor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::ExternalSignal;
+
+    fn pipeline() -> MediaPipeline {
+        MediaPipeline::new("test-session".to_string(), &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_silence_is_trimmed() {
+        let mut pipeline = pipeline();
+        let events = pipeline.process_frame(&vec![0i16; 320]).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_turn_started_with_pre_roll() {
+        let mut pipeline = pipeline();
+
+        for _ in 0..5 {
+            pipeline.process_frame(&vec![0i16; 320]).unwrap();
+        }
+
+        let mut events = Vec::new();
+        for _ in 0..5 {
+            events = pipeline.process_frame(&vec![10000i16; 320]).unwrap();
+            if !events.is_empty() {
+                break;
+            }
+        }
+
+        assert!(matches!(events[0], MediaEvent::TurnStarted { .. }));
+        let audio_frames = events
+            .iter()
+            .filter(|e| matches!(e, MediaEvent::AudioFrame { .. }))
+            .count();
+        // Pre-roll frames plus the triggering frame
+        assert!(audio_frames > 1);
+    }
+
+    #[test]
+    fn test_turn_start_recorded_in_metrics() {
+        let mut config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let mut pipeline = pipeline().with_metrics(metrics.clone());
+
+        for _ in 0..5 {
+            pipeline.process_frame(&vec![10000i16; 320]).unwrap();
+        }
+
+        // Fixed thresholds are not reported as adapted
+        assert_eq!(metrics.turn_starts.get(), 1.0);
+        assert_eq!(metrics.adapted_vad_threshold.get_sample_count(), 0);
+
+        config.detection.adaptive_thresholds =
+            Some(crate::config::AdaptiveThresholdsConfig::default());
+        let mut pipeline = MediaPipeline::new("test-session".to_string(), &config)
+            .unwrap()
+            .with_metrics(metrics.clone());
+        for _ in 0..5 {
+            pipeline.process_frame(&vec![10000i16; 320]).unwrap();
+        }
+        assert_eq!(metrics.turn_starts.get(), 2.0);
+        assert_eq!(metrics.adapted_vad_threshold.get_sample_count(), 1);
+    }
+
+    #[test]
+    fn test_stage_latency_per_codec() {
+        let metrics = Arc::new(Metrics::new(&Config::default()));
+        let mut pipeline = pipeline().with_metrics(metrics.clone()).with_codec("opus");
+        pipeline.process_frame(&vec![0i16; 320]).unwrap();
+        pipeline.process_frame(&vec![0i16; 320]).unwrap();
+
+        for stage in ["voice_isolation", "features", "vad"] {
+            let latency = metrics.stage_latency_ms.with_label_values(&[stage, "opus"]);
+            assert_eq!(latency.get_sample_count(), 2);
+        }
+        assert_eq!(metrics.processing_latency_ms.get_sample_count(), 2);
+    }
+
+    #[test]
+    fn test_turn_latency_metrics() {
+        let metrics = Arc::new(Metrics::new(&Config::default()));
+        let mut pipeline = pipeline().with_metrics(metrics.clone());
+        let mut turn_ended = false;
+        for _ in 0..20 {
+            pipeline.process_frame(&vec![10000i16; 320]).unwrap();
+        }
+        for _ in 0..100 {
+            let events = pipeline.process_frame(&vec![0i16; 320]).unwrap();
+            if events
+                .iter()
+                .any(|e| matches!(e, MediaEvent::TurnEnded { .. }))
+            {
+                turn_ended = true;
+                break;
+            }
+        }
+        assert!(turn_ended);
+        let endpointing = metrics
+            .endpointing_latency_ms
+            .with_label_values(&["state_machine"]);
+        assert_eq!(endpointing.get_sample_count(), 1);
+        assert_eq!(metrics.endpointing_target_ms.get(), 800);
+
+        pipeline
+            .apply_command(&OrchestrationCommand::PlayAudio {
+                session_id: "test-session".to_string(),
+                audio_data: vec![0; 16000 * 2 * 2],
+                audio_format: "pcm16".to_string(),
+            })
+            .unwrap();
+        for _ in 0..30 {
+            pipeline.process_frame(&vec![10000i16; 320]).unwrap();
+        }
+        let reaction = metrics
+            .barge_in_reaction_ms
+            .with_label_values(&["state_machine"]);
+        assert_eq!(reaction.get_sample_count(), 1);
+    }
+
+    #[test]
+    fn test_unknown_detector_rejected() {
+        let mut config = Config::default();
+        config.detection.detector = "missing".to_string();
+        assert!(MediaPipeline::new("test-session".to_string(), &config).is_err());
+    }
+
+    #[test]
+    fn test_vad_calibration_from_config() {
+        assert!(pipeline().processor().vad().calibrator().is_none());
+
+        let mut config = Config::default();
+        config.audio.vad_calibration = true;
+        let mut pipeline = MediaPipeline::new("test-session".to_string(), &config).unwrap();
+        pipeline.process_frame(&vec![0i16; 320]).unwrap();
+
+        let calibrator = pipeline.processor().vad().calibrator().unwrap();
+        assert_eq!(calibrator.frames_observed(), 1);
+    }
+
+    #[test]
+    fn test_frame_budget_from_config() {
+        assert!(pipeline().processor().budget_watchdog().is_none());
+
+        let mut config = Config::default();
+        config.audio.frame_budget_ms = Some(f64::MIN_POSITIVE);
+        let mut pipeline = MediaPipeline::new("test-session".to_string(), &config).unwrap();
+        pipeline.process_frame(&vec![100i16; 320]).unwrap();
+
+        let watchdog = pipeline.processor().budget_watchdog().unwrap();
+        assert_eq!(watchdog.budget().frame_budget_ms, f64::MIN_POSITIVE);
+        assert_eq!(watchdog.total_overruns(), 1);
+    }
+
+    #[test]
+    fn test_detection_debug_events() {
+        use crate::detection::{MultiSignalFusion, TurnDetectionConfig, TurnDetectionEngine};
+
+        let detector = TurnDetectionEngine::new(TurnDetectionConfig::default())
+            .with_fusion(MultiSignalFusion::new());
+        let mut pipeline = pipeline()
+            .with_detector(Box::new(detector))
+            .with_detection_debug(2);
+
+        let debug_events = (0..4)
+            .flat_map(|_| pipeline.process_frame(&vec![0i16; 320]).unwrap())
+            .filter(|e| matches!(e, MediaEvent::DetectionDebug { .. }))
+            .count();
+
+        assert_eq!(debug_events, 2);
+        assert_eq!(pipeline.detection_debug().len(), 4);
+    }
+
+    #[test]
+    fn test_apply_adjust_vad() {
+        let mut pipeline = pipeline();
+        pipeline
+            .apply_command(&OrchestrationCommand::AdjustVAD {
+                session_id: "test-session".to_string(),
+                sensitivity: 0.8,
+                threshold_ms: 600,
+            })
+            .unwrap();
+
+        let config = pipeline.detector().config().unwrap();
+        assert_eq!(config.vad_threshold_enter, 0.8);
+        assert_eq!(config.max_silence_duration_ms, 600);
+    }
+
+    #[test]
+    fn test_apply_update_turn_config() {
+        use crate::detection::TurnConfigUpdate;
+
+        let mut pipeline = pipeline();
+        pipeline
+            .apply_command(&OrchestrationCommand::UpdateTurnConfig {
+                session_id: "test-session".to_string(),
+                update: TurnConfigUpdate {
+                    vad_threshold_exit: Some(0.2),
+                    min_speech_duration_ms: Some(100),
+                    ..Default::default()
+                },
+            })
+            .unwrap();
+
+        let config = pipeline.detector().config().unwrap();
+        assert_eq!(config.vad_threshold_exit, 0.2);
+        assert_eq!(config.min_speech_duration_ms, 100);
+
+        let invalid = pipeline.apply_command(&OrchestrationCommand::UpdateTurnConfig {
+            session_id: "test-session".to_string(),
+            update: TurnConfigUpdate {
+                vad_threshold_enter: Some(1.5),
+                ..Default::default()
+            },
+        });
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_inject_client_mute_signal() {
+        let mut pipeline = pipeline();
+        pipeline
+            .apply_command(&OrchestrationCommand::InjectSignal {
+                session_id: "test-session".to_string(),
+                signal: ExternalSignal::client_mute(true),
+            })
+            .unwrap();
+
+        // Loud audio from a muted client does not start a turn
+        let events: Vec<MediaEvent> = (0..20)
+            .flat_map(|_| pipeline.process_frame(&vec![10000i16; 320]).unwrap())
+            .collect();
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, MediaEvent::TurnStarted { .. })));
+        assert_eq!(pipeline.detection_debug().last().unwrap().external, -1.0);
+    }
+
+    #[test]
+    fn test_barge_in_event_during_playback() {
+        let mut pipeline = pipeline();
+        pipeline
+            .apply_command(&OrchestrationCommand::PlayAudio {
+                session_id: "test-session".to_string(),
+                audio_data: vec![0; 16000 * 2 * 2],
+                audio_format: "pcm16".to_string(),
+            })
+            .unwrap();
+        assert!(pipeline.detector().is_playback_active());
+
+        let barge_in = (0..30).any(|_| {
+            pipeline
+                .process_frame(&vec![10000i16; 320])
+                .unwrap()
+                .iter()
+                .any(|e| matches!(e, MediaEvent::BargeIn { .. }))
+        });
+        assert!(barge_in);
+    }
+
+    #[test]
+    fn test_playback_ends_with_queued_audio() {
+        let mut pipeline = pipeline();
+        let play = |format: &str, audio_data: Vec<u8>| OrchestrationCommand::PlayAudio {
+            session_id: "test-session".to_string(),
+            audio_data,
+            audio_format: format.to_string(),
+        };
+
+        // 60ms of PCM16 at 16 kHz, then 40ms of mu-law at 8 kHz
+        pipeline
+            .apply_command(&play("pcm16", vec![0; 1920]))
+            .unwrap();
+        pipeline
+            .apply_command(&play("pcmu", vec![0xff; 320]))
+            .unwrap();
+        for _ in 0..4 {
+            pipeline.process_frame(&vec![0i16; 320]).unwrap();
+            assert!(pipeline.detector().is_playback_active());
+        }
+        pipeline.process_frame(&vec![0i16; 320]).unwrap();
+        assert!(!pipeline.detector().is_playback_active());
+
+        pipeline
+            .apply_command(&play("pcm16", vec![0; 64000]))
+            .unwrap();
+        pipeline
+            .apply_command(&OrchestrationCommand::StopAudio {
+                session_id: "test-session".to_string(),
+                reason: "interrupted".to_string(),
+            })
+            .unwrap();
+        assert!(!pipeline.detector().is_playback_active());
+        pipeline.process_frame(&vec![0i16; 320]).unwrap();
+        assert!(!pipeline.detector().is_playback_active());
+    }
+
+    #[test]
+    fn test_backchannel_filter_from_config() {
+        let mut config = Config::default();
+        config.detection.backchannel = Some(crate::config::BackchannelFilterConfig::default());
+
+        // "uh-huh" while the agent talks: 300ms of speech, then silence
+        for (backchannel, barge_in) in [(None, true), (config.detection.backchannel.clone(), false)]
+        {
+            config.detection.backchannel = backchannel;
+            let mut pipeline = MediaPipeline::new("test-session".to_string(), &config).unwrap();
+            pipeline
+                .apply_command(&OrchestrationCommand::PlayAudio {
+                    session_id: "test-session".to_string(),
+                    audio_data: vec![0; 16000 * 2 * 2],
+                    audio_format: "pcm16".to_string(),
+                })
+                .unwrap();
+            let mut events = Vec::new();
+            for _ in 0..15 {
+                events.extend(pipeline.process_frame(&vec![10000i16; 320]).unwrap());
+            }
+            for _ in 0..30 {
+                events.extend(pipeline.process_frame(&vec![0i16; 320]).unwrap());
+            }
+            assert_eq!(
+                events
+                    .iter()
+                    .any(|e| matches!(e, MediaEvent::BargeIn { .. })),
+                barge_in
+            );
+        }
+    }
+
+    #[test]
+    fn test_debounce_from_config() {
+        let mut config = Config::default();
+        let turn_start_frame = |config: &Config| {
+            let mut pipeline = MediaPipeline::new("test-session".to_string(), config).unwrap();
+            (1..=20).find(|_| {
+                pipeline
+                    .process_frame(&vec![10000i16; 320])
+                    .unwrap()
+                    .iter()
+                    .any(|e| matches!(e, MediaEvent::TurnStarted { .. }))
+            })
+        };
+        let undebounced = turn_start_frame(&config).unwrap();
+
+        config.detection.debounce = Some(crate::config::DebounceFramesConfig {
+            idle_to_speaking_frames: 5,
+            ..Default::default()
+        });
+        assert_eq!(turn_start_frame(&config), Some(undebounced + 4));
+    }
+
+    #[test]
+    fn test_echo_loop_drops_turn() {
+        use crate::audio::fingerprint::tests::{babble, echo_of};
+        use crate::config::EchoLoopConfig;
+
+        let mut config = Config::default();
+        config.detection.echo_loop = Some(EchoLoopConfig::default());
+        let played = babble(7, 16000 * 3);
+
+        // Its own audio coming back, then someone else talking
+        for (heard, echo) in [
+            (echo_of(&played, 16000 / 5), true),
+            (babble(8, 16000 * 3), false),
+        ] {
+            let mut pipeline = MediaPipeline::new("test-session".to_string(), &config).unwrap();
+            pipeline
+                .apply_command(&OrchestrationCommand::PlayAudio {
+                    session_id: "test-session".to_string(),
+                    audio_data: played.iter().flat_map(|s| s.to_le_bytes()).collect(),
+                    audio_format: "pcm16".to_string(),
+                })
+                .unwrap();
+            let mut events = Vec::new();
+            for frame in heard.chunks_exact(320).chain([&[0i16; 320][..]; 50]) {
+                events.extend(pipeline.process_frame(frame).unwrap());
+            }
+            let detected = events
+                .iter()
+                .any(|e| matches!(e, MediaEvent::EchoLoopDetected { .. }));
+            let turn_ended = events
+                .iter()
+                .any(|e| matches!(e, MediaEvent::TurnEnded { .. }));
+            assert_eq!((detected, turn_ended), (echo, !echo));
+        }
+    }
+
+    #[test]
+    fn test_apply_transcript_update() {
+        let mut pipeline = pipeline();
+        pipeline.detector_mut().process(
+            0.9,
+            &crate::audio::AudioFeatures {
+                volume_db: -20.0,
+                ..Default::default()
+            },
+            20,
+        );
+
+        pipeline
+            .apply_command(&OrchestrationCommand::UpdateTranscript {
+                session_id: "test-session".to_string(),
+                text: "my number is".to_string(),
+                is_final: false,
+            })
+            .unwrap();
+
+        assert_eq!(
+            pipeline.detector().transcript().map(|t| t.text()),
+            Some("my number is")
+        );
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut pipeline = pipeline();
+        let session_id = "test-session".to_string();
+        pipeline
+            .apply_command(&OrchestrationCommand::Pause {
+                session_id: session_id.clone(),
+                mute_audio: false,
+            })
+            .unwrap();
+        assert!(pipeline.is_paused());
+
+        // Loud audio is forwarded but never starts a turn
+        for _ in 0..10 {
+            let events = pipeline.process_frame(&vec![10000i16; 320]).unwrap();
+            assert_eq!(events.len(), 1);
+            assert!(matches!(events[0], MediaEvent::AudioFrame { .. }));
+        }
+        assert_eq!(pipeline.detector().state(), TurnState::Idle);
+        assert_eq!(pipeline.frames_processed(), 10);
+
+        pipeline
+            .apply_command(&OrchestrationCommand::Pause {
+                session_id: session_id.clone(),
+                mute_audio: true,
+            })
+            .unwrap();
+        assert!(pipeline
+            .process_frame(&vec![10000i16; 320])
+            .unwrap()
+            .is_empty());
+
+        std::thread::sleep(Duration::from_millis(5));
+        pipeline
+            .apply_command(&OrchestrationCommand::Resume { session_id })
+            .unwrap();
+        assert!(!pipeline.is_paused());
+        let paused = pipeline.paused_duration();
+        assert!(paused >= Duration::from_millis(5));
+        assert_eq!(pipeline.paused_duration(), paused);
+
+        let mut events = Vec::new();
+        for _ in 0..5 {
+            events = pipeline.process_frame(&vec![10000i16; 320]).unwrap();
+            if !events.is_empty() {
+                break;
+            }
+        }
+        match &events[0] {
+            // The media clock kept running through the pause
+            MediaEvent::TurnStarted { timestamp_ms, .. } => assert!(*timestamp_ms > 220),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+}
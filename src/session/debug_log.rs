@@ -0,0 +1,88 @@
+//! Per-session capture of recent debug lines
+//!
+//! Every session keeps its latest detection decisions, commands and buffer
+//! stats in a small ring buffer, whether or not debug logging is on. An
+//! operator can dump it while the session runs, and it is attached to the
+//! `session_ended` sink event of sessions that ended badly, so a bad call
+//! can be explained without having logged every call at debug level.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// A captured debug line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DebugLine {
+    pub timestamp_ms: i64,
+    pub message: String,
+}
+
+/// Ring buffer of a session's latest debug lines
+#[derive(Debug, Clone, Default)]
+pub struct DebugLog {
+    lines: VecDeque<DebugLine>,
+    capacity: usize,
+    /// Lines pushed out by newer ones
+    dropped: u64,
+}
+
+impl DebugLog {
+    /// Keep the latest `capacity` lines, none when 0
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            dropped: 0,
+        }
+    }
+
+    /// Check if lines are kept at all
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Capture a line, dropping the oldest once full
+    pub fn record(&mut self, timestamp_ms: i64, message: String) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        self.lines.push_back(DebugLine {
+            timestamp_ms,
+            message,
+        });
+    }
+
+    /// Get the captured lines, oldest first
+    pub fn lines(&self) -> Vec<DebugLine> {
+        self.lines.iter().cloned().collect()
+    }
+
+    /// Get the number of lines pushed out by newer ones
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_latest_lines() {
+        let mut log = DebugLog::new(2);
+        for i in 0..5 {
+            log.record(i, format!("line {}", i));
+        }
+        let messages: Vec<String> = log.lines().into_iter().map(|line| line.message).collect();
+        assert_eq!(messages, vec!["line 3", "line 4"]);
+        assert_eq!(log.dropped(), 3);
+
+        let mut disabled = DebugLog::new(0);
+        disabled.record(0, "ignored".to_string());
+        assert!(disabled.lines().is_empty());
+        assert_eq!(disabled.dropped(), 0);
+    }
+}
@@ -3,9 +3,16 @@
 //! Provides session state management for distributed deployments.
 //! Uses Redis for state persistence across multiple pods.
 
+use crate::config::SessionsConfig;
+use crate::error::{AmwajError, Result};
+use crate::session::store::{MemorySessionStore, SessionStore};
+use crate::session::{sort_by_load, LoadReport, OrphanedSession, SessionJournal, SessionSnapshot};
 use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 /// Configuration for session management
@@ -15,8 +22,48 @@ pub struct SessionConfig {
     pub redis_url: Option<String>,
     /// Session TTL in seconds
     pub ttl_seconds: u64,
+    /// Interval of the expired session sweep, none when 0
+    pub cleanup_interval_seconds: u64,
     /// Maximum sessions per instance
     pub max_sessions: usize,
+    /// Maximum concurrent sessions of one user per instance
+    pub max_sessions_per_user: Option<usize>,
+    /// Maximum size of one metadata entry
+    pub max_metadata_entry_bytes: usize,
+    /// Maximum size of all metadata of a session
+    pub max_metadata_bytes: usize,
+    /// Lifetime of a session ownership lease, renewed by the heartbeat
+    pub lease_seconds: u64,
+    /// Address this instance is reachable at, recorded with its leases
+    pub advertise_address: Option<String>,
+    /// How long the snapshot of a migrating session waits to be resumed
+    pub snapshot_ttl_seconds: u64,
+    /// How long the journal of an ended session is kept
+    pub journal_ttl_seconds: u64,
+    /// How often the instance publishes its load
+    pub load_report_interval_seconds: u64,
+}
+
+impl From<&SessionsConfig> for SessionConfig {
+    fn from(config: &SessionsConfig) -> Self {
+        Self {
+            redis_url: config
+                .redis_url
+                .as_ref()
+                .map(|url| url.expose().to_string()),
+            ttl_seconds: config.ttl_seconds,
+            cleanup_interval_seconds: config.cleanup_interval_seconds,
+            max_sessions: config.max_sessions,
+            max_sessions_per_user: config.max_sessions_per_user,
+            max_metadata_entry_bytes: config.max_metadata_entry_bytes,
+            max_metadata_bytes: config.max_metadata_bytes,
+            lease_seconds: config.lease_seconds,
+            advertise_address: config.advertise_address.clone(),
+            snapshot_ttl_seconds: config.snapshot_ttl_seconds,
+            journal_ttl_seconds: config.journal_ttl_seconds,
+            load_report_interval_seconds: config.load_report_interval_seconds,
+        }
+    }
 }
 
 impl Default for SessionConfig {
@@ -24,11 +71,37 @@ impl Default for SessionConfig {
         Self {
             redis_url: None,
             ttl_seconds: 3600,
+            cleanup_interval_seconds: 60,
             max_sessions: 10000,
+            max_sessions_per_user: None,
+            max_metadata_entry_bytes: 16 * 1024,
+            max_metadata_bytes: 64 * 1024,
+            lease_seconds: 15,
+            advertise_address: None,
+            snapshot_ttl_seconds: 30,
+            journal_ttl_seconds: 86400,
+            load_report_interval_seconds: 5,
         }
     }
 }
 
+/// Instance holding the lease on a session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionOwner {
+    pub instance_id: String,
+    /// Address the instance is reachable at, like `10.0.3.7:50051`
+    pub address: Option<String>,
+    /// When the lease lapses unless renewed
+    pub lease_expires_ms: i64,
+}
+
+impl SessionOwner {
+    /// Check if the lease has lapsed
+    pub fn is_expired(&self) -> bool {
+        self.lease_expires_ms <= Utc::now().timestamp_millis()
+    }
+}
+
 /// Session data stored for each connection
 #[derive(Debug, Clone)]
 pub struct SessionData {
@@ -42,8 +115,8 @@ pub struct SessionData {
     pub last_activity: DateTime<Utc>,
     /// Session state
     pub state: SessionState,
-    /// Custom metadata
-    pub metadata: HashMap<String, String>,
+    /// Custom metadata, any JSON value
+    pub metadata: HashMap<String, Value>,
 }
 
 impl SessionData {
@@ -72,18 +145,43 @@ impl SessionData {
     }
 
     /// Add metadata
-    pub fn set_metadata(&mut self, key: String, value: String) {
-        self.metadata.insert(key, value);
+    pub fn set_metadata(&mut self, key: String, value: impl Into<Value>) {
+        self.metadata.insert(key, value.into());
     }
 
     /// Get metadata
-    pub fn get_metadata(&self, key: &str) -> Option<&String> {
+    pub fn get_metadata(&self, key: &str) -> Option<&Value> {
         self.metadata.get(key)
     }
+
+    /// Get a metadata entry as `T`, `None` when unset
+    pub fn metadata_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.metadata
+            .get(key)
+            .map(|value| T::deserialize(value))
+            .transpose()
+            .map_err(|e| {
+                AmwajError::InvalidMetadata(format!("{} has an unexpected type: {}", key, e))
+            })
+    }
+
+    /// Get the size of the metadata, keys and JSON-encoded values
+    pub fn metadata_size(&self) -> usize {
+        self.metadata
+            .iter()
+            .map(|(key, value)| metadata_entry_size(key, value))
+            .sum()
+    }
+}
+
+/// Get the size a metadata entry counts against the limits
+pub fn metadata_entry_size(key: &str, value: &Value) -> usize {
+    key.len() + value.to_string().len()
 }
 
 /// Session state
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SessionState {
     /// Session is active
     Active,
@@ -101,155 +199,468 @@ pub enum SessionState {
 /// In-memory storage is used by default, with optional Redis backend.
 pub struct DistributedSessionManager {
     config: SessionConfig,
-    sessions: Arc<RwLock<HashMap<String, SessionData>>>,
-    #[allow(dead_code)]
+    store: Arc<dyn SessionStore>,
+    /// Sessions registered through this instance, bounded by `max_sessions`
+    local: RwLock<HashSet<String>>,
+    /// Local sessions by user ID
+    users: RwLock<HashMap<String, HashSet<String>>>,
+    /// Local sessions reserved but not stored yet, left alone by cleanup
+    pending: RwLock<HashSet<String>>,
+    /// Sessions whose lease this instance holds
+    owned: RwLock<HashSet<String>>,
     instance_id: String,
 }
 
 impl DistributedSessionManager {
     /// Create a new session manager
+    ///
+    /// Sessions are kept in memory, see `with_store` to share them.
     pub fn new(config: SessionConfig) -> Self {
+        Self::with_store(config, Arc::new(MemorySessionStore::new()))
+    }
+
+    /// Create a session manager over a store
+    pub fn with_store(config: SessionConfig, store: Arc<dyn SessionStore>) -> Self {
         Self {
             config,
-            sessions: Arc::new(RwLock::new(HashMap::new())),
+            store,
+            local: RwLock::new(HashSet::new()),
+            users: RwLock::new(HashMap::new()),
+            pending: RwLock::new(HashSet::new()),
+            owned: RwLock::new(HashSet::new()),
             instance_id: uuid::Uuid::new_v4().to_string(),
         }
     }
 
-    /// Create a new session manager with Redis URL
-    pub fn with_redis(redis_url: &str, ttl_seconds: u64) -> anyhow::Result<Self> {
-        let config = SessionConfig {
-            redis_url: Some(redis_url.to_string()),
+    /// Create a session manager with Redis URL
+    ///
+    /// Needs the `redis-feature`.
+    pub fn with_redis(redis_url: &str, ttl_seconds: u64) -> Result<Self> {
+        Self::from_config(&SessionsConfig {
+            redis_url: Some(redis_url.into()),
             ttl_seconds,
-            ..SessionConfig::default()
+            ..SessionsConfig::default()
+        })
+    }
+
+    /// Create the session manager described by the configuration
+    pub fn from_config(config: &SessionsConfig) -> Result<Self> {
+        let Some(redis_url) = &config.redis_url else {
+            return Ok(Self::new(config.into()));
         };
-        Ok(Self::new(config))
+        #[cfg(feature = "redis-feature")]
+        {
+            let mut store = crate::session::RedisSessionStore::new(
+                redis_url.expose(),
+                &config.key_prefix,
+                config.ttl_seconds,
+            )?;
+            if let Some(encryption) = &config.encryption {
+                let cipher = crate::session::StateCipher::from_config(encryption)
+                    .map_err(|e| AmwajError::ConfigError(e.to_string()))?;
+                store = store.with_cipher(cipher);
+            }
+            Ok(Self::with_store(config.into(), Arc::new(store)))
+        }
+        #[cfg(not(feature = "redis-feature"))]
+        Err(AmwajError::ConfigError(format!(
+            "Redis session store at {} needs the redis-feature",
+            crate::config::secrets::redact_url(redis_url.expose())
+        )))
     }
 
     /// Create a new session
-    pub async fn create_session(&self, user_id: Option<String>) -> anyhow::Result<String> {
+    pub async fn create_session(&self, user_id: Option<String>) -> Result<String> {
         let session_id = uuid::Uuid::new_v4().to_string();
-        let mut session = SessionData::new(session_id.clone());
-        session.user_id = user_id;
+        self.register_session(&session_id, user_id).await?;
+        Ok(session_id)
+    }
 
-        {
-            let mut sessions = self.sessions.write();
+    /// Register a session under a caller-chosen ID
+    ///
+    /// Registering an existing session, e.g. one migrating here, only
+    /// refreshes its activity. New sessions fail with
+    /// `AmwajError::CapacityExceeded` once the instance or their user is at the
+    /// configured limit.
+    pub async fn register_session(&self, session_id: &str, user_id: Option<String>) -> Result<()> {
+        if let Some(mut session) = self.store.get(session_id).await? {
+            session.touch();
+            self.store.update(&session).await?;
+            self.remember(session_id, session.user_id.as_deref());
+            return Ok(());
+        }
 
-            // Check capacity
-            if sessions.len() >= self.config.max_sessions {
-                // Clean up expired sessions first
-                self.cleanup_expired_internal(&mut sessions);
+        // Clean up expired sessions first
+        if self.local.read().len() >= self.config.max_sessions {
+            self.cleanup_expired().await?;
+        }
+        // The slot is taken before the store round trip, so concurrent
+        // registrations can't all pass the checks
+        self.reserve(session_id, user_id.as_deref())?;
+
+        let mut session = SessionData::new(session_id.to_string());
+        session.user_id = user_id;
+        let inserted = self.store.insert(&session).await;
+        self.pending.write().remove(session_id);
+        if let Err(e) = inserted {
+            self.forget(session_id);
+            return Err(e.into());
+        }
+        Ok(())
+    }
 
-                if sessions.len() >= self.config.max_sessions {
-                    return Err(anyhow::anyhow!("Maximum session limit reached"));
-                }
+    /// Count a new session against the instance and user limits
+    fn reserve(&self, session_id: &str, user_id: Option<&str>) -> Result<()> {
+        let mut local = self.local.write();
+        let mut users = self.users.write();
+        if local.len() >= self.config.max_sessions {
+            return Err(AmwajError::CapacityExceeded(
+                "Maximum session limit reached".into(),
+            ));
+        }
+        if let (Some(user_id), Some(limit)) = (user_id, self.config.max_sessions_per_user) {
+            if users.get(user_id).map_or(0, HashSet::len) >= limit {
+                return Err(AmwajError::CapacityExceeded(format!(
+                    "User {} reached the limit of {} concurrent sessions",
+                    user_id, limit
+                )));
             }
+        }
+        local.insert(session_id.to_string());
+        if let Some(user_id) = user_id {
+            users
+                .entry(user_id.to_string())
+                .or_default()
+                .insert(session_id.to_string());
+        }
+        self.pending.write().insert(session_id.to_string());
+        Ok(())
+    }
+
+    /// Get the IDs of a user's sessions on this instance
+    pub fn sessions_for_user(&self, user_id: &str) -> Vec<String> {
+        let mut session_ids: Vec<String> = self
+            .users
+            .read()
+            .get(user_id)
+            .map(|sessions| sessions.iter().cloned().collect())
+            .unwrap_or_default();
+        session_ids.sort();
+        session_ids
+    }
 
-            sessions.insert(session_id.clone(), session);
+    /// Track a session registered through this instance
+    fn remember(&self, session_id: &str, user_id: Option<&str>) {
+        self.local.write().insert(session_id.to_string());
+        if let Some(user_id) = user_id {
+            self.users
+                .write()
+                .entry(user_id.to_string())
+                .or_default()
+                .insert(session_id.to_string());
         }
+    }
 
-        Ok(session_id)
+    /// Stop tracking a session that left this instance
+    fn forget(&self, session_id: &str) {
+        self.local.write().remove(session_id);
+        self.users.write().retain(|_, sessions| {
+            sessions.remove(session_id);
+            !sessions.is_empty()
+        });
     }
 
     /// Get session data
-    pub async fn get_session(&self, session_id: &str) -> Option<SessionData> {
-        let sessions = self.sessions.read();
-        sessions.get(session_id).cloned()
+    pub async fn get_session(&self, session_id: &str) -> Result<Option<SessionData>> {
+        Ok(self.store.get(session_id).await?)
     }
 
     /// Update session activity
-    pub async fn touch_session(&self, session_id: &str) -> anyhow::Result<()> {
-        let mut sessions = self.sessions.write();
-        if let Some(session) = sessions.get_mut(session_id) {
-            session.touch();
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Session not found"))
-        }
+    pub async fn touch_session(&self, session_id: &str) -> Result<()> {
+        let mut session = self
+            .store
+            .get(session_id)
+            .await?
+            .ok_or_else(|| AmwajError::SessionNotFound(session_id.to_string()))?;
+        session.touch();
+        Ok(self.store.update(&session).await?)
     }
 
     /// Update session state
-    pub async fn update_state(&self, session_id: &str, state: SessionState) -> anyhow::Result<()> {
-        let mut sessions = self.sessions.write();
-        if let Some(session) = sessions.get_mut(session_id) {
-            session.state = state;
-            session.touch();
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Session not found"))
+    pub async fn update_state(&self, session_id: &str, state: SessionState) -> Result<()> {
+        let mut session = self
+            .store
+            .get(session_id)
+            .await?
+            .ok_or_else(|| AmwajError::SessionNotFound(session_id.to_string()))?;
+        session.state = state;
+        session.touch();
+        Ok(self.store.update(&session).await?)
+    }
+
+    /// Check a full metadata map against the configured sizes
+    pub fn check_metadata(&self, metadata: &HashMap<String, Value>) -> Result<()> {
+        let mut total = 0;
+        for (key, value) in metadata {
+            total += self.check_metadata_entry(key, value)?;
         }
+        self.check_metadata_total(total)
+    }
+
+    fn check_metadata_entry(&self, key: &str, value: &Value) -> Result<usize> {
+        let size = metadata_entry_size(key, value);
+        if size > self.config.max_metadata_entry_bytes {
+            return Err(AmwajError::InvalidMetadata(format!(
+                "{} is {} bytes, over the {} byte limit",
+                key, size, self.config.max_metadata_entry_bytes
+            )));
+        }
+        Ok(size)
+    }
+
+    fn check_metadata_total(&self, total: usize) -> Result<()> {
+        if total > self.config.max_metadata_bytes {
+            return Err(AmwajError::InvalidMetadata(format!(
+                "metadata is {} bytes, over the {} byte limit",
+                total, self.config.max_metadata_bytes
+            )));
+        }
+        Ok(())
     }
 
     /// Set session metadata
+    ///
+    /// Fails with `AmwajError::InvalidMetadata` when the entry, or the
+    /// session's metadata with it, exceeds the configured size.
     pub async fn set_metadata(
         &self,
         session_id: &str,
         key: String,
-        value: String,
-    ) -> anyhow::Result<()> {
-        let mut sessions = self.sessions.write();
-        if let Some(session) = sessions.get_mut(session_id) {
-            session.set_metadata(key, value);
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Session not found"))
+        value: impl Into<Value>,
+    ) -> Result<()> {
+        let value = value.into();
+        let size = self.check_metadata_entry(&key, &value)?;
+        let session = self
+            .store
+            .get(session_id)
+            .await?
+            .ok_or_else(|| AmwajError::SessionNotFound(session_id.to_string()))?;
+        let replaced = session
+            .metadata
+            .get(&key)
+            .map_or(0, |old| metadata_entry_size(&key, old));
+        self.check_metadata_total(session.metadata_size() - replaced + size)?;
+        Ok(self.store.set_metadata(session_id, &key, &value).await?)
+    }
+
+    /// End a session, releasing its lease
+    pub async fn end_session(&self, session_id: &str) -> Result<()> {
+        self.forget(session_id);
+        self.release_session(session_id).await?;
+        Ok(self.store.remove(session_id).await?)
+    }
+
+    /// Get the ID of this instance
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Claim a session for this instance, or renew its lease
+    ///
+    /// Fails while another instance holds a live lease on the session.
+    pub async fn claim_session(&self, session_id: &str) -> Result<SessionOwner> {
+        let lease_ms = self.config.lease_seconds as i64 * 1000;
+        let lease = SessionOwner {
+            instance_id: self.instance_id.clone(),
+            address: self.config.advertise_address.clone(),
+            lease_expires_ms: Utc::now().timestamp_millis() + lease_ms,
+        };
+        let owner = self
+            .store
+            .claim(session_id, &lease, self.config.lease_seconds)
+            .await?;
+        if owner.instance_id != self.instance_id {
+            self.owned.write().remove(session_id);
+            return Err(AmwajError::SessionOwnedElsewhere(format!(
+                "Session {} is owned by instance {} at {}",
+                session_id,
+                owner.instance_id,
+                owner.address.as_deref().unwrap_or("an unknown address")
+            )));
         }
+        self.owned.write().insert(session_id.to_string());
+        Ok(owner)
     }
 
-    /// End a session
-    pub async fn end_session(&self, session_id: &str) -> anyhow::Result<()> {
-        let mut sessions = self.sessions.write();
-        if let Some(session) = sessions.get_mut(session_id) {
-            session.state = SessionState::Ended;
+    /// Give up this instance's lease on a session
+    pub async fn release_session(&self, session_id: &str) -> Result<()> {
+        if self.owned.write().remove(session_id) {
+            self.store.release(session_id, &self.instance_id).await?;
         }
-        sessions.remove(session_id);
         Ok(())
     }
 
-    /// Get active session count
-    pub fn active_session_count(&self) -> usize {
-        let sessions = self.sessions.read();
-        sessions
-            .values()
-            .filter(|s| s.state == SessionState::Active)
-            .count()
+    /// Get the instance holding a live lease on a session
+    pub async fn owner_of(&self, session_id: &str) -> Result<Option<SessionOwner>> {
+        Ok(self.store.owner(session_id).await?)
     }
 
-    /// Get total session count
-    pub fn total_session_count(&self) -> usize {
-        self.sessions.read().len()
+    /// Renew the leases this instance holds, returns the sessions it lost
+    pub async fn renew_leases(&self) -> Vec<String> {
+        let owned: Vec<String> = self.owned.read().iter().cloned().collect();
+        let mut lost = Vec::new();
+        for session_id in owned {
+            if let Err(e) = self.claim_session(&session_id).await {
+                tracing::warn!("Lost the lease on session {}: {}", session_id, e);
+                lost.push(session_id);
+            }
+        }
+        lost
     }
 
-    /// Cleanup expired sessions
-    pub async fn cleanup_expired(&self) -> usize {
-        let mut sessions = self.sessions.write();
-        self.cleanup_expired_internal(&mut sessions)
+    /// Hand a session over to the next instance that claims it
+    ///
+    /// Parks the snapshot in the store and releases the lease. The session
+    /// record stays, the next owner picks it up.
+    pub async fn export_session(&self, snapshot: &SessionSnapshot) -> Result<()> {
+        self.store
+            .save_snapshot(snapshot, self.config.snapshot_ttl_seconds)
+            .await?;
+        self.forget(&snapshot.session_id);
+        self.release_session(&snapshot.session_id).await
     }
 
-    fn cleanup_expired_internal(&self, sessions: &mut HashMap<String, SessionData>) -> usize {
-        let ttl = self.config.ttl_seconds;
-        let expired: Vec<String> = sessions
-            .iter()
-            .filter(|(_, s)| s.is_expired(ttl))
-            .map(|(k, _)| k.clone())
-            .collect();
+    /// Publish the reconnection state of a session parked on drain
+    ///
+    /// Export the session first; the record is withdrawn once another
+    /// instance resumes it, or expires with the snapshot.
+    pub async fn publish_orphan(&self, snapshot: &SessionSnapshot) -> Result<OrphanedSession> {
+        let orphan = snapshot.orphan(self.config.snapshot_ttl_seconds);
+        self.store
+            .publish_orphan(&orphan, self.config.snapshot_ttl_seconds)
+            .await?;
+        Ok(orphan)
+    }
 
-        let count = expired.len();
-        for id in expired {
-            sessions.remove(&id);
+    /// List the sessions parked on drain and not resumed yet
+    pub async fn orphaned_sessions(&self) -> Result<Vec<OrphanedSession>> {
+        Ok(self.store.orphans().await?)
+    }
+
+    /// Take the snapshot of a session migrating to this instance
+    ///
+    /// Claim the session first, so no other instance resumes it too.
+    pub async fn take_snapshot(&self, session_id: &str) -> Result<Option<SessionSnapshot>> {
+        let Some(snapshot) = self.store.take_snapshot(session_id).await? else {
+            return Ok(None);
+        };
+        if !snapshot.is_compatible() {
+            tracing::warn!(
+                "Discarding snapshot of session {}, version {} is not supported",
+                session_id,
+                snapshot.version
+            );
+            return Ok(None);
         }
-        count
+        Ok(Some(snapshot))
+    }
+
+    /// Keep the journal of an ended session
+    pub async fn save_journal(&self, session_id: &str, journal: &SessionJournal) -> Result<()> {
+        Ok(self
+            .store
+            .save_journal(session_id, journal, self.config.journal_ttl_seconds)
+            .await?)
+    }
+
+    /// Get the saved journal of an ended session
+    pub async fn journal(&self, session_id: &str) -> Result<Option<SessionJournal>> {
+        Ok(self.store.journal(session_id).await?)
+    }
+
+    /// Publish the load of this instance
+    ///
+    /// The report is kept until three intervals pass without a new one.
+    pub async fn publish_load(&self, report: &LoadReport) -> Result<()> {
+        Ok(self
+            .store
+            .publish_load(report, self.config.load_report_interval_seconds * 3)
+            .await?)
+    }
+
+    /// List the load of the instances still reporting, least loaded first
+    pub async fn load_reports(&self) -> Result<Vec<LoadReport>> {
+        let mut reports = self.store.load_reports().await?;
+        sort_by_load(&mut reports);
+        Ok(reports)
+    }
+
+    /// Get the interval the instance publishes its load at
+    pub fn load_report_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.config.load_report_interval_seconds.max(1))
+    }
+
+    /// Get the interval leases are renewed at
+    pub fn lease_renewal_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.config.lease_seconds * 1000 / 3)
+            .max(std::time::Duration::from_millis(10))
+    }
+
+    /// Get the interval expired sessions are swept at, if they are
+    pub fn cleanup_interval(&self) -> Option<std::time::Duration> {
+        (self.config.cleanup_interval_seconds > 0)
+            .then(|| std::time::Duration::from_secs(self.config.cleanup_interval_seconds))
+    }
+
+    /// Get active session count
+    pub async fn active_session_count(&self) -> Result<usize> {
+        let mut count = 0;
+        for session_id in self.store.list().await? {
+            if let Some(session) = self.store.get(&session_id).await? {
+                count += (session.state == SessionState::Active) as usize;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Get total session count
+    pub async fn total_session_count(&self) -> Result<usize> {
+        Ok(self.store.list().await?.len())
+    }
+
+    /// Cleanup expired sessions
+    ///
+    /// Sessions this instance registered that expired elsewhere, like in
+    /// Redis, stop counting against its limit.
+    pub async fn cleanup_expired(&self) -> Result<usize> {
+        let count = self.store.remove_expired(self.config.ttl_seconds).await?;
+        let local: Vec<String> = self.local.read().iter().cloned().collect();
+        for session_id in local {
+            if self.pending.read().contains(&session_id) {
+                continue;
+            }
+            if self.store.get(&session_id).await?.is_none() {
+                self.forget(&session_id);
+            }
+        }
+        Ok(count)
     }
 
     /// List all session IDs
-    pub fn list_sessions(&self) -> Vec<String> {
-        self.sessions.read().keys().cloned().collect()
+    pub async fn list_sessions(&self) -> Result<Vec<String>> {
+        Ok(self.store.list().await?)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_session_data_creation() {
@@ -264,7 +675,7 @@ mod tests {
         let mut session = SessionData::new("test".to_string());
         session.set_metadata("key1".to_string(), "value1".to_string());
 
-        assert_eq!(session.get_metadata("key1"), Some(&"value1".to_string()));
+        assert_eq!(session.get_metadata("key1"), Some(&Value::from("value1")));
         assert_eq!(session.get_metadata("nonexistent"), None);
     }
 
@@ -279,11 +690,31 @@ mod tests {
             .unwrap();
         assert!(!session_id.is_empty());
 
-        let session = manager.get_session(&session_id).await;
+        let session = manager.get_session(&session_id).await.unwrap();
         assert!(session.is_some());
         assert_eq!(session.unwrap().user_id, Some("user-1".to_string()));
     }
 
+    #[tokio::test]
+    async fn test_session_manager_register() {
+        let manager = DistributedSessionManager::new(SessionConfig::default());
+
+        manager.register_session("s1", None).await.unwrap();
+        manager
+            .register_session("s1", Some("user-1".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(manager.total_session_count().await.unwrap(), 1);
+        assert!(manager
+            .get_session("s1")
+            .await
+            .unwrap()
+            .unwrap()
+            .user_id
+            .is_none());
+    }
+
     #[tokio::test]
     async fn test_session_manager_touch() {
         let config = SessionConfig::default();
@@ -310,7 +741,7 @@ mod tests {
             .await
             .unwrap();
 
-        let session = manager.get_session(&session_id).await.unwrap();
+        let session = manager.get_session(&session_id).await.unwrap().unwrap();
         assert_eq!(session.state, SessionState::Paused);
     }
 
@@ -319,13 +750,13 @@ mod tests {
         let config = SessionConfig::default();
         let manager = DistributedSessionManager::new(config);
 
-        assert_eq!(manager.total_session_count(), 0);
+        assert_eq!(manager.total_session_count().await.unwrap(), 0);
 
         manager.create_session(None).await.unwrap();
         manager.create_session(None).await.unwrap();
 
-        assert_eq!(manager.total_session_count(), 2);
-        assert_eq!(manager.active_session_count(), 2);
+        assert_eq!(manager.total_session_count().await.unwrap(), 2);
+        assert_eq!(manager.active_session_count().await.unwrap(), 2);
     }
 
     #[tokio::test]
@@ -334,9 +765,206 @@ mod tests {
         let manager = DistributedSessionManager::new(config);
 
         let session_id = manager.create_session(None).await.unwrap();
-        assert_eq!(manager.total_session_count(), 1);
+        assert_eq!(manager.total_session_count().await.unwrap(), 1);
 
         manager.end_session(&session_id).await.unwrap();
-        assert_eq!(manager.total_session_count(), 0);
+        assert_eq!(manager.total_session_count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_session_manager_limit() {
+        let manager = DistributedSessionManager::new(SessionConfig {
+            max_sessions: 1,
+            ..SessionConfig::default()
+        });
+
+        manager.register_session("s1", None).await.unwrap();
+        assert!(manager.register_session("s2", None).await.is_err());
+        // Refreshing a known session doesn't count against the limit
+        manager.register_session("s1", None).await.unwrap();
+
+        manager.end_session("s1").await.unwrap();
+        manager.register_session("s2", None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sessions_per_user() {
+        let manager = DistributedSessionManager::new(SessionConfig {
+            max_sessions_per_user: Some(2),
+            ..SessionConfig::default()
+        });
+        let user = || Some("user-1".to_string());
+
+        manager.register_session("s1", user()).await.unwrap();
+        manager.register_session("s2", user()).await.unwrap();
+        let error = manager.register_session("s3", user()).await.unwrap_err();
+        assert!(matches!(error, AmwajError::CapacityExceeded(_)));
+        // Other users and anonymous sessions are unaffected
+        manager
+            .register_session("s4", Some("user-2".to_string()))
+            .await
+            .unwrap();
+        manager.register_session("s5", None).await.unwrap();
+        assert_eq!(manager.sessions_for_user("user-1"), vec!["s1", "s2"]);
+
+        manager.end_session("s1").await.unwrap();
+        assert_eq!(manager.sessions_for_user("user-1"), vec!["s2"]);
+        manager.register_session("s3", user()).await.unwrap();
+        assert!(manager.sessions_for_user("unknown").is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_registrations() {
+        let manager = Arc::new(DistributedSessionManager::new(SessionConfig {
+            max_sessions: 3,
+            max_sessions_per_user: Some(2),
+            ..SessionConfig::default()
+        }));
+        let register = |session_id: String, user_id: &str| {
+            let manager = Arc::clone(&manager);
+            let user_id = user_id.to_string();
+            tokio::spawn(async move {
+                manager
+                    .register_session(&session_id, Some(user_id))
+                    .await
+                    .is_ok()
+            })
+        };
+        let tasks: Vec<_> = (0..16)
+            .map(|i| register(format!("s{}", i), "user-1"))
+            .chain((16..32).map(|i| register(format!("s{}", i), "user-2")))
+            .collect();
+        let mut registered = 0;
+        for task in tasks {
+            registered += usize::from(task.await.unwrap());
+        }
+        assert_eq!(registered, 3);
+        assert!(manager.sessions_for_user("user-1").len() <= 2);
+        assert!(manager.sessions_for_user("user-2").len() <= 2);
+        assert_eq!(manager.list_sessions().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_limits() {
+        let manager = DistributedSessionManager::new(SessionConfig {
+            max_metadata_entry_bytes: 32,
+            max_metadata_bytes: 48,
+            ..SessionConfig::default()
+        });
+        manager.register_session("s1", None).await.unwrap();
+        let is_invalid = |error| matches!(error, AmwajError::InvalidMetadata(_));
+
+        manager
+            .set_metadata("s1", "limits".to_string(), json!({"max_turns": 20}))
+            .await
+            .unwrap();
+        let error = manager
+            .set_metadata("s1", "notes".to_string(), "x".repeat(40))
+            .await
+            .unwrap_err();
+        assert!(is_invalid(error));
+        // 27 bytes on its own, but over the 48 byte budget with "limits"
+        let error = manager
+            .set_metadata("s1", "notes".to_string(), "x".repeat(20))
+            .await
+            .unwrap_err();
+        assert!(is_invalid(error));
+        // Replacing an entry only counts the new value
+        manager
+            .set_metadata("s1", "limits".to_string(), json!({"max_turns": 30}))
+            .await
+            .unwrap();
+
+        let session = manager.get_session("s1").await.unwrap().unwrap();
+        let limits: HashMap<String, u32> = session.metadata_as("limits").unwrap().unwrap();
+        assert_eq!(limits["max_turns"], 30);
+        assert!(session.metadata_as::<u32>("limits").is_err());
+        assert!(manager
+            .check_metadata(&HashMap::from([("tier".to_string(), json!(2))]))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_session_ownership() {
+        let store: Arc<dyn SessionStore> = Arc::new(MemorySessionStore::new());
+        let config = SessionConfig {
+            advertise_address: Some("10.0.0.1:50051".to_string()),
+            ..SessionConfig::default()
+        };
+        let first = DistributedSessionManager::with_store(config, Arc::clone(&store));
+        let second = DistributedSessionManager::with_store(SessionConfig::default(), store);
+
+        first.register_session("s1", None).await.unwrap();
+        let owner = first.claim_session("s1").await.unwrap();
+        assert_eq!(owner.instance_id, first.instance_id());
+        assert_eq!(owner.address.as_deref(), Some("10.0.0.1:50051"));
+        // Claiming again renews the lease
+        first.claim_session("s1").await.unwrap();
+
+        let error = second.claim_session("s1").await.unwrap_err();
+        assert!(error.to_string().contains("10.0.0.1:50051"));
+        assert_eq!(
+            second.owner_of("s1").await.unwrap().unwrap().instance_id,
+            first.instance_id()
+        );
+        assert!(first.renew_leases().await.is_empty());
+
+        first.end_session("s1").await.unwrap();
+        assert!(second.owner_of("s1").await.unwrap().is_none());
+        second.claim_session("s1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_handoff() {
+        let store: Arc<dyn SessionStore> = Arc::new(MemorySessionStore::new());
+        let first =
+            DistributedSessionManager::with_store(SessionConfig::default(), Arc::clone(&store));
+        let second = DistributedSessionManager::with_store(SessionConfig::default(), store);
+
+        first.register_session("s1", None).await.unwrap();
+        first.claim_session("s1").await.unwrap();
+        let mut snapshot = SessionSnapshot {
+            version: crate::session::SNAPSHOT_VERSION,
+            session_id: "s1".to_string(),
+            source_instance_id: first.instance_id().to_string(),
+            taken_at_ms: 0,
+            created_at_ms: 0,
+            user_id: None,
+            metadata: HashMap::new(),
+            codec: crate::grpc::audio_stream::AudioEncoding::Pcm16,
+            sample_rate: 16000,
+            detector: "state_machine".to_string(),
+            frames_processed: 50,
+            playback_sequence: 0,
+            debug: false,
+            detection: None,
+            peer: None,
+            journal: SessionJournal::default(),
+            tags: HashMap::new(),
+            tenant: None,
+            tally: crate::sinks::CallTally::default(),
+        };
+        first.export_session(&snapshot).await.unwrap();
+        assert!(first.owner_of("s1").await.unwrap().is_none());
+
+        // The session record survives the handoff
+        second.claim_session("s1").await.unwrap();
+        assert_eq!(
+            second.take_snapshot("s1").await.unwrap(),
+            Some(snapshot.clone())
+        );
+        second.register_session("s1", None).await.unwrap();
+        assert_eq!(second.total_session_count().await.unwrap(), 1);
+        assert!(second.take_snapshot("s1").await.unwrap().is_none());
+
+        snapshot.version += 1;
+        second.export_session(&snapshot).await.unwrap();
+        assert!(first.take_snapshot("s1").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_redis_needs_feature() {
+        let manager = DistributedSessionManager::with_redis("redis://127.0.0.1:6379", 60);
+        assert_eq!(manager.is_ok(), cfg!(feature = "redis-feature"));
     }
 }
@@ -0,0 +1,261 @@
+//! At-rest encryption of persisted session state
+//!
+//! Session records, metadata, snapshots, journals and orphan records may
+//! carry PII, so with `[sessions.encryption]` the Redis store seals them
+//! with AES-256-GCM before writing. Each sealed value names the key it was
+//! sealed with: new state uses the current key while retired keys still
+//! open what was written before a rotation. Values that aren't sealed are
+//! rejected, unless plaintext is allowed while a store written before
+//! encryption was enabled is migrated.
+
+use crate::config::EncryptionConfig;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Prefix of sealed values, followed by `<key id>:<base64 nonce and ciphertext>`
+const SEALED_PREFIX: &str = "amwaj:enc:v1:";
+/// Bytes of an AES-GCM nonce
+const NONCE_BYTES: usize = 12;
+
+/// An AES-256 data key and its ID
+#[derive(Clone)]
+pub struct DataKey {
+    pub id: String,
+    pub key: [u8; 32],
+}
+
+/// Source of the keys sealing session state, like a KMS
+///
+/// Providers talking to a remote KMS should cache the data keys, they are
+/// asked for on every read and write.
+pub trait KeyProvider: Send + Sync {
+    /// Get the key new state is sealed with
+    fn current_key(&self) -> anyhow::Result<DataKey>;
+
+    /// Get a key by ID, current or retired
+    fn key(&self, key_id: &str) -> anyhow::Result<DataKey>;
+}
+
+/// Keys listed in the configuration
+pub struct ConfigKeyProvider {
+    current_key_id: String,
+    keys: HashMap<String, [u8; 32]>,
+}
+
+impl ConfigKeyProvider {
+    /// Decode the configured keys, each must be 32 bytes
+    pub fn new(config: &EncryptionConfig) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        for (key_id, encoded) in &config.keys {
+            if key_id.is_empty() || key_id.contains(':') {
+                return Err(anyhow::anyhow!("Invalid encryption key ID: {:?}", key_id));
+            }
+            let key: [u8; 32] = STANDARD
+                .decode(encoded.expose())
+                .map_err(|e| anyhow::anyhow!("Encryption key {} is not base64: {}", key_id, e))?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Encryption key {} is not 32 bytes", key_id))?;
+            keys.insert(key_id.clone(), key);
+        }
+        if !keys.contains_key(&config.current_key_id) {
+            return Err(anyhow::anyhow!(
+                "Current encryption key {} is not configured",
+                config.current_key_id
+            ));
+        }
+        Ok(Self {
+            current_key_id: config.current_key_id.clone(),
+            keys,
+        })
+    }
+}
+
+impl KeyProvider for ConfigKeyProvider {
+    fn current_key(&self) -> anyhow::Result<DataKey> {
+        self.key(&self.current_key_id)
+    }
+
+    fn key(&self, key_id: &str) -> anyhow::Result<DataKey> {
+        let key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown encryption key: {}", key_id))?;
+        Ok(DataKey {
+            id: key_id.to_string(),
+            key: *key,
+        })
+    }
+}
+
+/// Seals and opens persisted session state
+#[derive(Clone)]
+pub struct StateCipher {
+    provider: Arc<dyn KeyProvider>,
+    allow_plaintext: bool,
+}
+
+impl StateCipher {
+    /// Create a cipher using the keys of `provider`, rejecting unsealed
+    /// values
+    pub fn new(provider: Arc<dyn KeyProvider>) -> Self {
+        Self {
+            provider,
+            allow_plaintext: false,
+        }
+    }
+
+    /// Create a cipher using the configured keys
+    pub fn from_config(config: &EncryptionConfig) -> anyhow::Result<Self> {
+        Ok(Self::new(Arc::new(ConfigKeyProvider::new(config)?))
+            .with_plaintext_allowed(config.allow_plaintext))
+    }
+
+    /// Let unsealed values pass through `open`, for state written before
+    /// encryption was enabled
+    pub fn with_plaintext_allowed(mut self, allow_plaintext: bool) -> Self {
+        self.allow_plaintext = allow_plaintext;
+        self
+    }
+
+    /// Seal a value with the current key
+    ///
+    /// `context` is bound to the value, usually the key it is stored at,
+    /// so a sealed value can't be moved to another session.
+    pub fn seal(&self, context: &str, plaintext: &str) -> anyhow::Result<String> {
+        let key = self.provider.current_key()?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: context.as_bytes(),
+        };
+        let ciphertext = cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| anyhow::anyhow!("Failed to seal {}", context))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!(
+            "{}{}:{}",
+            SEALED_PREFIX,
+            key.id,
+            STANDARD.encode(sealed)
+        ))
+    }
+
+    /// Open a value sealed for `context`
+    ///
+    /// Unsealed values pass through only when plaintext is allowed.
+    pub fn open(&self, context: &str, stored: &str) -> anyhow::Result<String> {
+        let Some((key_id, encoded)) = sealed_parts(stored) else {
+            if self.allow_plaintext {
+                return Ok(stored.to_string());
+            }
+            return Err(anyhow::anyhow!("Value of {} is not sealed", context));
+        };
+        let key = self.provider.key(key_id)?;
+        let sealed = STANDARD.decode(encoded)?;
+        if sealed.len() < NONCE_BYTES {
+            return Err(anyhow::anyhow!("Sealed value of {} is truncated", context));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
+        let payload = Payload {
+            msg: ciphertext,
+            aad: context.as_bytes(),
+        };
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| anyhow::anyhow!("Failed to open {}, wrong key or tampered", context))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    /// Check if a stored value is sealed with the current key
+    ///
+    /// Values sealed with a retired key, or not sealed, are re-sealed when
+    /// they are next written.
+    pub fn is_current(&self, stored: &str) -> bool {
+        match (sealed_parts(stored), self.provider.current_key()) {
+            (Some((key_id, _)), Ok(current)) => key_id == current.id,
+            _ => false,
+        }
+    }
+}
+
+/// Split a sealed value into its key ID and payload
+fn sealed_parts(stored: &str) -> Option<(&str, &str)> {
+    stored.strip_prefix(SEALED_PREFIX)?.split_once(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn config(current_key_id: &str, keys: &[(&str, u8)]) -> EncryptionConfig {
+        EncryptionConfig {
+            current_key_id: current_key_id.to_string(),
+            keys: keys
+                .iter()
+                .map(|(key_id, byte)| (key_id.to_string(), STANDARD.encode([*byte; 32]).into()))
+                .collect(),
+            key_files: BTreeMap::new(),
+            allow_plaintext: false,
+        }
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let cipher = StateCipher::from_config(&config("k1", &[("k1", 1)])).unwrap();
+        let sealed = cipher.seal("amwaj:session:s1", "{\"ssn\":1}").unwrap();
+        assert!(sealed.starts_with("amwaj:enc:v1:k1:"));
+        assert!(!sealed.contains("ssn"));
+        assert_eq!(
+            cipher.open("amwaj:session:s1", &sealed).unwrap(),
+            "{\"ssn\":1}"
+        );
+        // Bound to where it is stored
+        assert!(cipher.open("amwaj:session:s2", &sealed).is_err());
+        // Written before encryption was enabled, or by someone without the key
+        assert!(cipher.open("amwaj:session:s1", "{}").is_err());
+        let migrating = cipher.with_plaintext_allowed(true);
+        assert_eq!(migrating.open("amwaj:session:s1", "{}").unwrap(), "{}");
+        assert_eq!(
+            migrating.open("amwaj:session:s1", &sealed).unwrap(),
+            "{\"ssn\":1}"
+        );
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let old = StateCipher::from_config(&config("k1", &[("k1", 1)])).unwrap();
+        let sealed = old.seal("ctx", "state").unwrap();
+
+        let rotated = StateCipher::from_config(&config("k2", &[("k1", 1), ("k2", 2)])).unwrap();
+        assert!(!rotated.is_current(&sealed));
+        assert_eq!(rotated.open("ctx", &sealed).unwrap(), "state");
+        let resealed = rotated.seal("ctx", "state").unwrap();
+        assert!(rotated.is_current(&resealed));
+
+        // Once k1 is dropped its state can't be opened
+        let retired = StateCipher::from_config(&config("k2", &[("k2", 2)])).unwrap();
+        assert!(retired.open("ctx", &sealed).is_err());
+        assert_eq!(retired.open("ctx", &resealed).unwrap(), "state");
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(ConfigKeyProvider::new(&config("k2", &[("k1", 1)])).is_err());
+        assert!(ConfigKeyProvider::new(&config("a:b", &[("a:b", 1)])).is_err());
+        let short = EncryptionConfig {
+            current_key_id: "k1".to_string(),
+            keys: BTreeMap::from([("k1".to_string(), STANDARD.encode([0u8; 16]).into())]),
+            key_files: BTreeMap::new(),
+            allow_plaintext: false,
+        };
+        assert!(ConfigKeyProvider::new(&short).is_err());
+    }
+}
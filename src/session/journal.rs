@@ -0,0 +1,153 @@
+//! Per-session event journal for post-call analytics
+//!
+//! Every session keeps an append-only record of its turns, barge-ins,
+//! commands and quality stats, so analytics can read a call back after the
+//! fact instead of tapping the live media stream. The journal is bounded:
+//! once full, later entries are counted but not kept.
+
+use crate::detection::TurnSegment;
+use crate::grpc::service::CommandStatus;
+use serde::{Deserialize, Serialize};
+
+/// Entries kept per session unless configured otherwise
+pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// Something that happened in a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JournalEvent {
+    TurnStarted {
+        vad_probability: f32,
+    },
+    TurnEnded {
+        duration_ms: u32,
+        segment: TurnSegment,
+    },
+    BargeIn {
+        vad_probability: f32,
+    },
+    Overlap {
+        duration_ms: u32,
+    },
+    /// An orchestration command and what became of it
+    Command {
+        command: String,
+        status: CommandStatus,
+        /// Why a rejected command was not applied
+        reason: Option<String>,
+    },
+    /// Media quality of the session, recorded when it ends
+    Quality {
+        frames_processed: u64,
+        rtp_packets_processed: u64,
+        packet_loss_ratio: f32,
+        jitter_buffer_level_percent: f32,
+    },
+}
+
+/// A journal event and when it happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp_ms: i64,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+/// Append-only, bounded journal of a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionJournal {
+    entries: Vec<JournalEntry>,
+    max_entries: usize,
+    /// Entries recorded after the journal was full
+    dropped: u64,
+}
+
+impl SessionJournal {
+    /// Create an empty journal keeping up to `max_entries` entries
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            max_entries,
+            dropped: 0,
+        }
+    }
+
+    /// Append an entry, counted as dropped once the journal is full
+    pub fn record(&mut self, timestamp_ms: i64, event: JournalEvent) {
+        if self.entries.len() >= self.max_entries {
+            self.dropped += 1;
+            return;
+        }
+        self.entries.push(JournalEntry {
+            timestamp_ms,
+            event,
+        });
+    }
+
+    /// Get the recorded entries, oldest first
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Get the number of entries dropped because the journal was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Keep up to `max_entries` entries from now on
+    pub fn set_max_entries(&mut self, max_entries: usize) {
+        self.max_entries = max_entries;
+    }
+}
+
+impl Default for SessionJournal {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_is_bounded() {
+        let mut journal = SessionJournal::new(2);
+        journal.record(
+            1,
+            JournalEvent::TurnStarted {
+                vad_probability: 0.9,
+            },
+        );
+        journal.record(
+            2,
+            JournalEvent::BargeIn {
+                vad_probability: 0.8,
+            },
+        );
+        journal.record(3, JournalEvent::Overlap { duration_ms: 200 });
+
+        assert_eq!(journal.entries().len(), 2);
+        assert_eq!(journal.entries()[1].timestamp_ms, 2);
+        assert_eq!(journal.dropped(), 1);
+    }
+
+    #[test]
+    fn test_journal_serialization() {
+        let mut journal = SessionJournal::default();
+        journal.record(
+            1,
+            JournalEvent::Command {
+                command: "stop_audio".to_string(),
+                status: CommandStatus::Completed,
+                reason: None,
+            },
+        );
+
+        let json = serde_json::to_value(&journal).unwrap();
+        assert_eq!(json["entries"][0]["type"], "command");
+        assert_eq!(json["entries"][0]["status"], "completed");
+        let restored: SessionJournal = serde_json::from_value(json).unwrap();
+        assert_eq!(restored, journal);
+    }
+}
@@ -0,0 +1,155 @@
+//! Instance load reports for session placement
+//!
+//! Every instance periodically publishes a `LoadReport` to the session
+//! store: how many sessions it runs, how busy its audio path is and how
+//! much CPU it has left. Orchestrators read the reports back to place new
+//! sessions on the least-loaded instance instead of round-robin. Reports
+//! expire, so instances that died drop out of placement on their own.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Load of one instance at a point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoadReport {
+    pub instance_id: String,
+    /// Address the instance is reachable at
+    pub address: Option<String>,
+    pub active_sessions: usize,
+    pub max_sessions: usize,
+    /// Share of the instance's CPU left after audio processing, 0 to 1
+    pub cpu_headroom: f32,
+    /// Time spent processing audio per second, in cores; above 1 when
+    /// sessions are processed in parallel
+    pub audio_thread_utilization: f32,
+    /// Draining instances take no new sessions
+    pub draining: bool,
+    pub reported_at_ms: i64,
+}
+
+impl LoadReport {
+    /// Get the sessions the instance can still take
+    pub fn spare_sessions(&self) -> usize {
+        self.max_sessions.saturating_sub(self.active_sessions)
+    }
+
+    /// Check if new sessions can be placed on the instance
+    pub fn accepts_sessions(&self) -> bool {
+        !self.draining && self.spare_sessions() > 0
+    }
+}
+
+/// Order reports from least to most loaded
+///
+/// Instances taking sessions come first, by CPU headroom and then by spare
+/// sessions.
+pub fn sort_by_load(reports: &mut [LoadReport]) {
+    reports.sort_by(|a, b| {
+        b.accepts_sessions()
+            .cmp(&a.accepts_sessions())
+            .then(b.cpu_headroom.total_cmp(&a.cpu_headroom))
+            .then(b.spare_sessions().cmp(&a.spare_sessions()))
+            .then(a.instance_id.cmp(&b.instance_id))
+    });
+}
+
+/// Time the audio path was busy, sampled into utilization per report
+pub struct LoadSampler {
+    busy_us: AtomicU64,
+    /// When the last sample was taken and the busy time then
+    last: Mutex<(Instant, u64)>,
+    cores: usize,
+}
+
+impl LoadSampler {
+    /// Sample busy time against the cores of this machine
+    pub fn new() -> Self {
+        Self::with_cores(num_cpus::get())
+    }
+
+    /// Sample busy time against `cores` cores
+    pub fn with_cores(cores: usize) -> Self {
+        Self {
+            busy_us: AtomicU64::new(0),
+            last: Mutex::new((Instant::now(), 0)),
+            cores: cores.max(1),
+        }
+    }
+
+    /// Charge the processing of one frame
+    pub fn record_busy(&self, busy: Duration) {
+        self.busy_us
+            .fetch_add(busy.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Get the audio thread utilization and CPU headroom since the last
+    /// sample
+    pub fn sample(&self) -> (f32, f32) {
+        let busy_us = self.busy_us.load(Ordering::Relaxed);
+        let mut last = self.last.lock();
+        let elapsed_us = last.0.elapsed().as_micros().max(1) as f64;
+        let utilization = (busy_us - last.1) as f64 / elapsed_us;
+        *last = (Instant::now(), busy_us);
+        let headroom = (1.0 - utilization / self.cores as f64).clamp(0.0, 1.0);
+        (utilization as f32, headroom as f32)
+    }
+}
+
+impl Default for LoadSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(instance_id: &str, active_sessions: usize, cpu_headroom: f32) -> LoadReport {
+        LoadReport {
+            instance_id: instance_id.to_string(),
+            address: None,
+            active_sessions,
+            max_sessions: 10,
+            cpu_headroom,
+            audio_thread_utilization: 0.0,
+            draining: false,
+            reported_at_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_sort_by_load() {
+        let mut draining = report("a", 0, 1.0);
+        draining.draining = true;
+        let mut reports = vec![
+            draining,
+            report("b", 10, 0.9),
+            report("c", 4, 0.5),
+            report("d", 2, 0.8),
+            report("e", 1, 0.8),
+        ];
+        sort_by_load(&mut reports);
+        let order: Vec<&str> = reports.iter().map(|r| r.instance_id.as_str()).collect();
+        assert_eq!(order, vec!["e", "d", "c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_sampler() {
+        let sampler = LoadSampler::with_cores(64);
+        std::thread::sleep(Duration::from_millis(50));
+        // Frames of several sessions processed in parallel
+        sampler.record_busy(Duration::from_millis(1000));
+        let (utilization, headroom) = sampler.sample();
+        assert!(utilization > 1.0 && utilization <= 20.0, "{}", utilization);
+        assert!(headroom > 0.65 && headroom < 1.0, "{}", headroom);
+
+        // Only busy time since the last sample counts
+        std::thread::sleep(Duration::from_millis(10));
+        let (utilization, headroom) = sampler.sample();
+        assert_eq!(utilization, 0.0);
+        assert_eq!(headroom, 1.0);
+    }
+}
@@ -1,5 +1,30 @@
 //! Session management module for distributed state
 
+pub mod debug_log;
 pub mod distributed_state;
+pub mod encryption;
+pub mod journal;
+pub mod load;
+pub mod recorder;
+#[cfg(feature = "redis-feature")]
+pub mod redis_store;
+pub mod s3;
+pub mod snapshot;
+pub mod store;
+pub mod tags;
+pub mod usage;
 
-pub use distributed_state::{DistributedSessionManager, SessionConfig, SessionData};
+pub use debug_log::{DebugLine, DebugLog};
+pub use distributed_state::{
+    DistributedSessionManager, SessionConfig, SessionData, SessionOwner, SessionState,
+};
+pub use encryption::{ConfigKeyProvider, DataKey, KeyProvider, StateCipher};
+pub use journal::{JournalEntry, JournalEvent, SessionJournal};
+pub use load::{sort_by_load, LoadReport, LoadSampler};
+pub use recorder::{read_trace, SessionRecorder, TraceEntry, TraceRecord};
+#[cfg(feature = "redis-feature")]
+pub use redis_store::RedisSessionStore;
+pub use snapshot::{OrphanedSession, SessionSnapshot, SNAPSHOT_VERSION};
+pub use store::{MemorySessionStore, SessionStore};
+pub use tags::{check_tags, matches_tags};
+pub use usage::{SessionUsage, UsageMetric};
@@ -0,0 +1,261 @@
+//! Per-session JSONL traces of production traffic
+//!
+//! A recorded session writes one JSON object per line: RTP packet arrivals
+//! with the jitter at that point, the VAD probability and turn state of
+//! every frame, state transitions and the events sent. Traces are what the
+//! offline evaluation harness is fed with to replay real calls, without
+//! keeping their audio. Recording is turned on for every session with
+//! `recorder.record_all`, or for single sessions through the admin API.
+//!
+//! Lines are buffered and written from the media path, so only a fraction
+//! of sessions should be recorded at a time. Finished traces stay in
+//! `recorder.dir` or are uploaded to S3-compatible storage.
+
+use crate::config::RecorderConfig;
+use crate::detection::TurnState;
+use crate::session::journal::JournalEvent;
+use crate::session::s3::S3Client;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// What a trace line records
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TraceRecord {
+    /// First line of a trace
+    Start {
+        session_id: String,
+        sample_rate: u32,
+        frame_duration_ms: u32,
+        detector: String,
+    },
+    /// An RTP packet taken off the network
+    Packet {
+        sequence_number: u16,
+        rtp_timestamp: u32,
+        /// Interarrival jitter after the packet
+        jitter_ms: f32,
+    },
+    /// A frame through detection, `media_ms` being its end
+    Frame {
+        media_ms: i64,
+        vad_probability: f32,
+        state: TurnState,
+    },
+    Transition {
+        media_ms: i64,
+        from: TurnState,
+        to: TurnState,
+    },
+    /// An event raised by the frame ending at `media_ms`
+    Event {
+        media_ms: i64,
+        name: String,
+        /// Details of turn events
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        detail: Option<JournalEvent>,
+    },
+}
+
+/// A trace line, with the wall clock time it was written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub wall_ms: i64,
+    #[serde(flatten)]
+    pub record: TraceRecord,
+}
+
+/// Writes the trace of one session
+pub struct SessionRecorder {
+    path: PathBuf,
+    /// `None` once a write failed, the rest of the session goes unrecorded
+    writer: Option<BufWriter<File>>,
+    entries: u64,
+}
+
+impl SessionRecorder {
+    /// Start a trace in `dir`, named after the session and the time
+    pub fn create(dir: &Path, session_id: &str) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(trace_file_name(
+            session_id,
+            chrono::Utc::now().timestamp_millis(),
+        ));
+        let file = File::create(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+        Ok(Self {
+            path,
+            writer: Some(BufWriter::new(file)),
+            entries: 0,
+        })
+    }
+
+    /// Get the path of the trace
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the number of lines written
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// Append a line
+    pub fn record(&mut self, record: TraceRecord) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        let entry = TraceEntry {
+            wall_ms: chrono::Utc::now().timestamp_millis(),
+            record,
+        };
+        let written = serde_json::to_writer(&mut *writer, &entry)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writer.write_all(b"\n"));
+        match written {
+            Ok(()) => self.entries += 1,
+            Err(e) => {
+                tracing::warn!("Stopped recording to {}: {}", self.path.display(), e);
+                self.writer = None;
+            }
+        }
+    }
+
+    /// Flush the trace, returns its path
+    pub fn finish(mut self) -> anyhow::Result<PathBuf> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+        }
+        Ok(self.path)
+    }
+}
+
+/// Get the file name of a trace, with the session ID made safe for paths
+fn trace_file_name(session_id: &str, started_ms: i64) -> String {
+    let session_id: String = session_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}-{}.jsonl", session_id, started_ms)
+}
+
+/// Flush a finished trace and upload it if storage is configured
+///
+/// Uploads run in the background; the local file is removed once uploaded
+/// unless `keep_local` is set.
+pub fn store(recorder: SessionRecorder, config: &RecorderConfig) {
+    let path = match recorder.finish() {
+        Ok(path) => path,
+        Err(e) => {
+            tracing::warn!("Failed to write a session trace: {}", e);
+            return;
+        }
+    };
+    let Some(s3) = config.s3.clone() else {
+        tracing::info!("Session trace written to {}", path.display());
+        return;
+    };
+    tokio::spawn(async move {
+        if let Err(e) = upload(&path, &s3).await {
+            tracing::warn!("Failed to upload {}: {:#}", path.display(), e);
+        }
+    });
+}
+
+/// Upload a trace, keyed by its file name
+async fn upload(path: &Path, s3: &crate::config::S3Config) -> anyhow::Result<()> {
+    let key = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Trace has no file name"))?;
+    let client = S3Client::new(s3)?;
+    let body = tokio::fs::read(path).await?;
+    client.put_object(key, body).await?;
+    tracing::info!("Session trace uploaded to {}", client.object_url(key));
+    if !s3.keep_local {
+        tokio::fs::remove_file(path).await?;
+    }
+    Ok(())
+}
+
+/// Read a trace back, for the evaluation harness
+pub fn read_trace(path: &Path) -> anyhow::Result<Vec<TraceEntry>> {
+    let file = File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|(index, line)| {
+            serde_json::from_str(&line?)
+                .map_err(|e| anyhow::anyhow!("{}:{}: {}", path.display(), index + 1, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_file_name() {
+        assert_eq!(trace_file_name("call/1 a", 5), "call_1_a-5.jsonl");
+    }
+
+    #[test]
+    fn test_record_and_read() {
+        let dir = std::env::temp_dir().join(format!("amwaj-trace-{}", uuid::Uuid::new_v4()));
+        let mut recorder = SessionRecorder::create(&dir, "s1").unwrap();
+        let records = vec![
+            TraceRecord::Packet {
+                sequence_number: 7,
+                rtp_timestamp: 1120,
+                jitter_ms: 2.5,
+            },
+            TraceRecord::Frame {
+                media_ms: 20,
+                vad_probability: 0.75,
+                state: TurnState::Idle,
+            },
+            TraceRecord::Transition {
+                media_ms: 20,
+                from: TurnState::Idle,
+                to: TurnState::Speaking,
+            },
+            TraceRecord::Event {
+                media_ms: 20,
+                name: "turn_started".to_string(),
+                detail: Some(JournalEvent::TurnStarted {
+                    vad_probability: 0.75,
+                }),
+            },
+        ];
+        for record in records.clone() {
+            recorder.record(record);
+        }
+        assert_eq!(recorder.entries(), 4);
+        let path = recorder.finish().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content
+            .lines()
+            .nth(2)
+            .unwrap()
+            .contains(r#""type":"transition""#));
+        let read: Vec<TraceRecord> = read_trace(&path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.record)
+            .collect();
+        assert_eq!(read, records);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,984 @@
+//! Redis session store
+//!
+//! Each session is a JSON record at `<prefix>:session:<id>`, set with the
+//! session TTL and refreshed on every update, so idle sessions expire on
+//! their own. Metadata lives in a hash at `<prefix>:metadata:<id>` with the
+//! same TTL, values JSON-encoded; values that aren't JSON, written by older
+//! versions, read back as strings. Sessions are listed by scanning the
+//! record keys. The owner of a session is kept at `<prefix>:owner:<id>`,
+//! expiring with its lease, the snapshot of a migrating session at
+//! `<prefix>:snapshot:<id>`, the orphan record of a session parked on
+//! drain at `<prefix>:orphan:<id>` and the journal of an ended session at
+//! `<prefix>:journal:<id>`. Instances report their load at
+//! `<prefix>:load:<instance id>`.
+//!
+//! Leases are taken, renewed and dropped by Lua scripts that compare the
+//! holder and write in one step, so two instances never both hold one.
+//!
+//! With a `StateCipher` every value but the lease and the load reports is
+//! sealed before it is written. Metadata entries sealed with a retired key
+//! are re-sealed when read, records and the short-lived values when next
+//! written.
+
+use crate::session::store::SessionStore;
+use crate::session::{
+    LoadReport, OrphanedSession, SessionData, SessionJournal, SessionOwner, SessionSnapshot,
+    SessionState, StateCipher,
+};
+use chrono::{DateTime, Utc};
+use redis::aio::MultiplexedConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::sync::OnceCell;
+
+/// Keys fetched per `SCAN` round trip
+const SCAN_COUNT: usize = 500;
+
+/// Set the lease in `ARGV[1]` for `ARGV[3]` seconds unless another
+/// instance than `ARGV[2]` holds it, returns the lease held afterwards
+const CLAIM_SCRIPT: &str = r"
+local current = redis.call('GET', KEYS[1])
+if current and cjson.decode(current).instance_id ~= ARGV[2] then
+    return current
+end
+redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[3])
+return ARGV[1]
+";
+
+/// Delete the lease if instance `ARGV[1]` holds it
+const RELEASE_SCRIPT: &str = r"
+local current = redis.call('GET', KEYS[1])
+if current and cjson.decode(current).instance_id == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+/// Session fields stored in the record, metadata is kept apart
+#[derive(Serialize, Deserialize)]
+struct SessionRecord {
+    session_id: String,
+    user_id: Option<String>,
+    created_at_ms: i64,
+    last_activity_ms: i64,
+    state: SessionState,
+}
+
+impl From<&SessionData> for SessionRecord {
+    fn from(session: &SessionData) -> Self {
+        Self {
+            session_id: session.session_id.clone(),
+            user_id: session.user_id.clone(),
+            created_at_ms: session.created_at.timestamp_millis(),
+            last_activity_ms: session.last_activity.timestamp_millis(),
+            state: session.state,
+        }
+    }
+}
+
+impl SessionRecord {
+    fn into_session(self, metadata: HashMap<String, String>) -> SessionData {
+        let metadata = metadata
+            .into_iter()
+            .map(|(key, value)| {
+                let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+                (key, value)
+            })
+            .collect();
+        let timestamp = |ms| DateTime::<Utc>::from_timestamp_millis(ms).unwrap_or_default();
+        SessionData {
+            session_id: self.session_id,
+            user_id: self.user_id,
+            created_at: timestamp(self.created_at_ms),
+            last_activity: timestamp(self.last_activity_ms),
+            state: self.state,
+            metadata,
+        }
+    }
+}
+
+/// Sessions shared through Redis
+pub struct RedisSessionStore {
+    client: redis::Client,
+    connection: OnceCell<MultiplexedConnection>,
+    key_prefix: String,
+    ttl_seconds: u64,
+    cipher: Option<StateCipher>,
+}
+
+impl RedisSessionStore {
+    /// Create a store, connecting on first use
+    pub fn new(redis_url: &str, key_prefix: &str, ttl_seconds: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(redis_url)?,
+            connection: OnceCell::new(),
+            key_prefix: key_prefix.to_string(),
+            ttl_seconds,
+            cipher: None,
+        })
+    }
+
+    /// Seal the stored session state with `cipher`
+    pub fn with_cipher(mut self, cipher: StateCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Seal a value stored at `key`, if encryption is on
+    fn seal(&self, key: &str, value: String) -> anyhow::Result<String> {
+        match &self.cipher {
+            Some(cipher) => cipher.seal(key, &value),
+            None => Ok(value),
+        }
+    }
+
+    /// Open a value read from `key`
+    fn open(&self, key: &str, value: String) -> anyhow::Result<String> {
+        match &self.cipher {
+            Some(cipher) => cipher.open(key, &value),
+            None => Ok(value),
+        }
+    }
+
+    /// Get the context a metadata entry is sealed for
+    fn entry_key(&self, session_id: &str, key: &str) -> String {
+        format!("{}/{}", self.metadata_key(session_id), key)
+    }
+
+    async fn connection(&self) -> anyhow::Result<MultiplexedConnection> {
+        let connection = self
+            .connection
+            .get_or_try_init(|| self.client.get_multiplexed_tokio_connection())
+            .await?;
+        Ok(connection.clone())
+    }
+
+    fn record_key(&self, session_id: &str) -> String {
+        format!("{}:session:{}", self.key_prefix, session_id)
+    }
+
+    fn metadata_key(&self, session_id: &str) -> String {
+        format!("{}:metadata:{}", self.key_prefix, session_id)
+    }
+
+    fn owner_key(&self, session_id: &str) -> String {
+        format!("{}:owner:{}", self.key_prefix, session_id)
+    }
+
+    fn snapshot_key(&self, session_id: &str) -> String {
+        format!("{}:snapshot:{}", self.key_prefix, session_id)
+    }
+
+    fn journal_key(&self, session_id: &str) -> String {
+        format!("{}:journal:{}", self.key_prefix, session_id)
+    }
+
+    fn orphan_key(&self, session_id: &str) -> String {
+        format!("{}:orphan:{}", self.key_prefix, session_id)
+    }
+
+    fn load_key(&self, instance_id: &str) -> String {
+        format!("{}:load:{}", self.key_prefix, instance_id)
+    }
+
+    /// List the session IDs of the keys starting with `prefix`
+    async fn scan_ids(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        let mut connection = self.connection().await?;
+        let pattern = format!("{}*", prefix);
+        let mut session_ids = Vec::new();
+        let mut cursor = 0u64;
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut connection)
+                .await?;
+            session_ids.extend(
+                keys.iter()
+                    .filter_map(|key| key.strip_prefix(prefix))
+                    .map(String::from),
+            );
+            if next == 0 {
+                return Ok(session_ids);
+            }
+            cursor = next;
+        }
+    }
+
+    /// Write a record if `condition` (`NX` or `XX`) holds, returns whether it was written
+    async fn set_record(&self, session: &SessionData, condition: &str) -> anyhow::Result<bool> {
+        let key = self.record_key(&session.session_id);
+        let record = self.seal(&key, serde_json::to_string(&SessionRecord::from(session))?)?;
+        let written: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(record)
+            .arg(condition)
+            .arg("EX")
+            .arg(self.ttl_seconds)
+            .query_async(&mut self.connection().await?)
+            .await?;
+        Ok(written.is_some())
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn insert(&self, session: &SessionData) -> anyhow::Result<bool> {
+        if !self.set_record(session, "NX").await? {
+            return Ok(false);
+        }
+        let metadata_key = self.metadata_key(&session.session_id);
+        let mut pipeline = redis::pipe();
+        pipeline.cmd("DEL").arg(&metadata_key).ignore();
+        if !session.metadata.is_empty() {
+            let entries = session
+                .metadata
+                .iter()
+                .map(|(key, value)| {
+                    let entry_key = self.entry_key(&session.session_id, key);
+                    Ok((key, self.seal(&entry_key, value.to_string())?))
+                })
+                .collect::<anyhow::Result<Vec<(&String, String)>>>()?;
+            pipeline
+                .cmd("HSET")
+                .arg(&metadata_key)
+                .arg(entries)
+                .ignore()
+                .cmd("EXPIRE")
+                .arg(&metadata_key)
+                .arg(self.ttl_seconds)
+                .ignore();
+        }
+        pipeline
+            .query_async::<_, ()>(&mut self.connection().await?)
+            .await?;
+        Ok(true)
+    }
+
+    async fn get(&self, session_id: &str) -> anyhow::Result<Option<SessionData>> {
+        let mut connection = self.connection().await?;
+        let key = self.record_key(session_id);
+        let record: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut connection)
+            .await?;
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        let record: SessionRecord = serde_json::from_str(&self.open(&key, record)?)?;
+        let stored: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(self.metadata_key(session_id))
+            .query_async(&mut connection)
+            .await?;
+
+        let mut metadata = HashMap::with_capacity(stored.len());
+        let mut stale = Vec::new();
+        for (key, value) in stored {
+            let entry_key = self.entry_key(session_id, &key);
+            if let Some(cipher) = &self.cipher {
+                if !cipher.is_current(&value) {
+                    stale.push(key.clone());
+                }
+            }
+            metadata.insert(key, self.open(&entry_key, value)?);
+        }
+        if !stale.is_empty() {
+            // Sealed with a retired key or before encryption was enabled
+            let entries = stale
+                .iter()
+                .map(|key| {
+                    let entry_key = self.entry_key(session_id, key);
+                    Ok((key, self.seal(&entry_key, metadata[key].clone())?))
+                })
+                .collect::<anyhow::Result<Vec<(&String, String)>>>()?;
+            redis::cmd("HSET")
+                .arg(self.metadata_key(session_id))
+                .arg(entries)
+                .query_async::<_, ()>(&mut connection)
+                .await?;
+        }
+        Ok(Some(record.into_session(metadata)))
+    }
+
+    async fn update(&self, session: &SessionData) -> anyhow::Result<()> {
+        if !self.set_record(session, "XX").await? {
+            return Err(anyhow::anyhow!("Session not found"));
+        }
+        redis::cmd("EXPIRE")
+            .arg(self.metadata_key(&session.session_id))
+            .arg(self.ttl_seconds)
+            .query_async::<_, ()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_metadata(&self, session_id: &str, key: &str, value: &Value) -> anyhow::Result<()> {
+        let mut connection = self.connection().await?;
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(self.record_key(session_id))
+            .query_async(&mut connection)
+            .await?;
+        if !exists {
+            return Err(anyhow::anyhow!("Session not found"));
+        }
+        let metadata_key = self.metadata_key(session_id);
+        let value = self.seal(&self.entry_key(session_id, key), value.to_string())?;
+        redis::pipe()
+            .cmd("HSET")
+            .arg(&metadata_key)
+            .arg(key)
+            .arg(value)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&metadata_key)
+            .arg(self.ttl_seconds)
+            .ignore()
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, session_id: &str) -> anyhow::Result<()> {
+        redis::cmd("DEL")
+            .arg(self.record_key(session_id))
+            .arg(self.metadata_key(session_id))
+            .query_async::<_, ()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<String>> {
+        self.scan_ids(&self.record_key("")).await
+    }
+
+    async fn remove_expired(&self, _ttl_seconds: u64) -> anyhow::Result<usize> {
+        // Records expire in Redis
+        Ok(0)
+    }
+
+    async fn claim(
+        &self,
+        session_id: &str,
+        lease: &SessionOwner,
+        lease_seconds: u64,
+    ) -> anyhow::Result<SessionOwner> {
+        let held: String = redis::cmd("EVAL")
+            .arg(CLAIM_SCRIPT)
+            .arg(1)
+            .arg(self.owner_key(session_id))
+            .arg(serde_json::to_string(lease)?)
+            .arg(&lease.instance_id)
+            .arg(lease_seconds.max(1))
+            .query_async(&mut self.connection().await?)
+            .await?;
+        Ok(serde_json::from_str(&held)?)
+    }
+
+    async fn owner(&self, session_id: &str) -> anyhow::Result<Option<SessionOwner>> {
+        let owner: Option<String> = redis::cmd("GET")
+            .arg(self.owner_key(session_id))
+            .query_async(&mut self.connection().await?)
+            .await?;
+        Ok(owner
+            .map(|owner| serde_json::from_str(&owner))
+            .transpose()?)
+    }
+
+    async fn release(&self, session_id: &str, instance_id: &str) -> anyhow::Result<()> {
+        redis::cmd("EVAL")
+            .arg(RELEASE_SCRIPT)
+            .arg(1)
+            .arg(self.owner_key(session_id))
+            .arg(instance_id)
+            .query_async::<_, ()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_snapshot(
+        &self,
+        snapshot: &SessionSnapshot,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()> {
+        let key = self.snapshot_key(&snapshot.session_id);
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(self.seal(&key, serde_json::to_string(snapshot)?)?)
+            .arg("EX")
+            .arg(ttl_seconds.max(1))
+            .query_async::<_, ()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    async fn take_snapshot(&self, session_id: &str) -> anyhow::Result<Option<SessionSnapshot>> {
+        let mut connection = self.connection().await?;
+        let key = self.snapshot_key(session_id);
+        // GETDEL hands the snapshot to exactly one instance
+        let snapshot: Option<String> = redis::cmd("GETDEL")
+            .arg(&key)
+            .query_async(&mut connection)
+            .await?;
+        redis::cmd("DEL")
+            .arg(self.orphan_key(session_id))
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        snapshot
+            .map(|snapshot| Ok(serde_json::from_str(&self.open(&key, snapshot)?)?))
+            .transpose()
+    }
+
+    async fn publish_orphan(
+        &self,
+        orphan: &OrphanedSession,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()> {
+        let key = self.orphan_key(&orphan.session_id);
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(self.seal(&key, serde_json::to_string(orphan)?)?)
+            .arg("EX")
+            .arg(ttl_seconds.max(1))
+            .query_async::<_, ()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    async fn orphans(&self) -> anyhow::Result<Vec<OrphanedSession>> {
+        let mut connection = self.connection().await?;
+        let mut orphans = Vec::new();
+        for session_id in self.scan_ids(&self.orphan_key("")).await? {
+            // Resumed or expired since the scan
+            let key = self.orphan_key(&session_id);
+            let orphan: Option<String> = redis::cmd("GET")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await?;
+            if let Some(orphan) = orphan {
+                orphans.push(serde_json::from_str(&self.open(&key, orphan)?)?);
+            }
+        }
+        Ok(orphans)
+    }
+
+    async fn save_journal(
+        &self,
+        session_id: &str,
+        journal: &SessionJournal,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()> {
+        let key = self.journal_key(session_id);
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(self.seal(&key, serde_json::to_string(journal)?)?)
+            .arg("EX")
+            .arg(ttl_seconds.max(1))
+            .query_async::<_, ()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    async fn journal(&self, session_id: &str) -> anyhow::Result<Option<SessionJournal>> {
+        let key = self.journal_key(session_id);
+        let journal: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut self.connection().await?)
+            .await?;
+        journal
+            .map(|journal| Ok(serde_json::from_str(&self.open(&key, journal)?)?))
+            .transpose()
+    }
+
+    async fn publish_load(&self, report: &LoadReport, ttl_seconds: u64) -> anyhow::Result<()> {
+        redis::cmd("SET")
+            .arg(self.load_key(&report.instance_id))
+            .arg(serde_json::to_string(report)?)
+            .arg("EX")
+            .arg(ttl_seconds.max(1))
+            .query_async::<_, ()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_reports(&self) -> anyhow::Result<Vec<LoadReport>> {
+        let mut connection = self.connection().await?;
+        let mut reports = Vec::new();
+        for instance_id in self.scan_ids(&self.load_key("")).await? {
+            // Expired since the scan
+            let report: Option<String> = redis::cmd("GET")
+                .arg(self.load_key(&instance_id))
+                .query_async(&mut connection)
+                .await?;
+            if let Some(report) = report {
+                reports.push(serde_json::from_str(&report)?);
+            }
+        }
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    #[derive(Default)]
+    struct Data {
+        strings: HashMap<String, String>,
+        hashes: HashMap<String, HashMap<String, String>>,
+        ttls: HashMap<String, u64>,
+        /// Commands received, by name
+        commands: Vec<String>,
+    }
+
+    /// Instance id of a lease
+    fn holder(lease: &str) -> String {
+        serde_json::from_str::<SessionOwner>(lease)
+            .unwrap()
+            .instance_id
+    }
+
+    fn bulk(value: &str) -> String {
+        format!("${}\r\n{}\r\n", value.len(), value)
+    }
+
+    fn array(items: &[String]) -> String {
+        format!("*{}\r\n{}", items.len(), items.concat())
+    }
+
+    /// Answer one command the way Redis would
+    fn execute(data: &mut Data, args: &[String]) -> String {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        data.commands.push(args[0].to_string());
+        match args.as_slice() {
+            // The lease scripts, run as one step like Redis runs scripts
+            ["EVAL", CLAIM_SCRIPT, "1", key, lease, instance_id, ttl] => {
+                match data.strings.get(*key) {
+                    Some(current) if holder(current) != *instance_id => bulk(current),
+                    _ => {
+                        data.strings.insert(key.to_string(), lease.to_string());
+                        data.ttls.insert(key.to_string(), ttl.parse().unwrap());
+                        bulk(lease)
+                    }
+                }
+            }
+            ["EVAL", RELEASE_SCRIPT, "1", key, instance_id] => match data.strings.get(*key) {
+                Some(current) if holder(current) == *instance_id => {
+                    data.strings.remove(*key);
+                    ":1\r\n".to_string()
+                }
+                _ => ":0\r\n".to_string(),
+            },
+            ["GET", key] => data
+                .strings
+                .get(*key)
+                .map_or("$-1\r\n".to_string(), |value| bulk(value)),
+            ["SET", key, value, condition, "EX", ttl] => {
+                let exists = data.strings.contains_key(*key);
+                if (*condition == "NX" && exists) || (*condition == "XX" && !exists) {
+                    return "$-1\r\n".to_string();
+                }
+                data.strings.insert(key.to_string(), value.to_string());
+                data.ttls.insert(key.to_string(), ttl.parse().unwrap());
+                "+OK\r\n".to_string()
+            }
+            ["SET", key, value, "EX", ttl] => {
+                data.strings.insert(key.to_string(), value.to_string());
+                data.ttls.insert(key.to_string(), ttl.parse().unwrap());
+                "+OK\r\n".to_string()
+            }
+            ["GETDEL", key] => data
+                .strings
+                .remove(*key)
+                .map_or("$-1\r\n".to_string(), |value| bulk(&value)),
+            ["EXISTS", key] => format!(":{}\r\n", data.strings.contains_key(*key) as u8),
+            ["HSET", key, entries @ ..] => {
+                let hash = data.hashes.entry(key.to_string()).or_default();
+                for pair in entries.chunks(2) {
+                    hash.insert(pair[0].to_string(), pair[1].to_string());
+                }
+                format!(":{}\r\n", entries.len() / 2)
+            }
+            ["HGETALL", key] => {
+                let hash = data.hashes.get(*key).cloned().unwrap_or_default();
+                let items: Vec<String> =
+                    hash.iter().flat_map(|(k, v)| [bulk(k), bulk(v)]).collect();
+                array(&items)
+            }
+            ["EXPIRE", key, ttl] => {
+                data.ttls.insert(key.to_string(), ttl.parse().unwrap());
+                ":1\r\n".to_string()
+            }
+            ["DEL", keys @ ..] => {
+                for key in keys {
+                    data.strings.remove(*key);
+                    data.hashes.remove(*key);
+                }
+                format!(":{}\r\n", keys.len())
+            }
+            ["SCAN", _, "MATCH", pattern, "COUNT", _] => {
+                let prefix = pattern.trim_end_matches('*');
+                let keys: Vec<String> = data
+                    .strings
+                    .keys()
+                    .filter(|key| key.starts_with(prefix))
+                    .map(|key| bulk(key))
+                    .collect();
+                format!("*2\r\n{}{}", bulk("0"), array(&keys))
+            }
+            _ => "+OK\r\n".to_string(),
+        }
+    }
+
+    /// Speak just enough RESP to back the store
+    async fn serve() -> (String, Arc<Mutex<Data>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let data = Arc::new(Mutex::new(Data::default()));
+
+        let shared = Arc::clone(&data);
+        tokio::spawn(async move {
+            // One connection per store
+            while let Ok((stream, _)) = listener.accept().await {
+                let shared = Arc::clone(&shared);
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut reader = BufReader::new(reader);
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+                        let count: usize = line.trim_end()[1..].parse().unwrap();
+                        let mut args = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            line.clear();
+                            reader.read_line(&mut line).await.unwrap();
+                            let length: usize = line.trim_end()[1..].parse().unwrap();
+                            let mut arg = vec![0; length + 2];
+                            reader.read_exact(&mut arg).await.unwrap();
+                            arg.truncate(length);
+                            args.push(String::from_utf8(arg).unwrap());
+                        }
+                        let reply = execute(&mut shared.lock(), &args);
+                        writer.write_all(reply.as_bytes()).await.unwrap();
+                        line.clear();
+                    }
+                });
+            }
+        });
+        (url, data)
+    }
+
+    #[tokio::test]
+    async fn test_redis_store() {
+        let (url, data) = serve().await;
+        let store = RedisSessionStore::new(&url, "amwaj", 60).unwrap();
+
+        let mut session = SessionData::new("s1".to_string());
+        session.user_id = Some("user-1".to_string());
+        session.set_metadata("tenant".to_string(), "acme".to_string());
+        assert!(store.insert(&session).await.unwrap());
+        assert!(!store.insert(&session).await.unwrap());
+        assert_eq!(data.lock().ttls["amwaj:session:s1"], 60);
+        assert_eq!(data.lock().ttls["amwaj:metadata:s1"], 60);
+
+        store
+            .set_metadata("s1", "limits", &serde_json::json!({"max_turns": 20}))
+            .await
+            .unwrap();
+        assert!(store
+            .set_metadata("missing", "k", &"v".into())
+            .await
+            .is_err());
+        // Plain strings written by older versions still read back
+        data.lock()
+            .hashes
+            .get_mut("amwaj:metadata:s1")
+            .unwrap()
+            .insert("region".to_string(), "eu".to_string());
+
+        session.state = SessionState::Paused;
+        store.update(&session).await.unwrap();
+        let stored = store.get("s1").await.unwrap().unwrap();
+        assert_eq!(stored.user_id.as_deref(), Some("user-1"));
+        assert_eq!(stored.state, SessionState::Paused);
+        assert_eq!(
+            stored.created_at.timestamp_millis(),
+            session.created_at.timestamp_millis()
+        );
+        assert_eq!(stored.get_metadata("tenant").unwrap(), "acme");
+        assert_eq!(stored.get_metadata("region").unwrap(), "eu");
+        assert_eq!(
+            stored
+                .metadata_as::<HashMap<String, u32>>("limits")
+                .unwrap()
+                .unwrap()["max_turns"],
+            20
+        );
+        assert!(store
+            .update(&SessionData::new("missing".to_string()))
+            .await
+            .is_err());
+
+        store
+            .insert(&SessionData::new("s2".to_string()))
+            .await
+            .unwrap();
+        let mut listed = store.list().await.unwrap();
+        listed.sort();
+        assert_eq!(listed, vec!["s1", "s2"]);
+
+        store.remove("s1").await.unwrap();
+        assert!(store.get("s1").await.unwrap().is_none());
+        assert!(!data.lock().hashes.contains_key("amwaj:metadata:s1"));
+    }
+
+    #[tokio::test]
+    async fn test_redis_leases() {
+        let (url, data) = serve().await;
+        let store = RedisSessionStore::new(&url, "amwaj", 60).unwrap();
+        let lease = |instance_id: &str| SessionOwner {
+            instance_id: instance_id.to_string(),
+            address: Some(format!("{}:50051", instance_id)),
+            lease_expires_ms: 0,
+        };
+
+        assert_eq!(
+            store.claim("s1", &lease("a"), 15).await.unwrap(),
+            lease("a")
+        );
+        assert_eq!(data.lock().ttls["amwaj:owner:s1"], 15);
+        // Renewing keeps the lease, another instance is told who holds it
+        assert_eq!(
+            store.claim("s1", &lease("a"), 15).await.unwrap(),
+            lease("a")
+        );
+        assert_eq!(
+            store.claim("s1", &lease("b"), 15).await.unwrap(),
+            lease("a")
+        );
+
+        store.release("s1", "b").await.unwrap();
+        assert_eq!(store.owner("s1").await.unwrap(), Some(lease("a")));
+        store.release("s1", "a").await.unwrap();
+        assert!(store.owner("s1").await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_redis_leases_race() {
+        let (url, data) = serve().await;
+        let lease = |instance_id: &str| SessionOwner {
+            instance_id: instance_id.to_string(),
+            address: None,
+            lease_expires_ms: 0,
+        };
+        let store_a = Arc::new(RedisSessionStore::new(&url, "amwaj", 60).unwrap());
+        let store_b = Arc::new(RedisSessionStore::new(&url, "amwaj", 60).unwrap());
+
+        for _ in 0..50 {
+            // Both instances go for the same free session, one wins and the
+            // other is told so
+            let (a, b) = tokio::join!(
+                tokio::spawn({
+                    let store = Arc::clone(&store_a);
+                    async move { store.claim("s1", &lease("a"), 15).await.unwrap() }
+                }),
+                tokio::spawn({
+                    let store = Arc::clone(&store_b);
+                    async move { store.claim("s1", &lease("b"), 15).await.unwrap() }
+                }),
+            );
+            let (a, b) = (a.unwrap(), b.unwrap());
+            assert_eq!(a, b);
+            assert_eq!(store_a.owner("s1").await.unwrap(), Some(a.clone()));
+
+            // The loser releasing never drops the winner's lease
+            let (winner, loser) = if a.instance_id == "a" {
+                (&store_a, &store_b)
+            } else {
+                (&store_b, &store_a)
+            };
+            let loser_id = if a.instance_id == "a" { "b" } else { "a" };
+            let (released, renewed) =
+                tokio::join!(loser.release("s1", loser_id), winner.claim("s1", &a, 15));
+            released.unwrap();
+            assert_eq!(renewed.unwrap(), a);
+            assert_eq!(store_a.owner("s1").await.unwrap(), Some(a.clone()));
+            winner.release("s1", &a.instance_id).await.unwrap();
+        }
+
+        // Every claim and release is a single command
+        let commands = data.lock().commands.clone();
+        assert!(!commands
+            .iter()
+            .any(|command| command == "SET" || command == "DEL"));
+    }
+
+    #[tokio::test]
+    async fn test_redis_snapshots() {
+        let (url, data) = serve().await;
+        let store = RedisSessionStore::new(&url, "amwaj", 60).unwrap();
+        let snapshot = SessionSnapshot {
+            version: crate::session::SNAPSHOT_VERSION,
+            session_id: "s1".to_string(),
+            source_instance_id: "a".to_string(),
+            taken_at_ms: 0,
+            created_at_ms: 0,
+            user_id: Some("user-1".to_string()),
+            metadata: HashMap::from([("tenant".to_string(), "acme".into())]),
+            codec: crate::grpc::audio_stream::AudioEncoding::Opus,
+            sample_rate: 48000,
+            detector: "state_machine".to_string(),
+            frames_processed: 50,
+            playback_sequence: 3,
+            debug: false,
+            detection: None,
+            peer: None,
+            journal: SessionJournal::default(),
+            tags: HashMap::new(),
+            tenant: None,
+            tally: crate::sinks::CallTally::default(),
+        };
+
+        store.save_snapshot(&snapshot, 30).await.unwrap();
+        assert_eq!(data.lock().ttls["amwaj:snapshot:s1"], 30);
+        assert_eq!(
+            store.take_snapshot("s1").await.unwrap(),
+            Some(snapshot.clone())
+        );
+        assert!(store.take_snapshot("s1").await.unwrap().is_none());
+
+        store.save_snapshot(&snapshot, 30).await.unwrap();
+        store
+            .publish_orphan(&snapshot.orphan(30), 30)
+            .await
+            .unwrap();
+        assert_eq!(data.lock().ttls["amwaj:orphan:s1"], 30);
+        let orphans = store.orphans().await.unwrap();
+        assert_eq!(orphans, vec![snapshot.orphan(30)]);
+        assert_eq!(orphans[0].user_id.as_deref(), Some("user-1"));
+        store.take_snapshot("s1").await.unwrap().unwrap();
+        assert!(store.orphans().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_redis_journals() {
+        let (url, data) = serve().await;
+        let store = RedisSessionStore::new(&url, "amwaj", 60).unwrap();
+        let mut journal = SessionJournal::default();
+        journal.record(
+            1,
+            crate::session::JournalEvent::BargeIn {
+                vad_probability: 0.9,
+            },
+        );
+
+        store.save_journal("s1", &journal, 3600).await.unwrap();
+        assert_eq!(data.lock().ttls["amwaj:journal:s1"], 3600);
+        assert_eq!(store.journal("s1").await.unwrap(), Some(journal));
+        assert!(store.journal("s2").await.unwrap().is_none());
+    }
+
+    fn cipher(current_key_id: &str, key_ids: &[&str]) -> StateCipher {
+        use base64::Engine;
+        let config = crate::config::EncryptionConfig {
+            current_key_id: current_key_id.to_string(),
+            keys: key_ids
+                .iter()
+                .map(|key_id| {
+                    let key = [key_id.as_bytes()[0]; 32];
+                    let encoded = base64::engine::general_purpose::STANDARD.encode(key);
+                    (key_id.to_string(), encoded.into())
+                })
+                .collect(),
+            key_files: Default::default(),
+            allow_plaintext: false,
+        };
+        StateCipher::from_config(&config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_redis_encryption() {
+        let (url, data) = serve().await;
+        let store = RedisSessionStore::new(&url, "amwaj", 60)
+            .unwrap()
+            .with_cipher(cipher("a", &["a"]));
+
+        let mut session = SessionData::new("s1".to_string());
+        session.user_id = Some("caller@example.com".to_string());
+        session.set_metadata("phone".to_string(), "+15550100".to_string());
+        store.insert(&session).await.unwrap();
+        let mut journal = SessionJournal::default();
+        journal.record(
+            1,
+            crate::session::JournalEvent::BargeIn {
+                vad_probability: 0.9,
+            },
+        );
+        store.save_journal("s1", &journal, 60).await.unwrap();
+        {
+            let data = data.lock();
+            assert!(!data.strings["amwaj:session:s1"].contains("caller@example.com"));
+            assert!(!data.hashes["amwaj:metadata:s1"]["phone"].contains("+15550100"));
+            assert!(!data.strings["amwaj:journal:s1"].contains("barge_in"));
+        }
+        let stored = store.get("s1").await.unwrap().unwrap();
+        assert_eq!(stored.user_id.as_deref(), Some("caller@example.com"));
+        assert_eq!(stored.get_metadata("phone").unwrap(), "+15550100");
+        assert_eq!(store.journal("s1").await.unwrap(), Some(journal));
+
+        // After rotating to b, a still opens what it sealed and reads re-seal
+        let rotated = RedisSessionStore::new(&url, "amwaj", 60)
+            .unwrap()
+            .with_cipher(cipher("b", &["a", "b"]));
+        let stored = rotated.get("s1").await.unwrap().unwrap();
+        assert_eq!(stored.get_metadata("phone").unwrap(), "+15550100");
+        assert!(data.lock().hashes["amwaj:metadata:s1"]["phone"].starts_with("amwaj:enc:v1:b:"));
+        rotated.update(&stored).await.unwrap();
+
+        // Once a is retired the session still reads back
+        let retired = RedisSessionStore::new(&url, "amwaj", 60)
+            .unwrap()
+            .with_cipher(cipher("b", &["b"]));
+        let stored = retired.get("s1").await.unwrap().unwrap();
+        assert_eq!(stored.user_id.as_deref(), Some("caller@example.com"));
+        assert!(retired.journal("s1").await.is_err());
+
+        // State written before encryption was enabled is only read while
+        // plaintext is allowed
+        let plain = RedisSessionStore::new(&url, "amwaj", 60).unwrap();
+        plain
+            .insert(&SessionData::new("s2".to_string()))
+            .await
+            .unwrap();
+        assert!(retired.get("s2").await.is_err());
+        let migrating = RedisSessionStore::new(&url, "amwaj", 60)
+            .unwrap()
+            .with_cipher(cipher("b", &["b"]).with_plaintext_allowed(true));
+        assert!(migrating.get("s2").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_redis_load_reports() {
+        let (url, data) = serve().await;
+        let store = RedisSessionStore::new(&url, "amwaj", 60).unwrap();
+        let report = LoadReport {
+            instance_id: "a".to_string(),
+            address: Some("10.0.3.7:50051".to_string()),
+            active_sessions: 3,
+            max_sessions: 10,
+            cpu_headroom: 0.7,
+            audio_thread_utilization: 1.2,
+            draining: false,
+            reported_at_ms: 0,
+        };
+        store.publish_load(&report, 15).await.unwrap();
+        assert_eq!(data.lock().ttls["amwaj:load:a"], 15);
+        assert_eq!(store.load_reports().await.unwrap(), vec![report]);
+    }
+}
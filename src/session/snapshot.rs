@@ -0,0 +1,93 @@
+//! Session snapshots for live migration
+//!
+//! To move a session to another instance (rebalancing, node drain) its
+//! owner captures the session's state, parks the snapshot in the session
+//! store and releases its lease. The instance the client reconnects to
+//! claims the session and resumes from the snapshot instead of starting
+//! cold, so the call only loses the audio in flight during the reconnect.
+
+use crate::detection::DetectorSnapshot;
+use crate::grpc::audio_stream::AudioEncoding;
+use crate::session::SessionJournal;
+use crate::sinks::CallTally;
+use crate::webrtc::PeerSnapshot;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Version of the snapshot format, bumped on incompatible changes
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Full state of a session, as handed from one instance to the next
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    /// Snapshots of another version are discarded
+    pub version: u32,
+    pub session_id: String,
+    /// Instance the session migrates from
+    pub source_instance_id: String,
+    pub taken_at_ms: i64,
+    pub created_at_ms: i64,
+    pub user_id: Option<String>,
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub codec: AudioEncoding,
+    pub sample_rate: u32,
+    pub detector: String,
+    pub frames_processed: u64,
+    pub playback_sequence: u64,
+    pub debug: bool,
+    /// Turn detection state, unset when the detector can't snapshot
+    pub detection: Option<DetectorSnapshot>,
+    /// Jitter buffer and decoder state of WebRTC sessions
+    pub peer: Option<PeerSnapshot>,
+    /// Journal so far, empty from versions without one
+    #[serde(default)]
+    pub journal: SessionJournal,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// Tenant the session belongs to, see `config::TenantConfig`
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Counts for the call detail record
+    #[serde(default)]
+    pub tally: CallTally,
+}
+
+impl SessionSnapshot {
+    /// Check if this build can resume the snapshot
+    pub fn is_compatible(&self) -> bool {
+        self.version == SNAPSHOT_VERSION
+    }
+
+    /// Get the reconnection state of the session, parked for `ttl_seconds`
+    pub fn orphan(&self, ttl_seconds: u64) -> OrphanedSession {
+        OrphanedSession {
+            session_id: self.session_id.clone(),
+            source_instance_id: self.source_instance_id.clone(),
+            user_id: self.user_id.clone(),
+            orphaned_at_ms: self.taken_at_ms,
+            resume_by_ms: self.taken_at_ms + ttl_seconds as i64 * 1000,
+            frames_processed: self.frames_processed,
+            playback_sequence: self.playback_sequence,
+        }
+    }
+}
+
+/// A session parked by a draining instance, waiting for its client to
+/// reconnect elsewhere
+///
+/// Published next to the snapshot so clients and orchestrators can tell
+/// which sessions to re-attach, until when and where the media resumes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrphanedSession {
+    pub session_id: String,
+    /// Instance that was draining
+    pub source_instance_id: String,
+    pub user_id: Option<String>,
+    pub orphaned_at_ms: i64,
+    /// The snapshot expires after this, the session can't be resumed
+    pub resume_by_ms: i64,
+    /// Media position the session resumes at
+    pub frames_processed: u64,
+    /// Last playback chunk sequence number sent
+    pub playback_sequence: u64,
+}
@@ -0,0 +1,471 @@
+//! Session storage backends
+//!
+//! `DistributedSessionManager` keeps sessions in a `SessionStore`. The
+//! in-memory store serves a single instance; the Redis store
+//! (`redis-feature`) shares sessions across pods and survives restarts.
+
+use crate::session::{
+    LoadReport, OrphanedSession, SessionData, SessionJournal, SessionOwner, SessionSnapshot,
+};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+/// Storage for session data
+#[async_trait::async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Store a new session, returns false if one exists with its ID
+    async fn insert(&self, session: &SessionData) -> anyhow::Result<bool>;
+
+    /// Get a session with its metadata
+    async fn get(&self, session_id: &str) -> anyhow::Result<Option<SessionData>>;
+
+    /// Replace a stored session, refreshing its expiry
+    ///
+    /// Metadata is left as stored, it only changes with `set_metadata`.
+    async fn update(&self, session: &SessionData) -> anyhow::Result<()>;
+
+    /// Set one metadata entry of a stored session
+    async fn set_metadata(
+        &self,
+        session_id: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()>;
+
+    /// Remove a session, if stored
+    async fn remove(&self, session_id: &str) -> anyhow::Result<()>;
+
+    /// List the IDs of the stored sessions
+    async fn list(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Remove sessions idle for longer than `ttl_seconds`, returns how many
+    async fn remove_expired(&self, ttl_seconds: u64) -> anyhow::Result<usize>;
+
+    /// Take the lease on a session for `lease.instance_id`, or renew it
+    ///
+    /// Returns the owner holding the lease afterwards, another instance's
+    /// while its lease is live.
+    async fn claim(
+        &self,
+        session_id: &str,
+        lease: &SessionOwner,
+        lease_seconds: u64,
+    ) -> anyhow::Result<SessionOwner>;
+
+    /// Get the owner holding a live lease on a session
+    async fn owner(&self, session_id: &str) -> anyhow::Result<Option<SessionOwner>>;
+
+    /// Drop a lease, if `instance_id` holds it
+    async fn release(&self, session_id: &str, instance_id: &str) -> anyhow::Result<()>;
+
+    /// Park the snapshot of a migrating session for `ttl_seconds`
+    async fn save_snapshot(
+        &self,
+        snapshot: &SessionSnapshot,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()>;
+
+    /// Take the parked snapshot of a session, only one caller gets it
+    ///
+    /// Withdraws the session's orphan record, if one was published.
+    async fn take_snapshot(&self, session_id: &str) -> anyhow::Result<Option<SessionSnapshot>>;
+
+    /// Publish the reconnection state of a session parked on drain for
+    /// `ttl_seconds`
+    async fn publish_orphan(
+        &self,
+        orphan: &OrphanedSession,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()>;
+
+    /// List the orphaned sessions not resumed yet
+    async fn orphans(&self) -> anyhow::Result<Vec<OrphanedSession>>;
+
+    /// Keep the journal of an ended session for `ttl_seconds`
+    async fn save_journal(
+        &self,
+        session_id: &str,
+        journal: &SessionJournal,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()>;
+
+    /// Get the saved journal of a session
+    async fn journal(&self, session_id: &str) -> anyhow::Result<Option<SessionJournal>>;
+
+    /// Publish the load of an instance for `ttl_seconds`, replacing its
+    /// previous report
+    async fn publish_load(&self, report: &LoadReport, ttl_seconds: u64) -> anyhow::Result<()>;
+
+    /// List the load reports of the instances still reporting
+    async fn load_reports(&self) -> anyhow::Result<Vec<LoadReport>>;
+}
+
+/// Sessions held in this process
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: RwLock<HashMap<String, SessionData>>,
+    owners: RwLock<HashMap<String, SessionOwner>>,
+    /// Parked snapshots and when they expire (Unix ms)
+    snapshots: RwLock<HashMap<String, (SessionSnapshot, i64)>>,
+    /// Journals of ended sessions and when they expire (Unix ms)
+    journals: RwLock<HashMap<String, (SessionJournal, i64)>>,
+    /// Orphan records and when they expire (Unix ms)
+    orphans: RwLock<HashMap<String, (OrphanedSession, i64)>>,
+    /// Load reports by instance and when they expire (Unix ms)
+    loads: RwLock<HashMap<String, (LoadReport, i64)>>,
+}
+
+impl MemorySessionStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn insert(&self, session: &SessionData) -> anyhow::Result<bool> {
+        let mut sessions = self.sessions.write();
+        if sessions.contains_key(&session.session_id) {
+            return Ok(false);
+        }
+        sessions.insert(session.session_id.clone(), session.clone());
+        Ok(true)
+    }
+
+    async fn get(&self, session_id: &str) -> anyhow::Result<Option<SessionData>> {
+        Ok(self.sessions.read().get(session_id).cloned())
+    }
+
+    async fn update(&self, session: &SessionData) -> anyhow::Result<()> {
+        let mut sessions = self.sessions.write();
+        let stored = sessions
+            .get_mut(&session.session_id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+        let metadata = std::mem::take(&mut stored.metadata);
+        *stored = session.clone();
+        stored.metadata = metadata;
+        Ok(())
+    }
+
+    async fn set_metadata(
+        &self,
+        session_id: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        self.sessions
+            .write()
+            .get_mut(session_id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?
+            .set_metadata(key.to_string(), value.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, session_id: &str) -> anyhow::Result<()> {
+        self.sessions.write().remove(session_id);
+        Ok(())
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.sessions.read().keys().cloned().collect())
+    }
+
+    async fn remove_expired(&self, ttl_seconds: u64) -> anyhow::Result<usize> {
+        let mut sessions = self.sessions.write();
+        let before = sessions.len();
+        sessions.retain(|_, session| !session.is_expired(ttl_seconds));
+        Ok(before - sessions.len())
+    }
+
+    async fn claim(
+        &self,
+        session_id: &str,
+        lease: &SessionOwner,
+        _lease_seconds: u64,
+    ) -> anyhow::Result<SessionOwner> {
+        let mut owners = self.owners.write();
+        match owners.get(session_id) {
+            Some(owner) if owner.instance_id != lease.instance_id && !owner.is_expired() => {
+                Ok(owner.clone())
+            }
+            _ => {
+                owners.insert(session_id.to_string(), lease.clone());
+                Ok(lease.clone())
+            }
+        }
+    }
+
+    async fn owner(&self, session_id: &str) -> anyhow::Result<Option<SessionOwner>> {
+        Ok(self
+            .owners
+            .read()
+            .get(session_id)
+            .filter(|owner| !owner.is_expired())
+            .cloned())
+    }
+
+    async fn release(&self, session_id: &str, instance_id: &str) -> anyhow::Result<()> {
+        let mut owners = self.owners.write();
+        if owners
+            .get(session_id)
+            .is_some_and(|owner| owner.instance_id == instance_id)
+        {
+            owners.remove(session_id);
+        }
+        Ok(())
+    }
+
+    async fn save_snapshot(
+        &self,
+        snapshot: &SessionSnapshot,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()> {
+        let expires_ms = chrono::Utc::now().timestamp_millis() + ttl_seconds as i64 * 1000;
+        self.snapshots
+            .write()
+            .insert(snapshot.session_id.clone(), (snapshot.clone(), expires_ms));
+        Ok(())
+    }
+
+    async fn take_snapshot(&self, session_id: &str) -> anyhow::Result<Option<SessionSnapshot>> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        self.orphans.write().remove(session_id);
+        Ok(self
+            .snapshots
+            .write()
+            .remove(session_id)
+            .filter(|(_, expires_ms)| *expires_ms > now_ms)
+            .map(|(snapshot, _)| snapshot))
+    }
+
+    async fn publish_orphan(
+        &self,
+        orphan: &OrphanedSession,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()> {
+        let expires_ms = chrono::Utc::now().timestamp_millis() + ttl_seconds as i64 * 1000;
+        self.orphans
+            .write()
+            .insert(orphan.session_id.clone(), (orphan.clone(), expires_ms));
+        Ok(())
+    }
+
+    async fn orphans(&self) -> anyhow::Result<Vec<OrphanedSession>> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let mut orphans = self.orphans.write();
+        orphans.retain(|_, (_, expires_ms)| *expires_ms > now_ms);
+        Ok(orphans.values().map(|(orphan, _)| orphan.clone()).collect())
+    }
+
+    async fn save_journal(
+        &self,
+        session_id: &str,
+        journal: &SessionJournal,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let mut journals = self.journals.write();
+        journals.retain(|_, (_, expires_ms)| *expires_ms > now_ms);
+        journals.insert(
+            session_id.to_string(),
+            (journal.clone(), now_ms + ttl_seconds as i64 * 1000),
+        );
+        Ok(())
+    }
+
+    async fn journal(&self, session_id: &str) -> anyhow::Result<Option<SessionJournal>> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        Ok(self
+            .journals
+            .read()
+            .get(session_id)
+            .filter(|(_, expires_ms)| *expires_ms > now_ms)
+            .map(|(journal, _)| journal.clone()))
+    }
+
+    async fn publish_load(&self, report: &LoadReport, ttl_seconds: u64) -> anyhow::Result<()> {
+        let expires_ms = chrono::Utc::now().timestamp_millis() + ttl_seconds as i64 * 1000;
+        self.loads
+            .write()
+            .insert(report.instance_id.clone(), (report.clone(), expires_ms));
+        Ok(())
+    }
+
+    async fn load_reports(&self) -> anyhow::Result<Vec<LoadReport>> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let mut loads = self.loads.write();
+        loads.retain(|_, (_, expires_ms)| *expires_ms > now_ms);
+        Ok(loads.values().map(|(report, _)| report.clone()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemorySessionStore::new();
+        let mut session = SessionData::new("s1".to_string());
+        session.set_metadata("tenant".to_string(), "acme".to_string());
+        assert!(store.insert(&session).await.unwrap());
+        assert!(!store.insert(&session).await.unwrap());
+
+        // Updates keep the stored metadata
+        let mut update = SessionData::new("s1".to_string());
+        update.user_id = Some("user-1".to_string());
+        store.update(&update).await.unwrap();
+        let stored = store.get("s1").await.unwrap().unwrap();
+        assert_eq!(stored.user_id.as_deref(), Some("user-1"));
+        assert_eq!(stored.get_metadata("tenant").unwrap(), "acme");
+
+        assert!(store
+            .set_metadata("missing", "k", &"v".into())
+            .await
+            .is_err());
+        assert!(store
+            .update(&SessionData::new("missing".to_string()))
+            .await
+            .is_err());
+
+        let mut idle = SessionData::new("s2".to_string());
+        idle.last_activity = chrono::Utc::now() - chrono::Duration::seconds(120);
+        store.insert(&idle).await.unwrap();
+        assert_eq!(store.remove_expired(60).await.unwrap(), 1);
+        assert_eq!(store.list().await.unwrap(), vec!["s1"]);
+
+        store.remove("s1").await.unwrap();
+        assert!(store.get("s1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_leases() {
+        let store = MemorySessionStore::new();
+        let lease = |instance_id: &str, lease_expires_ms: i64| SessionOwner {
+            instance_id: instance_id.to_string(),
+            address: None,
+            lease_expires_ms,
+        };
+        let live = chrono::Utc::now().timestamp_millis() + 60_000;
+
+        assert_eq!(
+            store.claim("s1", &lease("a", 0), 15).await.unwrap(),
+            lease("a", 0)
+        );
+        // An expired lease is neither reported nor respected
+        assert!(store.owner("s1").await.unwrap().is_none());
+        store.claim("s1", &lease("b", live), 15).await.unwrap();
+        assert_eq!(
+            store
+                .claim("s1", &lease("a", live), 15)
+                .await
+                .unwrap()
+                .instance_id,
+            "b"
+        );
+
+        store.release("s1", "a").await.unwrap();
+        assert_eq!(store.owner("s1").await.unwrap().unwrap().instance_id, "b");
+        store.release("s1", "b").await.unwrap();
+        assert!(store.owner("s1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_snapshots() {
+        let store = MemorySessionStore::new();
+        let snapshot = SessionSnapshot {
+            version: crate::session::SNAPSHOT_VERSION,
+            session_id: "s1".to_string(),
+            source_instance_id: "a".to_string(),
+            taken_at_ms: 0,
+            created_at_ms: 0,
+            user_id: None,
+            metadata: HashMap::new(),
+            codec: crate::grpc::audio_stream::AudioEncoding::Pcm16,
+            sample_rate: 16000,
+            detector: "state_machine".to_string(),
+            frames_processed: 50,
+            playback_sequence: 0,
+            debug: false,
+            detection: None,
+            peer: None,
+            journal: SessionJournal::default(),
+            tags: HashMap::new(),
+            tenant: None,
+            tally: crate::sinks::CallTally::default(),
+        };
+        store.save_snapshot(&snapshot, 30).await.unwrap();
+        assert_eq!(
+            store.take_snapshot("s1").await.unwrap(),
+            Some(snapshot.clone())
+        );
+        assert!(store.take_snapshot("s1").await.unwrap().is_none());
+
+        // Expired snapshots are never resumed
+        store.save_snapshot(&snapshot, 0).await.unwrap();
+        assert!(store.take_snapshot("s1").await.unwrap().is_none());
+
+        // Resuming the session withdraws its orphan record
+        store.save_snapshot(&snapshot, 30).await.unwrap();
+        store
+            .publish_orphan(&snapshot.orphan(30), 30)
+            .await
+            .unwrap();
+        let orphans = store.orphans().await.unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].resume_by_ms, 30_000);
+        assert_eq!(orphans[0].frames_processed, 50);
+        store.take_snapshot("s1").await.unwrap().unwrap();
+        assert!(store.orphans().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_journals() {
+        let store = MemorySessionStore::new();
+        let mut journal = SessionJournal::default();
+        journal.record(
+            1,
+            crate::session::JournalEvent::Overlap { duration_ms: 200 },
+        );
+
+        store.save_journal("s1", &journal, 60).await.unwrap();
+        assert_eq!(store.journal("s1").await.unwrap(), Some(journal.clone()));
+        assert!(store.journal("s2").await.unwrap().is_none());
+        store.save_journal("s1", &journal, 0).await.unwrap();
+        assert!(store.journal("s1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_load_reports() {
+        let store = MemorySessionStore::new();
+        let report = LoadReport {
+            instance_id: "a".to_string(),
+            address: None,
+            active_sessions: 3,
+            max_sessions: 10,
+            cpu_headroom: 0.7,
+            audio_thread_utilization: 1.2,
+            draining: false,
+            reported_at_ms: 0,
+        };
+        store.publish_load(&report, 15).await.unwrap();
+        store
+            .publish_load(
+                &LoadReport {
+                    active_sessions: 4,
+                    ..report.clone()
+                },
+                15,
+            )
+            .await
+            .unwrap();
+        let reports = store.load_reports().await.unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].active_sessions, 4);
+
+        // Instances that stopped reporting drop out
+        store.publish_load(&report, 0).await.unwrap();
+        assert!(store.load_reports().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+//! Session tags
+//!
+//! Tags are short key/value labels like `campaign=support` or `lang=ar`,
+//! set when a session is created or with a `SetTags` command. Unlike
+//! metadata they are plain strings kept with the live session, so
+//! operators can list, inspect or drain just the sessions carrying them.
+
+use crate::error::AmwajError;
+use std::collections::HashMap;
+
+/// Longest tag key, in bytes
+pub const MAX_TAG_KEY_BYTES: usize = 64;
+/// Longest tag value, in bytes
+pub const MAX_TAG_VALUE_BYTES: usize = 256;
+
+/// Check a session's tags against the size limits
+///
+/// Keys are ASCII letters, digits and `-_.:/`, so tags can be written as
+/// `key=value` in filters.
+pub fn check_tags(tags: &HashMap<String, String>, max_tags: usize) -> Result<(), AmwajError> {
+    if tags.len() > max_tags {
+        return Err(AmwajError::InvalidTags(format!(
+            "{} tags, over the limit of {}",
+            tags.len(),
+            max_tags
+        )));
+    }
+    for (key, value) in tags {
+        let valid_key = !key.is_empty()
+            && key.len() <= MAX_TAG_KEY_BYTES
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.:/".contains(c));
+        if !valid_key {
+            return Err(AmwajError::InvalidTags(format!(
+                "Invalid tag key: {:?}",
+                key
+            )));
+        }
+        if value.len() > MAX_TAG_VALUE_BYTES {
+            return Err(AmwajError::InvalidTags(format!(
+                "Value of tag {} is {} bytes, over the limit of {}",
+                key,
+                value.len(),
+                MAX_TAG_VALUE_BYTES
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check if `tags` carry every tag of `filter`
+///
+/// An empty value in the filter matches any value of its key.
+pub fn matches_tags(tags: &HashMap<String, String>, filter: &HashMap<String, String>) -> bool {
+    filter.iter().all(|(key, wanted)| {
+        tags.get(key)
+            .is_some_and(|value| wanted.is_empty() || value == wanted)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_tags() {
+        assert!(check_tags(&tags(&[("campaign", "support"), ("lang", "ar")]), 2).is_ok());
+        assert!(check_tags(&tags(&[("campaign", "support"), ("lang", "ar")]), 1).is_err());
+        assert!(check_tags(&tags(&[("", "x")]), 4).is_err());
+        assert!(check_tags(&tags(&[("a=b", "x")]), 4).is_err());
+        let long = "x".repeat(MAX_TAG_VALUE_BYTES + 1);
+        assert!(check_tags(&tags(&[("note", &long)]), 4).is_err());
+    }
+
+    #[test]
+    fn test_matches_tags() {
+        let session = tags(&[("campaign", "support"), ("lang", "ar")]);
+        assert!(matches_tags(&session, &HashMap::new()));
+        assert!(matches_tags(&session, &tags(&[("lang", "ar")])));
+        assert!(matches_tags(&session, &tags(&[("campaign", "")])));
+        assert!(!matches_tags(&session, &tags(&[("lang", "en")])));
+        assert!(!matches_tags(
+            &session,
+            &tags(&[("lang", "ar"), ("tier", "")])
+        ));
+    }
+}
@@ -0,0 +1,242 @@
+//! Per-session resource accounting
+//!
+//! Each session is charged for the processing time of its frames, the
+//! audio it keeps buffered and the bytes it moves in and out. Rates are
+//! checked against the `[sessions.resource_limits]` ceilings so one heavy
+//! session can't starve the others on the instance.
+
+use crate::config::ResourceLimitsConfig;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Audio a session must have processed before its CPU rate is checked
+const CPU_WARMUP_MS: u64 = 1000;
+
+/// A resource sessions are charged for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageMetric {
+    /// Processing time per second of audio
+    Cpu,
+    /// Audio buffered for the session
+    Memory,
+    /// Bytes in and out per second
+    Bandwidth,
+}
+
+impl UsageMetric {
+    /// Get the metric's name, as used in the admin API
+    pub fn name(self) -> &'static str {
+        match self {
+            UsageMetric::Cpu => "cpu",
+            UsageMetric::Memory => "memory",
+            UsageMetric::Bandwidth => "bandwidth",
+        }
+    }
+}
+
+/// Resources used by a session so far
+#[derive(Debug, Clone)]
+pub struct SessionUsage {
+    started: Instant,
+    cpu_time: Duration,
+    audio_ms: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+    buffered_bytes: usize,
+    degraded: bool,
+    /// Data dropped under load, by kind
+    losses: BTreeMap<&'static str, u64>,
+}
+
+impl SessionUsage {
+    /// Start accounting for a new session
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            cpu_time: Duration::ZERO,
+            audio_ms: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            buffered_bytes: 0,
+            degraded: false,
+            losses: BTreeMap::new(),
+        }
+    }
+
+    /// Charge the processing of an inbound frame
+    pub fn record_frame(&mut self, cpu_time: Duration, audio_ms: u32, bytes: usize) {
+        self.cpu_time += cpu_time;
+        self.audio_ms += audio_ms as u64;
+        self.bytes_in += bytes as u64;
+    }
+
+    /// Charge bytes sent to the session's streams
+    pub fn record_sent(&mut self, bytes: usize) {
+        self.bytes_out += bytes as u64;
+    }
+
+    /// Set the bytes of audio currently buffered for the session
+    pub fn set_buffered_bytes(&mut self, bytes: usize) {
+        self.buffered_bytes = bytes;
+    }
+
+    /// Get the total processing time
+    pub fn cpu_time(&self) -> Duration {
+        self.cpu_time
+    }
+
+    /// Get the processing time per second of audio, in ms
+    pub fn cpu_ms_per_sec(&self) -> f64 {
+        if self.audio_ms == 0 {
+            return 0.0;
+        }
+        self.cpu_time.as_secs_f64() * 1_000_000.0 / self.audio_ms as f64
+    }
+
+    /// Get the bytes of audio buffered for the session
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    /// Get the bytes of audio received
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in
+    }
+
+    /// Get the bytes sent to the session's streams
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out
+    }
+
+    /// Get the bytes moved per second, averaged over at least a second
+    pub fn bandwidth_bytes_per_sec(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64().max(1.0);
+        (self.bytes_in + self.bytes_out) as f64 / elapsed
+    }
+
+    /// Get the usage of one resource, for ranking sessions
+    pub fn value(&self, metric: UsageMetric) -> f64 {
+        match metric {
+            UsageMetric::Cpu => self.cpu_ms_per_sec(),
+            UsageMetric::Memory => self.buffered_bytes as f64,
+            UsageMetric::Bandwidth => self.bandwidth_bytes_per_sec(),
+        }
+    }
+
+    /// Check if the session sheds optional work after exceeding a ceiling
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Mark the session as degraded, it stays so until it ends
+    pub fn set_degraded(&mut self) {
+        self.degraded = true;
+    }
+
+    /// Count data of the session dropped under load
+    pub fn record_loss(&mut self, kind: &'static str, count: u64) {
+        if count > 0 {
+            *self.losses.entry(kind).or_default() += count;
+        }
+    }
+
+    /// Get the data dropped so far, by kind
+    pub fn losses(&self) -> &BTreeMap<&'static str, u64> {
+        &self.losses
+    }
+
+    /// Get the first ceiling the session exceeds, with a description
+    pub fn exceeded(&self, limits: &ResourceLimitsConfig) -> Option<(UsageMetric, String)> {
+        if let Some(max) = limits.max_cpu_ms_per_sec {
+            let rate = self.cpu_ms_per_sec();
+            if self.audio_ms >= CPU_WARMUP_MS && rate > max as f64 {
+                return Some((
+                    UsageMetric::Cpu,
+                    format!("{:.1} ms of CPU per second of audio, over {}", rate, max),
+                ));
+            }
+        }
+        if let Some(max) = limits.max_buffered_bytes {
+            if self.buffered_bytes > max {
+                return Some((
+                    UsageMetric::Memory,
+                    format!(
+                        "{} bytes of buffered audio, over {}",
+                        self.buffered_bytes, max
+                    ),
+                ));
+            }
+        }
+        if let Some(max) = limits.max_bandwidth_bytes_per_sec {
+            let rate = self.bandwidth_bytes_per_sec();
+            if rate > max as f64 {
+                return Some((
+                    UsageMetric::Bandwidth,
+                    format!("{:.0} bytes per second, over {}", rate, max),
+                ));
+            }
+        }
+        None
+    }
+}
+
+impl Default for SessionUsage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_ceiling_after_warmup() {
+        let limits = ResourceLimitsConfig {
+            max_cpu_ms_per_sec: Some(100),
+            ..Default::default()
+        };
+        let mut usage = SessionUsage::new();
+        usage.record_frame(Duration::from_millis(10), 20, 640);
+        // 500 ms per second, but too little audio to judge
+        assert!(usage.exceeded(&limits).is_none());
+
+        for _ in 0..49 {
+            usage.record_frame(Duration::from_millis(1), 20, 640);
+        }
+        assert_eq!(usage.cpu_ms_per_sec().round(), 59.0);
+        assert!(usage.exceeded(&limits).is_none());
+        usage.record_frame(Duration::from_millis(60), 20, 640);
+        assert_eq!(usage.exceeded(&limits).unwrap().0, UsageMetric::Cpu);
+    }
+
+    #[test]
+    fn test_memory_and_bandwidth_ceilings() {
+        let limits = ResourceLimitsConfig {
+            max_buffered_bytes: Some(1000),
+            max_bandwidth_bytes_per_sec: Some(10_000),
+            ..Default::default()
+        };
+        let mut usage = SessionUsage::new();
+        usage.set_buffered_bytes(1001);
+        assert_eq!(usage.exceeded(&limits).unwrap().0, UsageMetric::Memory);
+
+        usage.set_buffered_bytes(0);
+        usage.record_sent(6000);
+        assert!(usage.exceeded(&limits).is_none());
+        usage.record_frame(Duration::ZERO, 20, 5000);
+        let (metric, reason) = usage.exceeded(&limits).unwrap();
+        assert_eq!(metric, UsageMetric::Bandwidth);
+        assert_eq!(reason, "11000 bytes per second, over 10000");
+    }
+
+    #[test]
+    fn test_losses() {
+        let mut usage = SessionUsage::new();
+        usage.record_loss("media_event", 3);
+        usage.record_loss("media_event", 2);
+        usage.record_loss("playback_audio", 0);
+        assert_eq!(usage.losses().len(), 1);
+        assert_eq!(usage.losses()["media_event"], 5);
+    }
+}
@@ -0,0 +1,122 @@
+//! Call detail records
+//!
+//! When a session ends, a record of its duration, codec, network quality,
+//! turns, barge-ins, mean VAD probability and why it ended is written to the
+//! `[cdr]` destinations: a JSONL file, a Postgres table (`postgres-feature`)
+//! or a webhook. Records go through sinks of their own, apart from the
+//! lifecycle events, so billing and analytics don't have to scrape metrics.
+
+use crate::grpc::service::EndReason;
+use serde::{Deserialize, Serialize};
+
+/// Summary of a finished session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallDetailRecord {
+    pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub started_at_ms: i64,
+    pub ended_at_ms: i64,
+    pub duration_ms: i64,
+    /// Codec the audio arrived in, negotiated for WebRTC and plain RTP
+    pub codec: String,
+    pub sample_rate: u32,
+    pub frames: u64,
+    pub rtp_packets: u64,
+    /// Share of RTP packets lost, 0 without WebRTC
+    pub packet_loss_ratio: f32,
+    pub turns: u32,
+    pub barge_ins: u32,
+    /// Mean VAD probability of the frames processed unpaused
+    pub mean_vad_probability: f32,
+    pub reason: EndReason,
+}
+
+/// Counts of a live session, kept for its record
+///
+/// Travels with the session when it migrates.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CallTally {
+    turns: u32,
+    barge_ins: u32,
+    vad_sum: f64,
+    vad_frames: u64,
+}
+
+impl CallTally {
+    /// Count a frame through detection
+    pub fn record_frame(&mut self, vad_probability: f32) {
+        self.vad_sum += f64::from(vad_probability);
+        self.vad_frames += 1;
+    }
+
+    pub fn record_turn(&mut self) {
+        self.turns += 1;
+    }
+
+    pub fn record_barge_in(&mut self) {
+        self.barge_ins += 1;
+    }
+
+    pub fn turns(&self) -> u32 {
+        self.turns
+    }
+
+    pub fn barge_ins(&self) -> u32 {
+        self.barge_ins
+    }
+
+    /// Get the mean VAD probability, 0 before any frame
+    pub fn mean_vad_probability(&self) -> f32 {
+        if self.vad_frames == 0 {
+            return 0.0;
+        }
+        (self.vad_sum / self.vad_frames as f64) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::SinkEvent;
+
+    #[test]
+    fn test_tally() {
+        let mut tally = CallTally::default();
+        assert_eq!(tally.mean_vad_probability(), 0.0);
+        tally.record_frame(0.2);
+        tally.record_frame(0.6);
+        tally.record_turn();
+        tally.record_barge_in();
+        assert!((tally.mean_vad_probability() - 0.4).abs() < 1e-6);
+        assert_eq!((tally.turns(), tally.barge_ins()), (1, 1));
+    }
+
+    #[test]
+    fn test_record_json() {
+        let event = SinkEvent::CallDetailRecord(CallDetailRecord {
+            session_id: "s1".to_string(),
+            user_id: None,
+            tenant: Some("acme".to_string()),
+            started_at_ms: 1000,
+            ended_at_ms: 61_000,
+            duration_ms: 60_000,
+            codec: "pcmu".to_string(),
+            sample_rate: 8000,
+            frames: 3000,
+            rtp_packets: 3000,
+            packet_loss_ratio: 0.0,
+            turns: 4,
+            barge_ins: 1,
+            mean_vad_probability: 0.5,
+            reason: EndReason::Completed,
+        });
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "call_detail_record");
+        assert_eq!(json["tenant"], "acme");
+        assert!(json.get("user_id").is_none());
+        assert_eq!(serde_json::from_value::<SinkEvent>(json).unwrap(), event);
+    }
+}
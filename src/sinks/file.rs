@@ -0,0 +1,82 @@
+//! JSONL file sink
+//!
+//! Each event is appended to the file as one line of JSON and flushed, so a
+//! record survives a crash right after it is written. The file is never
+//! rotated by the server; logrotate with `copytruncate` works alongside it.
+
+use crate::sinks::{EventSink, SinkEvent};
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Appends events to a file
+pub struct FileSink {
+    name: String,
+    file: Mutex<tokio::fs::File>,
+}
+
+impl FileSink {
+    /// Open a file for appending, creating it and its directory if needed
+    pub async fn open(name: &str, path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+        Ok(Self {
+            name: name.to_string(),
+            file: Mutex::new(file),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for FileSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn publish(&self, event: &SinkEvent) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_append_lines() {
+        let dir = std::env::temp_dir().join(format!("amwaj-file-sink-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("events.jsonl");
+        for session_id in ["s1", "s2"] {
+            // Reopening appends rather than truncates
+            let sink = FileSink::open("file", &path).await.unwrap();
+            let event = SinkEvent::TurnEnded {
+                session_id: session_id.to_string(),
+                timestamp_ms: 0,
+                duration_ms: 100,
+                tenant: None,
+            };
+            sink.publish(&event).await.unwrap();
+        }
+
+        let content = tokio::fs::read_to_string(&path).await.unwrap();
+        let events: Vec<SinkEvent> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].session_id(), "s2");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
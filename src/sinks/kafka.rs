@@ -0,0 +1,101 @@
+//! Kafka sink
+//!
+//! Events are produced as JSON, keyed by session ID so a session's events
+//! stay ordered within one partition, with the event type in a `type`
+//! header. librdkafka retries internally until `delivery_timeout_ms`.
+
+use crate::config::KafkaSinkConfig;
+use crate::sinks::{EventSink, SinkEvent};
+use rdkafka::config::ClientConfig;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use std::time::Duration;
+
+/// Produces events to a Kafka topic
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+    delivery_timeout: Duration,
+}
+
+impl KafkaSink {
+    /// Create a producer for the configured topic
+    ///
+    /// Brokers are contacted lazily, on the first event.
+    pub fn new(config: &KafkaSinkConfig) -> anyhow::Result<Self> {
+        let mut client = ClientConfig::new();
+        client
+            .set("bootstrap.servers", &config.brokers)
+            .set("message.timeout.ms", config.delivery_timeout_ms.to_string());
+        for (key, value) in &config.properties {
+            client.set(key, value);
+        }
+        let producer = client
+            .create()
+            .map_err(|e| anyhow::anyhow!("Failed to create Kafka producer: {}", e))?;
+        Ok(Self {
+            producer,
+            topic: config.topic.clone(),
+            delivery_timeout: Duration::from_millis(config.delivery_timeout_ms),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for KafkaSink {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    async fn publish(&self, event: &SinkEvent) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(event)?;
+        let record = FutureRecord::to(&self.topic)
+            .key(event.session_id())
+            .payload(&body)
+            .headers(OwnedHeaders::new().insert(Header {
+                key: "type",
+                value: Some(event.kind()),
+            }));
+        self.producer
+            .send(record, Timeout::After(self.delivery_timeout))
+            .await
+            .map_err(|(e, _)| anyhow::anyhow!("Kafka delivery to {} failed: {}", self.topic, e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_undeliverable_event_fails() {
+        let sink = KafkaSink::new(&KafkaSinkConfig {
+            brokers: "127.0.0.1:1".to_string(),
+            topic: "amwaj-events".to_string(),
+            delivery_timeout_ms: 200,
+            properties: [("client.id".to_string(), "amwaj-test".to_string())].into(),
+        })
+        .unwrap();
+        let event = SinkEvent::SessionStarted {
+            session_id: "s1".to_string(),
+            timestamp_ms: 0,
+            user_id: None,
+            tenant: None,
+        };
+        let error = sink.publish(&event).await.unwrap_err();
+        assert!(error.to_string().contains("amwaj-events"));
+    }
+
+    #[test]
+    fn test_invalid_property_rejected() {
+        let result = KafkaSink::new(&KafkaSinkConfig {
+            brokers: "127.0.0.1:9092".to_string(),
+            topic: "amwaj-events".to_string(),
+            delivery_timeout_ms: 1000,
+            properties: [("no.such.setting".to_string(), "1".to_string())].into(),
+        });
+        assert!(result.is_err());
+    }
+}
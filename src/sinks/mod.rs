@@ -0,0 +1,573 @@
+//! Event sinks for session lifecycle events
+//!
+//! Consumers that don't hold a media stream open, like analytics or
+//! billing, receive session starts, turn ends and session ends through
+//! sinks. Each sink has its own bounded queue and worker, so a slow or
+//! failing destination never blocks the media path; events are dropped
+//! once its queue is full.
+//!
+//! Besides the webhook, events can be produced to Kafka (`kafka-feature`)
+//! or published to NATS (`nats-feature`). Call detail records have sinks
+//! of their own, see `cdr`.
+
+pub mod cdr;
+pub mod file;
+#[cfg(feature = "kafka-feature")]
+pub mod kafka;
+#[cfg(feature = "nats-feature")]
+pub mod nats;
+#[cfg(feature = "postgres-feature")]
+pub mod postgres;
+pub mod webhook;
+
+pub use cdr::{CallDetailRecord, CallTally};
+pub use file::FileSink;
+#[cfg(feature = "kafka-feature")]
+pub use kafka::KafkaSink;
+#[cfg(feature = "nats-feature")]
+pub use nats::NatsSink;
+#[cfg(feature = "postgres-feature")]
+pub use postgres::PostgresSink;
+pub use webhook::WebhookSink;
+
+use crate::config::{CdrConfig, SinksConfig};
+use crate::grpc::service::EndReason;
+use crate::metrics::Metrics;
+use crate::session::DebugLine;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// A session lifecycle event, serialized with a `type` tag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkEvent {
+    SessionStarted {
+        session_id: String,
+        timestamp_ms: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        user_id: Option<String>,
+        /// Tenant of the session, see `config::TenantConfig`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tenant: Option<String>,
+    },
+    TurnEnded {
+        session_id: String,
+        timestamp_ms: i64,
+        duration_ms: u32,
+        /// Tenant of the session, see `config::TenantConfig`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tenant: Option<String>,
+    },
+    SessionEnded {
+        session_id: String,
+        timestamp_ms: i64,
+        duration_ms: i64,
+        total_frames: u32,
+        reason: EndReason,
+        /// Latest debug lines, only for sessions that ended badly
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        debug_log: Vec<DebugLine>,
+        /// Tenant of the session, see `config::TenantConfig`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tenant: Option<String>,
+    },
+    /// Only delivered to the `[cdr]` sinks
+    CallDetailRecord(CallDetailRecord),
+}
+
+impl SinkEvent {
+    /// Get the session the event belongs to
+    pub fn session_id(&self) -> &str {
+        match self {
+            SinkEvent::SessionStarted { session_id, .. }
+            | SinkEvent::TurnEnded { session_id, .. }
+            | SinkEvent::SessionEnded { session_id, .. } => session_id,
+            SinkEvent::CallDetailRecord(record) => &record.session_id,
+        }
+    }
+
+    /// Get the tenant of the session, if it has one
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            SinkEvent::SessionStarted { tenant, .. }
+            | SinkEvent::TurnEnded { tenant, .. }
+            | SinkEvent::SessionEnded { tenant, .. } => tenant.as_deref(),
+            SinkEvent::CallDetailRecord(record) => record.tenant.as_deref(),
+        }
+    }
+
+    /// Get the `type` tag of the event
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SinkEvent::SessionStarted { .. } => "session_started",
+            SinkEvent::TurnEnded { .. } => "turn_ended",
+            SinkEvent::SessionEnded { .. } => "session_ended",
+            SinkEvent::CallDetailRecord(_) => "call_detail_record",
+        }
+    }
+}
+
+/// A destination for lifecycle events
+#[async_trait::async_trait]
+pub trait EventSink: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &str;
+
+    /// Deliver one event, retrying as the destination requires
+    async fn publish(&self, event: &SinkEvent) -> anyhow::Result<()>;
+}
+
+/// Fans lifecycle events out to the configured sinks
+#[derive(Clone, Default)]
+pub struct EventSinks {
+    queues: Vec<(String, mpsc::Sender<SinkEvent>)>,
+    metrics: Option<Arc<Metrics>>,
+    /// Events queued or being published, across every sink
+    pending: Arc<AtomicUsize>,
+}
+
+impl EventSinks {
+    /// Create the sinks enabled in the configuration
+    ///
+    /// Fails if a sink needs a feature this build lacks.
+    pub async fn from_config(config: &SinksConfig, metrics: Arc<Metrics>) -> anyhow::Result<Self> {
+        let mut sinks = Self::default().with_metrics(metrics);
+        if let Some(webhook) = &config.webhook {
+            sinks = sinks.with_sink(Arc::new(WebhookSink::new(webhook)?), config.queue_size);
+        }
+        if let Some(kafka) = &config.kafka {
+            #[cfg(feature = "kafka-feature")]
+            {
+                sinks = sinks.with_sink(Arc::new(KafkaSink::new(kafka)?), config.queue_size);
+            }
+            #[cfg(not(feature = "kafka-feature"))]
+            return Err(anyhow::anyhow!(
+                "Kafka sink for topic {} needs the kafka-feature",
+                kafka.topic
+            ));
+        }
+        if let Some(nats) = &config.nats {
+            #[cfg(feature = "nats-feature")]
+            {
+                let sink = NatsSink::connect(nats).await?;
+                sinks = sinks.with_sink(Arc::new(sink), config.queue_size);
+            }
+            #[cfg(not(feature = "nats-feature"))]
+            return Err(anyhow::anyhow!(
+                "NATS sink for {} needs the nats-feature",
+                nats.url
+            ));
+        }
+        Ok(sinks)
+    }
+
+    /// Create the call detail record sinks of the configuration
+    ///
+    /// Fails if Postgres is configured without the `postgres-feature`, or
+    /// a destination can't be opened.
+    pub async fn from_cdr_config(
+        config: &CdrConfig,
+        metrics: Arc<Metrics>,
+    ) -> anyhow::Result<Self> {
+        let mut sinks = Self::default().with_metrics(metrics);
+        if let Some(path) = &config.file {
+            let sink = FileSink::open("cdr_file", std::path::Path::new(path)).await?;
+            sinks = sinks.with_sink(Arc::new(sink), config.queue_size);
+        }
+        if let Some(postgres) = &config.postgres {
+            #[cfg(feature = "postgres-feature")]
+            {
+                let sink = PostgresSink::connect(postgres).await?;
+                sinks = sinks.with_sink(Arc::new(sink), config.queue_size);
+            }
+            #[cfg(not(feature = "postgres-feature"))]
+            return Err(anyhow::anyhow!(
+                "Postgres sink for table {} needs the postgres-feature",
+                postgres.table
+            ));
+        }
+        if let Some(webhook) = &config.webhook {
+            let sink = WebhookSink::new(webhook)?.with_name("cdr_webhook");
+            sinks = sinks.with_sink(Arc::new(sink), config.queue_size);
+        }
+        Ok(sinks)
+    }
+
+    /// Record delivery metrics, applies to sinks added afterwards
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Add a sink, delivering from a queue of `queue_size` events
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn with_sink(mut self, sink: Arc<dyn EventSink>, queue_size: usize) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<SinkEvent>(queue_size.max(1));
+        self.queues.push((sink.name().to_string(), sender));
+        let metrics = self.metrics.clone();
+        let pending = Arc::clone(&self.pending);
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                let result = sink.publish(&event).await;
+                pending.fetch_sub(1, Ordering::SeqCst);
+                if let Err(e) = &result {
+                    tracing::warn!(
+                        "Sink {} failed to deliver {} for session {}: {}",
+                        sink.name(),
+                        event.kind(),
+                        event.session_id(),
+                        e
+                    );
+                }
+                if let Some(metrics) = &metrics {
+                    metrics.record_sink_delivery(sink.name(), result.is_ok());
+                    metrics.set_sink_queue_depth(sink.name(), receiver.len());
+                }
+            }
+        });
+        self
+    }
+
+    /// Check if no sink is configured
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+
+    /// Queue an event for every sink without waiting
+    pub fn emit(&self, event: SinkEvent) {
+        for (name, queue) in &self.queues {
+            // Counted first, the worker may publish before `try_send` returns
+            self.pending.fetch_add(1, Ordering::SeqCst);
+            let queued = queue.try_send(event.clone()).is_ok();
+            if !queued {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+                tracing::warn!("Sink {} is full, dropping {}", name, event.kind());
+            }
+            if let Some(metrics) = &self.metrics {
+                if queued {
+                    metrics.set_sink_queue_depth(name, queue.max_capacity() - queue.capacity());
+                } else {
+                    metrics.record_sink_drop(name);
+                }
+            }
+        }
+    }
+
+    /// Wait until the queued events are delivered, or given up on
+    ///
+    /// Returns how many were still pending at the timeout.
+    pub async fn flush(&self, timeout: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let pending = self.pending.load(Ordering::SeqCst);
+            if pending == 0 || tokio::time::Instant::now() >= deadline {
+                return pending;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    /// Sink recording what it receives
+    #[derive(Default)]
+    struct RecordingSink {
+        events: Mutex<Vec<SinkEvent>>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventSink for RecordingSink {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        async fn publish(&self, event: &SinkEvent) -> anyhow::Result<()> {
+            self.events.lock().push(event.clone());
+            Ok(())
+        }
+    }
+
+    /// Sink failing every delivery
+    struct FailingSink;
+
+    #[async_trait::async_trait]
+    impl EventSink for FailingSink {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn publish(&self, _event: &SinkEvent) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("unreachable"))
+        }
+    }
+
+    fn turn_ended(session_id: &str) -> SinkEvent {
+        SinkEvent::TurnEnded {
+            session_id: session_id.to_string(),
+            timestamp_ms: 0,
+            duration_ms: 100,
+            tenant: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delivery_metrics() {
+        use crate::config::Config;
+
+        let metrics = Arc::new(Metrics::new(&Config::default()));
+        let recording = Arc::new(RecordingSink::default());
+        let sinks = EventSinks::default()
+            .with_metrics(Arc::clone(&metrics))
+            .with_sink(recording.clone(), 1)
+            .with_sink(Arc::new(FailingSink), 8);
+
+        // The workers haven't run yet, so the single-slot queue overflows
+        for session_id in ["s1", "s2", "s3"] {
+            sinks.emit(turn_ended(session_id));
+        }
+        assert_eq!(
+            metrics
+                .sink_events_dropped
+                .with_label_values(&["recording"])
+                .get(),
+            2
+        );
+        assert_eq!(
+            metrics
+                .sink_queue_depth
+                .with_label_values(&["failing"])
+                .get(),
+            3
+        );
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while metrics
+                .sink_events_failed
+                .with_label_values(&["failing"])
+                .get()
+                < 3
+            {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            metrics
+                .sink_events_delivered
+                .with_label_values(&["recording"])
+                .get(),
+            1
+        );
+        assert_eq!(
+            metrics
+                .sink_queue_depth
+                .with_label_values(&["failing"])
+                .get(),
+            0
+        );
+        assert_eq!(recording.events.lock().clone(), vec![turn_ended("s1")]);
+    }
+
+    /// Sink taking a while per delivery
+    #[derive(Default)]
+    struct SlowSink {
+        delivered: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl EventSink for SlowSink {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        async fn publish(&self, _event: &SinkEvent) -> anyhow::Result<()> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.delivered.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush() {
+        let slow = Arc::new(SlowSink::default());
+        let sinks = EventSinks::default()
+            .with_sink(slow.clone(), 8)
+            .with_sink(Arc::new(FailingSink), 8);
+        for session_id in ["s1", "s2", "s3"] {
+            sinks.emit(turn_ended(session_id));
+        }
+
+        // Failed deliveries count as done, the slow ones are still queued
+        assert!(sinks.flush(Duration::from_millis(30)).await > 0);
+        assert_eq!(sinks.flush(Duration::from_secs(1)).await, 0);
+        assert_eq!(slow.delivered.load(Ordering::SeqCst), 3);
+        assert_eq!(EventSinks::default().flush(Duration::ZERO).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_missing_feature_rejected() {
+        use crate::config::{Config, KafkaSinkConfig, NatsSinkConfig};
+
+        let metrics = Arc::new(Metrics::new(&Config::default()));
+        let kafka = SinksConfig {
+            kafka: Some(KafkaSinkConfig {
+                brokers: "127.0.0.1:9092".to_string(),
+                topic: "amwaj-events".to_string(),
+                delivery_timeout_ms: 1000,
+                properties: Default::default(),
+            }),
+            ..Default::default()
+        };
+        // The producer connects lazily, so only a missing feature fails
+        let result = EventSinks::from_config(&kafka, Arc::clone(&metrics)).await;
+        assert_eq!(result.is_err(), cfg!(not(feature = "kafka-feature")));
+
+        let nats = SinksConfig {
+            nats: Some(NatsSinkConfig {
+                url: "nats://127.0.0.1:1".to_string(),
+                subject_prefix: "amwaj.events".to_string(),
+                credentials_file: None,
+            }),
+            ..Default::default()
+        };
+        // Without the feature the config is refused, with it nothing listens
+        assert!(EventSinks::from_config(&nats, metrics).await.is_err());
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = SinkEvent::SessionEnded {
+            session_id: "s1".to_string(),
+            timestamp_ms: 1000,
+            duration_ms: 500,
+            total_frames: 25,
+            reason: EndReason::NoMedia,
+            debug_log: Vec::new(),
+            tenant: None,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "session_ended",
+                "session_id": "s1",
+                "timestamp_ms": 1000,
+                "duration_ms": 500,
+                "total_frames": 25,
+                "reason": "no_media",
+            })
+        );
+
+        let started = SinkEvent::SessionStarted {
+            session_id: "s1".to_string(),
+            timestamp_ms: 0,
+            user_id: None,
+            tenant: None,
+        };
+        assert!(serde_json::to_value(&started)
+            .unwrap()
+            .get("user_id")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_lifecycle_published() {
+        use crate::config::Config;
+        use crate::grpc::service::{AmwajMediaService, SessionOptions};
+        use crate::metrics::Metrics;
+
+        let sink = Arc::new(RecordingSink::default());
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics)
+            .with_event_sinks(EventSinks::default().with_sink(sink.clone(), 16));
+
+        let session_id = service
+            .create_session(SessionOptions {
+                user_id: Some("user-1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // A turn: loud speech, then long enough silence to end it
+        for _ in 0..20 {
+            service.push_audio(&session_id, &[10000i16; 320]).unwrap();
+        }
+        for _ in 0..50 {
+            service.push_audio(&session_id, &[0i16; 320]).unwrap();
+        }
+        service.end_session(&session_id).await.unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while sink.events.lock().len() < 3 {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let kinds: Vec<&str> = sink.events.lock().iter().map(SinkEvent::kind).collect();
+        assert_eq!(
+            kinds,
+            vec!["session_started", "turn_ended", "session_ended"]
+        );
+        assert!(matches!(
+            &sink.events.lock()[0],
+            SinkEvent::SessionStarted { user_id: Some(user_id), .. } if user_id == "user-1"
+        ));
+        // A clean end carries no debug log
+        assert!(matches!(
+            &sink.events.lock()[2],
+            SinkEvent::SessionEnded { debug_log, .. } if debug_log.is_empty()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_anomalous_end_carries_debug_log() {
+        use crate::config::Config;
+        use crate::grpc::service::{AmwajMediaService, SessionOptions};
+        use crate::metrics::Metrics;
+
+        let sink = Arc::new(RecordingSink::default());
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics)
+            .with_event_sinks(EventSinks::default().with_sink(sink.clone(), 16));
+        let session_id = service
+            .create_session(SessionOptions::default())
+            .await
+            .unwrap();
+        for _ in 0..20 {
+            service.push_audio(&session_id, &[10000i16; 320]).unwrap();
+        }
+        service
+            .end_session_with_reason(&session_id, EndReason::Idle)
+            .await
+            .unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while sink.events.lock().len() < 2 {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let events = sink.events.lock();
+        let SinkEvent::SessionEnded { debug_log, .. } = &events[1] else {
+            panic!("Unexpected event: {:?}", events[1]);
+        };
+        assert!(debug_log
+            .iter()
+            .any(|line| line.message.starts_with("TurnStarted")));
+        let json = serde_json::to_value(&events[1]).unwrap();
+        assert!(json["debug_log"][0]["message"].is_string());
+    }
+}
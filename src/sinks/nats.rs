@@ -0,0 +1,142 @@
+//! NATS sink
+//!
+//! Events are published as JSON to `<subject_prefix>.<type>`, so consumers
+//! can subscribe to one event type or to `<subject_prefix>.>`. The session
+//! ID travels in an `Amwaj-Session-Id` header. Core NATS publishes are
+//! fire-and-forget; each one is flushed, so a delivery counts once the
+//! event is written to the server connection.
+
+use crate::config::NatsSinkConfig;
+use crate::sinks::{EventSink, SinkEvent};
+use async_nats::HeaderMap;
+
+/// Publishes events to NATS subjects
+pub struct NatsSink {
+    client: async_nats::Client,
+    subject_prefix: String,
+}
+
+impl NatsSink {
+    /// Connect to the configured server
+    pub async fn connect(config: &NatsSinkConfig) -> anyhow::Result<Self> {
+        let mut options = async_nats::ConnectOptions::new().name("amwaj-media");
+        if let Some(path) = &config.credentials_file {
+            options = options.credentials_file(path).await?;
+        }
+        let client = options
+            .connect(config.url.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to NATS at {}: {}", config.url, e))?;
+        Ok(Self {
+            client,
+            subject_prefix: config.subject_prefix.clone(),
+        })
+    }
+
+    /// Get the subject an event is published to
+    pub fn subject(&self, event: &SinkEvent) -> String {
+        format!("{}.{}", self.subject_prefix, event.kind())
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for NatsSink {
+    fn name(&self) -> &str {
+        "nats"
+    }
+
+    async fn publish(&self, event: &SinkEvent) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(event)?;
+        let mut headers = HeaderMap::new();
+        headers.insert("Amwaj-Session-Id", event.session_id());
+        self.client
+            .publish_with_headers(self.subject(event), headers, body.into())
+            .await?;
+        self.client.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Speak just enough of the NATS protocol to record publishes
+    async fn serve() -> (String, Arc<Mutex<Vec<(String, Vec<u8>)>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let published = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&published);
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let info = format!(
+                "INFO {{\"server_id\":\"test\",\"server_name\":\"test\",\"version\":\"2.10.0\",\
+                 \"go\":\"go1.21\",\"host\":\"127.0.0.1\",\"port\":{},\"headers\":true,\
+                 \"max_payload\":1048576,\"proto\":1}}\r\n",
+                addr.port()
+            );
+            writer.write_all(info.as_bytes()).await.unwrap();
+
+            let mut reader = BufReader::new(reader);
+            let mut line = String::new();
+            while reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                match parts.as_slice() {
+                    ["PING"] => writer.write_all(b"PONG\r\n").await.unwrap(),
+                    ["HPUB", subject, _header_len, total_len] => {
+                        let total_len: usize = total_len.parse().unwrap();
+                        let mut message = vec![0; total_len + 2];
+                        reader.read_exact(&mut message).await.unwrap();
+                        message.truncate(total_len);
+                        recorded.lock().push((subject.to_string(), message));
+                    }
+                    _ => {}
+                }
+                line.clear();
+            }
+        });
+        (format!("nats://{}", addr), published)
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let (url, published) = serve().await;
+        let sink = NatsSink::connect(&NatsSinkConfig {
+            url,
+            subject_prefix: "amwaj.events".to_string(),
+            credentials_file: None,
+        })
+        .await
+        .unwrap();
+
+        let event = SinkEvent::TurnEnded {
+            session_id: "s1".to_string(),
+            timestamp_ms: 1000,
+            duration_ms: 800,
+            tenant: None,
+        };
+        sink.publish(&event).await.unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while published.lock().is_empty() {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let published = published.lock();
+        assert_eq!(published.len(), 1);
+        let (subject, message) = &published[0];
+        assert_eq!(subject, "amwaj.events.turn_ended");
+        let message = String::from_utf8(message.clone()).unwrap();
+        let (headers, body) = message.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains("Amwaj-Session-Id: s1"));
+        assert_eq!(serde_json::from_str::<SinkEvent>(body).unwrap(), event);
+    }
+}
@@ -0,0 +1,223 @@
+//! HTTP webhook sink
+//!
+//! Each event is POSTed as JSON. With a secret configured the request
+//! carries `X-Amwaj-Timestamp` and `X-Amwaj-Signature: sha256=<hex>`, the
+//! HMAC-SHA256 of `"{timestamp}.{body}"`, so receivers can authenticate it
+//! and reject replays. Network errors, timeouts, 408, 429 and 5xx responses
+//! are retried with exponential backoff.
+
+use crate::config::WebhookConfig;
+use crate::sinks::{EventSink, SinkEvent};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Sign a webhook payload, returns the hex HMAC-SHA256
+pub fn sign(secret: &str, timestamp_ms: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp_ms.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Posts events to an HTTP endpoint
+pub struct WebhookSink {
+    name: String,
+    client: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl WebhookSink {
+    /// Create a sink for a webhook
+    pub fn new(config: &WebhookConfig) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+        Ok(Self {
+            name: "webhook".to_string(),
+            client,
+            config: config.clone(),
+        })
+    }
+
+    /// Name the sink in logs and metrics, `webhook` by default
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Make one delivery attempt, returns whether a failure may be retried
+    async fn post(&self, event: &SinkEvent, body: &[u8]) -> Result<(), (bool, anyhow::Error)> {
+        let timestamp_ms = chrono::Utc::now().timestamp_millis();
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header("content-type", "application/json")
+            .header("x-amwaj-event", event.kind())
+            .header("x-amwaj-timestamp", timestamp_ms.to_string())
+            .body(body.to_vec());
+        if let Some(secret) = &self.config.secret {
+            request = request.header(
+                "x-amwaj-signature",
+                format!("sha256={}", sign(secret.expose(), timestamp_ms, body)),
+            );
+        }
+
+        let response = request.send().await.map_err(|e| (true, e.into()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let retryable = status.is_server_error()
+            || status == reqwest::StatusCode::REQUEST_TIMEOUT
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        Err((
+            retryable,
+            anyhow::anyhow!("Webhook responded with {}", status),
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn publish(&self, event: &SinkEvent) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(event)?;
+        let mut backoff = Duration::from_millis(self.config.retry_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match self.post(event, &body).await {
+                Ok(()) => return Ok(()),
+                Err((true, e)) if attempt < self.config.max_retries => {
+                    tracing::debug!("Webhook delivery failed, retrying in {:?}: {}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err((_, e)) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    struct Received {
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// Serve one HTTP response status per request, recording the requests
+    async fn serve(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&received);
+        tokio::spawn(async move {
+            for status in statuses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = HashMap::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).await.unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    headers.insert(name.to_ascii_lowercase(), value.to_string());
+                }
+                let length = headers["content-length"].parse().unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).await.unwrap();
+                recorded.lock().push(Received { headers, body });
+
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                reader
+                    .into_inner()
+                    .write_all(response.as_bytes())
+                    .await
+                    .unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn config(url: String) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            secret: Some("s3cret".into()),
+            secret_file: None,
+            max_retries: 2,
+            retry_backoff_ms: 10,
+            timeout_ms: 1000,
+        }
+    }
+
+    fn event() -> SinkEvent {
+        SinkEvent::TurnEnded {
+            session_id: "s1".to_string(),
+            timestamp_ms: 1000,
+            duration_ms: 800,
+            tenant: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signed_delivery_with_retry() {
+        let (url, received) = serve(vec![503, 200]).await;
+        let sink = WebhookSink::new(&config(url)).unwrap();
+        sink.publish(&event()).await.unwrap();
+
+        let received = received.lock();
+        assert_eq!(received.len(), 2);
+        let request = &received[1];
+        assert_eq!(request.headers["x-amwaj-event"], "turn_ended");
+        let timestamp_ms: i64 = request.headers["x-amwaj-timestamp"].parse().unwrap();
+        assert_eq!(
+            request.headers["x-amwaj-signature"],
+            format!("sha256={}", sign("s3cret", timestamp_ms, &request.body))
+        );
+        let body: SinkEvent = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, event());
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_not_retried() {
+        let (url, received) = serve(vec![400, 200]).await;
+        let sink = WebhookSink::new(&config(url)).unwrap();
+        assert!(sink.publish(&event()).await.is_err());
+        assert_eq!(received.lock().len(), 1);
+
+        let (url, received) = serve(vec![500, 500, 500]).await;
+        let sink = WebhookSink::new(&config(url)).unwrap();
+        assert!(sink.publish(&event()).await.is_err());
+        assert_eq!(received.lock().len(), 3);
+    }
+
+    #[test]
+    fn test_signature() {
+        // echo -n '1.{}' | openssl dgst -sha256 -hmac key
+        assert_eq!(
+            sign("key", 1, b"{}"),
+            "1ba6b8171186efc613e8bcc0cbdab2748f24984d7c5a84faa2637afa0e40d224"
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! Transports ingesting audio besides gRPC and WebRTC
+//!
+//! Each transport maps its connections to sessions of the media service and
+//! pushes their audio through the same pipeline, so orchestrators get the
+//! same `MediaEvent`s whichever way the audio arrived.
+
+pub mod pcap;
+pub mod reactor;
+pub mod rtp;
+pub mod simulation;
+#[cfg(feature = "sip-feature")]
+pub mod sip;
+pub mod socket;
+pub mod websocket;
+
+pub use pcap::PcapReplay;
+pub use rtp::{RtpEndpoint, RtpIngest, RtpIngestOptions};
+pub use simulation::Simulation;
+#[cfg(feature = "sip-feature")]
+pub use sip::SipGateway;
+pub use websocket::WebSocketServer;
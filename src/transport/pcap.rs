@@ -0,0 +1,602 @@
+//! Offline replay of RTP from packet captures
+//!
+//! Reads pcap and pcapng captures of Ethernet, Linux cooked, loopback or
+//! raw IP, picks the UDP datagrams of one RTP stream and runs them through
+//! the jitter buffer, the decoder and a session's pipeline, like the plain
+//! RTP ingest would. Field issues can then be reproduced from a capture
+//! with the detection settings of a config file.
+//!
+//! Replay runs as fast as it can, or paced on the capture timestamps at a
+//! multiple of real time. Detection follows the media clock either way.
+
+use crate::config::Config;
+use crate::grpc::service::{AmwajMediaService, MediaEvent, SessionOptions};
+use crate::metrics::Metrics;
+use crate::transport::rtp::{self, RtpFrames, RtpIngestOptions};
+use crate::webrtc::sdp::NegotiatedCodec;
+use std::sync::Arc;
+use std::time::Duration;
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+/// Session the replayed audio goes to
+const REPLAY_SESSION_ID: &str = "replay";
+
+/// A UDP datagram of a capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedDatagram {
+    /// Capture time in microseconds since the epoch
+    pub timestamp_us: i64,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub payload: Vec<u8>,
+}
+
+/// Little or big endian reads of capture headers
+#[derive(Debug, Clone, Copy)]
+struct Endian(bool);
+
+impl Endian {
+    fn u16(self, data: &[u8], offset: usize) -> Option<u16> {
+        let bytes = data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.0 {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(self, data: &[u8], offset: usize) -> Option<u32> {
+        let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.0 {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+}
+
+/// Read the UDP datagrams of a pcap or pcapng capture
+///
+/// Packets that are not UDP over IPv4 or IPv6, and IPv4 fragments, are
+/// skipped.
+pub fn read_datagrams(capture: &[u8]) -> anyhow::Result<Vec<CapturedDatagram>> {
+    let magic = capture
+        .get(..4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| anyhow::anyhow!("Capture too short"))?;
+    match magic {
+        PCAPNG_SECTION_HEADER => read_pcapng(capture),
+        _ => read_pcap(capture),
+    }
+}
+
+fn read_pcap(capture: &[u8]) -> anyhow::Result<Vec<CapturedDatagram>> {
+    let truncated = || anyhow::anyhow!("Truncated pcap header");
+    let (endian, nanos) = [Endian(true), Endian(false)]
+        .into_iter()
+        .find_map(|endian| match endian.u32(capture, 0)? {
+            PCAP_MAGIC_MICROS => Some((endian, false)),
+            PCAP_MAGIC_NANOS => Some((endian, true)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("Not a pcap or pcapng capture"))?;
+    let linktype = endian.u32(capture, 20).ok_or_else(truncated)? as u16;
+
+    let mut datagrams = Vec::new();
+    let mut offset = 24;
+    while offset + 16 <= capture.len() {
+        let seconds = endian.u32(capture, offset).ok_or_else(truncated)? as i64;
+        let fraction = endian.u32(capture, offset + 4).ok_or_else(truncated)? as i64;
+        let length = endian.u32(capture, offset + 8).ok_or_else(truncated)? as usize;
+        let data = capture
+            .get(offset + 16..offset + 16 + length)
+            .ok_or_else(|| anyhow::anyhow!("Truncated packet at byte {}", offset))?;
+        let fraction_us = if nanos { fraction / 1000 } else { fraction };
+        datagrams.extend(udp_datagram(
+            linktype,
+            data,
+            seconds * 1_000_000 + fraction_us,
+        ));
+        offset += 16 + length;
+    }
+    Ok(datagrams)
+}
+
+/// Interface of a pcapng section
+struct Interface {
+    linktype: u16,
+    /// Timestamp units per second
+    units_per_sec: u64,
+}
+
+fn read_pcapng(capture: &[u8]) -> anyhow::Result<Vec<CapturedDatagram>> {
+    let mut datagrams = Vec::new();
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut endian = Endian(true);
+    let mut offset = 0;
+    while offset + 12 <= capture.len() {
+        let block_type = endian.u32(capture, offset).unwrap_or_default();
+        if block_type == PCAPNG_SECTION_HEADER {
+            // Each section sets its own byte order and interfaces
+            endian = [Endian(true), Endian(false)]
+                .into_iter()
+                .find(|endian| endian.u32(capture, offset + 8) == Some(PCAPNG_BYTE_ORDER_MAGIC))
+                .ok_or_else(|| anyhow::anyhow!("Invalid pcapng byte order"))?;
+            interfaces.clear();
+        }
+        let length = endian.u32(capture, offset + 4).unwrap_or_default() as usize;
+        let block = capture
+            .get(offset..offset + length)
+            .filter(|_| length >= 12)
+            .ok_or_else(|| anyhow::anyhow!("Truncated pcapng block at byte {}", offset))?;
+        let body = &block[8..length - 4];
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let linktype = endian.u16(body, 0).unwrap_or_default();
+                interfaces.push(Interface {
+                    linktype,
+                    units_per_sec: timestamp_resolution(endian, body.get(8..).unwrap_or_default()),
+                });
+            }
+            PCAPNG_ENHANCED_PACKET if body.len() >= 20 => {
+                let interface = endian.u32(body, 0).unwrap_or_default() as usize;
+                let interface = interfaces
+                    .get(interface)
+                    .ok_or_else(|| anyhow::anyhow!("Packet of unknown interface {}", interface))?;
+                let high = endian.u32(body, 4).unwrap_or_default() as u64;
+                let low = endian.u32(body, 8).unwrap_or_default() as u64;
+                let captured = endian.u32(body, 12).unwrap_or_default() as usize;
+                let data = body
+                    .get(20..20 + captured)
+                    .ok_or_else(|| anyhow::anyhow!("Truncated packet at byte {}", offset))?;
+                let units = (high << 32) | low;
+                let timestamp_us =
+                    (units as u128 * 1_000_000 / interface.units_per_sec as u128) as i64;
+                datagrams.extend(udp_datagram(interface.linktype, data, timestamp_us));
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+    Ok(datagrams)
+}
+
+/// Get the timestamp units per second from the options of an interface
+fn timestamp_resolution(endian: Endian, mut options: &[u8]) -> u64 {
+    while let (Some(code), Some(length)) = (endian.u16(options, 0), endian.u16(options, 2)) {
+        let length = length as usize;
+        if code == PCAPNG_OPTION_TSRESOL {
+            if let Some(&resolution) = options.get(4) {
+                let exponent = u32::from(resolution & 0x7f);
+                return if resolution & 0x80 != 0 {
+                    2u64.checked_pow(exponent)
+                } else {
+                    10u64.checked_pow(exponent)
+                }
+                .unwrap_or(1_000_000);
+            }
+        }
+        let Some(rest) = options.get(4 + length.div_ceil(4) * 4..) else {
+            break;
+        };
+        options = rest;
+    }
+    1_000_000
+}
+
+/// Extract the UDP datagram of a captured frame
+fn udp_datagram(linktype: u16, frame: &[u8], timestamp_us: i64) -> Option<CapturedDatagram> {
+    let endian = Endian(false);
+    let ip = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            while endian.u16(frame, offset)? == ETHERTYPE_VLAN {
+                offset += 4;
+            }
+            match endian.u16(frame, offset)? {
+                ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => frame.get(offset + 2..)?,
+                _ => return None,
+            }
+        }
+        LINKTYPE_LINUX_SLL => frame.get(16..)?,
+        LINKTYPE_LINUX_SLL2 => frame.get(20..)?,
+        // Address family in the host byte order of the capture
+        LINKTYPE_NULL => frame.get(4..)?,
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => frame,
+        _ => return None,
+    };
+    let udp = match ip.first()? >> 4 {
+        4 => {
+            let header_length = ((ip[0] & 0x0f) as usize) * 4;
+            let fragment = endian.u16(ip, 6)?;
+            // Fragments other than a whole datagram can't be reassembled here
+            if ip.get(9)? != &IP_PROTOCOL_UDP || fragment & 0x3fff != 0 {
+                return None;
+            }
+            ip.get(header_length..)?
+        }
+        6 if ip.get(6)? == &IP_PROTOCOL_UDP => ip.get(40..)?,
+        _ => return None,
+    };
+    let length = endian.u16(udp, 4)? as usize;
+    Some(CapturedDatagram {
+        timestamp_us,
+        src_port: endian.u16(udp, 0)?,
+        dst_port: endian.u16(udp, 2)?,
+        payload: udp.get(8..length.max(8))?.to_vec(),
+    })
+}
+
+/// An event emitted while replaying a capture
+#[derive(Debug, Clone)]
+pub struct ReplayedEvent {
+    /// Capture time of the packet that produced it, from the first packet
+    pub capture_ms: i64,
+    pub event: MediaEvent,
+}
+
+impl ReplayedEvent {
+    /// Render as a JSON object with a `type` tag
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "capture_ms": self.capture_ms,
+            "type": self.event.name(),
+        });
+        let fields = match &self.event {
+            MediaEvent::TurnStarted {
+                timestamp_ms,
+                vad_probability,
+                ..
+            }
+            | MediaEvent::BargeIn {
+                timestamp_ms,
+                vad_probability,
+                ..
+            } => serde_json::json!({
+                "timestamp_ms": timestamp_ms,
+                "vad_probability": vad_probability,
+            }),
+            MediaEvent::TurnEnded {
+                timestamp_ms,
+                duration_ms,
+                ..
+            }
+            | MediaEvent::Overlap {
+                timestamp_ms,
+                duration_ms,
+                ..
+            } => serde_json::json!({
+                "timestamp_ms": timestamp_ms,
+                "duration_ms": duration_ms,
+            }),
+            MediaEvent::EndOfTurnAnticipated {
+                timestamp_ms,
+                confidence,
+                ..
+            } => serde_json::json!({
+                "timestamp_ms": timestamp_ms,
+                "confidence": confidence,
+            }),
+            MediaEvent::DetectionDebug {
+                timestamp_ms,
+                state,
+                ..
+            } => serde_json::json!({
+                "timestamp_ms": timestamp_ms,
+                "state": state,
+            }),
+            _ => serde_json::json!({}),
+        };
+        if let (Some(json), serde_json::Value::Object(fields)) = (json.as_object_mut(), fields) {
+            json.extend(fields);
+        }
+        json
+    }
+}
+
+/// Totals of a replay
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    /// SSRC of the replayed stream, `None` without RTP
+    pub ssrc: Option<u32>,
+    pub packets: u64,
+    pub frames: u64,
+    pub events: u64,
+}
+
+/// Replays the RTP of a capture through a session's pipeline
+pub struct PcapReplay {
+    config: Config,
+    stream: RtpIngestOptions,
+    /// Multiple of real time, unpaced at 0
+    speed: f64,
+}
+
+impl PcapReplay {
+    /// Create a replay with the pipeline settings of `config`
+    ///
+    /// The stream is PCMU of the first SSRC found unless `with_stream` says
+    /// otherwise.
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            stream: RtpIngestOptions {
+                codec: "pcmu".to_string(),
+                ..Default::default()
+            },
+            speed: 0.0,
+        }
+    }
+
+    /// Replay the stream of this codec, payload type and SSRC
+    pub fn with_stream(mut self, stream: RtpIngestOptions) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Pace packets at a multiple of real time, 0 replays unpaced
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        self.speed = speed.max(0.0);
+        self
+    }
+
+    /// Replay a capture, handing each event over as it is emitted
+    ///
+    /// Audio frames are not handed over.
+    pub async fn run(
+        &self,
+        capture: &[u8],
+        mut on_event: impl FnMut(ReplayedEvent),
+    ) -> anyhow::Result<ReplaySummary> {
+        let negotiated = rtp::negotiate(&self.stream, &self.config.audio.codecs)?;
+        let datagrams = read_datagrams(capture)?;
+        let metrics = Arc::new(Metrics::new(&self.config));
+        let service = AmwajMediaService::new(self.config.clone(), metrics);
+        let sample_rate = rtp::session_sample_rate(negotiated.codec);
+        service
+            .create_session(SessionOptions {
+                session_id: Some(REPLAY_SESSION_ID.to_string()),
+                sample_rate: Some(sample_rate),
+                ..Default::default()
+            })
+            .await?;
+        let mut frames = RtpFrames::new(
+            REPLAY_SESSION_ID,
+            negotiated,
+            &self.config.audio.codecs,
+            self.config.audio.frame_duration_ms,
+        );
+
+        let mut summary = ReplaySummary {
+            ssrc: self.stream.ssrc,
+            ..Default::default()
+        };
+        let mut first_us = None;
+        let started = tokio::time::Instant::now();
+        for datagram in datagrams {
+            let payload = &datagram.payload;
+            if !is_stream_packet(payload, &negotiated, &mut summary.ssrc) {
+                continue;
+            }
+            let capture_us = datagram.timestamp_us - *first_us.get_or_insert(datagram.timestamp_us);
+            if self.speed > 0.0 {
+                let due = Duration::from_micros(capture_us.max(0) as u64).div_f64(self.speed);
+                tokio::time::sleep_until(started + due).await;
+            }
+            summary.packets += 1;
+            if let Err(e) = frames.push(payload) {
+                tracing::debug!("Dropping replayed RTP: {}", e);
+                continue;
+            }
+            while let Some(frame) = frames.next_frame() {
+                let events = service.push_audio(REPLAY_SESSION_ID, frame)?;
+                summary.frames += 1;
+                for event in events {
+                    if matches!(event, MediaEvent::AudioFrame { .. }) {
+                        continue;
+                    }
+                    summary.events += 1;
+                    on_event(ReplayedEvent {
+                        capture_ms: capture_us / 1000,
+                        event,
+                    });
+                }
+            }
+        }
+        service.end_session(REPLAY_SESSION_ID).await?;
+        Ok(summary)
+    }
+}
+
+/// Check a datagram is RTP of the replayed stream, latching onto the first
+/// SSRC when none is set
+fn is_stream_packet(data: &[u8], negotiated: &NegotiatedCodec, ssrc: &mut Option<u32>) -> bool {
+    if data.len() < 12 || data[0] >> 6 != 2 || data[1] & 0x7f != negotiated.payload_type {
+        return false;
+    }
+    let packet_ssrc = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+    *ssrc.get_or_insert(packet_ssrc) == packet_ssrc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webrtc::codec;
+    use crate::webrtc::RtpPacket;
+
+    /// Ethernet, IPv4 and UDP headers around an RTP packet
+    fn ethernet_frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        let total_length = (20 + 8 + payload.len()) as u16;
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&total_length.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, IP_PROTOCOL_UDP, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        frame.extend_from_slice(&4000u16.to_be_bytes());
+        frame.extend_from_slice(&5000u16.to_be_bytes());
+        frame.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn rtp(ssrc: u32, sequence_number: u16, samples: &[i16]) -> Vec<u8> {
+        RtpPacket {
+            version: 2,
+            padding: false,
+            extension: false,
+            csrc_count: 0,
+            marker: false,
+            payload_type: 0,
+            sequence_number,
+            timestamp: sequence_number as u32 * 160,
+            ssrc,
+            payload: codec::encode_pcmu(samples),
+        }
+        .serialize()
+    }
+
+    /// A pcap of packets captured at the given ms
+    fn pcap(packets: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut capture = Vec::new();
+        for field in [
+            PCAP_MAGIC_MICROS,
+            0x0004_0002,
+            0,
+            0,
+            65535,
+            LINKTYPE_ETHERNET as u32,
+        ] {
+            capture.extend_from_slice(&field.to_le_bytes());
+        }
+        for (capture_ms, packet) in packets {
+            let frame = ethernet_frame(packet);
+            let timestamp_us = 1_700_000_000_000_000u64 + capture_ms * 1000;
+            for field in [
+                (timestamp_us / 1_000_000) as u32,
+                (timestamp_us % 1_000_000) as u32,
+                frame.len() as u32,
+                frame.len() as u32,
+            ] {
+                capture.extend_from_slice(&field.to_le_bytes());
+            }
+            capture.extend_from_slice(&frame);
+        }
+        capture
+    }
+
+    #[test]
+    fn test_read_pcapng() {
+        let payload = rtp(7, 1, &[0; 160]);
+        let frame = ethernet_frame(&payload);
+        let block = |block_type: u32, body: &[u8]| {
+            let padded = body.len().div_ceil(4) * 4;
+            let length = (12 + padded) as u32;
+            let mut block = Vec::new();
+            block.extend_from_slice(&block_type.to_le_bytes());
+            block.extend_from_slice(&length.to_le_bytes());
+            block.extend_from_slice(body);
+            block.resize(8 + padded, 0);
+            block.extend_from_slice(&length.to_le_bytes());
+            block
+        };
+        let mut section = PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes().to_vec();
+        section.extend_from_slice(&[1, 0, 0, 0]);
+        section.extend_from_slice(&u64::MAX.to_le_bytes());
+        // Ethernet, nanosecond timestamps
+        let mut interface = vec![1, 0, 0, 0, 0, 0, 0, 0];
+        interface.extend_from_slice(&[PCAPNG_OPTION_TSRESOL as u8, 0, 1, 0, 9, 0, 0, 0]);
+        interface.extend_from_slice(&[0; 4]);
+        let timestamp_ns = 1_500_000_000u64;
+        let mut packet = 0u32.to_le_bytes().to_vec();
+        packet.extend_from_slice(&((timestamp_ns >> 32) as u32).to_le_bytes());
+        packet.extend_from_slice(&(timestamp_ns as u32).to_le_bytes());
+        packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&frame);
+
+        let mut capture = block(PCAPNG_SECTION_HEADER, &section);
+        capture.extend(block(PCAPNG_INTERFACE_DESCRIPTION, &interface));
+        capture.extend(block(PCAPNG_ENHANCED_PACKET, &packet));
+        let datagrams = read_datagrams(&capture).unwrap();
+        assert_eq!(
+            datagrams,
+            [CapturedDatagram {
+                timestamp_us: 1_500_000,
+                src_port: 4000,
+                dst_port: 5000,
+                payload,
+            }]
+        );
+        assert!(read_datagrams(b"not a capture").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_turn() {
+        let tone: Vec<i16> = (0..160)
+            .map(|i| ((i as f32 * 0.3).sin() * 12000.0) as i16)
+            .collect();
+        let mut packets = Vec::new();
+        for sequence_number in 0..150u16 {
+            let samples = if (25..75).contains(&sequence_number) {
+                tone.clone()
+            } else {
+                vec![0; 160]
+            };
+            let capture_ms = sequence_number as u64 * 20;
+            packets.push((capture_ms, rtp(0x1234, sequence_number, &samples)));
+            // Another stream in the same capture is left out
+            packets.push((capture_ms, rtp(0x9999, sequence_number, &tone)));
+        }
+        let capture = pcap(&packets);
+
+        let replay = PcapReplay::new(Config::default()).with_stream(RtpIngestOptions {
+            codec: "pcmu".to_string(),
+            payload_type: None,
+            ssrc: Some(0x1234),
+        });
+        let mut events = Vec::new();
+        let summary = replay
+            .run(&capture, |event| events.push(event))
+            .await
+            .unwrap();
+        assert_eq!(summary.ssrc, Some(0x1234));
+        assert_eq!(summary.packets, 150);
+        assert_eq!(summary.frames, 150);
+
+        let started = events
+            .iter()
+            .find(|event| matches!(event.event, MediaEvent::TurnStarted { .. }))
+            .unwrap();
+        assert!((500..1000).contains(&started.capture_ms));
+        let ended = events
+            .iter()
+            .find(|event| matches!(event.event, MediaEvent::TurnEnded { .. }))
+            .unwrap();
+        assert!(ended.capture_ms >= 1500);
+        let json = ended.to_json();
+        assert_eq!(json["type"], "turn_ended");
+        assert!(json["duration_ms"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,300 @@
+//! Shared RTP port read by several receive tasks
+//!
+//! With `shared_port` set, every plain RTP session receives on the same
+//! port and is told apart by the SSRC of its stream. The port is bound by
+//! one socket per reactor with SO_REUSEPORT, each read on a task of its
+//! own: the kernel hashes every flow onto one of the sockets, so receiving
+//! spreads across cores while the packets of a stream keep their order.
+//! Reactors count the packets they take, per reactor, to check the flows
+//! are balanced.
+
+use crate::config::SocketConfig;
+use crate::metrics::Metrics;
+use crate::transport::socket;
+use crate::webrtc::{pool, rtp_handler};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const MAX_DATAGRAM: usize = 65535;
+
+/// Datagrams queued for a session, a bit over a second of 20 ms audio
+const ROUTE_QUEUE: usize = 64;
+
+/// A datagram received for a session, its buffer to give back to
+/// `pool::packets()`
+#[derive(Debug)]
+pub struct Datagram {
+    pub data: Vec<u8>,
+    pub from: SocketAddr,
+}
+
+type Routes = Arc<RwLock<HashMap<u32, mpsc::Sender<Datagram>>>>;
+
+/// Receive tasks of the shared port, stopped when dropped
+pub struct RtpReactors {
+    sockets: Vec<Arc<UdpSocket>>,
+    routes: Routes,
+    packets: Vec<Arc<AtomicU64>>,
+    tasks: Vec<JoinHandle<()>>,
+    /// Socket the next route sends from
+    next: AtomicUsize,
+}
+
+impl RtpReactors {
+    /// Bind `reactors` sockets to `addr` and start reading them
+    ///
+    /// Port 0 binds them all to the same free port.
+    pub fn bind(
+        addr: SocketAddr,
+        reactors: usize,
+        config: &SocketConfig,
+        metrics: Arc<Metrics>,
+    ) -> anyhow::Result<Self> {
+        let reactors = reactors.max(1);
+        let reuse_port = reactors > 1;
+        let mut addr = addr;
+        let mut sockets = Vec::with_capacity(reactors);
+        for _ in 0..reactors {
+            let socket = socket::bind_udp(addr, config, reuse_port).map_err(|e| {
+                anyhow::anyhow!("Failed to bind the shared RTP port {}: {}", addr, e)
+            })?;
+            addr = socket.local_addr()?;
+            sockets.push(Arc::new(UdpSocket::from_std(socket)?));
+        }
+
+        let routes = Routes::default();
+        let packets: Vec<_> = (0..reactors).map(|_| Arc::new(AtomicU64::new(0))).collect();
+        let tasks = sockets
+            .iter()
+            .zip(&packets)
+            .enumerate()
+            .map(|(index, (socket, packets))| {
+                let counter = metrics
+                    .rtp_reactor_packets
+                    .with_label_values(&[&index.to_string()]);
+                tokio::spawn(receive(
+                    Arc::clone(socket),
+                    Arc::clone(&routes),
+                    Arc::clone(packets),
+                    counter,
+                ))
+            })
+            .collect();
+        tracing::info!("Receiving RTP on {} with {} reactors", addr, reactors);
+        Ok(Self {
+            sockets,
+            routes,
+            packets,
+            tasks,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.sockets[0].local_addr()
+    }
+
+    /// Take the packets of an SSRC until the route is dropped
+    pub fn route(&self, ssrc: u32) -> anyhow::Result<RtpRoute> {
+        let (sender, datagrams) = mpsc::channel(ROUTE_QUEUE);
+        {
+            let mut routes = self.routes.write();
+            if routes.contains_key(&ssrc) {
+                return Err(anyhow::anyhow!(
+                    "SSRC {:#010x} is already received on the shared RTP port",
+                    ssrc
+                ));
+            }
+            routes.insert(ssrc, sender);
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.sockets.len();
+        Ok(RtpRoute {
+            ssrc,
+            routes: Arc::clone(&self.routes),
+            socket: Arc::clone(&self.sockets[index]),
+            datagrams,
+        })
+    }
+
+    /// Get the number of packets each reactor received
+    pub fn packets(&self) -> Vec<u64> {
+        self.packets
+            .iter()
+            .map(|packets| packets.load(Ordering::Relaxed))
+            .collect()
+    }
+}
+
+impl Drop for RtpReactors {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// The packets of one SSRC on the shared port
+pub struct RtpRoute {
+    ssrc: u32,
+    routes: Routes,
+    /// Where packets to the stream's source go out
+    socket: Arc<UdpSocket>,
+    datagrams: mpsc::Receiver<Datagram>,
+}
+
+impl RtpRoute {
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    /// Wait for the next datagram, forever once the reactors stopped
+    pub async fn recv(&mut self) -> Datagram {
+        match self.datagrams.recv().await {
+            Some(datagram) => datagram,
+            None => std::future::pending().await,
+        }
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+}
+
+impl Drop for RtpRoute {
+    fn drop(&mut self) {
+        self.routes.write().remove(&self.ssrc);
+    }
+}
+
+/// Read a socket of the shared port, handing packets to their route
+async fn receive(
+    socket: Arc<UdpSocket>,
+    routes: Routes,
+    packets: Arc<AtomicU64>,
+    counter: prometheus::IntCounter,
+) {
+    let mut buffer = vec![0u8; MAX_DATAGRAM];
+    loop {
+        let Ok((len, from)) = socket.recv_from(&mut buffer).await else {
+            continue;
+        };
+        packets.fetch_add(1, Ordering::Relaxed);
+        counter.inc();
+        let data = &buffer[..len];
+        let Some(ssrc) = ssrc_of(data) else {
+            continue;
+        };
+        let routes = routes.read();
+        let Some(route) = routes.get(&ssrc) else {
+            tracing::debug!("Dropping RTP of unknown SSRC {:#010x} from {}", ssrc, from);
+            continue;
+        };
+        let mut packet = pool::packets().take();
+        packet.extend_from_slice(data);
+        if let Err(e) = route.try_send(Datagram { data: packet, from }) {
+            let datagram = match e {
+                mpsc::error::TrySendError::Full(datagram)
+                | mpsc::error::TrySendError::Closed(datagram) => datagram,
+            };
+            pool::packets().give(datagram.data);
+        }
+    }
+}
+
+/// Get the SSRC of an RTP packet, or of the sender of an RTCP one
+fn ssrc_of(data: &[u8]) -> Option<u32> {
+    if data.len() < 12 || data[0] >> 6 != 2 {
+        return None;
+    }
+    let at = if rtp_handler::is_rtcp(data) { 4 } else { 8 };
+    Some(u32::from_be_bytes([
+        data[at],
+        data[at + 1],
+        data[at + 2],
+        data[at + 3],
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::webrtc::RtpPacket;
+    use std::time::Duration;
+
+    fn rtp_packet(ssrc: u32, sequence_number: u16) -> Vec<u8> {
+        RtpPacket {
+            version: 2,
+            padding: false,
+            extension: false,
+            csrc_count: 0,
+            marker: false,
+            payload_type: 0,
+            sequence_number,
+            timestamp: sequence_number as u32 * 160,
+            ssrc,
+            payload: vec![0xFF; 160],
+        }
+        .serialize()
+    }
+
+    #[tokio::test]
+    async fn test_packets_are_routed_by_ssrc() {
+        let metrics = Arc::new(Metrics::new(&Config::default()));
+        let reactors = RtpReactors::bind(
+            "127.0.0.1:0".parse().unwrap(),
+            2,
+            &SocketConfig::default(),
+            Arc::clone(&metrics),
+        )
+        .unwrap();
+        let addr = reactors.local_addr().unwrap();
+        let mut first = reactors.route(1).unwrap();
+        let mut second = reactors.route(2).unwrap();
+        assert!(reactors.route(1).is_err());
+
+        let gateway = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for sequence_number in 1..=3 {
+            for ssrc in [1, 2, 3] {
+                gateway
+                    .send_to(&rtp_packet(ssrc, sequence_number), addr)
+                    .await
+                    .unwrap();
+            }
+        }
+        for (route, ssrc) in [(&mut first, 1), (&mut second, 2)] {
+            for sequence_number in 1..=3 {
+                let datagram = tokio::time::timeout(Duration::from_secs(5), route.recv())
+                    .await
+                    .unwrap();
+                let packet = RtpPacket::parse(&datagram.data).unwrap();
+                assert_eq!(
+                    (packet.ssrc, packet.sequence_number),
+                    (ssrc, sequence_number)
+                );
+                assert_eq!(datagram.from, gateway.local_addr().unwrap());
+            }
+        }
+        // Every packet was taken by some reactor, unknown SSRCs included
+        assert_eq!(reactors.packets().iter().sum::<u64>(), 9);
+        let counted: u64 = (0..2)
+            .map(|index| {
+                metrics
+                    .rtp_reactor_packets
+                    .with_label_values(&[&index.to_string()])
+                    .get()
+            })
+            .sum();
+        assert_eq!(counted, 9);
+
+        // A dropped route frees its SSRC
+        drop(first);
+        assert!(reactors.route(1).is_ok());
+    }
+}
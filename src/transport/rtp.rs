@@ -0,0 +1,623 @@
+//! Plain RTP ingest
+//!
+//! Media gateways on the same network can send a session's audio as plain
+//! RTP to a UDP port allocated for the session, without ICE or DTLS. The
+//! codec and payload type are set when the session is created, so there is
+//! no SDP exchange. Packets of other SSRCs than the expected one, the first
+//! one received when none is given, are dropped.
+//!
+//! Playback of the session goes back as RTP on G.711 to where the audio
+//! comes from. SIP calls are relayed the same way.
+//!
+//! The socket task only filters packets and hands them to the session's
+//! `PeerTask`; decoding and the pipeline run on tasks of their own, so a
+//! slow frame never holds up receiving.
+//!
+//! With a shared port, sessions take their SSRC's packets from the
+//! reactors reading it instead of a socket of their own.
+
+use crate::config::{CodecsConfig, SocketConfig};
+use crate::error::{self, AmwajError};
+use crate::grpc::service::{AmwajMediaService, MediaEvent};
+use crate::metrics::runtime::{self, TASK_RTP};
+use crate::proto;
+use crate::transport::reactor::RtpRoute;
+use crate::transport::socket;
+use crate::webrtc::codec::{self, codec_info, CodecInfo, CODEC_OPUS, CODEC_PCMA};
+use crate::webrtc::peer_task::DEFAULT_PACKET_QUEUE;
+use crate::webrtc::rtp_handler;
+use crate::webrtc::sdp::NegotiatedCodec;
+use crate::webrtc::{pool, PeerConnection, PeerOutput, PeerTask, RtpPacket};
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+
+/// Samples of a playback packet, 20 ms at 8 kHz
+const PLAYBACK_PACKET_SAMPLES: usize = 160;
+
+const PLAYBACK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How often a relay checks its session still exists
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+const MAX_DATAGRAM: usize = 65535;
+
+/// Plain RTP a session is to receive
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtpIngestOptions {
+    /// Codec name of `[audio.codecs]`: pcmu, pcma or opus
+    pub codec: String,
+    /// Payload type, the codec's configured or static one when unset
+    pub payload_type: Option<u8>,
+    /// SSRC of the stream, the first one received when unset
+    pub ssrc: Option<u32>,
+}
+
+/// Where a session receives plain RTP, and what
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtpEndpoint {
+    pub address: SocketAddr,
+    pub codec: &'static str,
+    pub payload_type: u8,
+    pub ssrc: Option<u32>,
+}
+
+/// Get the sample rate of the sessions of a codec
+///
+/// Opus is decoded at 16 kHz, G.711 stays at its 8 kHz.
+pub fn session_sample_rate(codec: &CodecInfo) -> u32 {
+    if codec.name == CODEC_OPUS {
+        16000
+    } else {
+        codec.clock_rate
+    }
+}
+
+/// Resolve the codec and payload type of plain RTP
+pub fn negotiate(
+    options: &RtpIngestOptions,
+    codecs: &CodecsConfig,
+) -> error::Result<NegotiatedCodec> {
+    let codec = codec_info(&options.codec)
+        .ok_or_else(|| AmwajError::CodecError(format!("Unknown codec: {}", options.codec)))?;
+    let payload_type = options
+        .payload_type
+        .or_else(|| codecs.payload_types.get(codec.name).copied())
+        .or(codec.static_payload_type)
+        .ok_or_else(|| {
+            AmwajError::CodecError(format!("No payload type for codec {}", codec.name))
+        })?;
+    if payload_type > 127 {
+        return Err(AmwajError::CodecError(format!(
+            "Invalid payload type {}",
+            payload_type
+        )));
+    }
+    Ok(NegotiatedCodec {
+        codec,
+        payload_type,
+    })
+}
+
+/// Range of UDP ports RTP is received on
+pub struct RtpPorts {
+    min: u16,
+    max: u16,
+    /// Offset in the range of the next port to try
+    next: u32,
+    socket: SocketConfig,
+}
+
+impl RtpPorts {
+    pub fn new(min: u16, max: u16) -> Self {
+        Self {
+            min,
+            max,
+            next: 0,
+            socket: SocketConfig::default(),
+        }
+    }
+
+    /// Bind sockets with these options
+    pub fn with_socket(mut self, socket: SocketConfig) -> Self {
+        self.socket = socket;
+        self
+    }
+
+    /// Bind a socket on `ip` to the next free port of the range
+    pub fn bind(&mut self, ip: IpAddr) -> anyhow::Result<UdpSocket> {
+        let span = u32::from(self.max.saturating_sub(self.min)) + 1;
+        for attempt in 0..span {
+            let offset = (self.next + attempt) % span;
+            let port = self.min + offset as u16;
+            let socket = match socket::bind_udp(SocketAddr::new(ip, port), &self.socket, false) {
+                Ok(socket) => socket,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::AddrInUse | std::io::ErrorKind::PermissionDenied
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+            self.next = (offset + 1) % span;
+            return Ok(UdpSocket::from_std(socket)?);
+        }
+        Err(anyhow::anyhow!(
+            "No free RTP port from {} to {}",
+            self.min,
+            self.max
+        ))
+    }
+}
+
+/// Where a relay receives RTP
+pub enum RtpSocket {
+    /// A port of the session's own
+    Owned(UdpSocket),
+    /// Its SSRC on the shared port
+    Shared(RtpRoute),
+}
+
+impl RtpSocket {
+    /// Wait for the next datagram, copied into `buffer`
+    async fn recv_from(&mut self, buffer: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        match self {
+            RtpSocket::Owned(socket) => socket.recv_from(buffer).await,
+            RtpSocket::Shared(route) => {
+                let datagram = route.recv().await;
+                let len = datagram.data.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&datagram.data[..len]);
+                pool::packets().give(datagram.data);
+                Ok((len, datagram.from))
+            }
+        }
+    }
+
+    fn sender(&self) -> &UdpSocket {
+        match self {
+            RtpSocket::Owned(socket) => socket,
+            RtpSocket::Shared(route) => route.socket(),
+        }
+    }
+}
+
+impl From<UdpSocket> for RtpSocket {
+    fn from(socket: UdpSocket) -> Self {
+        RtpSocket::Owned(socket)
+    }
+}
+
+impl From<RtpRoute> for RtpSocket {
+    fn from(route: RtpRoute) -> Self {
+        RtpSocket::Shared(route)
+    }
+}
+
+/// Relays RTP to and from a session until it ends
+pub struct RtpRelay {
+    service: AmwajMediaService,
+    session_id: String,
+    socket: RtpSocket,
+    /// Decoding state, moved onto its task once the relay runs
+    peer: Option<PeerConnection>,
+    negotiated: NegotiatedCodec,
+    /// Where playback goes, latched onto the source of the received RTP
+    remote: Option<SocketAddr>,
+    ssrc: Option<u32>,
+    frame_size: usize,
+    sample_rate: u32,
+    packets: Arc<AtomicU64>,
+}
+
+impl RtpRelay {
+    pub fn new(
+        service: AmwajMediaService,
+        session_id: &str,
+        socket: impl Into<RtpSocket>,
+        negotiated: NegotiatedCodec,
+    ) -> Self {
+        let sample_rate = session_sample_rate(negotiated.codec);
+        let frame_size = sample_rate * service.config().audio.frame_duration_ms / 1000;
+        let mut peer = PeerConnection::new(session_id.to_string())
+            .with_codecs(service.config().audio.codecs.clone());
+        peer.set_negotiated_codec(negotiated);
+        Self {
+            service,
+            session_id: session_id.to_string(),
+            socket: socket.into(),
+            peer: Some(peer),
+            negotiated,
+            remote: None,
+            ssrc: None,
+            frame_size: frame_size as usize,
+            sample_rate,
+            packets: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Send playback here until RTP arrives, as an SDP offer tells
+    pub fn with_remote(mut self, remote: Option<SocketAddr>) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Only take the RTP of this SSRC
+    pub fn with_ssrc(mut self, ssrc: Option<u32>) -> Self {
+        self.ssrc = ssrc;
+        self
+    }
+
+    pub fn negotiated_codec(&self) -> &NegotiatedCodec {
+        &self.negotiated
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.sender().local_addr()
+    }
+
+    /// Get the counter of the RTP packets taken
+    pub fn packets(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.packets)
+    }
+
+    /// Relay until the session ends or stops taking audio
+    pub async fn run(mut self) {
+        let Some(peer) = self.peer.take() else {
+            return;
+        };
+        let (peer, frames) = PeerTask::spawn(peer, DEFAULT_PACKET_QUEUE);
+        let barge_in = Arc::new(Notify::new());
+        let mut feeding = tokio::spawn(feed_frames(
+            self.service.clone(),
+            self.session_id.clone(),
+            self.frame_size,
+            frames,
+            Arc::clone(&self.packets),
+            Arc::clone(&barge_in),
+        ));
+        let mut buffer = vec![0u8; MAX_DATAGRAM];
+        let mut outbound: VecDeque<i16> = VecDeque::new();
+        let mut playback = self.service.subscribe_playback(&self.session_id).ok();
+        let mut pacing = tokio::time::interval(PLAYBACK_INTERVAL);
+        let mut session_check = tokio::time::interval(SESSION_CHECK_INTERVAL);
+        let ssrc = uuid::Uuid::new_v4().as_u128() as u32;
+        let mut sequence_number: u16 = 0;
+        let mut timestamp: u32 = 0;
+        let mut talking = false;
+        let mut skipped_playback = false;
+
+        loop {
+            tokio::select! {
+                received = self.socket.recv_from(&mut buffer) => {
+                    let Ok((len, from)) = received else { continue };
+                    self.on_datagram(&buffer[..len], from, &peer);
+                }
+                _ = barge_in.notified() => {
+                    // Talked over, drop what is left of the agent's audio
+                    outbound.clear();
+                }
+                _ = &mut feeding => break,
+                Some(chunk) = next_playback(&mut playback) => {
+                    let Ok(chunk) = chunk else { continue };
+                    if !self.queue_playback(&chunk, &mut outbound) && !skipped_playback {
+                        tracing::warn!(
+                            "Skipping playback of session {} the RTP stream can't carry",
+                            self.session_id
+                        );
+                        skipped_playback = true;
+                    }
+                }
+                _ = pacing.tick() => {
+                    timestamp = timestamp.wrapping_add(PLAYBACK_PACKET_SAMPLES as u32);
+                    let (Some(remote), false) = (self.remote, outbound.is_empty()) else {
+                        talking = false;
+                        continue;
+                    };
+                    let count = outbound.len().min(PLAYBACK_PACKET_SAMPLES);
+                    let mut samples: Vec<i16> = outbound.drain(..count).collect();
+                    samples.resize(PLAYBACK_PACKET_SAMPLES, 0);
+                    let payload = if self.negotiated.codec.name == CODEC_PCMA {
+                        codec::encode_pcma(&samples)
+                    } else {
+                        codec::encode_pcmu(&samples)
+                    };
+                    let packet = RtpPacket {
+                        version: 2,
+                        padding: false,
+                        extension: false,
+                        csrc_count: 0,
+                        // Starts a talk spurt
+                        marker: !talking,
+                        payload_type: self.negotiated.payload_type,
+                        sequence_number,
+                        timestamp,
+                        ssrc,
+                        payload,
+                    };
+                    talking = true;
+                    sequence_number = sequence_number.wrapping_add(1);
+                    if let Err(e) = self.socket.sender().send_to(&packet.serialize(), remote).await {
+                        tracing::debug!("Failed to send RTP to {}: {}", remote, e);
+                    }
+                }
+                _ = session_check.tick() => {
+                    if self.service.session_status(&self.session_id).await.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hand a received packet of the session to its peer task
+    fn on_datagram(&mut self, data: &[u8], from: SocketAddr, peer: &PeerTask) {
+        if data.len() < 12 || data[0] >> 6 != 2 {
+            return;
+        }
+        if rtp_handler::is_rtcp(data) {
+            peer.send_rtcp(data);
+            return;
+        }
+        // Comfort noise, DTMF events and the like
+        if data[1] & 0x7F != self.negotiated.payload_type {
+            return;
+        }
+        let ssrc = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+        match self.ssrc {
+            Some(expected) if expected != ssrc => {
+                tracing::debug!(
+                    "Dropping RTP of SSRC {:#010x} for session {}",
+                    ssrc,
+                    self.session_id
+                );
+                return;
+            }
+            Some(_) => {}
+            None => self.ssrc = Some(ssrc),
+        }
+        // Symmetric RTP, sending back where the stream comes from gets through NATs
+        self.remote = Some(from);
+        if !peer.send_packet(data) {
+            tracing::debug!(
+                "Dropping RTP of session {}, its decoding is behind",
+                self.session_id
+            );
+        }
+    }
+
+    /// Queue a chunk of playback, returning whether the stream can carry it
+    ///
+    /// Playback goes out on G.711 only, from PCM16 or mu-law at the
+    /// session's rate.
+    fn queue_playback(&self, chunk: &proto::AudioChunk, outbound: &mut VecDeque<i16>) -> bool {
+        if self.negotiated.codec.name == CODEC_OPUS || chunk.sample_rate != self.sample_rate {
+            return false;
+        }
+        match proto::audio_chunk::Encoding::try_from(chunk.encoding) {
+            Ok(proto::audio_chunk::Encoding::Pcm16) => outbound.extend(
+                chunk
+                    .data
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]])),
+            ),
+            Ok(proto::audio_chunk::Encoding::Pcmu) => {
+                outbound.extend(codec::decode_pcmu(&chunk.data))
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Feed what a relay's peer task decodes into the session, until either ends
+///
+/// Notifies `barge_in` when the caller talks over playback.
+async fn feed_frames(
+    service: AmwajMediaService,
+    session_id: String,
+    frame_size: usize,
+    mut frames: mpsc::Receiver<PeerOutput>,
+    packets: Arc<AtomicU64>,
+    barge_in: Arc<Notify>,
+) {
+    let mut pending: Vec<i16> = Vec::new();
+    while let Some(output) = frames.recv().await {
+        let pcm = match output {
+            PeerOutput::Packet {
+                sequence_number,
+                timestamp,
+                jitter_ms,
+            } => {
+                service.record_packet(&session_id, sequence_number, timestamp, || jitter_ms);
+                continue;
+            }
+            PeerOutput::Frame(pcm) => pcm,
+        };
+        packets.fetch_add(1, Ordering::Relaxed);
+        pending.extend_from_slice(&pcm);
+        pool::pcm().give(pcm);
+        while pending.len() >= frame_size {
+            let events = service.push_audio(&session_id, &pending[..frame_size]);
+            pending.drain(..frame_size);
+            match events {
+                Ok(events) => {
+                    if events
+                        .iter()
+                        .any(|event| matches!(event, MediaEvent::BargeIn { .. }))
+                    {
+                        barge_in.notify_one();
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Stopping the RTP relay of session {}: {}", session_id, e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Decodes a stream of RTP into the frames of a session's pipeline
+///
+/// Packets go through the jitter buffer and decoder of a peer connection
+/// of their own, without any network.
+pub struct RtpFrames {
+    peer: PeerConnection,
+    pending: Vec<i16>,
+    /// Samples of `pending` already read as frames
+    read: usize,
+    frame_size: usize,
+}
+
+impl RtpFrames {
+    pub fn new(
+        session_id: &str,
+        negotiated: NegotiatedCodec,
+        codecs: &CodecsConfig,
+        frame_duration_ms: u32,
+    ) -> Self {
+        let mut peer = PeerConnection::new(session_id.to_string()).with_codecs(codecs.clone());
+        peer.set_negotiated_codec(negotiated);
+        let frame_size = session_sample_rate(negotiated.codec) * frame_duration_ms / 1000;
+        Self {
+            peer,
+            pending: Vec::new(),
+            read: 0,
+            frame_size: frame_size as usize,
+        }
+    }
+
+    /// Take an RTP packet, the frames it completes then read by `next_frame`
+    pub fn push(&mut self, packet: &[u8]) -> error::Result<()> {
+        self.pending.drain(..self.read);
+        self.read = 0;
+        let mut decoded = self.peer.on_rtp_packet(packet)?;
+        while let Some(pcm) = decoded {
+            self.pending.extend_from_slice(&pcm);
+            pool::pcm().give(pcm);
+            decoded = self.peer.next_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Get the next complete frame, borrowed from the decoded audio
+    pub fn next_frame(&mut self) -> Option<&[i16]> {
+        let start = self.read;
+        let end = start + self.frame_size.max(1);
+        if end > self.pending.len() {
+            return None;
+        }
+        self.read = end;
+        Some(&self.pending[start..end])
+    }
+}
+
+/// Plain RTP ingest of a session, stopped when dropped
+pub struct RtpIngest {
+    endpoint: RtpEndpoint,
+    packets: Arc<AtomicU64>,
+    relay: JoinHandle<()>,
+}
+
+impl RtpIngest {
+    /// Start relaying, reporting `advertised` as the address to send to
+    ///
+    /// The session is to exist once the caller releases the sessions lock.
+    pub fn spawn(relay: RtpRelay, advertised: Option<IpAddr>) -> anyhow::Result<Self> {
+        let local = relay.local_addr()?;
+        let endpoint = RtpEndpoint {
+            address: SocketAddr::new(advertised.unwrap_or(local.ip()), local.port()),
+            codec: relay.negotiated.codec.name,
+            payload_type: relay.negotiated.payload_type,
+            ssrc: relay.ssrc,
+        };
+        let packets = relay.packets();
+        let service = relay.service.clone();
+        let relay = tokio::spawn(async move {
+            runtime::supervise(service.metrics(), TASK_RTP, relay.run()).await;
+        });
+        Ok(Self {
+            endpoint,
+            packets,
+            relay,
+        })
+    }
+
+    pub fn endpoint(&self) -> &RtpEndpoint {
+        &self.endpoint
+    }
+
+    /// Get the number of RTP packets decoded into the session
+    pub fn packets_received(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for RtpIngest {
+    fn drop(&mut self) {
+        self.relay.abort();
+    }
+}
+
+/// Wait for the next playback chunk, forever without a subscription
+async fn next_playback(
+    playback: &mut Option<mpsc::Receiver<Result<proto::AudioChunk, tonic::Status>>>,
+) -> Option<Result<proto::AudioChunk, tonic::Status>> {
+    match playback {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let codecs = CodecsConfig::default();
+        let options = RtpIngestOptions {
+            codec: "pcma".to_string(),
+            ..Default::default()
+        };
+        let negotiated = negotiate(&options, &codecs).unwrap();
+        assert_eq!(negotiated.payload_type, 8);
+        assert_eq!(session_sample_rate(negotiated.codec), 8000);
+
+        let options = RtpIngestOptions {
+            codec: "opus".to_string(),
+            payload_type: Some(96),
+            ..Default::default()
+        };
+        let negotiated = negotiate(&options, &codecs).unwrap();
+        assert_eq!(negotiated.payload_type, 96);
+        assert_eq!(session_sample_rate(negotiated.codec), 16000);
+
+        let options = RtpIngestOptions {
+            codec: "g729".to_string(),
+            ..Default::default()
+        };
+        assert!(negotiate(&options, &codecs).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_port_range() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let mut ports = RtpPorts::new(40200, 40201);
+        let first = ports.bind(ip).unwrap();
+        let second = ports.bind(ip).unwrap();
+        assert_eq!(first.local_addr().unwrap().port(), 40200);
+        assert_eq!(second.local_addr().unwrap().port(), 40201);
+        assert!(ports.bind(ip).is_err());
+        drop(first);
+        assert_eq!(ports.bind(ip).unwrap().local_addr().unwrap().port(), 40200);
+    }
+}
@@ -0,0 +1,297 @@
+//! Synthetic sessions driven by WAV files
+//!
+//! With `[simulation]` set, the server opens sessions of its own and plays
+//! recordings into them as G.711 RTP, in real time, through the jitter
+//! buffer, decoder and pipeline real sessions use. Packet loss and jitter
+//! are injected on the way, so an instance can be loaded and soaked in CI
+//! without browsers or phones. The sessions end once the service drains.
+
+use crate::asr::realtime::resample;
+use crate::config::SimulationConfig;
+use crate::detection::replay::read_wav;
+use crate::grpc::service::{AmwajMediaService, SessionOptions};
+use crate::transport::rtp::{self, RtpFrames, RtpIngestOptions};
+use crate::webrtc::codec::{self, CODEC_PCMA};
+use crate::webrtc::sdp::NegotiatedCodec;
+use crate::webrtc::RtpPacket;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+
+/// Audio of each RTP packet
+const PACKET_DURATION: Duration = Duration::from_millis(20);
+
+/// Tag marking the simulated sessions
+pub const SIMULATION_TAG: &str = "simulation";
+
+/// Small xorshift generator for the injected loss and jitter
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // Xorshift never leaves a zero state
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    /// Draw from `[0, 1)`
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Plays recordings into synthetic sessions
+pub struct Simulation {
+    service: AmwajMediaService,
+    config: SimulationConfig,
+    negotiated: NegotiatedCodec,
+    /// Recordings at the codec's rate
+    recordings: Arc<Vec<Vec<i16>>>,
+}
+
+impl Simulation {
+    /// Load the recordings of a simulation
+    pub fn new(service: AmwajMediaService, config: &SimulationConfig) -> anyhow::Result<Self> {
+        let negotiated = rtp::negotiate(
+            &RtpIngestOptions {
+                codec: config.codec.clone(),
+                ..Default::default()
+            },
+            &service.config().audio.codecs,
+        )?;
+        let rate = rtp::session_sample_rate(negotiated.codec);
+        let recordings = config
+            .wav_files
+            .iter()
+            .map(|path| {
+                let (pcm, sample_rate) = read_wav(Path::new(path))
+                    .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+                Ok(resample(&pcm, sample_rate, rate))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if recordings.iter().all(Vec::is_empty) {
+            return Err(anyhow::anyhow!("Simulation recordings hold no audio"));
+        }
+        Ok(Self {
+            service,
+            config: config.clone(),
+            negotiated,
+            recordings: Arc::new(recordings),
+        })
+    }
+
+    /// Start the sessions, they all stop when the handle is aborted
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            tracing::info!(
+                "Simulating {} sessions from {} recordings",
+                self.config.sessions,
+                self.recordings.len()
+            );
+            let mut sessions = JoinSet::new();
+            for index in 0..self.config.sessions {
+                if index > 0 && self.config.ramp_up_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(self.config.ramp_up_ms)).await;
+                }
+                if self.service.is_draining() {
+                    break;
+                }
+                let session = SimulatedSession {
+                    service: self.service.clone(),
+                    session_id: format!("sim-{}", index),
+                    negotiated: self.negotiated,
+                    recordings: Arc::clone(&self.recordings),
+                    loss: self.config.packet_loss_percent / 100.0,
+                    jitter: Duration::from_millis(self.config.jitter_ms.into()),
+                    rng: Rng::new(self.config.seed.wrapping_add(index as u64)),
+                };
+                sessions.spawn(session.run());
+            }
+            while let Some(ended) = sessions.join_next().await {
+                if let Ok(Err(e)) = ended {
+                    tracing::warn!("Simulated session failed: {:#}", e);
+                }
+            }
+        })
+    }
+}
+
+/// One synthetic caller
+struct SimulatedSession {
+    service: AmwajMediaService,
+    session_id: String,
+    negotiated: NegotiatedCodec,
+    recordings: Arc<Vec<Vec<i16>>>,
+    /// Share of packets dropped
+    loss: f64,
+    jitter: Duration,
+    rng: Rng,
+}
+
+impl SimulatedSession {
+    /// Play the recordings in a loop until the service drains
+    async fn run(mut self) -> anyhow::Result<()> {
+        let sample_rate = rtp::session_sample_rate(self.negotiated.codec);
+        self.service
+            .create_session(SessionOptions {
+                session_id: Some(self.session_id.clone()),
+                sample_rate: Some(sample_rate),
+                tags: HashMap::from([(SIMULATION_TAG.to_string(), "true".to_string())]),
+                ..Default::default()
+            })
+            .await?;
+        let config = self.service.config();
+        let mut frames = RtpFrames::new(
+            &self.session_id,
+            self.negotiated,
+            &config.audio.codecs,
+            config.audio.frame_duration_ms,
+        );
+        let packet_samples = (sample_rate as u128 * PACKET_DURATION.as_millis() / 1000) as usize;
+        let ssrc = uuid::Uuid::new_v4().as_u128() as u32;
+        let mut sequence_number: u16 = 0;
+        let mut timestamp: u32 = 0;
+        let mut talking = false;
+        // Packets on their way, with when they arrive
+        let mut in_flight: Vec<(Instant, Vec<u8>)> = Vec::new();
+        let mut pacing = tokio::time::interval(PACKET_DURATION);
+        let mut audio = self
+            .recordings
+            .iter()
+            .cycle()
+            .flat_map(|recording| recording.chunks(packet_samples));
+
+        while !self.service.is_draining() {
+            pacing.tick().await;
+            let Some(chunk) = audio.next() else { break };
+            let mut samples = chunk.to_vec();
+            samples.resize(packet_samples, 0);
+            let payload = if self.negotiated.codec.name == CODEC_PCMA {
+                codec::encode_pcma(&samples)
+            } else {
+                codec::encode_pcmu(&samples)
+            };
+            let packet = RtpPacket {
+                version: 2,
+                padding: false,
+                extension: false,
+                csrc_count: 0,
+                // Starts the only talk spurt
+                marker: !talking,
+                payload_type: self.negotiated.payload_type,
+                sequence_number,
+                timestamp,
+                ssrc,
+                payload,
+            };
+            talking = true;
+            sequence_number = sequence_number.wrapping_add(1);
+            timestamp = timestamp.wrapping_add(packet_samples as u32);
+            if self.rng.next_f64() >= self.loss {
+                let delay = self.jitter.mul_f64(self.rng.next_f64());
+                in_flight.push((Instant::now() + delay, packet.serialize()));
+            }
+
+            let now = Instant::now();
+            in_flight.sort_by_key(|(arrival, _)| *arrival);
+            let arrived = in_flight.partition_point(|(arrival, _)| *arrival <= now);
+            for (_, packet) in in_flight.drain(..arrived) {
+                if let Err(e) = frames.push(&packet) {
+                    tracing::debug!("Dropping RTP of session {}: {}", self.session_id, e);
+                    continue;
+                }
+                while let Some(frame) = frames.next_frame() {
+                    // Ended from the outside, by an operator or a limit
+                    if self.service.push_audio(&self.session_id, frame).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        if let Err(e) = self.service.end_session(&self.session_id).await {
+            tracing::debug!("Simulated session {} already ended: {}", self.session_id, e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::metrics::Metrics;
+
+    fn write_wav(samples: &[i16]) -> String {
+        let path = std::env::temp_dir().join(format!("amwaj-sim-{}.wav", uuid::Uuid::new_v4()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_rng_range() {
+        let mut rng = Rng::new(0);
+        let draws: Vec<f64> = (0..1000).map(|_| rng.next_f64()).collect();
+        assert!(draws.iter().all(|draw| (0.0..1.0).contains(draw)));
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        assert!((0.4..0.6).contains(&mean));
+        assert_eq!(Rng::new(7).next_f64(), Rng::new(7).next_f64());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_simulated_sessions() {
+        // Half a second of speech then half a second of silence
+        let mut samples: Vec<i16> = (0..8000)
+            .map(|i| ((i as f32 * 0.15).sin() * 12000.0) as i16)
+            .collect();
+        samples.resize(16000, 0);
+        let path = write_wav(&samples);
+        let simulation = SimulationConfig {
+            sessions: 3,
+            wav_files: vec![path.clone()],
+            packet_loss_percent: 5.0,
+            jitter_ms: 30,
+            ..Default::default()
+        };
+        let config = Config {
+            simulation: Some(simulation.clone()),
+            ..Config::default()
+        };
+        config.validate().unwrap();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, Arc::clone(&metrics));
+        let handle = Simulation::new(service.clone(), &simulation)
+            .unwrap()
+            .spawn();
+
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let stats = service.session_stats();
+        assert_eq!(stats.len(), 3);
+        for stats in &stats {
+            assert_eq!(stats.tags[SIMULATION_TAG], "true");
+            // Lost packets leave fewer frames than the 150 sent
+            assert!((100..150).contains(&stats.frames_processed));
+        }
+        assert!(metrics.turn_starts.get() >= 3.0);
+
+        service.drain(Duration::from_secs(1)).await;
+        handle.await.unwrap();
+        assert_eq!(service.session_count(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,707 @@
+//! SIP gateway answering calls into sessions
+//!
+//! A user agent server over UDP: an INVITE with an SDP offer is answered
+//! with the most preferred codec of `[audio.codecs]` it lists, G.711 or
+//! Opus, on an RTP port of `[transports.sip]`. The call gets a session,
+//! named by the `X-Amwaj-Session-Id` header or else the Call-ID, and its
+//! RTP is decoded into the session's pipeline. Playback of the session is
+//! sent back as RTP on G.711 calls. A BYE from the caller ends the session,
+//! and a session ended otherwise hangs the call up.
+//!
+//! There is no registration, authentication or transaction layer beyond
+//! answering retransmitted INVITEs again; trunk calls to the gateway from
+//! an SBC or a SIP proxy.
+
+use crate::config::{CodecsConfig, SipConfig};
+use crate::grpc::service::{AmwajMediaService, SessionOptions};
+use crate::metrics::runtime::{self, TASK_SIP};
+use crate::transport::rtp::{self, RtpPorts, RtpRelay};
+use crate::webrtc::sdp::{self, NegotiatedCodec};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Header of an INVITE naming the session of the call
+pub const SESSION_ID_HEADER: &str = "X-Amwaj-Session-Id";
+
+/// Methods the gateway handles
+const ALLOW: &str = "INVITE, ACK, BYE, CANCEL, OPTIONS";
+
+const MAX_DATAGRAM: usize = 65535;
+
+/// Full names of the compact header forms of RFC 3261
+const COMPACT_HEADERS: [(&str, &str); 7] = [
+    ("v", "Via"),
+    ("f", "From"),
+    ("t", "To"),
+    ("i", "Call-ID"),
+    ("m", "Contact"),
+    ("l", "Content-Length"),
+    ("c", "Content-Type"),
+];
+
+/// First line of a SIP message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartLine {
+    Request { method: String, uri: String },
+    Response { status: u16, reason: String },
+}
+
+/// A SIP request or response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SipMessage {
+    pub start: StartLine,
+    /// Headers in order, compact names expanded
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl SipMessage {
+    pub fn request(method: &str, uri: &str) -> Self {
+        Self {
+            start: StartLine::Request {
+                method: method.to_string(),
+                uri: uri.to_string(),
+            },
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    /// Parse a message from a datagram
+    ///
+    /// The body is cut to the Content-Length header when there is one.
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let text = std::str::from_utf8(data)?;
+        let (head, body) = text
+            .split_once("\r\n\r\n")
+            .or_else(|| text.split_once("\n\n"))
+            .unwrap_or((text, ""));
+        let mut lines = head.lines();
+        let first = lines.next().unwrap_or_default().trim();
+        let start = if let Some(status) = first.strip_prefix("SIP/2.0 ") {
+            let (status, reason) = status.split_once(' ').unwrap_or((status, ""));
+            StartLine::Response {
+                status: status.parse()?,
+                reason: reason.to_string(),
+            }
+        } else {
+            let mut fields = first.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(method), Some(uri), Some("SIP/2.0")) => StartLine::Request {
+                    method: method.to_ascii_uppercase(),
+                    uri: uri.to_string(),
+                },
+                _ => return Err(anyhow::anyhow!("Not a SIP message: {}", first)),
+            }
+        };
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in lines {
+            if line.starts_with([' ', '\t']) {
+                // Folded onto the previous header
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(anyhow::anyhow!("Malformed SIP header: {}", line));
+            };
+            let name = name.trim();
+            let name = COMPACT_HEADERS
+                .iter()
+                .find(|(compact, _)| compact.eq_ignore_ascii_case(name))
+                .map_or(name, |(_, full)| full);
+            headers.push((name.to_string(), value.trim().to_string()));
+        }
+
+        let mut message = Self {
+            start,
+            headers,
+            body: body.to_string(),
+        };
+        if let Some(length) = message.header("Content-Length") {
+            let length: usize = length.parse()?;
+            if let Some(body) = message.body.get(..length) {
+                message.body = body.to_string();
+            }
+        }
+        Ok(message)
+    }
+
+    /// Get the method of a request
+    pub fn method(&self) -> Option<&str> {
+        match &self.start {
+            StartLine::Request { method, .. } => Some(method),
+            StartLine::Response { .. } => None,
+        }
+    }
+
+    /// Get the status of a response
+    pub fn status(&self) -> Option<u16> {
+        match &self.start {
+            StartLine::Request { .. } => None,
+            StartLine::Response { status, .. } => Some(*status),
+        }
+    }
+
+    /// Get the first value of a header, whatever its case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Replace the values of a header
+    pub fn set_header(&mut self, name: &str, value: impl Into<String>) {
+        self.headers
+            .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.into()));
+    }
+
+    pub fn with_body(self, content_type: &str, body: String) -> Self {
+        let mut message = self.with_header("Content-Type", content_type);
+        message.body = body;
+        message
+    }
+
+    /// Start a response to a request, with the headers identifying its
+    /// transaction
+    pub fn response(&self, status: u16, reason: &str) -> Self {
+        let headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                ["Via", "From", "To", "Call-ID", "CSeq"]
+                    .iter()
+                    .any(|copied| name.eq_ignore_ascii_case(copied))
+            })
+            .cloned()
+            .collect();
+        Self {
+            start: StartLine::Response {
+                status,
+                reason: reason.to_string(),
+            },
+            headers,
+            body: String::new(),
+        }
+    }
+}
+
+impl fmt::Display for SipMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.start {
+            StartLine::Request { method, uri } => write!(f, "{} {} SIP/2.0\r\n", method, uri)?,
+            StartLine::Response { status, reason } => {
+                write!(f, "SIP/2.0 {} {}\r\n", status, reason)?
+            }
+        }
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("Content-Length") {
+                write!(f, "{}: {}\r\n", name, value)?;
+            }
+        }
+        write!(
+            f,
+            "Content-Length: {}\r\n\r\n{}",
+            self.body.len(),
+            self.body
+        )
+    }
+}
+
+/// Get the `tag` parameter of a From or To header
+fn tag(header: &str) -> Option<&str> {
+    let params = header.rsplit_once('>').map_or(header, |(_, params)| params);
+    params
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("tag="))
+}
+
+/// Get the URI of a From, To or Contact header
+fn header_uri(header: &str) -> &str {
+    match header.split_once('<') {
+        Some((_, rest)) => rest.split('>').next().unwrap_or(rest),
+        None => header.split(';').next().unwrap_or(header).trim(),
+    }
+}
+
+/// Get the host of a SIP URI, when it is an IP address
+fn uri_ip(uri: &str) -> Option<IpAddr> {
+    let host = uri.rsplit('@').next()?;
+    let host = host.strip_prefix("sip:").unwrap_or(host);
+    let host = host.split(';').next()?;
+    match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next()?.parse().ok(),
+        None => host.split(':').next()?.parse().ok(),
+    }
+}
+
+/// Dialog of an answered call
+struct Call {
+    session_id: String,
+    /// Caller's From header
+    from: String,
+    /// To header of our responses, with our tag
+    to: String,
+    /// Caller's Contact, where requests of the dialog go
+    remote_target: String,
+    /// Where the caller's signaling comes from
+    remote: SocketAddr,
+    contact: String,
+    answer: String,
+    relay: JoinHandle<()>,
+}
+
+/// Answers SIP calls and bridges their RTP into sessions
+pub struct SipGateway {
+    service: AmwajMediaService,
+    config: SipConfig,
+    codecs: CodecsConfig,
+    /// Calls by Call-ID
+    calls: HashMap<String, Call>,
+    rtp_ports: RtpPorts,
+    /// Call-IDs of calls whose session ended
+    ended_tx: mpsc::UnboundedSender<String>,
+    ended_rx: mpsc::UnboundedReceiver<String>,
+}
+
+impl SipGateway {
+    pub fn new(service: AmwajMediaService, config: &SipConfig) -> Self {
+        let codecs = service.config().audio.codecs.clone();
+        let rtp_ports = RtpPorts::new(config.rtp_port_min, config.rtp_port_max)
+            .with_socket(service.config().webrtc.socket.clone());
+        let (ended_tx, ended_rx) = mpsc::unbounded_channel();
+        Self {
+            service,
+            config: config.clone(),
+            codecs,
+            calls: HashMap::new(),
+            rtp_ports,
+            ended_tx,
+            ended_rx,
+        }
+    }
+
+    /// Get the number of calls in progress
+    pub fn call_count(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Handle a message received from `from` on the signaling socket bound
+    /// to `local`, returning the responses to send back
+    pub async fn handle(
+        &mut self,
+        message: SipMessage,
+        from: SocketAddr,
+        local: SocketAddr,
+    ) -> Vec<SipMessage> {
+        let Some(method) = message.method() else {
+            // Responses to our BYEs
+            return Vec::new();
+        };
+        if message.header("Call-ID").is_none() {
+            return vec![message.response(400, "Missing Call-ID")];
+        }
+        match method {
+            "INVITE" => {
+                let trying = message.response(100, "Trying");
+                vec![trying, self.invite(&message, from, local).await]
+            }
+            "ACK" => Vec::new(),
+            "BYE" => vec![self.bye(&message).await],
+            "CANCEL" => vec![message.response(200, "OK")],
+            "OPTIONS" => vec![message.response(200, "OK").with_header("Allow", ALLOW)],
+            _ => vec![message
+                .response(501, "Not Implemented")
+                .with_header("Allow", ALLOW)],
+        }
+    }
+
+    async fn invite(
+        &mut self,
+        request: &SipMessage,
+        from: SocketAddr,
+        local: SocketAddr,
+    ) -> SipMessage {
+        let call_id = request.header("Call-ID").unwrap_or_default().to_string();
+        // A retransmission, or a re-INVITE answered with the same media
+        if let Some(call) = self.calls.get(&call_id) {
+            return answer(request, call);
+        }
+        if !request.body.contains("m=audio") {
+            return request.response(488, "Not Acceptable Here");
+        }
+        let negotiated = match sdp::negotiate(&request.body, &self.codecs) {
+            Ok(negotiated) => negotiated,
+            Err(e) => {
+                tracing::info!("Rejecting SIP call {}: {}", call_id, e);
+                return request.response(488, "Not Acceptable Here");
+            }
+        };
+        match self
+            .accept(request, &call_id, negotiated, from, local)
+            .await
+        {
+            Ok(call) => {
+                let response = answer(request, &call);
+                self.calls.insert(call_id, call);
+                response
+            }
+            Err(e) => {
+                tracing::warn!("Failed to answer SIP call {}: {}", call_id, e);
+                request.response(500, "Server Internal Error")
+            }
+        }
+    }
+
+    /// Open the session and the RTP relay of a call
+    async fn accept(
+        &mut self,
+        request: &SipMessage,
+        call_id: &str,
+        negotiated: NegotiatedCodec,
+        from: SocketAddr,
+        local: SocketAddr,
+    ) -> anyhow::Result<Call> {
+        let ip = self.local_ip(request, local);
+        let socket = self.rtp_ports.bind(local.ip())?;
+        let rtp_address = SocketAddr::new(ip, socket.local_addr()?.port());
+
+        let session_id = request
+            .header(SESSION_ID_HEADER)
+            .filter(|session_id| !session_id.is_empty())
+            .unwrap_or(call_id)
+            .to_string();
+        let sample_rate = rtp::session_sample_rate(negotiated.codec);
+        let caller = request.header("From").map(header_uri).unwrap_or_default();
+        let metadata = HashMap::from([
+            ("call_id".to_string(), call_id.into()),
+            ("caller".to_string(), caller.into()),
+            ("codec".to_string(), negotiated.codec.name.into()),
+        ]);
+        self.service
+            .create_session(SessionOptions {
+                session_id: Some(session_id.clone()),
+                sample_rate: Some(sample_rate),
+                metadata,
+                ..Default::default()
+            })
+            .await?;
+
+        let relay = RtpRelay::new(self.service.clone(), &session_id, socket, negotiated)
+            .with_remote(sdp::rtp_address(&request.body));
+        let service = self.service.clone();
+        let ended = self.ended_tx.clone();
+        let ended_call = call_id.to_string();
+        let relay = tokio::spawn(async move {
+            runtime::supervise(service.metrics(), TASK_SIP, relay.run()).await;
+            let _ = ended.send(ended_call);
+        });
+
+        let from_header = request.header("From").unwrap_or_default().to_string();
+        let to = request.header("To").unwrap_or_default();
+        let to = match tag(to) {
+            Some(_) => to.to_string(),
+            None => format!(
+                "{};tag={}",
+                to,
+                &uuid::Uuid::new_v4().simple().to_string()[..12]
+            ),
+        };
+        let remote_target = request.header("Contact").map_or_else(
+            || header_uri(&from_header).to_string(),
+            |contact| header_uri(contact).to_string(),
+        );
+        tracing::info!(
+            "SIP call {} from {} answered with {} into session {}",
+            call_id,
+            caller,
+            negotiated.codec.name,
+            session_id
+        );
+        Ok(Call {
+            session_id,
+            from: from_header,
+            to,
+            remote_target,
+            remote: from,
+            contact: format!("<sip:amwaj@{}>", SocketAddr::new(ip, local.port())),
+            answer: sdp::answer_at(&negotiated, &self.codecs, rtp_address, Some(&request.body)),
+            relay,
+        })
+    }
+
+    async fn bye(&mut self, request: &SipMessage) -> SipMessage {
+        let call_id = request.header("Call-ID").unwrap_or_default();
+        let Some(call) = self.calls.remove(call_id) else {
+            return request.response(481, "Call/Transaction Does Not Exist");
+        };
+        call.relay.abort();
+        if let Err(e) = self.service.end_session(&call.session_id).await {
+            tracing::debug!("Session {} already ended: {}", call.session_id, e);
+        }
+        tracing::info!("SIP call {} hung up by the caller", call_id);
+        request.response(200, "OK")
+    }
+
+    /// End a call from our side, returning the BYE and where to send it
+    async fn hang_up(
+        &mut self,
+        call_id: &str,
+        local: SocketAddr,
+    ) -> Option<(SipMessage, SocketAddr)> {
+        let call = self.calls.remove(call_id)?;
+        call.relay.abort();
+        if let Err(e) = self.service.end_session(&call.session_id).await {
+            tracing::debug!("Session {} already ended: {}", call.session_id, e);
+        }
+        tracing::info!("Hanging up SIP call {}", call_id);
+        let branch = uuid::Uuid::new_v4().simple().to_string();
+        let bye = SipMessage::request("BYE", &call.remote_target)
+            .with_header(
+                "Via",
+                format!("SIP/2.0/UDP {};branch=z9hG4bK{}", local, branch),
+            )
+            .with_header("Max-Forwards", "70")
+            .with_header("From", call.to)
+            .with_header("To", call.from)
+            .with_header("Call-ID", call_id)
+            .with_header("CSeq", "1 BYE")
+            .with_header("Contact", call.contact);
+        Some((bye, call.remote))
+    }
+
+    /// Serve on `addr` until `shutdown` completes, then hang up the calls
+    pub async fn serve(
+        mut self,
+        addr: SocketAddr,
+        shutdown: impl Future<Output = ()>,
+    ) -> anyhow::Result<()> {
+        let socket = UdpSocket::bind(addr).await?;
+        let local = socket.local_addr()?;
+        tracing::info!("SIP gateway listening on udp://{}", local);
+        tokio::pin!(shutdown);
+        let mut buffer = vec![0u8; MAX_DATAGRAM];
+        loop {
+            let outgoing = tokio::select! {
+                received = socket.recv_from(&mut buffer) => {
+                    let (len, from) = match received {
+                        Ok(received) => received,
+                        Err(e) => {
+                            tracing::debug!("SIP receive error: {}", e);
+                            continue;
+                        }
+                    };
+                    match SipMessage::parse(&buffer[..len]) {
+                        Ok(message) => self
+                            .handle(message, from, local)
+                            .await
+                            .into_iter()
+                            .map(|reply| (reply, from))
+                            .collect(),
+                        Err(e) => {
+                            tracing::debug!("Dropping SIP datagram from {}: {}", from, e);
+                            Vec::new()
+                        }
+                    }
+                }
+                Some(call_id) = self.ended_rx.recv() => {
+                    self.hang_up(&call_id, local).await.into_iter().collect()
+                }
+                _ = &mut shutdown => break,
+            };
+            for (message, to) in outgoing {
+                if let Err(e) = socket.send_to(message.to_string().as_bytes(), to).await {
+                    tracing::warn!("Failed to send SIP message to {}: {}", to, e);
+                }
+            }
+        }
+
+        let call_ids: Vec<String> = self.calls.keys().cloned().collect();
+        for call_id in call_ids {
+            if let Some((bye, to)) = self.hang_up(&call_id, local).await {
+                let _ = socket.send_to(bye.to_string().as_bytes(), to).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Address the caller reaches us on, for SDP and Contact headers
+    fn local_ip(&self, request: &SipMessage, local: SocketAddr) -> IpAddr {
+        let requested = match &request.start {
+            StartLine::Request { uri, .. } => uri_ip(uri),
+            StartLine::Response { .. } => None,
+        };
+        self.config
+            .public_address
+            .as_deref()
+            .and_then(|address| address.parse().ok())
+            .or(requested)
+            .or(Some(local.ip()))
+            .filter(|ip: &IpAddr| !ip.is_unspecified())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+    }
+}
+
+/// 200 OK to an INVITE of a call
+fn answer(request: &SipMessage, call: &Call) -> SipMessage {
+    let mut response = request.response(200, "OK");
+    response.set_header("To", call.to.clone());
+    response
+        .with_header("Contact", call.contact.clone())
+        .with_header("Allow", ALLOW)
+        .with_body("application/sdp", call.answer.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::metrics::Metrics;
+    use crate::webrtc::codec;
+    use crate::webrtc::RtpPacket;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const SIGNALING: &str = "127.0.0.1:5060";
+    const CALLER: &str = "127.0.0.1:5062";
+
+    fn invite(call_id: &str, rtp_port: u16) -> String {
+        let sdp = format!(
+            "v=0\r\no=- 1 1 IN IP4 127.0.0.1\r\ns=-\r\nc=IN IP4 127.0.0.1\r\nt=0 0\r\n\
+             m=audio {} RTP/AVP 0 101\r\na=rtpmap:0 PCMU/8000\r\n\
+             a=rtpmap:101 telephone-event/8000\r\n",
+            rtp_port
+        );
+        format!(
+            "INVITE sip:agent@127.0.0.1:5060 SIP/2.0\r\n\
+             v: SIP/2.0/UDP 127.0.0.1:5062;branch=z9hG4bK776asdhds\r\n\
+             Max-Forwards: 70\r\n\
+             f: <sip:+15550100@127.0.0.1>;tag=1928301774\r\n\
+             t: <sip:agent@127.0.0.1>\r\n\
+             i: {}\r\n\
+             CSeq: 314159 INVITE\r\n\
+             m: <sip:+15550100@127.0.0.1:5062>\r\n\
+             c: application/sdp\r\n\
+             l: {}\r\n\r\n{}",
+            call_id,
+            sdp.len(),
+            sdp
+        )
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let message = SipMessage::parse(invite("a84b4c76e66710", 4000).as_bytes()).unwrap();
+        assert_eq!(message.method(), Some("INVITE"));
+        assert_eq!(message.header("call-id"), Some("a84b4c76e66710"));
+        assert_eq!(message.header("Content-Type"), Some("application/sdp"));
+        assert!(message.body.ends_with("telephone-event/8000\r\n"));
+        assert_eq!(tag(message.header("From").unwrap()), Some("1928301774"));
+        assert_eq!(tag(message.header("To").unwrap()), None);
+        assert_eq!(
+            header_uri(message.header("Contact").unwrap()),
+            "sip:+15550100@127.0.0.1:5062"
+        );
+        assert_eq!(
+            uri_ip("sip:agent@127.0.0.1:5060"),
+            Some("127.0.0.1".parse().unwrap())
+        );
+
+        let response = message.response(180, "Ringing");
+        assert_eq!(response.header("CSeq"), Some("314159 INVITE"));
+        assert_eq!(response.header("Max-Forwards"), None);
+        let parsed = SipMessage::parse(response.to_string().as_bytes()).unwrap();
+        assert_eq!(parsed.status(), Some(180));
+        assert_eq!(parsed.header("Via"), message.header("Via"));
+
+        assert!(SipMessage::parse(b"HTTP/1.1 200 OK\r\n\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_call_flow() {
+        let mut config = Config::default();
+        config.audio.codecs.preferred = vec!["pcmu".to_string(), "opus".to_string()];
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let sip = SipConfig {
+            rtp_port_min: 40100,
+            rtp_port_max: 40199,
+            ..SipConfig::default()
+        };
+        let mut gateway = SipGateway::new(service.clone(), &sip);
+        let caller_rtp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let request = invite("call-1", caller_rtp.local_addr().unwrap().port());
+        let request = SipMessage::parse(request.as_bytes()).unwrap();
+        let (caller, local) = (CALLER.parse().unwrap(), SIGNALING.parse().unwrap());
+
+        let replies = gateway.handle(request.clone(), caller, local).await;
+        assert_eq!(replies[0].status(), Some(100));
+        let ok = &replies[1];
+        assert_eq!(ok.status(), Some(200));
+        assert!(tag(ok.header("To").unwrap()).is_some());
+        assert!(ok.body.contains("a=rtpmap:0 PCMU/8000\r\n"));
+        let rtp_address = sdp::rtp_address(&ok.body).unwrap();
+        assert!((40100..=40199).contains(&rtp_address.port()));
+        // A retransmitted INVITE gets the same answer
+        let replies = gateway.handle(request.clone(), caller, local).await;
+        assert_eq!(replies[1], *ok);
+        assert_eq!(gateway.call_count(), 1);
+
+        for sequence_number in 1..=4u16 {
+            let packet = RtpPacket {
+                version: 2,
+                padding: false,
+                extension: false,
+                csrc_count: 0,
+                marker: sequence_number == 1,
+                payload_type: 0,
+                sequence_number,
+                timestamp: u32::from(sequence_number) * 160,
+                ssrc: 7,
+                payload: codec::encode_pcmu(&[0; 160]),
+            };
+            caller_rtp
+                .send_to(&packet.serialize(), rtp_address)
+                .await
+                .unwrap();
+        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while service
+                .session_status("call-1")
+                .await
+                .is_none_or(|status| status.frames_processed < 4)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let mut bye = request.clone();
+        bye.start = StartLine::Request {
+            method: "BYE".to_string(),
+            uri: "sip:agent@127.0.0.1".to_string(),
+        };
+        bye.set_header("CSeq", "314160 BYE");
+        let replies = gateway.handle(bye.clone(), caller, local).await;
+        assert_eq!(replies[0].status(), Some(200));
+        assert_eq!(service.session_count(), 0);
+        let replies = gateway.handle(bye, caller, local).await;
+        assert_eq!(replies[0].status(), Some(481));
+    }
+}
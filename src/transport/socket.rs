@@ -0,0 +1,85 @@
+//! UDP sockets for media
+//!
+//! Media sockets are bound with the options of `[webrtc.socket]`: a larger
+//! receive buffer rides out bursts the default one drops packets on, and a
+//! DSCP mark lets enterprise networks give the media sent priority, EF (46)
+//! being the usual class for voice.
+
+use crate::config::SocketConfig;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::SocketAddr;
+
+/// Bind a UDP socket with the options of `config`
+///
+/// `reuse_port` lets other sockets bound the same way share the port, the
+/// kernel spreading flows across them. The socket is left nonblocking.
+pub fn bind_udp(
+    addr: SocketAddr,
+    config: &SocketConfig,
+    reuse_port: bool,
+) -> io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if reuse_port {
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        #[cfg(not(unix))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SO_REUSEPORT is only available on Unix",
+        ));
+    }
+    if let Some(bytes) = config.recv_buffer_bytes {
+        socket.set_recv_buffer_size(bytes)?;
+        // The kernel caps the size at net.core.rmem_max without failing
+        let granted = socket.recv_buffer_size()?;
+        if granted < bytes {
+            tracing::warn!(
+                "Receive buffer of {} bytes capped to {}, raise net.core.rmem_max",
+                bytes,
+                granted
+            );
+        }
+    }
+    if let Some(dscp) = config.dscp {
+        let tos = u32::from(dscp) << 2;
+        match addr {
+            SocketAddr::V4(_) => socket.set_tos(tos)?,
+            #[cfg(target_os = "linux")]
+            SocketAddr::V6(_) => socket.set_tclass_v6(tos)?,
+            #[cfg(not(target_os = "linux"))]
+            SocketAddr::V6(_) => {}
+        }
+    }
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_are_applied() {
+        let config = SocketConfig {
+            recv_buffer_bytes: Some(256 * 1024),
+            dscp: Some(46),
+        };
+        let socket = bind_udp("127.0.0.1:0".parse().unwrap(), &config, false).unwrap();
+        let socket = Socket::from(socket);
+        assert_eq!(socket.tos().unwrap(), 46 << 2);
+        // Linux doubles the size asked for its bookkeeping
+        assert!(socket.recv_buffer_size().unwrap() >= 4096);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reuse_port_shares_the_port() {
+        let config = SocketConfig::default();
+        let first = bind_udp("127.0.0.1:0".parse().unwrap(), &config, true).unwrap();
+        let addr = first.local_addr().unwrap();
+        assert!(bind_udp(addr, &config, true).is_ok());
+        assert!(bind_udp(addr, &config, false).is_err());
+    }
+}
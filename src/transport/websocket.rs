@@ -0,0 +1,745 @@
+//! Twilio Media Streams over WebSocket
+//!
+//! Telephony providers stream calls as JSON text messages carrying base64
+//! mu-law audio at 8 kHz. A stream opens with `connected` and `start`, then
+//! sends `media` until `stop`. `start` names the session: the `session_id`
+//! custom parameter of the `<Stream>`, or the call SID. A session that
+//! doesn't exist yet is created for the stream and ended with it.
+//!
+//! Back on the socket go the session's playback audio as `media` messages,
+//! and a `clear` on barge-in so the provider drops the agent audio it still
+//! has queued.
+//!
+//! Upgrades are authenticated with Twilio's `X-Twilio-Signature`, the
+//! base64 HMAC-SHA1 of the stream URL keyed with the account's auth token,
+//! or with a configured bearer token, and refused with 401 otherwise. A
+//! stream only attaches to an existing session of the endpoint's tenant,
+//! or, without a tenant, to one another stream created.
+
+use crate::config::WebSocketConfig;
+use crate::grpc::audio_stream::{AudioChunk, AudioEncoding, AudioIngest};
+use crate::grpc::service::{AmwajMediaService, MediaEvent, SessionOptions, SessionStatus};
+use crate::metrics::runtime::{self, TASK_WEBSOCKET};
+use crate::proto;
+use crate::webrtc::codec;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use subtle::ConstantTimeEq;
+use tokio::sync::mpsc;
+
+/// Sample rate of Twilio media streams
+pub const TWILIO_SAMPLE_RATE: u32 = 8000;
+
+/// Encoding of Twilio media streams
+pub const TWILIO_ENCODING: &str = "audio/x-mulaw";
+
+/// Custom parameter of a `<Stream>` naming its session
+pub const SESSION_ID_PARAMETER: &str = "session_id";
+
+/// Header carrying Twilio's request signature
+pub const TWILIO_SIGNATURE_HEADER: &str = "x-twilio-signature";
+
+/// Tag marking the sessions streams created
+pub const TRANSPORT_TAG: &str = "transport";
+
+/// Value of [`TRANSPORT_TAG`] for this endpoint
+const TRANSPORT_NAME: &str = "websocket";
+
+/// Track of the caller's audio
+const INBOUND_TRACK: &str = "inbound";
+
+/// A message from the provider
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum InboundMessage {
+    Connected {
+        #[serde(default)]
+        protocol: String,
+    },
+    Start {
+        start: StreamStart,
+    },
+    Media {
+        media: MediaPayload,
+    },
+    Mark {
+        mark: Mark,
+    },
+    Dtmf {
+        dtmf: Dtmf,
+    },
+    Stop,
+}
+
+/// Description of a stream, sent once before its audio
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamStart {
+    pub stream_sid: String,
+    #[serde(default)]
+    pub account_sid: String,
+    #[serde(default)]
+    pub call_sid: String,
+    #[serde(default)]
+    pub tracks: Vec<String>,
+    /// `<Parameter>`s of the `<Stream>`
+    #[serde(default)]
+    pub custom_parameters: HashMap<String, String>,
+    pub media_format: MediaFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaFormat {
+    pub encoding: String,
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+/// A chunk of audio of a track
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MediaPayload {
+    #[serde(default = "default_track")]
+    pub track: String,
+    /// Index of the chunk in the stream, from 1, as a string
+    pub chunk: String,
+    /// Time since the stream started in ms, as a string
+    pub timestamp: String,
+    /// Base64 mu-law audio
+    pub payload: String,
+}
+
+fn default_track() -> String {
+    INBOUND_TRACK.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Mark {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Dtmf {
+    #[serde(default)]
+    pub track: String,
+    pub digit: String,
+}
+
+/// A message to the provider
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum OutboundMessage {
+    /// Audio to play to the caller
+    Media {
+        #[serde(rename = "streamSid")]
+        stream_sid: String,
+        media: OutboundMedia,
+    },
+    /// Drop the audio queued for the caller
+    Clear {
+        #[serde(rename = "streamSid")]
+        stream_sid: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutboundMedia {
+    /// Base64 mu-law audio
+    pub payload: String,
+}
+
+/// A started stream and the session its audio goes to
+pub struct TwilioStream {
+    service: AmwajMediaService,
+    stream_sid: String,
+    session_id: String,
+    /// Created for the stream, ended with it
+    owned: bool,
+    ingest: AudioIngest,
+}
+
+impl TwilioStream {
+    /// Open the session of a stream, creating it for `tenant` unless it exists
+    ///
+    /// Fails for audio other than 8 kHz mono mu-law, and for an existing
+    /// session at another sample rate or the stream may not attach to.
+    pub async fn start(
+        service: AmwajMediaService,
+        start: StreamStart,
+        tenant: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let format = &start.media_format;
+        if format.encoding != TWILIO_ENCODING
+            || format.sample_rate != TWILIO_SAMPLE_RATE
+            || format.channels != 1
+        {
+            return Err(anyhow::anyhow!(
+                "Unsupported media format {} at {} Hz with {} channels",
+                format.encoding,
+                format.sample_rate,
+                format.channels
+            ));
+        }
+        let session_id = start
+            .custom_parameters
+            .get(SESSION_ID_PARAMETER)
+            .filter(|session_id| !session_id.is_empty())
+            .unwrap_or(&start.call_sid)
+            .clone();
+        if session_id.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Stream {} has no session",
+                start.stream_sid
+            ));
+        }
+
+        let owned = match service.session_status(&session_id).await {
+            Some(status) if status.sample_rate != TWILIO_SAMPLE_RATE => {
+                return Err(anyhow::anyhow!(
+                    "Session {} runs at {} Hz, the stream at {} Hz",
+                    session_id,
+                    status.sample_rate,
+                    TWILIO_SAMPLE_RATE
+                ));
+            }
+            Some(status) if !may_attach(&status, tenant) => {
+                return Err(anyhow::anyhow!(
+                    "Stream {} may not attach to session {}",
+                    start.stream_sid,
+                    session_id
+                ));
+            }
+            Some(_) => false,
+            None => {
+                let metadata = HashMap::from([
+                    ("call_sid".to_string(), start.call_sid.clone().into()),
+                    ("stream_sid".to_string(), start.stream_sid.clone().into()),
+                ]);
+                service
+                    .create_session(SessionOptions {
+                        session_id: Some(session_id.clone()),
+                        codec: Some(AudioEncoding::Pcmu),
+                        sample_rate: Some(TWILIO_SAMPLE_RATE),
+                        metadata,
+                        tags: HashMap::from([(
+                            TRANSPORT_TAG.to_string(),
+                            TRANSPORT_NAME.to_string(),
+                        )]),
+                        tenant: tenant.map(str::to_string),
+                        ..Default::default()
+                    })
+                    .await?;
+                true
+            }
+        };
+        let frame_size = TWILIO_SAMPLE_RATE * service.config().audio.frame_duration_ms / 1000;
+        tracing::info!(
+            "Twilio stream {} of call {} feeds session {}",
+            start.stream_sid,
+            start.call_sid,
+            session_id
+        );
+        Ok(Self {
+            service,
+            stream_sid: start.stream_sid,
+            session_id,
+            owned,
+            ingest: AudioIngest::new(TWILIO_SAMPLE_RATE, frame_size as usize),
+        })
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Push a chunk of the caller's audio through the session
+    ///
+    /// Returns a `clear` when the caller barged in on the agent.
+    pub fn media(&mut self, media: &MediaPayload) -> anyhow::Result<Option<OutboundMessage>> {
+        if media.track != INBOUND_TRACK {
+            return Ok(None);
+        }
+        let chunk = AudioChunk {
+            session_id: self.session_id.clone(),
+            sequence_number: media.chunk.parse()?,
+            timestamp_ms: media.timestamp.parse()?,
+            encoding: AudioEncoding::Pcmu,
+            sample_rate: TWILIO_SAMPLE_RATE,
+            channels: 1,
+            data: STANDARD.decode(&media.payload)?,
+        };
+        let mut barge_in = false;
+        for frame in self.ingest.push(&chunk)? {
+            let events = self.service.push_audio(&self.session_id, &frame)?;
+            barge_in |= events
+                .iter()
+                .any(|event| matches!(event, MediaEvent::BargeIn { .. }));
+        }
+        Ok(barge_in.then(|| OutboundMessage::Clear {
+            stream_sid: self.stream_sid.clone(),
+        }))
+    }
+
+    /// Turn a chunk of the session's playback audio into a `media` message
+    ///
+    /// Playback is relayed as PCM16 or mu-law at 8 kHz, anything else is
+    /// skipped.
+    pub fn playback(&self, chunk: &proto::AudioChunk) -> Option<OutboundMessage> {
+        let payload = match proto::audio_chunk::Encoding::try_from(chunk.encoding) {
+            _ if chunk.sample_rate != TWILIO_SAMPLE_RATE => None,
+            Ok(proto::audio_chunk::Encoding::Pcmu) => Some(chunk.data.clone()),
+            Ok(proto::audio_chunk::Encoding::Pcm16) => {
+                let samples: Vec<i16> = chunk
+                    .data
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect();
+                Some(codec::encode_pcmu(&samples))
+            }
+            _ => None,
+        };
+        let Some(payload) = payload else {
+            tracing::warn!(
+                "Skipping playback of session {} the stream can't carry",
+                self.session_id
+            );
+            return None;
+        };
+        Some(OutboundMessage::Media {
+            stream_sid: self.stream_sid.clone(),
+            media: OutboundMedia {
+                payload: STANDARD.encode(payload),
+            },
+        })
+    }
+
+    /// End the session if it was created for the stream
+    pub async fn stop(self) {
+        if !self.owned {
+            return;
+        }
+        if let Err(e) = self.service.end_session(&self.session_id).await {
+            tracing::debug!("Session {} already ended: {}", self.session_id, e);
+        }
+    }
+}
+
+/// Whether a stream of `tenant` may feed an existing session
+///
+/// With a tenant, only sessions of that tenant; without one, only untenanted
+/// sessions another stream created, never those of the gRPC API.
+fn may_attach(status: &SessionStatus, tenant: Option<&str>) -> bool {
+    match tenant {
+        Some(tenant) => status.tenant.as_deref() == Some(tenant),
+        None => {
+            status.tenant.is_none()
+                && status.tags.get(TRANSPORT_TAG).map(String::as_str) == Some(TRANSPORT_NAME)
+        }
+    }
+}
+
+/// Compute Twilio's signature of a URL, the base64 HMAC-SHA1 keyed with the auth token
+pub fn twilio_signature(auth_token: &str, url: &str) -> String {
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(auth_token.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(url.as_bytes());
+    STANDARD.encode(mac.finalize().into_bytes())
+}
+
+/// Serves Twilio Media Streams on a WebSocket endpoint
+#[derive(Clone)]
+pub struct WebSocketServer {
+    service: AmwajMediaService,
+    config: WebSocketConfig,
+}
+
+impl WebSocketServer {
+    pub fn new(service: AmwajMediaService, config: &WebSocketConfig) -> Self {
+        Self {
+            service,
+            config: config.clone(),
+        }
+    }
+
+    /// Check the credentials of an upgrade request
+    ///
+    /// Passes a valid Twilio signature of the request URL or the bearer
+    /// token, whichever is configured; nothing passes without either.
+    fn authenticate(&self, headers: &HeaderMap, uri: &Uri) -> bool {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let matches =
+            |given: &str, expected: &str| bool::from(given.as_bytes().ct_eq(expected.as_bytes()));
+        if let (Some(token), Some(signature)) = (
+            self.config
+                .twilio_auth_token
+                .as_ref()
+                .filter(|token| !token.is_empty()),
+            header(TWILIO_SIGNATURE_HEADER),
+        ) {
+            let url = match (&self.config.public_url, header(header::HOST.as_str())) {
+                (Some(url), _) => url.clone(),
+                (None, Some(host)) => format!("wss://{}{}", host, uri),
+                (None, None) => return false,
+            };
+            if matches(signature, &twilio_signature(token.expose(), &url)) {
+                return true;
+            }
+        }
+        match (
+            self.config
+                .bearer_token
+                .as_ref()
+                .filter(|token| !token.is_empty()),
+            header(header::AUTHORIZATION.as_str()).and_then(|value| value.strip_prefix("Bearer ")),
+        ) {
+            (Some(token), Some(given)) => matches(given, token.expose()),
+            _ => false,
+        }
+    }
+
+    /// Get the routes of the server
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route(&self.config.path, get(upgrade))
+            .with_state(self.clone())
+    }
+
+    /// Serve on `addr` until `shutdown` completes
+    pub async fn serve(
+        self,
+        addr: SocketAddr,
+        shutdown: impl Future<Output = ()>,
+    ) -> anyhow::Result<()> {
+        let server = axum::Server::try_bind(&addr)?.serve(self.router().into_make_service());
+        tracing::info!(
+            "WebSocket media endpoint listening on {}{}",
+            addr,
+            self.config.path
+        );
+        server.with_graceful_shutdown(shutdown).await?;
+        Ok(())
+    }
+}
+
+async fn upgrade(
+    State(server): State<WebSocketServer>,
+    headers: HeaderMap,
+    uri: Uri,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    if !server.authenticate(&headers, &uri) {
+        tracing::warn!("Refused an unauthenticated WebSocket media stream");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    upgrade.on_upgrade(move |socket| async move {
+        let service = server.service.clone();
+        runtime::supervise(
+            service.metrics(),
+            TASK_WEBSOCKET,
+            run(server.service, server.config.tenant, socket),
+        )
+        .await;
+    })
+}
+
+/// Relay a connection until either side closes it
+async fn run(service: AmwajMediaService, tenant: Option<String>, mut socket: WebSocket) {
+    let mut stream: Option<TwilioStream> = None;
+    let mut playback: Option<mpsc::Receiver<Result<proto::AudioChunk, tonic::Status>>> = None;
+    loop {
+        let reply = tokio::select! {
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                match handle(&service, tenant.as_deref(), &mut stream, &text).await {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        tracing::warn!("Closing WebSocket media stream: {}", e);
+                        break;
+                    }
+                }
+            }
+            Some(chunk) = next_playback(&mut playback) => {
+                match (chunk, stream.as_ref()) {
+                    (Ok(chunk), Some(stream)) => stream.playback(&chunk),
+                    _ => None,
+                }
+            }
+        };
+        if playback.is_none() {
+            if let Some(stream) = &stream {
+                playback = service.subscribe_playback(stream.session_id()).ok();
+            }
+        }
+        if let Some(reply) = reply {
+            let text = match serde_json::to_string(&reply) {
+                Ok(text) => text,
+                Err(e) => {
+                    tracing::warn!("Failed to encode a WebSocket message: {}", e);
+                    continue;
+                }
+            };
+            if socket.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    }
+    if let Some(stream) = stream {
+        stream.stop().await;
+    }
+}
+
+/// Handle a message of the provider, returning any reply
+async fn handle(
+    service: &AmwajMediaService,
+    tenant: Option<&str>,
+    stream: &mut Option<TwilioStream>,
+    text: &str,
+) -> anyhow::Result<Option<OutboundMessage>> {
+    match serde_json::from_str::<InboundMessage>(text)? {
+        InboundMessage::Connected { protocol } => {
+            tracing::debug!("WebSocket media stream connected, protocol {}", protocol);
+            Ok(None)
+        }
+        InboundMessage::Start { start } => {
+            if stream.is_some() {
+                return Err(anyhow::anyhow!("Stream {} started twice", start.stream_sid));
+            }
+            *stream = Some(TwilioStream::start(service.clone(), start, tenant).await?);
+            Ok(None)
+        }
+        InboundMessage::Media { media } => match stream.as_mut() {
+            Some(stream) => stream.media(&media),
+            None => Err(anyhow::anyhow!("Media before the stream started")),
+        },
+        InboundMessage::Mark { mark } => {
+            tracing::debug!("Mark {} played", mark.name);
+            Ok(None)
+        }
+        InboundMessage::Dtmf { dtmf } => {
+            tracing::debug!("DTMF digit {} on {}", dtmf.digit, dtmf.track);
+            Ok(None)
+        }
+        InboundMessage::Stop => Err(anyhow::anyhow!("Stream stopped")),
+    }
+}
+
+/// Wait for the next playback chunk, forever without a subscription
+async fn next_playback(
+    playback: &mut Option<mpsc::Receiver<Result<proto::AudioChunk, tonic::Status>>>,
+) -> Option<Result<proto::AudioChunk, tonic::Status>> {
+    match playback {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::metrics::Metrics;
+    use std::sync::Arc;
+
+    const START: &str = r#"{
+        "event": "start",
+        "sequenceNumber": "1",
+        "start": {
+            "accountSid": "AC1",
+            "streamSid": "MZ1",
+            "callSid": "CA1",
+            "tracks": ["inbound"],
+            "customParameters": {},
+            "mediaFormat": {"encoding": "audio/x-mulaw", "sampleRate": 8000, "channels": 1}
+        },
+        "streamSid": "MZ1"
+    }"#;
+
+    fn media(chunk: u64, samples: &[i16]) -> MediaPayload {
+        MediaPayload {
+            track: INBOUND_TRACK.to_string(),
+            chunk: chunk.to_string(),
+            timestamp: (chunk * 20).to_string(),
+            payload: STANDARD.encode(codec::encode_pcmu(samples)),
+        }
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        let InboundMessage::Start { start } = serde_json::from_str(START).unwrap() else {
+            panic!("not a start message");
+        };
+        assert_eq!(start.stream_sid, "MZ1");
+        assert_eq!(start.call_sid, "CA1");
+        assert_eq!(start.media_format.sample_rate, 8000);
+
+        let stop: InboundMessage =
+            serde_json::from_str(r#"{"event": "stop", "streamSid": "MZ1", "stop": {}}"#).unwrap();
+        assert_eq!(stop, InboundMessage::Stop);
+        assert!(serde_json::from_str::<InboundMessage>(r#"{"event": "unknown"}"#).is_err());
+
+        let clear = OutboundMessage::Clear {
+            stream_sid: "MZ1".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&clear).unwrap(),
+            r#"{"event":"clear","streamSid":"MZ1"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_session() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let InboundMessage::Start { mut start } = serde_json::from_str(START).unwrap() else {
+            panic!("not a start message");
+        };
+        start
+            .custom_parameters
+            .insert(SESSION_ID_PARAMETER.to_string(), "call-1".to_string());
+        let mut stream = TwilioStream::start(service.clone(), start.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(stream.session_id(), "call-1");
+        let status = service.session_status("call-1").await.unwrap();
+        assert_eq!(status.sample_rate, TWILIO_SAMPLE_RATE);
+        assert_eq!(status.codec, AudioEncoding::Pcmu);
+
+        for chunk in 1..=5 {
+            assert!(stream.media(&media(chunk, &[0; 160])).unwrap().is_none());
+        }
+        assert_eq!(service.session_stats()[0].frames_processed, 5);
+
+        let playback = proto::AudioChunk {
+            encoding: proto::audio_chunk::Encoding::Pcm16 as i32,
+            sample_rate: TWILIO_SAMPLE_RATE,
+            data: vec![0; 320],
+            ..Default::default()
+        };
+        let Some(OutboundMessage::Media { media, .. }) = stream.playback(&playback) else {
+            panic!("playback not relayed");
+        };
+        assert_eq!(STANDARD.decode(media.payload).unwrap(), vec![0xFF; 160]);
+
+        stream.stop().await;
+        assert_eq!(service.session_count(), 0);
+
+        start.media_format.sample_rate = 16000;
+        assert!(TwilioStream::start(service, start, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_attach() {
+        let mut config = Config::default();
+        for tenant in ["acme", "globex"] {
+            config
+                .tenants
+                .insert(tenant.to_string(), crate::config::TenantConfig::default());
+        }
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let InboundMessage::Start { mut start } = serde_json::from_str(START).unwrap() else {
+            panic!("not a start message");
+        };
+        let options = |session_id: &str, tenant: Option<&str>| SessionOptions {
+            session_id: Some(session_id.to_string()),
+            codec: Some(AudioEncoding::Pcmu),
+            sample_rate: Some(TWILIO_SAMPLE_RATE),
+            tenant: tenant.map(str::to_string),
+            ..Default::default()
+        };
+        service.create_session(options("api", None)).await.unwrap();
+        service
+            .create_session(options("acme", Some("acme")))
+            .await
+            .unwrap();
+        let mut attach = |session_id: &str| {
+            start
+                .custom_parameters
+                .insert(SESSION_ID_PARAMETER.to_string(), session_id.to_string());
+            start.clone()
+        };
+
+        // Sessions of the gRPC API and of other tenants are out of reach
+        for (session_id, tenant) in [("api", None), ("acme", None), ("acme", Some("globex"))] {
+            let start = attach(session_id);
+            assert!(TwilioStream::start(service.clone(), start, tenant)
+                .await
+                .is_err());
+        }
+        let stream = TwilioStream::start(service.clone(), attach("acme"), Some("acme"))
+            .await
+            .unwrap();
+        stream.stop().await;
+        assert!(service.session_status("acme").await.is_some());
+
+        // A second stream joins the session a first one created
+        let first = TwilioStream::start(service.clone(), attach("call"), None)
+            .await
+            .unwrap();
+        let second = TwilioStream::start(service.clone(), attach("call"), None)
+            .await
+            .unwrap();
+        second.stop().await;
+        first.stop().await;
+        assert!(service.session_status("call").await.is_none());
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let server = WebSocketServer::new(
+            service,
+            &WebSocketConfig {
+                twilio_auth_token: Some("auth-token".into()),
+                bearer_token: Some("bearer-token".into()),
+                ..Default::default()
+            },
+        );
+        let uri: Uri = "/media".parse().unwrap();
+        let request = |pairs: &[(&'static str, String)]| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::HOST, "media.example.com".parse().unwrap());
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+
+        assert!(!server.authenticate(&request(&[]), &uri));
+        let signature = twilio_signature("auth-token", "wss://media.example.com/media");
+        assert!(server.authenticate(&request(&[(TWILIO_SIGNATURE_HEADER, signature)]), &uri));
+        let forged = twilio_signature("other-token", "wss://media.example.com/media");
+        assert!(!server.authenticate(&request(&[(TWILIO_SIGNATURE_HEADER, forged)]), &uri));
+        let bearer = |token: &str| request(&[("authorization", format!("Bearer {}", token))]);
+        assert!(server.authenticate(&bearer("bearer-token"), &uri));
+        assert!(!server.authenticate(&bearer("auth-token"), &uri));
+
+        // Nothing passes an endpoint without credentials
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let open = WebSocketServer::new(
+            AmwajMediaService::new(config, metrics),
+            &WebSocketConfig::default(),
+        );
+        assert!(!open.authenticate(&bearer(""), &uri));
+    }
+}
@@ -2,6 +2,14 @@
 //!
 //! Provides Opus encoding/decoding for WebRTC audio streams.
 //! When the `opus-feature` is enabled, uses the audiopus crate.
+//! G.711 payloads of peers that negotiate it are expanded to PCM directly.
+
+use crate::config::OpusCodecConfig;
+use crate::error::{AmwajError, Result};
+use crate::metrics::Metrics;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
 
 /// Opus codec configuration
 #[derive(Debug, Clone)]
@@ -24,18 +32,175 @@ pub struct OpusConfig {
 
 impl Default for OpusConfig {
     fn default() -> Self {
+        Self::from(&OpusCodecConfig::default())
+    }
+}
+
+impl From<&OpusCodecConfig> for OpusConfig {
+    fn from(config: &OpusCodecConfig) -> Self {
         Self {
             sample_rate: 16000,
             channels: 1,
-            bitrate: 28000, // 28 kbps - optimal for voice
-            complexity: 9,  // High quality
-            use_dtx: true,
-            use_fec: true,
+            bitrate: config.bitrate,
+            complexity: config.complexity,
+            use_dtx: config.dtx,
+            use_fec: config.fec,
             frame_size: 320, // 20ms at 16kHz
         }
     }
 }
 
+/// Codec name of Opus in the configuration
+pub const CODEC_OPUS: &str = "opus";
+/// Codec name of G.711 mu-law in the configuration
+pub const CODEC_PCMU: &str = "pcmu";
+/// Codec name of G.711 A-law in the configuration
+pub const CODEC_PCMA: &str = "pcma";
+
+/// An audio codec as described in SDP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecInfo {
+    /// Name in the configuration
+    pub name: &'static str,
+    /// Encoding name of the `a=rtpmap` line
+    pub encoding_name: &'static str,
+    pub clock_rate: u32,
+    pub channels: u8,
+    /// Payload type assigned by RFC 3551, for codecs that have one
+    pub static_payload_type: Option<u8>,
+}
+
+/// Codecs the server can decode
+pub const CODECS: &[CodecInfo] = &[
+    CodecInfo {
+        name: CODEC_OPUS,
+        encoding_name: "opus",
+        clock_rate: 48000,
+        channels: 2,
+        static_payload_type: None,
+    },
+    CodecInfo {
+        name: CODEC_PCMU,
+        encoding_name: "PCMU",
+        clock_rate: 8000,
+        channels: 1,
+        static_payload_type: Some(0),
+    },
+    CodecInfo {
+        name: CODEC_PCMA,
+        encoding_name: "PCMA",
+        clock_rate: 8000,
+        channels: 1,
+        static_payload_type: Some(8),
+    },
+];
+
+/// Look a codec up by its configuration name
+pub fn codec_info(name: &str) -> Option<&'static CodecInfo> {
+    CODECS
+        .iter()
+        .find(|codec| codec.name.eq_ignore_ascii_case(name))
+}
+
+/// Expand G.711 mu-law samples to 16-bit PCM
+pub fn decode_pcmu(payload: &[u8]) -> Vec<i16> {
+    let mut pcm = Vec::with_capacity(payload.len());
+    decode_pcmu_into(payload, &mut pcm);
+    pcm
+}
+
+/// Expand G.711 mu-law samples, appending them to `pcm`
+pub fn decode_pcmu_into(payload: &[u8], pcm: &mut Vec<i16>) {
+    pcm.extend(payload.iter().map(|&byte| {
+        let byte = !byte;
+        let exponent = (byte >> 4) & 0x07;
+        let mantissa = (byte & 0x0F) as i16;
+        let magnitude = (((mantissa << 3) + 0x84) << exponent) - 0x84;
+        if byte & 0x80 != 0 {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }));
+}
+
+/// Compress 16-bit PCM samples to G.711 mu-law
+pub fn encode_pcmu(samples: &[i16]) -> Vec<u8> {
+    const BIAS: i32 = 0x84;
+    const CLIP: i32 = 32635;
+    samples
+        .iter()
+        .map(|&sample| {
+            let sign = if sample < 0 { 0x80 } else { 0 };
+            let magnitude = (sample as i32).abs().min(CLIP) + BIAS;
+            let mut exponent = 7;
+            while exponent > 0 && magnitude & (0x80 << exponent) == 0 {
+                exponent -= 1;
+            }
+            let mantissa = (magnitude >> (exponent + 3)) & 0x0F;
+            !(sign | (exponent << 4) as u8 | mantissa as u8)
+        })
+        .collect()
+}
+
+/// Expand G.711 A-law samples to 16-bit PCM
+pub fn decode_pcma(payload: &[u8]) -> Vec<i16> {
+    let mut pcm = Vec::with_capacity(payload.len());
+    decode_pcma_into(payload, &mut pcm);
+    pcm
+}
+
+/// Expand G.711 A-law samples, appending them to `pcm`
+pub fn decode_pcma_into(payload: &[u8], pcm: &mut Vec<i16>) {
+    pcm.extend(payload.iter().map(|&byte| {
+        let byte = byte ^ 0x55;
+        let exponent = (byte >> 4) & 0x07;
+        let mantissa = (byte & 0x0F) as i16;
+        let magnitude = match exponent {
+            0 => (mantissa << 4) + 8,
+            _ => ((mantissa << 4) + 0x108) << (exponent - 1),
+        };
+        if byte & 0x80 != 0 {
+            magnitude
+        } else {
+            -magnitude
+        }
+    }));
+}
+
+/// Compress 16-bit PCM samples to G.711 A-law
+pub fn encode_pcma(samples: &[i16]) -> Vec<u8> {
+    samples
+        .iter()
+        .map(|&sample| {
+            let mut magnitude = (sample >> 3) as i32;
+            let mask = if magnitude >= 0 {
+                0xD5
+            } else {
+                magnitude = -magnitude - 1;
+                0x55
+            };
+            let Some(segment) = (0..8).find(|segment| magnitude < (0x20 << segment)) else {
+                return 0x7F ^ mask;
+            };
+            let shift = if segment < 2 { 1 } else { segment };
+            let value = (segment << 4) | ((magnitude >> shift) & 0x0F);
+            value as u8 ^ mask
+        })
+        .collect()
+}
+
+/// Decoder settings carried over when a session migrates
+///
+/// Opus decoder state itself isn't portable; a decoder rebuilt from the hint
+/// conceals at most a packet while it converges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecoderHint {
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub frames_decoded: u64,
+}
+
 /// Opus decoder
 pub struct OpusDecoder {
     sample_rate: u32,
@@ -54,7 +219,7 @@ impl OpusDecoder {
     }
 
     /// Create decoder with configuration
-    pub fn with_config(config: &OpusConfig) -> anyhow::Result<Self> {
+    pub fn with_config(config: &OpusConfig) -> Result<Self> {
         // TODO: When opus-feature is enabled:
         // let decoder = audiopus::coder::Decoder::new(
         //     audiopus::SampleRate::Hz16000,
@@ -68,9 +233,16 @@ impl OpusDecoder {
     }
 
     /// Decode Opus data to PCM
-    pub fn decode(&mut self, opus_data: &[u8]) -> anyhow::Result<Vec<i16>> {
+    pub fn decode(&mut self, opus_data: &[u8]) -> Result<Vec<i16>> {
+        let mut pcm = Vec::new();
+        self.decode_into(opus_data, &mut pcm)?;
+        Ok(pcm)
+    }
+
+    /// Decode Opus data, appending the PCM to `pcm`
+    pub fn decode_into(&mut self, opus_data: &[u8], pcm: &mut Vec<i16>) -> Result<()> {
         if opus_data.is_empty() {
-            return Err(anyhow::anyhow!("Empty opus data"));
+            return Err(AmwajError::CodecError("Empty opus data".into()));
         }
 
         self.frames_decoded += 1;
@@ -86,13 +258,13 @@ impl OpusDecoder {
         // Stub: Generate silence proportional to input
         // Real Opus decoding would produce actual audio
         let samples_per_frame = (self.sample_rate / 50) as usize; // 20ms frame
-        let pcm = vec![0i16; samples_per_frame * self.channels as usize];
+        pcm.resize(pcm.len() + samples_per_frame * self.channels as usize, 0);
 
-        Ok(pcm)
+        Ok(())
     }
 
     /// Decode with FEC (forward error correction)
-    pub fn decode_fec(&mut self, opus_data: Option<&[u8]>) -> anyhow::Result<Vec<i16>> {
+    pub fn decode_fec(&mut self, opus_data: Option<&[u8]>) -> Result<Vec<i16>> {
         match opus_data {
             Some(data) => self.decode(data),
             None => {
@@ -117,6 +289,24 @@ impl OpusDecoder {
     pub fn reset(&mut self) {
         self.frames_decoded = 0;
     }
+
+    /// Get the settings a migrated session rebuilds its decoder from
+    pub fn hint(&self) -> DecoderHint {
+        DecoderHint {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            frames_decoded: self.frames_decoded,
+        }
+    }
+
+    /// Rebuild the decoder of a migrated session
+    pub fn from_hint(hint: &DecoderHint) -> Self {
+        Self {
+            sample_rate: hint.sample_rate,
+            channels: hint.channels,
+            frames_decoded: hint.frames_decoded,
+        }
+    }
 }
 
 /// Opus encoder
@@ -143,7 +333,7 @@ impl OpusEncoder {
     }
 
     /// Create encoder with configuration
-    pub fn with_config(config: OpusConfig) -> anyhow::Result<Self> {
+    pub fn with_config(config: OpusConfig) -> Result<Self> {
         // TODO: When opus-feature is enabled:
         // let encoder = audiopus::coder::Encoder::new(
         //     audiopus::SampleRate::Hz16000,
@@ -160,9 +350,9 @@ impl OpusEncoder {
     }
 
     /// Encode PCM to Opus
-    pub fn encode(&mut self, pcm_data: &[i16]) -> anyhow::Result<Vec<u8>> {
+    pub fn encode(&mut self, pcm_data: &[i16]) -> Result<Vec<u8>> {
         if pcm_data.is_empty() {
-            return Err(anyhow::anyhow!("Empty PCM data"));
+            return Err(AmwajError::CodecError("Empty PCM data".into()));
         }
 
         self.frames_encoded += 1;
@@ -241,24 +431,46 @@ impl OpusEncoder {
 pub struct OpusCodecManager {
     encoder: OpusEncoder,
     decoder: OpusDecoder,
+    metrics: Option<Arc<Metrics>>,
 }
 
 impl OpusCodecManager {
     /// Create a new codec manager
-    pub fn new(config: OpusConfig) -> anyhow::Result<Self> {
+    pub fn new(config: OpusConfig) -> Result<Self> {
         let encoder = OpusEncoder::with_config(config.clone())?;
         let decoder = OpusDecoder::with_config(&config)?;
-        Ok(Self { encoder, decoder })
+        Ok(Self {
+            encoder,
+            decoder,
+            metrics: None,
+        })
+    }
+
+    /// Report encode and decode latency to metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
     }
 
     /// Encode PCM to Opus
-    pub fn encode(&mut self, pcm_data: &[i16]) -> anyhow::Result<Vec<u8>> {
-        self.encoder.encode(pcm_data)
+    pub fn encode(&mut self, pcm_data: &[i16]) -> Result<Vec<u8>> {
+        let started = Instant::now();
+        let opus_data = self.encoder.encode(pcm_data)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_encode("opus", started.elapsed().as_secs_f64() * 1000.0);
+        }
+        Ok(opus_data)
     }
 
     /// Decode Opus to PCM
-    pub fn decode(&mut self, opus_data: &[u8]) -> anyhow::Result<Vec<i16>> {
-        self.decoder.decode(opus_data)
+    pub fn decode(&mut self, opus_data: &[u8]) -> Result<Vec<i16>> {
+        let started = Instant::now();
+        let pcm = self.decoder.decode(opus_data)?;
+        if let Some(metrics) = &self.metrics {
+            let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+            metrics.record_decode("opus", None, latency_ms);
+        }
+        Ok(pcm)
     }
 
     /// Enable adaptive bitrate
@@ -360,6 +572,36 @@ mod tests {
         assert!(!decoded.is_empty());
     }
 
+    #[test]
+    fn test_g711() {
+        // Silence, full scale and the sign bit of each law
+        assert_eq!(
+            decode_pcmu(&[0xFF, 0x7F, 0x80, 0x00]),
+            vec![0, 0, 32124, -32124]
+        );
+        assert_eq!(
+            decode_pcma(&[0xD5, 0x55, 0xAA, 0x2A]),
+            vec![8, -8, 32256, -32256]
+        );
+        assert_eq!(encode_pcmu(&[0, 32124, -32124]), vec![0xFF, 0x80, 0x00]);
+        assert_eq!(
+            encode_pcma(&[0, -8, 32256, -32256]),
+            vec![0xD5, 0x55, 0xAA, 0x2A]
+        );
+        let samples = [-12000, -300, 5, 1000, 20000];
+        for decoded in [
+            decode_pcmu(&encode_pcmu(&samples)),
+            decode_pcma(&encode_pcma(&samples)),
+        ] {
+            for (decoded, sample) in decoded.iter().zip(samples) {
+                // Quantization error grows with the segment
+                assert!((decoded - sample).abs() <= sample.abs() / 16 + 8);
+            }
+        }
+        assert_eq!(codec_info("PCMU").unwrap().static_payload_type, Some(0));
+        assert!(codec_info("g729").is_none());
+    }
+
     #[test]
     fn test_opus_config_default() {
         let config = OpusConfig::default();
@@ -0,0 +1,236 @@
+//! Connection state of a peer
+//!
+//! The state of a connection follows from the states of its ICE and DTLS
+//! transports the way `RTCPeerConnection.connectionState` does: any failed
+//! transport fails it, a disconnected ICE transport disconnects it, and it
+//! is connected once both transports are. Closing is final.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// State of a peer connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    #[default]
+    New,
+    Connecting,
+    Connected,
+    /// Lost connectivity, which ICE may still restore
+    Disconnected,
+    Failed,
+    Closed,
+}
+
+impl ConnectionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionState::New => "new",
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::Failed => "failed",
+            ConnectionState::Closed => "closed",
+        }
+    }
+}
+
+/// State of the ICE transport of a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IceState {
+    #[default]
+    New,
+    Checking,
+    Connected,
+    Completed,
+    Disconnected,
+    Failed,
+    Closed,
+}
+
+/// State of the DTLS transport of a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DtlsState {
+    #[default]
+    New,
+    Connecting,
+    Connected,
+    Failed,
+    Closed,
+}
+
+/// A connection moving from one state to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateChange {
+    pub previous: ConnectionState,
+    pub state: ConnectionState,
+    /// Time spent in the previous state
+    pub previous_duration: Duration,
+}
+
+/// Tracks the transports of a connection and the state they add up to
+#[derive(Debug, Clone)]
+pub struct ConnectionStateMachine {
+    ice: IceState,
+    dtls: DtlsState,
+    state: ConnectionState,
+    since: Instant,
+}
+
+impl ConnectionStateMachine {
+    pub fn new() -> Self {
+        Self {
+            ice: IceState::New,
+            dtls: DtlsState::New,
+            state: ConnectionState::New,
+            since: Instant::now(),
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn ice(&self) -> IceState {
+        self.ice
+    }
+
+    /// Get how long the connection has been in its state
+    pub fn elapsed(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    /// Take a new ICE state, returns the change of connection state it
+    /// makes if any
+    pub fn set_ice(&mut self, ice: IceState) -> Option<StateChange> {
+        self.ice = ice;
+        self.update(aggregate(self.ice, self.dtls))
+    }
+
+    /// Take a new DTLS state, returns the change of connection state it
+    /// makes if any
+    pub fn set_dtls(&mut self, dtls: DtlsState) -> Option<StateChange> {
+        self.dtls = dtls;
+        self.update(aggregate(self.ice, self.dtls))
+    }
+
+    /// Restart connectivity checks, e.g. on an ICE restart, returns the
+    /// change of connection state it makes if any
+    ///
+    /// A failed DTLS transport is set up anew along with ICE.
+    pub fn restart(&mut self) -> Option<StateChange> {
+        if self.dtls == DtlsState::Failed {
+            self.dtls = DtlsState::New;
+        }
+        self.set_ice(IceState::Checking)
+    }
+
+    /// Close the connection, returns the change unless it already was
+    pub fn close(&mut self) -> Option<StateChange> {
+        self.update(ConnectionState::Closed)
+    }
+
+    fn update(&mut self, state: ConnectionState) -> Option<StateChange> {
+        if state == self.state || self.state == ConnectionState::Closed {
+            return None;
+        }
+        let change = StateChange {
+            previous: self.state,
+            state,
+            previous_duration: self.since.elapsed(),
+        };
+        self.state = state;
+        self.since = Instant::now();
+        Some(change)
+    }
+}
+
+impl Default for ConnectionStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn aggregate(ice: IceState, dtls: DtlsState) -> ConnectionState {
+    use DtlsState as Dtls;
+    use IceState as Ice;
+    if ice == Ice::Failed || dtls == Dtls::Failed {
+        ConnectionState::Failed
+    } else if ice == Ice::Disconnected {
+        ConnectionState::Disconnected
+    } else if matches!(ice, Ice::New | Ice::Closed) && matches!(dtls, Dtls::New | Dtls::Closed) {
+        ConnectionState::New
+    } else if matches!(ice, Ice::New | Ice::Checking)
+        || matches!(dtls, Dtls::New | Dtls::Connecting)
+    {
+        ConnectionState::Connecting
+    } else {
+        ConnectionState::Connected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transports_drive_the_state() {
+        let mut machine = ConnectionStateMachine::new();
+        let change = machine.set_ice(IceState::Checking).unwrap();
+        assert_eq!(
+            (change.previous, change.state),
+            (ConnectionState::New, ConnectionState::Connecting)
+        );
+        // Still connecting until DTLS is up too
+        assert_eq!(machine.set_ice(IceState::Connected), None);
+        assert_eq!(machine.set_dtls(DtlsState::Connecting), None);
+        let change = machine.set_dtls(DtlsState::Connected).unwrap();
+        assert_eq!(change.state, ConnectionState::Connected);
+
+        assert_eq!(
+            machine.set_ice(IceState::Disconnected).unwrap().state,
+            ConnectionState::Disconnected
+        );
+        assert_eq!(
+            machine.set_ice(IceState::Completed).unwrap().state,
+            ConnectionState::Connected
+        );
+        assert_eq!(
+            machine.set_dtls(DtlsState::Failed).unwrap().state,
+            ConnectionState::Failed
+        );
+    }
+
+    #[test]
+    fn test_restart_recovers() {
+        let mut machine = ConnectionStateMachine::new();
+        machine.set_ice(IceState::Connected);
+        machine.set_dtls(DtlsState::Connected);
+        machine.set_ice(IceState::Disconnected);
+        assert_eq!(
+            machine.restart().unwrap().state,
+            ConnectionState::Connecting
+        );
+
+        machine.set_dtls(DtlsState::Failed);
+        assert_eq!(machine.state(), ConnectionState::Failed);
+        assert_eq!(
+            machine.restart().unwrap().state,
+            ConnectionState::Connecting
+        );
+        machine.set_ice(IceState::Connected);
+        assert_eq!(
+            machine.set_dtls(DtlsState::Connected).unwrap().state,
+            ConnectionState::Connected
+        );
+    }
+
+    #[test]
+    fn test_closed_is_final() {
+        let mut machine = ConnectionStateMachine::new();
+        assert_eq!(machine.close().unwrap().state, ConnectionState::Closed);
+        assert_eq!(machine.close(), None);
+        assert_eq!(machine.set_ice(IceState::Checking), None);
+        assert_eq!(machine.state(), ConnectionState::Closed);
+    }
+}
@@ -29,6 +29,20 @@ impl std::fmt::Display for CandidateType {
     }
 }
 
+impl std::str::FromStr for CandidateType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "host" => Ok(CandidateType::Host),
+            "srflx" => Ok(CandidateType::ServerReflexive),
+            "prflx" => Ok(CandidateType::PeerReflexive),
+            "relay" => Ok(CandidateType::Relay),
+            other => Err(anyhow::anyhow!("Unknown candidate type: {}", other)),
+        }
+    }
+}
+
 /// ICE candidate
 #[derive(Debug, Clone)]
 pub struct IceCandidate {
@@ -123,17 +137,54 @@ impl IceCandidate {
     }
 }
 
-/// TURN server configuration
-#[derive(Debug, Clone)]
-pub struct TurnServerConfig {
-    /// Server URL
-    pub url: String,
-    /// Username for authentication
-    pub username: String,
-    /// Password/credential
-    pub credential: String,
+impl std::str::FromStr for IceCandidate {
+    type Err = anyhow::Error;
+
+    /// Parse a `candidate:` attribute, as trickled by browsers
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let s = s.strip_prefix("a=").unwrap_or(s);
+        let s = s
+            .strip_prefix("candidate:")
+            .ok_or_else(|| anyhow::anyhow!("Not an ICE candidate: {}", s))?;
+
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() < 8 || fields[6] != "typ" {
+            return Err(anyhow::anyhow!("Malformed ICE candidate: {}", s));
+        }
+        let ip: std::net::IpAddr = fields[4].parse()?;
+        let port: u16 = fields[5].parse()?;
+
+        // Extensions come as name/value pairs after the type
+        let extensions: Vec<(&str, &str)> = fields[8..]
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+        let extension = |name: &str| {
+            extensions
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+        };
+        let related_address = match (extension("raddr"), extension("rport")) {
+            (Some(ip), Some(port)) => Some(SocketAddr::new(ip.parse()?, port.parse()?)),
+            _ => None,
+        };
+
+        Ok(Self {
+            foundation: fields[0].to_string(),
+            component: fields[1].parse()?,
+            transport: fields[2].to_uppercase(),
+            priority: fields[3].parse()?,
+            address: SocketAddr::new(ip, port),
+            candidate_type: fields[7].parse()?,
+            related_address,
+        })
+    }
 }
 
+pub use crate::config::{TurnServerConfig, TurnTransport};
+
 /// ICE gatherer for collecting candidates
 pub struct IceGatherer {
     stun_servers: Vec<String>,
@@ -271,7 +322,11 @@ impl TurnClient {
     /// Allocate a relay address
     pub async fn allocate(&mut self) -> anyhow::Result<SocketAddr> {
         // TODO: Implement actual TURN allocation
-        tracing::debug!("TURN allocation to {}", self.config.url);
+        tracing::debug!(
+            "TURN allocation to {} over {:?}",
+            self.config.url,
+            self.config.transport
+        );
         self.allocated = true;
         let addr: SocketAddr = "0.0.0.0:0".parse()?;
         self.relay_address = Some(addr);
@@ -366,6 +421,36 @@ mod tests {
         assert!(sdp.contains("192.168.1.100"));
     }
 
+    #[test]
+    fn test_candidate_parse() {
+        let candidate: IceCandidate =
+            "candidate:842163049 1 udp 1677729535 203.0.113.1 54400 typ srflx raddr 192.168.1.100 rport 54400 generation 0"
+                .parse()
+                .unwrap();
+
+        assert_eq!(candidate.foundation, "842163049");
+        assert_eq!(candidate.component, 1);
+        assert_eq!(candidate.transport, "UDP");
+        assert_eq!(candidate.priority, 1677729535);
+        assert_eq!(candidate.candidate_type, CandidateType::ServerReflexive);
+        assert_eq!(candidate.address, "203.0.113.1:54400".parse().unwrap());
+        assert_eq!(
+            candidate.related_address,
+            Some("192.168.1.100:54400".parse().unwrap())
+        );
+
+        let host = IceCandidate::host("192.168.1.100:5000".parse().unwrap(), 1);
+        let parsed: IceCandidate = format!("a={}", host.to_sdp()).parse().unwrap();
+        assert_eq!(parsed.to_sdp(), host.to_sdp());
+
+        assert!("candidate:1 1 udp 1 10.0.0.1 5000 host"
+            .parse::<IceCandidate>()
+            .is_err());
+        assert!("candidate:1 1 udp 1 10.0.0.1 5000 typ bogus"
+            .parse::<IceCandidate>()
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_ice_gatherer() {
         let mut gatherer = IceGatherer::with_defaults();
@@ -386,7 +471,9 @@ mod tests {
         let config = TurnServerConfig {
             url: "turn:turn.example.com:3478".to_string(),
             username: "user".to_string(),
-            credential: "pass".to_string(),
+            credential: "pass".into(),
+            credential_file: None,
+            transport: TurnTransport::Udp,
         };
 
         let mut client = TurnClient::new(config);
@@ -0,0 +1,225 @@
+//! Simulated network impairment between the socket and the jitter buffer
+//!
+//! `Impairment` drops, delays, reorders and duplicates packets on a virtual
+//! clock, drawing from a seeded generator, so the same seed always yields
+//! the same arrivals. `ImpairedPeer` puts it in front of a peer
+//! connection, letting tests check how the jitter buffer, FEC and loss
+//! concealment hold up without any network or timing flakiness.
+//!
+//! Built for tests and with the `impairment-feature`.
+
+use crate::error::Result;
+use crate::transport::simulation::Rng;
+use crate::webrtc::PeerConnection;
+
+/// What the network does to packets, shares in percent
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImpairmentConfig {
+    pub loss_percent: f64,
+    /// Random delay of each packet, up to this
+    pub jitter_ms: u32,
+    /// Packets held back `reorder_delay_ms` longer, arriving after later ones
+    pub reorder_percent: f64,
+    pub reorder_delay_ms: u32,
+    /// Packets delivered twice, each copy with its own delay
+    pub duplicate_percent: f64,
+    pub seed: u64,
+}
+
+/// What was done to the packets so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImpairmentStats {
+    pub sent: u64,
+    pub dropped: u64,
+    pub reordered: u64,
+    pub duplicated: u64,
+    pub delivered: u64,
+}
+
+/// A packet on its way
+struct InFlight {
+    arrival_ms: u64,
+    /// Send order, keeping arrivals at the same time in order
+    order: u64,
+    packet: Vec<u8>,
+}
+
+/// Lossy, jittery link on a virtual clock
+pub struct Impairment {
+    config: ImpairmentConfig,
+    rng: Rng,
+    in_flight: Vec<InFlight>,
+    stats: ImpairmentStats,
+}
+
+impl Impairment {
+    pub fn new(config: ImpairmentConfig) -> Self {
+        Self {
+            rng: Rng::new(config.seed),
+            config,
+            in_flight: Vec::new(),
+            stats: ImpairmentStats::default(),
+        }
+    }
+
+    /// Check a draw against a share in percent
+    fn happens(&mut self, percent: f64) -> bool {
+        percent > 0.0 && self.rng.next_f64() * 100.0 < percent
+    }
+
+    /// Send a packet at `now_ms`
+    pub fn send(&mut self, now_ms: u64, packet: Vec<u8>) {
+        self.stats.sent += 1;
+        if self.happens(self.config.loss_percent) {
+            self.stats.dropped += 1;
+            return;
+        }
+        let copies = if self.happens(self.config.duplicate_percent) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let mut delay_ms = (self.config.jitter_ms as f64 * self.rng.next_f64()) as u64;
+            if self.happens(self.config.reorder_percent) {
+                self.stats.reordered += 1;
+                delay_ms += self.config.reorder_delay_ms as u64;
+            }
+            self.in_flight.push(InFlight {
+                arrival_ms: now_ms + delay_ms,
+                order: self.stats.sent,
+                packet: packet.clone(),
+            });
+        }
+    }
+
+    /// Take the packets arrived by `now_ms`, in arrival order
+    pub fn receive(&mut self, now_ms: u64) -> Vec<Vec<u8>> {
+        self.in_flight
+            .sort_by_key(|packet| (packet.arrival_ms, packet.order));
+        let arrived = self
+            .in_flight
+            .partition_point(|packet| packet.arrival_ms <= now_ms);
+        self.stats.delivered += arrived as u64;
+        self.in_flight
+            .drain(..arrived)
+            .map(|packet| packet.packet)
+            .collect()
+    }
+
+    /// Take every packet still on its way
+    pub fn flush(&mut self) -> Vec<Vec<u8>> {
+        self.receive(u64::MAX)
+    }
+
+    /// Get the packets still on their way
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn stats(&self) -> ImpairmentStats {
+        self.stats
+    }
+}
+
+/// A peer connection receiving through an impaired link
+pub struct ImpairedPeer {
+    peer: PeerConnection,
+    link: Impairment,
+}
+
+impl ImpairedPeer {
+    pub fn new(peer: PeerConnection, config: ImpairmentConfig) -> Self {
+        Self {
+            peer,
+            link: Impairment::new(config),
+        }
+    }
+
+    /// Send an RTP packet at `now_ms`, returns the audio decoded from the
+    /// packets arriving by then
+    pub fn send(&mut self, now_ms: u64, packet: Vec<u8>) -> Result<Vec<Vec<i16>>> {
+        self.link.send(now_ms, packet);
+        let packets = self.link.receive(now_ms);
+        self.deliver(packets)
+    }
+
+    /// Deliver every packet still on its way
+    pub fn flush(&mut self) -> Result<Vec<Vec<i16>>> {
+        let packets = self.link.flush();
+        self.deliver(packets)
+    }
+
+    fn deliver(&mut self, packets: Vec<Vec<u8>>) -> Result<Vec<Vec<i16>>> {
+        let mut decoded = Vec::new();
+        for packet in packets {
+            let mut pcm = self.peer.on_rtp_packet(&packet)?;
+            while let Some(frame) = pcm {
+                decoded.push(frame);
+                pcm = self.peer.next_frame()?;
+            }
+        }
+        Ok(decoded)
+    }
+
+    pub fn peer(&self) -> &PeerConnection {
+        &self.peer
+    }
+
+    pub fn link(&self) -> &Impairment {
+        &self.link
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send 1000 packets 20 ms apart, returns their arrival order
+    fn arrivals(config: ImpairmentConfig) -> (Vec<u8>, ImpairmentStats) {
+        let mut link = Impairment::new(config);
+        let mut arrived = Vec::new();
+        for i in 0..1000u64 {
+            link.send(i * 20, vec![(i % 256) as u8]);
+            arrived.extend(link.receive(i * 20));
+        }
+        arrived.extend(link.flush());
+        (
+            arrived.into_iter().map(|packet| packet[0]).collect(),
+            link.stats(),
+        )
+    }
+
+    #[test]
+    fn test_clean_link() {
+        let (arrived, stats) = arrivals(ImpairmentConfig::default());
+        let expected: Vec<u8> = (0..1000u64).map(|i| (i % 256) as u8).collect();
+        assert_eq!(arrived, expected);
+        assert_eq!(stats.delivered, 1000);
+    }
+
+    #[test]
+    fn test_impairments() {
+        let config = ImpairmentConfig {
+            loss_percent: 10.0,
+            jitter_ms: 30,
+            reorder_percent: 5.0,
+            reorder_delay_ms: 60,
+            duplicate_percent: 5.0,
+            seed: 3,
+        };
+        let (arrived, stats) = arrivals(config.clone());
+        assert_eq!(stats.sent, 1000);
+        assert!((60..140).contains(&stats.dropped));
+        assert!((20..80).contains(&stats.duplicated));
+        assert!(stats.reordered > 0);
+        assert_eq!(
+            stats.delivered,
+            stats.sent - stats.dropped + stats.duplicated
+        );
+        assert_eq!(arrived.len() as u64, stats.delivered);
+        // Same seed, same arrivals
+        assert_eq!(arrivals(config).0, arrived);
+    }
+}
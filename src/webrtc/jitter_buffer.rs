@@ -1,7 +1,18 @@
 //! Jitter Buffer for RTP packet reordering and timing
 
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+/// Buffered packets and counters, carried over when a session migrates
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JitterBufferSnapshot {
+    /// Buffered packets by sequence number, in order
+    pub packets: Vec<(u16, Vec<u8>)>,
+    pub last_sequence: Option<u16>,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+}
+
 /// Jitter buffer to handle out-of-order RTP packets
 pub struct JitterBuffer {
     buffer: BTreeMap<u16, Vec<u8>>,
@@ -10,6 +21,8 @@ pub struct JitterBuffer {
     last_sequence: Option<u16>,
     packets_received: u64,
     packets_lost: u64,
+    /// Packets dropped unplayed because the buffer was full
+    packets_evicted: u64,
 }
 
 impl JitterBuffer {
@@ -26,6 +39,7 @@ impl JitterBuffer {
             last_sequence: None,
             packets_received: 0,
             packets_lost: 0,
+            packets_evicted: 0,
         }
     }
 
@@ -50,6 +64,7 @@ impl JitterBuffer {
         while self.buffer.len() > max_packets {
             if let Some((&oldest_seq, _)) = self.buffer.iter().next() {
                 self.buffer.remove(&oldest_seq);
+                self.packets_evicted += 1;
             }
         }
     }
@@ -112,6 +127,16 @@ impl JitterBuffer {
         }
     }
 
+    /// Get the packets missing from the sequence numbers received
+    pub fn packets_lost(&self) -> u64 {
+        self.packets_lost
+    }
+
+    /// Get the packets evicted unplayed because the buffer was full
+    pub fn packets_evicted(&self) -> u64 {
+        self.packets_evicted
+    }
+
     /// Clear the buffer
     pub fn clear(&mut self) {
         self.buffer.clear();
@@ -122,6 +147,29 @@ impl JitterBuffer {
     pub fn reset_stats(&mut self) {
         self.packets_received = 0;
         self.packets_lost = 0;
+        self.packets_evicted = 0;
+    }
+
+    /// Capture the buffered packets and counters
+    pub fn snapshot(&self) -> JitterBufferSnapshot {
+        JitterBufferSnapshot {
+            packets: self
+                .buffer
+                .iter()
+                .map(|(&sequence, data)| (sequence, data.clone()))
+                .collect(),
+            last_sequence: self.last_sequence,
+            packets_received: self.packets_received,
+            packets_lost: self.packets_lost,
+        }
+    }
+
+    /// Replace the buffer contents with a snapshot
+    pub fn restore(&mut self, snapshot: &JitterBufferSnapshot) {
+        self.buffer = snapshot.packets.iter().cloned().collect();
+        self.last_sequence = snapshot.last_sequence;
+        self.packets_received = snapshot.packets_received;
+        self.packets_lost = snapshot.packets_lost;
     }
 
     fn max_packets(&self) -> usize {
@@ -168,6 +216,22 @@ mod tests {
         assert_eq!(buffer.size(), 0);
     }
 
+    #[test]
+    fn test_snapshot_restore() {
+        let mut buffer = JitterBuffer::new(100, 16000);
+        buffer.insert(1, vec![1]);
+        buffer.get_ready_frame();
+        buffer.insert(4, vec![4]);
+        buffer.insert(3, vec![3]);
+
+        let mut restored = JitterBuffer::new(100, 16000);
+        restored.restore(&buffer.snapshot());
+        assert_eq!(restored.snapshot(), buffer.snapshot());
+        assert_eq!(restored.packet_loss_ratio(), buffer.packet_loss_ratio());
+        assert_eq!(restored.get_ready_frame(), Some(vec![3]));
+        assert_eq!(restored.get_ready_frame(), Some(vec![4]));
+    }
+
     #[test]
     fn test_is_ready() {
         let mut buffer = JitterBuffer::new(100, 16000);
@@ -180,4 +244,19 @@ mod tests {
 
         assert!(buffer.is_ready(3));
     }
+
+    #[test]
+    fn test_overflow_evictions() {
+        // 10 packets at most
+        let mut buffer = JitterBuffer::new(100, 16000);
+        for sequence in 0..15 {
+            buffer.insert(sequence, vec![sequence as u8]);
+        }
+        assert_eq!(buffer.size(), 10);
+        assert_eq!(buffer.packets_evicted(), 5);
+        assert_eq!(buffer.get_ready_frame(), Some(vec![5]));
+
+        buffer.reset_stats();
+        assert_eq!(buffer.packets_evicted(), 0);
+    }
 }
@@ -0,0 +1,354 @@
+//! Media clock of an inbound stream
+//!
+//! RTP timestamps count samples on the sender's clock, which never runs at
+//! exactly the rate of ours: a sender 100 ppm fast hands over 0.36 s of
+//! audio too much per hour, which ends up queued behind the live audio.
+//! The clock maps RTP timestamps to local time through the smallest
+//! transit delay seen in each window, the one packets queued least, and
+//! fits the drift between both clocks to how that delay moves. Decoded
+//! audio is then kept on our clock by dropping or repeating a sample at
+//! the quietest point of a frame now and then, which is not audible.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Local time over which the smallest transit delay is taken
+const WINDOW: Duration = Duration::from_secs(2);
+
+/// Windows the drift is fitted over
+const MAX_WINDOWS: usize = 30;
+
+/// Windows needed before the drift is trusted
+const MIN_WINDOWS: usize = 5;
+
+/// Drift below this is left alone, being within the noise of the fit
+const MIN_DRIFT_PPM: f64 = 10.0;
+
+/// Drift is capped here, more is no clock but a stream going wrong
+const MAX_DRIFT_PPM: f64 = 1000.0;
+
+/// Transit delay moving this much at once means the stream restarted
+const RESYNC_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Samples `compensate` may add to a frame
+pub const MAX_INSERTED_SAMPLES: usize = 1;
+
+/// Maps the RTP timestamps of a stream to local time and compensates for
+/// the drift between the sender's clock and ours
+#[derive(Debug, Clone)]
+pub struct MediaClock {
+    clock_rate: u32,
+    /// First packet's arrival, local times count from there
+    origin: Option<Instant>,
+    last_timestamp: u32,
+    /// Timestamp of the latest packet, unwrapped and counted from the first
+    extended: i64,
+    /// Smallest transit delay of the window in progress (s)
+    window_min: f64,
+    window_start: f64,
+    /// Local time and smallest transit delay of the latest windows (s)
+    windows: VecDeque<(f64, f64)>,
+    /// Smallest transit delay since the talk spurt in progress started (s)
+    spurt_min: f64,
+    drift_ppm: Option<f64>,
+    /// Samples owed to the stream, negative when it has too many
+    debt: f64,
+    samples_inserted: u64,
+    samples_deleted: u64,
+}
+
+impl MediaClock {
+    pub fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate: clock_rate.max(1),
+            origin: None,
+            last_timestamp: 0,
+            extended: 0,
+            window_min: f64::INFINITY,
+            window_start: 0.0,
+            windows: VecDeque::with_capacity(MAX_WINDOWS + 1),
+            spurt_min: f64::INFINITY,
+            drift_ppm: None,
+            debt: 0.0,
+            samples_inserted: 0,
+            samples_deleted: 0,
+        }
+    }
+
+    pub fn clock_rate(&self) -> u32 {
+        self.clock_rate
+    }
+
+    /// Take the RTP timestamp of a packet that arrived at `arrival`
+    pub fn observe(&mut self, timestamp: u32, arrival: Instant) {
+        let Some(origin) = self.origin else {
+            self.origin = Some(arrival);
+            self.last_timestamp = timestamp;
+            return;
+        };
+        // Reordered packets step back, wraps step forward
+        self.extended += i64::from(timestamp.wrapping_sub(self.last_timestamp) as i32);
+        self.last_timestamp = timestamp;
+
+        let local = arrival.saturating_duration_since(origin).as_secs_f64();
+        let transit = local - self.media_seconds(self.extended);
+        let reference = self.windows.back().map_or(self.window_min, |w| w.1);
+        if reference.is_finite() && (transit - reference).abs() > RESYNC_THRESHOLD.as_secs_f64() {
+            tracing::debug!("RTP timestamps jumped, resynchronizing the media clock");
+            self.resync(timestamp, arrival);
+            return;
+        }
+        self.window_min = self.window_min.min(transit);
+        self.spurt_min = self.spurt_min.min(transit);
+        if local - self.window_start >= WINDOW.as_secs_f64() {
+            self.windows.push_back((local, self.window_min));
+            if self.windows.len() > MAX_WINDOWS {
+                self.windows.pop_front();
+            }
+            self.window_start = local;
+            self.window_min = f64::INFINITY;
+            self.drift_ppm = self.fit();
+        }
+    }
+
+    /// Get the local time the audio of an RTP timestamp is due, once a
+    /// packet was seen
+    ///
+    /// That is when it would have arrived with the least queueing seen in
+    /// the talk spurt, or the latest window before it.
+    pub fn playout_time(&self, timestamp: u32) -> Option<Instant> {
+        let origin = self.origin?;
+        let extended =
+            self.extended + i64::from(timestamp.wrapping_sub(self.last_timestamp) as i32);
+        let transit = if self.spurt_min.is_finite() {
+            self.spurt_min
+        } else {
+            self.windows
+                .back()
+                .map_or(self.window_min, |window| window.1)
+        };
+        let transit = if transit.is_finite() { transit } else { 0.0 };
+        let offset = self.media_seconds(extended) + transit;
+        Some(if offset >= 0.0 {
+            origin + Duration::from_secs_f64(offset)
+        } else {
+            origin
+                .checked_sub(Duration::from_secs_f64(-offset))
+                .unwrap_or(origin)
+        })
+    }
+
+    /// Start a talk spurt, the delay of its playout being taken anew
+    ///
+    /// Call before observing the packet starting it.
+    pub fn start_spurt(&mut self) {
+        self.spurt_min = f64::INFINITY;
+    }
+
+    /// Get how much faster the sender's clock runs than ours, in parts per
+    /// million, once enough of the stream was seen to tell
+    pub fn drift_ppm(&self) -> Option<f64> {
+        self.drift_ppm
+    }
+
+    /// Drop or repeat samples of decoded audio to keep it on our clock
+    pub fn compensate(&mut self, pcm: &mut Vec<i16>) {
+        let Some(drift_ppm) = self.drift_ppm.filter(|ppm| ppm.abs() >= MIN_DRIFT_PPM) else {
+            return;
+        };
+        if pcm.len() < 2 {
+            return;
+        }
+        // A fast sender owes us fewer samples than it sends
+        self.debt -= drift_ppm * 1e-6 * pcm.len() as f64;
+        if self.debt <= -1.0 {
+            pcm.remove(quietest(pcm));
+            self.debt += 1.0;
+            self.samples_deleted += 1;
+        } else if self.debt >= 1.0 {
+            let at = quietest(pcm);
+            let previous = if at > 0 { pcm[at - 1] } else { pcm[at] };
+            let repeated = ((i32::from(previous) + i32::from(pcm[at])) / 2) as i16;
+            pcm.insert(at, repeated);
+            self.debt -= 1.0;
+            self.samples_inserted += 1;
+        }
+    }
+
+    /// Get the samples repeated to make up for a slow sender
+    pub fn samples_inserted(&self) -> u64 {
+        self.samples_inserted
+    }
+
+    /// Get the samples dropped to make up for a fast sender
+    pub fn samples_deleted(&self) -> u64 {
+        self.samples_deleted
+    }
+
+    /// Start over from a packet, keeping the counters
+    fn resync(&mut self, timestamp: u32, arrival: Instant) {
+        *self = Self {
+            samples_inserted: self.samples_inserted,
+            samples_deleted: self.samples_deleted,
+            ..Self::new(self.clock_rate)
+        };
+        self.origin = Some(arrival);
+        self.last_timestamp = timestamp;
+    }
+
+    fn media_seconds(&self, extended: i64) -> f64 {
+        extended as f64 / self.clock_rate as f64
+    }
+
+    /// Fit the drift to the smallest transit delays of the windows
+    fn fit(&self) -> Option<f64> {
+        if self.windows.len() < MIN_WINDOWS {
+            return None;
+        }
+        let n = self.windows.len() as f64;
+        let (mean_t, mean_d) = self
+            .windows
+            .iter()
+            .fold((0.0, 0.0), |(t, d), w| (t + w.0 / n, d + w.1 / n));
+        let (covariance, variance) =
+            self.windows
+                .iter()
+                .fold((0.0, 0.0), |(covariance, variance), (t, d)| {
+                    (
+                        covariance + (t - mean_t) * (d - mean_d),
+                        variance + (t - mean_t) * (t - mean_t),
+                    )
+                });
+        if variance <= 0.0 {
+            return None;
+        }
+        // A delay shrinking over time means the sender's clock runs fast
+        let drift_ppm = -covariance / variance * 1e6;
+        Some(drift_ppm.clamp(-MAX_DRIFT_PPM, MAX_DRIFT_PPM))
+    }
+}
+
+/// Get the index of the quietest sample, away from the edges of the frame
+fn quietest(pcm: &[i16]) -> usize {
+    let edge = pcm.len() / 8;
+    (edge..pcm.len() - edge)
+        .min_by_key(|&i| pcm[i].unsigned_abs())
+        .unwrap_or(pcm.len() / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `seconds` of 20 ms packets from a sender `drift_ppm` fast, with
+    /// up to 30 ms of jitter
+    fn feed(clock: &mut MediaClock, start: Instant, seconds: u32, drift_ppm: f64) {
+        let packets = seconds * 50;
+        for i in 0..packets {
+            let sent = i as f64 * 0.02 / (1.0 + drift_ppm * 1e-6);
+            let jitter = ((i * 7919) % 31) as f64 / 1000.0;
+            let arrival = start + Duration::from_secs_f64(sent + 0.05 + jitter);
+            clock.observe((i * 160).wrapping_add(u32::MAX - 8000), arrival);
+        }
+    }
+
+    #[test]
+    fn test_drift_is_estimated() {
+        let start = Instant::now();
+        let mut fast = MediaClock::new(8000);
+        feed(&mut fast, start, 60, 200.0);
+        let drift = fast.drift_ppm().unwrap();
+        assert!((drift - 200.0).abs() < 20.0, "{}", drift);
+
+        let mut slow = MediaClock::new(8000);
+        feed(&mut slow, start, 60, -100.0);
+        let drift = slow.drift_ppm().unwrap();
+        assert!((drift + 100.0).abs() < 20.0, "{}", drift);
+
+        let mut short = MediaClock::new(8000);
+        feed(&mut short, start, 4, 200.0);
+        assert_eq!(short.drift_ppm(), None);
+    }
+
+    #[test]
+    fn test_timestamps_map_to_local_time() {
+        let start = Instant::now();
+        let mut clock = MediaClock::new(8000);
+        assert_eq!(clock.playout_time(0), None);
+        feed(&mut clock, start, 10, 0.0);
+        // One second of media after the first packet, with the 50 ms of least
+        // transit delay
+        let due = clock
+            .playout_time(8000u32.wrapping_add(u32::MAX - 8000))
+            .unwrap();
+        let offset = due.duration_since(start).as_secs_f64();
+        assert!((offset - 1.05).abs() < 0.005, "{}", offset);
+    }
+
+    #[test]
+    fn test_compensation_tracks_drift() {
+        let start = Instant::now();
+        let mut clock = MediaClock::new(8000);
+        feed(&mut clock, start, 20, 500.0);
+        let drift = clock.drift_ppm().unwrap();
+
+        // An hour of 20 ms frames loses as many samples as the drift says
+        let mut total = 0;
+        for _ in 0..180_000 {
+            let mut pcm: Vec<i16> = (0..160).map(|i| (i * 100) as i16).collect();
+            clock.compensate(&mut pcm);
+            total += pcm.len();
+        }
+        let expected = 160.0 * 180_000.0 * (1.0 - drift * 1e-6);
+        assert!((total as f64 - expected).abs() <= 1.0);
+        assert_eq!(clock.samples_inserted(), 0);
+        assert!(clock.samples_deleted() > 0);
+    }
+
+    #[test]
+    fn test_compensation_fits_pooled_buffers() {
+        let start = Instant::now();
+        let mut clock = MediaClock::new(8000);
+        feed(&mut clock, start, 20, -500.0);
+        assert!(clock.drift_ppm().is_some());
+
+        // A slow sender has full 20 ms frames grow in place
+        let mut pcm = crate::webrtc::pool::pcm().take();
+        for _ in 0..100 {
+            pcm.clear();
+            pcm.extend((0..960).map(|i| (i * 30) as i16));
+            let (address, capacity) = (pcm.as_ptr(), pcm.capacity());
+            clock.compensate(&mut pcm);
+            assert_eq!((pcm.as_ptr(), pcm.capacity()), (address, capacity));
+        }
+        assert!(clock.samples_inserted() > 0);
+        crate::webrtc::pool::pcm().give(pcm);
+    }
+
+    #[test]
+    fn test_talk_spurt_retakes_playout_delay() {
+        let start = Instant::now();
+        let mut clock = MediaClock::new(8000);
+        feed(&mut clock, start, 10, 0.0);
+        // After a pause, the spurt arrives 200 ms later than the last one did
+        clock.start_spurt();
+        let timestamp = (500u32 * 160).wrapping_add(u32::MAX - 8000);
+        clock.observe(
+            timestamp,
+            start + Duration::from_secs_f64(10.0 + 0.05 + 0.2),
+        );
+        let due = clock.playout_time(timestamp).unwrap();
+        let offset = due.duration_since(start).as_secs_f64();
+        assert!((offset - 10.25).abs() < 0.005, "{}", offset);
+    }
+
+    #[test]
+    fn test_timestamp_jump_resyncs() {
+        let start = Instant::now();
+        let mut clock = MediaClock::new(8000);
+        feed(&mut clock, start, 20, 300.0);
+        assert!(clock.drift_ppm().is_some());
+        clock.observe(123_456_789, start + Duration::from_secs(21));
+        assert_eq!(clock.drift_ppm(), None);
+    }
+}
@@ -1,48 +1,140 @@
 //! WebRTC module for Amwaj Media Server
 
 pub mod codec;
+pub mod connection_state;
 pub mod ice;
+#[cfg(any(test, feature = "impairment-feature"))]
+pub mod impairment;
 pub mod jitter_buffer;
+pub mod media_clock;
 pub mod peer_connection;
+pub mod peer_task;
+pub mod pool;
+pub mod quality;
 pub mod rtp_handler;
+pub mod sdp;
+pub mod stats;
+pub mod talk_spurt;
 
-pub use codec::{OpusCodecManager, OpusConfig, OpusDecoder, OpusEncoder};
+pub use codec::{DecoderHint, OpusCodecManager, OpusConfig, OpusDecoder, OpusEncoder};
+pub use connection_state::{ConnectionState, DtlsState, IceState, StateChange};
 pub use ice::{CandidateType, IceCandidate, IceGatherer, StunClient, TurnClient, TurnServerConfig};
-pub use jitter_buffer::JitterBuffer;
-pub use peer_connection::PeerConnection;
-pub use rtp_handler::RtpPacket;
+pub use jitter_buffer::{JitterBuffer, JitterBufferSnapshot};
+pub use media_clock::MediaClock;
+pub use peer_connection::{PeerConnection, PeerSnapshot};
+pub use peer_task::{PeerOutput, PeerState, PeerTask};
+pub use quality::{QualityMonitor, QualityStats};
+pub use rtp_handler::{RtpHeader, RtpPacket};
+pub use stats::{RtcStats, RtcStatsReport};
 
+use crate::config::CodecsConfig;
+use crate::error::{AmwajError, Result};
+use crate::metrics::Metrics;
+use parking_lot::RwLock;
+use peer_task::DEFAULT_PACKET_QUEUE;
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::Arc;
 
+/// Shards of the connection map, a power of two
+const SHARD_COUNT: usize = 32;
+
+/// Peer connections by session
+///
+/// Sessions are spread over shards with a lock of their own, held only to
+/// look a handle up. Each connection runs as a `PeerTask` owning its jitter
+/// buffer, so packets of different sessions never wait on each other and
+/// nothing else touches a connection but through its handle.
 pub struct WebRtcManager {
-    connections: HashMap<String, PeerConnection>,
+    shards: Box<[RwLock<HashMap<String, PeerTask>>]>,
+    hasher: RandomState,
+    metrics: Option<Arc<Metrics>>,
+    codecs: CodecsConfig,
 }
 
 impl WebRtcManager {
     pub fn new() -> Self {
         Self {
-            connections: HashMap::new(),
+            shards: (0..SHARD_COUNT).map(|_| RwLock::default()).collect(),
+            hasher: RandomState::new(),
+            metrics: None,
+            codecs: CodecsConfig::default(),
+        }
+    }
+
+    /// Report the decode latency of new connections to metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Negotiate these codecs on new connections
+    pub fn with_codecs(mut self, codecs: CodecsConfig) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    fn shard(&self, session_id: &str) -> &RwLock<HashMap<String, PeerTask>> {
+        let hash = self.hasher.hash_one(session_id) as usize;
+        &self.shards[hash & (SHARD_COUNT - 1)]
+    }
+
+    /// Start the task of a new connection, needs a Tokio runtime
+    fn new_peer(&self, session_id: String) -> PeerTask {
+        let mut peer = PeerConnection::new(session_id).with_codecs(self.codecs.clone());
+        if let Some(metrics) = &self.metrics {
+            peer = peer.with_metrics(Arc::clone(metrics));
+        }
+        // Signaling peers get no RTP through the manager, nothing comes out
+        let (peer, _output) = PeerTask::spawn(peer, DEFAULT_PACKET_QUEUE);
+        peer
+    }
+
+    /// Create the connection of a session, replacing any it had
+    ///
+    /// Runs the connection on a task of the current Tokio runtime.
+    pub fn create_connection(&self, session_id: String) -> Result<PeerTask> {
+        let peer = self.new_peer(session_id.clone());
+        if let Some(replaced) = self
+            .shard(&session_id)
+            .write()
+            .insert(session_id, peer.clone())
+        {
+            replaced.close();
         }
+        Ok(peer)
     }
 
-    pub fn create_connection(&mut self, session_id: String) -> anyhow::Result<()> {
-        let peer = PeerConnection::new(session_id.clone());
-        self.connections.insert(session_id, peer);
-        Ok(())
+    /// Get the connection of a session, created if it has none
+    pub fn get_or_create_connection(&self, session_id: &str) -> PeerTask {
+        if let Some(peer) = self.shard(session_id).read().get(session_id) {
+            return peer.clone();
+        }
+        let mut shard = self.shard(session_id).write();
+        shard
+            .entry(session_id.to_string())
+            .or_insert_with(|| self.new_peer(session_id.to_string()))
+            .clone()
     }
 
-    pub fn get_connection(&mut self, session_id: &str) -> anyhow::Result<&mut PeerConnection> {
-        self.connections
-            .get_mut(session_id)
-            .ok_or_else(|| anyhow::anyhow!("Session not found"))
+    pub fn get_connection(&self, session_id: &str) -> Result<PeerTask> {
+        self.shard(session_id)
+            .read()
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| AmwajError::SessionNotFound(session_id.to_string()))
     }
 
-    pub fn remove_connection(&mut self, session_id: &str) -> Option<PeerConnection> {
-        self.connections.remove(session_id)
+    /// Remove the connection of a session, closing it
+    pub fn remove_connection(&self, session_id: &str) -> Option<PeerTask> {
+        let peer = self.shard(session_id).write().remove(session_id)?;
+        peer.close();
+        Some(peer)
     }
 
     pub fn connection_count(&self) -> usize {
-        self.connections.len()
+        self.shards.iter().map(|shard| shard.read().len()).sum()
     }
 }
 
@@ -51,3 +143,50 @@ impl Default for WebRtcManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sessions_spread_over_shards() {
+        let manager = WebRtcManager::new();
+        for i in 0..256 {
+            manager.create_connection(format!("s{}", i)).unwrap();
+        }
+        assert_eq!(manager.connection_count(), 256);
+        let used = manager
+            .shards
+            .iter()
+            .filter(|shard| !shard.read().is_empty())
+            .count();
+        assert!(used > SHARD_COUNT / 2, "{}", used);
+    }
+
+    #[tokio::test]
+    async fn test_handles_are_shared() {
+        let manager = WebRtcManager::new();
+        let created = manager.get_or_create_connection("s1");
+        let again = manager.get_or_create_connection("s1");
+        created.set_ice_state(IceState::Connected).await.unwrap();
+        again.set_dtls_state(DtlsState::Connected).await.unwrap();
+        assert!(created.state().is_connected());
+
+        // A removed connection is closed, its task serves handles still held
+        let removed = manager.remove_connection("s1").unwrap();
+        assert!(manager.get_connection("s1").is_err());
+        removed.stats().await.unwrap();
+        assert_eq!(again.state().connection_state, ConnectionState::Closed);
+
+        let tasks: Vec<_> = (0..4)
+            .map(|i| {
+                let peer = manager.create_connection(format!("t{}", i)).unwrap();
+                tokio::spawn(async move { peer.clear_buffer().await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(manager.connection_count(), 4);
+    }
+}
@@ -1,18 +1,74 @@
 //! WebRTC Peer Connection Handler
 
-use crate::webrtc::{JitterBuffer, OpusDecoder, RtpPacket};
-use parking_lot::Mutex;
+use crate::config::CodecsConfig;
+use crate::error::{AmwajError, ErrorContext, Result};
+use crate::metrics::Metrics;
+use crate::webrtc::codec::{self, DecoderHint, CODEC_PCMA, CODEC_PCMU};
+use crate::webrtc::connection_state::{
+    ConnectionState, ConnectionStateMachine, DtlsState, IceState, StateChange,
+};
+use crate::webrtc::jitter_buffer::JitterBufferSnapshot;
+use crate::webrtc::media_clock::MediaClock;
+use crate::webrtc::quality::{self, QualityMonitor, QualityStats, SenderInfo, OPUS_CLOCK_RATE};
+use crate::webrtc::rtp_handler::RtpHeader;
+use crate::webrtc::sdp::{self, NegotiatedCodec};
+use crate::webrtc::stats::{
+    self, CandidatePairStats, InboundRtpStats, RemoteOutboundRtpStats, RtcStats, RtcStatsReport,
+};
+use crate::webrtc::talk_spurt::TalkSpurtDetector;
+use crate::webrtc::{pool, IceCandidate, JitterBuffer, OpusDecoder};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+/// Media state of a peer connection, carried over when a session migrates
+///
+/// The connection itself (SDP, ICE) is renegotiated with the new instance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerSnapshot {
+    pub jitter_buffer: JitterBufferSnapshot,
+    pub decoder: DecoderHint,
+    pub packets_processed: u64,
+}
+
+/// Counters of the inbound stream reported by `get_stats`
+#[derive(Debug, Clone, Default)]
+struct StreamCounters {
+    /// SSRC of the latest RTP packet
+    ssrc: Option<u32>,
+    bytes_received: u64,
+    header_bytes_received: u64,
+    rtcp_packets: u64,
+    rtcp_bytes: u64,
+    last_packet_at: Option<SystemTime>,
+    samples_received: u64,
+    frames_emitted: u64,
+    /// Latest sender report and when it came
+    sender_report: Option<(SenderInfo, SystemTime)>,
+    sender_reports: u64,
+}
 
 /// Represents a WebRTC peer connection
 pub struct PeerConnection {
     session_id: String,
-    is_connected: bool,
+    state: ConnectionStateMachine,
     remote_sdp: Option<String>,
     local_sdp: Option<String>,
-    jitter_buffer: Arc<Mutex<JitterBuffer>>,
+    remote_candidates: Vec<IceCandidate>,
+    codecs: CodecsConfig,
+    /// Codec of the answer, Opus until one is negotiated
+    negotiated: Option<NegotiatedCodec>,
+    jitter_buffer: JitterBuffer,
     decoder: OpusDecoder,
+    /// Clock of the inbound stream, set up with its first packet
+    clock: Option<MediaClock>,
+    talk_spurts: TalkSpurtDetector,
+    /// Packet starting a spurt, with its buffered frames still to decode
+    flush: Option<(RtpHeader, usize)>,
     packets_processed: u64,
+    counters: StreamCounters,
+    quality: QualityMonitor,
+    metrics: Option<Arc<Metrics>>,
 }
 
 impl PeerConnection {
@@ -20,15 +76,40 @@ impl PeerConnection {
     pub fn new(session_id: String) -> Self {
         Self {
             session_id,
-            is_connected: false,
+            state: ConnectionStateMachine::new(),
             remote_sdp: None,
             local_sdp: None,
-            jitter_buffer: Arc::new(Mutex::new(JitterBuffer::new(100, 16000))),
+            remote_candidates: Vec::new(),
+            codecs: CodecsConfig::default(),
+            negotiated: None,
+            jitter_buffer: JitterBuffer::new(100, 16000),
             decoder: OpusDecoder::new(16000),
+            clock: None,
+            talk_spurts: TalkSpurtDetector::new(),
+            flush: None,
             packets_processed: 0,
+            counters: StreamCounters::default(),
+            quality: QualityMonitor::new(OPUS_CLOCK_RATE),
+            metrics: None,
         }
     }
 
+    /// Report decode latency per payload type and call quality to metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Negotiate the codecs of `[audio.codecs]`
+    pub fn with_codecs(mut self, codecs: CodecsConfig) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        self.metrics.as_ref()
+    }
+
     /// Get the session ID
     pub fn session_id(&self) -> &str {
         &self.session_id
@@ -36,16 +117,51 @@ impl PeerConnection {
 
     /// Check if connected
     pub fn is_connected(&self) -> bool {
-        self.is_connected
+        self.state.state() == ConnectionState::Connected
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        self.state.state()
     }
 
-    /// Set connected state
-    pub fn set_connected(&mut self, connected: bool) {
-        self.is_connected = connected;
+    /// Take the state of the ICE transport, returns the change of
+    /// connection state it makes if any
+    pub fn set_ice_state(&mut self, ice: IceState) -> Option<StateChange> {
+        let change = self.state.set_ice(ice);
+        self.record_state_change(change)
+    }
+
+    /// Take the state of the DTLS transport, returns the change of
+    /// connection state it makes if any
+    pub fn set_dtls_state(&mut self, dtls: DtlsState) -> Option<StateChange> {
+        let change = self.state.set_dtls(dtls);
+        self.record_state_change(change)
+    }
+
+    /// Start connectivity checks, anew when the connection was up before
+    pub fn start_checks(&mut self) -> Option<StateChange> {
+        let change = self.state.restart();
+        self.record_state_change(change)
+    }
+
+    /// Close the connection for good
+    pub fn close(&mut self) -> Option<StateChange> {
+        let change = self.state.close();
+        self.record_state_change(change)
+    }
+
+    fn record_state_change(&self, change: Option<StateChange>) -> Option<StateChange> {
+        if let (Some(change), Some(metrics)) = (&change, &self.metrics) {
+            metrics.record_connection_state(
+                change.previous.as_str(),
+                change.previous_duration.as_secs_f64(),
+            );
+        }
+        change
     }
 
     /// Set remote SDP offer
-    pub fn set_remote_sdp(&mut self, sdp: String) -> anyhow::Result<()> {
+    pub fn set_remote_sdp(&mut self, sdp: String) -> Result<()> {
         self.remote_sdp = Some(sdp);
         Ok(())
     }
@@ -56,53 +172,291 @@ impl PeerConnection {
     }
 
     /// Create SDP answer
-    pub fn create_answer(&mut self) -> anyhow::Result<String> {
-        // TODO: Implement proper SDP answer creation
-        let answer = "v=0\r\n\
-             o=- 0 0 IN IP4 127.0.0.1\r\n\
-             s=Amwaj Media Server\r\n\
-             t=0 0\r\n\
-             m=audio 0 RTP/AVP 111\r\n\
-             a=rtpmap:111 opus/48000/2\r\n"
-            .to_string();
+    ///
+    /// Answers with the most preferred codec of the offer; an offer without
+    /// an audio section gets the most preferred codec overall, with RTCP
+    /// multiplexed. Fails if the offer has no codec in common.
+    pub fn create_answer(&mut self) -> Result<String> {
+        let (negotiated, offer) = match &self.remote_sdp {
+            Some(offer) if offer.contains("m=audio") => {
+                (sdp::negotiate(offer, &self.codecs)?, Some(offer.as_str()))
+            }
+            _ => (sdp::preferred(&self.codecs)?, None),
+        };
+        let answer = sdp::answer(&negotiated, &self.codecs, offer);
+        self.negotiated = Some(negotiated);
         self.local_sdp = Some(answer.clone());
         Ok(answer)
     }
 
+    /// Get the codec of the answer, if one was created
+    pub fn negotiated_codec(&self) -> Option<&NegotiatedCodec> {
+        self.negotiated.as_ref()
+    }
+
+    /// Decode with a codec agreed on without SDP, as with plain RTP
+    pub fn set_negotiated_codec(&mut self, negotiated: NegotiatedCodec) {
+        self.negotiated = Some(negotiated);
+    }
+
+    /// Get the local SDP answer
+    pub fn local_sdp(&self) -> Option<&String> {
+        self.local_sdp.as_ref()
+    }
+
+    /// Add a trickled remote ICE candidate
+    ///
+    /// Candidates are only accepted once the remote SDP has been set.
+    pub fn add_ice_candidate(&mut self, candidate: IceCandidate) -> Result<()> {
+        if self.remote_sdp.is_none() {
+            return Err(AmwajError::WebRtcError("Remote SDP not set".into()));
+        }
+        self.remote_candidates.push(candidate);
+        Ok(())
+    }
+
+    /// Get the remote ICE candidates
+    pub fn remote_candidates(&self) -> &[IceCandidate] {
+        &self.remote_candidates
+    }
+
     /// Handle incoming RTP packet
-    pub fn on_rtp_packet(&mut self, packet_data: &[u8]) -> anyhow::Result<Option<Vec<i16>>> {
-        let packet = RtpPacket::parse(packet_data)?;
+    ///
+    /// The payload and the PCM come from the buffer pools; callers done with
+    /// the PCM give it back to `pool::pcm()` to keep the path allocation free.
+    /// A packet starting a talk spurt flushes what is left of the last one
+    /// with its own, so delay built up during a spurt is dropped in the
+    /// silence after it. The oldest frame is returned and `next_frame` gives
+    /// the others, one per call.
+    pub fn on_rtp_packet(&mut self, packet_data: &[u8]) -> Result<Option<Vec<i16>>> {
+        let (header, payload) = RtpHeader::parse(packet_data)
+            .map_err(|e| e.with_context(ErrorContext::session(&self.session_id)))?;
 
         self.packets_processed += 1;
+        self.counters.ssrc = Some(header.ssrc);
+        self.counters.bytes_received += packet_data.len() as u64;
+        self.counters.header_bytes_received += (packet_data.len() - payload.len()) as u64;
+        self.counters.last_packet_at = Some(SystemTime::now());
+        let clock_rate = self
+            .negotiated
+            .map_or(OPUS_CLOCK_RATE, |negotiated| negotiated.codec.clock_rate);
+        let clock = self
+            .clock
+            .get_or_insert_with(|| MediaClock::new(clock_rate));
+        // Jitter and playout delay are taken anew after a pause
+        let spurt_start = self.talk_spurts.on_packet(&header);
+        if spurt_start {
+            self.quality.restart_transit();
+            clock.start_spurt();
+        }
+        self.quality.on_rtp(header.timestamp);
+        clock.observe(header.timestamp, Instant::now());
+
+        let mut buffered = pool::packets().take();
+        buffered.extend_from_slice(payload);
+        self.jitter_buffer.insert(header.sequence_number, buffered);
 
-        // Insert into jitter buffer
-        {
-            let mut buffer = self.jitter_buffer.lock();
-            buffer.insert(packet.sequence_number, packet.payload.clone());
+        // Decode the next frame once it is ready, every buffered one at the
+        // start of a spurt
+        self.flush = spurt_start
+            .then(|| (header, self.jitter_buffer.size().saturating_sub(1)))
+            .filter(|(_, frames)| *frames > 0);
+        self.decode_frame(&header)
+    }
+
+    /// Decode the next frame flushed at the start of a talk spurt
+    ///
+    /// Returns `None` once `on_rtp_packet` emitted all of them.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<i16>>> {
+        let Some((header, frames)) = self.flush else {
+            return Ok(None);
+        };
+        self.flush = (frames > 1).then_some((header, frames - 1));
+        let pcm = self.decode_frame(&header)?;
+        if pcm.is_none() {
+            self.flush = None;
         }
+        Ok(pcm)
+    }
 
-        // Try to get a ready frame and decode it
-        let frame = {
-            let mut buffer = self.jitter_buffer.lock();
-            buffer.get_ready_frame()
+    /// Decode the jitter buffer's next ready frame of the stream of `header`
+    fn decode_frame(&mut self, header: &RtpHeader) -> Result<Option<Vec<i16>>> {
+        let Some(payload) = self.jitter_buffer.get_ready_frame() else {
+            return Ok(None);
+        };
+        let started = Instant::now();
+        let codec = self
+            .negotiated
+            .map_or(codec::CODEC_OPUS, |negotiated| negotiated.codec.name);
+        let mut pcm = pool::pcm().take();
+        let decoded = match codec {
+            CODEC_PCMU => {
+                codec::decode_pcmu_into(&payload, &mut pcm);
+                Ok(())
+            }
+            CODEC_PCMA => {
+                codec::decode_pcma_into(&payload, &mut pcm);
+                Ok(())
+            }
+            _ => self.decoder.decode_into(&payload, &mut pcm),
         };
+        pool::packets().give(payload);
+        if let Err(e) = decoded {
+            pool::pcm().give(pcm);
+            return Err(
+                e.with_context(ErrorContext::session(&self.session_id).with_ssrc(header.ssrc))
+            );
+        }
+        if let Some(metrics) = &self.metrics {
+            let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+            metrics.record_decode(codec, Some(header.payload_type), latency_ms);
+        }
+        self.counters.samples_received += pcm.len() as u64;
+        self.counters.frames_emitted += 1;
+        if let Some(clock) = &mut self.clock {
+            let (inserted, deleted) = (clock.samples_inserted(), clock.samples_deleted());
+            clock.compensate(&mut pcm);
+            if let Some(metrics) = &self.metrics {
+                metrics.record_clock_compensation(
+                    clock.samples_inserted() - inserted,
+                    clock.samples_deleted() - deleted,
+                );
+            }
+        }
+        Ok(Some(pcm))
+    }
 
-        if let Some(opus_data) = frame {
-            let pcm = self.decoder.decode(&opus_data)?;
-            Ok(Some(pcm))
-        } else {
-            Ok(None)
+    /// Handle an incoming RTCP compound packet
+    ///
+    /// Reports on our sender reports give the round-trip time. Each report
+    /// also samples the call quality into metrics, so they are observed at
+    /// the RTCP interval of the session.
+    pub fn on_rtcp_packet(&mut self, packet_data: &[u8]) -> Result<()> {
+        let blocks = quality::parse_report_blocks(packet_data)
+            .map_err(|e| e.with_context(ErrorContext::session(&self.session_id)))?;
+        let sender_info = quality::parse_sender_info(packet_data)
+            .map_err(|e| e.with_context(ErrorContext::session(&self.session_id)))?;
+        let now = SystemTime::now();
+        self.counters.rtcp_packets += 1;
+        self.counters.rtcp_bytes += packet_data.len() as u64;
+        self.counters.last_packet_at = Some(now);
+        if let Some(sender_info) = sender_info {
+            self.counters.sender_report = Some((sender_info, now));
+            self.counters.sender_reports += 1;
         }
+        self.quality.on_report_blocks(&blocks, now);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_quality(&self.quality_stats());
+        }
+        Ok(())
+    }
+
+    /// Get the interarrival jitter of the inbound audio in ms
+    pub fn jitter_ms(&self) -> f32 {
+        self.quality.jitter_ms() as f32
+    }
+
+    /// Get the quality of the inbound audio
+    pub fn quality_stats(&self) -> QualityStats {
+        let packet_loss_ratio = self.jitter_buffer.packet_loss_ratio();
+        self.quality.stats(packet_loss_ratio)
     }
 
     /// Get jitter buffer statistics
     pub fn get_buffer_stats(&self) -> BufferStats {
-        let buffer = self.jitter_buffer.lock();
+        let quality = self.quality_stats();
+        let buffer = &self.jitter_buffer;
         BufferStats {
             size: buffer.size(),
             level_percent: buffer.level_percent(),
             packet_loss_ratio: buffer.packet_loss_ratio(),
+            packets_evicted: buffer.packets_evicted(),
+            jitter_ms: quality.jitter_ms,
+            rtt_ms: quality.rtt_ms,
+            mos: quality.mos,
+            clock_drift_ppm: self
+                .clock
+                .as_ref()
+                .and_then(|clock| clock.drift_ppm())
+                .map(|ppm| ppm as f32),
+            samples_compensated: self.clock.as_ref().map_or(0, |clock| {
+                clock.samples_inserted() + clock.samples_deleted()
+            }),
+            talk_spurts: self.talk_spurts.talk_spurts(),
+        }
+    }
+
+    /// Get the statistics of the connection in the shape of WebRTC getStats
+    ///
+    /// The inbound-rtp appears with the first RTP packet, the
+    /// remote-outbound-rtp with the first RTCP sender report.
+    pub fn get_stats(&self) -> RtcStatsReport {
+        let now = stats::timestamp(SystemTime::now());
+        let counters = &self.counters;
+        let last_packet_received_timestamp = counters.last_packet_at.map(stats::timestamp);
+        let mut report = RtcStatsReport::new();
+
+        let inbound_id = counters.ssrc.map(|ssrc| format!("inbound-rtp-{}", ssrc));
+        let remote = counters
+            .sender_report
+            .filter(|(info, _)| Some(info.ssrc) == counters.ssrc);
+        if let (Some(inbound_id), Some((info, received_at))) = (&inbound_id, remote) {
+            report.insert(RtcStats::RemoteOutboundRtp(RemoteOutboundRtpStats {
+                id: format!("remote-outbound-rtp-{}", info.ssrc),
+                timestamp: stats::timestamp(received_at),
+                ssrc: info.ssrc,
+                kind: "audio",
+                local_id: inbound_id.clone(),
+                remote_timestamp: stats::timestamp(info.sent_at()),
+                packets_sent: info.packet_count.into(),
+                bytes_sent: info.octet_count.into(),
+                reports_sent: counters.sender_reports,
+            }));
+        }
+        if let (Some(id), Some(ssrc)) = (inbound_id, counters.ssrc) {
+            report.insert(RtcStats::InboundRtp(InboundRtpStats {
+                id,
+                timestamp: now,
+                ssrc,
+                kind: "audio",
+                remote_id: remote.map(|_| format!("remote-outbound-rtp-{}", ssrc)),
+                packets_received: self.packets_processed,
+                packets_lost: self.jitter_buffer.packets_lost(),
+                packets_discarded: self.jitter_buffer.packets_evicted(),
+                bytes_received: counters.bytes_received,
+                header_bytes_received: counters.header_bytes_received,
+                jitter: self.quality.jitter_ms() / 1000.0,
+                last_packet_received_timestamp,
+                total_samples_received: counters.samples_received,
+                jitter_buffer_emitted_count: counters.frames_emitted,
+                inserted_samples_for_deceleration: self
+                    .clock
+                    .as_ref()
+                    .map_or(0, MediaClock::samples_inserted),
+                removed_samples_for_acceleration: self
+                    .clock
+                    .as_ref()
+                    .map_or(0, MediaClock::samples_deleted),
+            }));
         }
+
+        let ice = self.state.ice();
+        report.insert(RtcStats::CandidatePair(CandidatePairStats {
+            id: stats::CANDIDATE_PAIR_ID.to_string(),
+            timestamp: now,
+            state: stats::candidate_pair_state(ice),
+            nominated: matches!(ice, IceState::Connected | IceState::Completed),
+            packets_received: self.packets_processed + counters.rtcp_packets,
+            bytes_received: counters.bytes_received + counters.rtcp_bytes,
+            last_packet_received_timestamp,
+            current_round_trip_time: self.quality.rtt_ms().map(|rtt| rtt / 1000.0),
+        }));
+        report
+    }
+
+    /// Get the clock of the inbound stream, once a packet was received
+    pub fn media_clock(&self) -> Option<&MediaClock> {
+        self.clock.as_ref()
     }
 
     /// Get total packets processed
@@ -112,8 +466,25 @@ impl PeerConnection {
 
     /// Clear the jitter buffer
     pub fn clear_buffer(&mut self) {
-        let mut buffer = self.jitter_buffer.lock();
-        buffer.clear();
+        self.jitter_buffer.clear();
+        self.flush = None;
+    }
+
+    /// Capture the jitter buffer and decoder state
+    pub fn snapshot(&self) -> PeerSnapshot {
+        PeerSnapshot {
+            jitter_buffer: self.jitter_buffer.snapshot(),
+            decoder: self.decoder.hint(),
+            packets_processed: self.packets_processed,
+        }
+    }
+
+    /// Resume the jitter buffer and decoder of a migrated session
+    pub fn restore(&mut self, snapshot: &PeerSnapshot) {
+        self.jitter_buffer.restore(&snapshot.jitter_buffer);
+        self.flush = None;
+        self.decoder = OpusDecoder::from_hint(&snapshot.decoder);
+        self.packets_processed = snapshot.packets_processed;
     }
 }
 
@@ -123,6 +494,18 @@ pub struct BufferStats {
     pub size: usize,
     pub level_percent: f32,
     pub packet_loss_ratio: f32,
+    /// Packets dropped unplayed because the jitter buffer was full
+    pub packets_evicted: u64,
+    pub jitter_ms: f32,
+    pub rtt_ms: Option<f32>,
+    /// Estimated mean opinion score of the inbound audio
+    pub mos: f32,
+    /// How much faster the sender's clock runs than ours, once estimated
+    pub clock_drift_ppm: Option<f32>,
+    /// Samples dropped or repeated to make up for the drift
+    pub samples_compensated: u64,
+    /// Talk spurts of the inbound audio, the first packet starting one
+    pub talk_spurts: u64,
 }
 
 #[cfg(test)]
@@ -156,6 +539,38 @@ mod tests {
         assert!(answer_str.contains("opus"));
     }
 
+    #[test]
+    fn test_answer_negotiates_codec() {
+        let codecs = CodecsConfig {
+            preferred: vec!["pcmu".to_string(), "opus".to_string()],
+            ..CodecsConfig::default()
+        };
+        let mut peer = PeerConnection::new("test".to_string()).with_codecs(codecs);
+        peer.set_remote_sdp(
+            "v=0\r\nm=audio 9 RTP/AVP 111 0\r\na=rtpmap:111 opus/48000/2\r\n".to_string(),
+        )
+        .unwrap();
+        let answer = peer.create_answer().unwrap();
+        assert!(answer.contains("a=rtpmap:0 PCMU/8000"));
+        assert_eq!(peer.negotiated_codec().unwrap().codec.name, "pcmu");
+
+        // mu-law silence, payload type 0
+        let rtp_data = [0x80, 0x00, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0xFF, 0xFF];
+        assert_eq!(peer.on_rtp_packet(&rtp_data).unwrap(), Some(vec![0, 0]));
+    }
+
+    #[test]
+    fn test_add_ice_candidate() {
+        let mut peer = PeerConnection::new("test".to_string());
+        let candidate = IceCandidate::host("192.168.1.100:5000".parse().unwrap(), 1);
+
+        assert!(peer.add_ice_candidate(candidate.clone()).is_err());
+
+        peer.set_remote_sdp("v=0\r\n".to_string()).unwrap();
+        peer.add_ice_candidate(candidate).unwrap();
+        assert_eq!(peer.remote_candidates().len(), 1);
+    }
+
     #[test]
     fn test_rtp_packet_handling() {
         let mut peer = PeerConnection::new("test".to_string());
@@ -172,4 +587,127 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(peer.packets_processed(), 1);
     }
+
+    #[test]
+    fn test_talk_spurt_flushes_buffer() {
+        let mut peer = PeerConnection::new("test".to_string());
+        peer.set_negotiated_codec(NegotiatedCodec {
+            codec: codec::codec_info("pcmu").unwrap(),
+            payload_type: 0,
+        });
+        let packet = |sequence: u16, timestamp: u32, marker: bool| {
+            let mut data = vec![0x80, if marker { 0x80 } else { 0 }];
+            data.extend(sequence.to_be_bytes());
+            data.extend(timestamp.to_be_bytes());
+            data.extend([0, 0, 0, 1]);
+            data.extend([0xFF; 160]);
+            data
+        };
+        // Packets left buffered by a migration
+        peer.restore(&PeerSnapshot {
+            jitter_buffer: JitterBufferSnapshot {
+                packets: vec![(8, vec![0xFF; 160]), (9, vec![0xFF; 160])],
+                last_sequence: Some(7),
+                ..Default::default()
+            },
+            ..peer.snapshot()
+        });
+        // Emitted a frame at a time
+        let pcm = peer
+            .on_rtp_packet(&packet(10, 1600, false))
+            .unwrap()
+            .unwrap();
+        assert_eq!((pcm.len(), peer.get_buffer_stats().size), (160, 2));
+        for size in [1, 0] {
+            let pcm = peer.next_frame().unwrap().unwrap();
+            assert_eq!((pcm.len(), peer.get_buffer_stats().size), (160, size));
+        }
+        assert_eq!(peer.next_frame().unwrap(), None);
+        let pcm = peer
+            .on_rtp_packet(&packet(11, 1760, false))
+            .unwrap()
+            .unwrap();
+        assert_eq!(pcm.len(), 160);
+        peer.on_rtp_packet(&packet(12, 8000, true)).unwrap();
+        assert_eq!(peer.get_buffer_stats().talk_spurts, 2);
+    }
+
+    #[test]
+    fn test_rtcp_quality() {
+        let metrics = Arc::new(Metrics::new(&crate::config::Config::default()));
+        let mut peer = PeerConnection::new("test".to_string()).with_metrics(Arc::clone(&metrics));
+        assert_eq!(peer.get_buffer_stats().rtt_ms, None);
+
+        // Receiver report on a sender report sent 300 ms ago, held 250 ms
+        let last_sr =
+            quality::ntp_middle_32(SystemTime::now() - std::time::Duration::from_millis(300));
+        let mut rtcp = vec![0x81, 201, 0, 7, 0, 0, 0, 1, 0, 0, 0, 2];
+        rtcp.extend([0; 12]);
+        rtcp.extend(last_sr.to_be_bytes());
+        rtcp.extend((65536u32 / 4).to_be_bytes());
+        peer.on_rtcp_packet(&rtcp).unwrap();
+
+        let stats = peer.get_buffer_stats();
+        let rtt = stats.rtt_ms.unwrap();
+        assert!((rtt - 50.0).abs() < 5.0, "{}", rtt);
+        assert!(stats.mos > 4.0);
+        assert_eq!(metrics.session_mos.get_sample_count(), 1);
+        assert!(peer.on_rtcp_packet(&rtcp[..10]).is_err());
+    }
+
+    #[test]
+    fn test_get_stats() {
+        let mut peer = PeerConnection::new("test".to_string());
+        peer.set_negotiated_codec(NegotiatedCodec {
+            codec: codec::codec_info("pcmu").unwrap(),
+            payload_type: 0,
+        });
+        let report = peer.get_stats();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report.to_json()["candidate-pair"]["state"], "frozen");
+
+        for sequence in [1u16, 2, 4] {
+            let mut data = vec![0x80, 0];
+            data.extend(sequence.to_be_bytes());
+            data.extend((u32::from(sequence) * 160).to_be_bytes());
+            data.extend(0x1234u32.to_be_bytes());
+            data.extend([0xFF; 160]);
+            peer.on_rtp_packet(&data).unwrap();
+        }
+        // Sender report of the stream
+        let mut rtcp = vec![0x80, 200, 0, 6];
+        for word in [0x1234u32, 3_913_056_000, 0, 640, 4, 640] {
+            rtcp.extend(word.to_be_bytes());
+        }
+        peer.on_rtcp_packet(&rtcp).unwrap();
+        peer.set_ice_state(IceState::Connected);
+
+        let json = peer.get_stats().to_json();
+        let inbound = &json["inbound-rtp-4660"];
+        assert_eq!(inbound["type"], "inbound-rtp");
+        assert_eq!(inbound["ssrc"], 0x1234);
+        assert_eq!(inbound["kind"], "audio");
+        assert_eq!(inbound["packetsReceived"], 3);
+        assert_eq!(inbound["packetsLost"], 1);
+        assert_eq!(inbound["bytesReceived"], 3 * 172);
+        assert_eq!(inbound["headerBytesReceived"], 3 * 12);
+        assert_eq!(inbound["totalSamplesReceived"], 3 * 160);
+        assert_eq!(inbound["jitterBufferEmittedCount"], 3);
+        assert_eq!(inbound["remoteId"], "remote-outbound-rtp-4660");
+        assert!(inbound["lastPacketReceivedTimestamp"].as_f64().unwrap() > 0.0);
+
+        let remote = &json["remote-outbound-rtp-4660"];
+        assert_eq!(remote["type"], "remote-outbound-rtp");
+        assert_eq!(remote["localId"], "inbound-rtp-4660");
+        assert_eq!(remote["remoteTimestamp"], 1_704_067_200_000.0);
+        assert_eq!(remote["packetsSent"], 4);
+        assert_eq!(remote["bytesSent"], 640);
+        assert_eq!(remote["reportsSent"], 1);
+
+        let pair = &json["candidate-pair"];
+        assert_eq!(pair["state"], "succeeded");
+        assert_eq!(pair["nominated"], true);
+        assert_eq!(pair["packetsReceived"], 4);
+        assert_eq!(pair["bytesReceived"], 3 * 172 + 28);
+    }
 }
@@ -0,0 +1,452 @@
+//! Peer connections run as tasks of their own
+//!
+//! A `PeerTask` owns its `PeerConnection` and takes packets and commands
+//! over channels, so whoever reads the socket only hands packets over:
+//! reordering and decoding happen on the peer's task, and the decoded
+//! frames come out of a channel for the session to process. Packets that
+//! find the queue full are dropped and counted instead of waited on, so a
+//! peer falling behind never stalls the reader. After each packet or
+//! command the task publishes a `PeerState`, which readers look at without
+//! a round trip.
+
+use crate::error::{AmwajError, Result};
+use crate::metrics::runtime::{self, TASK_PEER};
+use crate::webrtc::peer_connection::BufferStats;
+use crate::webrtc::rtp_handler::RtpHeader;
+use crate::webrtc::{
+    pool, ConnectionState, DtlsState, IceCandidate, IceState, PeerConnection, PeerSnapshot,
+    RtcStatsReport, StateChange,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// Packets queued for a peer by default, a bit over a second of 20 ms audio
+pub const DEFAULT_PACKET_QUEUE: usize = 64;
+
+/// Commands queued for a peer
+const COMMAND_QUEUE: usize = 16;
+
+/// What a peer task is asked to do besides taking RTP
+pub enum PeerCommand {
+    /// Take an RTCP compound packet
+    Rtcp(Vec<u8>),
+    SetRemoteSdp(String, oneshot::Sender<Result<()>>),
+    CreateAnswer(oneshot::Sender<Result<String>>),
+    AddIceCandidate(IceCandidate, oneshot::Sender<Result<()>>),
+    Stats(oneshot::Sender<PeerStats>),
+    RtcStats(oneshot::Sender<RtcStatsReport>),
+    Snapshot(oneshot::Sender<PeerSnapshot>),
+    Restore(Box<PeerSnapshot>),
+    SetIceState(IceState, oneshot::Sender<Option<StateChange>>),
+    SetDtlsState(DtlsState, oneshot::Sender<Option<StateChange>>),
+    StartChecks(oneshot::Sender<Option<StateChange>>),
+    Close,
+    ClearBuffer,
+}
+
+/// What comes out of a peer task
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerOutput {
+    /// An RTP packet was taken, in arrival order
+    Packet {
+        sequence_number: u16,
+        timestamp: u32,
+        jitter_ms: f32,
+    },
+    /// PCM decoded out of the jitter buffer, to give back to `pool::pcm()`
+    Frame(Vec<i16>),
+}
+
+/// State of a peer, as its task reports it
+#[derive(Debug, Clone)]
+pub struct PeerStats {
+    pub packets_processed: u64,
+    pub buffer: BufferStats,
+}
+
+/// State of a peer as of the last packet or command its task took
+#[derive(Debug, Clone)]
+pub struct PeerState {
+    pub connection_state: ConnectionState,
+    pub packets_processed: u64,
+    pub buffer: BufferStats,
+    /// Name of the negotiated codec, once answered
+    pub codec: Option<&'static str>,
+}
+
+impl PeerState {
+    fn of(peer: &PeerConnection) -> Self {
+        Self {
+            connection_state: peer.connection_state(),
+            packets_processed: peer.packets_processed(),
+            buffer: peer.get_buffer_stats(),
+            codec: peer
+                .negotiated_codec()
+                .map(|negotiated| negotiated.codec.name),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection_state == ConnectionState::Connected
+    }
+}
+
+/// Handle to a peer connection running as a task
+///
+/// Handles are cheap to clone; the task ends once every one is dropped and
+/// the packets queued are decoded.
+#[derive(Clone)]
+pub struct PeerTask {
+    session_id: String,
+    packets: mpsc::Sender<Vec<u8>>,
+    commands: mpsc::Sender<PeerCommand>,
+    state: watch::Receiver<PeerState>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl PeerTask {
+    /// Start the task of a peer, queueing up to `queue` packets
+    ///
+    /// Returns the handle and the output of the task, which waits on the
+    /// output when it isn't read.
+    pub fn spawn(peer: PeerConnection, queue: usize) -> (Self, mpsc::Receiver<PeerOutput>) {
+        let (packets, packets_rx) = mpsc::channel(queue.max(1));
+        let (commands, commands_rx) = mpsc::channel(COMMAND_QUEUE);
+        let (output, output_rx) = mpsc::channel(queue.max(1));
+        let (state_tx, state) = watch::channel(PeerState::of(&peer));
+        let session_id = peer.session_id().to_string();
+        let metrics = peer.metrics().cloned();
+        let task = run(peer, packets_rx, commands_rx, output, state_tx);
+        match metrics {
+            Some(metrics) => tokio::spawn(async move {
+                let task = runtime::poll_timed(&metrics, TASK_PEER, task);
+                runtime::supervise(&metrics, TASK_PEER, task).await;
+            }),
+            None => tokio::spawn(task),
+        };
+        let handle = Self {
+            session_id,
+            packets,
+            commands,
+            state,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        (handle, output_rx)
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Queue an RTP packet without waiting, returns false when it was
+    /// dropped
+    pub fn send_packet(&self, data: &[u8]) -> bool {
+        let mut packet = pool::packets().take();
+        packet.extend_from_slice(data);
+        match self.packets.try_send(packet) {
+            Ok(()) => true,
+            Err(e) => {
+                pool::packets().give(e.into_inner());
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// Queue an RTCP packet without waiting, returns false when it was
+    /// dropped
+    pub fn send_rtcp(&self, data: &[u8]) -> bool {
+        self.commands
+            .try_send(PeerCommand::Rtcp(data.to_vec()))
+            .is_ok()
+    }
+
+    /// Get the number of packets dropped on a full queue
+    pub fn packets_dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Get the state the task last published
+    pub fn state(&self) -> PeerState {
+        self.state.borrow().clone()
+    }
+
+    pub async fn set_remote_sdp(&self, sdp: String) -> Result<()> {
+        self.request(|reply| PeerCommand::SetRemoteSdp(sdp, reply))
+            .await?
+    }
+
+    pub async fn create_answer(&self) -> Result<String> {
+        self.request(PeerCommand::CreateAnswer).await?
+    }
+
+    pub async fn add_ice_candidate(&self, candidate: IceCandidate) -> Result<()> {
+        self.request(|reply| PeerCommand::AddIceCandidate(candidate, reply))
+            .await?
+    }
+
+    pub async fn stats(&self) -> Result<PeerStats> {
+        self.request(PeerCommand::Stats).await
+    }
+
+    /// Get the statistics of the connection in the shape of WebRTC getStats
+    pub async fn rtc_stats(&self) -> Result<RtcStatsReport> {
+        self.request(PeerCommand::RtcStats).await
+    }
+
+    pub async fn snapshot(&self) -> Result<PeerSnapshot> {
+        self.request(PeerCommand::Snapshot).await
+    }
+
+    /// Continue from the snapshot of a migrated connection
+    pub async fn restore(&self, snapshot: PeerSnapshot) -> Result<()> {
+        self.commands
+            .send(PeerCommand::Restore(Box::new(snapshot)))
+            .await
+            .map_err(|_| self.stopped())
+    }
+
+    /// Take the state of the ICE transport, returns the change of
+    /// connection state it makes if any
+    pub async fn set_ice_state(&self, ice: IceState) -> Result<Option<StateChange>> {
+        self.request(|reply| PeerCommand::SetIceState(ice, reply))
+            .await
+    }
+
+    /// Take the state of the DTLS transport, returns the change of
+    /// connection state it makes if any
+    pub async fn set_dtls_state(&self, dtls: DtlsState) -> Result<Option<StateChange>> {
+        self.request(|reply| PeerCommand::SetDtlsState(dtls, reply))
+            .await
+    }
+
+    /// Start connectivity checks, anew when the connection was up before
+    pub async fn start_checks(&self) -> Result<Option<StateChange>> {
+        self.request(PeerCommand::StartChecks).await
+    }
+
+    /// Close the connection for good, without waiting
+    pub fn close(&self) {
+        if self.commands.try_send(PeerCommand::Close).is_err() {
+            tracing::debug!(
+                "Peer of session {} is gone or busy closing",
+                self.session_id
+            );
+        }
+    }
+
+    pub async fn clear_buffer(&self) -> Result<()> {
+        self.commands
+            .send(PeerCommand::ClearBuffer)
+            .await
+            .map_err(|_| self.stopped())
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> PeerCommand,
+    ) -> Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| self.stopped())?;
+        response.await.map_err(|_| self.stopped())
+    }
+
+    fn stopped(&self) -> AmwajError {
+        AmwajError::WebRtcError(format!("Peer task of session {} stopped", self.session_id))
+    }
+}
+
+async fn run(
+    mut peer: PeerConnection,
+    mut packets: mpsc::Receiver<Vec<u8>>,
+    mut commands: mpsc::Receiver<PeerCommand>,
+    output: mpsc::Sender<PeerOutput>,
+    state: watch::Sender<PeerState>,
+) {
+    loop {
+        tokio::select! {
+            biased;
+            Some(command) = commands.recv() => {
+                on_command(&mut peer, command);
+                state.send_replace(PeerState::of(&peer));
+            }
+            packet = packets.recv() => {
+                let Some(packet) = packet else { break };
+                let received = on_packet(&mut peer, &packet);
+                pool::packets().give(packet);
+                state.send_replace(PeerState::of(&peer));
+                for item in received.into_iter().flatten() {
+                    put_out(&output, item).await;
+                }
+                // The rest of a spurt start's flush, a frame at a time
+                let mut flushed = false;
+                while let Some(pcm) = next_frame(&mut peer) {
+                    flushed = true;
+                    put_out(&output, PeerOutput::Frame(pcm)).await;
+                }
+                if flushed {
+                    state.send_replace(PeerState::of(&peer));
+                }
+            }
+        }
+    }
+}
+
+/// Take an RTP packet, returns what to put out
+fn on_packet(peer: &mut PeerConnection, packet: &[u8]) -> [Option<PeerOutput>; 2] {
+    let Ok((header, _)) = RtpHeader::parse(packet) else {
+        tracing::debug!("Dropping malformed RTP of session {}", peer.session_id());
+        return [None, None];
+    };
+    let frame = match peer.on_rtp_packet(packet) {
+        Ok(pcm) => pcm.map(PeerOutput::Frame),
+        Err(e) => {
+            tracing::debug!("Dropping RTP of session {}: {}", peer.session_id(), e);
+            None
+        }
+    };
+    let received = PeerOutput::Packet {
+        sequence_number: header.sequence_number,
+        timestamp: header.timestamp,
+        jitter_ms: peer.jitter_ms(),
+    };
+    [Some(received), frame]
+}
+
+/// Decode the next frame flushed at the start of a talk spurt
+fn next_frame(peer: &mut PeerConnection) -> Option<Vec<i16>> {
+    match peer.next_frame() {
+        Ok(pcm) => pcm,
+        Err(e) => {
+            tracing::debug!("Dropping RTP of session {}: {}", peer.session_id(), e);
+            None
+        }
+    }
+}
+
+async fn put_out(output: &mpsc::Sender<PeerOutput>, item: PeerOutput) {
+    if let Err(mpsc::error::SendError(PeerOutput::Frame(pcm))) = output.send(item).await {
+        pool::pcm().give(pcm);
+    }
+}
+
+fn on_command(peer: &mut PeerConnection, command: PeerCommand) {
+    match command {
+        PeerCommand::Rtcp(data) => {
+            if let Err(e) = peer.on_rtcp_packet(&data) {
+                tracing::debug!("Dropping RTCP of session {}: {}", peer.session_id(), e);
+            }
+        }
+        PeerCommand::SetRemoteSdp(sdp, reply) => {
+            let _ = reply.send(peer.set_remote_sdp(sdp));
+        }
+        PeerCommand::CreateAnswer(reply) => {
+            let _ = reply.send(peer.create_answer());
+        }
+        PeerCommand::AddIceCandidate(candidate, reply) => {
+            let _ = reply.send(peer.add_ice_candidate(candidate));
+        }
+        PeerCommand::Stats(reply) => {
+            let _ = reply.send(PeerStats {
+                packets_processed: peer.packets_processed(),
+                buffer: peer.get_buffer_stats(),
+            });
+        }
+        PeerCommand::RtcStats(reply) => {
+            let _ = reply.send(peer.get_stats());
+        }
+        PeerCommand::Snapshot(reply) => {
+            let _ = reply.send(peer.snapshot());
+        }
+        PeerCommand::Restore(snapshot) => peer.restore(&snapshot),
+        PeerCommand::SetIceState(ice, reply) => {
+            let _ = reply.send(peer.set_ice_state(ice));
+        }
+        PeerCommand::SetDtlsState(dtls, reply) => {
+            let _ = reply.send(peer.set_dtls_state(dtls));
+        }
+        PeerCommand::StartChecks(reply) => {
+            let _ = reply.send(peer.start_checks());
+        }
+        PeerCommand::Close => {
+            peer.close();
+        }
+        PeerCommand::ClearBuffer => peer.clear_buffer(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webrtc::codec::codec_info;
+    use crate::webrtc::sdp::NegotiatedCodec;
+    use crate::webrtc::RtpPacket;
+
+    fn pcmu_packet(sequence_number: u16) -> Vec<u8> {
+        RtpPacket {
+            version: 2,
+            padding: false,
+            extension: false,
+            csrc_count: 0,
+            marker: false,
+            payload_type: 0,
+            sequence_number,
+            timestamp: sequence_number as u32 * 160,
+            ssrc: 1,
+            payload: vec![0xFF; 160],
+        }
+        .serialize()
+    }
+
+    fn pcmu_peer() -> PeerConnection {
+        let mut peer = PeerConnection::new("s1".to_string());
+        peer.set_negotiated_codec(NegotiatedCodec {
+            codec: codec_info("pcmu").unwrap(),
+            payload_type: 0,
+        });
+        peer
+    }
+
+    #[tokio::test]
+    async fn test_packets_decode_on_the_task() {
+        let (task, mut output) = PeerTask::spawn(pcmu_peer(), 8);
+        assert!(task.send_packet(&pcmu_packet(1)));
+
+        let mut frames = 0;
+        while frames == 0 {
+            match output.recv().await.unwrap() {
+                PeerOutput::Packet {
+                    sequence_number, ..
+                } => assert_eq!(sequence_number, 1),
+                PeerOutput::Frame(pcm) => {
+                    assert_eq!(pcm.len(), 160);
+                    pool::pcm().give(pcm);
+                    frames += 1;
+                }
+            }
+        }
+        let stats = task.stats().await.unwrap();
+        assert_eq!(stats.packets_processed, 1);
+        assert_eq!(task.state().packets_processed, 1);
+        assert_eq!(task.state().codec, Some("pcmu"));
+        assert_eq!(task.snapshot().await.unwrap().packets_processed, 1);
+
+        // The task ends with its handle
+        drop(task);
+        while output.recv().await.is_some() {}
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_packets() {
+        let (task, _output) = PeerTask::spawn(pcmu_peer(), 1);
+        // The task doesn't get to run in between, so one packet fits
+        let sent = (0..16)
+            .filter(|&sequence_number| task.send_packet(&pcmu_packet(sequence_number)))
+            .count();
+        assert_eq!((sent, task.packets_dropped()), (1, 15));
+    }
+}
@@ -0,0 +1,171 @@
+//! Recycled buffers for the packet path
+//!
+//! Every RTP packet needs a copy of its payload for the jitter buffer and a
+//! buffer for the PCM it decodes to. Both come from process-wide freelists
+//! and go back once used, so sessions in steady state allocate nothing per
+//! packet. A pool starts empty and keeps up to its capacity of returned
+//! buffers; `allocated` still rising after warm-up, or buffers `discarded`,
+//! mean the capacity is too small for the load.
+
+use crate::webrtc::media_clock::MAX_INSERTED_SAMPLES;
+use crossbeam_channel::{Receiver, Sender};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+/// Buffers kept per pool by default
+pub const DEFAULT_POOL_CAPACITY: usize = 4096;
+
+/// Room of a packet buffer, a full datagram
+const PACKET_BUFFER_BYTES: usize = 1500;
+
+/// Room of a PCM buffer, 20 ms at 48 kHz and what drift compensation adds
+const PCM_BUFFER_SAMPLES: usize = 960 + MAX_INSERTED_SAMPLES;
+
+/// Pool label of the RTP payload buffers
+pub const POOL_PACKETS: &str = "packets";
+
+/// Pool label of the decoded PCM buffers
+pub const POOL_PCM: &str = "pcm";
+
+/// Counters of a pool since it was created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub name: &'static str,
+    pub capacity: usize,
+    /// Buffers waiting to be reused
+    pub idle: usize,
+    /// Buffers taken that had to be allocated
+    pub allocated: u64,
+    pub reused: u64,
+    /// Buffers given back to a full pool and freed
+    pub discarded: u64,
+}
+
+/// Freelist of vectors, keeping their capacity between uses
+pub struct BufferPool<T> {
+    name: &'static str,
+    capacity: usize,
+    buffer_capacity: usize,
+    free: (Sender<Vec<T>>, Receiver<Vec<T>>),
+    allocated: AtomicU64,
+    reused: AtomicU64,
+    discarded: AtomicU64,
+}
+
+impl<T> BufferPool<T> {
+    /// Create a pool keeping up to `capacity` buffers of `buffer_capacity`
+    /// elements; none are kept when `capacity` is 0
+    pub fn new(name: &'static str, capacity: usize, buffer_capacity: usize) -> Self {
+        Self {
+            name,
+            capacity,
+            buffer_capacity,
+            free: crossbeam_channel::bounded(capacity),
+            allocated: AtomicU64::new(0),
+            reused: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+        }
+    }
+
+    /// Take an empty buffer, reused when one is idle
+    pub fn take(&self) -> Vec<T> {
+        match self.free.1.try_recv() {
+            Ok(buffer) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                buffer
+            }
+            Err(_) => {
+                self.allocated.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(self.buffer_capacity)
+            }
+        }
+    }
+
+    /// Give a buffer back for reuse
+    pub fn give(&self, mut buffer: Vec<T>) {
+        if buffer.capacity() == 0 {
+            return;
+        }
+        buffer.clear();
+        if self.free.0.try_send(buffer).is_err() {
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            name: self.name,
+            capacity: self.capacity,
+            idle: self.free.1.len(),
+            allocated: self.allocated.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static CAPACITY: OnceLock<usize> = OnceLock::new();
+static PACKETS: OnceLock<BufferPool<u8>> = OnceLock::new();
+static PCM: OnceLock<BufferPool<i16>> = OnceLock::new();
+
+/// Set the capacity of the pools, returns false once they are in use
+pub fn configure(capacity: usize) -> bool {
+    CAPACITY.set(capacity).is_ok()
+}
+
+fn capacity() -> usize {
+    *CAPACITY.get_or_init(|| DEFAULT_POOL_CAPACITY)
+}
+
+/// Get the pool of RTP payload buffers
+pub fn packets() -> &'static BufferPool<u8> {
+    PACKETS.get_or_init(|| BufferPool::new(POOL_PACKETS, capacity(), PACKET_BUFFER_BYTES))
+}
+
+/// Get the pool of decoded PCM buffers
+pub fn pcm() -> &'static BufferPool<i16> {
+    PCM.get_or_init(|| BufferPool::new(POOL_PCM, capacity(), PCM_BUFFER_SAMPLES))
+}
+
+/// Get the counters of every pool
+pub fn stats() -> [PoolStats; 2] {
+    [packets().stats(), pcm().stats()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = BufferPool::<u8>::new("test", 2, 16);
+        let mut first = pool.take();
+        first.extend_from_slice(&[1, 2, 3]);
+        let address = first.as_ptr();
+        pool.give(first);
+
+        // Same allocation, emptied
+        let again = pool.take();
+        assert_eq!((again.as_ptr(), again.len()), (address, 0));
+        pool.give(again);
+        let stats = pool.stats();
+        assert_eq!((stats.allocated, stats.reused, stats.idle), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_full_pool_discards() {
+        let pool = BufferPool::<i16>::new("test", 1, 4);
+        let buffers = [pool.take(), pool.take()];
+        for buffer in buffers {
+            pool.give(buffer);
+        }
+        // Buffers without room aren't worth keeping
+        pool.give(Vec::new());
+        let stats = pool.stats();
+        assert_eq!((stats.allocated, stats.idle, stats.discarded), (2, 1, 1));
+
+        let disabled = BufferPool::<u8>::new("off", 0, 4);
+        disabled.give(disabled.take());
+        assert_eq!(disabled.stats().discarded, 1);
+    }
+}
@@ -0,0 +1,379 @@
+//! Call quality of WebRTC sessions
+//!
+//! Interarrival jitter is computed from the RTP timestamps as in RFC 3550
+//! A.8, the round-trip time from the LSR/DLSR fields of RTCP reports, and
+//! both are folded with the packet loss into a MOS estimate with the
+//! simplified E-model of ITU-T G.107.
+
+use crate::error::{AmwajError, Result};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// RTP clock rate of Opus, whatever the decoded sample rate
+pub const OPUS_CLOCK_RATE: u32 = 48000;
+
+/// RTCP sender report packet type
+const RTCP_SR: u8 = 200;
+/// RTCP receiver report packet type
+const RTCP_RR: u8 = 201;
+/// Bytes of a report block
+const REPORT_BLOCK_BYTES: usize = 24;
+/// Seconds from the NTP epoch (1900) to the Unix epoch
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// Default transmission rating, G.107
+const R0: f64 = 93.2;
+/// Packet loss robustness of the codec with concealment, G.113
+const LOSS_ROBUSTNESS: f64 = 25.1;
+/// Delay added by packetization and decoding, in ms
+const CODEC_DELAY_MS: f64 = 20.0;
+
+/// Quality of a session's inbound audio
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct QualityStats {
+    pub jitter_ms: f32,
+    pub packet_loss_ratio: f32,
+    /// Unknown until the peer reports on a sender report of ours
+    pub rtt_ms: Option<f32>,
+    /// Estimated mean opinion score, 1 to 4.5
+    pub mos: f32,
+}
+
+/// A report block of an RTCP SR or RR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportBlock {
+    pub ssrc: u32,
+    /// Fraction lost since the last report, out of 256
+    pub fraction_lost: u8,
+    pub cumulative_lost: u32,
+    pub highest_sequence: u32,
+    /// Interarrival jitter in RTP timestamp units
+    pub jitter: u32,
+    /// Middle 32 bits of the NTP time of the last sender report
+    pub last_sr: u32,
+    /// Delay since the last sender report, in 1/65536 s
+    pub delay_since_last_sr: u32,
+}
+
+/// Sender info of an RTCP sender report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SenderInfo {
+    pub ssrc: u32,
+    /// NTP time the report was sent at, 32.32 fixed point
+    pub ntp_timestamp: u64,
+    /// RTP timestamp of the same instant
+    pub rtp_timestamp: u32,
+    pub packet_count: u32,
+    pub octet_count: u32,
+}
+
+impl SenderInfo {
+    /// Get the time the report was sent at, on the sender's clock
+    pub fn sent_at(&self) -> SystemTime {
+        let secs = (self.ntp_timestamp >> 32).saturating_sub(NTP_UNIX_OFFSET_SECS);
+        let nanos = ((self.ntp_timestamp & 0xFFFF_FFFF) * 1_000_000_000) >> 32;
+        UNIX_EPOCH + Duration::new(secs, nanos as u32)
+    }
+}
+
+/// Split a compound RTCP packet into its packets
+fn split_compound(data: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut packets = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let packet = &data[offset..];
+        if packet.len() < 4 {
+            return Err(AmwajError::RtpParseError(format!(
+                "RTCP packet too short: {} bytes",
+                packet.len()
+            )));
+        }
+        let version = packet[0] >> 6;
+        if version != 2 {
+            return Err(AmwajError::RtpParseError(format!(
+                "invalid RTCP version {}",
+                version
+            )));
+        }
+        let length = (u16::from_be_bytes([packet[2], packet[3]]) as usize + 1) * 4;
+        if packet.len() < length {
+            return Err(AmwajError::RtpParseError("RTCP packet truncated".into()));
+        }
+        packets.push(&packet[..length]);
+        offset += length;
+    }
+    Ok(packets)
+}
+
+/// Parse the sender info of a compound RTCP packet, if it has a sender
+/// report
+pub fn parse_sender_info(data: &[u8]) -> Result<Option<SenderInfo>> {
+    for packet in split_compound(data)? {
+        if packet[1] != RTCP_SR {
+            continue;
+        }
+        if packet.len() < 28 {
+            return Err(AmwajError::RtpParseError(
+                "RTCP sender info truncated".into(),
+            ));
+        }
+        let word =
+            |i: usize| u32::from_be_bytes([packet[i], packet[i + 1], packet[i + 2], packet[i + 3]]);
+        return Ok(Some(SenderInfo {
+            ssrc: word(4),
+            ntp_timestamp: (u64::from(word(8)) << 32) | u64::from(word(12)),
+            rtp_timestamp: word(16),
+            packet_count: word(20),
+            octet_count: word(24),
+        }));
+    }
+    Ok(None)
+}
+
+/// Parse the report blocks of a compound RTCP packet
+///
+/// Packets other than sender and receiver reports are skipped.
+pub fn parse_report_blocks(data: &[u8]) -> Result<Vec<ReportBlock>> {
+    let mut blocks = Vec::new();
+    for packet in split_compound(data)? {
+        let count = (packet[0] & 0x1F) as usize;
+        let packet_type = packet[1];
+        let length = packet.len();
+
+        let blocks_start = match packet_type {
+            RTCP_SR => Some(28),
+            RTCP_RR => Some(8),
+            _ => None,
+        };
+        if let Some(start) = blocks_start {
+            if start + count * REPORT_BLOCK_BYTES > length {
+                return Err(AmwajError::RtpParseError(
+                    "RTCP report blocks truncated".into(),
+                ));
+            }
+            for block in packet[start..].chunks_exact(REPORT_BLOCK_BYTES).take(count) {
+                let word = |i: usize| {
+                    u32::from_be_bytes([block[i], block[i + 1], block[i + 2], block[i + 3]])
+                };
+                blocks.push(ReportBlock {
+                    ssrc: word(0),
+                    fraction_lost: block[4],
+                    cumulative_lost: word(4) & 0x00FF_FFFF,
+                    highest_sequence: word(8),
+                    jitter: word(12),
+                    last_sr: word(16),
+                    delay_since_last_sr: word(20),
+                });
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+/// Get the middle 32 bits of the current NTP time, as used in LSR
+pub fn ntp_middle_32(now: SystemTime) -> u32 {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() + NTP_UNIX_OFFSET_SECS;
+    let fraction = ((since_epoch.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    (((secs & 0xFFFF) << 16) | (fraction >> 16)) as u32
+}
+
+/// Estimate the MOS of a call with the simplified E-model
+///
+/// `delay_ms` is the one-way mouth-to-ear delay.
+pub fn estimate_mos(delay_ms: f64, packet_loss_ratio: f64) -> f64 {
+    let delay_impairment = 0.024 * delay_ms + 0.11 * (delay_ms - 177.3).max(0.0);
+    let loss_percent = packet_loss_ratio.clamp(0.0, 1.0) * 100.0;
+    let loss_impairment = 95.0 * loss_percent / (loss_percent + LOSS_ROBUSTNESS);
+    let r = (R0 - delay_impairment - loss_impairment).clamp(0.0, 100.0);
+    1.0 + 0.035 * r + 7e-6 * r * (r - 60.0) * (100.0 - r)
+}
+
+/// Tracks the jitter and round-trip time of one inbound stream
+#[derive(Debug, Clone)]
+pub struct QualityMonitor {
+    clock_rate: u32,
+    started: Instant,
+    /// Last relative transit time, in RTP timestamp units
+    last_transit: Option<u32>,
+    /// Smoothed jitter, in RTP timestamp units
+    jitter: f64,
+    rtt_ms: Option<f64>,
+}
+
+impl QualityMonitor {
+    /// Monitor a stream with an RTP clock of `clock_rate` Hz
+    pub fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate: clock_rate.max(1),
+            started: Instant::now(),
+            last_transit: None,
+            jitter: 0.0,
+            rtt_ms: None,
+        }
+    }
+
+    /// Account for an RTP packet arriving now
+    pub fn on_rtp(&mut self, rtp_timestamp: u32) {
+        self.on_rtp_at(rtp_timestamp, Instant::now());
+    }
+
+    /// Account for an RTP packet that arrived at `arrival`
+    pub fn on_rtp_at(&mut self, rtp_timestamp: u32, arrival: Instant) {
+        let elapsed = arrival.saturating_duration_since(self.started);
+        let arrival_units = (elapsed.as_secs_f64() * self.clock_rate as f64) as u64 as u32;
+        let transit = arrival_units.wrapping_sub(rtp_timestamp);
+        if let Some(last_transit) = self.last_transit {
+            let d = (transit.wrapping_sub(last_transit) as i32).unsigned_abs() as f64;
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+    }
+
+    /// Start the transit times over, at a talk spurt, so the silence
+    /// before it isn't taken for jitter
+    pub fn restart_transit(&mut self) {
+        self.last_transit = None;
+    }
+
+    /// Take the round-trip time from report blocks received at `now`
+    ///
+    /// Blocks that don't refer to a sender report carry no round trip.
+    pub fn on_report_blocks(&mut self, blocks: &[ReportBlock], now: SystemTime) {
+        let arrival = ntp_middle_32(now);
+        for block in blocks.iter().filter(|block| block.last_sr != 0) {
+            let rtt = arrival
+                .wrapping_sub(block.last_sr)
+                .wrapping_sub(block.delay_since_last_sr);
+            // A negative round trip wraps around, clocks went backwards
+            if (rtt as i32) >= 0 {
+                self.rtt_ms = Some(rtt as f64 * 1000.0 / 65536.0);
+            }
+        }
+    }
+
+    /// Get the interarrival jitter in ms
+    pub fn jitter_ms(&self) -> f64 {
+        self.jitter * 1000.0 / self.clock_rate as f64
+    }
+
+    /// Get the latest round-trip time in ms
+    pub fn rtt_ms(&self) -> Option<f64> {
+        self.rtt_ms
+    }
+
+    /// Get the quality of the stream given its packet loss
+    ///
+    /// The one-way delay is half the round trip plus a jitter buffer twice
+    /// the jitter deep and the codec delay.
+    pub fn stats(&self, packet_loss_ratio: f32) -> QualityStats {
+        let jitter_ms = self.jitter_ms();
+        let delay_ms = self.rtt_ms.unwrap_or(0.0) / 2.0 + 2.0 * jitter_ms + CODEC_DELAY_MS;
+        QualityStats {
+            jitter_ms: jitter_ms as f32,
+            packet_loss_ratio,
+            rtt_ms: self.rtt_ms.map(|rtt| rtt as f32),
+            mos: estimate_mos(delay_ms, packet_loss_ratio as f64) as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receiver_report(blocks: &[(u32, u32)]) -> Vec<u8> {
+        let length = (8 + blocks.len() * REPORT_BLOCK_BYTES) / 4 - 1;
+        let mut data = vec![0x80 | blocks.len() as u8, RTCP_RR];
+        data.extend((length as u16).to_be_bytes());
+        data.extend(0x1234u32.to_be_bytes());
+        for (last_sr, delay) in blocks {
+            data.extend(0x5678u32.to_be_bytes());
+            data.extend([12, 0, 0, 3]);
+            data.extend(100u32.to_be_bytes());
+            data.extend(480u32.to_be_bytes());
+            data.extend(last_sr.to_be_bytes());
+            data.extend(delay.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_report_blocks() {
+        let mut compound = receiver_report(&[(7, 9)]);
+        // An SDES packet after the report is skipped
+        compound.extend([0x81, 202, 0, 1, 0, 0, 0x12, 0x34]);
+        let blocks = parse_report_blocks(&compound).unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].ssrc, 0x5678);
+        assert_eq!(blocks[0].fraction_lost, 12);
+        assert_eq!(blocks[0].cumulative_lost, 3);
+        assert_eq!(blocks[0].jitter, 480);
+        assert_eq!((blocks[0].last_sr, blocks[0].delay_since_last_sr), (7, 9));
+
+        assert!(parse_report_blocks(&compound[..10]).is_err());
+        assert!(parse_report_blocks(&[0x40, RTCP_RR, 0, 1, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_parse_sender_info() {
+        let mut compound = vec![0x80, RTCP_SR, 0, 6];
+        for word in [0x1234u32, 3_913_056_000, 0x8000_0000, 48000, 50, 8000] {
+            compound.extend(word.to_be_bytes());
+        }
+        compound.extend(receiver_report(&[]));
+        let info = parse_sender_info(&compound).unwrap().unwrap();
+        assert_eq!(info.ssrc, 0x1234);
+        assert_eq!(
+            (info.rtp_timestamp, info.packet_count, info.octet_count),
+            (48000, 50, 8000)
+        );
+        // 2024-01-01 00:00:00.5 UTC
+        let sent = info.sent_at().duration_since(UNIX_EPOCH).unwrap();
+        assert_eq!(sent, Duration::from_millis(1_704_067_200_500));
+
+        assert_eq!(parse_sender_info(&receiver_report(&[])).unwrap(), None);
+        assert!(parse_sender_info(&[0x80, RTCP_SR, 0, 1, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut monitor = QualityMonitor::new(OPUS_CLOCK_RATE);
+        let start = monitor.started;
+        // 20 ms packets arriving every 20 ms have no jitter
+        for i in 0..10u32 {
+            monitor.on_rtp_at(i * 960, start + Duration::from_millis(i as u64 * 20));
+        }
+        assert!(monitor.jitter_ms() < 0.1);
+
+        // Alternating 10 ms early and late
+        for i in 10..200u32 {
+            let skew = if i % 2 == 0 { 10 } else { 0 };
+            monitor.on_rtp_at(i * 960, start + Duration::from_millis(i as u64 * 20 + skew));
+        }
+        let jitter = monitor.jitter_ms();
+        assert!(jitter > 9.0 && jitter < 11.0, "{}", jitter);
+    }
+
+    #[test]
+    fn test_rtt_from_report_blocks() {
+        let mut monitor = QualityMonitor::new(OPUS_CLOCK_RATE);
+        let now = SystemTime::now();
+        let sent = ntp_middle_32(now - Duration::from_millis(1100));
+        // The peer held the report for a second, so 100 ms round trip
+        let blocks = parse_report_blocks(&receiver_report(&[(0, 0), (sent, 65536)])).unwrap();
+        monitor.on_report_blocks(&blocks, now);
+        let rtt = monitor.rtt_ms().unwrap();
+        assert!((rtt - 100.0).abs() < 1.0, "{}", rtt);
+    }
+
+    #[test]
+    fn test_estimate_mos() {
+        let clean = estimate_mos(20.0, 0.0);
+        assert!(clean > 4.3 && clean <= 4.5, "{}", clean);
+        let lossy = estimate_mos(20.0, 0.05);
+        assert!(lossy > 3.5 && lossy < clean - 0.3, "{}", lossy);
+        let late = estimate_mos(400.0, 0.0);
+        assert!(late < 3.5, "{}", late);
+        assert!(estimate_mos(2000.0, 1.0) >= 1.0);
+    }
+}
@@ -1,5 +1,19 @@
 //! RTP Packet Handler
 
+use crate::error::{AmwajError, Result};
+use std::ops::RangeInclusive;
+
+/// Packet types of RTCP, as the second byte of a packet reads
+pub const RTCP_PACKET_TYPES: RangeInclusive<u8> = 192..=223;
+
+/// RTP payload types that would read as RTCP with RTCP multiplexed (RFC 5761)
+pub const RTCP_MUX_PAYLOAD_TYPES: RangeInclusive<u8> = 64..=95;
+
+/// Tell RTCP from RTP sharing a port, by the packet type
+pub fn is_rtcp(data: &[u8]) -> bool {
+    data.len() >= 2 && RTCP_PACKET_TYPES.contains(&data[1])
+}
+
 /// RTP Packet structure according to RFC 3550
 #[derive(Debug, Clone)]
 pub struct RtpPacket {
@@ -15,50 +29,75 @@ pub struct RtpPacket {
     pub payload: Vec<u8>,
 }
 
-impl RtpPacket {
-    /// Parse an RTP packet from raw bytes
-    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+/// Header fields of an RTP packet, parsed without copying its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtpHeader {
+    pub version: u8,
+    pub padding: bool,
+    pub extension: bool,
+    pub csrc_count: u8,
+    pub marker: bool,
+    pub payload_type: u8,
+    pub sequence_number: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+}
+
+impl RtpHeader {
+    /// Parse the header of an RTP packet, returns it with the payload
+    pub fn parse(data: &[u8]) -> Result<(Self, &[u8])> {
         if data.len() < 12 {
-            return Err(anyhow::anyhow!(
-                "RTP packet too short: {} bytes",
+            return Err(AmwajError::RtpParseError(format!(
+                "packet too short: {} bytes",
                 data.len()
-            ));
+            )));
         }
 
         let version = (data[0] >> 6) & 0x3;
         if version != 2 {
-            return Err(anyhow::anyhow!("Invalid RTP version: {}", version));
+            return Err(AmwajError::RtpParseError(format!(
+                "invalid version {}",
+                version
+            )));
         }
 
-        let padding = (data[0] & 0x20) != 0;
-        let extension = (data[0] & 0x10) != 0;
         let csrc_count = data[0] & 0xF;
-        let marker = (data[1] & 0x80) != 0;
-        let payload_type = data[1] & 0x7F;
-        let sequence_number = u16::from_be_bytes([data[2], data[3]]);
-        let timestamp = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
-        let ssrc = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
-
         let header_size = 12 + (csrc_count as usize * 4);
 
         if data.len() < header_size {
-            return Err(anyhow::anyhow!("RTP packet header incomplete"));
+            return Err(AmwajError::RtpParseError("header incomplete".into()));
         }
 
-        let payload_start = header_size;
-        let payload = data[payload_start..].to_vec();
-
-        Ok(Self {
+        let header = Self {
             version,
-            padding,
-            extension,
+            padding: (data[0] & 0x20) != 0,
+            extension: (data[0] & 0x10) != 0,
             csrc_count,
-            marker,
-            payload_type,
-            sequence_number,
-            timestamp,
-            ssrc,
-            payload,
+            marker: (data[1] & 0x80) != 0,
+            payload_type: data[1] & 0x7F,
+            sequence_number: u16::from_be_bytes([data[2], data[3]]),
+            timestamp: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ssrc: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+        };
+        Ok((header, &data[header_size..]))
+    }
+}
+
+impl RtpPacket {
+    /// Parse an RTP packet from raw bytes
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let (header, payload) = RtpHeader::parse(data)?;
+        Ok(Self {
+            version: header.version,
+            padding: header.padding,
+            extension: header.extension,
+            csrc_count: header.csrc_count,
+            marker: header.marker,
+            payload_type: header.payload_type,
+            sequence_number: header.sequence_number,
+            timestamp: header.timestamp,
+            ssrc: header.ssrc,
+            payload: payload.to_vec(),
         })
     }
 
@@ -118,10 +157,22 @@ mod tests {
         assert_eq!(packet.payload.len(), 4);
     }
 
+    #[test]
+    fn test_demux_rtcp() {
+        // Receiver report, then PCMU with and without the marker bit
+        assert!(is_rtcp(&[0x81, 201, 0, 7]));
+        assert!(!is_rtcp(&[0x80, 0x00]));
+        assert!(!is_rtcp(&[0x80, 0x80]));
+        assert!(!is_rtcp(&[0x80]));
+    }
+
     #[test]
     fn test_parse_too_short() {
         let data = vec![0x80, 0x78, 0x00];
-        assert!(RtpPacket::parse(&data).is_err());
+        assert!(matches!(
+            RtpPacket::parse(&data),
+            Err(AmwajError::RtpParseError(_))
+        ));
     }
 
     #[test]
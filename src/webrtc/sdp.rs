@@ -0,0 +1,464 @@
+//! SDP Codec Negotiation
+//!
+//! The answer to an offer carries one audio codec: the most preferred one
+//! of `[audio.codecs]` that the offer lists, on the payload type the offer
+//! gave it. Offers that list a payload type without an `a=rtpmap` line are
+//! matched on the configured payload types.
+//!
+//! RTCP is taken on the RTP port (RFC 5761). Answers say so with
+//! `a=rtcp-mux` when the offer does, and with `a=rtcp-mux-only` too when the
+//! offer can't do without it (RFC 8858), as browsers offer. Payload types 64
+//! to 95 read as RTCP then, so they aren't negotiated.
+//!
+//! Answers mirror the media sections of the offer in order, each with its
+//! `a=mid`, and turn down all but the audio one with port 0. When the offer
+//! bundles the audio section (`a=group:BUNDLE`), the answer groups it too,
+//! so everything bundled runs over the audio transport (RFC 8843).
+
+use crate::config::CodecsConfig;
+use crate::error::{AmwajError, Result};
+use crate::webrtc::codec::{codec_info, CodecInfo, CODEC_OPUS};
+use crate::webrtc::rtp_handler::RTCP_MUX_PAYLOAD_TYPES;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Codec agreed on with a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiatedCodec {
+    pub codec: &'static CodecInfo,
+    pub payload_type: u8,
+}
+
+/// Whether RTCP shares the port of RTP
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RtcpMux {
+    /// RTCP goes to the next port up
+    #[default]
+    Separate,
+    Mux,
+    /// Multiplexed, with no fallback to a separate port
+    MuxOnly,
+}
+
+/// Payload types of the audio section of an offer, with their rtpmap
+struct AudioOffer {
+    payload_types: Vec<u8>,
+    /// Encoding name and clock rate by payload type
+    rtpmap: HashMap<u8, (String, u32)>,
+    rtcp_mux: RtcpMux,
+}
+
+fn parse_audio_offer(sdp: &str) -> Option<AudioOffer> {
+    let mut offer: Option<AudioOffer> = None;
+    for line in sdp.lines().map(str::trim) {
+        if let Some(media) = line.strip_prefix("m=") {
+            if offer.is_some() {
+                // Only the first audio section is negotiated
+                break;
+            }
+            let mut fields = media.split_whitespace();
+            if fields.next() != Some("audio") {
+                continue;
+            }
+            offer = Some(AudioOffer {
+                payload_types: fields.skip(2).filter_map(|pt| pt.parse().ok()).collect(),
+                rtpmap: HashMap::new(),
+                rtcp_mux: RtcpMux::Separate,
+            });
+        } else if let (Some(offer), "a=rtcp-mux") = (offer.as_mut(), line) {
+            offer.rtcp_mux = offer.rtcp_mux.max(RtcpMux::Mux);
+        } else if let (Some(offer), "a=rtcp-mux-only") = (offer.as_mut(), line) {
+            offer.rtcp_mux = RtcpMux::MuxOnly;
+        } else if let (Some(offer), Some(rtpmap)) = (offer.as_mut(), line.strip_prefix("a=rtpmap:"))
+        {
+            let Some((pt, encoding)) = rtpmap.split_once(' ') else {
+                continue;
+            };
+            let mut encoding = encoding.split('/');
+            let (Ok(pt), Some(name), Some(Ok(clock_rate))) = (
+                pt.parse::<u8>(),
+                encoding.next(),
+                encoding.next().map(str::parse::<u32>),
+            ) else {
+                continue;
+            };
+            offer.rtpmap.insert(pt, (name.to_string(), clock_rate));
+        }
+    }
+    offer
+}
+
+/// Pick the codec to answer an offer with
+///
+/// Fails if the offer has no audio section or none of its codecs is
+/// accepted.
+pub fn negotiate(offer: &str, codecs: &CodecsConfig) -> Result<NegotiatedCodec> {
+    let audio = parse_audio_offer(offer)
+        .ok_or_else(|| AmwajError::WebRtcError("Offer has no audio section".into()))?;
+    for name in &codecs.preferred {
+        let Some(codec) = codec_info(name) else {
+            continue;
+        };
+        let configured = codecs.payload_types.get(codec.name).copied();
+        let matched = audio
+            .payload_types
+            .iter()
+            .copied()
+            .filter(|pt| {
+                audio.rtcp_mux == RtcpMux::Separate || !RTCP_MUX_PAYLOAD_TYPES.contains(pt)
+            })
+            .find(|pt| match audio.rtpmap.get(pt) {
+                Some((encoding_name, clock_rate)) => {
+                    encoding_name.eq_ignore_ascii_case(codec.encoding_name)
+                        && *clock_rate == codec.clock_rate
+                }
+                None => Some(*pt) == codec.static_payload_type || Some(*pt) == configured,
+            });
+        if let Some(payload_type) = matched {
+            return Ok(NegotiatedCodec {
+                codec,
+                payload_type,
+            });
+        }
+    }
+    Err(AmwajError::CodecError(format!(
+        "No codec in common with the offer, accepting {}",
+        codecs.preferred.join(", ")
+    )))
+}
+
+/// Get the most preferred codec on its configured payload type, for
+/// answering without an offer to match
+pub fn preferred(codecs: &CodecsConfig) -> Result<NegotiatedCodec> {
+    codecs
+        .preferred
+        .iter()
+        .find_map(|name| {
+            let codec = codec_info(name)?;
+            let payload_type = codecs
+                .payload_types
+                .get(codec.name)
+                .copied()
+                .or(codec.static_payload_type)?;
+            Some(NegotiatedCodec {
+                codec,
+                payload_type,
+            })
+        })
+        .ok_or_else(|| AmwajError::CodecError("No preferred codec has a payload type".into()))
+}
+
+/// Media section of an offer, as the answer mirrors it
+struct OfferedSection {
+    media: String,
+    proto: String,
+    formats: String,
+    mid: Option<String>,
+}
+
+/// Media sections of an offer, and the mids of its BUNDLE group
+fn parse_sections(offer: &str) -> (Vec<OfferedSection>, Vec<String>) {
+    let mut sections: Vec<OfferedSection> = Vec::new();
+    let mut bundle = Vec::new();
+    for line in offer.lines().map(str::trim) {
+        if let Some(media) = line.strip_prefix("m=") {
+            let mut fields = media.split_whitespace();
+            let (Some(media), Some(_port), Some(proto)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            sections.push(OfferedSection {
+                media: media.to_string(),
+                proto: proto.to_string(),
+                formats: fields.collect::<Vec<_>>().join(" "),
+                mid: None,
+            });
+        } else if let Some(mid) = line.strip_prefix("a=mid:") {
+            if let Some(section) = sections.last_mut() {
+                section.mid = Some(mid.to_string());
+            }
+        } else if let Some(group) = line.strip_prefix("a=group:BUNDLE") {
+            if sections.is_empty() {
+                bundle = group.split_whitespace().map(str::to_string).collect();
+            }
+        }
+    }
+    (sections, bundle)
+}
+
+/// Get how the audio section of an offer multiplexes RTCP
+pub fn rtcp_mux(offer: &str) -> RtcpMux {
+    parse_audio_offer(offer).map_or(RtcpMux::Separate, |audio| audio.rtcp_mux)
+}
+
+/// Get where the audio of an offer is to be sent, from its `c=` and `m=audio` lines
+///
+/// The connection line of the audio section wins over the session's.
+pub fn rtp_address(offer: &str) -> Option<SocketAddr> {
+    let mut session: Option<IpAddr> = None;
+    let mut audio: Option<IpAddr> = None;
+    let mut port = None;
+    // Session level until the first media section, then in it only when audio
+    let mut section = Some(false);
+    for line in offer.lines().map(str::trim) {
+        if let Some(connection) = line.strip_prefix("c=") {
+            let address = connection
+                .split_whitespace()
+                .nth(2)
+                .and_then(|address| address.split('/').next()?.parse().ok());
+            match section {
+                Some(false) => session = address,
+                Some(true) => audio = address,
+                _ => {}
+            }
+        } else if let Some(media) = line.strip_prefix("m=") {
+            if port.is_some() {
+                break;
+            }
+            let mut fields = media.split_whitespace();
+            let is_audio = fields.next() == Some("audio");
+            if is_audio {
+                port = fields.next().and_then(|port| port.parse::<u16>().ok());
+            }
+            section = is_audio.then_some(true);
+        }
+    }
+    Some(SocketAddr::new(audio.or(session)?, port?))
+}
+
+/// Write the SDP answer to `offer` for a negotiated codec
+///
+/// Without an offer, the answer is an audio section alone that takes RTCP
+/// multiplexed only.
+pub fn answer(negotiated: &NegotiatedCodec, codecs: &CodecsConfig, offer: Option<&str>) -> String {
+    write_answer(negotiated, codecs, None, offer)
+}
+
+/// Write the SDP answer to `offer` for a negotiated codec, receiving RTP on
+/// `address`
+pub fn answer_at(
+    negotiated: &NegotiatedCodec,
+    codecs: &CodecsConfig,
+    address: SocketAddr,
+    offer: Option<&str>,
+) -> String {
+    write_answer(negotiated, codecs, Some(address), offer)
+}
+
+fn write_answer(
+    negotiated: &NegotiatedCodec,
+    codecs: &CodecsConfig,
+    address: Option<SocketAddr>,
+    offer: Option<&str>,
+) -> String {
+    let rtcp_mux = offer.map_or(RtcpMux::MuxOnly, rtcp_mux);
+    let (sections, bundle) = offer.map(parse_sections).unwrap_or_default();
+    let audio = sections.iter().position(|section| section.media == "audio");
+    let audio_mid = audio.and_then(|index| sections[index].mid.as_deref());
+    let codec = negotiated.codec;
+    let pt = negotiated.payload_type;
+    let (ip, port) = address.map_or(("127.0.0.1".to_string(), 0), |address| {
+        (address.ip().to_string(), address.port())
+    });
+    let family = if address.is_some_and(|address| address.is_ipv6()) {
+        "IP6"
+    } else {
+        "IP4"
+    };
+    let mut sdp = format!(
+        "v=0\r\n\
+         o=- 0 0 IN {family} {ip}\r\n\
+         s=Amwaj Media Server\r\n",
+    );
+    if address.is_some() {
+        sdp.push_str(&format!("c=IN {} {}\r\n", family, ip));
+    }
+    sdp.push_str("t=0 0\r\n");
+    if let Some(mid) = audio_mid.filter(|mid| bundle.iter().any(|bundled| bundled == mid)) {
+        sdp.push_str(&format!("a=group:BUNDLE {}\r\n", mid));
+    }
+    for section in &sections[..audio.unwrap_or(sections.len())] {
+        reject_section(&mut sdp, section);
+    }
+    sdp.push_str(&format!("m=audio {port} RTP/AVP {pt}\r\n"));
+    if let Some(mid) = audio_mid {
+        sdp.push_str(&format!("a=mid:{}\r\n", mid));
+    }
+    if codec.channels > 1 {
+        sdp.push_str(&format!(
+            "a=rtpmap:{} {}/{}/{}\r\n",
+            pt, codec.encoding_name, codec.clock_rate, codec.channels
+        ));
+    } else {
+        sdp.push_str(&format!(
+            "a=rtpmap:{} {}/{}\r\n",
+            pt, codec.encoding_name, codec.clock_rate
+        ));
+    }
+    if codec.name == CODEC_OPUS {
+        let opus = &codecs.opus;
+        sdp.push_str(&format!(
+            "a=fmtp:{} minptime=10;useinbandfec={};usedtx={};maxaveragebitrate={}\r\n",
+            pt,
+            u8::from(opus.fec),
+            u8::from(opus.dtx),
+            opus.bitrate
+        ));
+    }
+    match rtcp_mux {
+        RtcpMux::Separate => {}
+        RtcpMux::Mux => sdp.push_str("a=rtcp-mux\r\n"),
+        RtcpMux::MuxOnly => sdp.push_str("a=rtcp-mux\r\na=rtcp-mux-only\r\n"),
+    }
+    for section in audio.map_or(&[][..], |index| &sections[index + 1..]) {
+        reject_section(&mut sdp, section);
+    }
+    sdp
+}
+
+/// Turn down a media section of the offer, as sections other than the
+/// audio one are
+fn reject_section(sdp: &mut String, section: &OfferedSection) {
+    sdp.push_str(&format!(
+        "m={} 0 {} {}\r\n",
+        section.media, section.proto, section.formats
+    ));
+    if let Some(mid) = &section.mid {
+        sdp.push_str(&format!("a=mid:{}\r\n", mid));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFER: &str = "v=0\r\n\
+        o=- 1 2 IN IP4 10.0.0.1\r\n\
+        s=-\r\n\
+        t=0 0\r\n\
+        m=audio 9 UDP/TLS/RTP/SAVPF 109 0 8\r\n\
+        a=rtpmap:109 opus/48000/2\r\n\
+        a=rtpmap:0 PCMU/8000\r\n";
+
+    #[test]
+    fn test_negotiate_preference_order() {
+        let mut codecs = CodecsConfig::default();
+        let negotiated = negotiate(OFFER, &codecs).unwrap();
+        assert_eq!(negotiated.codec.name, "opus");
+        // The offer's payload type, not the configured one
+        assert_eq!(negotiated.payload_type, 109);
+
+        codecs.preferred = vec!["pcma".to_string(), "opus".to_string()];
+        let negotiated = negotiate(OFFER, &codecs).unwrap();
+        assert_eq!(negotiated.codec.name, "pcma");
+        assert_eq!(negotiated.payload_type, 8);
+
+        let answer = answer(&negotiated, &codecs, Some(OFFER));
+        assert!(answer.contains("m=audio 0 RTP/AVP 8\r\n"));
+        assert!(answer.contains("a=rtpmap:8 PCMA/8000\r\n"));
+    }
+
+    #[test]
+    fn test_no_common_codec() {
+        let codecs = CodecsConfig {
+            preferred: vec!["pcma".to_string()],
+            ..CodecsConfig::default()
+        };
+        let offer = OFFER.replace(" 109 0 8", " 109 0");
+        assert!(negotiate(&offer, &codecs).is_err());
+        assert!(negotiate("v=0\r\n", &codecs).is_err());
+    }
+
+    #[test]
+    fn test_rtp_address() {
+        let offer = "v=0\r\n\
+            c=IN IP4 10.0.0.1\r\n\
+            m=audio 49170 RTP/AVP 0\r\n";
+        assert_eq!(rtp_address(offer), Some("10.0.0.1:49170".parse().unwrap()));
+        let offer = format!("{}c=IN IP4 10.0.0.2\r\n", offer);
+        assert_eq!(rtp_address(&offer), Some("10.0.0.2:49170".parse().unwrap()));
+        assert_eq!(rtp_address(OFFER), None);
+
+        let codecs = CodecsConfig::default();
+        let negotiated = negotiate(OFFER, &codecs).unwrap();
+        let address = "192.0.2.7:20000".parse().unwrap();
+        let answer = answer_at(&negotiated, &codecs, address, Some(OFFER));
+        assert!(answer.contains("c=IN IP4 192.0.2.7\r\n"));
+        assert!(answer.contains("m=audio 20000 RTP/AVP 109\r\n"));
+    }
+
+    #[test]
+    fn test_opus_answer_parameters() {
+        let mut codecs = CodecsConfig::default();
+        codecs.opus.dtx = false;
+        codecs.opus.bitrate = 32000;
+        let answer = answer(&preferred(&codecs).unwrap(), &codecs, None);
+        assert!(answer.contains("a=rtpmap:111 opus/48000/2\r\n"));
+        assert!(answer.contains("useinbandfec=1;usedtx=0;maxaveragebitrate=32000"));
+    }
+
+    #[test]
+    fn test_rtcp_mux() {
+        let codecs = CodecsConfig::default();
+        assert_eq!(rtcp_mux(OFFER), RtcpMux::Separate);
+        let negotiated = negotiate(OFFER, &codecs).unwrap();
+        assert!(!answer(&negotiated, &codecs, Some(OFFER)).contains("rtcp-mux"));
+
+        let offer = format!("{}a=rtcp-mux\r\n", OFFER);
+        assert_eq!(rtcp_mux(&offer), RtcpMux::Mux);
+        let answer_mux = answer(&negotiated, &codecs, Some(&offer));
+        assert!(answer_mux.ends_with("a=rtcp-mux\r\n"));
+
+        let offer = format!("{}a=rtcp-mux-only\r\na=rtcp-mux\r\n", OFFER);
+        assert_eq!(rtcp_mux(&offer), RtcpMux::MuxOnly);
+        let answer_only = answer(&negotiated, &codecs, Some(&offer));
+        assert!(answer_only.ends_with("a=rtcp-mux\r\na=rtcp-mux-only\r\n"));
+
+        // Opus on 72 would read as RTCP
+        let codecs = CodecsConfig {
+            preferred: vec!["opus".to_string(), "pcmu".to_string()],
+            ..codecs
+        };
+        let offer = offer.replace("109", "72");
+        assert_eq!(negotiate(&offer, &codecs).unwrap().codec.name, "pcmu");
+        let offer = offer.replace("a=rtcp-mux-only\r\na=rtcp-mux\r\n", "");
+        assert_eq!(negotiate(&offer, &codecs).unwrap().payload_type, 72);
+    }
+
+    #[test]
+    fn test_bundle() {
+        // As Chrome offers with max-bundle
+        let offer = "v=0\r\n\
+            o=- 1 2 IN IP4 127.0.0.1\r\n\
+            s=-\r\n\
+            t=0 0\r\n\
+            a=group:BUNDLE 0 1\r\n\
+            m=audio 9 UDP/TLS/RTP/SAVPF 111 0\r\n\
+            a=mid:0\r\n\
+            a=rtcp-mux\r\n\
+            a=rtpmap:111 opus/48000/2\r\n\
+            m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\n\
+            a=mid:1\r\n";
+        let codecs = CodecsConfig::default();
+        let negotiated = negotiate(offer, &codecs).unwrap();
+        let answer = answer(&negotiated, &codecs, Some(offer));
+        assert!(
+            answer.contains("t=0 0\r\na=group:BUNDLE 0\r\nm=audio 0 RTP/AVP 111\r\na=mid:0\r\n")
+        );
+        assert!(answer.ends_with("m=application 0 UDP/DTLS/SCTP webrtc-datachannel\r\na=mid:1\r\n"));
+
+        // Sections keep the order of the offer, audio unbundled
+        let offer = offer
+            .replace("a=group:BUNDLE 0 1\r\n", "")
+            .replace("m=audio 9 UDP/TLS/RTP/SAVPF 111 0\r\n", "")
+            .replace(
+                "a=mid:1\r\n",
+                "a=mid:1\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111 0\r\n",
+            );
+        let answer = super::answer(&negotiated, &codecs, Some(&offer));
+        assert!(!answer.contains("BUNDLE"));
+        let application = answer.find("m=application").unwrap();
+        assert!(application < answer.find("m=audio").unwrap());
+    }
+}
@@ -0,0 +1,188 @@
+//! Statistics of a peer connection in the shape of WebRTC getStats
+//!
+//! The report follows `RTCStatsReport` of the W3C webrtc-stats spec: stats
+//! objects keyed by their `id`, each with a `type` and a `timestamp` in ms
+//! since the Unix epoch, and fields in camelCase. The server only receives,
+//! so a report has the inbound stream, what its sender last told about it
+//! in an RTCP sender report, and the candidate pair it arrives over.
+//! Dashboards and diagnostics written against a browser's `getStats()` read
+//! it the same way.
+
+use crate::webrtc::connection_state::IceState;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Id of the one candidate pair of a connection
+pub const CANDIDATE_PAIR_ID: &str = "candidate-pair";
+
+/// A stats object, tagged with its `type`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum RtcStats {
+    InboundRtp(InboundRtpStats),
+    RemoteOutboundRtp(RemoteOutboundRtpStats),
+    CandidatePair(CandidatePairStats),
+}
+
+impl RtcStats {
+    pub fn id(&self) -> &str {
+        match self {
+            RtcStats::InboundRtp(stats) => &stats.id,
+            RtcStats::RemoteOutboundRtp(stats) => &stats.id,
+            RtcStats::CandidatePair(stats) => &stats.id,
+        }
+    }
+}
+
+/// `RTCInboundRtpStreamStats` of the received audio
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InboundRtpStats {
+    pub id: String,
+    pub timestamp: f64,
+    pub ssrc: u32,
+    pub kind: &'static str,
+    /// Id of the remote-outbound-rtp of the stream, once a sender report came
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_id: Option<String>,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+    /// Packets dropped unplayed because the jitter buffer was full
+    pub packets_discarded: u64,
+    pub bytes_received: u64,
+    pub header_bytes_received: u64,
+    /// Interarrival jitter in seconds
+    pub jitter: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_packet_received_timestamp: Option<f64>,
+    pub total_samples_received: u64,
+    pub jitter_buffer_emitted_count: u64,
+    /// Samples repeated to make up for a slow sender clock
+    pub inserted_samples_for_deceleration: u64,
+    /// Samples dropped to make up for a fast sender clock
+    pub removed_samples_for_acceleration: u64,
+}
+
+/// `RTCRemoteOutboundRtpStreamStats`, from the latest RTCP sender report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteOutboundRtpStats {
+    pub id: String,
+    /// When the sender report was received
+    pub timestamp: f64,
+    pub ssrc: u32,
+    pub kind: &'static str,
+    /// Id of the inbound-rtp the report is about
+    pub local_id: String,
+    /// When the sender report was sent, on the sender's clock
+    pub remote_timestamp: f64,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub reports_sent: u64,
+}
+
+/// `RTCIceCandidatePairStats` of the pair media arrives over
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidatePairStats {
+    pub id: String,
+    pub timestamp: f64,
+    pub state: &'static str,
+    pub nominated: bool,
+    /// RTP and RTCP packets
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_packet_received_timestamp: Option<f64>,
+    /// Round-trip time in seconds, once the peer reported on a sender report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_round_trip_time: Option<f64>,
+}
+
+/// Stats objects of a peer connection, serialized as a map keyed by id
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct RtcStatsReport {
+    stats: BTreeMap<String, RtcStats>,
+}
+
+impl RtcStatsReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a stats object, replacing any with the same id
+    pub fn insert(&mut self, stats: RtcStats) {
+        self.stats.insert(stats.id().to_string(), stats);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&RtcStats> {
+        self.stats.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RtcStats> {
+        self.stats.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.is_empty()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// Get a time as a `DOMHighResTimeStamp`, ms since the Unix epoch
+pub fn timestamp(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        * 1000.0
+}
+
+/// Get the `RTCStatsIceCandidatePairState` of the pair in use given the
+/// ICE state
+pub fn candidate_pair_state(ice: IceState) -> &'static str {
+    match ice {
+        IceState::New => "frozen",
+        IceState::Checking => "in-progress",
+        IceState::Connected | IceState::Completed | IceState::Disconnected => "succeeded",
+        IceState::Failed | IceState::Closed => "failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_report_serializes_like_get_stats() {
+        let mut report = RtcStatsReport::new();
+        report.insert(RtcStats::CandidatePair(CandidatePairStats {
+            id: CANDIDATE_PAIR_ID.to_string(),
+            timestamp: timestamp(UNIX_EPOCH + Duration::from_millis(1500)),
+            state: candidate_pair_state(IceState::Completed),
+            nominated: true,
+            packets_received: 3,
+            bytes_received: 480,
+            last_packet_received_timestamp: None,
+            current_round_trip_time: Some(0.1),
+        }));
+        let json = report.to_json();
+        let pair = &json[CANDIDATE_PAIR_ID];
+        assert_eq!(pair["type"], "candidate-pair");
+        assert_eq!(pair["id"], CANDIDATE_PAIR_ID);
+        assert_eq!(pair["timestamp"], 1500.0);
+        assert_eq!(pair["state"], "succeeded");
+        assert_eq!(pair["bytesReceived"], 480);
+        assert_eq!(pair["currentRoundTripTime"], 0.1);
+        assert!(pair.get("lastPacketReceivedTimestamp").is_none());
+    }
+}
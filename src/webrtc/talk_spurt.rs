@@ -0,0 +1,112 @@
+//! Talk spurts of an inbound stream
+//!
+//! Senders with DTX stop sending during silence. The first packet after a
+//! pause starts a talk spurt: senders flag it with the marker bit (RFC 3551
+//! 4.1), and those that don't give it away by its timestamp jumping ahead
+//! of its sequence number. A spurt start is where the receiver can drop
+//! the delay it built up without cutting into speech, and where the
+//! silence must not be taken for jitter.
+
+use crate::webrtc::rtp_handler::RtpHeader;
+
+/// Timestamp advance of one packet that reads as a pause, in packets
+const PAUSE_PACKETS: u32 = 3;
+
+/// Tells the packets starting a talk spurt
+#[derive(Debug, Clone, Default)]
+pub struct TalkSpurtDetector {
+    /// Sequence number and timestamp of the latest packet in order
+    last: Option<(u16, u32)>,
+    /// Timestamp advance of a packet, once two packets in a row were seen
+    packet_duration: Option<u32>,
+    talk_spurts: u64,
+}
+
+impl TalkSpurtDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a packet, returns whether it starts a talk spurt
+    ///
+    /// Packets older than the latest are never a start.
+    pub fn on_packet(&mut self, header: &RtpHeader) -> bool {
+        let Some((last_sequence, last_timestamp)) = self.last else {
+            self.last = Some((header.sequence_number, header.timestamp));
+            self.talk_spurts += 1;
+            return true;
+        };
+        let sequence_step = header.sequence_number.wrapping_sub(last_sequence);
+        if sequence_step == 0 || sequence_step >= 0x8000 {
+            return false;
+        }
+        let advance = header.timestamp.wrapping_sub(last_timestamp);
+        self.last = Some((header.sequence_number, header.timestamp));
+        // Lost packets advance the timestamp as much as they would have
+        let per_packet = advance / u32::from(sequence_step);
+        let paused = match self.packet_duration {
+            Some(duration) => per_packet > duration.saturating_mul(PAUSE_PACKETS),
+            None => false,
+        };
+        let started = header.marker || paused;
+        if !paused && advance < 0x8000_0000 && sequence_step == 1 && per_packet > 0 {
+            self.packet_duration = Some(per_packet);
+        }
+        if started {
+            self.talk_spurts += 1;
+        }
+        started
+    }
+
+    /// Get the talk spurts started, the first packet's included
+    pub fn talk_spurts(&self) -> u64 {
+        self.talk_spurts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(sequence_number: u16, timestamp: u32, marker: bool) -> RtpHeader {
+        RtpHeader {
+            version: 2,
+            padding: false,
+            extension: false,
+            csrc_count: 0,
+            marker,
+            payload_type: 0,
+            sequence_number,
+            timestamp,
+            ssrc: 1,
+        }
+    }
+
+    #[test]
+    fn test_marker_and_gaps_start_spurts() {
+        let mut detector = TalkSpurtDetector::new();
+        assert!(detector.on_packet(&header(1, 0, false)));
+        assert!(!detector.on_packet(&header(2, 160, false)));
+        assert!(!detector.on_packet(&header(3, 320, false)));
+        // Flagged by the sender, even without a gap
+        assert!(detector.on_packet(&header(4, 480, true)));
+        assert!(!detector.on_packet(&header(5, 640, false)));
+        // A pause without the marker
+        assert!(detector.on_packet(&header(6, 9000, false)));
+        assert!(!detector.on_packet(&header(7, 9160, false)));
+        assert_eq!(detector.talk_spurts(), 3);
+    }
+
+    #[test]
+    fn test_loss_and_reordering_are_no_spurts() {
+        let mut detector = TalkSpurtDetector::new();
+        detector.on_packet(&header(65534, 0, false));
+        assert!(!detector.on_packet(&header(65535, 160, false)));
+        // Three packets lost across the wrap
+        assert!(!detector.on_packet(&header(3, 160 * 5, false)));
+        // Late, and a duplicate
+        assert!(!detector.on_packet(&header(1, 160 * 3, true)));
+        assert!(!detector.on_packet(&header(3, 160 * 5, false)));
+        assert_eq!(detector.talk_spurts(), 1);
+    }
+}
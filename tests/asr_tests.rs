@@ -0,0 +1,198 @@
+#[cfg(test)]
+mod asr_tests {
+    use amwaj_media::config::{AsrConfig, AsrProtocol, Config};
+    use amwaj_media::grpc::service::{AmwajMediaService, SessionOptions};
+    use amwaj_media::metrics::Metrics;
+    use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+    use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+    use amwaj_media::proto::recognize_request::Request as RecognizeInput;
+    use amwaj_media::proto::speech_recognizer_server::{SpeechRecognizer, SpeechRecognizerServer};
+    use amwaj_media::proto::{self, media_event, orchestration_command};
+    use parking_lot::Mutex;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_stream::wrappers::ReceiverStream;
+    use tokio_stream::Stream;
+    use tonic::{Request, Response, Status, Streaming};
+
+    /// What the fake recognizer received
+    #[derive(Debug, Default)]
+    struct Received {
+        config: Option<proto::RecognitionConfig>,
+        audio_frames: usize,
+        turn_ends: usize,
+    }
+
+    /// Answers the first audio with a partial transcript and each turn end
+    /// with a final one
+    #[derive(Clone, Default)]
+    struct FakeRecognizer {
+        received: Arc<Mutex<Received>>,
+    }
+
+    #[tonic::async_trait]
+    impl SpeechRecognizer for FakeRecognizer {
+        type RecognizeStream =
+            Pin<Box<dyn Stream<Item = Result<proto::RecognizeResponse, Status>> + Send>>;
+
+        async fn recognize(
+            &self,
+            request: Request<Streaming<proto::RecognizeRequest>>,
+        ) -> Result<Response<Self::RecognizeStream>, Status> {
+            let mut requests = request.into_inner();
+            let received = Arc::clone(&self.received);
+            let (sender, receiver) = tokio::sync::mpsc::channel(16);
+            tokio::spawn(async move {
+                while let Ok(Some(request)) = requests.message().await {
+                    let response = {
+                        let mut received = received.lock();
+                        match request.request {
+                            Some(RecognizeInput::Config(config)) => {
+                                received.config = Some(config);
+                                None
+                            }
+                            Some(RecognizeInput::Audio(_)) => {
+                                received.audio_frames += 1;
+                                (received.audio_frames == 1).then_some(("hello", false))
+                            }
+                            Some(RecognizeInput::EndOfTurn(_)) => {
+                                received.turn_ends += 1;
+                                Some(("hello there", true))
+                            }
+                            None => None,
+                        }
+                    };
+                    if let Some((text, is_final)) = response {
+                        let response = proto::RecognizeResponse {
+                            text: text.to_string(),
+                            is_final,
+                            confidence: 0.9,
+                        };
+                        if sender.send(Ok(response)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            });
+            Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grpc_recognizer_bridge() {
+        let recognizer = FakeRecognizer::default();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let recognizer_server = tonic::transport::Server::builder()
+            .add_service(SpeechRecognizerServer::new(recognizer.clone()))
+            .serve_with_shutdown("127.0.0.1:50114".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let recognizer_handle = tokio::spawn(recognizer_server);
+
+        let config = Config {
+            asr: Some(AsrConfig {
+                protocol: AsrProtocol::Grpc,
+                url: "http://127.0.0.1:50114".to_string(),
+                language: Some("en".to_string()),
+                ..AsrConfig::default()
+            }),
+            ..Config::default()
+        };
+        config.validate().unwrap();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let (server_shutdown_tx, server_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service.clone()))
+            .serve_with_shutdown("127.0.0.1:50115".parse().unwrap(), async {
+                let _ = server_shutdown_rx.await;
+            });
+        let server_handle = tokio::spawn(server);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        service
+            .create_session(SessionOptions {
+                session_id: Some("asr-1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50115")
+            .await
+            .unwrap();
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(10);
+        let mut events = client
+            .media_stream(ReceiverStream::new(command_rx))
+            .await
+            .unwrap()
+            .into_inner();
+        command_tx
+            .send(proto::OrchestrationCommand {
+                session_id: "asr-1".to_string(),
+                timestamp_ms: 0,
+                command_id: String::new(),
+                command: Some(orchestration_command::Command::Resume(proto::Resume {})),
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // One turn between silences, only its audio and pre-roll is sent
+        for _ in 0..20 {
+            service.push_audio("asr-1", &[0i16; 320]).unwrap();
+        }
+        for _ in 0..30 {
+            service.push_audio("asr-1", &[10000i16; 320]).unwrap();
+            tokio::task::yield_now().await;
+        }
+        for _ in 0..100 {
+            service.push_audio("asr-1", &[0i16; 320]).unwrap();
+        }
+
+        let mut transcripts = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while transcripts.len() < 2 {
+                let event = events.message().await.unwrap().unwrap();
+                if let Some(media_event::Event::PartialTranscript(transcript)) = event.event {
+                    transcripts.push((transcript.text, transcript.is_final));
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            transcripts,
+            [
+                ("hello".to_string(), false),
+                ("hello there".to_string(), true)
+            ]
+        );
+
+        {
+            let received = recognizer.received.lock();
+            let config = received.config.as_ref().unwrap();
+            assert_eq!(config.session_id, "asr-1");
+            assert_eq!(config.sample_rate, 16000);
+            assert_eq!(config.language, "en");
+            assert_eq!(received.turn_ends, 1);
+            // The silence after the turn stays out
+            assert!(received.audio_frames >= 30);
+            assert!(received.audio_frames < 100);
+        }
+        let metrics = service.metrics();
+        assert_eq!(
+            metrics.asr_transcripts.with_label_values(&["final"]).get(),
+            1
+        );
+
+        // The recognizer call ends with the session
+        service.end_session("asr-1").await.unwrap();
+        drop(command_tx);
+        drop(events);
+        let _ = server_shutdown_tx.send(());
+        server_handle.await.unwrap().unwrap();
+        let _ = shutdown_tx.send(());
+        recognizer_handle.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+#[cfg(all(test, feature = "client-feature"))]
+mod client_tests {
+    use amwaj_media::client::{Backoff, MediaClient, MockServer};
+    use amwaj_media::grpc::service::CommandStatus;
+    use amwaj_media::proto::{self, media_event, orchestration_command};
+    use std::time::Duration;
+
+    fn stop_audio(session_id: &str) -> proto::OrchestrationCommand {
+        proto::OrchestrationCommand {
+            session_id: session_id.to_string(),
+            timestamp_ms: 0,
+            command_id: String::new(),
+            command: Some(orchestration_command::Command::StopAudio(
+                proto::StopAudio::default(),
+            )),
+        }
+    }
+
+    fn client(endpoint: String) -> MediaClient {
+        MediaClient::new(endpoint)
+            .with_backoff(
+                Backoff::new(Duration::from_millis(10), Duration::from_millis(50))
+                    .with_max_attempts(20),
+            )
+            .with_ack_timeout(Duration::from_secs(2))
+    }
+
+    #[tokio::test]
+    async fn test_commands_and_events() {
+        let server = MockServer::start().await.unwrap();
+        let (commands, mut events) = client(server.endpoint()).open_stream().await.unwrap();
+
+        assert_eq!(
+            commands.send(stop_audio("s1")).await.unwrap(),
+            CommandStatus::Completed
+        );
+        server.reject_commands(Some("no such session"));
+        let error = commands.send(stop_audio("s2")).await.unwrap_err();
+        assert!(error.to_string().contains("no such session"));
+
+        commands.send_nowait(stop_audio("s3")).await.unwrap();
+        let event = proto::MediaEvent {
+            session_id: "s1".to_string(),
+            timestamp_ms: 0,
+            event: Some(media_event::Event::TurnStarted(proto::TurnStarted {
+                vad_probability: 0.9,
+                ..Default::default()
+            })),
+        };
+        assert_eq!(server.send_event(event.clone()), 1);
+        // Acks are consumed by the sender, only the event comes through
+        assert_eq!(events.next().await.unwrap(), event);
+
+        let received = server.commands();
+        assert_eq!(received.len(), 3);
+        assert!(!received[0].command_id.is_empty());
+        assert!(received[2].command_id.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let server = MockServer::start().await.unwrap();
+        let (commands, _events) = client(server.endpoint()).open_stream().await.unwrap();
+        commands.send(stop_audio("s1")).await.unwrap();
+
+        server.disconnect_streams();
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while server.stream_count() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            commands.send(stop_audio("s1")).await.unwrap(),
+            CommandStatus::Completed
+        );
+        assert_eq!(server.commands().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_server() {
+        use amwaj_media::config::{ApiKeyConfig, AuthConfig, Config};
+        use amwaj_media::grpc::server::GrpcServer;
+        use amwaj_media::metrics::Metrics;
+        use std::sync::Arc;
+
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 50086;
+        config.grpc.drain_timeout_secs = 0;
+        config.grpc.auth = Some(AuthConfig {
+            api_keys: vec![ApiKeyConfig {
+                principal: "orchestrator".to_string(),
+                key: "key-123".into(),
+                key_file: None,
+            }],
+            jwt: None,
+        });
+        let metrics = Arc::new(Metrics::new(&config));
+        let server = GrpcServer::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(async move { server.start_with_shutdown(shutdown_rx).await });
+
+        let anonymous = client("http://127.0.0.1:50086".to_string());
+        assert!(anonymous.open_stream().await.is_err());
+
+        let authenticated = client("http://127.0.0.1:50086".to_string()).with_api_key("key-123");
+        let (commands, _events) = authenticated.open_stream().await.unwrap();
+        assert_eq!(
+            commands.send(stop_audio("s1")).await.unwrap(),
+            CommandStatus::Completed
+        );
+        let info = authenticated
+            .grpc_client()
+            .await
+            .unwrap()
+            .get_session(proto::GetSessionRequest {
+                session_id: "s1".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(info.session_id, "s1");
+
+        drop(commands);
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap().unwrap();
+    }
+}
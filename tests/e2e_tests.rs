@@ -37,9 +37,9 @@ mod e2e_tests {
         assert_eq!(event, TurnEvent::TurnStarted);
     }
 
-    #[test]
-    fn test_webrtc_to_audio_pipeline() {
-        let mut manager = WebRtcManager::new();
+    #[tokio::test]
+    async fn test_webrtc_to_audio_pipeline() {
+        let manager = WebRtcManager::new();
 
         // Create connection
         manager
@@ -143,9 +143,9 @@ mod e2e_tests {
         assert_eq!(metrics.active_connections.get(), 1);
     }
 
-    #[test]
-    fn test_multiple_sessions() {
-        let mut manager = WebRtcManager::new();
+    #[tokio::test]
+    async fn test_multiple_sessions() {
+        let manager = WebRtcManager::new();
 
         // Create multiple sessions
         for i in 0..100 {
@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod grpc_tests {
     use amwaj_media::config::Config;
+    use amwaj_media::detection::TurnSegment;
     use amwaj_media::grpc::server::GrpcServer;
     use amwaj_media::grpc::service::{
         AmwajMediaService, MediaEvent, MessageBuffer, SessionHandler,
@@ -61,6 +62,12 @@ mod grpc_tests {
                 session_id: "test".to_string(),
                 timestamp_ms: 2000,
                 duration_ms: 1000,
+                segment: TurnSegment {
+                    start_ms: 1000,
+                    end_ms: 2000,
+                    duration_ms: 1000,
+                    ..Default::default()
+                },
             })
             .await
             .unwrap();
@@ -140,4 +147,1155 @@ mod grpc_tests {
         let result = handle.await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_media_stream_roundtrip() {
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+        use amwaj_media::proto::{self, media_event, orchestration_command};
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service.clone()))
+            .serve_with_shutdown("127.0.0.1:50097".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50097")
+            .await
+            .unwrap();
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(10);
+        let mut events = client
+            .media_stream(tokio_stream::wrappers::ReceiverStream::new(command_rx))
+            .await
+            .unwrap()
+            .into_inner();
+
+        command_tx
+            .send(proto::OrchestrationCommand {
+                session_id: "s1".to_string(),
+                timestamp_ms: 0,
+                command_id: String::new(),
+                command: Some(orchestration_command::Command::InjectSignal(
+                    proto::InjectSignal {
+                        kind: proto::inject_signal::Kind::AsrWord as i32,
+                        value: 1.0,
+                        timeout_ms: None,
+                    },
+                )),
+            })
+            .await
+            .unwrap();
+        while service.session_count() == 0 {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
+
+        // Audio pushed into the session comes back on the stream
+        for _ in 0..10 {
+            service.push_audio("s1", &vec![10000i16; 320]).unwrap();
+        }
+        let event = events.message().await.unwrap().unwrap();
+        assert_eq!(event.session_id, "s1");
+        assert!(matches!(
+            event.event,
+            Some(media_event::Event::TurnStarted(_))
+        ));
+
+        let debug = client
+            .get_detection_debug(proto::DetectionDebugRequest {
+                session_id: "s1".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(debug.frames.len(), 10);
+        assert!(debug.frames[0].external_contribution > 0.0);
+
+        let missing = client
+            .get_detection_debug(proto::DetectionDebugRequest {
+                session_id: "missing".to_string(),
+            })
+            .await;
+        assert_eq!(missing.unwrap_err().code(), tonic::Code::NotFound);
+
+        let _ = shutdown_tx.send(());
+        drop(command_tx);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_audio_in_and_out_streams() {
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+        use amwaj_media::proto::{self, orchestration_command};
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service.clone()))
+            .serve_with_shutdown("127.0.0.1:50096".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50096")
+            .await
+            .unwrap();
+
+        // 10 chunks of 30ms loud PCM, sequence 4 lost in transit
+        let chunks: Vec<proto::AudioChunk> = (0..11u64)
+            .filter(|seq| *seq != 4)
+            .map(|seq| proto::AudioChunk {
+                session_id: "s1".to_string(),
+                sequence_number: seq,
+                timestamp_ms: seq as i64 * 30,
+                encoding: proto::audio_chunk::Encoding::Pcm16 as i32,
+                sample_rate: 16000,
+                channels: 1,
+                data: [10000i16.to_le_bytes(); 480].concat(),
+            })
+            .collect();
+        let summary = client
+            .stream_audio_in(tokio_stream::iter(chunks))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(summary.session_id, "s1");
+        assert_eq!(summary.chunks_received, 10);
+        assert_eq!(summary.chunks_missing, 1);
+        assert_eq!(summary.frames_processed, 15);
+
+        // Agent audio sent with PlayAudio is relayed to the renderer
+        let mut playback = client
+            .stream_audio_out(proto::StreamAudioOutRequest {
+                session_id: "s1".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(10);
+        let _events = client
+            .media_stream(tokio_stream::wrappers::ReceiverStream::new(command_rx))
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            command_tx
+                .send(proto::OrchestrationCommand {
+                    session_id: "s1".to_string(),
+                    timestamp_ms: 0,
+                    command_id: String::new(),
+                    command: Some(orchestration_command::Command::PlayAudio(
+                        proto::PlayAudio {
+                            audio_data: vec![1, 2, 3, 4],
+                            audio_format: "pcm16".to_string(),
+                            sequence_number: 0,
+                        },
+                    )),
+                })
+                .await
+                .unwrap();
+        }
+
+        for expected in 0..2 {
+            let chunk = playback.message().await.unwrap().unwrap();
+            assert_eq!(chunk.sequence_number, expected);
+            assert_eq!(chunk.data, vec![1, 2, 3, 4]);
+        }
+
+        let _ = shutdown_tx.send(());
+        drop(command_tx);
+        drop(playback);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_control_rpcs() {
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+        use amwaj_media::proto::{self, media_event, orchestration_command};
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service.clone()))
+            .serve_with_shutdown("127.0.0.1:50095".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50095")
+            .await
+            .unwrap();
+        let created = client
+            .create_session(proto::CreateSessionRequest {
+                user_id: "user-1".to_string(),
+                turn_config: Some(proto::UpdateTurnConfig {
+                    max_silence_duration_ms: Some(800),
+                    ..Default::default()
+                }),
+                webrtc: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!created.session_id.is_empty());
+        assert_eq!(created.state, "active");
+        assert_eq!(created.sample_rate, 16000);
+        assert_eq!(created.turn_state, "idle");
+        assert!(created.webrtc);
+
+        let duplicate = client
+            .create_session(proto::CreateSessionRequest {
+                session_id: created.session_id.clone(),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(duplicate.unwrap_err().code(), tonic::Code::AlreadyExists);
+
+        // The media stream joins the created session
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(10);
+        let mut events = client
+            .media_stream(tokio_stream::wrappers::ReceiverStream::new(command_rx))
+            .await
+            .unwrap()
+            .into_inner();
+        command_tx
+            .send(proto::OrchestrationCommand {
+                session_id: created.session_id.clone(),
+                timestamp_ms: 0,
+                command_id: String::new(),
+                command: Some(orchestration_command::Command::StopAudio(
+                    proto::StopAudio {
+                        reason: "test".to_string(),
+                    },
+                )),
+            })
+            .await
+            .unwrap();
+        // Let the stream attach before the session ends
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        for _ in 0..3 {
+            service
+                .push_audio(&created.session_id, &vec![0i16; 320])
+                .unwrap();
+        }
+
+        let info = client
+            .get_session(proto::GetSessionRequest {
+                session_id: created.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(info.user_id, "user-1");
+        assert_eq!(info.frames_processed, 3);
+        assert_eq!(service.session_count(), 1);
+
+        let ended = client
+            .end_session(proto::EndSessionRequest {
+                session_id: created.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(ended.total_frames, 3);
+        let event = events.message().await.unwrap().unwrap();
+        assert!(matches!(
+            event.event,
+            Some(media_event::Event::SessionEnded(_))
+        ));
+
+        let missing = client
+            .get_session(proto::GetSessionRequest {
+                session_id: created.session_id.clone(),
+            })
+            .await;
+        assert_eq!(missing.unwrap_err().code(), tonic::Code::NotFound);
+
+        let _ = shutdown_tx.send(());
+        drop(command_tx);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_signal_offer_answer() {
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+        use amwaj_media::proto::{self, signal_message};
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service.clone()))
+            .serve_with_shutdown("127.0.0.1:50094".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50094")
+            .await
+            .unwrap();
+        let (signal_tx, signal_rx) = tokio::sync::mpsc::channel(10);
+        let mut replies = client
+            .signal(tokio_stream::wrappers::ReceiverStream::new(signal_rx))
+            .await
+            .unwrap()
+            .into_inner();
+
+        signal_tx
+            .send(proto::SignalMessage {
+                session_id: "s1".to_string(),
+                signal: Some(signal_message::Signal::OfferSdp(
+                    "v=0\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\n".to_string(),
+                )),
+            })
+            .await
+            .unwrap();
+
+        let answer = replies.message().await.unwrap().unwrap();
+        assert_eq!(answer.session_id, "s1");
+        match answer.signal {
+            Some(signal_message::Signal::AnswerSdp(sdp)) => assert!(sdp.contains("opus")),
+            other => panic!("Unexpected signal: {:?}", other),
+        }
+
+        // Server candidates are trickled until an empty candidate
+        loop {
+            match replies.message().await.unwrap().unwrap().signal {
+                Some(signal_message::Signal::Candidate(init)) if init.candidate.is_empty() => break,
+                Some(signal_message::Signal::Candidate(init)) => {
+                    assert!(init.candidate.starts_with("candidate:"))
+                }
+                other => panic!("Unexpected signal: {:?}", other),
+            }
+        }
+
+        let info = client
+            .get_session(proto::GetSessionRequest {
+                session_id: "s1".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(info.webrtc);
+
+        let _ = shutdown_tx.send(());
+        drop(signal_tx);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mtls_san_authorization() {
+        use amwaj_media::config::TlsConfig;
+        use amwaj_media::proto;
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
+
+        let fixture =
+            |name: &str| format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 50093;
+        config.grpc.tls = Some(TlsConfig {
+            cert_path: fixture("server.pem"),
+            key_path: fixture("server.key"),
+            client_ca_path: Some(fixture("ca.pem")),
+            require_client_auth: true,
+            allowed_client_sans: vec!["spiffe://amwaj/orchestrator".to_string()],
+        });
+        let metrics = Arc::new(Metrics::new(&config));
+        let server = GrpcServer::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(async move { server.start_with_shutdown(shutdown_rx).await });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let endpoint = |client_name: Option<&str>| {
+            let mut tls = ClientTlsConfig::new()
+                .ca_certificate(Certificate::from_pem(
+                    std::fs::read(fixture("ca.pem")).unwrap(),
+                ))
+                .domain_name("localhost");
+            if let Some(name) = client_name {
+                tls = tls.identity(Identity::from_pem(
+                    std::fs::read(fixture(&format!("{}.pem", name))).unwrap(),
+                    std::fs::read(fixture(&format!("{}.key", name))).unwrap(),
+                ));
+            }
+            Channel::from_static("https://127.0.0.1:50093")
+                .tls_config(tls)
+                .unwrap()
+        };
+        let request = || proto::GetSessionRequest {
+            session_id: "missing".to_string(),
+        };
+
+        // An allowed client reaches the service
+        let channel = endpoint(Some("orchestrator")).connect().await.unwrap();
+        let status = AmwajMediaServerClient::new(channel)
+            .get_session(request())
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        // A valid certificate with a SAN outside the allow-list is refused
+        let channel = endpoint(Some("intruder")).connect().await.unwrap();
+        let status = AmwajMediaServerClient::new(channel)
+            .get_session(request())
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        // Without a client certificate the handshake fails
+        let anonymous = match endpoint(None).connect().await {
+            Ok(channel) => AmwajMediaServerClient::new(channel)
+                .get_session(request())
+                .await
+                .is_err(),
+            Err(_) => true,
+        };
+        assert!(anonymous);
+
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_key_authentication() {
+        use amwaj_media::config::{ApiKeyConfig, AuthConfig};
+        use amwaj_media::proto;
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 50092;
+        config.grpc.auth = Some(AuthConfig {
+            api_keys: vec![ApiKeyConfig {
+                principal: "orchestrator".to_string(),
+                key: "key-123".into(),
+                key_file: None,
+            }],
+            jwt: None,
+        });
+        let metrics = Arc::new(Metrics::new(&config));
+        let server = GrpcServer::new(config, Arc::clone(&metrics));
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(async move { server.start_with_shutdown(shutdown_rx).await });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50092")
+            .await
+            .unwrap();
+
+        // Streams are rejected before any message is exchanged
+        let (_command_tx, command_rx) = tokio::sync::mpsc::channel(1);
+        let status = client
+            .media_stream(tokio_stream::wrappers::ReceiverStream::new(command_rx))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut request = tonic::Request::new(proto::GetSessionRequest {
+            session_id: "missing".to_string(),
+        });
+        request
+            .metadata_mut()
+            .insert("x-api-key", "key-123".parse().unwrap());
+        let status = client.get_session(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        assert_eq!(
+            metrics
+                .auth_requests
+                .with_label_values(&["orchestrator"])
+                .get(),
+            1
+        );
+        assert_eq!(metrics.auth_failures.get(), 1.0);
+
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_command_acks() {
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+        use amwaj_media::proto::{self, command_ack, media_event, orchestration_command};
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service))
+            .serve_with_shutdown("127.0.0.1:50091".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50091")
+            .await
+            .unwrap();
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(10);
+        let mut events = client
+            .media_stream(tokio_stream::wrappers::ReceiverStream::new(command_rx))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let command = |command_id: &str, command| proto::OrchestrationCommand {
+            session_id: "s1".to_string(),
+            timestamp_ms: 0,
+            command_id: command_id.to_string(),
+            command,
+        };
+        let commands = [
+            command(
+                "stop",
+                Some(orchestration_command::Command::StopAudio(
+                    proto::StopAudio {
+                        reason: "barge-in".to_string(),
+                    },
+                )),
+            ),
+            // No renderer is attached, so the audio is not delivered yet
+            command(
+                "play",
+                Some(orchestration_command::Command::PlayAudio(
+                    proto::PlayAudio {
+                        audio_data: vec![0; 4],
+                        audio_format: "pcm16".to_string(),
+                        sequence_number: 0,
+                    },
+                )),
+            ),
+            // Fire-and-forget commands are not acknowledged
+            command(
+                "",
+                Some(orchestration_command::Command::StopAudio(
+                    proto::StopAudio::default(),
+                )),
+            ),
+            command(
+                "vad",
+                Some(orchestration_command::Command::UpdateTurnConfig(
+                    proto::UpdateTurnConfig {
+                        vad_threshold_enter: Some(2.0),
+                        ..Default::default()
+                    },
+                )),
+            ),
+            command("empty", None),
+        ];
+        for command in commands {
+            command_tx.send(command).await.unwrap();
+        }
+
+        let expected = [
+            ("stop", command_ack::Status::Completed),
+            ("play", command_ack::Status::Accepted),
+            ("vad", command_ack::Status::Rejected),
+            ("empty", command_ack::Status::Rejected),
+        ];
+        for (command_id, status) in expected {
+            match events.message().await.unwrap().unwrap().event {
+                Some(media_event::Event::CommandAck(ack)) => {
+                    assert_eq!(ack.command_id, command_id);
+                    assert_eq!(ack.status, status as i32);
+                    assert_eq!(
+                        ack.reason.is_empty(),
+                        status != command_ack::Status::Rejected
+                    );
+                }
+                other => panic!("Unexpected event: {:?}", other),
+            }
+        }
+
+        let _ = shutdown_tx.send(());
+        drop(command_tx);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_idle_stream_closed() {
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 50090;
+        config.grpc.idle_stream_timeout_ms = Some(100);
+        let metrics = Arc::new(Metrics::new(&config));
+        let server = GrpcServer::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(async move { server.start_with_shutdown(shutdown_rx).await });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50090")
+            .await
+            .unwrap();
+        let (_command_tx, command_rx) = tokio::sync::mpsc::channel(1);
+        let mut events = client
+            .media_stream(tokio_stream::wrappers::ReceiverStream::new(command_rx))
+            .await
+            .unwrap()
+            .into_inner();
+
+        // The client never sends anything, so the server gives up on it
+        let status = tokio::time::timeout(tokio::time::Duration::from_secs(2), events.message())
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_graceful_drain() {
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::{self, media_event, orchestration_command};
+
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 50089;
+        config.grpc.drain_timeout_secs = 10;
+        let metrics = Arc::new(Metrics::new(&config));
+        let server = GrpcServer::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(async move { server.start_with_shutdown(shutdown_rx).await });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50089")
+            .await
+            .unwrap();
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(10);
+        let mut events = client
+            .media_stream(tokio_stream::wrappers::ReceiverStream::new(command_rx))
+            .await
+            .unwrap()
+            .into_inner();
+        command_tx
+            .send(proto::OrchestrationCommand {
+                session_id: "call-1".to_string(),
+                timestamp_ms: 0,
+                command_id: "stop".to_string(),
+                command: Some(orchestration_command::Command::StopAudio(
+                    proto::StopAudio::default(),
+                )),
+            })
+            .await
+            .unwrap();
+        let ack = events.message().await.unwrap().unwrap();
+        assert!(matches!(ack.event, Some(media_event::Event::CommandAck(_))));
+
+        let _ = shutdown_tx.send(());
+        let draining = events.message().await.unwrap().unwrap();
+        match draining.event {
+            Some(media_event::Event::ServerDraining(draining)) => {
+                assert!(draining.deadline_ms > chrono::Utc::now().timestamp_millis())
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        // The call in progress still works, new ones are refused
+        let status = client
+            .create_session(proto::CreateSessionRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        client
+            .end_session(proto::EndSessionRequest {
+                session_id: "call-1".to_string(),
+            })
+            .await
+            .unwrap();
+
+        // With the last session ended the server stops well before the timeout
+        let ended = events.message().await.unwrap().unwrap();
+        assert!(matches!(
+            ended.event,
+            Some(media_event::Event::SessionEnded(_))
+        ));
+        assert!(events.message().await.unwrap().is_none());
+        tokio::time::timeout(tokio::time::Duration::from_secs(5), handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compressed_stream() {
+        use amwaj_media::proto;
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use tonic::codec::CompressionEncoding;
+
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 50088;
+        config.grpc.compression = vec!["gzip".to_string(), "zstd".to_string()];
+        let metrics = Arc::new(Metrics::new(&config));
+        let server = GrpcServer::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(async move { server.start_with_shutdown(shutdown_rx).await });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        for encoding in [CompressionEncoding::Gzip, CompressionEncoding::Zstd] {
+            let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50088")
+                .await
+                .unwrap()
+                .send_compressed(encoding)
+                .accept_compressed(encoding);
+            let info = client
+                .create_session(proto::CreateSessionRequest {
+                    metadata: [("padding".to_string(), "a".repeat(4096))].into(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(info.metadata["padding"].len(), 4096);
+        }
+
+        // Clients without compression are still served
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50088")
+            .await
+            .unwrap();
+        let status = client
+            .get_session(proto::GetSessionRequest {
+                session_id: "missing".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_event_subscription() {
+        use amwaj_media::grpc::subscription::{AUDIO_DECIMATION_HEADER, EVENT_MASK_HEADER};
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+        use amwaj_media::proto::{self, media_event, orchestration_command};
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service.clone()))
+            .serve_with_shutdown("127.0.0.1:50087".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50087")
+            .await
+            .unwrap();
+        let open = |mask: &str, command_rx| {
+            let mut request =
+                tonic::Request::new(tokio_stream::wrappers::ReceiverStream::new(command_rx));
+            request
+                .metadata_mut()
+                .insert(EVENT_MASK_HEADER, mask.parse().unwrap());
+            request
+                .metadata_mut()
+                .insert(AUDIO_DECIMATION_HEADER, "2".parse().unwrap());
+            request
+        };
+
+        let (_rejected_tx, rejected_rx) = tokio::sync::mpsc::channel(1);
+        let status = client
+            .media_stream(open("1024", rejected_rx))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        // Turn events only
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(10);
+        let mut events = client
+            .media_stream(open("2", command_rx))
+            .await
+            .unwrap()
+            .into_inner();
+        command_tx
+            .send(proto::OrchestrationCommand {
+                session_id: "s1".to_string(),
+                timestamp_ms: 0,
+                command_id: "stop".to_string(),
+                command: Some(orchestration_command::Command::StopAudio(
+                    proto::StopAudio::default(),
+                )),
+            })
+            .await
+            .unwrap();
+        let ack = events.message().await.unwrap().unwrap();
+        assert!(matches!(ack.event, Some(media_event::Event::CommandAck(_))));
+
+        for _ in 0..20 {
+            service.push_audio("s1", &vec![10000i16; 320]).unwrap();
+        }
+        service.end_session("s1").await.unwrap();
+
+        let mut received = Vec::new();
+        while let Some(event) = events.message().await.unwrap() {
+            let ended = matches!(event.event, Some(media_event::Event::SessionEnded(_)));
+            received.push(event.event.unwrap());
+            if ended {
+                break;
+            }
+        }
+        assert!(matches!(received[0], media_event::Event::TurnStarted(_)));
+        assert!(received.iter().all(|event| matches!(
+            event,
+            media_event::Event::TurnStarted(_) | media_event::Event::SessionEnded(_)
+        )));
+
+        let _ = shutdown_tx.send(());
+        drop(command_tx);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_api_credentials() {
+        use amwaj_media::config::{AdminConfig, ApiKeyConfig, AuthConfig};
+        use amwaj_media::proto;
+        use amwaj_media::proto::amwaj_admin_client::AmwajAdminClient;
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+
+        let auth = |principal: &str, key: &str| AuthConfig {
+            api_keys: vec![ApiKeyConfig {
+                principal: principal.to_string(),
+                key: key.into(),
+                key_file: None,
+            }],
+            jwt: None,
+        };
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 50085;
+        config.grpc.drain_timeout_secs = 0;
+        config.grpc.auth = Some(auth("orchestrator", "media-key"));
+        config.grpc.admin = Some(AdminConfig {
+            auth: auth("ops", "admin-key"),
+        });
+        let metrics = Arc::new(Metrics::new(&config));
+        let server = GrpcServer::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(async move { server.start_with_shutdown(shutdown_rx).await });
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        fn with_key<T>(message: T, key: &str) -> tonic::Request<T> {
+            let mut request = tonic::Request::new(message);
+            request
+                .metadata_mut()
+                .insert("x-api-key", key.parse().unwrap());
+            request
+        }
+
+        let mut media = AmwajMediaServerClient::connect("http://127.0.0.1:50085")
+            .await
+            .unwrap();
+        media
+            .create_session(with_key(
+                proto::CreateSessionRequest {
+                    session_id: "s1".to_string(),
+                    ..Default::default()
+                },
+                "media-key",
+            ))
+            .await
+            .unwrap();
+        let status = media
+            .get_session(with_key(
+                proto::GetSessionRequest {
+                    session_id: "s1".to_string(),
+                },
+                "admin-key",
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut admin = AmwajAdminClient::connect("http://127.0.0.1:50085")
+            .await
+            .unwrap();
+        let status = admin
+            .list_sessions(with_key(proto::ListSessionsRequest::default(), "media-key"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let sessions = admin
+            .list_sessions(with_key(proto::ListSessionsRequest::default(), "admin-key"))
+            .await
+            .unwrap()
+            .into_inner()
+            .sessions;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].session_id, "s1");
+
+        let dump = admin
+            .get_config(with_key(proto::GetConfigRequest {}, "admin-key"))
+            .await
+            .unwrap()
+            .into_inner()
+            .toml;
+        assert!(!dump.contains("media-key") && !dump.contains("admin-key"));
+
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_schema_version_negotiation() {
+        use amwaj_media::grpc::capabilities::{SCHEMA_VERSION, SCHEMA_VERSION_HEADER};
+        use amwaj_media::proto;
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service))
+            .serve_with_shutdown("127.0.0.1:50084".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50084")
+            .await
+            .unwrap();
+        let capabilities = client
+            .server_capabilities(proto::ServerCapabilitiesRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(capabilities.schema_version, SCHEMA_VERSION);
+        assert!(capabilities
+            .codecs
+            .contains(&(proto::audio_chunk::Encoding::Opus as i32)));
+        assert!(capabilities
+            .event_types
+            .contains(&"server_draining".to_string()));
+
+        let open = |version: u32| {
+            let (_command_tx, command_rx) =
+                tokio::sync::mpsc::channel::<proto::OrchestrationCommand>(1);
+            let mut request =
+                tonic::Request::new(tokio_stream::wrappers::ReceiverStream::new(command_rx));
+            request
+                .metadata_mut()
+                .insert(SCHEMA_VERSION_HEADER, version.to_string().parse().unwrap());
+            request
+        };
+        let status = client
+            .media_stream(open(SCHEMA_VERSION + 1))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert!(client.media_stream(open(SCHEMA_VERSION)).await.is_ok());
+
+        let _ = shutdown_tx.send(());
+        let _ = handle.await;
+    }
+
+    #[tokio::test]
+    async fn test_session_owner_lookup() {
+        use amwaj_media::proto;
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service))
+            .serve_with_shutdown("127.0.0.1:50083".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50083")
+            .await
+            .unwrap();
+        let created = client
+            .create_session(proto::CreateSessionRequest::default())
+            .await
+            .unwrap()
+            .into_inner();
+        let owner = client
+            .get_session_owner(proto::GetSessionRequest {
+                session_id: created.session_id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(owner.session_id, created.session_id);
+        assert!(!owner.instance_id.is_empty());
+        assert!(owner.lease_expires_ms > chrono::Utc::now().timestamp_millis());
+
+        // Ending the session drops the lease
+        client
+            .end_session(proto::EndSessionRequest {
+                session_id: created.session_id.clone(),
+            })
+            .await
+            .unwrap();
+        let status = client
+            .get_session_owner(proto::GetSessionRequest {
+                session_id: created.session_id,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let _ = shutdown_tx.send(());
+        let _ = handle.await;
+    }
+
+    #[tokio::test]
+    async fn test_per_user_session_limit() {
+        use amwaj_media::proto;
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+
+        let mut config = Config::default();
+        config.sessions.max_sessions_per_user = Some(1);
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service.clone()))
+            .serve_with_shutdown("127.0.0.1:50082".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50082")
+            .await
+            .unwrap();
+        let request = || proto::CreateSessionRequest {
+            user_id: "user-1".to_string(),
+            ..Default::default()
+        };
+        let created = client.create_session(request()).await.unwrap().into_inner();
+        let status = client.create_session(request()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(
+            service.session_manager().sessions_for_user("user-1"),
+            vec![created.session_id]
+        );
+
+        let _ = shutdown_tx.send(());
+        let _ = handle.await;
+    }
+
+    #[tokio::test]
+    async fn test_session_history() {
+        use amwaj_media::proto;
+        use amwaj_media::proto::amwaj_media_server_client::AmwajMediaServerClient;
+        use amwaj_media::proto::amwaj_media_server_server::AmwajMediaServerServer;
+        use amwaj_media::proto::journal_entry::Entry;
+
+        let mut config = Config::default();
+        config.sessions.flush_journal_on_end = true;
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(AmwajMediaServerServer::new(service.clone()))
+            .serve_with_shutdown("127.0.0.1:50081".parse().unwrap(), async {
+                let _ = shutdown_rx.await;
+            });
+        let handle = tokio::spawn(server);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        let mut client = AmwajMediaServerClient::connect("http://127.0.0.1:50081")
+            .await
+            .unwrap();
+        let request = || proto::GetSessionRequest {
+            session_id: "s1".to_string(),
+        };
+        let status = client.get_session_history(request()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        client
+            .create_session(proto::CreateSessionRequest {
+                session_id: "s1".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        for _ in 0..30 {
+            service.push_audio("s1", &[10000i16; 320]).unwrap();
+        }
+        client
+            .end_session(proto::EndSessionRequest {
+                session_id: "s1".to_string(),
+            })
+            .await
+            .unwrap();
+
+        // Still available once the session ended
+        let history = client
+            .get_session_history(request())
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!history.live);
+        assert!(matches!(
+            history.entries[0].entry,
+            Some(Entry::TurnStarted(_))
+        ));
+        match &history.entries.last().unwrap().entry {
+            Some(Entry::Quality(quality)) => assert_eq!(quality.frames_processed, 30),
+            other => panic!("Unexpected entry: {:?}", other),
+        }
+
+        let _ = shutdown_tx.send(());
+        let _ = handle.await;
+    }
 }
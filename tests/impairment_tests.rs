@@ -0,0 +1,111 @@
+#[cfg(all(test, feature = "impairment-feature"))]
+mod impairment_tests {
+    use amwaj_media::config::CodecsConfig;
+    use amwaj_media::transport::rtp::{negotiate, RtpIngestOptions};
+    use amwaj_media::webrtc::codec::encode_pcmu;
+    use amwaj_media::webrtc::impairment::{ImpairedPeer, ImpairmentConfig};
+    use amwaj_media::webrtc::{PeerConnection, RtpPacket};
+
+    /// Samples of a 20 ms G.711 packet
+    const PACKET_SAMPLES: usize = 160;
+
+    fn pcmu_peer(config: ImpairmentConfig) -> ImpairedPeer {
+        let negotiated = negotiate(
+            &RtpIngestOptions {
+                codec: "pcmu".to_string(),
+                ..Default::default()
+            },
+            &CodecsConfig::default(),
+        )
+        .unwrap();
+        let mut peer = PeerConnection::new("impaired".to_string());
+        peer.set_negotiated_codec(negotiated);
+        ImpairedPeer::new(peer, config)
+    }
+
+    fn packet(sequence_number: u16) -> Vec<u8> {
+        let samples: Vec<i16> = (0..PACKET_SAMPLES)
+            .map(|i| ((i as f32 * 0.2).sin() * 8000.0) as i16)
+            .collect();
+        RtpPacket {
+            version: 2,
+            padding: false,
+            extension: false,
+            csrc_count: 0,
+            marker: sequence_number == 0,
+            payload_type: 0,
+            sequence_number,
+            timestamp: u32::from(sequence_number) * PACKET_SAMPLES as u32,
+            ssrc: 0x5eed,
+            payload: encode_pcmu(&samples),
+        }
+        .serialize()
+    }
+
+    /// Stream 10 s of audio through the link, returns the decoded packets
+    fn stream(peer: &mut ImpairedPeer) -> Vec<Vec<i16>> {
+        let mut decoded = Vec::new();
+        for sequence_number in 0..500u16 {
+            let now_ms = u64::from(sequence_number) * 20;
+            decoded.extend(peer.send(now_ms, packet(sequence_number)).unwrap());
+        }
+        decoded.extend(peer.flush().unwrap());
+        decoded
+    }
+
+    #[test]
+    fn test_loss_seen_by_jitter_buffer() {
+        let mut peer = pcmu_peer(ImpairmentConfig {
+            loss_percent: 10.0,
+            seed: 1,
+            ..Default::default()
+        });
+        let decoded = stream(&mut peer);
+        let stats = peer.link().stats();
+        assert_eq!(decoded.len() as u64, stats.delivered);
+        assert!(decoded.iter().all(|pcm| pcm.len() == PACKET_SAMPLES));
+
+        // Trailing losses go unnoticed until a later packet arrives
+        let expected = stats.dropped as f32 / stats.sent as f32;
+        let measured = peer.peer().get_buffer_stats().packet_loss_ratio;
+        assert!(
+            (measured - expected).abs() < 0.01,
+            "{} vs {}",
+            measured,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_every_arrival_is_played() {
+        let mut peer = pcmu_peer(ImpairmentConfig {
+            jitter_ms: 60,
+            reorder_percent: 5.0,
+            reorder_delay_ms: 80,
+            seed: 2,
+            ..Default::default()
+        });
+        let decoded = stream(&mut peer);
+        let stats = peer.link().stats();
+        assert!(stats.reordered > 0);
+        assert_eq!(stats.delivered, 500);
+        assert_eq!(decoded.len(), 500);
+        assert_eq!(peer.peer().get_buffer_stats().packets_evicted, 0);
+    }
+
+    #[test]
+    fn test_runs_are_reproducible() {
+        let config = ImpairmentConfig {
+            loss_percent: 5.0,
+            jitter_ms: 40,
+            reorder_percent: 3.0,
+            reorder_delay_ms: 40,
+            duplicate_percent: 2.0,
+            seed: 42,
+        };
+        let mut first = pcmu_peer(config.clone());
+        let mut second = pcmu_peer(config);
+        assert_eq!(stream(&mut first), stream(&mut second));
+        assert_eq!(first.link().stats(), second.link().stats());
+    }
+}
@@ -0,0 +1,288 @@
+#[cfg(test)]
+mod transport_tests {
+    use amwaj_media::config::{Config, RtpConfig, WebSocketConfig};
+    use amwaj_media::grpc::service::{AmwajMediaService, OrchestrationCommand, SessionOptions};
+    use amwaj_media::metrics::Metrics;
+    use amwaj_media::transport::{RtpIngestOptions, WebSocketServer};
+    use amwaj_media::webrtc::{codec, RtpPacket};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use futures_util::{SinkExt, StreamExt};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::{Error, Message};
+
+    #[tokio::test]
+    async fn test_twilio_media_stream() {
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let server = WebSocketServer::new(
+            service.clone(),
+            &WebSocketConfig {
+                bearer_token: Some("stream-token".into()),
+                ..Default::default()
+            },
+        );
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(server.serve("127.0.0.1:50113".parse().unwrap(), async {
+            let _ = shutdown_rx.await;
+        }));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Upgrades without the token are refused
+        let Err(Error::Http(response)) =
+            tokio_tungstenite::connect_async("ws://127.0.0.1:50113/media").await
+        else {
+            panic!("unauthenticated upgrade accepted");
+        };
+        assert_eq!(response.status(), 401);
+
+        let mut request = "ws://127.0.0.1:50113/media".into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer stream-token".parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let messages = [
+            r#"{"event":"connected","protocol":"Call","version":"1.0.0"}"#.to_string(),
+            r#"{"event":"start","sequenceNumber":"1","streamSid":"MZ1","start":{
+                "accountSid":"AC1","streamSid":"MZ1","callSid":"CA1","tracks":["inbound"],
+                "customParameters":{"session_id":"twilio-1"},
+                "mediaFormat":{"encoding":"audio/x-mulaw","sampleRate":8000,"channels":1}}}"#
+                .to_string(),
+        ];
+        for message in messages {
+            socket.send(Message::Text(message)).await.unwrap();
+        }
+        let payload = STANDARD.encode(codec::encode_pcmu(&[0; 160]));
+        for chunk in 1..=10 {
+            let media = format!(
+                r#"{{"event":"media","streamSid":"MZ1","media":{{"track":"inbound","chunk":"{}","timestamp":"{}","payload":"{}"}}}}"#,
+                chunk,
+                chunk * 20,
+                payload
+            );
+            socket.send(Message::Text(media)).await.unwrap();
+        }
+        while service
+            .session_stats()
+            .first()
+            .is_none_or(|stats| stats.frames_processed < 10)
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Agent audio goes back to the caller as mu-law
+        service
+            .apply_command(&OrchestrationCommand::PlayAudio {
+                session_id: "twilio-1".to_string(),
+                audio_data: vec![0; 320],
+                audio_format: "pcm16".to_string(),
+            })
+            .unwrap();
+        let reply = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let reply: serde_json::Value = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+        assert_eq!(reply["event"], "media");
+        assert_eq!(reply["streamSid"], "MZ1");
+        let audio = STANDARD
+            .decode(reply["media"]["payload"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(audio.len(), 160);
+
+        // The session created for the stream ends with it
+        socket
+            .send(Message::Text(
+                r#"{"event":"stop","streamSid":"MZ1","stop":{"callSid":"CA1"}}"#.to_string(),
+            ))
+            .await
+            .unwrap();
+        while service.session_count() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap().unwrap();
+    }
+
+    fn rtp_packet(ssrc: u32, sequence_number: u16) -> Vec<u8> {
+        RtpPacket {
+            version: 2,
+            padding: false,
+            extension: false,
+            csrc_count: 0,
+            marker: false,
+            payload_type: 0,
+            sequence_number,
+            timestamp: u32::from(sequence_number) * 160,
+            ssrc,
+            payload: codec::encode_pcmu(&[0; 160]),
+        }
+        .serialize()
+    }
+
+    #[tokio::test]
+    async fn test_plain_rtp_ingest() {
+        let options = SessionOptions {
+            session_id: Some("rtp-1".to_string()),
+            rtp: Some(RtpIngestOptions {
+                codec: "pcmu".to_string(),
+                payload_type: None,
+                ssrc: Some(0x1234),
+            }),
+            ..Default::default()
+        };
+        let config = Config::default();
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        // Refused unless configured
+        assert!(service.create_session(options.clone()).await.is_err());
+
+        let mut config = Config::default();
+        config.transports.rtp = Some(RtpConfig {
+            advertised_address: Some("127.0.0.1".to_string()),
+            port_min: 40300,
+            port_max: 40309,
+            ..Default::default()
+        });
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        service.create_session(options).await.unwrap();
+        let status = service.session_status("rtp-1").await.unwrap();
+        assert_eq!(status.sample_rate, 8000);
+        let endpoint = status.rtp.unwrap();
+        assert_eq!(endpoint.address, "127.0.0.1:40300".parse().unwrap());
+        assert_eq!((endpoint.codec, endpoint.payload_type), ("pcmu", 0));
+
+        let gateway = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        // Another stream's packets are dropped
+        for sequence_number in 1..=3 {
+            gateway
+                .send_to(&rtp_packet(0x9999, sequence_number), endpoint.address)
+                .await
+                .unwrap();
+        }
+        for sequence_number in 1..=5 {
+            gateway
+                .send_to(&rtp_packet(0x1234, sequence_number), endpoint.address)
+                .await
+                .unwrap();
+        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while service
+                .session_status("rtp-1")
+                .await
+                .is_none_or(|status| status.frames_processed < 5)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let status = service.session_status("rtp-1").await.unwrap();
+        assert_eq!(status.frames_processed, 5);
+        assert_eq!(status.rtp_packets_processed, 5);
+
+        // Playback goes back to where the RTP came from
+        service
+            .apply_command(&OrchestrationCommand::PlayAudio {
+                session_id: "rtp-1".to_string(),
+                audio_data: vec![0; 320],
+                audio_format: "pcm16".to_string(),
+            })
+            .unwrap();
+        let mut buffer = [0u8; 1500];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(5), gateway.recv_from(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let packet = RtpPacket::parse(&buffer[..len]).unwrap();
+        assert_eq!(packet.payload_type, 0);
+        assert_eq!(packet.payload.len(), 160);
+
+        // The port is released with the session
+        service.end_session("rtp-1").await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while std::net::UdpSocket::bind(endpoint.address).is_err() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shared_rtp_port() {
+        let mut config = Config::default();
+        config.transports.rtp = Some(RtpConfig {
+            advertised_address: Some("127.0.0.1".to_string()),
+            shared_port: Some(0),
+            reactors: 2,
+            ..Default::default()
+        });
+        let metrics = Arc::new(Metrics::new(&config));
+        let service = AmwajMediaService::new(config, metrics);
+        let options = |session_id: &str, ssrc: Option<u32>| SessionOptions {
+            session_id: Some(session_id.to_string()),
+            rtp: Some(RtpIngestOptions {
+                codec: "pcmu".to_string(),
+                payload_type: None,
+                ssrc,
+            }),
+            ..Default::default()
+        };
+        // Streams are told apart by SSRC alone
+        assert!(service.create_session(options("any", None)).await.is_err());
+        service
+            .create_session(options("a", Some(0xA)))
+            .await
+            .unwrap();
+        service
+            .create_session(options("b", Some(0xB)))
+            .await
+            .unwrap();
+        let address = service
+            .session_status("a")
+            .await
+            .unwrap()
+            .rtp
+            .unwrap()
+            .address;
+        let other = service
+            .session_status("b")
+            .await
+            .unwrap()
+            .rtp
+            .unwrap()
+            .address;
+        assert_eq!(address, other);
+
+        let gateway = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for sequence_number in 1..=3 {
+            for ssrc in [0xA, 0xB] {
+                gateway
+                    .send_to(&rtp_packet(ssrc, sequence_number), address)
+                    .await
+                    .unwrap();
+            }
+        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            for session_id in ["a", "b"] {
+                while service
+                    .session_status(session_id)
+                    .await
+                    .is_none_or(|status| status.rtp_packets_processed < 3)
+                {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
@@ -1,9 +1,13 @@
 #[cfg(test)]
 mod turn_detection_tests {
     use amwaj_media::audio::AudioFeatures;
+    use amwaj_media::config::{Config, OverlapDetectionConfig};
     use amwaj_media::detection::{
-        MultiSignalFusion, TurnDetectionConfig, TurnDetectionEngine, TurnEvent, TurnState,
+        ConversationContext, MultiSignalFusion, TurnDetectionConfig, TurnDetectionEngine,
+        TurnEvent, TurnState,
     };
+    use amwaj_media::grpc::service::{MediaEvent, OrchestrationCommand};
+    use amwaj_media::pipeline::MediaPipeline;
 
     fn create_features(volume_db: f32) -> AudioFeatures {
         AudioFeatures {
@@ -126,7 +130,8 @@ mod turn_detection_tests {
         let features = create_features(-25.0);
 
         let score_neutral = fusion.fuse_signals(0.5, &features, None);
-        let score_expecting = fusion.fuse_signals(0.5, &features, Some("expecting_response"));
+        let score_expecting =
+            fusion.fuse_signals(0.5, &features, Some(ConversationContext::ExpectingResponse));
 
         assert!(score_expecting > score_neutral);
     }
@@ -164,4 +169,47 @@ mod turn_detection_tests {
         let avg = engine.average_vad();
         assert!(avg > 0.4 && avg < 0.6); // Average of 0.8 and 0.2
     }
+
+    /// Overlaps of 500ms of caller speech at `heard` during a second of
+    /// agent audio at `played`
+    fn overlaps(played: i16, heard: i16) -> Vec<u32> {
+        let mut config = Config::default();
+        config.detection.overlap = Some(OverlapDetectionConfig::default());
+        let mut pipeline = MediaPipeline::new("s1".to_string(), &config).unwrap();
+        pipeline
+            .apply_command(&OrchestrationCommand::PlayAudio {
+                session_id: "s1".to_string(),
+                audio_data: [played; 16000]
+                    .iter()
+                    .flat_map(|s| s.to_le_bytes())
+                    .collect(),
+                audio_format: "pcm16".to_string(),
+            })
+            .unwrap();
+
+        let mut events = Vec::new();
+        for frame in [[heard; 320]; 25].iter().chain(&[[0; 320]; 50]) {
+            events.extend(pipeline.process_frame(frame).unwrap());
+        }
+        events
+            .iter()
+            .filter_map(|event| match event {
+                MediaEvent::Overlap { duration_ms, .. } => Some(*duration_ms),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_overlap_through_pipeline() {
+        // The caller talking over the agent
+        let talked_over = overlaps(8000, 8000);
+        assert_eq!(talked_over.len(), 1);
+        assert!(talked_over[0] >= 100);
+
+        // Speech far below the agent's audio is its echo
+        assert!(overlaps(32000, 6000).is_empty());
+        // and is an overlap without the agent's audio to compare with
+        assert!(!overlaps(0, 6000).is_empty());
+    }
 }
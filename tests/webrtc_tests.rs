@@ -89,9 +89,9 @@ mod webrtc_tests {
         assert_eq!(decoder.frames_decoded(), 1);
     }
 
-    #[test]
-    fn test_webrtc_manager() {
-        let mut manager = WebRtcManager::new();
+    #[tokio::test]
+    async fn test_webrtc_manager() {
+        let manager = WebRtcManager::new();
 
         assert!(manager.create_connection("session1".to_string()).is_ok());
         assert!(manager.create_connection("session2".to_string()).is_ok());
@@ -103,9 +103,9 @@ mod webrtc_tests {
         assert_eq!(conn.unwrap().session_id(), "session1");
     }
 
-    #[test]
-    fn test_webrtc_manager_remove() {
-        let mut manager = WebRtcManager::new();
+    #[tokio::test]
+    async fn test_webrtc_manager_remove() {
+        let manager = WebRtcManager::new();
 
         manager.create_connection("session1".to_string()).unwrap();
         assert_eq!(manager.connection_count(), 1);